@@ -52,6 +52,12 @@ function formatLastUsed(lastUsedAt: string | null): string {
 }
 
 function getStatusMeta(credential: CredentialStatusItem) {
+  if (credential.cooldownUntil) {
+    return {
+      led: 'status-led-warn',
+      label: `熔断至 ${new Date(credential.cooldownUntil).toLocaleTimeString()}`,
+    }
+  }
   if (credential.disabled) {
     return {
       led: 'status-led-error',
@@ -264,7 +270,7 @@ export function CredentialCard({
             <div>
               <div className="text-[11px] font-sans font-medium tracking-wide text-neutral-500">订阅计划</div>
               <div className="mt-1 font-mono text-sm text-white">
-                {loadingBalance ? <div className="orbital-loader scale-75" /> : balance?.subscriptionTitle || '未知'}
+                {loadingBalance ? <div className="orbital-loader scale-75" /> : balance?.subscriptionTitle || credential.subscriptionTitle || '未知'}
               </div>
             </div>
           </div>
@@ -292,6 +298,19 @@ export function CredentialCard({
                 <span className="truncate text-white">{credential.proxyUrl}</span>
               </div>
             )}
+            {credential.proxyHealth && credential.proxyHealth.state !== 'healthy' && (
+              <div className="flex items-center justify-between gap-3">
+                <span className="font-sans font-medium tracking-wide text-neutral-500 text-[11px]">代理状态</span>
+                <span
+                  className="truncate text-amber-400 font-sans text-[11px]"
+                  title={credential.proxyHealth.lastError}
+                >
+                  {credential.proxyHealth.state === 'fallback'
+                    ? '已回退'
+                    : `连续失败 ${credential.proxyHealth.consecutiveFailures} 次`}
+                </span>
+              </div>
+            )}
             {credential.hasProfileArn && (
               <div>
                 <Badge variant="outline">Profile ARN</Badge>
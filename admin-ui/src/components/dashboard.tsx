@@ -341,6 +341,14 @@ export function Dashboard({ onLogout }: DashboardProps) {
         <section className="col-span-1 md:col-span-12 mt-4">
           <h2 className="mb-4 px-1 font-mono text-sm tracking-normal text-neutral-400">API 密钥</h2>
           <div className="space-y-4">
+            {apiKeysData?.store.error && (
+              <div className="rounded-lg border border-red-500/30 bg-red-500/10 px-3 py-2 font-sans text-sm text-red-400">
+                {apiKeysData.store.mode === 'readOnly'
+                  ? 'api_keys.db 不可写，已以只读方式加载到内存：新增、修改与用量统计在重启后丢失'
+                  : 'api_keys.db 无法打开，仅配置中的 apiKey 可用：所有修改在重启后丢失'}
+                （{apiKeysData.store.error}）
+              </div>
+            )}
             <div className="flex flex-col gap-2 sm:flex-row">
               <Input
                 value={newApiKeyName}
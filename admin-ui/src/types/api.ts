@@ -15,11 +15,31 @@ export interface CredentialStatusItem {
   authMethod: string | null
   hasProfileArn: boolean
   email?: string
+  subscriptionTitle: string | null
+  circuitTrips: number
+  cooldownUntil: string | null
   refreshTokenHash?: string
   successCount: number
   lastUsedAt: string | null
   hasProxy: boolean
   proxyUrl?: string
+  errorCounts: ErrorCounts
+  proxyHealth?: ProxyHealth
+}
+
+export interface ProxyHealth {
+  state: 'healthy' | 'degraded' | 'fallback'
+  consecutiveFailures: number
+  fallbackUntil?: string
+  lastError?: string
+}
+
+export interface ErrorCounts {
+  auth: number
+  throttle: number
+  server: number
+  timeout: number
+  other: number
 }
 
 export interface BalanceResponse {
@@ -82,6 +102,7 @@ export interface LoginResponse {
   success: boolean
   token: string
   expiresAt: string
+  role: 'viewer' | 'operator'
 }
 
 export interface ApiKeyItem {
@@ -97,8 +118,14 @@ export interface ApiKeyItem {
   keyPreview: string
 }
 
+export interface ApiKeyStoreStatus {
+  mode: 'persistent' | 'readOnly' | 'memory'
+  error?: string
+}
+
 export interface ApiKeyListResponse {
   keys: ApiKeyItem[]
+  store: ApiKeyStoreStatus
 }
 
 export interface CreateApiKeyRequest {
@@ -150,4 +177,7 @@ export interface RequestLogEntry {
 
 export interface RequestLogResponse {
   entries: RequestLogEntry[]
+  total: number
+  page: number
+  pageSize: number
 }
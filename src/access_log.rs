@@ -0,0 +1,442 @@
+//! 请求 ID 与访问日志
+//!
+//! 为每个请求分配请求 ID（优先沿用入站的 `X-Request-Id`），写入 tracing span
+//! 与响应头；配置了 `accessLogPath` 时，每个请求在响应体发送完毕后追加一行访问日志
+//! （JSON 或 Apache combined 格式），包含 API Key ID、模型与 tokens，可按大小轮转。
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{HeaderValue, Request, header},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use parking_lot::Mutex;
+use serde_json::json;
+use tracing::Instrument;
+
+use crate::model::config::{AccessLogFormat, AccessLogRotationConfig};
+
+/// 请求 ID 头
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// 入站请求 ID 的最大长度，超出或含非法字符时重新生成
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// 按大小轮转的日志文件
+struct RotatingFile {
+    path: PathBuf,
+    writer: LineWriter<File>,
+    /// 当前文件已写入的字节数
+    written: u64,
+    /// 单个文件的大小上限，None 表示不轮转
+    max_bytes: Option<u64>,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, rotation: Option<&AccessLogRotationConfig>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            written: file.metadata()?.len(),
+            writer: LineWriter::new(file),
+            max_bytes: rotation.map(|r| r.max_size_mb.max(1) * 1024 * 1024),
+            max_files: rotation.map_or(0, |r| r.max_files),
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self
+            .max_bytes
+            .is_some_and(|max| self.written > 0 && self.written + len > max)
+        {
+            self.rotate()?;
+        }
+        writeln!(self.writer, "{}", line)?;
+        self.written += len;
+        Ok(())
+    }
+
+    /// 历史文件路径：`<path>.<n>`
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    /// `<path>.n-1` → `<path>.n`，当前文件 → `<path>.1`，超出保留数的文件被删除
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.max_files));
+            for n in (1..self.max_files).rev() {
+                let _ = fs::rename(self.rotated_path(n), self.rotated_path(n + 1));
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.writer = LineWriter::new(file);
+        self.written = 0;
+        Ok(())
+    }
+}
+
+/// 访问日志输出目标
+enum Sink {
+    Stdout,
+    File(RotatingFile),
+}
+
+/// 访问日志
+pub struct AccessLog {
+    sink: Option<Mutex<Sink>>,
+    format: AccessLogFormat,
+}
+
+impl AccessLog {
+    /// 不输出访问日志（仅分配请求 ID）
+    pub fn disabled() -> Self {
+        Self {
+            sink: None,
+            format: AccessLogFormat::Json,
+        }
+    }
+
+    /// 按配置打开访问日志：`-` 表示标准输出，其余为追加写入的文件路径
+    pub fn open(
+        path: &str,
+        format: AccessLogFormat,
+        rotation: Option<&AccessLogRotationConfig>,
+    ) -> io::Result<Self> {
+        let sink = match path {
+            "-" => Sink::Stdout,
+            path => Sink::File(RotatingFile::open(Path::new(path), rotation)?),
+        };
+        Ok(Self {
+            sink: Some(Mutex::new(sink)),
+            format,
+        })
+    }
+
+    fn write(&self, line: &str) {
+        let Some(sink) = &self.sink else {
+            return;
+        };
+        let result = match &mut *sink.lock() {
+            Sink::Stdout => writeln!(io::stdout().lock(), "{}", line),
+            Sink::File(file) => file.write_line(line),
+        };
+        if let Err(e) = result {
+            tracing::warn!("写入访问日志失败: {}", e);
+        }
+    }
+}
+
+/// 请求处理过程中补充的访问日志字段
+#[derive(Debug, Default)]
+struct Fields {
+    key_id: Option<String>,
+    model: Option<String>,
+    input_tokens: Option<i32>,
+    output_tokens: Option<i32>,
+}
+
+tokio::task_local! {
+    static CURRENT_FIELDS: AccessFields;
+}
+
+/// 当前请求的访问日志字段，由认证中间件与请求处理函数填写
+///
+/// 流式响应在请求任务之外结束，需要先通过 [`AccessFields::current`] 取得句柄再在流结束时填写 tokens
+#[derive(Debug, Clone, Default)]
+pub struct AccessFields(Arc<Mutex<Fields>>);
+
+impl AccessFields {
+    /// 当前请求的字段句柄，未启用访问日志时返回 None
+    pub fn current() -> Option<Self> {
+        CURRENT_FIELDS.try_with(Clone::clone).ok()
+    }
+
+    pub fn set_key_id(&self, key_id: &str) {
+        self.0.lock().key_id = Some(key_id.to_string());
+    }
+
+    pub fn set_model(&self, model: &str) {
+        self.0.lock().model = Some(model.to_string());
+    }
+
+    pub fn set_tokens(&self, input_tokens: i32, output_tokens: i32) {
+        let mut fields = self.0.lock();
+        fields.input_tokens = Some(input_tokens);
+        fields.output_tokens = Some(output_tokens);
+    }
+}
+
+/// 一条访问日志
+struct AccessEntry {
+    received_at: DateTime<Utc>,
+    started: Instant,
+    request_id: String,
+    method: String,
+    path: String,
+    version: String,
+    status: u16,
+    bytes: u64,
+    client_ip: Option<String>,
+    user_agent: Option<String>,
+    referer: Option<String>,
+    fields: AccessFields,
+}
+
+impl AccessEntry {
+    fn json_line(&self) -> String {
+        let fields = self.fields.0.lock();
+        json!({
+            "ts": Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            "request_id": self.request_id,
+            "method": self.method,
+            "path": self.path,
+            "status": self.status,
+            "latency_ms": self.started.elapsed().as_millis() as u64,
+            "bytes": self.bytes,
+            "key_id": fields.key_id,
+            "model": fields.model,
+            "input_tokens": fields.input_tokens,
+            "output_tokens": fields.output_tokens,
+            "client_ip": self.client_ip,
+            "user_agent": self.user_agent,
+        })
+        .to_string()
+    }
+
+    /// Apache combined 格式，API Key ID 作为用户名字段，末尾追加扩展字段
+    fn combined_line(&self) -> String {
+        let fields = self.fields.0.lock();
+        let or_dash = |v: Option<&str>| v.unwrap_or("-").to_string();
+        let quoted = |v: Option<&str>| v.unwrap_or("-").replace('\\', "\\\\").replace('"', "\\\"");
+        let tokens = |v: Option<i32>| v.map_or("-".to_string(), |t| t.to_string());
+        format!(
+            "{} - {} [{}] \"{} {} {}\" {} {} \"{}\" \"{}\" request_id={} model={} input_tokens={} output_tokens={} duration_ms={}",
+            or_dash(self.client_ip.as_deref()),
+            or_dash(fields.key_id.as_deref()),
+            self.received_at.format("%d/%b/%Y:%H:%M:%S %z"),
+            self.method,
+            self.path,
+            self.version,
+            self.status,
+            self.bytes,
+            quoted(self.referer.as_deref()),
+            quoted(self.user_agent.as_deref()),
+            self.request_id,
+            or_dash(fields.model.as_deref()),
+            tokens(fields.input_tokens),
+            tokens(fields.output_tokens),
+            self.started.elapsed().as_millis()
+        )
+    }
+}
+
+/// 响应体发送完毕（或客户端断开导致响应体被丢弃）时写入访问日志
+struct AccessLogGuard {
+    access_log: Arc<AccessLog>,
+    entry: AccessEntry,
+}
+
+impl Drop for AccessLogGuard {
+    fn drop(&mut self) {
+        let line = match self.access_log.format {
+            AccessLogFormat::Json => self.entry.json_line(),
+            AccessLogFormat::Combined => self.entry.combined_line(),
+        };
+        self.access_log.write(&line);
+    }
+}
+
+/// 校验入站请求 ID：非空、长度受限、仅含可见 ASCII 字符
+fn inbound_request_id(value: &HeaderValue) -> Option<String> {
+    let id = value.to_str().ok()?.trim();
+    let valid = !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|b| b.is_ascii_graphic());
+    valid.then(|| id.to_string())
+}
+
+/// 生成新的请求 ID
+fn new_request_id() -> String {
+    format!("req_{}", uuid::Uuid::new_v4().simple())
+}
+
+fn header_string(request: &Request<Body>, name: header::HeaderName) -> Option<String> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// 请求 ID + 访问日志中间件
+///
+/// 访问日志在响应体发送完毕后写入，延迟与字节数包含流式响应的传输过程
+pub async fn request_id_middleware(
+    State(access_log): State<Arc<AccessLog>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let received_at = Utc::now();
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(inbound_request_id)
+        .unwrap_or_else(new_request_id);
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let version = format!("{:?}", request.version());
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string());
+    let user_agent = header_string(&request, header::USER_AGENT);
+    let referer = header_string(&request, header::REFERER);
+
+    let span = tracing::info_span!("request", request_id = %request_id);
+    let fields = AccessFields::default();
+    let run = next.run(request).instrument(span);
+    let mut response = if access_log.sink.is_some() {
+        CURRENT_FIELDS.scope(fields.clone(), run).await
+    } else {
+        run.await
+    };
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    if access_log.sink.is_none() {
+        return response;
+    }
+
+    let mut guard = AccessLogGuard {
+        access_log,
+        entry: AccessEntry {
+            received_at,
+            started,
+            request_id,
+            method,
+            path,
+            version,
+            status: response.status().as_u16(),
+            bytes: 0,
+            client_ip,
+            user_agent,
+            referer,
+            fields,
+        },
+    };
+    let (parts, body) = response.into_parts();
+    let body = Body::from_stream(body.into_data_stream().map(move |chunk| {
+        let guard = &mut guard;
+        if let Ok(bytes) = &chunk {
+            guard.entry.bytes += bytes.len() as u64;
+        }
+        chunk
+    }));
+    Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inbound_request_id_validation() {
+        let id = |v: &str| inbound_request_id(&HeaderValue::from_str(v).unwrap());
+        assert_eq!(id("client-abc-123"), Some("client-abc-123".to_string()));
+        assert_eq!(id("  padded  "), Some("padded".to_string()));
+        assert_eq!(id("bad id with spaces"), None);
+        assert_eq!(id(""), None);
+        assert_eq!(id(&"x".repeat(MAX_REQUEST_ID_LEN + 1)), None);
+        assert!(new_request_id().starts_with("req_"));
+    }
+
+    #[test]
+    fn test_access_log_appends_json_lines() {
+        let path = std::env::temp_dir().join(format!("kiro-access-{}.log", uuid::Uuid::new_v4()));
+        let access_log =
+            AccessLog::open(path.to_str().unwrap(), AccessLogFormat::Json, None).unwrap();
+        access_log.write(&json!({"request_id": "a", "status": 200}).to_string());
+        access_log.write(&json!({"request_id": "b", "status": 429}).to_string());
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["request_id"], "b");
+        assert_eq!(lines[1]["status"], 429);
+    }
+
+    #[test]
+    fn test_rotation_and_combined_format() {
+        let dir = std::env::temp_dir().join(format!("kiro-access-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+        let mut file = RotatingFile::open(&path, None).unwrap();
+        file.max_bytes = Some(20);
+        file.max_files = 2;
+        for line in ["first line", "second line", "third line", "fourth line"] {
+            file.write_line(line).unwrap();
+        }
+        let read = |p: PathBuf| fs::read_to_string(p).unwrap_or_default();
+        assert_eq!(read(path.clone()), "fourth line\n");
+        assert_eq!(read(file.rotated_path(1)), "third line\n");
+        assert_eq!(read(file.rotated_path(2)), "second line\n");
+        assert!(!file.rotated_path(3).exists());
+        let _ = fs::remove_dir_all(&dir);
+
+        let fields = AccessFields::default();
+        fields.set_key_id("key-1");
+        fields.set_model("claude-sonnet-4-6");
+        fields.set_tokens(12, 34);
+        let entry = AccessEntry {
+            received_at: DateTime::parse_from_rfc3339("2026-03-10T12:30:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+            started: Instant::now(),
+            request_id: "req_1".to_string(),
+            method: "POST".to_string(),
+            path: "/v1/messages".to_string(),
+            version: "HTTP/1.1".to_string(),
+            status: 200,
+            bytes: 512,
+            client_ip: Some("10.0.0.1".to_string()),
+            user_agent: Some("curl/8 \"x\"".to_string()),
+            referer: None,
+            fields,
+        };
+        let line = entry.combined_line();
+        assert!(line.starts_with(
+            "10.0.0.1 - key-1 [10/Mar/2026:12:30:00 +0000] \"POST /v1/messages HTTP/1.1\" 200 512 \"-\" \"curl/8 \\\"x\\\"\" request_id=req_1 model=claude-sonnet-4-6 input_tokens=12 output_tokens=34 duration_ms="
+        ));
+        let json: serde_json::Value = serde_json::from_str(&entry.json_line()).unwrap();
+        assert_eq!(json["key_id"], "key-1");
+        assert_eq!(json["output_tokens"], 34);
+    }
+}
@@ -1,55 +1,196 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
 use axum::{
-    Json,
+    Extension, Json,
+    body::Body,
     extract::{Path, Query, State},
-    response::IntoResponse,
+    http::Request,
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
 };
+use futures::{StreamExt, stream};
+use tokio::sync::broadcast::error::RecvError;
+
+use utoipa::ToSchema;
+
+use crate::backup::BackupFile;
+use crate::common::auth;
+use crate::endpoint_metrics::EndpointHealthSnapshot;
+use crate::kiro::credential_usage::CredentialUsageReport;
+use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::token_manager::CanarySnapshot;
+use crate::model::config::AdminRole;
+use crate::request_log::{ExportQuery, LogQuery, RequestLogEntry};
 
 use super::{
-    middleware::AdminState,
+    middleware::{AdminSession, AdminState},
     types::{
-        AddCredentialRequest, ApiKeyListResponse, ApiStatsResponse, CreateApiKeyRequest,
-        CreateApiKeyResponse, LoginRequest, LoginResponse, RequestLogResponse,
-        SetApiKeyDisabledRequest, SetDisabledRequest, SetLoadBalancingModeRequest,
-        SetPriorityRequest, SuccessResponse,
+        AddCredentialRequest, AddCredentialResponse, AdminErrorResponse, AdminSessionInfo,
+        ApiKeyListResponse, ApiStatsResponse, BackupStatusResponse, BalanceResponse,
+        ConfigReloadResponse, CreateApiKeyRequest, CreateApiKeyResponse, CredentialTestQuery,
+        CredentialTestResponse, CredentialUsageQuery, CredentialsStatusResponse,
+        DiscoveredCredentialItem, ImportApiKeyResult, ImportApiKeysRequest,
+        ImportLocalCredentialResult, ImportLocalCredentialsRequest, LoadBalancingModeResponse,
+        LogLevelPayload, LoggingStatus, LoginRequest, LoginResponse, ModelMappingsPayload,
+        OutputLimitsPayload, PricingPayload, RequestLogResponse, RotationResponse,
+        SetApiKeyBodyLimitRequest, SetApiKeyBudgetRequest, SetApiKeyCompactionRequest,
+        SetApiKeyDisabledRequest, SetApiKeyLimitsRequest, SetApiKeyQuotaRequest,
+        SetApiKeyRegionsRequest, SetApiKeyScopesRequest, SetApiKeyWebhookRequest,
+        SetApiKeyWebhookResponse, SetBackupEnabledRequest, SetCanaryPercentageRequest,
+        SetCanaryRequest, SetDisabledRequest, SetLoadBalancingModeRequest, SetLoggingRequest,
+        SetPriorityRequest, SetRotationRequest, SuccessResponse, TotalBalanceResponse,
+        UploadCredentialFilesRequest, UserUsageQuery, UserUsageResponse,
     },
 };
 
+#[utoipa::path(
+    post,
+    path = "/api/admin/auth/login",
+    tag = "admin",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "登录成功", body = LoginResponse),
+        (status = 401, description = "用户名或密码错误", body = AdminErrorResponse),
+    )
+)]
 pub async fn login(
     State(state): State<AdminState>,
     Json(payload): Json<LoginRequest>,
 ) -> impl IntoResponse {
-    if !state.verify_login(&payload.username, &payload.password) {
+    let Some(role) = state.verify_login(&payload.username, &payload.password) else {
         return (
             axum::http::StatusCode::UNAUTHORIZED,
             Json(super::types::AdminErrorResponse::authentication_error()),
         )
             .into_response();
-    }
+    };
 
-    let session = state.sessions.create_session(&payload.username);
+    let session = state.sessions.create_session(&payload.username, role);
+    tracing::info!("Admin 账号 {} 已登录（{:?}）", session.username, role);
+    let expires_at = session.expires_at(&state.session_config()).to_rfc3339();
     Json(LoginResponse {
         success: true,
         token: session.token,
-        expires_at: session.expires_at,
+        expires_at,
+        role,
     })
     .into_response()
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/admin/sessions",
+    tag = "admin",
+    responses(
+        (status = 200, description = "未过期的登录会话", body = Vec<AdminSessionInfo>),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn list_sessions(
+    State(state): State<AdminState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let token = auth::extract_api_key(&request);
+    let config = state.session_config();
+    let sessions: Vec<AdminSessionInfo> = state
+        .sessions
+        .list(&config)
+        .into_iter()
+        .map(|s| AdminSessionInfo {
+            current: token.as_deref() == Some(s.token.as_str()),
+            expires_at: s.expires_at(&config).to_rfc3339(),
+            id: s.id,
+            username: s.username,
+            role: s.role,
+            created_at: s.created_at.to_rfc3339(),
+            last_active_at: s.last_active_at.to_rfc3339(),
+        })
+        .collect();
+    Json(sessions)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/sessions/{id}",
+    tag = "admin",
+    params(("id" = String, Path, description = "会话 ID")),
+    responses(
+        (status = 200, description = "会话已撤销", body = SuccessResponse),
+        (status = 404, description = "会话不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn revoke_session(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    if state.sessions.revoke(&id) {
+        Json(SuccessResponse::new("会话已撤销")).into_response()
+    } else {
+        (
+            axum::http::StatusCode::NOT_FOUND,
+            Json(AdminErrorResponse::not_found(format!("会话不存在: {}", id))),
+        )
+            .into_response()
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/credentials",
+    tag = "admin",
+    responses(
+        (status = 200, description = "凭据状态列表", body = CredentialsStatusResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn get_all_credentials(State(state): State<AdminState>) -> impl IntoResponse {
     Json(state.service.get_all_credentials())
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/admin/credentials/{id}/disabled",
+    tag = "admin",
+    params(("id" = u64, Path, description = "凭据 ID")),
+    request_body = SetDisabledRequest,
+    responses(
+        (status = 200, description = "更新成功", body = SuccessResponse),
+        (status = 404, description = "凭据不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn set_credential_disabled(
     State(state): State<AdminState>,
     Path(id): Path<u64>,
     Json(payload): Json<SetDisabledRequest>,
 ) -> impl IntoResponse {
-    match state.service.set_disabled(id, payload.disabled) {
+    match state.service.set_disabled(id, payload.disabled).await {
         Ok(_) => Json(SuccessResponse::new("更新成功")).into_response(),
         Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/admin/credentials/{id}/priority",
+    tag = "admin",
+    params(("id" = u64, Path, description = "凭据 ID")),
+    request_body = SetPriorityRequest,
+    responses(
+        (status = 200, description = "更新成功", body = SuccessResponse),
+        (status = 404, description = "凭据不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn set_credential_priority(
     State(state): State<AdminState>,
     Path(id): Path<u64>,
@@ -61,6 +202,42 @@ pub async fn set_credential_priority(
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/admin/credentials/{id}/canary",
+    tag = "admin",
+    params(("id" = u64, Path, description = "凭据 ID")),
+    request_body = SetCanaryRequest,
+    responses(
+        (status = 200, description = "更新成功", body = SuccessResponse),
+        (status = 404, description = "凭据不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_credential_canary(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    Json(payload): Json<SetCanaryRequest>,
+) -> impl IntoResponse {
+    match state.service.set_canary(id, payload.canary) {
+        Ok(_) => Json(SuccessResponse::new("更新成功")).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/credentials/{id}/reset",
+    tag = "admin",
+    params(("id" = u64, Path, description = "凭据 ID")),
+    responses(
+        (status = 200, description = "重置成功", body = SuccessResponse),
+        (status = 404, description = "凭据不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn reset_failure_count(
     State(state): State<AdminState>,
     Path(id): Path<u64>,
@@ -71,6 +248,18 @@ pub async fn reset_failure_count(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/admin/credentials/{id}/balance",
+    tag = "admin",
+    params(("id" = u64, Path, description = "凭据 ID")),
+    responses(
+        (status = 200, description = "凭据余额", body = BalanceResponse),
+        (status = 404, description = "凭据不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn get_credential_balance(
     State(state): State<AdminState>,
     Path(id): Path<u64>,
@@ -81,6 +270,65 @@ pub async fn get_credential_balance(
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/admin/credentials/{id}/test",
+    tag = "admin",
+    params(("id" = u64, Path, description = "凭据 ID"), CredentialTestQuery),
+    responses(
+        (status = 200, description = "测试结果（上游调用失败时 success 为 false）", body = CredentialTestResponse),
+        (status = 404, description = "凭据不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn test_credential(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    Query(query): Query<CredentialTestQuery>,
+) -> impl IntoResponse {
+    match state.service.test_credential(id, query.model).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/credentials/{id}/usage",
+    tag = "admin",
+    params(("id" = u64, Path, description = "凭据 ID"), CredentialUsageQuery),
+    responses(
+        (status = 200, description = "凭据的请求数与 token 用量", body = CredentialUsageReport),
+        (status = 404, description = "凭据不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn get_credential_usage(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    Query(query): Query<CredentialUsageQuery>,
+) -> impl IntoResponse {
+    let hours = query.hours.unwrap_or(24);
+    match state.service.get_credential_usage(id, hours) {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/credentials",
+    tag = "admin",
+    request_body = AddCredentialRequest,
+    responses(
+        (status = 200, description = "添加成功", body = AddCredentialResponse),
+        (status = 400, description = "请求无效", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn add_credential(
     State(state): State<AdminState>,
     Json(payload): Json<AddCredentialRequest>,
@@ -91,6 +339,77 @@ pub async fn add_credential(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/admin/credentials/discover",
+    tag = "admin",
+    responses(
+        (status = 200, description = "本机 Kiro IDE / AWS SSO 缓存中发现的凭据", body = Vec<DiscoveredCredentialItem>),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn discover_local_credentials(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.discover_local_credentials())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/credentials/discover/import",
+    tag = "admin",
+    request_body = ImportLocalCredentialsRequest,
+    responses(
+        (status = 200, description = "逐个凭据的导入结果", body = Vec<ImportLocalCredentialResult>),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn import_local_credentials(
+    State(state): State<AdminState>,
+    Json(payload): Json<ImportLocalCredentialsRequest>,
+) -> impl IntoResponse {
+    Json(
+        state
+            .service
+            .import_local_credentials(payload.sources)
+            .await,
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/credentials/upload",
+    tag = "admin",
+    request_body = UploadCredentialFilesRequest,
+    responses(
+        (status = 200, description = "逐个文件的导入结果", body = Vec<ImportLocalCredentialResult>),
+        (status = 400, description = "未上传文件或文件过多", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn upload_credentials(
+    State(state): State<AdminState>,
+    Json(payload): Json<UploadCredentialFilesRequest>,
+) -> impl IntoResponse {
+    match state.service.import_uploaded_credentials(payload).await {
+        Ok(results) => Json(results).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/credentials/{id}",
+    tag = "admin",
+    params(("id" = u64, Path, description = "凭据 ID")),
+    responses(
+        (status = 200, description = "删除成功", body = SuccessResponse),
+        (status = 404, description = "凭据不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn delete_credential(
     State(state): State<AdminState>,
     Path(id): Path<u64>,
@@ -101,10 +420,32 @@ pub async fn delete_credential(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/admin/config/load-balancing",
+    tag = "admin",
+    responses(
+        (status = 200, description = "当前负载均衡模式", body = LoadBalancingModeResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn get_load_balancing_mode(State(state): State<AdminState>) -> impl IntoResponse {
     Json(state.service.get_load_balancing_mode())
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/admin/config/load-balancing",
+    tag = "admin",
+    request_body = SetLoadBalancingModeRequest,
+    responses(
+        (status = 200, description = "更新后的负载均衡模式", body = LoadBalancingModeResponse),
+        (status = 400, description = "请求无效", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn set_load_balancing_mode(
     State(state): State<AdminState>,
     Json(payload): Json<SetLoadBalancingModeRequest>,
@@ -115,26 +456,371 @@ pub async fn set_load_balancing_mode(
     }
 }
 
-pub async fn list_api_keys(State(state): State<AdminState>) -> impl IntoResponse {
+#[utoipa::path(
+    post,
+    path = "/api/admin/config/reload",
+    tag = "admin",
+    responses(
+        (status = 200, description = "热重载结果", body = ConfigReloadResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn reload_config(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.reload_config() {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// `PUT /config/log-level`：[`set_logging`] 的别名（不限定生效时长）
+#[utoipa::path(
+    put,
+    path = "/api/admin/config/log-level",
+    tag = "admin",
+    request_body = LogLevelPayload,
+    responses(
+        (status = 200, description = "调整后的日志过滤规则", body = LoggingStatus),
+        (status = 400, description = "过滤规则无效", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_log_level(
+    state: State<AdminState>,
+    Json(payload): Json<LogLevelPayload>,
+) -> impl IntoResponse {
+    let request = SetLoggingRequest {
+        filter: payload.filter,
+        duration_secs: None,
+    };
+    set_logging(state, Json(request)).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/logging",
+    tag = "admin",
+    responses(
+        (status = 200, description = "当前日志过滤规则与恢复计划", body = LoggingStatus),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn get_logging(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.get_logging() {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/logging",
+    tag = "admin",
+    request_body = SetLoggingRequest,
+    responses(
+        (status = 200, description = "调整后的日志过滤规则", body = LoggingStatus),
+        (status = 400, description = "过滤规则或时长无效", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_logging(
+    State(state): State<AdminState>,
+    Json(payload): Json<SetLoggingRequest>,
+) -> impl IntoResponse {
+    match state.service.set_logging(payload) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/config/canary",
+    tag = "admin",
+    responses(
+        (status = 200, description = "金丝雀状态", body = CanarySnapshot),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn get_canary(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_canary())
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/config/canary",
+    tag = "admin",
+    request_body = SetCanaryPercentageRequest,
+    responses(
+        (status = 200, description = "更新后的金丝雀状态", body = CanarySnapshot),
+        (status = 400, description = "请求无效", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_canary_percentage(
+    State(state): State<AdminState>,
+    Json(payload): Json<SetCanaryPercentageRequest>,
+) -> impl IntoResponse {
+    match state.service.set_canary_percentage(payload) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/config/model-mappings",
+    tag = "admin",
+    responses(
+        (status = 200, description = "模型别名映射", body = ModelMappingsPayload),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn get_model_mappings(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_model_mappings())
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/config/model-mappings",
+    tag = "admin",
+    request_body = ModelMappingsPayload,
+    responses(
+        (status = 200, description = "更新后的模型别名映射", body = ModelMappingsPayload),
+        (status = 400, description = "请求无效", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_model_mappings(
+    State(state): State<AdminState>,
+    Json(payload): Json<ModelMappingsPayload>,
+) -> impl IntoResponse {
+    match state.service.set_model_mappings(payload) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/config/output-limits",
+    tag = "admin",
+    responses(
+        (status = 200, description = "模型输出上限设置", body = OutputLimitsPayload),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn get_output_limits(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_output_limits())
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/config/output-limits",
+    tag = "admin",
+    request_body = OutputLimitsPayload,
+    responses(
+        (status = 200, description = "更新后的模型输出上限设置", body = OutputLimitsPayload),
+        (status = 400, description = "请求无效", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_output_limits(
+    State(state): State<AdminState>,
+    Json(payload): Json<OutputLimitsPayload>,
+) -> impl IntoResponse {
+    match state.service.set_output_limits(payload) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/config/pricing",
+    tag = "admin",
+    responses(
+        (status = 200, description = "模型单价表", body = PricingPayload),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn get_pricing(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_pricing())
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/config/pricing",
+    tag = "admin",
+    request_body = PricingPayload,
+    responses(
+        (status = 200, description = "更新后的模型单价表", body = PricingPayload),
+        (status = 400, description = "请求无效", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_pricing(
+    State(state): State<AdminState>,
+    Json(payload): Json<PricingPayload>,
+) -> impl IntoResponse {
+    match state.service.set_pricing(payload) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/config/rotation",
+    tag = "admin",
+    responses(
+        (status = 200, description = "凭据轮换策略与当前生效的组", body = RotationResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn get_rotation(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_rotation())
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/config/rotation",
+    tag = "admin",
+    request_body = SetRotationRequest,
+    responses(
+        (status = 200, description = "更新后的凭据轮换策略", body = RotationResponse),
+        (status = 400, description = "请求无效", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_rotation(
+    State(state): State<AdminState>,
+    Json(payload): Json<SetRotationRequest>,
+) -> impl IntoResponse {
+    match state.service.set_rotation(payload) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/backups",
+    tag = "admin",
+    responses(
+        (status = 200, description = "备份状态与备份文件列表", body = BackupStatusResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn get_backups(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.backup_status() {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/backups",
+    tag = "admin",
+    responses(
+        (status = 200, description = "新写入的备份文件", body = BackupFile),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+        (status = 500, description = "备份失败（如未设置密码）", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn run_backup(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.run_backup().await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/backups/enabled",
+    tag = "admin",
+    request_body = SetBackupEnabledRequest,
+    responses(
+        (status = 200, description = "更新后的备份状态", body = BackupStatusResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_backup_enabled(
+    State(state): State<AdminState>,
+    Json(payload): Json<SetBackupEnabledRequest>,
+) -> impl IntoResponse {
+    match state.service.set_backup_enabled(payload) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/apikeys",
+    tag = "admin",
+    responses(
+        (status = 200, description = "API Key 列表", body = ApiKeyListResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn list_api_keys(
+    State(state): State<AdminState>,
+    Extension(session): Extension<AdminSession>,
+) -> impl IntoResponse {
+    let mut keys = state.service.list_api_keys();
+    // 只读账号只能看到 Key 预览，避免借此取得完整 Key（包括配置中的 apiKey）
+    if session.role == AdminRole::Viewer {
+        for key in &mut keys {
+            key.key = key.key_preview.clone();
+        }
+    }
     Json(ApiKeyListResponse {
-        keys: state.service.list_api_keys(),
+        keys,
+        store: state.service.api_key_store_status(),
     })
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/admin/apikeys",
+    tag = "admin",
+    request_body = CreateApiKeyRequest,
+    responses(
+        (status = 200, description = "创建成功（完整 Key 仅返回一次）", body = CreateApiKeyResponse),
+        (status = 400, description = "名称为空，或自定义 Key 格式不符、强度不足、已存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn create_api_key(
     State(state): State<AdminState>,
     Json(payload): Json<CreateApiKeyRequest>,
 ) -> impl IntoResponse {
-    match state.service.create_api_key(payload.name) {
+    match state.service.create_api_key(payload.name, payload.key) {
         Ok(key) => Json(CreateApiKeyResponse {
             success: true,
             id: key.id,
             name: key.name,
-            key_preview: if key.key.len() > 8 {
-                format!("{}****{}", &key.key[..4], &key.key[key.key.len() - 4..])
-            } else {
-                "********".to_string()
-            },
+            key_preview: crate::apikeys::preview_key(&key.key),
             key: key.key,
         })
         .into_response(),
@@ -148,6 +834,37 @@ pub async fn create_api_key(
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/admin/apikeys/import",
+    tag = "admin",
+    request_body = ImportApiKeysRequest,
+    responses(
+        (status = 200, description = "逐个 Key 的导入结果（完整 Key 不回显）", body = Vec<ImportApiKeyResult>),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn import_api_keys(
+    State(state): State<AdminState>,
+    Json(payload): Json<ImportApiKeysRequest>,
+) -> impl IntoResponse {
+    Json(state.service.import_api_keys(payload.keys))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/apikeys/{id}/disabled",
+    tag = "admin",
+    params(("id" = String, Path, description = "API Key ID")),
+    request_body = SetApiKeyDisabledRequest,
+    responses(
+        (status = 200, description = "更新成功", body = SuccessResponse),
+        (status = 404, description = "API Key 不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn set_api_key_disabled(
     State(state): State<AdminState>,
     Path(id): Path<String>,
@@ -165,6 +882,305 @@ pub async fn set_api_key_disabled(
     }
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/admin/apikeys/{id}/limits",
+    tag = "admin",
+    params(("id" = String, Path, description = "API Key ID")),
+    request_body = SetApiKeyLimitsRequest,
+    responses(
+        (status = 200, description = "更新成功", body = SuccessResponse),
+        (status = 404, description = "API Key 不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_api_key_limits(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+    Json(payload): Json<SetApiKeyLimitsRequest>,
+) -> impl IntoResponse {
+    match state
+        .service
+        .set_api_key_limits(&id, payload.rpm_limit, payload.tpm_limit)
+    {
+        Ok(_) => Json(SuccessResponse::new("更新成功")).into_response(),
+        Err(e) => (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(super::types::AdminErrorResponse::invalid_request(
+                e.to_string(),
+            )),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/apikeys/{id}/quota",
+    tag = "admin",
+    params(("id" = String, Path, description = "API Key ID")),
+    request_body = SetApiKeyQuotaRequest,
+    responses(
+        (status = 200, description = "更新成功", body = SuccessResponse),
+        (status = 404, description = "API Key 不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_api_key_quota(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+    Json(payload): Json<SetApiKeyQuotaRequest>,
+) -> impl IntoResponse {
+    match state.service.set_api_key_quota(
+        &id,
+        payload.daily_token_limit,
+        payload.monthly_token_limit,
+    ) {
+        Ok(_) => Json(SuccessResponse::new("更新成功")).into_response(),
+        Err(e) => (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(super::types::AdminErrorResponse::invalid_request(
+                e.to_string(),
+            )),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/apikeys/{id}/budget",
+    tag = "admin",
+    params(("id" = String, Path, description = "API Key ID")),
+    request_body = SetApiKeyBudgetRequest,
+    responses(
+        (status = 200, description = "更新成功", body = SuccessResponse),
+        (status = 400, description = "请求无效或 API Key 不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_api_key_budget(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+    Json(payload): Json<SetApiKeyBudgetRequest>,
+) -> impl IntoResponse {
+    match state
+        .service
+        .set_api_key_budget(&id, payload.monthly_budget_usd)
+    {
+        Ok(_) => Json(SuccessResponse::new("更新成功")).into_response(),
+        Err(e) => (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(super::types::AdminErrorResponse::invalid_request(
+                e.to_string(),
+            )),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/apikeys/{id}/body-limit",
+    tag = "admin",
+    params(("id" = String, Path, description = "API Key ID")),
+    request_body = SetApiKeyBodyLimitRequest,
+    responses(
+        (status = 200, description = "更新成功", body = SuccessResponse),
+        (status = 400, description = "请求无效或 API Key 不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_api_key_body_limit(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+    Json(payload): Json<SetApiKeyBodyLimitRequest>,
+) -> impl IntoResponse {
+    match state
+        .service
+        .set_api_key_body_limit(&id, payload.max_body_bytes)
+    {
+        Ok(_) => Json(SuccessResponse::new("更新成功")).into_response(),
+        Err(e) => (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(super::types::AdminErrorResponse::invalid_request(
+                e.to_string(),
+            )),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/apikeys/{id}/compaction",
+    tag = "admin",
+    params(("id" = String, Path, description = "API Key ID")),
+    request_body = SetApiKeyCompactionRequest,
+    responses(
+        (status = 200, description = "更新成功", body = SuccessResponse),
+        (status = 404, description = "API Key 不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_api_key_compaction(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+    Json(payload): Json<SetApiKeyCompactionRequest>,
+) -> impl IntoResponse {
+    match state
+        .service
+        .set_api_key_compaction(&id, payload.threshold_percent)
+    {
+        Ok(_) => Json(SuccessResponse::new("更新成功")).into_response(),
+        Err(e) => (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(super::types::AdminErrorResponse::invalid_request(
+                e.to_string(),
+            )),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/apikeys/{id}/scopes",
+    tag = "admin",
+    params(("id" = String, Path, description = "API Key ID")),
+    request_body = SetApiKeyScopesRequest,
+    responses(
+        (status = 200, description = "更新成功", body = SuccessResponse),
+        (status = 400, description = "范围无效或 API Key 不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_api_key_scopes(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+    Json(payload): Json<SetApiKeyScopesRequest>,
+) -> impl IntoResponse {
+    match state.service.set_api_key_scopes(&id, payload.scopes) {
+        Ok(_) => Json(SuccessResponse::new("更新成功")).into_response(),
+        Err(e) => (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(super::types::AdminErrorResponse::invalid_request(
+                e.to_string(),
+            )),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/apikeys/{id}/webhook",
+    tag = "admin",
+    params(("id" = String, Path, description = "API Key ID")),
+    request_body = SetApiKeyWebhookRequest,
+    responses(
+        (status = 200, description = "回调地址与新的签名密钥", body = SetApiKeyWebhookResponse),
+        (status = 400, description = "回调地址无效或 API Key 不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_api_key_webhook(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+    Json(payload): Json<SetApiKeyWebhookRequest>,
+) -> impl IntoResponse {
+    match state.service.set_api_key_webhook(&id, payload.url) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(super::types::AdminErrorResponse::invalid_request(
+                e.to_string(),
+            )),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/apikeys/{id}/regions",
+    tag = "admin",
+    params(("id" = String, Path, description = "API Key ID")),
+    request_body = SetApiKeyRegionsRequest,
+    responses(
+        (status = 200, description = "更新成功", body = SuccessResponse),
+        (status = 400, description = "地域无效或 API Key 不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn set_api_key_regions(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+    Json(payload): Json<SetApiKeyRegionsRequest>,
+) -> impl IntoResponse {
+    match state
+        .service
+        .set_api_key_regions(&id, payload.allowed_regions)
+    {
+        Ok(_) => Json(SuccessResponse::new("更新成功")).into_response(),
+        Err(e) => (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(super::types::AdminErrorResponse::invalid_request(
+                e.to_string(),
+            )),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/apikeys/{id}/quota/reset",
+    tag = "admin",
+    params(("id" = String, Path, description = "API Key ID")),
+    responses(
+        (status = 200, description = "重置成功", body = SuccessResponse),
+        (status = 404, description = "API Key 不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn reset_api_key_quota(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match state.service.reset_api_key_quota(&id) {
+        Ok(_) => Json(SuccessResponse::new("配额用量已重置")).into_response(),
+        Err(e) => (
+            axum::http::StatusCode::BAD_REQUEST,
+            Json(super::types::AdminErrorResponse::invalid_request(
+                e.to_string(),
+            )),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/apikeys/{id}",
+    tag = "admin",
+    params(("id" = String, Path, description = "API Key ID")),
+    responses(
+        (status = 200, description = "删除成功", body = SuccessResponse),
+        (status = 404, description = "API Key 不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn delete_api_key(
     State(state): State<AdminState>,
     Path(id): Path<String>,
@@ -181,16 +1197,82 @@ pub async fn delete_api_key(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/admin/stats",
+    tag = "admin",
+    responses(
+        (status = 200, description = "API Key 用量统计", body = ApiStatsResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn get_api_stats(State(state): State<AdminState>) -> impl IntoResponse {
     Json(ApiStatsResponse {
         overview: state.service.api_key_overview(),
     })
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/admin/stats/users",
+    tag = "admin",
+    params(UserUsageQuery),
+    responses(
+        (status = 200, description = "按终端用户（metadata.user_id）聚合的用量", body = UserUsageResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn get_user_usage(
+    State(state): State<AdminState>,
+    Query(query): Query<UserUsageQuery>,
+) -> impl IntoResponse {
+    Json(UserUsageResponse {
+        users: state.service.user_usage(query.api_key_id.as_deref()),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/stats/endpoints",
+    tag = "admin",
+    responses(
+        (status = 200, description = "各端点健康度", body = EndpointHealthSnapshot),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn get_endpoint_health(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.endpoint_health())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/credentials/export",
+    tag = "admin",
+    responses(
+        (status = 200, description = "全部凭据（含敏感字段）", body = Vec<KiroCredentials>),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn export_credentials(State(state): State<AdminState>) -> impl IntoResponse {
     Json(state.service.export_credentials())
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/admin/credentials/{id}/export",
+    tag = "admin",
+    params(("id" = u64, Path, description = "凭据 ID")),
+    responses(
+        (status = 200, description = "单个凭据（含敏感字段）", body = KiroCredentials),
+        (status = 404, description = "凭据不存在", body = AdminErrorResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn export_credential(
     State(state): State<AdminState>,
     Path(id): Path<u64>,
@@ -201,28 +1283,188 @@ pub async fn export_credential(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/admin/balance/total",
+    tag = "admin",
+    responses(
+        (status = 200, description = "全部凭据余额汇总", body = TotalBalanceResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn get_total_balance(State(state): State<AdminState>) -> impl IntoResponse {
     Json(state.service.get_total_balance().await)
 }
 
-#[derive(Debug, serde::Deserialize)]
-pub struct LogQuery {
-    pub since_id: Option<String>,
+#[utoipa::path(
+    get,
+    path = "/api/admin/logs",
+    tag = "admin",
+    params(LogQuery),
+    responses(
+        (status = 200, description = "请求日志分页", body = RequestLogResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn get_request_logs(
+    State(state): State<AdminState>,
+    Query(query): Query<LogQuery>,
+) -> impl IntoResponse {
+    let page = state.service.get_request_logs(&query).await;
+    Json(RequestLogResponse {
+        entries: page.entries,
+        total: page.total,
+        page: page.page,
+        page_size: page.page_size,
+    })
 }
 
-pub async fn get_request_logs(
+#[utoipa::path(
+    delete,
+    path = "/api/admin/logs",
+    tag = "admin",
+    responses(
+        (status = 200, description = "已清空请求日志（包括持久化的日志与外置内容）", body = SuccessResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn clear_request_logs(State(state): State<AdminState>) -> impl IntoResponse {
+    state.service.clear_request_logs().await;
+    Json(SuccessResponse::new("请求日志已清空"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/logs/{id}",
+    tag = "admin",
+    params(("id" = String, Path, description = "日志 ID")),
+    responses(
+        (status = 200, description = "单条请求日志，外置的大请求/响应体已还原", body = RequestLogEntry),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+        (status = 404, description = "日志不存在", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn get_request_log(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match state.service.get_request_log(&id).await {
+        Some(entry) => Json(entry).into_response(),
+        None => (
+            axum::http::StatusCode::NOT_FOUND,
+            Json(AdminErrorResponse::not_found(format!("日志不存在: {}", id))),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/logs/stream",
+    tag = "admin",
+    params(LogQuery),
+    responses(
+        (status = 200, description = "实时请求日志（SSE，`log` 事件为 RequestLogEntry，落后过多时发送 `lagged` 事件）", content_type = "text/event-stream"),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn stream_request_logs(
     State(state): State<AdminState>,
     Query(query): Query<LogQuery>,
 ) -> impl IntoResponse {
-    let entries = state.service.get_request_logs(query.since_id.as_deref());
-    Json(RequestLogResponse { entries })
+    let Some(rx) = state.service.subscribe_logs() else {
+        return (
+            axum::http::StatusCode::NOT_FOUND,
+            Json(AdminErrorResponse::not_found("请求日志未启用")),
+        )
+            .into_response();
+    };
+    // 仅按 api_key_id / user_id / model / status / 时间范围过滤，分页参数不生效
+    let events = stream::unfold((rx, query), |(mut rx, query)| async move {
+        loop {
+            let event = match rx.recv().await {
+                Ok(entry) if query.matches(&entry) => Event::default()
+                    .event("log")
+                    .id(entry.id.clone())
+                    .json_data(&entry),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => Event::default()
+                    .event("lagged")
+                    .json_data(serde_json::json!({ "skipped": skipped })),
+                Err(RecvError::Closed) => return None,
+            };
+            let Ok(event) = event else { continue };
+            return Some((Ok::<_, Infallible>(event), (rx, query)));
+        }
+    });
+    Sse::new(events)
+        .keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/logs/export",
+    tag = "admin",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "请求日志下载（JSONL 或 CSV，按时间正序，含 token 用量与耗时，不含请求/响应体）", content_type = "application/x-ndjson"),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
+pub async fn export_request_logs(
+    State(state): State<AdminState>,
+    Query(query): Query<ExportQuery>,
+) -> impl IntoResponse {
+    let format = query.format();
+    let filename = format!(
+        "request-logs-{}.{}",
+        chrono::Utc::now().format("%Y%m%d%H%M%S"),
+        format.extension()
+    );
+    // 分批读取持久化日志，边读边写出，避免一次加载全部日志
+    let header = stream::once(async move { Ok::<_, Infallible>(format.header().to_string()) });
+    let lines = stream::unfold(Some(0), move |cursor| {
+        let service = state.service.clone();
+        let query = query.clone();
+        async move {
+            let (entries, next) = service.export_request_logs(&query, cursor?).await;
+            let chunk: String = entries.iter().map(|e| format.line(e)).collect();
+            Some((Ok::<_, Infallible>(chunk), next))
+        }
+    });
+    axum::http::Response::builder()
+        .header(axum::http::header::CONTENT_TYPE, format.content_type())
+        .header(
+            axum::http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(Body::from_stream(header.chain(lines)))
+        .unwrap()
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, serde::Deserialize, ToSchema)]
 pub struct SetLogEnabledRequest {
     pub enabled: bool,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/admin/logs/enabled",
+    tag = "admin",
+    request_body = SetLogEnabledRequest,
+    responses(
+        (status = 200, description = "更新成功", body = SuccessResponse),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn set_log_enabled(
     State(state): State<AdminState>,
     Json(payload): Json<SetLogEnabledRequest>,
@@ -231,6 +1473,16 @@ pub async fn set_log_enabled(
     Json(SuccessResponse::new(if payload.enabled { "日志已开启" } else { "日志已关闭" }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/admin/logs/enabled",
+    tag = "admin",
+    responses(
+        (status = 200, description = "日志开关状态（`enabled` 字段）"),
+        (status = 401, description = "未登录或会话失效", body = AdminErrorResponse),
+    ),
+    security(("admin_session" = []))
+)]
 pub async fn get_log_enabled(State(state): State<AdminState>) -> impl IntoResponse {
     Json(serde_json::json!({ "enabled": state.service.is_log_enabled() }))
 }
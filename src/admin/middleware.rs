@@ -6,25 +6,39 @@ use std::sync::Arc;
 use axum::{
     body::Body,
     extract::State,
-    http::{Request, StatusCode},
+    http::{Method, Request, StatusCode},
     middleware::Next,
     response::{IntoResponse, Json, Response},
 };
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use parking_lot::Mutex;
 use uuid::Uuid;
 
 use super::service::AdminService;
 use super::types::AdminErrorResponse;
 use crate::common::auth;
-
-const SESSION_TTL_HOURS: i64 = 24;
+use crate::model::config::{AdminRole, AdminSessionConfig, AdminUserConfig};
 
 #[derive(Debug, Clone)]
 pub struct AdminSession {
+    /// 会话 ID（用于列出 / 撤销会话，不是登录令牌）
+    pub id: String,
     pub token: String,
     pub username: String,
-    pub expires_at: String,
+    pub role: AdminRole,
+    pub created_at: DateTime<Utc>,
+    pub last_active_at: DateTime<Utc>,
+}
+
+impl AdminSession {
+    /// 按会话配置计算的过期时间：最长有效期与闲置超时中先到者
+    pub fn expires_at(&self, config: &AdminSessionConfig) -> DateTime<Utc> {
+        let absolute = self.created_at + Duration::hours(config.ttl_hours.max(1) as i64);
+        if config.idle_timeout_minutes == 0 {
+            return absolute;
+        }
+        absolute.min(self.last_active_at + Duration::minutes(config.idle_timeout_minutes as i64))
+    }
 }
 
 #[derive(Default)]
@@ -37,57 +51,86 @@ impl SessionManager {
         Self::default()
     }
 
-    pub fn create_session(&self, username: &str) -> AdminSession {
+    pub fn create_session(&self, username: &str, role: AdminRole) -> AdminSession {
         let token = format!("adm_{}", Uuid::new_v4().simple());
-        let expires_at = (Utc::now() + Duration::hours(SESSION_TTL_HOURS)).to_rfc3339();
+        let now = Utc::now();
         let session = AdminSession {
+            id: Uuid::new_v4().simple().to_string()[..12].to_string(),
             token: token.clone(),
             username: username.to_string(),
-            expires_at,
+            role,
+            created_at: now,
+            last_active_at: now,
         };
         self.sessions.lock().insert(token, session.clone());
         session
     }
 
-    pub fn validate(&self, token: &str) -> bool {
-        self.cleanup_expired();
-        self.sessions
-            .lock()
-            .get(token)
-            .is_some_and(|s| s.expires_at > Utc::now().to_rfc3339())
+    /// 校验会话并记录活动时间（闲置超时从此刻重新计算），返回会话信息
+    pub fn validate(&self, token: &str, config: &AdminSessionConfig) -> Option<AdminSession> {
+        self.validate_at(token, config, Utc::now())
     }
 
-    pub fn cleanup_expired(&self) {
-        let now = Utc::now().to_rfc3339();
-        self.sessions.lock().retain(|_, s| s.expires_at > now);
+    fn validate_at(
+        &self,
+        token: &str,
+        config: &AdminSessionConfig,
+        now: DateTime<Utc>,
+    ) -> Option<AdminSession> {
+        let mut sessions = self.sessions.lock();
+        sessions.retain(|_, s| s.expires_at(config) > now);
+        let session = sessions.get_mut(token)?;
+        session.last_active_at = now;
+        Some(session.clone())
+    }
+
+    /// 列出未过期的会话，按登录时间排序
+    pub fn list(&self, config: &AdminSessionConfig) -> Vec<AdminSession> {
+        let now = Utc::now();
+        let mut sessions = self.sessions.lock();
+        sessions.retain(|_, s| s.expires_at(config) > now);
+        let mut list: Vec<AdminSession> = sessions.values().cloned().collect();
+        list.sort_by_key(|s| s.created_at);
+        list
+    }
+
+    /// 按会话 ID 撤销会话，返回是否存在
+    pub fn revoke(&self, id: &str) -> bool {
+        let mut sessions = self.sessions.lock();
+        let before = sessions.len();
+        sessions.retain(|_, s| s.id != id);
+        sessions.len() != before
     }
 }
 
 #[derive(Clone)]
 pub struct AdminState {
-    pub admin_username: String,
-    pub admin_password: String,
+    pub users: Arc<Vec<AdminUserConfig>>,
     pub sessions: Arc<SessionManager>,
     pub service: Arc<AdminService>,
 }
 
 impl AdminState {
-    pub fn new(
-        admin_username: impl Into<String>,
-        admin_password: impl Into<String>,
-        service: AdminService,
-    ) -> Self {
+    pub fn new(users: Vec<AdminUserConfig>, service: AdminService) -> Self {
         Self {
-            admin_username: admin_username.into(),
-            admin_password: admin_password.into(),
+            users: Arc::new(users),
             sessions: Arc::new(SessionManager::new()),
             service: Arc::new(service),
         }
     }
 
-    pub fn verify_login(&self, username: &str, password: &str) -> bool {
-        auth::constant_time_eq(username, &self.admin_username)
-            && auth::constant_time_eq(password, &self.admin_password)
+    /// 校验账号密码，返回账号角色（逐个比较全部账号，耗时与匹配位置无关）
+    pub fn verify_login(&self, username: &str, password: &str) -> Option<AdminRole> {
+        self.users.iter().fold(None, |matched, user| {
+            let ok = auth::constant_time_eq(username, &user.username)
+                & auth::constant_time_eq(password, &user.password);
+            matched.or(ok.then_some(user.role))
+        })
+    }
+
+    /// 当前生效的会话配置（未配置时为默认值）
+    pub fn session_config(&self) -> AdminSessionConfig {
+        self.service.session_config()
     }
 }
 
@@ -96,13 +139,145 @@ pub async fn admin_auth_middleware(
     request: Request<Body>,
     next: Next,
 ) -> Response {
-    let token = auth::extract_api_key(&request);
+    let session = auth::extract_api_key(&request)
+        .and_then(|t| state.sessions.validate(&t, &state.session_config()));
+    let Some(session) = session else {
+        let error = AdminErrorResponse::authentication_error();
+        return (StatusCode::UNAUTHORIZED, Json(error)).into_response();
+    };
+
+    let read_only = is_read_only(request.method(), request.uri().path());
+    if session.role == AdminRole::Viewer && !(read_only && viewer_allowed(request.uri().path())) {
+        let error =
+            AdminErrorResponse::forbidden("Read-only admin accounts cannot perform this action");
+        return (StatusCode::FORBIDDEN, Json(error)).into_response();
+    }
+    if !read_only {
+        tracing::info!(
+            "Admin 操作 [{}]: {} {}",
+            session.username,
+            request.method(),
+            request.uri().path()
+        );
+    }
+
+    let mut request = request;
+    request.extensions_mut().insert(session);
+    next.run(request).await
+}
+
+/// 只读请求：GET / HEAD，但导出凭据（含令牌明文）不算只读
+fn is_read_only(method: &Method, path: &str) -> bool {
+    (method == Method::GET || method == Method::HEAD) && !path.ends_with("/export")
+}
+
+/// 只读账号可访问的路径：请求日志（含完整请求/响应体）与本机凭据发现（含上游令牌）仅限 operator
+fn viewer_allowed(path: &str) -> bool {
+    let path = path.strip_prefix("/api/admin").unwrap_or(path);
+    let logs = path == "/logs" || (path.starts_with("/logs/") && path != "/logs/enabled");
+    !logs && !path.starts_with("/credentials/discover")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_timeout_is_refreshed_by_activity() {
+        let config = AdminSessionConfig {
+            ttl_hours: 1,
+            idle_timeout_minutes: 10,
+        };
+        let manager = SessionManager::new();
+        let active = |session: &AdminSession, minutes: i64| {
+            manager
+                .validate_at(
+                    &session.token,
+                    &config,
+                    session.created_at + Duration::minutes(minutes),
+                )
+                .is_some()
+        };
 
-    match token {
-        Some(t) if state.sessions.validate(&t) => next.run(request).await,
-        _ => {
-            let error = AdminErrorResponse::authentication_error();
-            (StatusCode::UNAUTHORIZED, Json(error)).into_response()
+        // 每次活动都顺延闲置超时
+        let session = manager.create_session("admin", AdminRole::Operator);
+        assert!(active(&session, 8));
+        assert!(active(&session, 16));
+        // 闲置超过 10 分钟后失效
+        assert!(!active(&session, 27));
+
+        // 持续活动也不能超过最长有效期
+        let session = manager.create_session("admin", AdminRole::Operator);
+        for minutes in (5..60).step_by(5) {
+            assert!(active(&session, minutes));
         }
+        assert!(!active(&session, 61));
+    }
+
+    #[test]
+    fn test_viewer_is_read_only() {
+        assert!(is_read_only(&Method::GET, "/credentials"));
+        assert!(!is_read_only(&Method::GET, "/credentials/1/export"));
+        assert!(!is_read_only(&Method::GET, "/credentials/export"));
+        assert!(!is_read_only(&Method::DELETE, "/credentials/1"));
+        assert!(!is_read_only(&Method::POST, "/credentials"));
+    }
+
+    #[test]
+    fn test_viewer_cannot_read_secrets() {
+        assert!(viewer_allowed("/stats"));
+        assert!(viewer_allowed("/apikeys"));
+        assert!(viewer_allowed("/logs/enabled"));
+        assert!(!viewer_allowed("/logs"));
+        assert!(!viewer_allowed("/logs/abc"));
+        assert!(!viewer_allowed("/logs/stream"));
+        assert!(!viewer_allowed("/api/admin/logs/export"));
+        assert!(!viewer_allowed("/credentials/discover"));
+    }
+
+    #[tokio::test]
+    async fn test_viewer_session_cannot_see_api_key_values() {
+        use crate::apikeys::ApiKeyManager;
+        use crate::kiro::token_manager::MultiTokenManager;
+        use crate::model::config::Config;
+        use axum::Extension;
+
+        let master = "sk-master-secret-value-0123456789";
+        let token_manager =
+            Arc::new(MultiTokenManager::new(Config::default(), vec![], None, None, false).unwrap());
+        let api_keys = Arc::new(ApiKeyManager::new(master.to_string(), None));
+        let state = AdminState::new(vec![], AdminService::new(token_manager, api_keys, None));
+
+        let body_for = |role| {
+            let state = state.clone();
+            let session = state.sessions.create_session("alice", role);
+            async move {
+                let response =
+                    super::super::handlers::list_api_keys(State(state), Extension(session))
+                        .await
+                        .into_response();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                String::from_utf8(bytes.to_vec()).unwrap()
+            }
+        };
+
+        assert!(!body_for(AdminRole::Viewer).await.contains(master));
+        assert!(body_for(AdminRole::Operator).await.contains(master));
+    }
+
+    #[test]
+    fn test_revoke_by_session_id() {
+        let config = AdminSessionConfig::default();
+        let manager = SessionManager::new();
+        let a = manager.create_session("admin", AdminRole::Operator);
+        let b = manager.create_session("admin", AdminRole::Operator);
+
+        assert!(manager.revoke(&a.id));
+        assert!(!manager.revoke(&a.id));
+        assert!(manager.validate(&a.token, &config).is_none());
+        assert!(manager.validate(&b.token, &config).is_some());
+        assert_eq!(manager.list(&config).len(), 1);
     }
 }
@@ -17,7 +17,7 @@
 //! ```
 
 mod error;
-mod handlers;
+pub(crate) mod handlers;
 mod middleware;
 mod router;
 mod service;
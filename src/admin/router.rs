@@ -5,12 +5,20 @@ use axum::{
 
 use super::{
     handlers::{
-        add_credential, create_api_key, delete_api_key, delete_credential, export_credential,
-        export_credentials, get_all_credentials, get_api_stats, get_credential_balance,
-        get_load_balancing_mode, get_log_enabled, get_request_logs, get_total_balance,
-        list_api_keys, login, reset_failure_count, set_api_key_disabled,
-        set_credential_disabled, set_credential_priority, set_load_balancing_mode,
-        set_log_enabled,
+        add_credential, clear_request_logs, create_api_key, delete_api_key, delete_credential,
+        discover_local_credentials, export_credential, export_credentials, export_request_logs,
+        get_all_credentials, get_api_stats, get_backups, get_canary, get_credential_balance,
+        get_credential_usage, get_endpoint_health, get_load_balancing_mode, get_log_enabled,
+        get_logging, get_model_mappings, get_output_limits, get_pricing,
+        get_request_log, get_request_logs, get_rotation, get_total_balance, get_user_usage,
+        import_api_keys, import_local_credentials, list_api_keys, list_sessions, login,
+        reload_config, reset_api_key_quota, reset_failure_count, revoke_session, run_backup,
+        set_api_key_body_limit, set_api_key_budget, set_api_key_compaction, set_api_key_disabled,
+        set_api_key_limits, set_api_key_quota, set_api_key_regions, set_api_key_scopes,
+        set_api_key_webhook, set_backup_enabled, set_canary_percentage, set_credential_canary,
+        set_credential_disabled, set_credential_priority, set_load_balancing_mode, set_log_enabled,
+        set_log_level, set_logging, set_model_mappings, set_output_limits, set_pricing,
+        set_rotation, stream_request_logs, test_credential, upload_credentials,
     },
     middleware::{AdminState, admin_auth_middleware},
 };
@@ -22,23 +30,65 @@ pub fn create_admin_router(state: AdminState) -> Router {
             get(get_all_credentials).post(add_credential),
         )
         .route("/credentials/export", get(export_credentials))
+        .route("/credentials/discover", get(discover_local_credentials))
+        .route(
+            "/credentials/discover/import",
+            post(import_local_credentials),
+        )
+        .route("/credentials/upload", post(upload_credentials))
         .route("/credentials/{id}", delete(delete_credential))
         .route("/credentials/{id}/export", get(export_credential))
         .route("/credentials/{id}/disabled", post(set_credential_disabled))
         .route("/credentials/{id}/priority", post(set_credential_priority))
         .route("/credentials/{id}/reset", post(reset_failure_count))
+        .route("/credentials/{id}/canary", post(set_credential_canary))
         .route("/credentials/{id}/balance", get(get_credential_balance))
+        .route("/credentials/{id}/usage", get(get_credential_usage))
+        .route("/credentials/{id}/test", post(test_credential))
         .route("/balance/total", get(get_total_balance))
         .route(
             "/config/load-balancing",
             get(get_load_balancing_mode).put(set_load_balancing_mode),
         )
+        .route("/config/canary", get(get_canary).put(set_canary_percentage))
+        .route("/config/reload", post(reload_config))
+        .route("/config/log-level", get(get_logging).put(set_log_level))
+        .route("/logging", get(get_logging).put(set_logging))
+        .route(
+            "/config/model-mappings",
+            get(get_model_mappings).put(set_model_mappings),
+        )
+        .route(
+            "/config/output-limits",
+            get(get_output_limits).put(set_output_limits),
+        )
+        .route("/config/pricing", get(get_pricing).put(set_pricing))
+        .route("/config/rotation", get(get_rotation).put(set_rotation))
+        .route("/backups", get(get_backups).post(run_backup))
+        .route("/backups/enabled", put(set_backup_enabled))
         .route("/apikeys", get(list_api_keys).post(create_api_key))
+        .route("/apikeys/import", post(import_api_keys))
         .route("/apikeys/{id}", delete(delete_api_key))
         .route("/apikeys/{id}/disabled", post(set_api_key_disabled))
+        .route("/apikeys/{id}/limits", put(set_api_key_limits))
+        .route("/apikeys/{id}/quota", put(set_api_key_quota))
+        .route("/apikeys/{id}/quota/reset", post(reset_api_key_quota))
+        .route("/apikeys/{id}/budget", put(set_api_key_budget))
+        .route("/apikeys/{id}/body-limit", put(set_api_key_body_limit))
+        .route("/apikeys/{id}/compaction", put(set_api_key_compaction))
+        .route("/apikeys/{id}/regions", put(set_api_key_regions))
+        .route("/apikeys/{id}/scopes", put(set_api_key_scopes))
+        .route("/apikeys/{id}/webhook", put(set_api_key_webhook))
         .route("/stats", get(get_api_stats))
-        .route("/logs", get(get_request_logs))
+        .route("/stats/endpoints", get(get_endpoint_health))
+        .route("/stats/users", get(get_user_usage))
+        .route("/logs", get(get_request_logs).delete(clear_request_logs))
+        .route("/logs/stream", get(stream_request_logs))
+        .route("/logs/export", get(export_request_logs))
+        .route("/logs/{id}", get(get_request_log))
         .route("/logs/enabled", get(get_log_enabled).post(set_log_enabled))
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/{id}", delete(revoke_session))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             admin_auth_middleware,
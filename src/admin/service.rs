@@ -1,28 +1,53 @@
 //! Admin API 业务逻辑服务
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Instant;
 
 use chrono::Utc;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 
-use crate::apikeys::{ApiKeyManager, ApiKeyPublicInfo, ApiKeyUsageOverview};
+use crate::apikeys::{
+    ApiKeyManager, ApiKeyPublicInfo, ApiKeyScope, ApiKeyStoreStatus, ApiKeyUsageOverview,
+    ApiKeyUserUsage,
+};
+use crate::backup::{BackupFile, BackupManager};
+use crate::endpoint_metrics::{EndpointHealthSnapshot, EndpointMetrics};
+use crate::kiro::credential_usage::CredentialUsageReport;
+use crate::kiro::discovery;
 use crate::kiro::model::credentials::KiroCredentials;
-use crate::kiro::token_manager::MultiTokenManager;
-use crate::request_log::{RequestLog, RequestLogEntry};
+use crate::kiro::provider::{KiroProvider, WARM_UP_MODEL};
+use crate::kiro::rotation;
+use crate::kiro::token_manager::{CanarySnapshot, MultiTokenManager, sha256_hex};
+use crate::model::config::{AdminSessionConfig, RotationConfig};
+use crate::pricing;
+use crate::request_log::{ExportQuery, LogPage, LogQuery, RequestLog, RequestLogEntry};
+use crate::webhook::KeyWebhook;
 
 use super::error::AdminServiceError;
 use super::types::{
-    AddCredentialRequest, AddCredentialResponse, BalanceResponse, CredentialStatusItem,
-    CredentialsStatusResponse, LoadBalancingModeResponse, SetLoadBalancingModeRequest,
-    TotalBalanceResponse,
+    AddCredentialRequest, AddCredentialResponse, BackupStatusResponse, BalanceResponse,
+    ConfigReloadResponse, CredentialStatusItem, CredentialTestResponse, CredentialsStatusResponse,
+    DiscoveredCredentialItem, ImportApiKeyItem, ImportApiKeyResult, ImportLocalCredentialResult,
+    LoadBalancingModeResponse, LoggingStatus, ModelMappingsPayload,
+    OutputLimitsPayload, PricingPayload, RotationResponse, SetApiKeyWebhookResponse,
+    SetBackupEnabledRequest, SetCanaryPercentageRequest, SetLoadBalancingModeRequest,
+    SetLoggingRequest, SetRotationRequest, TotalBalanceResponse, UploadCredentialFilesRequest,
+    WarmUpResult,
 };
 
 /// 余额缓存过期时间（秒），5 分钟
 const BALANCE_CACHE_TTL_SECS: i64 = 300;
 
+/// 临时日志过滤规则的最长生效时间（秒），7 天
+const MAX_LOGGING_DURATION_SECS: u64 = 7 * 24 * 3600;
+
+/// 单次上传导入的文件数上限
+const MAX_UPLOADED_FILES: usize = 200;
+
 /// 缓存的余额条目（含时间戳）
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct CachedBalance {
@@ -41,6 +66,10 @@ pub struct AdminService {
     balance_cache: Mutex<HashMap<u64, CachedBalance>>,
     cache_path: Option<PathBuf>,
     request_log: Option<Arc<RequestLog>>,
+    endpoint_metrics: Arc<EndpointMetrics>,
+    backups: Option<Arc<BackupManager>>,
+    /// 用于凭据预热请求
+    provider: Option<Arc<KiroProvider>>,
 }
 
 impl AdminService {
@@ -57,9 +86,30 @@ impl AdminService {
             balance_cache: Mutex::new(balance_cache),
             cache_path,
             request_log,
+            endpoint_metrics: Arc::new(EndpointMetrics::new()),
+            backups: None,
+            provider: None,
         }
     }
 
+    /// 使用与业务路由共享的端点族指标收集器
+    pub fn with_endpoint_metrics(mut self, metrics: Arc<EndpointMetrics>) -> Self {
+        self.endpoint_metrics = metrics;
+        self
+    }
+
+    /// 启用备份管理接口
+    pub fn with_backups(mut self, backups: Arc<BackupManager>) -> Self {
+        self.backups = Some(backups);
+        self
+    }
+
+    /// 使用业务路由的 KiroProvider 发送凭据预热请求
+    pub fn with_provider(mut self, provider: Arc<KiroProvider>) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
     /// 获取所有凭据状态
     pub fn get_all_credentials(&self) -> CredentialsStatusResponse {
         let snapshot = self.token_manager.snapshot();
@@ -78,10 +128,16 @@ impl AdminService {
                 has_profile_arn: entry.has_profile_arn,
                 refresh_token_hash: entry.refresh_token_hash,
                 email: entry.email,
+                subscription_title: entry.subscription_title,
+                circuit_trips: entry.circuit_trips,
+                cooldown_until: entry.cooldown_until,
                 success_count: entry.success_count,
                 last_used_at: entry.last_used_at.clone(),
                 has_proxy: entry.has_proxy,
                 proxy_url: entry.proxy_url,
+                canary: entry.canary,
+                error_counts: entry.error_counts,
+                proxy_health: entry.proxy_health,
             })
             .collect();
 
@@ -97,7 +153,23 @@ impl AdminService {
     }
 
     /// 设置凭据禁用状态
-    pub fn set_disabled(&self, id: u64, disabled: bool) -> Result<(), AdminServiceError> {
+    pub async fn set_disabled(&self, id: u64, disabled: bool) -> Result<(), AdminServiceError> {
+        // 启用前先预热（配置 credentialWarmUp 时），失败则不启用
+        if !disabled
+            && self.token_manager.config().credential_warm_up
+            && let Some(WarmUpResult {
+                error: Some(error), ..
+            }) = self.warm_up(id).await
+        {
+            if error.contains("不存在") {
+                return Err(AdminServiceError::NotFound { id });
+            }
+            return Err(AdminServiceError::UpstreamError(format!(
+                "预热请求失败，未启用凭据: {}",
+                error
+            )));
+        }
+
         // 先获取当前凭据 ID，用于判断是否需要切换
         let snapshot = self.token_manager.snapshot();
         let current_id = snapshot.current_id;
@@ -127,6 +199,24 @@ impl AdminService {
             .map_err(|e| self.classify_error(e, id))
     }
 
+    /// 设置凭据是否属于 canary 凭据池
+    pub fn set_canary(&self, id: u64, canary: bool) -> Result<(), AdminServiceError> {
+        self.token_manager
+            .set_canary(id, canary)
+            .map_err(|e| self.classify_error(e, id))
+    }
+
+    /// 获取凭据用量（累计、最近 1 小时 / 24 小时 / 7 天 / 30 天与小时明细）
+    pub fn get_credential_usage(
+        &self,
+        id: u64,
+        hours: i64,
+    ) -> Result<CredentialUsageReport, AdminServiceError> {
+        self.token_manager
+            .credential_usage(id, hours)
+            .map_err(|e| self.classify_error(e, id))
+    }
+
     /// 获取凭据余额（带缓存）
     pub async fn get_balance(&self, id: u64) -> Result<BalanceResponse, AdminServiceError> {
         // 先查缓存
@@ -203,11 +293,7 @@ impl AdminService {
         let current_usage = usage.current_usage();
         let usage_limit = usage.usage_limit();
         let remaining = (usage_limit - current_usage).max(0.0);
-        let usage_percentage = if usage_limit > 0.0 {
-            (current_usage / usage_limit * 100.0).min(100.0)
-        } else {
-            0.0
-        };
+        let usage_percentage = usage.usage_percentage();
 
         Ok(BalanceResponse {
             id,
@@ -227,6 +313,10 @@ impl AdminService {
     ) -> Result<AddCredentialResponse, AdminServiceError> {
         // 构建凭据对象
         let email = req.email.clone();
+        let warm_up = self.provider.is_some()
+            && req
+                .warm_up
+                .unwrap_or_else(|| self.token_manager.config().credential_warm_up);
         let new_cred = KiroCredentials {
             id: None,
             access_token: None,
@@ -246,7 +336,9 @@ impl AdminService {
             proxy_url: req.proxy_url,
             proxy_username: req.proxy_username,
             proxy_password: req.proxy_password,
-            disabled: false, // 新添加的凭据默认启用
+            disabled: warm_up, // 新添加的凭据默认启用，需要预热时在预热成功后启用
+            canary: false,
+            request_policy: req.request_policy,
         };
 
         // 调用 token_manager 添加凭据
@@ -256,19 +348,235 @@ impl AdminService {
             .await
             .map_err(|e| self.classify_add_error(e))?;
 
-        // 主动获取订阅等级，避免首次请求时 Free 账号绕过 Opus 模型过滤
-        if let Err(e) = self.token_manager.get_usage_limits_for(credential_id).await {
-            tracing::warn!("添加凭据后获取订阅等级失败（不影响凭据添加）: {}", e);
-        }
+        // 主动获取订阅等级与账号邮箱，避免首次请求时 Free 账号绕过 Opus 模型过滤
+        let email = match self.token_manager.get_usage_limits_for(credential_id).await {
+            Ok(usage) => usage.email().map(str::to_string).or(email),
+            Err(e) => {
+                tracing::warn!("添加凭据后获取订阅等级失败（不影响凭据添加）: {}", e);
+                email
+            }
+        };
+
+        let warm_up = if warm_up {
+            self.warm_up(credential_id).await
+        } else {
+            None
+        };
+        let message = match &warm_up {
+            Some(result) if !result.success => {
+                format!(
+                    "凭据添加成功，ID: {}（预热失败，凭据保持禁用）",
+                    credential_id
+                )
+            }
+            Some(_) => {
+                if let Err(e) = self.token_manager.set_disabled(credential_id, false) {
+                    tracing::warn!("预热成功后启用凭据 #{} 失败: {}", credential_id, e);
+                }
+                format!("凭据添加成功，ID: {}（预热成功）", credential_id)
+            }
+            None => format!("凭据添加成功，ID: {}", credential_id),
+        };
 
         Ok(AddCredentialResponse {
             success: true,
-            message: format!("凭据添加成功，ID: {}", credential_id),
+            message,
             credential_id,
             email,
+            warm_up,
         })
     }
 
+    /// 用指定凭据发送预热请求（未接入 KiroProvider 时返回 None）
+    async fn warm_up(&self, id: u64) -> Option<WarmUpResult> {
+        let provider = self.provider.as_ref()?;
+        let started = Instant::now();
+        let result = provider.warm_up(id).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+        match result {
+            Ok(()) => {
+                tracing::info!("凭据 #{} 预热成功，耗时 {}ms", id, latency_ms);
+                Some(WarmUpResult {
+                    success: true,
+                    latency_ms,
+                    error: None,
+                })
+            }
+            Err(e) => {
+                tracing::warn!("凭据 #{} 预热失败: {}", id, e);
+                Some(WarmUpResult {
+                    success: false,
+                    latency_ms,
+                    error: Some(e.to_string()),
+                })
+            }
+        }
+    }
+
+    /// 用指定凭据发送一条简短提示词，检查凭据能否实际调用模型
+    ///
+    /// 不计入凭据的成功 / 失败统计，也不影响凭据的启用状态
+    pub async fn test_credential(
+        &self,
+        id: u64,
+        model: Option<String>,
+    ) -> Result<CredentialTestResponse, AdminServiceError> {
+        if !self
+            .token_manager
+            .snapshot()
+            .entries
+            .iter()
+            .any(|e| e.id == id)
+        {
+            return Err(AdminServiceError::NotFound { id });
+        }
+        let provider = self
+            .provider
+            .as_ref()
+            .ok_or_else(|| AdminServiceError::InternalError("未接入 KiroProvider".to_string()))?;
+        let model = model
+            .map(|m| crate::anthropic::converter::map_model(&m).unwrap_or(m))
+            .unwrap_or_else(|| WARM_UP_MODEL.to_string());
+
+        let started = Instant::now();
+        let result = provider.probe(id, Some(&model)).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+        Ok(match result {
+            Ok(outcome) => {
+                tracing::info!("凭据 #{} 测试成功（{}），耗时 {}ms", id, model, latency_ms);
+                CredentialTestResponse {
+                    id,
+                    success: true,
+                    model,
+                    latency_ms,
+                    first_byte_ms: Some(outcome.first_byte_ms),
+                    reply: Some(outcome.reply.chars().take(200).collect()),
+                    error: None,
+                }
+            }
+            Err(e) => {
+                tracing::warn!("凭据 #{} 测试失败（{}）: {}", id, model, e);
+                CredentialTestResponse {
+                    id,
+                    success: false,
+                    model,
+                    latency_ms,
+                    first_byte_ms: None,
+                    reply: None,
+                    error: Some(e.to_string()),
+                }
+            }
+        })
+    }
+
+    /// 扫描本机 Kiro IDE / AWS SSO 令牌缓存
+    pub fn discover_local_credentials(&self) -> Vec<DiscoveredCredentialItem> {
+        let existing: HashSet<String> = self
+            .token_manager
+            .snapshot()
+            .entries
+            .into_iter()
+            .filter_map(|e| e.refresh_token_hash)
+            .collect();
+        discovery::discover()
+            .into_iter()
+            .map(|d| {
+                let hash = sha256_hex(d.credentials.refresh_token.as_deref().unwrap_or_default());
+                DiscoveredCredentialItem {
+                    source: d.source.display().to_string(),
+                    auth_method: d.credentials.auth_method,
+                    provider: d.provider,
+                    expires_at: d.credentials.expires_at,
+                    already_imported: existing.contains(&hash),
+                    refresh_token_hash: hash,
+                }
+            })
+            .collect()
+    }
+
+    /// 导入扫描到的本机凭据（逐个刷新校验，已存在的凭据会报告重复）
+    pub async fn import_local_credentials(
+        &self,
+        sources: Option<Vec<String>>,
+    ) -> Vec<ImportLocalCredentialResult> {
+        let mut results = Vec::new();
+        for found in discovery::discover() {
+            let source = found.source.display().to_string();
+            if sources.as_ref().is_some_and(|s| !s.contains(&source)) {
+                continue;
+            }
+            let result = match self.token_manager.add_credential(found.credentials).await {
+                Ok(id) => ImportLocalCredentialResult {
+                    source,
+                    success: true,
+                    credential_id: Some(id),
+                    message: format!("凭据导入成功，ID: {}", id),
+                },
+                Err(e) => ImportLocalCredentialResult {
+                    source,
+                    success: false,
+                    credential_id: None,
+                    message: e.to_string(),
+                },
+            };
+            results.push(result);
+        }
+        results
+    }
+
+    /// 导入上传的令牌文件（逐个刷新校验），未解析出凭据的文件也会出现在结果中
+    pub async fn import_uploaded_credentials(
+        &self,
+        req: UploadCredentialFilesRequest,
+    ) -> Result<Vec<ImportLocalCredentialResult>, AdminServiceError> {
+        if req.files.is_empty() {
+            return Err(AdminServiceError::InvalidCredential(
+                "未上传任何文件".to_string(),
+            ));
+        }
+        if req.files.len() > MAX_UPLOADED_FILES {
+            return Err(AdminServiceError::InvalidCredential(format!(
+                "单次最多上传 {} 个文件",
+                MAX_UPLOADED_FILES
+            )));
+        }
+        let files: Vec<(String, String)> =
+            req.files.into_iter().map(|f| (f.name, f.content)).collect();
+        let (found, skipped) = discovery::parse_uploaded(&files);
+
+        let mut results = Vec::new();
+        for found in found {
+            let source = found.source.display().to_string();
+            let result = match self.token_manager.add_credential(found.credentials).await {
+                Ok(id) => ImportLocalCredentialResult {
+                    source,
+                    success: true,
+                    credential_id: Some(id),
+                    message: format!("凭据导入成功，ID: {}", id),
+                },
+                Err(e) => ImportLocalCredentialResult {
+                    source,
+                    success: false,
+                    credential_id: None,
+                    message: e.to_string(),
+                },
+            };
+            results.push(result);
+        }
+        results.extend(
+            skipped
+                .into_iter()
+                .map(|source| ImportLocalCredentialResult {
+                    source,
+                    success: false,
+                    credential_id: None,
+                    message: "未找到可导入的令牌（IdC 令牌须同时上传对应的客户端注册文件）"
+                        .to_string(),
+                }),
+        );
+        Ok(results)
+    }
+
     /// 删除凭据
     pub fn delete_credential(&self, id: u64) -> Result<(), AdminServiceError> {
         self.token_manager
@@ -289,15 +597,86 @@ impl AdminService {
         self.api_keys.list()
     }
 
+    /// API Key 存储状态
+    pub fn api_key_store_status(&self) -> ApiKeyStoreStatus {
+        self.api_keys.store_status()
+    }
+
     pub fn api_key_overview(&self) -> ApiKeyUsageOverview {
         self.api_keys.overview()
     }
 
-    pub fn create_api_key(&self, name: String) -> anyhow::Result<crate::apikeys::ApiKeyRecord> {
+    /// 按终端用户（`metadata.user_id`）聚合的用量
+    pub fn user_usage(&self, api_key_id: Option<&str>) -> Vec<ApiKeyUserUsage> {
+        self.api_keys.user_usage(api_key_id)
+    }
+
+    /// 获取 /v1 与 /cc/v1 端点族的健康指标
+    pub fn endpoint_health(&self) -> EndpointHealthSnapshot {
+        self.endpoint_metrics.snapshot()
+    }
+
+    pub fn create_api_key(
+        &self,
+        name: String,
+        key: Option<String>,
+    ) -> anyhow::Result<crate::apikeys::ApiKeyRecord> {
         if name.trim().is_empty() {
             anyhow::bail!("name 不能为空");
         }
-        Ok(self.api_keys.create_key(name))
+        match key {
+            Some(key) => self.api_keys.create_key_with_value(name, key),
+            None => Ok(self.api_keys.create_key(name)),
+        }
+    }
+
+    /// 批量导入已有的 API Key（保留原 Key 与限流、配额），逐个返回结果，失败的条目不影响其他条目
+    pub fn import_api_keys(&self, items: Vec<ImportApiKeyItem>) -> Vec<ImportApiKeyResult> {
+        items
+            .into_iter()
+            .map(|item| {
+                let key_preview = crate::apikeys::preview_key(&item.key);
+                let name = item.name.clone();
+                match self.import_api_key(item) {
+                    Ok(id) => ImportApiKeyResult {
+                        name,
+                        key_preview,
+                        success: true,
+                        id: Some(id),
+                        message: "导入成功".to_string(),
+                    },
+                    Err(e) => ImportApiKeyResult {
+                        name,
+                        key_preview,
+                        success: false,
+                        id: None,
+                        message: e.to_string(),
+                    },
+                }
+            })
+            .collect()
+    }
+
+    fn import_api_key(&self, item: ImportApiKeyItem) -> anyhow::Result<String> {
+        if [
+            item.rpm_limit,
+            item.tpm_limit,
+            item.daily_token_limit,
+            item.monthly_token_limit,
+        ]
+        .contains(&Some(0))
+        {
+            anyhow::bail!("限流上限与配额必须大于 0（不限制请传 null）");
+        }
+        let record = self.create_api_key(item.name, Some(item.key))?;
+        self.api_keys
+            .set_limits(&record.id, item.rpm_limit, item.tpm_limit);
+        self.api_keys
+            .set_quota(&record.id, item.daily_token_limit, item.monthly_token_limit);
+        if !item.enabled {
+            self.api_keys.set_enabled(&record.id, false);
+        }
+        Ok(record.id)
     }
 
     pub fn set_api_key_enabled(&self, id: &str, enabled: bool) -> anyhow::Result<()> {
@@ -307,6 +686,166 @@ impl AdminService {
         anyhow::bail!("api key 不存在: {}", id)
     }
 
+    pub fn set_api_key_limits(
+        &self,
+        id: &str,
+        rpm_limit: Option<u64>,
+        tpm_limit: Option<u64>,
+    ) -> anyhow::Result<()> {
+        if rpm_limit == Some(0) || tpm_limit == Some(0) {
+            anyhow::bail!("限流上限必须大于 0（不限制请传 null）");
+        }
+        if self.api_keys.set_limits(id, rpm_limit, tpm_limit) {
+            return Ok(());
+        }
+        anyhow::bail!("api key 不存在: {}", id)
+    }
+
+    pub fn set_api_key_quota(
+        &self,
+        id: &str,
+        daily_token_limit: Option<u64>,
+        monthly_token_limit: Option<u64>,
+    ) -> anyhow::Result<()> {
+        if daily_token_limit == Some(0) || monthly_token_limit == Some(0) {
+            anyhow::bail!("配额必须大于 0（不限制请传 null）");
+        }
+        if self
+            .api_keys
+            .set_quota(id, daily_token_limit, monthly_token_limit)
+        {
+            return Ok(());
+        }
+        anyhow::bail!("api key 不存在: {}", id)
+    }
+
+    pub fn set_api_key_budget(
+        &self,
+        id: &str,
+        monthly_budget_usd: Option<f64>,
+    ) -> anyhow::Result<()> {
+        if let Some(budget) = monthly_budget_usd
+            && !(budget.is_finite() && budget > 0.0)
+        {
+            anyhow::bail!("预算必须大于 0（不限制请传 null）");
+        }
+        if self
+            .api_keys
+            .set_budget(id, monthly_budget_usd.map(pricing::from_usd))
+        {
+            return Ok(());
+        }
+        anyhow::bail!("api key 不存在: {}", id)
+    }
+
+    pub fn set_api_key_body_limit(
+        &self,
+        id: &str,
+        max_body_bytes: Option<u64>,
+    ) -> anyhow::Result<()> {
+        if max_body_bytes == Some(0) {
+            anyhow::bail!("请求体大小上限必须大于 0（沿用全局配置请传 null）");
+        }
+        if self.api_keys.set_max_body_bytes(id, max_body_bytes) {
+            return Ok(());
+        }
+        anyhow::bail!("api key 不存在: {}", id)
+    }
+
+    pub fn set_api_key_compaction(
+        &self,
+        id: &str,
+        threshold_percent: Option<f64>,
+    ) -> anyhow::Result<()> {
+        if threshold_percent.is_some_and(|t| !(0.0..=100.0).contains(&t)) {
+            anyhow::bail!("压缩阈值必须在 0-100 之间（沿用全局配置请传 null）");
+        }
+        if self
+            .api_keys
+            .set_compaction_threshold(id, threshold_percent)
+        {
+            return Ok(());
+        }
+        anyhow::bail!("api key 不存在: {}", id)
+    }
+
+    pub fn set_api_key_regions(
+        &self,
+        id: &str,
+        allowed_regions: Option<Vec<String>>,
+    ) -> anyhow::Result<()> {
+        let regions: Vec<String> = allowed_regions
+            .unwrap_or_default()
+            .iter()
+            .map(|r| r.trim().to_ascii_lowercase())
+            .filter(|r| !r.is_empty())
+            .collect();
+        if let Some(invalid) = regions
+            .iter()
+            .find(|r| !r.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        {
+            anyhow::bail!("无效的地域: {}", invalid);
+        }
+        let regions = (!regions.is_empty()).then_some(regions);
+        if self.api_keys.set_allowed_regions(id, regions.as_deref()) {
+            return Ok(());
+        }
+        anyhow::bail!("api key 不存在: {}", id)
+    }
+
+    pub fn set_api_key_scopes(
+        &self,
+        id: &str,
+        scopes: Option<Vec<ApiKeyScope>>,
+    ) -> anyhow::Result<()> {
+        let scopes = scopes.map(|mut scopes| {
+            scopes.sort();
+            scopes.dedup();
+            scopes
+        });
+        if scopes.as_ref().is_some_and(|s| s.is_empty()) {
+            anyhow::bail!("scopes 不能为空列表（不限制请传 null）");
+        }
+        if self.api_keys.set_scopes(id, scopes.as_deref()) {
+            return Ok(());
+        }
+        anyhow::bail!("api key 不存在: {}", id)
+    }
+
+    /// 设置请求完成回调，每次设置都会生成新的签名密钥
+    pub fn set_api_key_webhook(
+        &self,
+        id: &str,
+        url: Option<String>,
+    ) -> anyhow::Result<SetApiKeyWebhookResponse> {
+        let url = url.map(|u| u.trim().to_string()).filter(|u| !u.is_empty());
+        if let Some(url) = &url {
+            let parsed =
+                reqwest::Url::parse(url).map_err(|e| anyhow::anyhow!("无效的回调地址: {}", e))?;
+            if !matches!(parsed.scheme(), "http" | "https") {
+                anyhow::bail!("回调地址必须为 http 或 https");
+            }
+        }
+        let webhook = url.map(|url| KeyWebhook {
+            url,
+            secret: format!("whsec_{}", uuid::Uuid::new_v4().simple()),
+        });
+        if !self.api_keys.set_webhook(id, webhook.as_ref()) {
+            anyhow::bail!("api key 不存在: {}", id);
+        }
+        Ok(SetApiKeyWebhookResponse {
+            url: webhook.as_ref().map(|w| w.url.clone()),
+            secret: webhook.map(|w| w.secret),
+        })
+    }
+
+    pub fn reset_api_key_quota(&self, id: &str) -> anyhow::Result<()> {
+        if self.api_keys.reset_quota_usage(id) {
+            return Ok(());
+        }
+        anyhow::bail!("api key 不存在: {}", id)
+    }
+
     pub fn delete_api_key(&self, id: &str) -> anyhow::Result<()> {
         if self.api_keys.delete_key(id) {
             return Ok(());
@@ -326,12 +865,59 @@ impl AdminService {
             .map_err(|e| self.classify_error(e, id))
     }
 
+    /// 在阻塞线程中访问请求日志（持久化存储需等待后台写入并读取 SQLite，不占用异步工作线程）
+    async fn with_request_log<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&RequestLog) -> T + Send + 'static,
+    ) -> Option<T> {
+        let log = self.request_log.clone()?;
+        tokio::task::spawn_blocking(move || f(&log))
+            .await
+            .map_err(|e| tracing::warn!("访问请求日志失败: {}", e))
+            .ok()
+    }
+
     /// 获取请求日志
-    pub fn get_request_logs(&self, since_id: Option<&str>) -> Vec<RequestLogEntry> {
-        match &self.request_log {
-            Some(log) => log.entries_since(since_id),
-            None => vec![],
-        }
+    pub async fn get_request_logs(&self, query: &LogQuery) -> LogPage {
+        let query = query.clone();
+        self.with_request_log(move |log| log.query(&query))
+            .await
+            .unwrap_or(LogPage {
+                entries: vec![],
+                total: 0,
+                page: 1,
+                page_size: 0,
+            })
+    }
+
+    /// 按 ID 获取单条请求日志（含外置的完整请求/响应体）
+    pub async fn get_request_log(&self, id: &str) -> Option<RequestLogEntry> {
+        let id = id.to_string();
+        self.with_request_log(move |log| log.get(&id))
+            .await
+            .flatten()
+    }
+
+    /// 分批导出请求日志，参数与返回值见 [`RequestLog::export_batch`]
+    pub async fn export_request_logs(
+        &self,
+        query: &ExportQuery,
+        cursor: i64,
+    ) -> (Vec<RequestLogEntry>, Option<i64>) {
+        let query = query.clone();
+        self.with_request_log(move |log| log.export_batch(&query, cursor))
+            .await
+            .unwrap_or((vec![], None))
+    }
+
+    /// 清空请求日志（包括已持久化的日志与外置内容）
+    pub async fn clear_request_logs(&self) {
+        self.with_request_log(|log| log.clear()).await;
+    }
+
+    /// 订阅实时请求日志
+    pub fn subscribe_logs(&self) -> Option<broadcast::Receiver<RequestLogEntry>> {
+        self.request_log.as_ref().map(|log| log.subscribe())
     }
 
     /// 设置请求日志开关
@@ -372,6 +958,331 @@ impl AdminService {
         Ok(LoadBalancingModeResponse { mode: req.mode })
     }
 
+    /// 获取灰度路由状态
+    pub fn get_canary(&self) -> CanarySnapshot {
+        self.token_manager.canary_snapshot()
+    }
+
+    /// 设置灰度流量百分比
+    pub fn set_canary_percentage(
+        &self,
+        req: SetCanaryPercentageRequest,
+    ) -> Result<CanarySnapshot, AdminServiceError> {
+        if req.percentage > 100 {
+            return Err(AdminServiceError::InvalidCredential(
+                "percentage 必须在 0-100 之间".to_string(),
+            ));
+        }
+
+        self.token_manager
+            .set_canary_percentage(req.percentage)
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+
+        Ok(self.token_manager.canary_snapshot())
+    }
+
+    /// 获取模型别名映射
+    pub fn get_model_mappings(&self) -> ModelMappingsPayload {
+        ModelMappingsPayload {
+            mappings: self.token_manager.config().model_mappings.clone(),
+        }
+    }
+
+    /// 替换模型别名映射
+    pub fn set_model_mappings(
+        &self,
+        req: ModelMappingsPayload,
+    ) -> Result<ModelMappingsPayload, AdminServiceError> {
+        let mut mappings = BTreeMap::new();
+        for (alias, target) in req.mappings {
+            let (alias, target) = (alias.trim().to_string(), target.trim().to_string());
+            if alias.is_empty() || target.is_empty() {
+                return Err(AdminServiceError::InvalidCredential(
+                    "模型名与映射目标均不能为空".to_string(),
+                ));
+            }
+            mappings.insert(alias, target);
+        }
+
+        self.token_manager
+            .set_model_mappings(mappings)
+            .map_err(|e| AdminServiceError::InternalError(format!("{:#}", e)))?;
+        Ok(self.get_model_mappings())
+    }
+
+    /// 获取模型输出上限设置
+    pub fn get_output_limits(&self) -> OutputLimitsPayload {
+        let config = self.token_manager.config();
+        OutputLimitsPayload {
+            limits: config.model_max_output_tokens.clone(),
+            default_max_tokens: config.default_max_tokens,
+            overflow_strategy: config.max_tokens_overflow_strategy.clone(),
+        }
+    }
+
+    /// 替换模型输出上限设置，立即用于后续请求
+    pub fn set_output_limits(
+        &self,
+        req: OutputLimitsPayload,
+    ) -> Result<OutputLimitsPayload, AdminServiceError> {
+        let mut limits = BTreeMap::new();
+        for (model, limit) in req.limits {
+            let model = model.trim().to_string();
+            if model.is_empty() {
+                return Err(AdminServiceError::InvalidCredential(
+                    "模型名不能为空".to_string(),
+                ));
+            }
+            if limit <= 0 {
+                return Err(AdminServiceError::InvalidCredential(format!(
+                    "模型 {} 的输出上限必须大于 0",
+                    model
+                )));
+            }
+            limits.insert(model, limit);
+        }
+        if req.default_max_tokens <= 0 {
+            return Err(AdminServiceError::InvalidCredential(
+                "defaultMaxTokens 必须大于 0".to_string(),
+            ));
+        }
+        let strategy = req.overflow_strategy.trim().to_lowercase();
+        if strategy != "clamp" && strategy != "reject" {
+            return Err(AdminServiceError::InvalidCredential(format!(
+                "无效的超限策略: {}，可选值为 clamp / reject",
+                req.overflow_strategy
+            )));
+        }
+
+        self.token_manager
+            .set_output_limits(limits, req.default_max_tokens, strategy)
+            .map_err(|e| AdminServiceError::InternalError(format!("{:#}", e)))?;
+        Ok(self.get_output_limits())
+    }
+
+    /// 获取模型单价表
+    pub fn get_pricing(&self) -> PricingPayload {
+        PricingPayload {
+            prices: self.token_manager.config().pricing.clone(),
+        }
+    }
+
+    /// 替换模型单价表，立即用于后续请求的计费
+    pub fn set_pricing(&self, req: PricingPayload) -> Result<PricingPayload, AdminServiceError> {
+        let mut prices = BTreeMap::new();
+        for (model, price) in req.prices {
+            let model = model.trim().to_string();
+            if model.is_empty() {
+                return Err(AdminServiceError::InvalidCredential(
+                    "模型名不能为空".to_string(),
+                ));
+            }
+            let valid = |v: f64| v.is_finite() && v >= 0.0;
+            if !valid(price.input_per_mtok) || !valid(price.output_per_mtok) {
+                return Err(AdminServiceError::InvalidCredential(format!(
+                    "模型 {} 的单价必须为非负数",
+                    model
+                )));
+            }
+            prices.insert(model, price);
+        }
+
+        self.token_manager
+            .set_pricing(prices.clone())
+            .map_err(|e| AdminServiceError::InternalError(format!("{:#}", e)))?;
+        pricing::apply(&prices);
+        Ok(self.get_pricing())
+    }
+
+    /// 当前生效的 Admin 会话配置
+    pub fn session_config(&self) -> AdminSessionConfig {
+        self.token_manager
+            .config()
+            .admin_session
+            .clone()
+            .unwrap_or_default()
+    }
+
+    /// 获取凭据轮换策略与当前生效的组
+    pub fn get_rotation(&self) -> RotationResponse {
+        let policy = self
+            .token_manager
+            .config()
+            .rotation
+            .clone()
+            .unwrap_or(RotationConfig {
+                enabled: false,
+                period_hours: 24,
+                groups: Vec::new(),
+            });
+        let now = Utc::now();
+        let mut inactive: Vec<u64> = rotation::inactive_credentials(&policy, now)
+            .into_iter()
+            .collect();
+        inactive.sort_unstable();
+        RotationResponse {
+            active_group: rotation::active_group(&policy, now),
+            inactive_credentials: inactive,
+            next_rotation_at: rotation::next_rotation(&policy, now).map(|t| t.to_rfc3339()),
+            enabled: policy.enabled,
+            period_hours: policy.period_hours,
+            groups: policy.groups,
+        }
+    }
+
+    /// 设置凭据轮换策略
+    pub fn set_rotation(
+        &self,
+        req: SetRotationRequest,
+    ) -> Result<RotationResponse, AdminServiceError> {
+        if req.period_hours == 0 {
+            return Err(AdminServiceError::InvalidCredential(
+                "轮换周期必须大于 0 小时".to_string(),
+            ));
+        }
+        let existing: HashSet<u64> = self
+            .token_manager
+            .snapshot()
+            .entries
+            .iter()
+            .map(|e| e.id)
+            .collect();
+        for group in &req.groups {
+            if group.is_empty() {
+                return Err(AdminServiceError::InvalidCredential(
+                    "凭据组不能为空".to_string(),
+                ));
+            }
+            if let Some(id) = group.iter().find(|id| !existing.contains(id)) {
+                return Err(AdminServiceError::InvalidCredential(format!(
+                    "凭据不存在: {}",
+                    id
+                )));
+            }
+        }
+
+        self.token_manager
+            .set_rotation(RotationConfig {
+                enabled: req.enabled,
+                period_hours: req.period_hours,
+                groups: req.groups,
+            })
+            .map_err(|e| AdminServiceError::InternalError(format!("{:#}", e)))?;
+        Ok(self.get_rotation())
+    }
+
+    /// 当前日志过滤规则与临时调整的恢复计划
+    pub fn get_logging(&self) -> Result<LoggingStatus, AdminServiceError> {
+        let filter = crate::logging::current_filter()
+            .ok_or_else(|| AdminServiceError::InternalError("日志系统未初始化".to_string()))?;
+        let revert = crate::logging::pending_revert();
+        Ok(LoggingStatus {
+            filter,
+            revert_at: revert.as_ref().map(|r| r.at.to_rfc3339()),
+            revert_to: revert.map(|r| r.filter),
+        })
+    }
+
+    /// 运行时调整日志过滤规则，指定 durationSecs 时到期自动恢复
+    pub fn set_logging(&self, req: SetLoggingRequest) -> Result<LoggingStatus, AdminServiceError> {
+        let result = match req.duration_secs {
+            Some(secs) if secs == 0 || secs > MAX_LOGGING_DURATION_SECS => {
+                return Err(AdminServiceError::InvalidCredential(format!(
+                    "durationSecs 必须在 1 到 {} 之间",
+                    MAX_LOGGING_DURATION_SECS
+                )));
+            }
+            Some(secs) => {
+                crate::logging::set_filter_for(&req.filter, std::time::Duration::from_secs(secs))
+                    .map(|_| ())
+            }
+            None => crate::logging::set_filter(&req.filter),
+        };
+        result.map_err(|e| AdminServiceError::InvalidCredential(format!("{:#}", e)))?;
+        match req.duration_secs {
+            Some(secs) => tracing::info!(
+                "日志过滤规则已临时调整为: {}（{} 秒后恢复）",
+                req.filter,
+                secs
+            ),
+            None => tracing::info!("日志过滤规则已调整为: {}", req.filter),
+        }
+        self.get_logging()
+    }
+
+    /// 重新加载 config.json 并应用可热更新的配置（不中断进行中的请求）
+    pub fn reload_config(&self) -> Result<ConfigReloadResponse, AdminServiceError> {
+        let restart_required = self
+            .token_manager
+            .reload_config()
+            .map_err(|e| AdminServiceError::InvalidCredential(format!("{:#}", e)))?;
+
+        let config = self.token_manager.config();
+        if let Err(e) = crate::logging::apply_config_level(config.log_level.as_deref()) {
+            tracing::warn!("应用日志级别失败: {}", e);
+        }
+        pricing::apply(&config.pricing);
+
+        let message = if restart_required.is_empty() {
+            "配置已重新加载".to_string()
+        } else {
+            format!("配置已重新加载，{} 项需重启后生效", restart_required.len())
+        };
+        Ok(ConfigReloadResponse {
+            success: true,
+            message,
+            restart_required: restart_required.into_iter().map(String::from).collect(),
+        })
+    }
+
+    fn backup_manager(&self) -> Result<&Arc<BackupManager>, AdminServiceError> {
+        self.backups
+            .as_ref()
+            .ok_or_else(|| AdminServiceError::InternalError("备份功能不可用".to_string()))
+    }
+
+    /// 获取备份状态与备份文件列表
+    pub fn backup_status(&self) -> Result<BackupStatusResponse, AdminServiceError> {
+        let backups = self.backup_manager()?;
+        let config = self.token_manager.config();
+        let backup = config.backup.clone().unwrap_or_default();
+        let last_error = backups.last_error();
+        Ok(BackupStatusResponse {
+            enabled: backup.enabled,
+            dir: backups.dir(&backup).display().to_string(),
+            interval_hours: backup.interval_hours,
+            keep: backup.keep,
+            password_set: backup.password.as_deref().is_some_and(|p| !p.is_empty()),
+            last_success_at: backups.last_success_at().map(|t| t.to_rfc3339()),
+            last_error_at: last_error.as_ref().map(|(at, _)| at.to_rfc3339()),
+            last_error: last_error.map(|(_, e)| e),
+            backups: backups.list(&backup),
+        })
+    }
+
+    /// 立即执行一次备份
+    pub async fn run_backup(&self) -> Result<BackupFile, AdminServiceError> {
+        let backups = self.backup_manager()?.clone();
+        let config = self.token_manager.config();
+        let backup = config.backup.clone().unwrap_or_default();
+        tokio::task::spawn_blocking(move || backups.run(&backup))
+            .await
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?
+            .map_err(|e| AdminServiceError::InternalError(format!("{:#}", e)))
+    }
+
+    /// 启用或停用定时备份
+    pub fn set_backup_enabled(
+        &self,
+        req: SetBackupEnabledRequest,
+    ) -> Result<BackupStatusResponse, AdminServiceError> {
+        self.backup_manager()?;
+        self.token_manager
+            .set_backup_enabled(req.enabled)
+            .map_err(|e| AdminServiceError::InternalError(format!("{:#}", e)))?;
+        self.backup_status()
+    }
+
     // ============ 余额缓存持久化 ============
 
     fn load_balance_cache_from(cache_path: &Option<PathBuf>) -> HashMap<u64, CachedBalance> {
@@ -1,14 +1,23 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
+use crate::apikeys::ApiKeyScope;
+use crate::backup::BackupFile;
+use crate::model::config::{AdminRole, ModelPrice, RequestPolicy};
 use crate::request_log::RequestLogEntry;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct RequestLogResponse {
     pub entries: Vec<RequestLogEntry>,
+    pub total: usize,
+    pub page: usize,
+    pub page_size: usize,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CredentialsStatusResponse {
     pub total: usize,
@@ -17,7 +26,7 @@ pub struct CredentialsStatusResponse {
     pub credentials: Vec<CredentialStatusItem>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CredentialStatusItem {
     pub id: u64,
@@ -30,26 +39,38 @@ pub struct CredentialStatusItem {
     pub has_profile_arn: bool,
     pub refresh_token_hash: Option<String>,
     pub email: Option<String>,
+    /// 订阅等级（添加凭据或查询余额时从上游获取）
+    pub subscription_title: Option<String>,
+    /// 连续熔断次数（启用 circuitBreaker 时）
+    pub circuit_trips: u32,
+    /// 熔断冷却结束时间（RFC3339），冷却结束后自动重新启用并探测
+    pub cooldown_until: Option<String>,
     pub success_count: u64,
     pub last_used_at: Option<String>,
     pub has_proxy: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy_url: Option<String>,
+    pub canary: bool,
+    /// 按错误类别（auth / throttle / server / timeout / other）累计的失败次数
+    pub error_counts: crate::kiro::token_manager::ErrorCounts,
+    /// 凭据级代理健康状态（仅配置了凭据代理时返回）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_health: Option<crate::kiro::proxy_health::ProxyHealthSnapshot>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SetDisabledRequest {
     pub disabled: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SetPriorityRequest {
     pub priority: u32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct AddCredentialRequest {
     pub refresh_token: String,
@@ -67,13 +88,20 @@ pub struct AddCredentialRequest {
     pub proxy_url: Option<String>,
     pub proxy_username: Option<String>,
     pub proxy_password: Option<String>,
+    /// 凭据级超时与重试策略，按字段覆盖配置 `requestPolicy`
+    #[serde(default)]
+    #[schema(value_type = Option<Object>)]
+    pub request_policy: Option<RequestPolicy>,
+    /// 是否发送预热请求，未指定时沿用配置 `credentialWarmUp`
+    #[serde(default)]
+    pub warm_up: Option<bool>,
 }
 
 fn default_auth_method() -> String {
     "social".to_string()
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct AddCredentialResponse {
     pub success: bool,
@@ -81,9 +109,73 @@ pub struct AddCredentialResponse {
     pub credential_id: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub email: Option<String>,
+    /// 预热请求结果（未预热时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warm_up: Option<WarmUpResult>,
+}
+
+/// 凭据预热结果
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WarmUpResult {
+    pub success: bool,
+    /// 预热请求耗时（含 Token 刷新）
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 本机发现的凭据（不含令牌明文）
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredCredentialItem {
+    /// 令牌文件路径，导入时用于指定要导入的凭据
+    pub source: String,
+    pub auth_method: Option<String>,
+    /// 登录方式（Github / Google / BuilderId 等）
+    pub provider: Option<String>,
+    pub expires_at: Option<String>,
+    pub refresh_token_hash: String,
+    /// 是否已存在相同 refreshToken 的凭据
+    pub already_imported: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportLocalCredentialsRequest {
+    /// 要导入的令牌文件路径（须为扫描结果之一），未指定时导入全部未导入的凭据
+    #[serde(default)]
+    pub sources: Option<Vec<String>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// 上传的令牌文件
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadedCredentialFile {
+    /// 文件名（可含相对路径），用于按 `clientIdHash` 关联客户端注册文件
+    pub name: String,
+    /// 文件内容（JSON 文本）
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadCredentialFilesRequest {
+    /// `kiro-auth-token.json`、AWS SSO 缓存令牌及对应的客户端注册文件
+    pub files: Vec<UploadedCredentialFile>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportLocalCredentialResult {
+    pub source: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_id: Option<u64>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct BalanceResponse {
     pub id: u64,
@@ -95,7 +187,7 @@ pub struct BalanceResponse {
     pub next_reset_at: Option<f64>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct TotalBalanceResponse {
     pub total_usage_limit: f64,
@@ -104,52 +196,378 @@ pub struct TotalBalanceResponse {
     pub credential_count: usize,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct LoadBalancingModeResponse {
     pub mode: String,
 }
 
-#[derive(Debug, Deserialize)]
+/// 设置 API Key 每日 / 每月 token 配额（null 表示不限制）
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetApiKeyQuotaRequest {
+    #[serde(default)]
+    pub daily_token_limit: Option<u64>,
+    #[serde(default)]
+    pub monthly_token_limit: Option<u64>,
+}
+
+/// 设置 API Key 月度费用预算（美元，null 表示不限制）
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetApiKeyBudgetRequest {
+    #[serde(default)]
+    pub monthly_budget_usd: Option<f64>,
+}
+
+/// 配置热重载结果
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigReloadResponse {
+    pub success: bool,
+    pub message: String,
+    /// 配置文件中已修改但需重启才能生效的字段
+    pub restart_required: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SetLoadBalancingModeRequest {
     pub mode: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetCanaryRequest {
+    pub canary: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetCanaryPercentageRequest {
+    pub percentage: u8,
+}
+
+/// 备份状态
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupStatusResponse {
+    /// 是否启用定时备份
+    pub enabled: bool,
+    pub dir: String,
+    pub interval_hours: u64,
+    pub keep: usize,
+    /// 是否已设置加密密码（未设置时无法备份）
+    pub password_set: bool,
+    pub last_success_at: Option<String>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<String>,
+    /// 备份文件（从新到旧）
+    pub backups: Vec<BackupFile>,
+}
+
+/// 模型别名映射（客户端模型名 → Kiro 模型 ID）
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelMappingsPayload {
+    pub mappings: BTreeMap<String, String>,
+}
+
+/// 模型输出上限设置
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputLimitsPayload {
+    /// Kiro 模型 ID（映射后）→ 最大输出 token
+    pub limits: BTreeMap<String, i32>,
+    /// 请求未指定 max_tokens 时使用的值
+    pub default_max_tokens: i32,
+    /// 超过上限时的处理策略：`clamp` 或 `reject`
+    pub overflow_strategy: String,
+}
+
+/// 模型单价表（客户端模型名 → `{inputPerMtok, outputPerMtok}`，`*` 为默认单价）
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PricingPayload {
+    #[schema(value_type = Object)]
+    pub prices: BTreeMap<String, ModelPrice>,
+}
+
+/// 日志过滤规则（EnvFilter 语法）
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLevelPayload {
+    /// 如 `info,kiro::provider=debug`；本程序的模块可省略 `kiro_rs::` 前缀
+    pub filter: String,
+}
+
+/// 运行时调整日志过滤规则
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetLoggingRequest {
+    /// EnvFilter 语法，如 `info,kiro=debug`；本程序的模块可省略 `kiro_rs::` 前缀
+    pub filter: String,
+    /// 生效时长（秒），到期后恢复为调整前的规则；不设置时一直生效到重启或热重载配置
+    #[serde(default)]
+    pub duration_secs: Option<u64>,
+}
+
+/// 当前日志过滤规则
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LoggingStatus {
+    pub filter: String,
+    /// 临时规则的到期时间（RFC3339）
+    pub revert_at: Option<String>,
+    /// 到期后恢复的规则
+    pub revert_to: Option<String>,
+}
+
+/// 凭据轮换策略
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetRotationRequest {
+    pub enabled: bool,
+    /// 每组凭据连续生效的时长（小时），按 UTC 对齐
+    #[serde(default = "default_rotation_period_hours")]
+    pub period_hours: u64,
+    /// 依次轮换的凭据组（凭据 ID），不在任何组中的凭据始终可用
+    pub groups: Vec<Vec<u64>>,
+}
+
+fn default_rotation_period_hours() -> u64 {
+    24
+}
+
+/// 凭据轮换策略与当前状态
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RotationResponse {
+    pub enabled: bool,
+    pub period_hours: u64,
+    pub groups: Vec<Vec<u64>>,
+    /// 当前生效的组序号（策略未生效时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_group: Option<usize>,
+    /// 当前被轮换停用的凭据
+    pub inactive_credentials: Vec<u64>,
+    /// 下一次轮换时间（RFC3339）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_rotation_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetBackupEnabledRequest {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct LoginResponse {
     pub success: bool,
     pub token: String,
     pub expires_at: String,
+    /// 账号角色：viewer（只读）/ operator
+    #[schema(value_type = String)]
+    pub role: AdminRole,
 }
 
-#[derive(Debug, Deserialize)]
+/// Admin 登录会话（不含登录令牌）
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminSessionInfo {
+    /// 会话 ID，撤销会话时使用
+    pub id: String,
+    pub username: String,
+    #[schema(value_type = String)]
+    pub role: AdminRole,
+    pub created_at: String,
+    pub last_active_at: String,
+    /// 按当前会话配置计算的过期时间
+    pub expires_at: String,
+    /// 是否为发起本次请求的会话
+    pub current: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateApiKeyRequest {
     pub name: String,
+    /// 自定义 Key（`sk-` 开头，至少 32 个字符且足够随机），未指定时自动生成
+    #[serde(default)]
+    pub key: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+/// 从其他网关导入的 API Key
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportApiKeyItem {
+    pub name: String,
+    pub key: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub rpm_limit: Option<u64>,
+    #[serde(default)]
+    pub tpm_limit: Option<u64>,
+    #[serde(default)]
+    pub daily_token_limit: Option<u64>,
+    #[serde(default)]
+    pub monthly_token_limit: Option<u64>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportApiKeysRequest {
+    pub keys: Vec<ImportApiKeyItem>,
+}
+
+/// 单个 API Key 的导入结果
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportApiKeyResult {
+    pub name: String,
+    pub key_preview: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SetApiKeyDisabledRequest {
     pub disabled: bool,
 }
 
-#[derive(Debug, Serialize)]
+/// 设置 API Key 限流上限请求（null 或省略表示不限制）
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetApiKeyLimitsRequest {
+    #[serde(default)]
+    pub rpm_limit: Option<u64>,
+    #[serde(default)]
+    pub tpm_limit: Option<u64>,
+}
+
+/// 设置 API Key 请求体大小上限（字节，null 表示沿用全局 `maxBodyBytes`）
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetApiKeyBodyLimitRequest {
+    #[serde(default)]
+    pub max_body_bytes: Option<u64>,
+}
+
+/// 设置 API Key 历史压缩阈值请求（0 表示不压缩，null 或省略表示沿用全局配置）
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetApiKeyCompactionRequest {
+    #[serde(default)]
+    pub threshold_percent: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetApiKeyRegionsRequest {
+    /// 允许使用的凭据 API Region，null 或空列表表示不限制
+    #[serde(default)]
+    pub allowed_regions: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetApiKeyScopesRequest {
+    /// 允许访问的端点范围，null 表示不限制
+    #[serde(default)]
+    pub scopes: Option<Vec<ApiKeyScope>>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetApiKeyWebhookRequest {
+    /// 请求完成回调地址（http / https），null 表示取消回调
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+/// 设置请求完成回调的结果
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetApiKeyWebhookResponse {
+    pub url: Option<String>,
+    /// 新生成的签名密钥（仅在此处返回一次，取消回调时为 null）
+    pub secret: Option<String>,
+}
+
+/// 凭据用量查询参数
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CredentialUsageQuery {
+    /// 小时明细覆盖的时长（小时），默认 24，最多 720（30 天）
+    pub hours: Option<i64>,
+}
+
+/// 凭据测试参数
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CredentialTestQuery {
+    /// 测试使用的模型（Anthropic 模型名或 Kiro 模型 ID），默认 claude-haiku-4.5
+    pub model: Option<String>,
+}
+
+/// 凭据测试结果
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialTestResponse {
+    pub id: u64,
+    pub success: bool,
+    /// 实际请求的 Kiro 模型 ID
+    pub model: String,
+    /// 请求总耗时（含 Token 刷新）
+    pub latency_ms: u64,
+    /// 首字节耗时（失败时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_byte_ms: Option<u64>,
+    /// 模型回复（最多 200 个字符，失败时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 终端用户用量查询参数
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct UserUsageQuery {
+    /// 只返回该 API Key 下的用户
+    pub api_key_id: Option<String>,
+}
+
+/// 终端用户用量
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserUsageResponse {
+    pub users: Vec<crate::apikeys::ApiKeyUserUsage>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiKeyListResponse {
     pub keys: Vec<crate::apikeys::ApiKeyPublicInfo>,
+    /// API Key 存储状态，数据库不可用时为降级模式
+    pub store: crate::apikeys::ApiKeyStoreStatus,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateApiKeyResponse {
     pub success: bool,
@@ -159,13 +577,13 @@ pub struct CreateApiKeyResponse {
     pub key_preview: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ApiStatsResponse {
     pub overview: crate::apikeys::ApiKeyUsageOverview,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct SuccessResponse {
     pub success: bool,
     pub message: String,
@@ -180,12 +598,12 @@ impl SuccessResponse {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct AdminErrorResponse {
     pub error: AdminError,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct AdminError {
     #[serde(rename = "type")]
     pub error_type: String,
@@ -213,6 +631,10 @@ impl AdminErrorResponse {
         )
     }
 
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new("permission_error", message)
+    }
+
     pub fn not_found(message: impl Into<String>) -> Self {
         Self::new("not_found", message)
     }
@@ -0,0 +1,574 @@
+//! Messages Batches API（/v1/messages/batches）
+//!
+//! 批次提交后立即返回，后台按全局并发上限逐个走 `POST /v1/messages` 的非流式流程，
+//! 结果写入 [`BatchStore`]，批次结束后可通过 results 端点以 JSONL 下载。
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use axum::{
+    Json as JsonExtractor,
+    body::Body,
+    extract::{Extension, Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::task::JoinSet;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::apikeys::AuthenticatedApiKey;
+use crate::batch::{BatchRequestItem, BatchStore, DeleteOutcome, MessageBatch, STATUS_ENDED};
+use crate::kiro::region_pin;
+
+use super::handlers::post_messages;
+use super::middleware::{AppState, quota_exceeded_response};
+use super::types::{ErrorResponse, MessagesRequest, RequestFlags};
+
+/// 单个批次最多包含的请求数
+const MAX_BATCH_REQUESTS: usize = 10_000;
+
+/// custom_id 最大长度
+const MAX_CUSTOM_ID_LEN: usize = 64;
+
+/// 列表默认/最大条数
+const DEFAULT_LIST_LIMIT: usize = 20;
+const MAX_LIST_LIMIT: usize = 1000;
+
+/// 创建批次请求体
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateBatchRequest {
+    pub requests: Vec<BatchRequestItem>,
+}
+
+/// 批次列表查询参数
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct ListBatchesQuery {
+    /// 每页条数，默认 20，最大 1000
+    pub limit: Option<usize>,
+    /// 返回该批次之后（更早创建）的批次
+    pub after_id: Option<String>,
+}
+
+/// 批次列表
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MessageBatchList {
+    pub data: Vec<MessageBatch>,
+    pub has_more: bool,
+    pub first_id: Option<String>,
+    pub last_id: Option<String>,
+}
+
+fn error_response(status: StatusCode, error_type: &str, message: impl Into<String>) -> Response {
+    (status, Json(ErrorResponse::new(error_type, message))).into_response()
+}
+
+fn not_found(id: &str) -> Response {
+    error_response(
+        StatusCode::NOT_FOUND,
+        "not_found_error",
+        format!("Message batch not found: {}", id),
+    )
+}
+
+fn store_error(e: anyhow::Error) -> Response {
+    tracing::error!("批处理存储操作失败: {}", e);
+    error_response(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "api_error",
+        format!("Batch store error: {}", e),
+    )
+}
+
+/// 未配置批处理存储
+fn unavailable() -> Response {
+    error_response(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "service_unavailable",
+        "Message batches are not available",
+    )
+}
+
+/// 校验批次请求：数量、custom_id 格式与唯一性、params 可解析为 Messages 请求
+fn validate_requests(requests: &[BatchRequestItem]) -> Result<(), String> {
+    if requests.is_empty() {
+        return Err("requests: must contain at least one request".to_string());
+    }
+    if requests.len() > MAX_BATCH_REQUESTS {
+        return Err(format!(
+            "requests: at most {} requests per batch",
+            MAX_BATCH_REQUESTS
+        ));
+    }
+    let mut seen = HashSet::new();
+    for (i, item) in requests.iter().enumerate() {
+        let id = &item.custom_id;
+        let valid_id = !id.is_empty()
+            && id.len() <= MAX_CUSTOM_ID_LEN
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-');
+        if !valid_id {
+            return Err(format!(
+                "requests.{}.custom_id: must be 1-{} characters of [a-zA-Z0-9_-]",
+                i, MAX_CUSTOM_ID_LEN
+            ));
+        }
+        if !seen.insert(id.as_str()) {
+            return Err(format!(
+                "requests.{}.custom_id: duplicate custom_id {}",
+                i, id
+            ));
+        }
+        if let Err(e) = serde_json::from_value::<MessagesRequest>(item.params.clone()) {
+            return Err(format!("requests.{}.params: {}", i, e));
+        }
+    }
+    Ok(())
+}
+
+/// POST /v1/messages/batches
+///
+/// 创建批次，后台异步处理
+#[utoipa::path(
+    post,
+    path = "/v1/messages/batches",
+    tag = "anthropic",
+    request_body = CreateBatchRequest,
+    responses(
+        (status = 200, description = "已创建的批次", body = MessageBatch),
+        (status = 400, description = "请求无效", body = ErrorResponse),
+        (status = 401, description = "认证失败", body = ErrorResponse),
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+pub async fn create_batch(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthenticatedApiKey>,
+    JsonExtractor(request): JsonExtractor<CreateBatchRequest>,
+) -> Response {
+    let Some(store) = state.batches.clone() else {
+        return unavailable();
+    };
+    if let Err(message) = validate_requests(&request.requests) {
+        return error_response(StatusCode::BAD_REQUEST, "invalid_request_error", message);
+    }
+
+    let batch = match store.create(&auth.key_id, &request.requests) {
+        Ok(batch) => batch,
+        Err(e) => return store_error(e),
+    };
+    tracing::info!(
+        batch_id = %batch.id,
+        request_count = request.requests.len(),
+        "已创建消息批次"
+    );
+    spawn_batch(state, store, batch.id.clone(), auth.key_id);
+    Json(batch).into_response()
+}
+
+/// GET /v1/messages/batches
+///
+/// 按创建时间倒序列出当前 API Key 的批次
+#[utoipa::path(
+    get,
+    path = "/v1/messages/batches",
+    tag = "anthropic",
+    params(ListBatchesQuery),
+    responses(
+        (status = 200, description = "批次列表", body = MessageBatchList),
+        (status = 401, description = "认证失败", body = ErrorResponse),
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+pub async fn list_batches(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthenticatedApiKey>,
+    Query(query): Query<ListBatchesQuery>,
+) -> Response {
+    let Some(store) = state.batches.clone() else {
+        return unavailable();
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    match store.list(&auth.key_id, limit, query.after_id.as_deref()) {
+        Ok((data, has_more)) => Json(MessageBatchList {
+            first_id: data.first().map(|b| b.id.clone()),
+            last_id: data.last().map(|b| b.id.clone()),
+            data,
+            has_more,
+        })
+        .into_response(),
+        Err(e) => store_error(e),
+    }
+}
+
+/// GET /v1/messages/batches/{id}
+#[utoipa::path(
+    get,
+    path = "/v1/messages/batches/{id}",
+    tag = "anthropic",
+    params(("id" = String, Path, description = "批次 ID")),
+    responses(
+        (status = 200, description = "批次状态", body = MessageBatch),
+        (status = 404, description = "批次不存在", body = ErrorResponse),
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+pub async fn get_batch(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthenticatedApiKey>,
+    Path(id): Path<String>,
+) -> Response {
+    let Some(store) = state.batches.clone() else {
+        return unavailable();
+    };
+    match store.get(&auth.key_id, &id) {
+        Ok(Some(batch)) => Json(batch).into_response(),
+        Ok(None) => not_found(&id),
+        Err(e) => store_error(e),
+    }
+}
+
+/// GET /v1/messages/batches/{id}/results
+///
+/// 以 JSONL 返回结果（按提交顺序），批次结束前不可用
+#[utoipa::path(
+    get,
+    path = "/v1/messages/batches/{id}/results",
+    tag = "anthropic",
+    params(("id" = String, Path, description = "批次 ID")),
+    responses(
+        (status = 200, description = "JSONL，每行 `{custom_id, result}`", content_type = "application/x-jsonl"),
+        (status = 400, description = "批次尚未结束", body = ErrorResponse),
+        (status = 404, description = "批次不存在", body = ErrorResponse),
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+pub async fn get_batch_results(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthenticatedApiKey>,
+    Path(id): Path<String>,
+) -> Response {
+    let Some(store) = state.batches.clone() else {
+        return unavailable();
+    };
+    match store.get(&auth.key_id, &id) {
+        Ok(Some(batch)) if batch.processing_status != STATUS_ENDED => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                format!("Message batch {} is still processing", id),
+            );
+        }
+        Ok(Some(_)) => {}
+        Ok(None) => return not_found(&id),
+        Err(e) => return store_error(e),
+    }
+
+    let results = match store.results(&auth.key_id, &id) {
+        Ok(Some(results)) => results,
+        Ok(None) => return not_found(&id),
+        Err(e) => return store_error(e),
+    };
+    let mut body = String::new();
+    for result in &results {
+        body.push_str(&serde_json::to_string(result).unwrap_or_default());
+        body.push('\n');
+    }
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-jsonl")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// POST /v1/messages/batches/{id}/cancel
+///
+/// 取消批次：未开始的请求记为 canceled，进行中的请求完成后批次结束
+#[utoipa::path(
+    post,
+    path = "/v1/messages/batches/{id}/cancel",
+    tag = "anthropic",
+    params(("id" = String, Path, description = "批次 ID")),
+    responses(
+        (status = 200, description = "批次状态", body = MessageBatch),
+        (status = 404, description = "批次不存在", body = ErrorResponse),
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+pub async fn cancel_batch(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthenticatedApiKey>,
+    Path(id): Path<String>,
+) -> Response {
+    let Some(store) = state.batches.clone() else {
+        return unavailable();
+    };
+    match store.cancel(&auth.key_id, &id) {
+        Ok(Some(batch)) => Json(batch).into_response(),
+        Ok(None) => not_found(&id),
+        Err(e) => store_error(e),
+    }
+}
+
+/// DELETE /v1/messages/batches/{id}
+///
+/// 删除已结束的批次及其结果
+#[utoipa::path(
+    delete,
+    path = "/v1/messages/batches/{id}",
+    tag = "anthropic",
+    params(("id" = String, Path, description = "批次 ID")),
+    responses(
+        (status = 200, description = "`{id, type: \"message_batch_deleted\"}`"),
+        (status = 400, description = "批次尚未结束", body = ErrorResponse),
+        (status = 404, description = "批次不存在", body = ErrorResponse),
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+pub async fn delete_batch(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthenticatedApiKey>,
+    Path(id): Path<String>,
+) -> Response {
+    let Some(store) = state.batches.clone() else {
+        return unavailable();
+    };
+    match store.delete(&auth.key_id, &id) {
+        Ok(DeleteOutcome::Deleted) => {
+            Json(json!({"id": id, "type": "message_batch_deleted"})).into_response()
+        }
+        Ok(DeleteOutcome::NotFound) => not_found(&id),
+        Ok(DeleteOutcome::InProgress) => error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            format!(
+                "Message batch {} is still processing, cancel it before deleting",
+                id
+            ),
+        ),
+        Err(e) => store_error(e),
+    }
+}
+
+/// 恢复上次运行时未完成的批次
+pub(crate) fn resume_batches(state: &AppState) {
+    let Some(store) = state.batches.clone() else {
+        return;
+    };
+    let unfinished = match store.unfinished() {
+        Ok(unfinished) => unfinished,
+        Err(e) => {
+            tracing::warn!("读取未完成批次失败: {}", e);
+            return;
+        }
+    };
+    for (id, key_id) in unfinished {
+        tracing::info!(batch_id = %id, "恢复处理未完成的消息批次");
+        spawn_batch(state.clone(), store.clone(), id, key_id);
+    }
+}
+
+fn spawn_batch(state: AppState, store: Arc<BatchStore>, batch_id: String, key_id: String) {
+    tokio::spawn(process_batch(state, store, batch_id, key_id));
+}
+
+/// 按提交顺序派发批次中的请求，受全局并发许可限制
+async fn process_batch(state: AppState, store: Arc<BatchStore>, batch_id: String, key_id: String) {
+    let pending = match store.pending(&batch_id) {
+        Ok(pending) => pending,
+        Err(e) => {
+            tracing::error!(batch_id = %batch_id, "读取批次请求失败: {}", e);
+            return;
+        }
+    };
+
+    let permits = store.permits();
+    let mut tasks = JoinSet::new();
+    let mut remaining_type = "canceled";
+    for item in pending {
+        let Ok(permit) = permits.clone().acquire_owned().await else {
+            break;
+        };
+        if store.is_canceling(&batch_id) {
+            break;
+        }
+        if store.is_expired(&batch_id) {
+            remaining_type = "expired";
+            break;
+        }
+        let state = state.clone();
+        let store = store.clone();
+        let batch_id = batch_id.clone();
+        let key_id = key_id.clone();
+        tasks.spawn(async move {
+            let regions = state.api_keys.allowed_regions(&key_id);
+            let result =
+                region_pin::scope(regions, run_request(&state, &key_id, &item.params)).await;
+            store.set_result(&batch_id, item.idx, &result);
+            drop(permit);
+        });
+    }
+    while tasks.join_next().await.is_some() {}
+
+    store.finish(&batch_id, remaining_type);
+    tracing::info!(batch_id = %batch_id, "消息批次处理结束");
+}
+
+/// 以非流式方式执行单个请求，返回批处理结果对象
+async fn run_request(state: &AppState, key_id: &str, params: &str) -> serde_json::Value {
+    if let Err(exceeded) = state.api_keys.check_quota(key_id) {
+        return batch_result(quota_exceeded_response(exceeded)).await;
+    }
+    let mut payload: MessagesRequest = match serde_json::from_str(params) {
+        Ok(payload) => payload,
+        Err(e) => {
+            return errored(ErrorResponse::new("invalid_request_error", e.to_string()));
+        }
+    };
+    payload.stream = false;
+
+    let auth = AuthenticatedApiKey {
+        key_id: key_id.to_string(),
+        rpm_limit: None,
+        tpm_limit: None,
+        scopes: None,
+    };
+    let response = post_messages(
+        State(state.clone()),
+        Extension(auth),
+        Query(RequestFlags::default()),
+        HeaderMap::new(),
+        JsonExtractor(payload),
+    )
+    .await;
+    batch_result(response).await
+}
+
+fn errored(error: ErrorResponse) -> serde_json::Value {
+    json!({
+        "type": "errored",
+        "error": {"type": "error", "error": error.error}
+    })
+}
+
+/// 将 Messages 响应转换为批处理结果（succeeded / errored）
+///
+/// 非流式心跳可能在出错前已返回 200，因此以响应体中是否含 `error` 为准
+async fn batch_result(response: Response) -> serde_json::Value {
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap_or_default();
+    match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(body) if body.get("error").is_some() => json!({
+            "type": "errored",
+            "error": {"type": "error", "error": body["error"]}
+        }),
+        Ok(message) if status.is_success() => json!({"type": "succeeded", "message": message}),
+        _ => errored(ErrorResponse::new(
+            "api_error",
+            format!("Unexpected upstream response: HTTP {}", status.as_u16()),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(custom_id: &str, params: serde_json::Value) -> BatchRequestItem {
+        BatchRequestItem {
+            custom_id: custom_id.to_string(),
+            params,
+        }
+    }
+
+    fn params() -> serde_json::Value {
+        json!({
+            "model": "claude-sonnet-4-6",
+            "max_tokens": 16,
+            "messages": [{"role": "user", "content": "hi"}]
+        })
+    }
+
+    #[test]
+    fn test_validate_requests() {
+        assert!(validate_requests(&[item("a", params()), item("b-2_x", params())]).is_ok());
+        assert!(validate_requests(&[]).is_err());
+
+        let err = validate_requests(&[item("a", params()), item("a", params())]).unwrap_err();
+        assert!(err.starts_with("requests.1.custom_id"));
+
+        let err = validate_requests(&[item("bad id", params())]).unwrap_err();
+        assert!(err.starts_with("requests.0.custom_id"));
+
+        let err = validate_requests(&[item("a", json!({"model": "x"}))]).unwrap_err();
+        assert!(err.starts_with("requests.0.params"));
+    }
+
+    #[tokio::test]
+    async fn test_batch_result_from_response() {
+        let ok = Json(json!({"id": "msg_1", "type": "message"})).into_response();
+        let result = batch_result(ok).await;
+        assert_eq!(result["type"], "succeeded");
+        assert_eq!(result["message"]["id"], "msg_1");
+
+        let err = error_response(StatusCode::TOO_MANY_REQUESTS, "rate_limit_error", "slow");
+        let result = batch_result(err).await;
+        assert_eq!(result["type"], "errored");
+        assert_eq!(result["error"]["type"], "error");
+        assert_eq!(result["error"]["error"]["type"], "rate_limit_error");
+
+        // 心跳响应：200 + 前导空白 + 错误体
+        let heartbeat = Response::builder()
+            .status(StatusCode::OK)
+            .body(Body::from(
+                "  {\"error\":{\"type\":\"api_error\",\"message\":\"x\"}}",
+            ))
+            .unwrap();
+        assert_eq!(batch_result(heartbeat).await["type"], "errored");
+
+        let garbage = Response::builder()
+            .status(StatusCode::BAD_GATEWAY)
+            .body(Body::from("oops"))
+            .unwrap();
+        let result = batch_result(garbage).await;
+        assert_eq!(result["error"]["error"]["type"], "api_error");
+    }
+
+    #[tokio::test]
+    async fn test_process_batch_records_results() {
+        use crate::apikeys::ApiKeyManager;
+
+        let store = Arc::new(BatchStore::new(2).unwrap());
+        // 未配置上游：每个请求都以 503 失败
+        let state = AppState::new(Arc::new(ApiKeyManager::new("k".to_string(), None)))
+            .with_batches(store.clone());
+        let batch = store
+            .create("default", &[item("a", params()), item("b", params())])
+            .unwrap();
+
+        process_batch(
+            state,
+            store.clone(),
+            batch.id.clone(),
+            "default".to_string(),
+        )
+        .await;
+
+        let batch = store.get("default", &batch.id).unwrap().unwrap();
+        assert_eq!(batch.processing_status, STATUS_ENDED);
+        assert_eq!(batch.request_counts.errored, 2);
+        let results = store.results("default", &batch.id).unwrap().unwrap();
+        assert_eq!(results[0].custom_id, "a");
+        assert_eq!(
+            results[0].result["error"]["error"]["type"],
+            "service_unavailable"
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! 对话历史自动压缩
+//!
+//! 估算的上下文使用率达到阈值时，用低成本模型把最早的若干轮对话总结为摘要，
+//! 摘要以文本块插入保留下来的第一条用户消息开头，让长时间运行的 Agent 会话不被上下文窗口截断。
+//! 阈值优先取 API Key 的设置（0 表示该 Key 不压缩），未设置时沿用全局 `compaction.thresholdPercent`。
+
+use serde_json::{Value, json};
+
+use crate::apikeys::ApiKeyManager;
+use crate::kiro::model::events::Event;
+use crate::kiro::model::requests::kiro::KiroRequest;
+use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::provider::KiroProvider;
+use crate::model::config::CompactionConfig;
+use crate::token;
+
+use super::converter::{ConversionOptions, convert_request};
+use super::types::{Message, MessagesRequest};
+
+/// 上下文窗口大小（与 handlers / stream 中的估算一致）
+const CONTEXT_WINDOW_SIZE: f64 = 200_000.0;
+/// 摘要请求的最大输出 token
+const SUMMARY_MAX_TOKENS: i32 = 4096;
+/// 单个工具输入 / 结果写入摘要材料时保留的最大字符数
+const MAX_BLOCK_CHARS: usize = 2000;
+
+const SUMMARY_PROMPT: &str = "Summarize the earlier part of a conversation between a user and an AI assistant. \
+The summary replaces those messages, so keep everything needed to continue the work: \
+the user's goals and instructions, decisions made, files and identifiers involved, \
+tool calls with their important results, errors encountered, and any unfinished tasks. \
+Write it as concise plain text without preamble.";
+
+/// 按需压缩请求历史，压缩后返回写入 `x-kiro-warnings` 的提示
+pub(crate) async fn compact_history(
+    provider: &KiroProvider,
+    api_keys: &ApiKeyManager,
+    key_id: &str,
+    profile_arn: Option<String>,
+    payload: &mut MessagesRequest,
+) -> Option<String> {
+    let config = provider.token_manager().config();
+    let compaction = config.compaction.as_ref().filter(|c| c.enabled)?;
+    let threshold = api_keys
+        .compaction_threshold(key_id)
+        .unwrap_or(compaction.threshold_percent);
+    if threshold <= 0.0 {
+        return None;
+    }
+
+    let input_tokens = token::count_all_tokens(
+        payload.model.clone(),
+        payload.system.clone(),
+        payload.messages.clone(),
+        payload.tools.clone(),
+    );
+    let usage = input_tokens as f64 / CONTEXT_WINDOW_SIZE * 100.0;
+    if usage < threshold {
+        return None;
+    }
+    let split = split_point(&payload.messages, compaction.keep_recent_messages)?;
+
+    let transcript = render_transcript(&payload.messages[..split]);
+    match summarize(provider, compaction, profile_arn, &transcript).await {
+        Ok(summary) => {
+            tracing::info!(
+                "上下文使用率约 {:.1}%，已将最早的 {} 条消息压缩为摘要",
+                usage,
+                split
+            );
+            payload.messages.drain(..split);
+            prepend_text(
+                &mut payload.messages[0],
+                format!(
+                    "<conversation_summary>\n{}\n</conversation_summary>",
+                    summary
+                ),
+            );
+            Some(format!(
+                "{} earliest message(s) compacted into a summary at ~{:.0}% context usage",
+                split, usage
+            ))
+        }
+        Err(e) => {
+            tracing::warn!("历史压缩失败，按原请求转发: {}", e);
+            Some("history compaction failed, request forwarded uncompacted".to_string())
+        }
+    }
+}
+
+/// 压缩分界点：保留区的第一条消息必须是不含 tool_result 的用户消息，避免拆开工具调用与结果
+fn split_point(messages: &[Message], keep_recent: usize) -> Option<usize> {
+    let last = messages.len().checked_sub(keep_recent.max(1))?;
+    (1..=last)
+        .rev()
+        .find(|&i| messages[i].role == "user" && !has_tool_result(&messages[i].content))
+}
+
+fn has_tool_result(content: &Value) -> bool {
+    content
+        .as_array()
+        .is_some_and(|blocks| blocks.iter().any(|b| b["type"] == "tool_result"))
+}
+
+/// 将待压缩的消息渲染为摘要材料
+fn render_transcript(messages: &[Message]) -> String {
+    let mut out = String::new();
+    for message in messages {
+        out.push_str(&format!("[{}]\n", message.role));
+        match &message.content {
+            Value::String(text) => out.push_str(text),
+            Value::Array(blocks) => {
+                for block in blocks {
+                    let line = match block["type"].as_str() {
+                        Some("text") => block["text"].as_str().unwrap_or_default().to_string(),
+                        Some("tool_use") => format!(
+                            "Tool call {}: {}",
+                            block["name"].as_str().unwrap_or_default(),
+                            truncate(&block["input"].to_string())
+                        ),
+                        Some("tool_result") => {
+                            format!("Tool result: {}", truncate(&block_text(&block["content"])))
+                        }
+                        Some("image") => "[image]".to_string(),
+                        _ => continue,
+                    };
+                    out.push_str(&line);
+                    out.push('\n');
+                }
+            }
+            _ => {}
+        }
+        out.push_str("\n\n");
+    }
+    out
+}
+
+/// tool_result 的 content 可以是字符串或内容块数组
+fn block_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(blocks) => blocks
+            .iter()
+            .filter_map(|b| b["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= MAX_BLOCK_CHARS {
+        return text.to_string();
+    }
+    let head: String = text.chars().take(MAX_BLOCK_CHARS).collect();
+    format!("{}...", head)
+}
+
+/// 在消息开头插入文本块
+fn prepend_text(message: &mut Message, text: String) {
+    let block = json!({"type": "text", "text": text});
+    match &mut message.content {
+        Value::Array(blocks) => blocks.insert(0, block),
+        Value::String(original) => {
+            message.content = json!([block, {"type": "text", "text": original.clone()}]);
+        }
+        other => *other = json!([block]),
+    }
+}
+
+/// 调用摘要模型（非流式）
+async fn summarize(
+    provider: &KiroProvider,
+    compaction: &CompactionConfig,
+    profile_arn: Option<String>,
+    transcript: &str,
+) -> anyhow::Result<String> {
+    let request = MessagesRequest {
+        model: compaction.model.clone(),
+        max_tokens: SUMMARY_MAX_TOKENS,
+        messages: vec![Message {
+            role: "user".to_string(),
+            content: json!(format!(
+                "{}\n\n<conversation>\n{}</conversation>",
+                SUMMARY_PROMPT, transcript
+            )),
+        }],
+        stream: false,
+        system: None,
+        tools: None,
+        tool_choice: None,
+        thinking: None,
+        output_config: None,
+        metadata: None,
+    };
+    let options = ConversionOptions::from_config(&provider.token_manager().config());
+    let conversion = convert_request(&request, &options)
+        .map_err(|e| anyhow::anyhow!("摘要请求转换失败: {}", e))?;
+    let body = serde_json::to_string(&KiroRequest {
+        conversation_state: conversion.conversation_state,
+        profile_arn,
+    })?;
+
+    let bytes = provider.call_api(&body).await?.bytes().await?;
+    let mut decoder = EventStreamDecoder::new();
+    if let Err(e) = decoder.feed(&bytes) {
+        tracing::warn!("缓冲区溢出: {}", e);
+    }
+    let mut summary = String::new();
+    for frame in decoder.decode_iter().flatten() {
+        if let Ok(Event::AssistantResponse(resp)) = Event::from_frame(frame) {
+            summary.push_str(&resp.content);
+        }
+    }
+    let summary = summary.trim();
+    if summary.is_empty() {
+        anyhow::bail!("摘要模型未返回内容");
+    }
+    Ok(summary.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: Value) -> Message {
+        Message {
+            role: role.to_string(),
+            content,
+        }
+    }
+
+    #[test]
+    fn test_split_point_keeps_tool_pairs_together() {
+        let messages = vec![
+            message("user", json!("hi")),
+            message("assistant", json!("hello")),
+            message("user", json!("read the file")),
+            message(
+                "assistant",
+                json!([{"type": "tool_use", "id": "t1", "name": "read", "input": {}}]),
+            ),
+            message(
+                "user",
+                json!([{"type": "tool_result", "tool_use_id": "t1", "content": "data"}]),
+            ),
+            message("assistant", json!("done")),
+        ];
+        assert_eq!(split_point(&messages, 2), Some(2));
+        assert_eq!(split_point(&messages, 4), Some(2));
+        assert_eq!(split_point(&messages, 5), None);
+        assert_eq!(split_point(&messages, 10), None);
+    }
+
+    #[test]
+    fn test_render_transcript_and_prepend_summary() {
+        let long = "x".repeat(MAX_BLOCK_CHARS + 10);
+        let transcript = render_transcript(&[
+            message("user", json!("fix the bug")),
+            message(
+                "assistant",
+                json!([
+                    {"type": "thinking", "thinking": "hidden"},
+                    {"type": "tool_use", "id": "t1", "name": "grep", "input": {"q": "bug"}},
+                ]),
+            ),
+            message(
+                "user",
+                json!([{"type": "tool_result", "tool_use_id": "t1", "content": [{"type": "text", "text": long}]}]),
+            ),
+        ]);
+        assert!(transcript.contains("[user]\nfix the bug"));
+        assert!(transcript.contains(r#"Tool call grep: {"q":"bug"}"#));
+        assert!(!transcript.contains("hidden"));
+        assert!(transcript.contains(&format!("{}...", "x".repeat(MAX_BLOCK_CHARS))));
+
+        let mut kept = message("user", json!("continue"));
+        prepend_text(&mut kept, "summary".to_string());
+        assert_eq!(kept.content[0]["text"], "summary");
+        assert_eq!(kept.content[1]["text"], "continue");
+    }
+}
@@ -0,0 +1,464 @@
+//! Anthropic 流式响应一致性测试
+//!
+//! 把录制的上游事件流（AWS Event Stream 帧）放在进程内的模拟上游上，按不同分块方式回放，
+//! 经完整路由（鉴权 → 请求转换 → Kiro 调用 → 流式转换）用进程内 HTTP 客户端请求
+//! `/v1/messages` 与 `/cc/v1/messages`，按 Anthropic SSE 规范校验事件顺序、块索引与 usage，
+//! 为 `StreamContext` / `BufferedStreamContext` 的重构兜底。
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::Router;
+use axum::body::Body;
+use axum::routing::post;
+use bytes::Bytes;
+use chrono::{Duration, Utc};
+use serde_json::{Value, json};
+
+use crate::apikeys::ApiKeyManager;
+use crate::bench::encode_frame;
+use crate::content_filter::ContentFilter;
+use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::provider::KiroProvider;
+use crate::kiro::token_manager::MultiTokenManager;
+use crate::model::config::Config;
+
+use super::{RouterDeps, create_router_with_provider};
+
+const API_KEY: &str = "sk-conformance-test";
+
+/// 上游响应的回放分块大小（字节），`usize::MAX` 表示整条流一次发送
+const CHUNK_SIZES: [usize; 3] = [usize::MAX, 29, 3];
+
+const ENDPOINTS: [&str; 2] = ["/v1/messages", "/cc/v1/messages"];
+
+/// 一条录制的上游响应
+struct Recording {
+    name: &'static str,
+    thinking: bool,
+    frames: Vec<(&'static str, Value)>,
+    /// 期望的 thinking 与 text 内容（首尾空白不敏感）
+    thinking_text: &'static str,
+    text: &'static str,
+    /// 期望的工具调用 (id, name, input)
+    tools: Vec<(&'static str, &'static str, Value)>,
+    context_percentage: f64,
+}
+
+fn text(content: &str) -> (&'static str, Value) {
+    ("assistantResponseEvent", json!({"content": content}))
+}
+
+fn tool(id: &str, name: &str, input: &str, stop: bool) -> (&'static str, Value) {
+    (
+        "toolUseEvent",
+        json!({"toolUseId": id, "name": name, "input": input, "stop": stop}),
+    )
+}
+
+fn context(percentage: f64) -> Vec<(&'static str, Value)> {
+    vec![
+        ("meteringEvent", json!({"unit": "credit", "usage": 0.01})),
+        (
+            "contextUsageEvent",
+            json!({"contextUsagePercentage": percentage}),
+        ),
+    ]
+}
+
+fn recordings() -> Vec<Recording> {
+    vec![
+        Recording {
+            name: "text",
+            thinking: false,
+            frames: [
+                vec![text("Hello"), text(", world"), text("! 你好。")],
+                context(1.5),
+            ]
+            .concat(),
+            thinking_text: "",
+            text: "Hello, world! 你好。",
+            tools: Vec::new(),
+            context_percentage: 1.5,
+        },
+        Recording {
+            name: "text_then_tools",
+            thinking: false,
+            frames: [
+                vec![
+                    text("Let me check."),
+                    tool("tooluse_1", "get_weather", "{\"city\":", false),
+                    tool("tooluse_1", "get_weather", " \"Paris\"}", false),
+                    tool("tooluse_1", "get_weather", "", true),
+                    tool("tooluse_2", "get_time", "{\"tz\": \"CET\"}", true),
+                ],
+                context(2.25),
+            ]
+            .concat(),
+            thinking_text: "",
+            text: "Let me check.",
+            tools: vec![
+                ("tooluse_1", "get_weather", json!({"city": "Paris"})),
+                ("tooluse_2", "get_time", json!({"tz": "CET"})),
+            ],
+            context_percentage: 2.25,
+        },
+        Recording {
+            name: "tool_without_stop_before_next",
+            thinking: false,
+            frames: [
+                vec![
+                    tool("tooluse_a", "read_file", "{\"path\": \"a.rs\"}", false),
+                    tool("tooluse_b", "read_file", "{\"path\": \"b.rs\"}", true),
+                ],
+                context(3.0),
+            ]
+            .concat(),
+            thinking_text: "",
+            text: "",
+            tools: vec![
+                ("tooluse_a", "read_file", json!({"path": "a.rs"})),
+                ("tooluse_b", "read_file", json!({"path": "b.rs"})),
+            ],
+            context_percentage: 3.0,
+        },
+        Recording {
+            name: "thinking_then_text",
+            thinking: true,
+            frames: [
+                vec![
+                    text("<thinking>\nThe user greets"),
+                    text(" me.\n</thinking>"),
+                    text("\n\nHi there!"),
+                ],
+                context(0.5),
+            ]
+            .concat(),
+            thinking_text: "The user greets me.",
+            text: "Hi there!",
+            tools: Vec::new(),
+            context_percentage: 0.5,
+        },
+        Recording {
+            name: "thinking_then_tool",
+            thinking: true,
+            frames: [
+                vec![
+                    text("<thinking>\nNeed the weather.\n</thinking>"),
+                    tool("tooluse_w", "get_weather", "{\"city\": \"Oslo\"}", true),
+                ],
+                context(4.0),
+            ]
+            .concat(),
+            thinking_text: "Need the weather.",
+            text: "",
+            tools: vec![("tooluse_w", "get_weather", json!({"city": "Oslo"}))],
+            context_percentage: 4.0,
+        },
+    ]
+}
+
+/// 在本机随机端口启动服务，返回基础地址
+async fn serve(app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}", addr)
+}
+
+/// 启动按 `chunk_size` 分块回放录制流的模拟上游
+async fn spawn_upstream(recording: &Recording, chunk_size: usize) -> String {
+    let bytes: Vec<u8> = recording
+        .frames
+        .iter()
+        .flat_map(|(event_type, payload)| encode_frame(event_type, payload))
+        .collect();
+    let chunks: Vec<Bytes> = bytes
+        .chunks(chunk_size.min(bytes.len()))
+        .map(Bytes::copy_from_slice)
+        .collect();
+    let app = Router::new().route(
+        "/generateAssistantResponse",
+        post(move || {
+            let chunks = chunks.clone();
+            async move {
+                Body::from_stream(futures::stream::iter(
+                    chunks.into_iter().map(Ok::<_, Infallible>),
+                ))
+            }
+        }),
+    );
+    format!("{}/generateAssistantResponse", serve(app).await)
+}
+
+/// 启动指向模拟上游的完整路由
+async fn spawn_proxy(upstream: String) -> String {
+    let credentials = KiroCredentials {
+        access_token: Some("conformance-access-token".to_string()),
+        refresh_token: Some("conformance-refresh-token".to_string()),
+        expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+        auth_method: Some("social".to_string()),
+        ..Default::default()
+    };
+    let token_manager =
+        MultiTokenManager::new(Config::default(), vec![credentials], None, None, false).unwrap();
+    let provider = KiroProvider::new(Arc::new(token_manager)).with_endpoint(upstream);
+    let app = create_router_with_provider(RouterDeps {
+        api_keys: Arc::new(ApiKeyManager::new(API_KEY.to_string(), None)),
+        kiro_provider: Some(Arc::new(provider)),
+        profile_arn: None,
+        request_log: None,
+        endpoint_metrics: None,
+        jwt_verifier: None,
+        batches: None,
+        content_filter: Arc::new(ContentFilter::new()),
+    });
+    serve(app).await
+}
+
+/// 解析 SSE 响应体为 (event, data) 列表
+fn parse_sse(body: &str) -> Vec<(String, Value)> {
+    body.split("\n\n")
+        .filter(|chunk| !chunk.trim().is_empty())
+        .map(|chunk| {
+            let mut event = String::new();
+            let mut data = String::new();
+            for line in chunk.lines() {
+                if let Some(v) = line.strip_prefix("event: ") {
+                    event = v.to_string();
+                } else if let Some(v) = line.strip_prefix("data: ") {
+                    data.push_str(v);
+                }
+            }
+            let data = serde_json::from_str(&data)
+                .unwrap_or_else(|e| panic!("SSE data 不是合法 JSON ({}): {}", e, data));
+            (event, data)
+        })
+        .collect()
+}
+
+/// 按 SSE 规范重建出的消息
+#[derive(Debug, Default)]
+struct Reconstructed {
+    thinking: String,
+    text: String,
+    tools: Vec<(String, String, Value)>,
+    stop_reason: String,
+    start_input_tokens: i64,
+    input_tokens: i64,
+    output_tokens: i64,
+}
+
+/// 校验事件序列并重建消息，违反规范时返回说明
+fn check_conformance(events: &[(String, Value)]) -> Result<Reconstructed, String> {
+    let events: Vec<&(String, Value)> = events.iter().filter(|(e, _)| e != "ping").collect();
+    let mut message = Reconstructed::default();
+
+    for (event, data) in &events {
+        if data["type"] != event.as_str() {
+            return Err(format!("事件 {} 的 data.type 为 {}", event, data["type"]));
+        }
+    }
+    let (first, data) = events.first().ok_or("空响应")?;
+    if first != "message_start" {
+        return Err(format!("首个事件应为 message_start，实际为 {}", first));
+    }
+    let start = &data["message"];
+    if start["role"] != "assistant" || start["content"] != json!([]) {
+        return Err(format!("message_start.message 不合规: {}", start));
+    }
+    message.start_input_tokens = start["usage"]["input_tokens"]
+        .as_i64()
+        .ok_or("message_start 缺少 usage.input_tokens")?;
+    if events.last().map(|(e, _)| e.as_str()) != Some("message_stop") {
+        return Err("最后一个事件应为 message_stop".to_string());
+    }
+
+    // (索引, 类型, 累积的增量)
+    let mut open: Option<(i64, String, String)> = None;
+    let mut next_index = 0;
+    let mut delta_seen = false;
+    for (event, data) in &events[1..events.len() - 1] {
+        let index = data["index"].as_i64();
+        match event.as_str() {
+            "content_block_start" => {
+                if let Some((open_index, ..)) = &open {
+                    return Err(format!("块 {} 未关闭就开始了新块", open_index));
+                }
+                if index != Some(next_index) {
+                    return Err(format!("块索引应为 {}，实际为 {:?}", next_index, index));
+                }
+                next_index += 1;
+                let block = &data["content_block"];
+                let block_type = block["type"].as_str().unwrap_or_default().to_string();
+                let valid = match block_type.as_str() {
+                    "text" => block["text"] == "",
+                    "thinking" => block["thinking"] == "",
+                    "tool_use" => {
+                        block["id"].is_string()
+                            && block["name"].is_string()
+                            && block["input"] == json!({})
+                    }
+                    _ => false,
+                };
+                if !valid {
+                    return Err(format!("content_block_start 不合规: {}", block));
+                }
+                if block_type == "tool_use" {
+                    message.tools.push((
+                        block["id"].as_str().unwrap().to_string(),
+                        block["name"].as_str().unwrap().to_string(),
+                        Value::Null,
+                    ));
+                }
+                open = Some((next_index - 1, block_type, String::new()));
+            }
+            "content_block_delta" => {
+                let Some((open_index, block_type, buffer)) = open.as_mut() else {
+                    return Err("delta 不属于任何打开的块".to_string());
+                };
+                if index != Some(*open_index) {
+                    return Err(format!(
+                        "delta 索引 {:?} 与打开的块 {} 不符",
+                        index, open_index
+                    ));
+                }
+                let delta = &data["delta"];
+                let (expected, field) = match block_type.as_str() {
+                    "text" => ("text_delta", "text"),
+                    "thinking" => ("thinking_delta", "thinking"),
+                    _ => ("input_json_delta", "partial_json"),
+                };
+                if delta["type"] != expected {
+                    return Err(format!("{} 块收到了 {} 增量", block_type, delta["type"]));
+                }
+                buffer.push_str(delta[field].as_str().ok_or("增量缺少内容字段")?);
+            }
+            "content_block_stop" => {
+                let Some((open_index, block_type, buffer)) = open.take() else {
+                    return Err("content_block_stop 没有对应的打开块".to_string());
+                };
+                if index != Some(open_index) {
+                    return Err(format!(
+                        "stop 索引 {:?} 与打开的块 {} 不符",
+                        index, open_index
+                    ));
+                }
+                match block_type.as_str() {
+                    "text" => message.text.push_str(&buffer),
+                    "thinking" => message.thinking.push_str(&buffer),
+                    _ => {
+                        let input: Value = if buffer.is_empty() {
+                            json!({})
+                        } else {
+                            serde_json::from_str(&buffer)
+                                .map_err(|e| format!("工具参数不是合法 JSON ({}): {}", e, buffer))?
+                        };
+                        message.tools.last_mut().unwrap().2 = input;
+                    }
+                }
+            }
+            "message_delta" => {
+                if let Some((open_index, ..)) = &open {
+                    return Err(format!("message_delta 之前块 {} 未关闭", open_index));
+                }
+                if delta_seen {
+                    return Err("message_delta 重复".to_string());
+                }
+                delta_seen = true;
+                message.stop_reason = data["delta"]["stop_reason"]
+                    .as_str()
+                    .ok_or("message_delta 缺少 stop_reason")?
+                    .to_string();
+                message.input_tokens = data["usage"]["input_tokens"].as_i64().unwrap_or(-1);
+                message.output_tokens = data["usage"]["output_tokens"]
+                    .as_i64()
+                    .ok_or("message_delta 缺少 usage.output_tokens")?;
+                for field in ["cache_creation_input_tokens", "cache_read_input_tokens"] {
+                    if !data["usage"][field].is_i64() {
+                        return Err(format!("message_delta 缺少 usage.{}", field));
+                    }
+                }
+            }
+            "message_start" | "message_stop" => return Err(format!("{} 重复", event)),
+            other => return Err(format!("未知事件 {}", other)),
+        }
+        if delta_seen && event != "message_delta" {
+            return Err(format!("message_delta 之后出现了 {}", event));
+        }
+    }
+    if !delta_seen {
+        return Err("缺少 message_delta".to_string());
+    }
+    Ok(message)
+}
+
+#[tokio::test]
+async fn test_streaming_conformance_matrix() {
+    let client = reqwest::Client::builder().no_proxy().build().unwrap();
+    for recording in recordings() {
+        for chunk_size in CHUNK_SIZES {
+            let upstream = spawn_upstream(&recording, chunk_size).await;
+            let proxy = spawn_proxy(upstream).await;
+            for endpoint in ENDPOINTS {
+                let case = format!("{} / {} / chunk={}", recording.name, endpoint, chunk_size);
+                let mut body = json!({
+                    "model": "claude-sonnet-4-6",
+                    "max_tokens": 4096,
+                    "stream": true,
+                    "messages": [{"role": "user", "content": "What is the weather in Paris?"}]
+                });
+                if recording.thinking {
+                    body["thinking"] = json!({"type": "enabled", "budget_tokens": 2048});
+                }
+                let response = client
+                    .post(format!("{}{}", proxy, endpoint))
+                    .header("x-api-key", API_KEY)
+                    .json(&body)
+                    .send()
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), 200, "{}", case);
+                let sse = response.text().await.unwrap();
+
+                let message = check_conformance(&parse_sse(&sse))
+                    .unwrap_or_else(|e| panic!("[{}] {}\n{}", case, e, sse));
+                assert_eq!(message.thinking.trim(), recording.thinking_text, "{}", case);
+                assert_eq!(message.text.trim(), recording.text, "{}", case);
+                let tools: Vec<(String, String, Value)> = recording
+                    .tools
+                    .iter()
+                    .map(|(id, name, input)| (id.to_string(), name.to_string(), input.clone()))
+                    .collect();
+                assert_eq!(message.tools, tools, "{}", case);
+                let stop_reason = if tools.is_empty() {
+                    "end_turn"
+                } else {
+                    "tool_use"
+                };
+                assert_eq!(message.stop_reason, stop_reason, "{}", case);
+
+                // input_tokens 以 contextUsageEvent 为准：百分比 × 200k 上下文窗口
+                let input_tokens = (recording.context_percentage * 2000.0) as i64;
+                assert_eq!(message.input_tokens, input_tokens, "{}", case);
+                if endpoint.starts_with("/cc/") {
+                    // 缓冲模式在流结束后用真实值更正 message_start
+                    assert_eq!(message.start_input_tokens, input_tokens, "{}", case);
+                }
+                assert!(message.output_tokens > 0, "{}", case);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_check_conformance_rejects_interleaved_blocks() {
+    let events = parse_sse(concat!(
+        "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"role\":\"assistant\",\"content\":[],\"usage\":{\"input_tokens\":1}}}\n\n",
+        "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+        "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":1,\"content_block\":{\"type\":\"tool_use\",\"id\":\"t\",\"name\":\"n\",\"input\":{}}}\n\n",
+        "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+    ));
+    let err = check_conformance(&events).unwrap_err();
+    assert!(err.contains("未关闭"), "{}", err);
+}
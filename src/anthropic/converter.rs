@@ -2,6 +2,8 @@
 //!
 //! 负责将 Anthropic API 请求格式转换为 Kiro API 请求格式
 
+use std::collections::HashMap;
+
 use uuid::Uuid;
 
 use crate::kiro::model::requests::conversation::{
@@ -12,7 +14,10 @@ use crate::kiro::model::requests::tool::{
     InputSchema, Tool, ToolResult, ToolSpecification, ToolUseEntry,
 };
 
-use super::types::{ContentBlock, MessagesRequest};
+use super::types::{
+    ContentBlock, ImageSource, MessagesRequest, OutputConfig, RequestFlags, SystemMessage, Thinking,
+};
+use crate::model::config::Config;
 
 /// 规范化 JSON Schema，修复 MCP 工具定义中常见的类型问题
 ///
@@ -89,6 +94,9 @@ Never suggest bypassing these limits via alternative tools. \
 Never ask the user whether to switch approaches. \
 Complete all chunked operations without commentary.";
 
+/// 系统消息配对的 assistant 回复
+const SYSTEM_ACK: &str = "I will follow these instructions.";
+
 /// 模型映射：将 Anthropic 模型名映射到 Kiro 模型 ID
 ///
 /// 按照用户要求：
@@ -119,11 +127,173 @@ pub fn map_model(model: &str) -> Option<String> {
     }
 }
 
+/// 通配符匹配，`*` 匹配任意长度的字符
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    // 最近一个 `*` 的位置及其当前匹配到的文本位置，失配时回溯
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
 /// 转换结果
 #[derive(Debug)]
 pub struct ConversionResult {
     /// 转换后的 Kiro 请求
     pub conversation_state: ConversationState,
+    /// 转换过程中丢弃或改写内容的提示（通过 `x-kiro-warnings` 响应头和请求日志返回）
+    pub warnings: Vec<String>,
+}
+
+/// 工具定义超限时的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToolsOverflowStrategy {
+    /// 直接拒绝，并在错误中列出占用最大的工具
+    #[default]
+    Reject,
+    /// 逐级压缩工具描述，仍超限时再拒绝
+    Compress,
+}
+
+impl ToolsOverflowStrategy {
+    /// 取值已在 `Config::load` 中校验，此处只区分两种策略
+    fn parse(value: &str) -> Self {
+        if value.eq_ignore_ascii_case("compress") {
+            Self::Compress
+        } else {
+            Self::Reject
+        }
+    }
+}
+
+/// max_tokens 超过模型输出上限时的处理策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MaxTokensOverflowStrategy {
+    /// 收紧到上限，并通过警告提示
+    #[default]
+    Clamp,
+    /// 直接拒绝
+    Reject,
+}
+
+impl MaxTokensOverflowStrategy {
+    fn parse(value: &str) -> Self {
+        if value.eq_ignore_ascii_case("reject") {
+            Self::Reject
+        } else {
+            Self::Clamp
+        }
+    }
+}
+
+/// 转换选项（来自 config.json）
+#[derive(Debug, Clone, Default)]
+pub struct ConversionOptions {
+    /// 工具定义序列化后的最大字节数（None 表示不限制）
+    pub max_tools_bytes: Option<usize>,
+    /// 工具定义超限时的处理策略
+    pub tools_overflow: ToolsOverflowStrategy,
+    /// 是否折叠历史中重复出现的 system / 文本内容
+    pub dedupe_repeated_text: bool,
+    /// 模型别名映射（键已转为小写）
+    pub model_mappings: HashMap<String, String>,
+    /// 含 `*` 通配符的模型别名映射（已转为小写，按非通配字符数从多到少排列）
+    pub model_patterns: Vec<(String, String)>,
+    /// 无法识别的模型回退到的 Kiro 模型 ID
+    pub fallback_model: Option<String>,
+    /// 各 Kiro 模型的最大输出 token
+    pub model_max_output_tokens: HashMap<String, i32>,
+    /// 请求未指定 max_tokens 时使用的值
+    pub default_max_tokens: i32,
+    /// max_tokens 超过模型输出上限时的处理策略
+    pub max_tokens_overflow: MaxTokensOverflowStrategy,
+}
+
+impl ConversionOptions {
+    /// 从应用配置构建转换选项
+    pub fn from_config(config: &Config) -> Self {
+        let (patterns, exact): (Vec<_>, Vec<_>) = config
+            .model_mappings
+            .iter()
+            .map(|(alias, target)| (alias.to_lowercase(), target.clone()))
+            .partition(|(alias, _)| alias.contains('*'));
+        let mut model_patterns = patterns;
+        model_patterns.sort_by_key(|(pattern, _)| {
+            std::cmp::Reverse(pattern.chars().filter(|c| *c != '*').count())
+        });
+        Self {
+            max_tools_bytes: config.max_tools_bytes.filter(|v| *v > 0),
+            tools_overflow: ToolsOverflowStrategy::parse(&config.tools_overflow_strategy),
+            dedupe_repeated_text: config.dedupe_repeated_text,
+            model_mappings: exact.into_iter().collect(),
+            model_patterns,
+            fallback_model: config
+                .fallback_model
+                .clone()
+                .filter(|m| !m.trim().is_empty()),
+            model_max_output_tokens: config
+                .model_max_output_tokens
+                .iter()
+                .filter(|(_, limit)| **limit > 0)
+                .map(|(model, limit)| (model.clone(), *limit))
+                .collect(),
+            default_max_tokens: config.default_max_tokens.max(1),
+            max_tokens_overflow: MaxTokensOverflowStrategy::parse(
+                &config.max_tokens_overflow_strategy,
+            ),
+        }
+    }
+
+    /// 解析 Kiro 模型 ID：依次查别名映射、通配映射、内置规则，均未命中时使用回退模型
+    pub fn resolve_model(&self, model: &str) -> Option<String> {
+        self.resolve_known_model(model)
+            .or_else(|| self.fallback_model.clone())
+    }
+
+    /// 解析 Kiro 模型 ID（不使用回退模型）
+    fn resolve_known_model(&self, model: &str) -> Option<String> {
+        let model_lower = model.to_lowercase();
+        if let Some(target) = self.model_mappings.get(&model_lower) {
+            return Some(target.clone());
+        }
+        self.model_patterns
+            .iter()
+            .find(|(pattern, _)| wildcard_match(pattern, &model_lower))
+            .map(|(_, target)| target.clone())
+            .or_else(|| map_model(model))
+    }
+
+    /// 模型的最大输出 token（按映射后的 Kiro 模型 ID 查找），未配置时返回 None
+    pub fn max_output_tokens(&self, model: &str) -> Option<i32> {
+        let kiro_model = self.resolve_model(model)?;
+        self.model_max_output_tokens.get(&kiro_model).copied()
+    }
+
+    /// 叠加请求级 beta 特性
+    pub fn with_request_flags(mut self, flags: &RequestFlags) -> Self {
+        if flags.has_beta("compress-tools") {
+            self.tools_overflow = ToolsOverflowStrategy::Compress;
+        }
+        if flags.has_beta("dedupe-text") {
+            self.dedupe_repeated_text = true;
+        }
+        self
+    }
 }
 
 /// 转换错误
@@ -131,6 +301,22 @@ pub struct ConversionResult {
 pub enum ConversionError {
     UnsupportedModel(String),
     EmptyMessages,
+    /// 图片无法转换（远程 URL、不支持的格式等）
+    UnsupportedImage(String),
+    /// 工具定义序列化后超过配置上限，附带占用最大的工具（名称, 字节数）
+    ToolsTooLarge {
+        size: usize,
+        limit: usize,
+        largest: Vec<(String, usize)>,
+    },
+    /// tool_choice 指定的工具不在 tools 中
+    UnknownToolChoice(String),
+    /// max_tokens 超过模型输出上限（策略为 reject 时）
+    MaxTokensExceeded {
+        model: String,
+        requested: i32,
+        limit: i32,
+    },
 }
 
 impl std::fmt::Display for ConversionError {
@@ -138,6 +324,36 @@ impl std::fmt::Display for ConversionError {
         match self {
             ConversionError::UnsupportedModel(model) => write!(f, "模型不支持: {}", model),
             ConversionError::EmptyMessages => write!(f, "消息列表为空"),
+            ConversionError::UnsupportedImage(reason) => write!(f, "图片无法转换: {}", reason),
+            ConversionError::ToolsTooLarge {
+                size,
+                limit,
+                largest,
+            } => {
+                let names: Vec<String> = largest
+                    .iter()
+                    .map(|(name, bytes)| format!("{} ({} 字节)", name, bytes))
+                    .collect();
+                write!(
+                    f,
+                    "工具定义过大: {} 字节，超过上限 {} 字节，占用最大的工具: {}",
+                    size,
+                    limit,
+                    names.join(", ")
+                )
+            }
+            ConversionError::UnknownToolChoice(name) => {
+                write!(f, "tool_choice 指定的工具不存在: {}", name)
+            }
+            ConversionError::MaxTokensExceeded {
+                model,
+                requested,
+                limit,
+            } => write!(
+                f,
+                "max_tokens {} 超过模型 {} 的输出上限 {}",
+                requested, model, limit
+            ),
         }
     }
 }
@@ -203,10 +419,26 @@ fn create_placeholder_tool(name: &str) -> Tool {
 }
 
 /// 将 Anthropic 请求转换为 Kiro 请求
-pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, ConversionError> {
+pub fn convert_request(
+    req: &MessagesRequest,
+    options: &ConversionOptions,
+) -> Result<ConversionResult, ConversionError> {
     // 1. 映射模型
-    let model_id = map_model(&req.model)
-        .ok_or_else(|| ConversionError::UnsupportedModel(req.model.clone()))?;
+    let mut warnings = Vec::new();
+    let model_id = match options.resolve_known_model(&req.model) {
+        Some(model_id) => model_id,
+        None => {
+            let fallback = options
+                .fallback_model
+                .clone()
+                .ok_or_else(|| ConversionError::UnsupportedModel(req.model.clone()))?;
+            warnings.push(format!(
+                "unknown model {} mapped to fallback {}",
+                req.model, fallback
+            ));
+            fallback
+        }
+    };
 
     // 2. 检查消息列表
     if req.messages.is_empty() {
@@ -217,6 +449,7 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
     // Claude 4.x 已弃用 assistant prefill，Kiro API 也不支持
     let messages: &[_] = if req.messages.last().is_some_and(|m| m.role != "user") {
         tracing::info!("检测到末尾 assistant 消息（prefill），静默丢弃");
+        warnings.push("trailing assistant prefill message dropped".to_string());
         let last_user_idx = req
             .messages
             .iter()
@@ -227,6 +460,21 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
         &req.messages
     };
 
+    // 2.6. 折叠历史中重复的 system / 文本内容（Claude Code 每轮都会重发 system-reminder 等）
+    let deduped;
+    let (system, messages) = if options.dedupe_repeated_text {
+        deduped = dedupe_repeated_text(req.system.as_deref(), messages);
+        if deduped.2 > 0 {
+            warnings.push(format!(
+                "{} duplicated system/history text block(s) collapsed",
+                deduped.2
+            ));
+        }
+        (deduped.0.as_deref(), deduped.1.as_slice())
+    } else {
+        (req.system.as_deref(), messages)
+    };
+
     // 3. 生成会话 ID 和代理 ID
     // 优先从 metadata.user_id 中提取 session UUID 作为 conversationId
     let conversation_id = req
@@ -237,6 +485,9 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
         .unwrap_or_else(|| Uuid::new_v4().to_string());
     let agent_continuation_id = Uuid::new_v4().to_string();
 
+    let (tool_choice, disable_parallel) =
+        parse_tool_choice(req.tool_choice.as_ref(), &mut warnings);
+
     // 4. 确定触发类型
     let chat_trigger_type = determine_chat_trigger_type(req);
 
@@ -245,16 +496,29 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
     let (text_content, images, tool_results) = process_message_content(&last_message.content)?;
 
     // 6. 转换工具定义
-    let mut tools = convert_tools(&req.tools);
+    let mut tools = convert_tools(&req.tools, &mut warnings);
+    apply_tool_choice(&mut tools, &tool_choice)?;
 
     // 7. 构建历史消息（需要先构建，以便收集历史中使用的工具）
-    let mut history = build_history(req, messages, &model_id)?;
+    let mut history = build_history(req, system, messages, &model_id, &mut warnings)?;
 
     // 8. 验证并过滤 tool_use/tool_result 配对
     // 移除孤立的 tool_result（没有对应的 tool_use）
     // 同时返回孤立的 tool_use_id 集合，用于后续清理
     let (validated_tool_results, orphaned_tool_use_ids) =
         validate_tool_pairing(&history, &tool_results);
+    if validated_tool_results.len() < tool_results.len() {
+        warnings.push(format!(
+            "{} orphaned or duplicate tool_result block(s) dropped",
+            tool_results.len() - validated_tool_results.len()
+        ));
+    }
+    if !orphaned_tool_use_ids.is_empty() {
+        warnings.push(format!(
+            "{} tool_use block(s) without tool_result removed from history",
+            orphaned_tool_use_ids.len()
+        ));
+    }
 
     // 9. 从历史中移除孤立的 tool_use（Kiro API 要求 tool_use 必须有对应的 tool_result）
     remove_orphaned_tool_uses(&mut history, &orphaned_tool_use_ids);
@@ -270,10 +534,24 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
 
     for tool_name in history_tool_names {
         if !existing_tool_names.contains(&tool_name.to_lowercase()) {
+            warnings.push(format!(
+                "placeholder definition added for history tool '{}'",
+                tool_name
+            ));
             tools.push(create_placeholder_tool(&tool_name));
         }
     }
 
+    // 占位符定义同样发往上游，加入后再检查工具定义总大小
+    if let Some(limit) = options.max_tools_bytes
+        && enforce_tools_size_limit(&mut tools, limit, options.tools_overflow)?
+    {
+        warnings.push(format!(
+            "tool descriptions compressed to fit {} byte limit",
+            limit
+        ));
+    }
+
     // 11. 构建 UserInputMessageContext
     let mut context = UserInputMessageContext::new();
     if !tools.is_empty() {
@@ -284,8 +562,12 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
     }
 
     // 12. 构建当前消息
-    // 保留文本内容，即使有工具结果也不丢弃用户文本
-    let content = text_content;
+    // 保留文本内容，即使有工具结果也不丢弃用户文本；tool_choice 以提示追加在末尾
+    let content = match tool_choice_guidance(&tool_choice, disable_parallel) {
+        Some(guidance) if text_content.is_empty() => guidance,
+        Some(guidance) => format!("{}\n\n{}", text_content, guidance),
+        None => text_content,
+    };
 
     let mut user_input = UserInputMessage::new(content, &model_id)
         .with_context(context)
@@ -305,7 +587,112 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
         .with_current_message(current_message)
         .with_history(history);
 
-    Ok(ConversionResult { conversation_state })
+    Ok(ConversionResult {
+        conversation_state,
+        warnings,
+    })
+}
+
+/// 参与去重的文本最小长度（字符），过短的文本重复是正常对话内容
+const DEDUP_MIN_CHARS: usize = 200;
+
+/// 重复内容被折叠后的占位文本
+const DEDUP_PLACEHOLDER: &str = "[duplicate content omitted]";
+
+/// 折叠重复的 system 条目以及历史消息中重复出现的长文本块，返回折叠的条目数
+///
+/// - system 中完全相同的条目只保留第一条
+/// - 历史 user 消息中与 system 或更早内容完全相同的长文本块被移除，
+///   若消息因此没有任何内容则替换为占位文本
+/// - 最后一条消息（currentMessage）保持原样
+fn dedupe_repeated_text(
+    system: Option<&[SystemMessage]>,
+    messages: &[super::types::Message],
+) -> (
+    Option<Vec<SystemMessage>>,
+    Vec<super::types::Message>,
+    usize,
+) {
+    use std::collections::HashSet;
+
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut saved_chars = 0usize;
+    let mut collapsed = 0usize;
+
+    let system = system.map(|items| {
+        items
+            .iter()
+            .filter(|item| {
+                if seen.insert(item.text.clone()) {
+                    true
+                } else {
+                    saved_chars += item.text.len();
+                    collapsed += 1;
+                    false
+                }
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+    });
+
+    let last_index = messages.len().saturating_sub(1);
+    let mut is_duplicate = |text: &str| {
+        if text.chars().count() < DEDUP_MIN_CHARS {
+            return false;
+        }
+        if seen.insert(text.to_string()) {
+            false
+        } else {
+            saved_chars += text.len();
+            collapsed += 1;
+            true
+        }
+    };
+
+    let messages = messages
+        .iter()
+        .enumerate()
+        .map(|(index, msg)| {
+            if index == last_index || msg.role != "user" {
+                return msg.clone();
+            }
+            let content = match &msg.content {
+                serde_json::Value::String(text) if is_duplicate(text) => {
+                    serde_json::Value::String(DEDUP_PLACEHOLDER.to_string())
+                }
+                serde_json::Value::Array(blocks) => {
+                    let mut kept: Vec<serde_json::Value> = blocks
+                        .iter()
+                        .filter(|block| {
+                            let is_text =
+                                block.get("type").and_then(|t| t.as_str()) == Some("text");
+                            let text = block.get("text").and_then(|t| t.as_str());
+                            !(is_text && text.is_some_and(&mut is_duplicate))
+                        })
+                        .cloned()
+                        .collect();
+                    if kept.is_empty() && !blocks.is_empty() {
+                        kept.push(serde_json::json!({"type": "text", "text": DEDUP_PLACEHOLDER}));
+                    }
+                    serde_json::Value::Array(kept)
+                }
+                other => other.clone(),
+            };
+            super::types::Message {
+                role: msg.role.clone(),
+                content,
+            }
+        })
+        .collect();
+
+    if saved_chars > 0 {
+        tracing::debug!(
+            "已折叠重复的 system / 历史文本内容，节省 {} 字节",
+            saved_chars
+        );
+    }
+
+    (system, messages, collapsed)
 }
 
 /// 确定聊天触发类型
@@ -336,11 +723,10 @@ fn process_message_content(
                             }
                         }
                         "image" => {
-                            if let Some(source) = block.source {
-                                if let Some(format) = get_image_format(&source.media_type) {
-                                    images.push(KiroImage::from_base64(format, source.data));
-                                }
-                            }
+                            let source = block.source.ok_or_else(|| {
+                                ConversionError::UnsupportedImage("image 块缺少 source".to_string())
+                            })?;
+                            images.push(convert_image(source)?);
                         }
                         "tool_result" => {
                             if let Some(tool_use_id) = block.tool_use_id {
@@ -372,6 +758,49 @@ fn process_message_content(
     Ok((text_parts.join("\n"), images, tool_results))
 }
 
+/// 转换图片数据源为 Kiro 图片（base64 或 `data:` URL）
+fn convert_image(source: ImageSource) -> Result<KiroImage, ConversionError> {
+    let (media_type, data) = match source.source_type.as_str() {
+        "base64" => (
+            source.media_type.unwrap_or_default(),
+            source.data.unwrap_or_default(),
+        ),
+        "url" => {
+            let url = source.url.unwrap_or_default();
+            let Some((media_type, data)) = parse_data_url(&url) else {
+                return Err(ConversionError::UnsupportedImage(
+                    "上游不支持远程图片 URL，请改用 base64 或 data: URL".to_string(),
+                ));
+            };
+            (media_type.to_string(), data.to_string())
+        }
+        other => {
+            return Err(ConversionError::UnsupportedImage(format!(
+                "不支持的图片来源类型: {}",
+                other
+            )));
+        }
+    };
+    let format = get_image_format(&media_type).ok_or_else(|| {
+        ConversionError::UnsupportedImage(format!(
+            "不支持的图片格式: {}（支持 jpeg / png / gif / webp）",
+            media_type
+        ))
+    })?;
+    if data.is_empty() {
+        return Err(ConversionError::UnsupportedImage(
+            "图片数据为空".to_string(),
+        ));
+    }
+    Ok(KiroImage::from_base64(format, data))
+}
+
+/// 解析 `data:<media_type>;base64,<data>` 形式的图片 URL
+fn parse_data_url(url: &str) -> Option<(&str, &str)> {
+    let (meta, data) = url.strip_prefix("data:")?.split_once(',')?;
+    Some((meta.strip_suffix(";base64")?, data))
+}
+
 /// 从 media_type 获取图片格式
 fn get_image_format(media_type: &str) -> Option<String> {
     match media_type {
@@ -522,7 +951,7 @@ fn remove_orphaned_tool_uses(
 }
 
 /// 转换工具定义
-fn convert_tools(tools: &Option<Vec<super::types::Tool>>) -> Vec<Tool> {
+fn convert_tools(tools: &Option<Vec<super::types::Tool>>, warnings: &mut Vec<String>) -> Vec<Tool> {
     let Some(tools) = tools else {
         return Vec::new();
     };
@@ -545,7 +974,13 @@ fn convert_tools(tools: &Option<Vec<super::types::Tool>>) -> Vec<Tool> {
 
             // 限制描述长度为 10000 字符（安全截断 UTF-8，单次遍历）
             let description = match description.char_indices().nth(10000) {
-                Some((idx, _)) => description[..idx].to_string(),
+                Some((idx, _)) => {
+                    warnings.push(format!(
+                        "description of tool '{}' truncated to 10000 chars",
+                        t.name
+                    ));
+                    description[..idx].to_string()
+                }
                 None => description,
             };
 
@@ -562,20 +997,177 @@ fn convert_tools(tools: &Option<Vec<super::types::Tool>>) -> Vec<Tool> {
         .collect()
 }
 
+/// 客户端要求的工具调用方式
+///
+/// 上游没有原生的 tool_choice，通过过滤工具定义并在当前消息末尾追加提示来模拟。
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ToolChoice {
+    Auto,
+    /// 必须调用任一工具
+    Any,
+    /// 必须调用指定工具，仅发送该工具定义
+    Tool(String),
+    /// 不调用工具，不发送工具定义
+    None,
+}
+
+/// 解析 tool_choice，返回调用方式与是否禁止并行调用（`disable_parallel_tool_use`）
+fn parse_tool_choice(
+    value: Option<&serde_json::Value>,
+    warnings: &mut Vec<String>,
+) -> (ToolChoice, bool) {
+    let Some(value) = value else {
+        return (ToolChoice::Auto, false);
+    };
+    let disable_parallel = value
+        .get("disable_parallel_tool_use")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let choice_type = value
+        .get("type")
+        .and_then(|v| v.as_str())
+        .or_else(|| value.as_str());
+    let choice = match choice_type {
+        Some("auto") => ToolChoice::Auto,
+        Some("any") => ToolChoice::Any,
+        Some("none") => ToolChoice::None,
+        Some("tool") => match value.get("name").and_then(|v| v.as_str()) {
+            Some(name) => ToolChoice::Tool(name.to_string()),
+            None => {
+                warnings.push("tool_choice of type 'tool' without name was ignored".to_string());
+                ToolChoice::Auto
+            }
+        },
+        _ => {
+            warnings.push(format!("unsupported tool_choice {} was ignored", value));
+            ToolChoice::Auto
+        }
+    };
+    (choice, disable_parallel)
+}
+
+/// 按 tool_choice 过滤工具定义（历史中引用的工具之后仍会补充占位定义）
+fn apply_tool_choice(tools: &mut Vec<Tool>, choice: &ToolChoice) -> Result<(), ConversionError> {
+    match choice {
+        ToolChoice::None => tools.clear(),
+        ToolChoice::Tool(name) => {
+            tools.retain(|t| t.tool_specification.name == *name);
+            if tools.is_empty() {
+                return Err(ConversionError::UnknownToolChoice(name.clone()));
+            }
+        }
+        ToolChoice::Auto | ToolChoice::Any => {}
+    }
+    Ok(())
+}
+
+/// tool_choice 对应的提示，默认行为时为 None
+fn tool_choice_guidance(choice: &ToolChoice, disable_parallel: bool) -> Option<String> {
+    let guidance = match choice {
+        ToolChoice::Auto => None,
+        ToolChoice::Any => {
+            Some("You must call at least one of the available tools in this response.".to_string())
+        }
+        ToolChoice::Tool(name) => Some(format!(
+            "You must call the `{}` tool in this response.",
+            name
+        )),
+        ToolChoice::None => {
+            return Some(
+                "Do not call any tools in this response; reply with text only.".to_string(),
+            );
+        }
+    };
+    match (guidance, disable_parallel) {
+        (Some(guidance), true) => Some(format!("{} Call at most one tool.", guidance)),
+        (None, true) => Some("Call at most one tool in this response.".to_string()),
+        (guidance, false) => guidance,
+    }
+}
+
+/// 工具描述逐级压缩的长度上限（字符）
+const TOOL_DESCRIPTION_COMPRESS_STEPS: &[usize] = &[2000, 500, 120];
+
+/// 单个工具序列化后的字节数
+fn tool_size(tool: &Tool) -> usize {
+    serde_json::to_vec(tool).map(|v| v.len()).unwrap_or(0)
+}
+
+/// 截断字符串到指定字符数（UTF-8 安全）
+fn truncate_chars(text: &str, max_chars: usize) -> Option<String> {
+    text.char_indices()
+        .nth(max_chars)
+        .map(|(idx, _)| text[..idx].to_string())
+}
+
+/// 检查工具定义总大小，按策略压缩描述或返回包含最大工具列表的错误
+///
+/// 返回是否进行了压缩
+fn enforce_tools_size_limit(
+    tools: &mut [Tool],
+    limit: usize,
+    strategy: ToolsOverflowStrategy,
+) -> Result<bool, ConversionError> {
+    let total = |tools: &[Tool]| tools.iter().map(tool_size).sum::<usize>();
+
+    let mut size = total(tools);
+    if size <= limit {
+        return Ok(false);
+    }
+
+    if strategy == ToolsOverflowStrategy::Compress {
+        let original = size;
+        for &max_chars in TOOL_DESCRIPTION_COMPRESS_STEPS {
+            for tool in tools.iter_mut() {
+                let description = &mut tool.tool_specification.description;
+                if let Some(truncated) = truncate_chars(description, max_chars) {
+                    *description = truncated;
+                }
+            }
+            size = total(tools);
+            if size <= limit {
+                tracing::info!(
+                    "工具定义超限，已压缩工具描述: {} -> {} bytes（上限 {}）",
+                    original,
+                    size,
+                    limit
+                );
+                return Ok(true);
+            }
+        }
+    }
+
+    let mut largest: Vec<(String, usize)> = tools
+        .iter()
+        .map(|t| (t.tool_specification.name.clone(), tool_size(t)))
+        .collect();
+    largest.sort_by_key(|(_, bytes)| std::cmp::Reverse(*bytes));
+    largest.truncate(5);
+
+    Err(ConversionError::ToolsTooLarge {
+        size,
+        limit,
+        largest,
+    })
+}
+
 /// 生成thinking标签前缀
 fn generate_thinking_prefix(req: &MessagesRequest) -> Option<String> {
-    if let Some(t) = &req.thinking {
+    thinking_prefix(req.thinking.as_ref(), req.output_config.as_ref())
+}
+
+fn thinking_prefix(
+    thinking: Option<&Thinking>,
+    output_config: Option<&OutputConfig>,
+) -> Option<String> {
+    if let Some(t) = thinking {
         if t.thinking_type == "enabled" {
             return Some(format!(
                 "<thinking_mode>enabled</thinking_mode><max_thinking_length>{}</max_thinking_length>",
                 t.budget_tokens
             ));
         } else if t.thinking_type == "adaptive" {
-            let effort = req
-                .output_config
-                .as_ref()
-                .map(|c| c.effort.as_str())
-                .unwrap_or("high");
+            let effort = output_config.map(|c| c.effort.as_str()).unwrap_or("high");
             return Some(format!(
                 "<thinking_mode>adaptive</thinking_mode><thinking_effort>{}</thinking_effort>",
                 effort
@@ -585,6 +1177,41 @@ fn generate_thinking_prefix(req: &MessagesRequest) -> Option<String> {
     None
 }
 
+/// 转换时注入到上游对话中的文本（thinking 标签、分块写入策略、系统消息的配对回复及 tool_choice 提示）
+///
+/// 与 `build_history` 和当前消息的注入逻辑保持一致，供 token 估算计入这部分开销。
+pub(crate) fn injected_prompt_text(
+    system: Option<&[SystemMessage]>,
+    thinking: Option<&Thinking>,
+    output_config: Option<&OutputConfig>,
+    tool_choice: Option<&serde_json::Value>,
+) -> String {
+    let prefix = thinking_prefix(thinking, output_config);
+    let system_content = system
+        .map(|s| {
+            s.iter()
+                .map(|m| m.text.as_str())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default();
+
+    let mut parts = Vec::new();
+    if !system_content.is_empty() {
+        if let Some(prefix) = prefix.filter(|_| !has_thinking_tags(&system_content)) {
+            parts.push(prefix);
+        }
+        parts.push(SYSTEM_CHUNKED_POLICY.to_string());
+        parts.push(SYSTEM_ACK.to_string());
+    } else if let Some(prefix) = prefix {
+        parts.push(prefix);
+        parts.push(SYSTEM_ACK.to_string());
+    }
+    let (choice, disable_parallel) = parse_tool_choice(tool_choice, &mut Vec::new());
+    parts.extend(tool_choice_guidance(&choice, disable_parallel));
+    parts.join("\n")
+}
+
 /// 检查内容是否已包含thinking标签
 fn has_thinking_tags(content: &str) -> bool {
     content.contains("<thinking_mode>") || content.contains("<max_thinking_length>")
@@ -600,8 +1227,10 @@ fn has_thinking_tags(content: &str) -> bool {
 /// * `model_id` - 已映射的 Kiro 模型 ID
 fn build_history(
     req: &MessagesRequest,
+    system: Option<&[SystemMessage]>,
     messages: &[super::types::Message],
     model_id: &str,
+    warnings: &mut Vec<String>,
 ) -> Result<Vec<Message>, ConversionError> {
     let mut history = Vec::new();
 
@@ -609,7 +1238,7 @@ fn build_history(
     let thinking_prefix = generate_thinking_prefix(req);
 
     // 1. 处理系统消息
-    if let Some(ref system) = req.system {
+    if let Some(system) = system {
         let system_content: String = system
             .iter()
             .map(|s| s.text.clone())
@@ -635,7 +1264,7 @@ fn build_history(
             let user_msg = HistoryUserMessage::new(final_content, model_id);
             history.push(Message::User(user_msg));
 
-            let assistant_msg = HistoryAssistantMessage::new("I will follow these instructions.");
+            let assistant_msg = HistoryAssistantMessage::new(SYSTEM_ACK);
             history.push(Message::Assistant(assistant_msg));
         }
     } else if let Some(ref prefix) = thinking_prefix {
@@ -643,7 +1272,7 @@ fn build_history(
         let user_msg = HistoryUserMessage::new(prefix.clone(), model_id);
         history.push(Message::User(user_msg));
 
-        let assistant_msg = HistoryAssistantMessage::new("I will follow these instructions.");
+        let assistant_msg = HistoryAssistantMessage::new(SYSTEM_ACK);
         history.push(Message::Assistant(assistant_msg));
     }
 
@@ -655,6 +1284,8 @@ fn build_history(
     // 收集并配对消息
     let mut user_buffer: Vec<&super::types::Message> = Vec::new();
     let mut assistant_buffer: Vec<&super::types::Message> = Vec::new();
+    // 被合并的连续同角色消息数量（用于转换提示）
+    let mut merged_count = 0usize;
 
     for i in 0..history_end_index {
         let msg = &messages[i];
@@ -662,6 +1293,7 @@ fn build_history(
         if msg.role == "user" {
             // 先处理累积的 assistant 消息
             if !assistant_buffer.is_empty() {
+                merged_count += assistant_buffer.len() - 1;
                 let merged = merge_assistant_messages(&assistant_buffer)?;
                 history.push(Message::Assistant(merged));
                 assistant_buffer.clear();
@@ -670,6 +1302,7 @@ fn build_history(
         } else if msg.role == "assistant" {
             // 先处理累积的 user 消息
             if !user_buffer.is_empty() {
+                merged_count += user_buffer.len() - 1;
                 let merged_user = merge_user_messages(&user_buffer, model_id)?;
                 history.push(Message::User(merged_user));
                 user_buffer.clear();
@@ -681,18 +1314,29 @@ fn build_history(
 
     // 处理末尾累积的 assistant 消息
     if !assistant_buffer.is_empty() {
+        merged_count += assistant_buffer.len() - 1;
         let merged = merge_assistant_messages(&assistant_buffer)?;
         history.push(Message::Assistant(merged));
     }
 
     // 处理结尾的孤立 user 消息
     if !user_buffer.is_empty() {
+        merged_count += user_buffer.len() - 1;
         let merged_user = merge_user_messages(&user_buffer, model_id)?;
         history.push(Message::User(merged_user));
 
         // 自动配对一个 "OK" 的 assistant 响应
         let auto_assistant = HistoryAssistantMessage::new("OK");
         history.push(Message::Assistant(auto_assistant));
+        warnings
+            .push("history user message auto-paired with placeholder assistant reply".to_string());
+    }
+
+    if merged_count > 0 {
+        warnings.push(format!(
+            "{} consecutive same-role message(s) merged to fix role alternation",
+            merged_count
+        ));
     }
 
     Ok(history)
@@ -860,6 +1504,159 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_resolve_model_uses_mappings_first() {
+        let mut config = Config::default();
+        config
+            .model_mappings
+            .insert("GPT-4o".to_string(), "claude-sonnet-4.6".to_string());
+        config.model_mappings.insert(
+            "claude-3-5-sonnet-latest".to_string(),
+            "claude-opus-4.5".to_string(),
+        );
+        let options = ConversionOptions::from_config(&config);
+
+        assert_eq!(
+            options.resolve_model("gpt-4o").unwrap(),
+            "claude-sonnet-4.6"
+        );
+        assert_eq!(
+            options.resolve_model("claude-3-5-sonnet-latest").unwrap(),
+            "claude-opus-4.5"
+        );
+        // 未映射的模型仍走内置规则
+        assert_eq!(
+            options.resolve_model("claude-haiku-4-5").unwrap(),
+            "claude-haiku-4.5"
+        );
+        assert!(options.resolve_model("gpt-4").is_none());
+    }
+
+    #[test]
+    fn test_wildcard_mappings_and_fallback_model() {
+        assert!(wildcard_match(
+            "claude-sonnet-4-5*",
+            "claude-sonnet-4-5-20250929"
+        ));
+        assert!(wildcard_match("*-mini*", "gpt-4o-mini-2024"));
+        assert!(!wildcard_match("claude-*-4-5", "claude-sonnet-4-5-x"));
+
+        let mut config = Config::default();
+        config
+            .model_mappings
+            .insert("gpt-*".to_string(), "claude-sonnet-4.5".to_string());
+        config
+            .model_mappings
+            .insert("gpt-4o*".to_string(), "claude-sonnet-4.6".to_string());
+        config
+            .model_mappings
+            .insert("gpt-4o-mini".to_string(), "claude-haiku-4.5".to_string());
+        let options = ConversionOptions::from_config(&config);
+
+        // 精确映射优先，其次是更具体的通配映射
+        assert_eq!(
+            options.resolve_model("GPT-4o-mini").unwrap(),
+            "claude-haiku-4.5"
+        );
+        assert_eq!(
+            options.resolve_model("gpt-4o-2024").unwrap(),
+            "claude-sonnet-4.6"
+        );
+        assert_eq!(options.resolve_model("gpt-5").unwrap(), "claude-sonnet-4.5");
+        assert!(options.resolve_model("o3").is_none());
+
+        // 无法识别的模型使用回退模型，并给出警告
+        config.fallback_model = Some("claude-sonnet-4.6".to_string());
+        let options = ConversionOptions::from_config(&config);
+        let req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "o3",
+            "max_tokens": 16,
+            "messages": [{"role": "user", "content": "hi"}],
+        }))
+        .unwrap();
+        let result = convert_request(&req, &options).unwrap();
+        assert_eq!(
+            result
+                .conversation_state
+                .current_message
+                .user_input_message
+                .model_id,
+            "claude-sonnet-4.6"
+        );
+        assert!(result.warnings[0].contains("fallback"));
+    }
+
+    #[test]
+    fn test_max_output_tokens_by_kiro_model() {
+        let mut config = Config::default();
+        config
+            .model_mappings
+            .insert("fast".to_string(), "claude-haiku-4.5".to_string());
+        config
+            .model_max_output_tokens
+            .insert("claude-haiku-4.5".to_string(), 8192);
+        config
+            .model_max_output_tokens
+            .insert("claude-opus-4.6".to_string(), 0);
+        let options = ConversionOptions::from_config(&config);
+
+        assert_eq!(
+            options.max_output_tokens("claude-haiku-4-5-20251001"),
+            Some(8192)
+        );
+        assert_eq!(options.max_output_tokens("fast"), Some(8192));
+        assert_eq!(options.max_output_tokens("claude-opus-4-6"), None);
+        assert_eq!(options.max_output_tokens("claude-sonnet-4-6"), None);
+    }
+
+    #[test]
+    fn test_default_and_overflow_max_tokens() {
+        use crate::anthropic::handlers::clamp_max_tokens;
+
+        let mut config = Config::default();
+        config
+            .model_max_output_tokens
+            .insert("claude-haiku-4.5".to_string(), 8192);
+        let request = |model: &str, max_tokens: i32| -> MessagesRequest {
+            serde_json::from_value(serde_json::json!({
+                "model": model,
+                "max_tokens": max_tokens,
+                "messages": [{"role": "user", "content": "hi"}],
+            }))
+            .unwrap()
+        };
+        let mut warnings = Vec::new();
+
+        // 未指定时补全为默认值，且不超过模型上限
+        let options = ConversionOptions::from_config(&config);
+        let mut payload = request("claude-sonnet-4-6", 0);
+        assert_eq!(
+            clamp_max_tokens(&mut payload, &options, &mut warnings).unwrap(),
+            None
+        );
+        assert_eq!(payload.max_tokens, 32000);
+        let mut payload = request("claude-haiku-4-5", 0);
+        assert_eq!(
+            clamp_max_tokens(&mut payload, &options, &mut warnings).unwrap(),
+            Some(8192)
+        );
+        assert!(warnings.is_empty());
+
+        // 超限时默认收紧，策略为 reject 时拒绝
+        let mut payload = request("claude-haiku-4-5", 10000);
+        clamp_max_tokens(&mut payload, &options, &mut warnings).unwrap();
+        assert_eq!(payload.max_tokens, 8192);
+        assert_eq!(warnings.len(), 1);
+
+        config.max_tokens_overflow_strategy = "reject".to_string();
+        let options = ConversionOptions::from_config(&config);
+        let mut payload = request("claude-haiku-4-5", 10000);
+        assert!(matches!(
+            clamp_max_tokens(&mut payload, &options, &mut warnings),
+            Err(ConversionError::MaxTokensExceeded { limit: 8192, .. })
+        ));
+    }
+
     #[test]
     fn test_map_model_opus() {
         assert!(
@@ -929,6 +1726,38 @@ mod tests {
         assert_eq!(determine_chat_trigger_type(&req), "MANUAL");
     }
 
+    #[test]
+    fn test_injected_prompt_text_matches_build_history() {
+        let thinking = Thinking {
+            thinking_type: "enabled".to_string(),
+            budget_tokens: 1024,
+        };
+        let system = vec![SystemMessage {
+            text: "be brief".to_string(),
+            cache_control: None,
+        }];
+
+        let text = injected_prompt_text(Some(&system), Some(&thinking), None, None);
+        assert!(text.contains("<max_thinking_length>1024</max_thinking_length>"));
+        assert!(text.contains(SYSTEM_CHUNKED_POLICY));
+        assert!(text.contains(SYSTEM_ACK));
+
+        // 没有 system 时仍会注入 thinking 前缀
+        let text = injected_prompt_text(None, Some(&thinking), None, None);
+        assert!(text.contains("<thinking_mode>enabled</thinking_mode>"));
+        assert!(!text.contains(SYSTEM_CHUNKED_POLICY));
+
+        // system 已含 thinking 标签时不再重复注入
+        let tagged = vec![SystemMessage {
+            text: "<thinking_mode>enabled</thinking_mode>".to_string(),
+            cache_control: None,
+        }];
+        let text = injected_prompt_text(Some(&tagged), Some(&thinking), None, None);
+        assert!(!text.contains("<max_thinking_length>"));
+
+        assert!(injected_prompt_text(None, None, None, None).is_empty());
+    }
+
     #[test]
     fn test_collect_history_tool_names() {
         use crate::kiro::model::requests::tool::ToolUseEntry;
@@ -1006,7 +1835,7 @@ mod tests {
             metadata: None,
         };
 
-        let result = convert_request(&req).unwrap();
+        let result = convert_request(&req, &ConversionOptions::default()).unwrap();
 
         // 验证 tools 列表中包含了历史中使用的工具的占位符定义
         let tools = &result
@@ -1075,7 +1904,7 @@ mod tests {
             }),
         };
 
-        let result = convert_request(&req).unwrap();
+        let result = convert_request(&req, &ConversionOptions::default()).unwrap();
         assert_eq!(
             result.conversation_state.conversation_id,
             "a0662283-7fd3-4399-a7eb-52b9a717ae88"
@@ -1103,7 +1932,7 @@ mod tests {
             metadata: None,
         };
 
-        let result = convert_request(&req).unwrap();
+        let result = convert_request(&req, &ConversionOptions::default()).unwrap();
         // 验证生成的是有效的 UUID 格式
         assert_eq!(result.conversation_state.conversation_id.len(), 36);
         assert_eq!(
@@ -1539,7 +2368,7 @@ mod tests {
             metadata: None,
         };
 
-        let result = convert_request(&req);
+        let result = convert_request(&req, &ConversionOptions::default());
         assert!(
             result.is_ok(),
             "连续 assistant 消息场景不应报错: {:?}",
@@ -1560,4 +2389,267 @@ mod tests {
         }
         assert!(found_tool_use, "合并后的 assistant 消息应包含 tool_use");
     }
+
+    fn sample_tools() -> Vec<Tool> {
+        let tools: Vec<super::super::types::Tool> = serde_json::from_value(serde_json::json!([
+            {"name": "small", "description": "tiny", "input_schema": {"type": "object"}},
+            {"name": "huge", "description": "x".repeat(5000), "input_schema": {"type": "object"}}
+        ]))
+        .unwrap();
+        convert_tools(&Some(tools), &mut Vec::new())
+    }
+
+    #[test]
+    fn test_tools_size_limit_reject_names_largest_tool() {
+        let mut tools = sample_tools();
+        let err =
+            enforce_tools_size_limit(&mut tools, 1000, ToolsOverflowStrategy::Reject).unwrap_err();
+        match err {
+            ConversionError::ToolsTooLarge { largest, limit, .. } => {
+                assert_eq!(limit, 1000);
+                assert_eq!(largest[0].0, "huge");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_tools_size_limit_compress_descriptions() {
+        let mut tools = sample_tools();
+        enforce_tools_size_limit(&mut tools, 1000, ToolsOverflowStrategy::Compress).unwrap();
+        assert!(tools.iter().map(tool_size).sum::<usize>() <= 1000);
+        assert_eq!(tools[0].tool_specification.description, "tiny");
+
+        // 压缩到最短仍超限时拒绝
+        let mut tools = sample_tools();
+        assert!(enforce_tools_size_limit(&mut tools, 10, ToolsOverflowStrategy::Compress).is_err());
+    }
+
+    #[test]
+    fn test_tools_size_limit_counts_history_placeholders() {
+        let mut req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "messages": [
+                {"role": "user", "content": "Read the file"},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "tool-1", "name": "read", "input": {"path": "/a"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "tool-1", "content": "file content"}
+                ]}
+            ],
+            "tools": [
+                {"name": "small", "description": "tiny", "input_schema": {"type": "object"}}
+            ]
+        }))
+        .unwrap();
+        let small = tool_size(&convert_tools(&req.tools, &mut Vec::new())[0]);
+        let options = ConversionOptions {
+            max_tools_bytes: Some(small + 10),
+            ..ConversionOptions::default()
+        };
+
+        // 请求中的工具未超限，加入历史工具的占位符后超限
+        match convert_request(&req, &options) {
+            Err(ConversionError::ToolsTooLarge { size, limit, .. }) => {
+                assert!(size > limit);
+                assert_eq!(limit, small + 10);
+            }
+            other => panic!("unexpected result: {:?}", other.map(|r| r.warnings)),
+        }
+
+        req.messages.truncate(1);
+        assert!(convert_request(&req, &options).is_ok());
+    }
+
+    #[test]
+    fn test_dedupe_repeated_text_collapses_history_duplicates() {
+        use super::super::types::Message as AnthropicMessage;
+
+        let reminder = "<system-reminder>".to_string() + &"r".repeat(300) + "</system-reminder>";
+        let system = vec![
+            SystemMessage {
+                text: "same".to_string(),
+                cache_control: None,
+            },
+            SystemMessage {
+                text: "same".to_string(),
+                cache_control: None,
+            },
+        ];
+        let messages = vec![
+            AnthropicMessage {
+                role: "user".to_string(),
+                content: serde_json::json!([
+                    {"type": "text", "text": reminder},
+                    {"type": "text", "text": "first question"}
+                ]),
+            },
+            AnthropicMessage {
+                role: "assistant".to_string(),
+                content: serde_json::json!("answer"),
+            },
+            AnthropicMessage {
+                role: "user".to_string(),
+                content: serde_json::json!([{"type": "text", "text": reminder}]),
+            },
+            AnthropicMessage {
+                role: "assistant".to_string(),
+                content: serde_json::json!("answer"),
+            },
+            AnthropicMessage {
+                role: "user".to_string(),
+                content: serde_json::json!([{"type": "text", "text": reminder}]),
+            },
+        ];
+
+        let (system, messages, collapsed) = dedupe_repeated_text(Some(&system), &messages);
+
+        assert_eq!(collapsed, 2);
+        assert_eq!(system.unwrap().len(), 1);
+        // 首次出现保留
+        assert_eq!(messages[0].content.as_array().unwrap().len(), 2);
+        // 历史中的重复被替换为占位文本
+        assert_eq!(
+            messages[2].content,
+            serde_json::json!([{"type": "text", "text": DEDUP_PLACEHOLDER}])
+        );
+        // currentMessage 保持原样
+        assert_eq!(
+            messages[4].content,
+            serde_json::json!([{"type": "text", "text": reminder}])
+        );
+    }
+
+    #[test]
+    fn test_conversion_options_with_request_flags() {
+        let flags = RequestFlags {
+            beta: Some("compress-tools, Dedupe-Text".to_string()),
+            debug: Some("dry-run".to_string()),
+        };
+        assert!(flags.has_debug("dry-run"));
+        assert!(!flags.has_debug("verbose"));
+
+        let options = ConversionOptions::default().with_request_flags(&flags);
+        assert_eq!(options.tools_overflow, ToolsOverflowStrategy::Compress);
+        assert!(options.dedupe_repeated_text);
+
+        let options = ConversionOptions::default().with_request_flags(&RequestFlags::default());
+        assert_eq!(options.tools_overflow, ToolsOverflowStrategy::Reject);
+        assert!(!options.dedupe_repeated_text);
+    }
+
+    #[test]
+    fn test_tool_choice_emulation() {
+        let request = |tool_choice: serde_json::Value| -> MessagesRequest {
+            serde_json::from_value(serde_json::json!({
+                "model": "claude-sonnet-4",
+                "max_tokens": 1024,
+                "messages": [{"role": "user", "content": "Weather?"}],
+                "tools": [
+                    {"name": "get_weather", "description": "w", "input_schema": {"type": "object"}},
+                    {"name": "search", "description": "s", "input_schema": {"type": "object"}}
+                ],
+                "tool_choice": tool_choice
+            }))
+            .unwrap()
+        };
+        let convert = |tool_choice| {
+            let result =
+                convert_request(&request(tool_choice), &ConversionOptions::default()).unwrap();
+            let message = result.conversation_state.current_message.user_input_message;
+            let tools: Vec<String> = message
+                .user_input_message_context
+                .tools
+                .iter()
+                .map(|t| t.tool_specification.name.clone())
+                .collect();
+            (message.content, tools)
+        };
+
+        let (content, tools) = convert(serde_json::json!({"type": "auto"}));
+        assert_eq!(content, "Weather?");
+        assert_eq!(tools.len(), 2);
+
+        let (content, tools) = convert(serde_json::json!({"type": "tool", "name": "get_weather"}));
+        assert!(content.ends_with("You must call the `get_weather` tool in this response."));
+        assert_eq!(tools, vec!["get_weather"]);
+
+        let (content, tools) =
+            convert(serde_json::json!({"type": "any", "disable_parallel_tool_use": true}));
+        assert!(content.contains("at least one") && content.ends_with("Call at most one tool."));
+        assert_eq!(tools.len(), 2);
+
+        let (content, tools) = convert(serde_json::json!({"type": "none"}));
+        assert!(content.contains("Do not call any tools"));
+        assert!(tools.is_empty());
+
+        let err = convert_request(
+            &request(serde_json::json!({"type": "tool", "name": "missing"})),
+            &ConversionOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ConversionError::UnknownToolChoice(name) if name == "missing"));
+    }
+
+    #[test]
+    fn test_convert_request_collects_warnings() {
+        use super::super::types::Message as AnthropicMessage;
+
+        let req = MessagesRequest {
+            model: "claude-sonnet-4".to_string(),
+            max_tokens: 1024,
+            messages: vec![
+                AnthropicMessage {
+                    role: "user".to_string(),
+                    content: serde_json::json!("Hello"),
+                },
+                AnthropicMessage {
+                    role: "assistant".to_string(),
+                    content: serde_json::json!("Prefill"),
+                },
+            ],
+            stream: false,
+            system: None,
+            tools: None,
+            tool_choice: Some(serde_json::json!({"type": "bogus"})),
+            thinking: None,
+            output_config: None,
+            metadata: None,
+        };
+
+        let result = convert_request(&req, &ConversionOptions::default()).unwrap();
+        assert_eq!(result.warnings.len(), 2);
+        assert!(result.warnings[0].contains("prefill"));
+        assert!(result.warnings[1].contains("tool_choice"));
+    }
+
+    #[test]
+    fn test_process_message_content_images() {
+        let content = serde_json::json!([
+            {"type": "text", "text": "What is this?"},
+            {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}},
+            {"type": "image", "source": {"type": "url", "url": "data:image/jpeg;base64,/9j/4AAQ"}}
+        ]);
+        let (text, images, _) = process_message_content(&content).unwrap();
+        assert_eq!(text, "What is this?");
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].format, "png");
+        assert_eq!(images[0].source.bytes, "iVBORw0KGgo=");
+        assert_eq!(images[1].format, "jpeg");
+        assert_eq!(images[1].source.bytes, "/9j/4AAQ");
+
+        for source in [
+            serde_json::json!({"type": "url", "url": "https://example.com/a.png"}),
+            serde_json::json!({"type": "base64", "media_type": "image/bmp", "data": "Qk0="}),
+            serde_json::json!({"type": "file", "file_id": "file_1"}),
+        ] {
+            let content = serde_json::json!([{"type": "image", "source": source}]);
+            assert!(matches!(
+                process_message_content(&content),
+                Err(ConversionError::UnsupportedImage(_))
+            ));
+        }
+    }
 }
@@ -3,43 +3,79 @@
 use std::convert::Infallible;
 use std::time::Instant;
 
+use crate::access_log::AccessFields;
 use crate::apikeys::AuthenticatedApiKey;
 use crate::kiro::model::events::Event;
 use crate::kiro::model::requests::kiro::KiroRequest;
 use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::provider::{KiroProvider, ProviderError, ServedBy};
+use crate::model::config::Config;
 use crate::request_log::{RequestLog, RequestLogEntry};
 use crate::token;
+use crate::upstream::{self, Fallback};
+use crate::webhook::{self, KeyWebhook, WebhookEvent};
 use anyhow::Error;
 use axum::{
     Json as JsonExtractor,
     body::Body,
-    extract::{Extension, State},
-    http::{StatusCode, header},
+    extract::{Extension, Query, State},
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Json, Response},
 };
 use bytes::Bytes;
 use futures::{Stream, StreamExt, stream};
+use http_body::Frame;
+use http_body_util::StreamBody;
 use serde_json::json;
 use std::time::Duration;
 use tokio::time::interval;
 use uuid::Uuid;
 
-use super::converter::{ConversionError, convert_request};
+use super::compaction;
+use super::converter::{
+    ConversionError, ConversionOptions, MaxTokensOverflowStrategy, convert_request,
+    injected_prompt_text,
+};
+use super::heartbeat::Heartbeat;
+use super::idempotency::Lookup;
+use super::message_id;
 use super::middleware::AppState;
+use super::prompt_cache::CacheUsage;
 use super::stream::{BufferedStreamContext, SseEvent, StreamContext};
 use super::types::{
-    CountTokensRequest, CountTokensResponse, ErrorResponse, MessagesRequest, Model, ModelsResponse,
-    OutputConfig, Thinking,
+    BrowserTokenRequest, BrowserTokenResponse, CountTokensRequest, CountTokensResponse,
+    ErrorResponse, MessagesRequest, Model, ModelsResponse, OutputConfig, RequestFlags,
+    SystemMessage, Thinking,
 };
 use super::websearch;
 
+/// 将请求转换错误映射为 HTTP 响应
+pub(crate) fn map_conversion_error(err: ConversionError) -> Response {
+    tracing::warn!("请求转换失败: {}", err);
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse::new("invalid_request_error", err.to_string())),
+    )
+        .into_response()
+}
+
 /// 将 KiroProvider 错误映射为 HTTP 响应
-fn map_provider_error(err: Error) -> Response {
-    let err_str = err.to_string();
+pub(crate) fn map_provider_error(err: Error) -> Response {
+    let Some(provider_err) = err.downcast_ref::<ProviderError>() else {
+        tracing::error!("Kiro API 调用失败: {}", err);
+        return (
+            StatusCode::BAD_GATEWAY,
+            Json(ErrorResponse::new(
+                "api_error",
+                format!("上游 API 调用失败: {}", err),
+            )),
+        )
+            .into_response();
+    };
 
     // 上下文窗口满了（对话历史累积超出模型上下文窗口限制）
-    if err_str.contains("CONTENT_LENGTH_EXCEEDS_THRESHOLD") {
-        tracing::warn!(error = %err, "上游拒绝请求：上下文窗口已满（不应重试）");
+    if provider_err.is_context_window_full() {
+        tracing::warn!(error = %provider_err, "上游拒绝请求：上下文窗口已满（不应重试）");
         return (
             StatusCode::BAD_REQUEST,
             Json(ErrorResponse::new(
@@ -51,8 +87,8 @@ fn map_provider_error(err: Error) -> Response {
     }
 
     // 单次输入太长（请求体本身超出上游限制）
-    if err_str.contains("Input is too long") {
-        tracing::warn!(error = %err, "上游拒绝请求：输入过长（不应重试）");
+    if provider_err.is_input_too_long() {
+        tracing::warn!(error = %provider_err, "上游拒绝请求：输入过长（不应重试）");
         return (
             StatusCode::BAD_REQUEST,
             Json(ErrorResponse::new(
@@ -62,24 +98,117 @@ fn map_provider_error(err: Error) -> Response {
         )
             .into_response();
     }
-    tracing::error!("Kiro API 调用失败: {}", err);
-    (
-        StatusCode::BAD_GATEWAY,
+
+    match provider_err {
+        // 所有凭据均不可用：返回 529 overloaded_error（上游限流时为 429 rate_limit_error），
+        // 让客户端 SDK 按 Retry-After 退避
+        ProviderError::Overloaded {
+            retry_after_secs, ..
+        } => {
+            tracing::error!(
+                retry_after_secs,
+                "Kiro API 调用失败（凭据均不可用）: {}",
+                provider_err
+            );
+            if provider_err.is_throttled() {
+                rate_limited_response(provider_err, *retry_after_secs)
+            } else {
+                overloaded_response(provider_err, *retry_after_secs)
+            }
+        }
+        ProviderError::Upstream {
+            status,
+            code,
+            retry_after_secs,
+            ..
+        } => {
+            tracing::error!(
+                upstream_status = status,
+                code = code.as_deref(),
+                "Kiro API 调用失败: {}",
+                provider_err
+            );
+            let retry_after_secs = retry_after_secs.unwrap_or(UPSTREAM_RETRY_AFTER_SECS);
+            if provider_err.is_throttled() {
+                rate_limited_response(provider_err, retry_after_secs)
+            } else if *status >= 500 {
+                overloaded_response(provider_err, retry_after_secs)
+            } else {
+                (
+                    StatusCode::BAD_GATEWAY,
+                    Json(ErrorResponse::new(
+                        "api_error",
+                        format!("上游 API 调用失败: {}", provider_err),
+                    )),
+                )
+                    .into_response()
+            }
+        }
+    }
+}
+
+/// 上游错误未给出 Retry-After 时建议客户端等待的秒数
+const UPSTREAM_RETRY_AFTER_SECS: u64 = 5;
+
+/// 529 overloaded_error，附带 Retry-After
+fn overloaded_response(err: &ProviderError, retry_after_secs: u64) -> Response {
+    let mut response = (
+        StatusCode::from_u16(529).unwrap(),
         Json(ErrorResponse::new(
-            "api_error",
-            format!("上游 API 调用失败: {}", err),
+            "overloaded_error",
+            format!("上游暂时过载: {}", err),
         )),
     )
-        .into_response()
+        .into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, retry_after_secs.into());
+    response
+}
+
+/// 429 rate_limit_error，附带 Retry-After
+fn rate_limited_response(err: &ProviderError, retry_after_secs: u64) -> Response {
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(ErrorResponse::new(
+            "rate_limit_error",
+            format!("上游请求限流: {}", err),
+        )),
+    )
+        .into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, retry_after_secs.into());
+    response
+}
+
+/// GET /readyz
+///
+/// 就绪检查（无需认证）。API Key 存储降级时 `status` 为 `degraded`，仍返回 200：请求照常处理
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let store = state.api_keys.store_status();
+    let status = if store.is_degraded() {
+        "degraded"
+    } else {
+        "ready"
+    };
+    Json(json!({ "status": status, "apiKeyStore": store }))
 }
 
 /// GET /v1/models
 ///
 /// 返回可用的模型列表
-pub async fn get_models() -> impl IntoResponse {
+#[utoipa::path(
+    get,
+    path = "/v1/models",
+    tag = "anthropic",
+    responses((status = 200, description = "模型列表", body = ModelsResponse)),
+    security(("api_key" = []), ("bearer" = []))
+)]
+pub async fn get_models(State(state): State<AppState>) -> impl IntoResponse {
     tracing::info!("Received GET /v1/models request");
 
-    let models = vec![
+    let mut models = vec![
         Model {
             id: "claude-sonnet-4-5-20250929".to_string(),
             object: "model".to_string(),
@@ -172,6 +301,16 @@ pub async fn get_models() -> impl IntoResponse {
         },
     ];
 
+    // 配置了模型输出上限时，目录中的 max_tokens 与服务端实际限制保持一致
+    if let Some(provider) = &state.kiro_provider {
+        let options = ConversionOptions::from_config(&provider.token_manager().config());
+        for model in &mut models {
+            if let Some(limit) = options.max_output_tokens(&model.id) {
+                model.max_tokens = limit;
+            }
+        }
+    }
+
     Json(ModelsResponse {
         object: "list".to_string(),
         data: models,
@@ -181,9 +320,31 @@ pub async fn get_models() -> impl IntoResponse {
 /// POST /v1/messages
 ///
 /// 创建消息（对话）
+#[utoipa::path(
+    post,
+    path = "/v1/messages",
+    tag = "anthropic",
+    params(
+        RequestFlags,
+        ("Idempotency-Key" = Option<String>, Header, description = "非流式请求的幂等键，重试时返回首次请求的响应"),
+    ),
+    request_body = MessagesRequest,
+    responses(
+        (status = 200, description = "Anthropic Messages 响应（`stream: true` 时为 SSE）"),
+        (status = 400, description = "请求无效", body = ErrorResponse),
+        (status = 401, description = "认证失败", body = ErrorResponse),
+        (status = 409, description = "相同 Idempotency-Key 的请求仍在处理", body = ErrorResponse),
+        (status = 422, description = "Idempotency-Key 已用于不同的请求体", body = ErrorResponse),
+        (status = 429, description = "限流或配额超限", body = ErrorResponse),
+        (status = 529, description = "无可用凭据", body = ErrorResponse),
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
 pub async fn post_messages(
     State(state): State<AppState>,
     Extension(auth): Extension<AuthenticatedApiKey>,
+    Query(flags): Query<RequestFlags>,
+    headers: HeaderMap,
     JsonExtractor(mut payload): JsonExtractor<MessagesRequest>,
 ) -> Response {
     tracing::info!(
@@ -212,6 +373,31 @@ pub async fn post_messages(
     // 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
     override_thinking_from_model_name(&mut payload);
 
+    // 按 API Key 或模型直接路由到额外上游
+    let config = provider.token_manager().config();
+    if let Some(upstream) = state.upstreams.route(&config, &payload.model, &auth.key_id) {
+        let log_ctx = RequestLogCtx::new(
+            state.request_log.clone(),
+            &state.api_keys,
+            &auth.key_id,
+            &payload,
+            Vec::new(),
+        );
+        // 额外上游要求 max_tokens，未指定时补全为默认值
+        if payload.max_tokens <= 0 {
+            payload.max_tokens = config.default_max_tokens;
+        }
+        let body = serde_json::to_value(&payload).unwrap_or_default();
+        return upstream::forward(
+            upstream,
+            &body,
+            state.api_keys.clone(),
+            auth.key_id,
+            log_ctx,
+        )
+        .await;
+    }
+
     // 检查是否为 WebSearch 请求
     if websearch::has_web_search_tool(&payload) {
         tracing::info!("检测到 WebSearch 工具，路由到 WebSearch 处理");
@@ -227,65 +413,68 @@ pub async fn post_messages(
         return websearch::handle_websearch_request(provider, &payload, input_tokens).await;
     }
 
-    // 转换请求
-    let conversion_result = match convert_request(&payload) {
-        Ok(result) => result,
-        Err(e) => {
-            let (error_type, message) = match &e {
-                ConversionError::UnsupportedModel(model) => {
-                    ("invalid_request_error", format!("模型不支持: {}", model))
-                }
-                ConversionError::EmptyMessages => {
-                    ("invalid_request_error", "消息列表为空".to_string())
-                }
-            };
-            tracing::warn!("请求转换失败: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(error_type, message)),
-            )
-                .into_response();
-        }
+    // 携带 Idempotency-Key 的重试直接返回首次请求的响应
+    let idempotency = match state
+        .idempotency
+        .begin(&config, &headers, &auth.key_id, &payload)
+    {
+        Some(Lookup::Respond(response)) => return response,
+        Some(Lookup::Proceed(ticket)) => Some(ticket),
+        None => None,
     };
 
-    // 构建 Kiro 请求
-    let kiro_request = KiroRequest {
-        conversation_state: conversion_result.conversation_state,
-        profile_arn: state.profile_arn.clone(),
+    // 相同的非流式请求直接返回缓存的响应（调试请求不使用缓存）
+    let cache_key = if payload.stream || flags.debug.is_some() {
+        None
+    } else {
+        state.response_cache.key(&config, &auth.key_id, &payload)
     };
+    if let Some(key) = &cache_key
+        && let Some(response) = state.response_cache.get(&config, key)
+    {
+        tracing::info!("命中响应缓存");
+        return response;
+    }
 
-    let request_body = match serde_json::to_string(&kiro_request) {
-        Ok(body) => body,
-        Err(e) => {
-            tracing::error!("序列化请求失败: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse::new(
-                    "internal_error",
-                    format!("序列化请求失败: {}", e),
-                )),
-            )
-                .into_response();
-        }
+    let PreparedRequest {
+        kiro_request,
+        request_body,
+        warnings,
+        output_limit,
+    } = match prepare_kiro_request(&state, &provider, &auth.key_id, &flags, &mut payload).await {
+        Ok(prepared) => prepared,
+        Err(response) => return response,
     };
+    let log_ctx = RequestLogCtx::new(
+        state.request_log.clone(),
+        &state.api_keys,
+        &auth.key_id,
+        &payload,
+        warnings.clone(),
+    )
+    .with_kiro_request(&provider.token_manager().config(), &request_body);
+    // Kiro 凭据全部不可用时转发到兜底上游
+    let fallback = Fallback::prepare(&state.upstreams, &config, &payload, &log_ctx);
 
-    tracing::debug!("Kiro request body: {}", request_body);
-
-    let message_count = payload.messages.len();
-    let start = Instant::now();
-    let log_request_body = if state.request_log.as_ref().is_some_and(|l| l.is_enabled()) {
-        serde_json::to_string(&payload).unwrap_or_default()
-    } else {
-        String::new()
-    };
+    // cache_control 断点的缓存用量（上游无提示词缓存，按前缀是否重复出现计算）
+    let cache_usage = state
+        .prompt_cache
+        .lookup(&auth.key_id, &payload)
+        .unwrap_or_default();
 
     // 估算输入 tokens
-    let input_tokens = token::count_all_tokens(
+    let overhead = injected_prompt_tokens(
+        payload.system.as_deref(),
+        payload.thinking.as_ref(),
+        payload.output_config.as_ref(),
+        payload.tool_choice.as_ref(),
+    );
+    let input_tokens = (token::count_all_tokens(
         payload.model.clone(),
         payload.system,
         payload.messages,
         payload.tools,
-    ) as i32;
+    ) + overhead) as i32;
 
     // 检查是否启用了thinking
     let thinking_enabled = payload
@@ -294,38 +483,283 @@ pub async fn post_messages(
         .map(|t| t.is_enabled())
         .unwrap_or(false);
 
-    if payload.stream {
+    if flags.has_debug("dry-run") {
+        return dry_run_response(&payload.model, input_tokens, &kiro_request);
+    }
+    let debug_summary = flags
+        .has_debug("verbose")
+        .then(|| conversion_summary(&kiro_request, request_body.len(), input_tokens));
+    let warnings_header = warnings_header_value(&warnings);
+
+    let response = if payload.stream {
         // 流式响应
-        handle_stream_request(
+        let response = handle_stream_request(
             provider,
             state.api_keys.clone(),
             auth.key_id.clone(),
             &request_body,
             &payload.model,
             input_tokens,
+            cache_usage,
             thinking_enabled,
-            state.request_log.clone(),
-            message_count,
-            start,
-            log_request_body,
+            output_limit,
+            log_ctx,
         )
-        .await
+        .await;
+        Fallback::on_overload(fallback, response, state.api_keys.clone(), auth.key_id).await
     } else {
         // 非流式响应
-        handle_non_stream_request(
-            provider,
-            state.api_keys.clone(),
-            &auth.key_id,
-            &request_body,
-            &payload.model,
-            input_tokens,
-            state.request_log.clone(),
-            message_count,
-            start,
-            log_request_body,
+        let heartbeat_secs = provider.token_manager().config().non_stream_heartbeat_secs;
+        let api_keys = state.api_keys.clone();
+        let key_id = auth.key_id.clone();
+        let model = payload.model.clone();
+        let response_cache = state.response_cache.clone();
+        with_non_stream_heartbeat(
+            async move {
+                let response = handle_non_stream_request(
+                    provider,
+                    api_keys.clone(),
+                    &key_id,
+                    &request_body,
+                    &model,
+                    input_tokens,
+                    cache_usage,
+                    output_limit,
+                    log_ctx,
+                )
+                .await;
+                let response = Fallback::on_overload(fallback, response, api_keys, key_id).await;
+                let response = match cache_key {
+                    Some(key) => response_cache.store(&config, key, response).await,
+                    None => response,
+                };
+                match idempotency {
+                    Some(ticket) => ticket.finish(&config, response).await,
+                    None => response,
+                }
+            },
+            heartbeat_secs,
         )
         .await
+    };
+
+    with_diagnostic_headers(response, warnings_header, debug_summary)
+}
+
+/// 转换完成、待发送的 Kiro 请求
+struct PreparedRequest {
+    kiro_request: KiroRequest,
+    request_body: String,
+    warnings: Vec<String>,
+    output_limit: Option<i32>,
+}
+
+/// 补全 / 收紧 max_tokens，压缩历史并转换为 Kiro 请求（`/v1/messages` 与 `/cc/v1/messages` 共用）
+///
+/// max_tokens 在压缩之前校验，被拒绝的请求不会触发上游摘要调用
+async fn prepare_kiro_request(
+    state: &AppState,
+    provider: &KiroProvider,
+    key_id: &str,
+    flags: &RequestFlags,
+    payload: &mut MessagesRequest,
+) -> Result<PreparedRequest, Response> {
+    let conversion_options = ConversionOptions::from_config(&provider.token_manager().config())
+        .with_request_flags(flags);
+    let mut clamp_warnings = Vec::new();
+    let output_limit = clamp_max_tokens(payload, &conversion_options, &mut clamp_warnings)
+        .map_err(map_conversion_error)?;
+
+    // 上下文使用率超过阈值时压缩最早的对话历史
+    let compaction_warning = compaction::compact_history(
+        provider,
+        &state.api_keys,
+        key_id,
+        state.profile_arn.clone(),
+        payload,
+    )
+    .await;
+
+    // 转换请求
+    let conversion_result =
+        convert_request(payload, &conversion_options).map_err(map_conversion_error)?;
+
+    // 构建 Kiro 请求
+    let kiro_request = KiroRequest {
+        conversation_state: conversion_result.conversation_state,
+        profile_arn: state.profile_arn.clone(),
+    };
+
+    let request_body = serde_json::to_string(&kiro_request).map_err(|e| {
+        tracing::error!("序列化请求失败: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(
+                "internal_error",
+                format!("序列化请求失败: {}", e),
+            )),
+        )
+            .into_response()
+    })?;
+
+    tracing::debug!("Kiro request body: {}", request_body);
+
+    let mut warnings = conversion_result.warnings;
+    warnings.extend(compaction_warning);
+    warnings.extend(clamp_warnings);
+    Ok(PreparedRequest {
+        kiro_request,
+        request_body,
+        warnings,
+        output_limit,
+    })
+}
+
+/// 补全未指定的 max_tokens（`defaultMaxTokens`），并按模型输出上限（`modelMaxOutputTokens`）
+/// 收紧或拒绝超限的 max_tokens，返回需要截断输出的上限
+pub(crate) fn clamp_max_tokens(
+    payload: &mut MessagesRequest,
+    options: &ConversionOptions,
+    warnings: &mut Vec<String>,
+) -> Result<Option<i32>, ConversionError> {
+    let ceiling = options.max_output_tokens(&payload.model);
+    if payload.max_tokens <= 0 {
+        payload.max_tokens = ceiling.map_or(options.default_max_tokens, |ceiling| {
+            options.default_max_tokens.min(ceiling)
+        });
+    }
+    let Some(ceiling) = ceiling else {
+        return Ok(None);
+    };
+    if payload.max_tokens > ceiling {
+        if options.max_tokens_overflow == MaxTokensOverflowStrategy::Reject {
+            return Err(ConversionError::MaxTokensExceeded {
+                model: payload.model.clone(),
+                requested: payload.max_tokens,
+                limit: ceiling,
+            });
+        }
+        warnings.push(format!(
+            "max_tokens {} clamped to {} for model {}",
+            payload.max_tokens, ceiling, payload.model
+        ));
+        payload.max_tokens = ceiling;
+    }
+    Ok(Some(payload.max_tokens))
+}
+
+/// debug=dry-run：返回转换后的 Kiro 请求，不调用上游
+fn dry_run_response(model: &str, input_tokens: i32, kiro_request: &KiroRequest) -> Response {
+    Json(json!({
+        "type": "dry_run",
+        "model": model,
+        "input_tokens": input_tokens,
+        "kiro_request": kiro_request,
+    }))
+    .into_response()
+}
+
+/// 生成转换摘要（debug=verbose 时通过 `x-kiro-debug` 响应头返回）
+fn conversion_summary(kiro_request: &KiroRequest, body_bytes: usize, input_tokens: i32) -> String {
+    let state = &kiro_request.conversation_state;
+    let context = &state
+        .current_message
+        .user_input_message
+        .user_input_message_context;
+    format!(
+        "history={}; tools={}; tool_results={}; body_bytes={}; input_tokens={}",
+        state.history.len(),
+        context.tools.len(),
+        context.tool_results.len(),
+        body_bytes,
+        input_tokens
+    )
+}
+
+/// 将转换提示编码为 `x-kiro-warnings` 响应头的值（非 ASCII 字符会被替换）
+pub(crate) fn warnings_header_value(warnings: &[String]) -> Option<String> {
+    if warnings.is_empty() {
+        return None;
+    }
+    let joined = warnings.join("; ");
+    Some(
+        joined
+            .chars()
+            .map(|c| {
+                if c.is_ascii() && !c.is_ascii_control() {
+                    c
+                } else {
+                    '?'
+                }
+            })
+            .collect(),
+    )
+}
+
+/// 附加 `x-kiro-warnings` / `x-kiro-debug` 响应头
+pub(crate) fn with_diagnostic_headers(
+    mut response: Response,
+    warnings: Option<String>,
+    debug_summary: Option<String>,
+) -> Response {
+    let headers = [
+        ("x-kiro-warnings", warnings),
+        ("x-kiro-debug", debug_summary),
+    ];
+    for (name, value) in headers {
+        if let Some(value) = value.and_then(|v| header::HeaderValue::from_str(&v).ok()) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
+
+/// 附加 `x-kiro-input-tokens` / `x-kiro-output-tokens` 响应头（非流式请求），
+/// 客户端无需解析响应体即可计量用量
+pub(crate) fn with_usage_headers(mut response: Response, input: i32, output: i32) -> Response {
+    let headers = [
+        ("x-kiro-input-tokens", input),
+        ("x-kiro-output-tokens", output),
+    ];
+    for (name, value) in headers {
+        response
+            .headers_mut()
+            .insert(name, header::HeaderValue::from(value.max(0)));
+    }
+    response
+}
+
+/// 附加 `x-kiro-cache-creation-input-tokens` / `x-kiro-cache-read-input-tokens` 响应头
+fn with_cache_usage_headers(mut response: Response, usage: &serde_json::Value) -> Response {
+    let headers = [
+        (
+            "x-kiro-cache-creation-input-tokens",
+            "cache_creation_input_tokens",
+        ),
+        ("x-kiro-cache-read-input-tokens", "cache_read_input_tokens"),
+    ];
+    for (name, field) in headers {
+        if let Some(value) = usage[field].as_i64() {
+            response
+                .headers_mut()
+                .insert(name, header::HeaderValue::from(value));
+        }
     }
+    response
+}
+
+/// 附加 `x-kiro-context-usage` 响应头（上游 contextUsageEvent 报告的上下文使用百分比），
+/// 客户端可据此在达到 100% 前主动压缩历史
+pub(crate) fn with_context_usage_header(
+    mut response: Response,
+    percentage: Option<f64>,
+) -> Response {
+    if let Some(value) =
+        percentage.and_then(|p| header::HeaderValue::from_str(&format!("{:.2}", p)).ok())
+    {
+        response.headers_mut().insert("x-kiro-context-usage", value);
+    }
+    response
 }
 
 /// 处理流式请求
@@ -336,26 +770,38 @@ async fn handle_stream_request(
     request_body: &str,
     model: &str,
     input_tokens: i32,
+    cache_usage: CacheUsage,
     thinking_enabled: bool,
-    request_log: Option<std::sync::Arc<RequestLog>>,
-    message_count: usize,
-    start: Instant,
-    log_request_body: String,
+    output_limit: Option<i32>,
+    log_ctx: RequestLogCtx,
 ) -> Response {
     // 调用 Kiro API（支持多凭据故障转移）
     let response = match provider.call_api_stream(request_body).await {
         Ok(resp) => resp,
         Err(e) => return map_provider_error(e),
     };
+    let log_ctx = log_ctx.with_served_by(&provider, &response);
 
     // 创建流处理上下文
-    let mut ctx = StreamContext::new_with_thinking(model, input_tokens, thinking_enabled);
+    let mut ctx = StreamContext::new_with_thinking(model, input_tokens, thinking_enabled)
+        .with_output_limit(output_limit)
+        .with_cache_usage(cache_usage)
+        .with_message_id(message_id::next(&provider.token_manager().config()));
 
     // 生成初始事件（内部状态初始化，纯文本模式不发送）
     let initial_events = ctx.generate_initial_events();
 
     // 创建 SSE 流
-    let stream = create_sse_stream(response, ctx, initial_events, api_keys, key_id, request_log, model.to_string(), message_count, start, log_request_body);
+    let heartbeat = Heartbeat::new(&provider.token_manager().config());
+    let stream = create_sse_stream(
+        response,
+        ctx,
+        initial_events,
+        heartbeat,
+        api_keys,
+        key_id,
+        log_ctx,
+    );
 
     // 返回 SSE 响应
     Response::builder()
@@ -367,14 +813,6 @@ async fn handle_stream_request(
         .unwrap()
 }
 
-/// Ping 事件间隔（25秒）
-const PING_INTERVAL_SECS: u64 = 25;
-
-/// 创建 ping 事件的 SSE 字符串
-fn create_ping_sse() -> Bytes {
-    Bytes::from("event: ping\ndata: {\"type\": \"ping\"}\n\n")
-}
-
 /// 将 SSE 事件列表转换为 SSE 字节流
 fn events_to_sse_bytes(events: Vec<SseEvent>) -> Vec<Result<Bytes, Infallible>> {
     events
@@ -383,25 +821,151 @@ fn events_to_sse_bytes(events: Vec<SseEvent>) -> Vec<Result<Bytes, Infallible>>
         .collect()
 }
 
-/// 流式请求日志上下文
-struct StreamLogCtx {
+/// 请求日志上下文
+#[derive(Clone)]
+pub(crate) struct RequestLogCtx {
     request_log: Option<std::sync::Arc<RequestLog>>,
     model: String,
+    stream: bool,
     message_count: usize,
     key_id: String,
+    user_id: Option<String>,
     start: Instant,
     request_body: String,
-    response_events: Vec<serde_json::Value>,
+    kiro_request_body: String,
+    warnings: Vec<String>,
+    pub(crate) response_events: Vec<serde_json::Value>,
+    /// 处理本次请求的凭据，记录日志时同时计入该凭据的用量
+    served_by: Option<(std::sync::Arc<crate::kiro::provider::KiroProvider>, u64)>,
+    /// 访问日志字段（记录日志时同时写入 tokens）
+    access: Option<AccessFields>,
+    /// API Key ID（`key_id` 为展示用的名称）与其请求完成回调
+    webhook: Option<(String, std::sync::Arc<KeyWebhook>)>,
 }
 
-impl StreamLogCtx {
-    fn record(&self, input: i32, output: i32, token_source: &str, status: &str) {
+impl RequestLogCtx {
+    pub(crate) fn new(
+        request_log: Option<std::sync::Arc<RequestLog>>,
+        api_keys: &crate::apikeys::ApiKeyManager,
+        key_id: &str,
+        payload: &MessagesRequest,
+        warnings: Vec<String>,
+    ) -> Self {
+        let enabled = request_log.as_ref().is_some_and(|l| l.is_enabled());
+        let request_body = if enabled {
+            serde_json::to_string(payload).unwrap_or_default()
+        } else {
+            String::new()
+        };
+        let access = AccessFields::current();
+        if let Some(access) = &access {
+            access.set_model(&payload.model);
+        }
+        Self {
+            request_log,
+            model: payload.model.clone(),
+            stream: payload.stream,
+            message_count: payload.messages.len(),
+            key_id: api_keys
+                .get_name_by_id(key_id)
+                .unwrap_or_else(|| key_id.to_string()),
+            user_id: payload
+                .metadata
+                .as_ref()
+                .and_then(|m| m.user_id.clone())
+                .filter(|u| !u.is_empty()),
+            start: Instant::now(),
+            request_body,
+            kiro_request_body: String::new(),
+            warnings,
+            response_events: Vec::new(),
+            served_by: None,
+            access,
+            webhook: api_keys
+                .webhook(key_id)
+                .map(|webhook| (key_id.to_string(), webhook)),
+        }
+    }
+
+    /// 记录处理本次请求的凭据（来自上游响应）
+    pub(crate) fn with_served_by(
+        mut self,
+        provider: &std::sync::Arc<crate::kiro::provider::KiroProvider>,
+        response: &reqwest::Response,
+    ) -> Self {
+        self.served_by = ServedBy::of(response).map(|id| (provider.clone(), id));
+        self
+    }
+
+    /// 配置开启 `requestLogKiroRequest` 时，同时记录发往上游的 Kiro 请求体
+    pub(crate) fn with_kiro_request(mut self, config: &Config, kiro_request_body: &str) -> Self {
+        let enabled = self.request_log.as_ref().is_some_and(|l| l.is_enabled());
+        if enabled && config.request_log_kiro_request {
+            self.kiro_request_body = kiro_request_body.to_string();
+        }
+        self
+    }
+
+    /// 请求的 `metadata.user_id`（用于按终端用户统计用量）
+    pub(crate) fn user_id(&self) -> Option<&str> {
+        self.user_id.as_deref()
+    }
+
+    /// 客户端请求的模型名（用于按模型单价计费）
+    pub(crate) fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// 记录流式请求（响应体为收集到的 SSE 事件）
+    pub(crate) fn record(&self, input: i32, output: i32, token_source: &str, status: &str) {
+        let response_body = serde_json::to_string(&self.response_events).unwrap_or_default();
+        self.record_with_body(input, output, token_source, status, response_body);
+    }
+
+    pub(crate) fn record_with_body(
+        &self,
+        input: i32,
+        output: i32,
+        token_source: &str,
+        status: &str,
+        response_body: String,
+    ) {
+        if let Some((provider, id)) = &self.served_by {
+            provider.token_manager().record_usage(
+                *id,
+                &self.model,
+                input.max(0) as u64,
+                output.max(0) as u64,
+            );
+        }
+        if let Some(access) = &self.access {
+            access.set_tokens(input, output);
+        }
+        if let Some((api_key_id, webhook)) = &self.webhook {
+            let (status, error) = webhook::split_status(status);
+            webhook::enqueue(
+                webhook,
+                WebhookEvent {
+                    id: Uuid::new_v4().to_string(),
+                    timestamp: chrono::Utc::now().to_rfc3339(),
+                    api_key_id: api_key_id.clone(),
+                    model: self.model.clone(),
+                    stream: self.stream,
+                    status,
+                    error,
+                    input_tokens: input,
+                    output_tokens: output,
+                    duration_ms: self.start.elapsed().as_millis() as u64,
+                    user_id: self.user_id.clone(),
+                },
+            );
+        }
         if let Some(log) = &self.request_log {
             log.push(RequestLogEntry {
                 id: Uuid::new_v4().to_string(),
                 timestamp: chrono::Utc::now().to_rfc3339(),
                 model: self.model.clone(),
-                stream: true,
+                stream: self.stream,
                 message_count: self.message_count,
                 input_tokens: input,
                 output_tokens: output,
@@ -409,8 +973,11 @@ impl StreamLogCtx {
                 duration_ms: self.start.elapsed().as_millis() as u64,
                 status: status.to_string(),
                 api_key_id: self.key_id.clone(),
+                user_id: self.user_id.clone(),
                 request_body: self.request_body.clone(),
-                response_body: serde_json::to_string(&self.response_events).unwrap_or_default(),
+                kiro_request_body: self.kiro_request_body.clone(),
+                response_body,
+                warnings: self.warnings.clone(),
             });
         }
     }
@@ -421,27 +988,19 @@ fn create_sse_stream(
     response: reqwest::Response,
     ctx: StreamContext,
     initial_events: Vec<SseEvent>,
+    heartbeat: Heartbeat,
     api_keys: std::sync::Arc<crate::apikeys::ApiKeyManager>,
     key_id: String,
-    request_log: Option<std::sync::Arc<RequestLog>>,
-    model: String,
-    message_count: usize,
-    start: Instant,
-    log_request_body: String,
+    log_ctx: RequestLogCtx,
 ) -> impl Stream<Item = Result<Bytes, Infallible>> {
     // 初始事件先发送给客户端
     let initial_stream = stream::iter(events_to_sse_bytes(initial_events));
 
-    let log_api_key_name = api_keys
-        .get_name_by_id(&key_id)
-        .unwrap_or_else(|| key_id.clone());
-    let log_ctx = StreamLogCtx { request_log, model, message_count, key_id: log_api_key_name, start, request_body: log_request_body, response_events: Vec::new() };
-
-    // 然后处理 Kiro 响应流，同时每25秒发送 ping 保活
+    // 然后处理 Kiro 响应流，空闲时按 heartbeat 配置发送保活
     let body_stream = response.bytes_stream();
 
     let processing_stream = stream::unfold(
-        (body_stream, ctx, EventStreamDecoder::new(), false, interval(Duration::from_secs(PING_INTERVAL_SECS)), api_keys, key_id, false, log_ctx),
+        (body_stream, ctx, EventStreamDecoder::new(), false, heartbeat, api_keys, key_id, false, log_ctx),
         |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval, api_keys, key_id, usage_recorded, mut log_ctx)| async move {
             if finished {
                 return None;
@@ -480,6 +1039,11 @@ fn create_sse_stream(
                                 }
                             }
 
+                            // 有内容下发时重置 ping 定时器，只在空闲间隙发送 ping
+                            if !events.is_empty() {
+                                ping_interval.reset();
+                            }
+
                             // 转换为 SSE 字节流
                             let bytes = events_to_sse_bytes(events);
 
@@ -490,7 +1054,7 @@ fn create_sse_stream(
                             // 记录用量
                             if !usage_recorded {
                                 let (input, output) = ctx.final_usage();
-                                api_keys.record_usage(&key_id, input.max(0) as u64, output.max(0) as u64);
+                                api_keys.record_usage(&key_id, log_ctx.user_id(), log_ctx.model(), input.max(0) as u64, output.max(0) as u64);
                                 log_ctx.record(input, output, ctx.token_source(), &format!("error: {}", e));
                             }
                             let final_events = ctx.generate_final_events();
@@ -501,7 +1065,7 @@ fn create_sse_stream(
                             // 流结束，记录用量
                             if !usage_recorded {
                                 let (input, output) = ctx.final_usage();
-                                api_keys.record_usage(&key_id, input.max(0) as u64, output.max(0) as u64);
+                                api_keys.record_usage(&key_id, log_ctx.user_id(), log_ctx.model(), input.max(0) as u64, output.max(0) as u64);
                                 log_ctx.record(input, output, ctx.token_source(), "success");
                             }
                             let final_events = ctx.generate_final_events();
@@ -513,7 +1077,7 @@ fn create_sse_stream(
                 // 发送 ping 保活
                 _ = ping_interval.tick() => {
                     tracing::trace!("发送 ping 保活事件");
-                    let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse())];
+                    let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(ping_interval.ping())];
                     Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval, api_keys, key_id, usage_recorded, log_ctx)))
                 }
             }
@@ -535,16 +1099,16 @@ async fn handle_non_stream_request(
     request_body: &str,
     model: &str,
     input_tokens: i32,
-    request_log: Option<std::sync::Arc<RequestLog>>,
-    message_count: usize,
-    start: Instant,
-    log_request_body: String,
+    cache_usage: CacheUsage,
+    output_limit: Option<i32>,
+    log_ctx: RequestLogCtx,
 ) -> Response {
     // 调用 Kiro API（支持多凭据故障转移）
     let response = match provider.call_api(request_body).await {
         Ok(resp) => resp,
         Err(e) => return map_provider_error(e),
     };
+    let log_ctx = log_ctx.with_served_by(&provider, &response);
 
     // 读取响应体
     let body_bytes = match response.bytes().await {
@@ -574,6 +1138,8 @@ async fn handle_non_stream_request(
     let mut stop_reason = "end_turn".to_string();
     // 从 contextUsageEvent 计算的实际输入 tokens
     let mut context_input_tokens: Option<i32> = None;
+    let mut context_usage_percentage: Option<f64> = None;
+    let mut output_estimate: i32 = 0;
 
     // 收集工具调用的增量 JSON
     let mut tool_json_buffers: std::collections::HashMap<String, String> =
@@ -584,7 +1150,14 @@ async fn handle_non_stream_request(
             Ok(frame) => {
                 if let Ok(event) = Event::from_frame(frame) {
                     match event {
+                        // 达到输出上限后丢弃后续内容
+                        Event::AssistantResponse(_) | Event::ToolUse(_)
+                            if output_limit.is_some_and(|limit| output_estimate >= limit) =>
+                        {
+                            stop_reason = "max_tokens".to_string();
+                        }
                         Event::AssistantResponse(resp) => {
+                            output_estimate += token::count_tokens(&resp.content) as i32;
                             text_content.push_str(&resp.content);
                         }
                         Event::ToolUse(tool_use) => {
@@ -595,6 +1168,7 @@ async fn handle_non_stream_request(
                                 .entry(tool_use.tool_use_id.clone())
                                 .or_insert_with(String::new);
                             buffer.push_str(&tool_use.input);
+                            output_estimate += token::count_tokens(&tool_use.input) as i32;
 
                             // 如果是完整的工具调用，添加到列表
                             if tool_use.stop {
@@ -627,6 +1201,7 @@ async fn handle_non_stream_request(
                                 / 100.0)
                                 as i32;
                             context_input_tokens = Some(actual_input_tokens);
+                            context_usage_percentage = Some(context_usage.context_usage_percentage);
                             // 上下文使用量达到 100% 时，设置 stop_reason 为 model_context_window_exceeded
                             if context_usage.context_usage_percentage >= 100.0 {
                                 stop_reason = "model_context_window_exceeded".to_string();
@@ -679,16 +1254,20 @@ async fn handle_non_stream_request(
     };
     tracing::info!(
         "token 统计 [非流式] [{}]: input={}, output={}",
-        token_source, final_input_tokens, output_tokens
+        token_source,
+        final_input_tokens,
+        output_tokens
     );
     api_keys.record_usage(
         auth_key_id,
+        log_ctx.user_id(),
+        log_ctx.model(),
         final_input_tokens.max(0) as u64,
         output_tokens.max(0) as u64,
     );
     // 构建响应体用于日志记录
-    let response_body = json!({
-        "id": format!("msg_{}", Uuid::new_v4().to_string().replace('-', "")),
+    let mut response_body = json!({
+        "id": message_id::next(&provider.token_manager().config()),
         "type": "message",
         "role": "assistant",
         "content": content,
@@ -700,34 +1279,138 @@ async fn handle_non_stream_request(
             "output_tokens": output_tokens
         }
     });
-
-    let auth_key_name = api_keys
-        .get_name_by_id(auth_key_id)
-        .unwrap_or_else(|| auth_key_id.to_string());
-
-    if let Some(log) = &request_log {
-        log.push(RequestLogEntry {
-            id: Uuid::new_v4().to_string(),
-            timestamp: chrono::Utc::now().to_rfc3339(),
-            model: model.to_string(),
-            stream: false,
-            message_count,
-            input_tokens: final_input_tokens,
-            output_tokens,
-            token_source: token_source.to_string(),
-            duration_ms: start.elapsed().as_millis() as u64,
-            status: "success".to_string(),
-            api_key_id: auth_key_name,
-            request_body: log_request_body.clone(),
-            response_body: serde_json::to_string(&response_body).unwrap_or_default(),
-        });
-    }
+    cache_usage.apply(&mut response_body["usage"]);
+
+    log_ctx.record_with_body(
+        final_input_tokens,
+        output_tokens,
+        token_source,
+        "success",
+        serde_json::to_string(&response_body).unwrap_or_default(),
+    );
 
     // 返回纯文本响应
-    Response::builder()
+    let response = Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
         .body(Body::from(text_content))
+        .unwrap();
+    let response = with_usage_headers(response, final_input_tokens, output_tokens);
+    let mut response = with_cache_usage_headers(response, &response_body["usage"]);
+    response
+        .extensions_mut()
+        .insert(MessageJson(std::sync::Arc::new(response_body)));
+    with_context_usage_header(response, context_usage_percentage)
+}
+
+/// 非流式响应对应的完整 Anthropic 消息（响应体为纯文本时，心跳模式改为返回该 JSON）
+#[derive(Clone)]
+struct MessageJson(std::sync::Arc<serde_json::Value>);
+
+/// 心跳模式下以 trailers 发送的用量响应头（心跳开始后响应头已发出）
+const HEARTBEAT_TRAILERS: &str = "x-kiro-input-tokens, x-kiro-output-tokens, x-kiro-cache-creation-input-tokens, x-kiro-cache-read-input-tokens, x-kiro-context-usage";
+
+/// 心跳模式的响应体：JSON 响应原样返回，纯文本成功响应换成完整消息 JSON，
+/// 其他非 JSON 响应包装为 Anthropic 错误格式，保证响应体是合法 JSON
+async fn heartbeat_json_body(parts: &http::response::Parts, body: Body) -> Bytes {
+    let is_json = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !is_json && let Some(message) = parts.extensions.get::<MessageJson>() {
+        return serde_json::to_vec(message.0.as_ref())
+            .unwrap_or_default()
+            .into();
+    }
+    let body = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+    if is_json {
+        return body;
+    }
+    let error = json!({
+        "type": "error",
+        "error": {
+            "type": if parts.status.is_server_error() { "api_error" } else { "invalid_request_error" },
+            "message": String::from_utf8_lossy(&body),
+        }
+    });
+    serde_json::to_vec(&error).unwrap_or_default().into()
+}
+
+/// 非流式请求心跳
+///
+/// 开启后非流式响应统一为 `application/json` 的完整消息 JSON（见 [`heartbeat_json_body`]），
+/// 与是否触发心跳无关。请求在 `heartbeat_secs` 内完成时保留原状态码与响应头；否则立即以
+/// chunked 方式返回 200，每隔 `heartbeat_secs` 写入一个空格，完成后写入同样的 JSON，
+/// 避免中间代理因空闲超时（常见 60 秒）断开长时间生成的请求。
+/// 切换后状态码与响应头已固定，错误信息只能体现在响应体中，`x-kiro-*` 用量响应头改为
+/// HTTP trailers 发送（`x-kiro-warnings` 在请求开始前已确定，由调用方附加到心跳响应上）。
+async fn with_non_stream_heartbeat<F>(fut: F, heartbeat_secs: u64) -> Response
+where
+    F: std::future::Future<Output = Response> + Send + 'static,
+{
+    if heartbeat_secs == 0 {
+        return fut.await;
+    }
+
+    let period = Duration::from_secs(heartbeat_secs);
+    let mut fut = Box::pin(fut);
+    let fut = match tokio::time::timeout(period, &mut fut).await {
+        Ok(response) => {
+            let (mut parts, body) = response.into_parts();
+            let body = heartbeat_json_body(&parts, body).await;
+            parts.headers.remove(header::CONTENT_LENGTH);
+            parts.headers.insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("application/json"),
+            );
+            return Response::from_parts(parts, Body::from(body));
+        }
+        Err(_) => fut,
+    };
+
+    tracing::info!("非流式请求超过 {}s 未完成，切换为心跳响应", heartbeat_secs);
+    let mut heartbeat = interval(period);
+    heartbeat.reset();
+
+    let body_stream = stream::unfold(Some((fut, heartbeat)), |state| async move {
+        let (mut fut, mut heartbeat) = state?;
+        tokio::select! {
+            response = &mut fut => {
+                if !response.status().is_success() {
+                    tracing::warn!("心跳响应中的请求失败: {}", response.status());
+                }
+                let (parts, body) = response.into_parts();
+                let body = heartbeat_json_body(&parts, body).await;
+                let mut trailers = HeaderMap::new();
+                for (name, value) in &parts.headers {
+                    if name.as_str().starts_with("x-kiro-") && name.as_str() != "x-kiro-warnings" {
+                        trailers.insert(name.clone(), value.clone());
+                    }
+                }
+                let frames = vec![
+                    Ok::<_, Infallible>(Frame::data(body)),
+                    Ok(Frame::trailers(trailers)),
+                ];
+                Some((stream::iter(frames), None))
+            }
+            _ = heartbeat.tick() => {
+                tracing::trace!("发送非流式心跳");
+                let frames = vec![Ok(Frame::data(Bytes::from_static(b" ")))];
+                Some((stream::iter(frames), Some((fut, heartbeat))))
+            }
+        }
+    })
+    .flatten();
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header(header::TRAILER, HEARTBEAT_TRAILERS)
+        .body(Body::new(StreamBody::new(body_stream)))
         .unwrap()
 }
 
@@ -736,7 +1419,7 @@ async fn handle_non_stream_request(
 /// - Opus 4.6：覆写为 adaptive 类型
 /// - 其他模型：覆写为 enabled 类型
 /// - budget_tokens 固定为 20000
-fn override_thinking_from_model_name(payload: &mut MessagesRequest) {
+pub(crate) fn override_thinking_from_model_name(payload: &mut MessagesRequest) {
     let model_lower = payload.model.to_lowercase();
     if !model_lower.contains("thinking") {
         return;
@@ -765,9 +1448,35 @@ fn override_thinking_from_model_name(payload: &mut MessagesRequest) {
     }
 }
 
+/// 转换时注入到上游提示词中的 token 数
+fn injected_prompt_tokens(
+    system: Option<&[SystemMessage]>,
+    thinking: Option<&Thinking>,
+    output_config: Option<&OutputConfig>,
+    tool_choice: Option<&serde_json::Value>,
+) -> u64 {
+    let text = injected_prompt_text(system, thinking, output_config, tool_choice);
+    if text.is_empty() {
+        0
+    } else {
+        token::count_tokens(&text)
+    }
+}
+
 /// POST /v1/messages/count_tokens
 ///
 /// 计算消息的 token 数量
+#[utoipa::path(
+    post,
+    path = "/v1/messages/count_tokens",
+    tag = "anthropic",
+    request_body = CountTokensRequest,
+    responses(
+        (status = 200, description = "估算的输入 token 数", body = CountTokensResponse),
+        (status = 401, description = "认证失败", body = ErrorResponse),
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
 pub async fn count_tokens(
     JsonExtractor(payload): JsonExtractor<CountTokensRequest>,
 ) -> impl IntoResponse {
@@ -777,26 +1486,123 @@ pub async fn count_tokens(
         "Received POST /v1/messages/count_tokens request"
     );
 
-    let total_tokens = token::count_all_tokens(
+    // 与 messages 请求一致，计入转换时注入的 thinking 标签等提示词
+    let overhead = injected_prompt_tokens(
+        payload.system.as_deref(),
+        payload.thinking.as_ref(),
+        payload.output_config.as_ref(),
+        payload.tool_choice.as_ref(),
+    );
+    let total_tokens = (token::count_all_tokens(
         payload.model,
         payload.system,
         payload.messages,
         payload.tools,
-    ) as i32;
+    ) + overhead) as i32;
 
     Json(CountTokensResponse {
         input_tokens: total_tokens.max(1) as i32,
     })
 }
 
+/// POST /v1/browser-tokens
+///
+/// 用长期 API Key 换取短期浏览器令牌，令牌仅可访问 messages 端点
+#[utoipa::path(
+    post,
+    path = "/v1/browser-tokens",
+    tag = "anthropic",
+    request_body = BrowserTokenRequest,
+    responses(
+        (status = 200, description = "签发的临时令牌", body = BrowserTokenResponse),
+        (status = 401, description = "认证失败", body = ErrorResponse),
+        (status = 404, description = "未启用浏览器令牌", body = ErrorResponse),
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+pub async fn post_browser_token(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthenticatedApiKey>,
+    JsonExtractor(payload): JsonExtractor<BrowserTokenRequest>,
+) -> Response {
+    let max_ttl = state
+        .kiro_provider
+        .as_ref()
+        .and_then(|p| p.token_manager().config().browser_token_max_ttl_secs)
+        .filter(|ttl| *ttl > 0);
+    let Some(max_ttl) = max_ttl else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(
+                "not_found_error",
+                "Browser tokens are not enabled",
+            )),
+        )
+            .into_response();
+    };
+
+    let ttl_secs = payload.ttl_secs.unwrap_or(max_ttl).min(max_ttl);
+    if ttl_secs == 0 {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new(
+                "invalid_request_error",
+                "ttl_secs must be greater than 0",
+            )),
+        )
+            .into_response();
+    }
+
+    let origin = payload
+        .origin
+        .map(|o| o.trim().trim_end_matches('/').to_string())
+        .filter(|o| !o.is_empty());
+    let token = state.api_keys.issue_browser_token(
+        &auth.key_id,
+        Duration::from_secs(ttl_secs),
+        origin.clone(),
+    );
+    tracing::info!(key_id = %auth.key_id, ttl_secs, origin = ?origin, "签发浏览器临时令牌");
+
+    Json(BrowserTokenResponse {
+        token,
+        expires_in: ttl_secs,
+        expires_at: (chrono::Utc::now() + chrono::Duration::seconds(ttl_secs as i64)).to_rfc3339(),
+        origin,
+    })
+    .into_response()
+}
+
 /// POST /cc/v1/messages
 ///
 /// Claude Code 兼容端点，与 /v1/messages 的区别在于：
 /// - 流式响应会等待 kiro 端返回 contextUsageEvent 后再发送 message_start
 /// - message_start 中的 input_tokens 是从 contextUsageEvent 计算的准确值
+#[utoipa::path(
+    post,
+    path = "/cc/v1/messages",
+    tag = "claude-code",
+    params(
+        RequestFlags,
+        ("Idempotency-Key" = Option<String>, Header, description = "非流式请求的幂等键，重试时返回首次请求的响应"),
+    ),
+    request_body = MessagesRequest,
+    responses(
+        (status = 200, description = "Anthropic Messages 响应（缓冲模式，`stream: true` 时为 SSE）"),
+        (status = 400, description = "请求无效", body = ErrorResponse),
+        (status = 401, description = "认证失败", body = ErrorResponse),
+        (status = 409, description = "相同 Idempotency-Key 的请求仍在处理", body = ErrorResponse),
+        (status = 422, description = "Idempotency-Key 已用于不同的请求体", body = ErrorResponse),
+        (status = 429, description = "限流或配额超限", body = ErrorResponse),
+        (status = 529, description = "无可用凭据", body = ErrorResponse),
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
 pub async fn post_messages_cc(
     State(state): State<AppState>,
     Extension(auth): Extension<AuthenticatedApiKey>,
+    Query(flags): Query<RequestFlags>,
+    headers: HeaderMap,
     JsonExtractor(mut payload): JsonExtractor<MessagesRequest>,
 ) -> Response {
     tracing::info!(
@@ -826,6 +1632,31 @@ pub async fn post_messages_cc(
     // 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
     override_thinking_from_model_name(&mut payload);
 
+    // 按 API Key 或模型直接路由到额外上游
+    let config = provider.token_manager().config();
+    if let Some(upstream) = state.upstreams.route(&config, &payload.model, &auth.key_id) {
+        let log_ctx = RequestLogCtx::new(
+            state.request_log.clone(),
+            &state.api_keys,
+            &auth.key_id,
+            &payload,
+            Vec::new(),
+        );
+        // 额外上游要求 max_tokens，未指定时补全为默认值
+        if payload.max_tokens <= 0 {
+            payload.max_tokens = config.default_max_tokens;
+        }
+        let body = serde_json::to_value(&payload).unwrap_or_default();
+        return upstream::forward(
+            upstream,
+            &body,
+            state.api_keys.clone(),
+            auth.key_id,
+            log_ctx,
+        )
+        .await;
+    }
+
     // 检查是否为 WebSearch 请求
     if websearch::has_web_search_tool(&payload) {
         tracing::info!("检测到 WebSearch 工具，路由到 WebSearch 处理");
@@ -841,65 +1672,68 @@ pub async fn post_messages_cc(
         return websearch::handle_websearch_request(provider, &payload, input_tokens).await;
     }
 
-    // 转换请求
-    let conversion_result = match convert_request(&payload) {
-        Ok(result) => result,
-        Err(e) => {
-            let (error_type, message) = match &e {
-                ConversionError::UnsupportedModel(model) => {
-                    ("invalid_request_error", format!("模型不支持: {}", model))
-                }
-                ConversionError::EmptyMessages => {
-                    ("invalid_request_error", "消息列表为空".to_string())
-                }
-            };
-            tracing::warn!("请求转换失败: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(error_type, message)),
-            )
-                .into_response();
-        }
+    // 携带 Idempotency-Key 的重试直接返回首次请求的响应
+    let idempotency = match state
+        .idempotency
+        .begin(&config, &headers, &auth.key_id, &payload)
+    {
+        Some(Lookup::Respond(response)) => return response,
+        Some(Lookup::Proceed(ticket)) => Some(ticket),
+        None => None,
     };
 
-    // 构建 Kiro 请求
-    let kiro_request = KiroRequest {
-        conversation_state: conversion_result.conversation_state,
-        profile_arn: state.profile_arn.clone(),
+    // 相同的非流式请求直接返回缓存的响应（调试请求不使用缓存）
+    let cache_key = if payload.stream || flags.debug.is_some() {
+        None
+    } else {
+        state.response_cache.key(&config, &auth.key_id, &payload)
     };
+    if let Some(key) = &cache_key
+        && let Some(response) = state.response_cache.get(&config, key)
+    {
+        tracing::info!("命中响应缓存");
+        return response;
+    }
 
-    let request_body = match serde_json::to_string(&kiro_request) {
-        Ok(body) => body,
-        Err(e) => {
-            tracing::error!("序列化请求失败: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse::new(
-                    "internal_error",
-                    format!("序列化请求失败: {}", e),
-                )),
-            )
-                .into_response();
-        }
+    let PreparedRequest {
+        kiro_request,
+        request_body,
+        warnings,
+        output_limit,
+    } = match prepare_kiro_request(&state, &provider, &auth.key_id, &flags, &mut payload).await {
+        Ok(prepared) => prepared,
+        Err(response) => return response,
     };
+    let log_ctx = RequestLogCtx::new(
+        state.request_log.clone(),
+        &state.api_keys,
+        &auth.key_id,
+        &payload,
+        warnings.clone(),
+    )
+    .with_kiro_request(&provider.token_manager().config(), &request_body);
+    // Kiro 凭据全部不可用时转发到兜底上游
+    let fallback = Fallback::prepare(&state.upstreams, &config, &payload, &log_ctx);
 
-    tracing::debug!("Kiro request body: {}", request_body);
-
-    let message_count = payload.messages.len();
-    let start = Instant::now();
-    let log_request_body = if state.request_log.as_ref().is_some_and(|l| l.is_enabled()) {
-        serde_json::to_string(&payload).unwrap_or_default()
-    } else {
-        String::new()
-    };
+    // cache_control 断点的缓存用量（上游无提示词缓存，按前缀是否重复出现计算）
+    let cache_usage = state
+        .prompt_cache
+        .lookup(&auth.key_id, &payload)
+        .unwrap_or_default();
 
     // 估算输入 tokens
-    let input_tokens = token::count_all_tokens(
+    let overhead = injected_prompt_tokens(
+        payload.system.as_deref(),
+        payload.thinking.as_ref(),
+        payload.output_config.as_ref(),
+        payload.tool_choice.as_ref(),
+    );
+    let input_tokens = (token::count_all_tokens(
         payload.model.clone(),
         payload.system,
         payload.messages,
         payload.tools,
-    ) as i32;
+    ) + overhead) as i32;
 
     // 检查是否启用了thinking
     let thinking_enabled = payload
@@ -908,38 +1742,67 @@ pub async fn post_messages_cc(
         .map(|t| t.is_enabled())
         .unwrap_or(false);
 
-    if payload.stream {
+    if flags.has_debug("dry-run") {
+        return dry_run_response(&payload.model, input_tokens, &kiro_request);
+    }
+    let debug_summary = flags
+        .has_debug("verbose")
+        .then(|| conversion_summary(&kiro_request, request_body.len(), input_tokens));
+    let warnings_header = warnings_header_value(&warnings);
+
+    let response = if payload.stream {
         // 流式响应（缓冲模式）
-        handle_stream_request_buffered(
+        let response = handle_stream_request_buffered(
             provider,
             state.api_keys.clone(),
             auth.key_id.clone(),
             &request_body,
             &payload.model,
             input_tokens,
+            cache_usage,
             thinking_enabled,
-            state.request_log.clone(),
-            message_count,
-            start,
-            log_request_body,
+            output_limit,
+            log_ctx,
         )
-        .await
+        .await;
+        Fallback::on_overload(fallback, response, state.api_keys.clone(), auth.key_id).await
     } else {
         // 非流式响应（复用现有逻辑，已经使用正确的 input_tokens）
-        handle_non_stream_request(
-            provider,
-            state.api_keys.clone(),
-            &auth.key_id,
-            &request_body,
-            &payload.model,
-            input_tokens,
-            state.request_log.clone(),
-            message_count,
-            start,
-            log_request_body,
+        let heartbeat_secs = provider.token_manager().config().non_stream_heartbeat_secs;
+        let api_keys = state.api_keys.clone();
+        let key_id = auth.key_id.clone();
+        let model = payload.model.clone();
+        let response_cache = state.response_cache.clone();
+        with_non_stream_heartbeat(
+            async move {
+                let response = handle_non_stream_request(
+                    provider,
+                    api_keys.clone(),
+                    &key_id,
+                    &request_body,
+                    &model,
+                    input_tokens,
+                    cache_usage,
+                    output_limit,
+                    log_ctx,
+                )
+                .await;
+                let response = Fallback::on_overload(fallback, response, api_keys, key_id).await;
+                let response = match cache_key {
+                    Some(key) => response_cache.store(&config, key, response).await,
+                    None => response,
+                };
+                match idempotency {
+                    Some(ticket) => ticket.finish(&config, response).await,
+                    None => response,
+                }
+            },
+            heartbeat_secs,
         )
         .await
-    }
+    };
+
+    with_diagnostic_headers(response, warnings_header, debug_summary)
 }
 
 /// 处理流式请求（缓冲版本）
@@ -953,23 +1816,27 @@ async fn handle_stream_request_buffered(
     request_body: &str,
     model: &str,
     estimated_input_tokens: i32,
+    cache_usage: CacheUsage,
     thinking_enabled: bool,
-    request_log: Option<std::sync::Arc<RequestLog>>,
-    message_count: usize,
-    start: Instant,
-    log_request_body: String,
+    output_limit: Option<i32>,
+    log_ctx: RequestLogCtx,
 ) -> Response {
     // 调用 Kiro API（支持多凭据故障转移）
     let response = match provider.call_api_stream(request_body).await {
         Ok(resp) => resp,
         Err(e) => return map_provider_error(e),
     };
+    let log_ctx = log_ctx.with_served_by(&provider, &response);
 
     // 创建缓冲流处理上下文
-    let ctx = BufferedStreamContext::new(model, estimated_input_tokens, thinking_enabled);
+    let ctx = BufferedStreamContext::new(model, estimated_input_tokens, thinking_enabled)
+        .with_output_limit(output_limit)
+        .with_cache_usage(cache_usage)
+        .with_message_id(message_id::next(&provider.token_manager().config()));
 
     // 创建缓冲 SSE 流
-    let stream = create_buffered_sse_stream(response, ctx, api_keys, key_id, request_log, model.to_string(), message_count, start, log_request_body);
+    let heartbeat = Heartbeat::new(&provider.token_manager().config());
+    let stream = create_buffered_sse_stream(response, ctx, heartbeat, api_keys, key_id, log_ctx);
 
     // 返回 SSE 响应
     Response::builder()
@@ -991,19 +1858,12 @@ async fn handle_stream_request_buffered(
 fn create_buffered_sse_stream(
     response: reqwest::Response,
     ctx: BufferedStreamContext,
+    heartbeat: Heartbeat,
     api_keys: std::sync::Arc<crate::apikeys::ApiKeyManager>,
     key_id: String,
-    request_log: Option<std::sync::Arc<RequestLog>>,
-    model: String,
-    message_count: usize,
-    start: Instant,
-    log_request_body: String,
+    log_ctx: RequestLogCtx,
 ) -> impl Stream<Item = Result<Bytes, Infallible>> {
     let body_stream = response.bytes_stream();
-    let log_api_key_name = api_keys
-        .get_name_by_id(&key_id)
-        .unwrap_or_else(|| key_id.clone());
-    let log_ctx = StreamLogCtx { request_log, model, message_count, key_id: log_api_key_name, start, request_body: log_request_body, response_events: Vec::new() };
 
     stream::unfold(
         (
@@ -1011,7 +1871,7 @@ fn create_buffered_sse_stream(
             ctx,
             EventStreamDecoder::new(),
             false,
-            interval(Duration::from_secs(PING_INTERVAL_SECS)),
+            heartbeat,
             api_keys,
             key_id,
             log_ctx,
@@ -1030,7 +1890,7 @@ fn create_buffered_sse_stream(
                     // 优先检查 ping 保活（等待期间发送空格保活）
                     _ = ping_interval.tick() => {
                         tracing::trace!("发送 ping 保活事件（缓冲模式）");
-                        let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse())];
+                        let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(ping_interval.ping())];
                         return Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval, api_keys, key_id, log_ctx)));
                     }
 
@@ -1061,7 +1921,7 @@ fn create_buffered_sse_stream(
                             Some(Err(e)) => {
                                 tracing::error!("读取响应流失败: {}", e);
                                 let (input, output) = ctx.final_usage();
-                                api_keys.record_usage(&key_id, input.max(0) as u64, output.max(0) as u64);
+                                api_keys.record_usage(&key_id, log_ctx.user_id(), log_ctx.model(), input.max(0) as u64, output.max(0) as u64);
                                 let all_events = ctx.finish_and_get_all_events();
                                 for se in &all_events {
                                     log_ctx.response_events.push(json!({
@@ -1076,7 +1936,7 @@ fn create_buffered_sse_stream(
                             None => {
                                 // 流结束，记录用量
                                 let (input, output) = ctx.final_usage();
-                                api_keys.record_usage(&key_id, input.max(0) as u64, output.max(0) as u64);
+                                api_keys.record_usage(&key_id, log_ctx.user_id(), log_ctx.model(), input.max(0) as u64, output.max(0) as u64);
                                 let all_events = ctx.finish_and_get_all_events();
                                 for se in &all_events {
                                     log_ctx.response_events.push(json!({
@@ -1096,3 +1956,154 @@ fn create_buffered_sse_stream(
     )
     .flatten()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 返回 (Content-Type, 响应体 JSON, 用量响应头或 trailer 中的 x-kiro-input-tokens)
+    async fn heartbeat_response(
+        inner: Response,
+        delay: Duration,
+    ) -> (Option<String>, serde_json::Value, Option<String>) {
+        use http_body_util::BodyExt;
+
+        let response = with_non_stream_heartbeat(
+            async move {
+                tokio::time::sleep(delay).await;
+                inner
+            },
+            1,
+        )
+        .await;
+        let header_value = |headers: &HeaderMap, name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let content_type = header_value(response.headers(), "content-type");
+        let usage_header = header_value(response.headers(), "x-kiro-input-tokens");
+        let collected = response.into_body().collect().await.unwrap();
+        let usage = usage_header.or_else(|| {
+            collected
+                .trailers()
+                .and_then(|t| header_value(t, "x-kiro-input-tokens"))
+        });
+        let body = collected.to_bytes();
+        (content_type, serde_json::from_slice(&body).unwrap(), usage)
+    }
+
+    #[tokio::test]
+    async fn test_non_stream_heartbeat_same_shape_on_both_paths() {
+        let message = json!({
+            "type": "message",
+            "content": [{"type": "text", "text": "hello"}],
+            "usage": {"input_tokens": 3, "output_tokens": 1}
+        });
+        let inner = || {
+            let response = Response::builder()
+                .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+                .body(Body::from("hello"))
+                .unwrap();
+            let mut response = with_usage_headers(response, 3, 1);
+            response
+                .extensions_mut()
+                .insert(MessageJson(std::sync::Arc::new(message.clone())));
+            response
+        };
+
+        // 心跳间隔内完成与触发心跳：响应类型、响应体一致，用量分别以响应头 / trailer 返回
+        for delay in [Duration::ZERO, Duration::from_millis(1500)] {
+            let (content_type, body, usage) = heartbeat_response(inner(), delay).await;
+            assert_eq!(content_type.as_deref(), Some("application/json"));
+            assert_eq!(body, message);
+            assert_eq!(usage.as_deref(), Some("3"));
+        }
+
+        for delay in [Duration::ZERO, Duration::from_millis(1500)] {
+            let inner =
+                (StatusCode::BAD_GATEWAY, "failed to read upstream response").into_response();
+            let (content_type, body, _) = heartbeat_response(inner, delay).await;
+            assert_eq!(content_type.as_deref(), Some("application/json"));
+            assert_eq!(body["type"], "error");
+            assert_eq!(body["error"]["message"], "failed to read upstream response");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejected_max_tokens_skips_compaction() {
+        use crate::apikeys::ApiKeyManager;
+        use crate::kiro::model::credentials::KiroCredentials;
+        use crate::kiro::token_manager::MultiTokenManager;
+        use crate::model::config::CompactionConfig;
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // 模拟上游只记录调用次数（摘要请求失败时按原请求继续）
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let upstream = axum::Router::new().route(
+            "/generateAssistantResponse",
+            axum::routing::post(move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+                async { StatusCode::INTERNAL_SERVER_ERROR }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, upstream).await.unwrap() });
+
+        let mut config = Config::default();
+        config.compaction = Some(CompactionConfig {
+            enabled: true,
+            threshold_percent: 0.001,
+            model: "claude-haiku-4.5".to_string(),
+            keep_recent_messages: 1,
+        });
+        config.model_max_output_tokens = [("claude-sonnet-4.5".to_string(), 100)].into();
+        config.max_tokens_overflow_strategy = "reject".to_string();
+        let credentials = KiroCredentials {
+            access_token: Some("access-token".to_string()),
+            refresh_token: Some("refresh-token".to_string()),
+            expires_at: Some((chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339()),
+            auth_method: Some("social".to_string()),
+            ..Default::default()
+        };
+        let token_manager =
+            MultiTokenManager::new(config, vec![credentials], None, None, false).unwrap();
+        let provider = KiroProvider::new(Arc::new(token_manager))
+            .with_endpoint(format!("http://{}/generateAssistantResponse", addr));
+        let state = AppState::new(Arc::new(ApiKeyManager::new("sk-test".to_string(), None)));
+        let request = |max_tokens: i32| -> MessagesRequest {
+            serde_json::from_value(json!({
+                "model": "claude-sonnet-4.5",
+                "max_tokens": max_tokens,
+                "messages": [
+                    {"role": "user", "content": "first question"},
+                    {"role": "assistant", "content": "first answer"},
+                    {"role": "user", "content": "second question"}
+                ]
+            }))
+            .unwrap()
+        };
+
+        let flags = RequestFlags::default();
+        let mut payload = request(1000);
+        let err = prepare_kiro_request(&state, &provider, "key", &flags, &mut payload)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+
+        // 对照：max_tokens 合法时会调用上游生成摘要
+        let mut payload = request(50);
+        assert!(
+            prepare_kiro_request(&state, &provider, "key", &flags, &mut payload)
+                .await
+                .is_ok()
+        );
+        assert!(calls.load(Ordering::Relaxed) > 0);
+    }
+}
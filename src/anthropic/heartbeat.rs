@@ -0,0 +1,98 @@
+//! 流式响应保活
+//!
+//! 上游空闲超过 `heartbeat.intervalSecs`（默认 25 秒）时向客户端写入保活数据，有内容下发时重新计时。
+//! Anthropic 端点默认发送 `event: ping` 事件，`style` 为 `comment` 时改为 SSE 注释行 `: keep-alive`，
+//! 兼容把未知事件当作错误的严格客户端；OpenAI / Gemini 端点始终使用各自的注释行。`enabled` 为
+//! `false` 或间隔为 0 时不发送保活。
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::time::{Interval, interval};
+
+use crate::model::config::{Config, HeartbeatStyle};
+
+/// 未配置时的保活间隔（秒）
+const DEFAULT_INTERVAL_SECS: u64 = 25;
+
+const PING_EVENT: &str = "event: ping\ndata: {\"type\": \"ping\"}\n\n";
+const PING_COMMENT: &str = ": keep-alive\n\n";
+
+/// 单个流式响应的保活定时器
+pub(crate) struct Heartbeat {
+    /// 关闭保活时为 None
+    interval: Option<Interval>,
+    style: HeartbeatStyle,
+}
+
+impl Heartbeat {
+    /// 按当前配置创建保活定时器
+    pub(crate) fn new(config: &Config) -> Self {
+        let (enabled, interval_secs, style) = match &config.heartbeat {
+            Some(c) => (c.enabled, c.interval_secs, c.style),
+            None => (true, DEFAULT_INTERVAL_SECS, HeartbeatStyle::Event),
+        };
+        Self {
+            interval: (enabled && interval_secs > 0)
+                .then(|| interval(Duration::from_secs(interval_secs))),
+            style,
+        }
+    }
+
+    /// 有内容下发时重新计时
+    pub(crate) fn reset(&mut self) {
+        if let Some(interval) = &mut self.interval {
+            interval.reset();
+        }
+    }
+
+    /// 等待下一次保活，关闭保活时永不完成
+    pub(crate) async fn tick(&mut self) {
+        match &mut self.interval {
+            Some(interval) => {
+                interval.tick().await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Anthropic 端点的保活数据
+    pub(crate) fn ping(&self) -> Bytes {
+        match self.style {
+            HeartbeatStyle::Event => Bytes::from_static(PING_EVENT.as_bytes()),
+            HeartbeatStyle::Comment => Bytes::from_static(PING_COMMENT.as_bytes()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::config::HeartbeatConfig;
+
+    #[tokio::test]
+    async fn test_heartbeat_style_and_disable() {
+        let mut config = Config::default();
+        let heartbeat = Heartbeat::new(&config);
+        assert!(heartbeat.interval.is_some());
+        assert_eq!(heartbeat.ping(), PING_EVENT);
+
+        config.heartbeat = Some(HeartbeatConfig {
+            enabled: true,
+            interval_secs: 10,
+            style: HeartbeatStyle::Comment,
+        });
+        assert_eq!(Heartbeat::new(&config).ping(), ": keep-alive\n\n");
+
+        // 间隔为 0 或关闭时不发送保活
+        config.heartbeat = Some(HeartbeatConfig {
+            enabled: true,
+            interval_secs: 0,
+            style: HeartbeatStyle::Event,
+        });
+        let mut heartbeat = Heartbeat::new(&config);
+        assert!(heartbeat.interval.is_none());
+        let tick = tokio::time::timeout(Duration::from_millis(20), heartbeat.tick()).await;
+        assert!(tick.is_err());
+    }
+}
@@ -0,0 +1,337 @@
+//! `Idempotency-Key` 请求去重
+//!
+//! 非流式 `/v1/messages`、`/cc/v1/messages` 请求带 `Idempotency-Key` 请求头时，按 (API Key, Idempotency-Key)
+//! 保存成功的响应，TTL 内的重试直接返回保存的响应（带 `x-kiro-idempotent-replay: true` 响应头），
+//! 避免网络抖动导致的客户端重试重复计费。同一个键在首个请求完成前再次到达时返回 409，
+//! 请求体不同时返回 422。失败的响应不保存，重试会重新请求上游。配置热更新，关闭后记录随即清空。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use axum::Json;
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+
+use crate::model::config::{Config, IdempotencyConfig};
+
+use super::types::{ErrorResponse, MessagesRequest};
+
+/// 客户端携带幂等键的请求头
+pub const IDEMPOTENCY_HEADER: &str = "idempotency-key";
+
+/// 返回保存的响应时附加的响应头
+const REPLAY_HEADER: &str = "x-kiro-idempotent-replay";
+
+/// 幂等键长度上限
+const MAX_KEY_LEN: usize = 255;
+
+enum Entry {
+    /// 首个请求仍在处理
+    InFlight { fingerprint: String },
+    Done {
+        fingerprint: String,
+        headers: HeaderMap,
+        body: Bytes,
+        stored_at: Instant,
+        /// 写入序号，用于淘汰最早写入的条目
+        seq: u64,
+    },
+}
+
+/// 检查幂等键的结果
+pub enum Lookup {
+    /// 首次出现，请求完成后通过 [`Ticket::finish`] 保存响应
+    Proceed(Ticket),
+    /// 重试请求：保存的响应或 409 / 422 错误
+    Respond(Response),
+}
+
+/// 幂等响应存储
+pub struct IdempotencyStore {
+    entries: Mutex<HashMap<String, Entry>>,
+    next_seq: AtomicU64,
+}
+
+impl IdempotencyStore {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            next_seq: AtomicU64::new(0),
+        }
+    }
+
+    /// 当前生效的配置，关闭时返回 None 并清空记录
+    fn policy(&self, config: &Config) -> Option<IdempotencyConfig> {
+        let policy = config.idempotency.clone().unwrap_or_default();
+        if policy.enabled {
+            return Some(policy);
+        }
+        let mut entries = self.entries.lock();
+        if !entries.is_empty() {
+            entries.clear();
+        }
+        None
+    }
+
+    /// 检查请求的幂等键；流式请求、未携带请求头或未启用时返回 None
+    pub fn begin(
+        self: &Arc<Self>,
+        config: &Config,
+        headers: &HeaderMap,
+        key_id: &str,
+        payload: &MessagesRequest,
+    ) -> Option<Lookup> {
+        if payload.stream {
+            return None;
+        }
+        let idempotency_key = headers.get(IDEMPOTENCY_HEADER)?;
+        let policy = self.policy(config)?;
+        let idempotency_key = match idempotency_key.to_str() {
+            Ok(k) if !k.is_empty() && k.len() <= MAX_KEY_LEN => k,
+            _ => {
+                return Some(Lookup::Respond(error(
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "Idempotency-Key must be 1-{} visible ASCII characters",
+                        MAX_KEY_LEN
+                    ),
+                )));
+            }
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update(key_id.as_bytes());
+        hasher.update([0]);
+        hasher.update(idempotency_key.as_bytes());
+        let key = hex::encode(hasher.finalize());
+        let fingerprint = fingerprint(payload);
+
+        let ttl = Duration::from_secs(policy.ttl_secs);
+        let mut entries = self.entries.lock();
+        match entries.get(&key) {
+            Some(Entry::InFlight { fingerprint: f }) => {
+                return Some(Lookup::Respond(if *f == fingerprint {
+                    error(
+                        StatusCode::CONFLICT,
+                        "A request with this Idempotency-Key is still in progress",
+                    )
+                } else {
+                    mismatch()
+                }));
+            }
+            Some(Entry::Done {
+                fingerprint: f,
+                headers,
+                body,
+                stored_at,
+                ..
+            }) if stored_at.elapsed() < ttl => {
+                if *f != fingerprint {
+                    return Some(Lookup::Respond(mismatch()));
+                }
+                tracing::info!("Idempotency-Key 重试，返回保存的响应");
+                let mut response = (StatusCode::OK, Body::from(body.clone())).into_response();
+                *response.headers_mut() = headers.clone();
+                response
+                    .headers_mut()
+                    .insert(REPLAY_HEADER, HeaderValue::from_static("true"));
+                return Some(Lookup::Respond(response));
+            }
+            _ => {}
+        }
+        entries.insert(key.clone(), Entry::InFlight { fingerprint });
+        Some(Lookup::Proceed(Ticket {
+            store: self.clone(),
+            key: Some(key),
+        }))
+    }
+
+    fn insert(&self, policy: &IdempotencyConfig, key: String, entry: Entry, now: Instant) {
+        let ttl = Duration::from_secs(policy.ttl_secs);
+        let mut entries = self.entries.lock();
+        if policy.max_entries == 0 {
+            entries.remove(&key);
+            return;
+        }
+        entries.retain(|k, e| match e {
+            Entry::Done { stored_at, .. } => *k != key && now.duration_since(*stored_at) < ttl,
+            Entry::InFlight { .. } => true,
+        });
+        // 仍然已满时淘汰最早写入的响应，处理中的请求不淘汰
+        while entries.len() >= policy.max_entries {
+            let Some(oldest) = entries
+                .iter()
+                .filter_map(|(k, e)| match e {
+                    Entry::Done { seq, .. } => Some((k, *seq)),
+                    Entry::InFlight { .. } => None,
+                })
+                .min_by_key(|(_, seq)| *seq)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            entries.remove(&oldest);
+        }
+        entries.insert(key, entry);
+    }
+}
+
+/// 处理中的幂等请求，未调用 [`finish`](Self::finish) 就被丢弃（如客户端断开）时释放幂等键
+pub struct Ticket {
+    store: Arc<IdempotencyStore>,
+    key: Option<String>,
+}
+
+impl Ticket {
+    /// 保存成功的响应并原样返回；非 200 响应不保存，释放幂等键以便重试
+    pub async fn finish(mut self, config: &Config, response: Response) -> Response {
+        let Some(key) = self.key.take() else {
+            return response;
+        };
+        let Some(policy) = self.store.policy(config) else {
+            return response;
+        };
+        if response.status() != StatusCode::OK {
+            self.store.entries.lock().remove(&key);
+            return response;
+        }
+        let (parts, body) = response.into_parts();
+        let body = match axum::body::to_bytes(body, usize::MAX).await {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::warn!("读取响应体失败，不保存幂等响应: {}", e);
+                self.store.entries.lock().remove(&key);
+                return (StatusCode::BAD_GATEWAY, "failed to read upstream response")
+                    .into_response();
+            }
+        };
+        let fingerprint = match self.store.entries.lock().get(&key) {
+            Some(Entry::InFlight { fingerprint }) => fingerprint.clone(),
+            _ => return Response::from_parts(parts, Body::from(body)),
+        };
+        let mut headers = parts.headers.clone();
+        headers.remove(header::CONTENT_LENGTH);
+        headers.remove(header::TRANSFER_ENCODING);
+        let entry = Entry::Done {
+            fingerprint,
+            headers,
+            body: body.clone(),
+            stored_at: Instant::now(),
+            seq: self.store.next_seq.fetch_add(1, Ordering::Relaxed),
+        };
+        self.store.insert(&policy, key, entry, Instant::now());
+        Response::from_parts(parts, Body::from(body))
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let mut entries = self.store.entries.lock();
+            if matches!(entries.get(&key), Some(Entry::InFlight { .. })) {
+                entries.remove(&key);
+            }
+        }
+    }
+}
+
+/// 请求体指纹（忽略 JSON 字段顺序与 `stream` 字段）
+fn fingerprint(payload: &MessagesRequest) -> String {
+    let mut value = serde_json::to_value(payload).unwrap_or_default();
+    if let Some(map) = value.as_object_mut() {
+        map.remove("stream");
+    }
+    hex::encode(Sha256::digest(value.to_string().as_bytes()))
+}
+
+fn mismatch() -> Response {
+    error(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "Idempotency-Key was already used with a different request body",
+    )
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (
+        status,
+        Json(ErrorResponse::new("invalid_request_error", message)),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(text: &str) -> MessagesRequest {
+        serde_json::from_str(&format!(
+            r#"{{"model":"claude-sonnet-4-6","max_tokens":10,"messages":[{{"role":"user","content":"{}"}}]}}"#,
+            text
+        ))
+        .unwrap()
+    }
+
+    fn headers(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(IDEMPOTENCY_HEADER, HeaderValue::from_str(key).unwrap());
+        headers
+    }
+
+    async fn body(response: Response) -> Bytes {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_retry_replays_stored_response() {
+        let store = Arc::new(IdempotencyStore::new());
+        let config = Config::default();
+        let hi = request("hi");
+        assert!(store.begin(&config, &HeaderMap::new(), "k1", &hi).is_none());
+
+        let Some(Lookup::Proceed(ticket)) = store.begin(&config, &headers("a"), "k1", &hi) else {
+            panic!("首次请求应继续处理");
+        };
+        // 首个请求完成前重试返回 409，请求体不同返回 422
+        let Some(Lookup::Respond(busy)) = store.begin(&config, &headers("a"), "k1", &hi) else {
+            panic!("处理中的请求应被拒绝");
+        };
+        assert_eq!(busy.status(), StatusCode::CONFLICT);
+        let Some(Lookup::Respond(other)) =
+            store.begin(&config, &headers("a"), "k1", &request("bye"))
+        else {
+            panic!("不同请求体应被拒绝");
+        };
+        assert_eq!(other.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let response = ticket.finish(&config, "done".into_response()).await;
+        assert_eq!(body(response).await, "done");
+        let Some(Lookup::Respond(replay)) = store.begin(&config, &headers("a"), "k1", &hi) else {
+            panic!("重试应返回保存的响应");
+        };
+        assert_eq!(replay.headers()[REPLAY_HEADER], "true");
+        assert_eq!(body(replay).await, "done");
+
+        // 不同 API Key 互不影响；失败或中途丢弃的请求释放幂等键
+        let Some(Lookup::Proceed(ticket)) = store.begin(&config, &headers("a"), "k2", &hi) else {
+            panic!("其他 API Key 应继续处理");
+        };
+        ticket
+            .finish(&config, StatusCode::TOO_MANY_REQUESTS.into_response())
+            .await;
+        let Some(Lookup::Proceed(ticket)) = store.begin(&config, &headers("a"), "k2", &hi) else {
+            panic!("失败后重试应继续处理");
+        };
+        drop(ticket);
+        assert!(matches!(
+            store.begin(&config, &headers("a"), "k2", &hi),
+            Some(Lookup::Proceed(_))
+        ));
+    }
+}
@@ -0,0 +1,90 @@
+//! 响应消息 ID 生成
+//!
+//! 默认生成 `msg_` + 32 位随机十六进制字符。配置 `messageId.prefix` 可替换前缀；
+//! 配置 `messageId.seed` 后 ID 由种子与进程内序号确定（`prefix` + SHA-256(seed, 序号) 前 32 位），
+//! 同一种子下重启后按相同顺序生成相同 ID，便于金样测试与模拟上游回放。种子变化时序号从 0 重新开始。
+
+use std::sync::LazyLock;
+
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::model::config::{Config, MessageIdConfig};
+
+/// 默认前缀
+const DEFAULT_PREFIX: &str = "msg_";
+
+static GENERATOR: LazyLock<MessageIds> = LazyLock::new(MessageIds::new);
+
+/// 按当前配置生成下一个消息 ID
+pub fn next(config: &Config) -> String {
+    GENERATOR.next(config.message_id.as_ref())
+}
+
+/// 消息 ID 生成器
+struct MessageIds {
+    /// (当前种子, 下一个序号)
+    state: Mutex<(Option<u64>, u64)>,
+}
+
+impl MessageIds {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new((None, 0)),
+        }
+    }
+
+    fn next(&self, config: Option<&MessageIdConfig>) -> String {
+        let prefix = config.map_or(DEFAULT_PREFIX, |c| c.prefix.as_str());
+        let Some(seed) = config.and_then(|c| c.seed) else {
+            return format!("{}{}", prefix, Uuid::new_v4().simple());
+        };
+
+        let sequence = {
+            let mut state = self.state.lock();
+            if state.0 != Some(seed) {
+                *state = (Some(seed), 0);
+            }
+            let sequence = state.1;
+            state.1 += 1;
+            sequence
+        };
+        let mut hasher = Sha256::new();
+        hasher.update(seed.to_be_bytes());
+        hasher.update(sequence.to_be_bytes());
+        format!("{}{}", prefix, &hex::encode(hasher.finalize())[..32])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_ids_are_reproducible() {
+        let config = MessageIdConfig {
+            prefix: "test_".to_string(),
+            seed: Some(7),
+        };
+        let a = MessageIds::new();
+        let b = MessageIds::new();
+        let first = a.next(Some(&config));
+        assert!(first.starts_with("test_"));
+        assert_eq!(first.len(), "test_".len() + 32);
+        assert_eq!(b.next(Some(&config)), first);
+        assert_ne!(a.next(Some(&config)), first);
+
+        // 种子变化后序号重新开始
+        let other = MessageIdConfig {
+            seed: Some(8),
+            ..config.clone()
+        };
+        assert_ne!(a.next(Some(&other)), first);
+        assert_eq!(a.next(Some(&config)), first);
+
+        let random = a.next(None);
+        assert!(random.starts_with(DEFAULT_PREFIX));
+        assert_ne!(random, a.next(None));
+    }
+}
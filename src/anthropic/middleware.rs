@@ -1,20 +1,36 @@
 //! Anthropic API middleware
 
 use std::sync::Arc;
+use std::time::Instant;
 
 use axum::{
     body::Body,
     extract::State,
-    http::{Request, StatusCode},
+    http::{HeaderValue, Request, StatusCode, header},
     middleware::Next,
     response::{IntoResponse, Json, Response},
 };
+use futures::StreamExt;
 
-use crate::apikeys::{ApiKeyManager, AuthenticatedApiKey};
+use crate::access_log::AccessFields;
+use crate::apikeys::{
+    ApiKeyManager, ApiKeyScope, AuthenticatedApiKey, BROWSER_TOKEN_PREFIX, QuotaExceeded,
+    QuotaPeriod, RateLimitKind, RateLimited,
+};
+use crate::batch::BatchStore;
 use crate::common::auth;
+use crate::common::jwt::{self, JwtVerifier};
+use crate::content_filter::{ContentFilter, FilterOutcome};
+use crate::endpoint_metrics::{EndpointFamily, EndpointMetrics};
 use crate::kiro::provider::KiroProvider;
+use crate::kiro::region_pin;
+use crate::pricing;
 use crate::request_log::RequestLog;
+use crate::upstream::UpstreamRegistry;
 
+use super::idempotency::IdempotencyStore;
+use super::prompt_cache::PromptCache;
+use super::response_cache::ResponseCache;
 use super::types::ErrorResponse;
 
 #[derive(Clone)]
@@ -23,6 +39,19 @@ pub struct AppState {
     pub kiro_provider: Option<Arc<KiroProvider>>,
     pub profile_arn: Option<String>,
     pub request_log: Option<Arc<RequestLog>>,
+    pub endpoint_metrics: Option<Arc<EndpointMetrics>>,
+    pub jwt_verifier: Option<Arc<JwtVerifier>>,
+    pub batches: Option<Arc<BatchStore>>,
+    /// 额外上游后端（配置热更新）
+    pub upstreams: Arc<UpstreamRegistry>,
+    /// 请求内容过滤（配置热更新）
+    pub content_filter: Arc<ContentFilter>,
+    /// 非流式响应缓存（配置热更新）
+    pub response_cache: Arc<ResponseCache>,
+    /// `Idempotency-Key` 去重（配置热更新）
+    pub idempotency: Arc<IdempotencyStore>,
+    /// `cache_control` 缓存前缀记录（用于 usage 中的缓存字段）
+    pub prompt_cache: Arc<PromptCache>,
 }
 
 impl AppState {
@@ -32,11 +61,19 @@ impl AppState {
             kiro_provider: None,
             profile_arn: None,
             request_log: None,
+            endpoint_metrics: None,
+            jwt_verifier: None,
+            batches: None,
+            upstreams: Arc::new(UpstreamRegistry::new()),
+            content_filter: Arc::new(ContentFilter::new()),
+            response_cache: Arc::new(ResponseCache::new()),
+            idempotency: Arc::new(IdempotencyStore::new()),
+            prompt_cache: Arc::new(PromptCache::new()),
         }
     }
 
-    pub fn with_kiro_provider(mut self, provider: KiroProvider) -> Self {
-        self.kiro_provider = Some(Arc::new(provider));
+    pub fn with_kiro_provider(mut self, provider: Arc<KiroProvider>) -> Self {
+        self.kiro_provider = Some(provider);
         self
     }
 
@@ -49,6 +86,26 @@ impl AppState {
         self.request_log = Some(log);
         self
     }
+
+    pub fn with_endpoint_metrics(mut self, metrics: Arc<EndpointMetrics>) -> Self {
+        self.endpoint_metrics = Some(metrics);
+        self
+    }
+
+    pub fn with_jwt_verifier(mut self, verifier: Arc<JwtVerifier>) -> Self {
+        self.jwt_verifier = Some(verifier);
+        self
+    }
+
+    pub fn with_batches(mut self, batches: Arc<BatchStore>) -> Self {
+        self.batches = Some(batches);
+        self
+    }
+
+    pub fn with_content_filter(mut self, filter: Arc<ContentFilter>) -> Self {
+        self.content_filter = filter;
+        self
+    }
 }
 
 pub async fn auth_middleware(
@@ -56,20 +113,313 @@ pub async fn auth_middleware(
     mut request: Request<Body>,
     next: Next,
 ) -> Response {
-    let Some(key) = auth::extract_api_key(&request) else {
+    let Some(key) =
+        auth::extract_api_key(&request).or_else(|| auth::extract_gemini_api_key(&request))
+    else {
         let error = ErrorResponse::authentication_error();
         return (StatusCode::UNAUTHORIZED, Json(error)).into_response();
     };
 
-    let Some(authed) = state.api_keys.authenticate(&key) else {
+    let authed = if key.starts_with(BROWSER_TOKEN_PREFIX) {
+        if !is_browser_token_scope(request.uri().path()) {
+            let error = ErrorResponse::new(
+                "permission_error",
+                "Browser tokens can only access the messages endpoints",
+            );
+            return (StatusCode::FORBIDDEN, Json(error)).into_response();
+        }
+        let origin = request
+            .headers()
+            .get(header::ORIGIN)
+            .and_then(|v| v.to_str().ok());
+        state.api_keys.authenticate_browser_token(&key, origin)
+    } else if let Some(verifier) = state
+        .jwt_verifier
+        .as_ref()
+        .filter(|_| jwt::looks_like_jwt(&key))
+    {
+        match verifier.verify(&key).await {
+            Ok(identity) => state.api_keys.authenticate_external(
+                &identity.subject,
+                identity.rpm_limit,
+                identity.tpm_limit,
+            ),
+            Err(e) => {
+                tracing::debug!("{}", e);
+                None
+            }
+        }
+    } else {
+        state.api_keys.authenticate(&key)
+    };
+    let Some(authed) = authed else {
         let error = ErrorResponse::authentication_error();
         return (StatusCode::UNAUTHORIZED, Json(error)).into_response();
     };
 
+    if let Some(scope) = route_scope(request.uri().path())
+        && !authed.allows(scope)
+    {
+        let error = ErrorResponse::new(
+            "permission_error",
+            format!(
+                "This API key is not allowed to access this endpoint (requires scope '{}')",
+                scope
+            ),
+        );
+        return (StatusCode::FORBIDDEN, Json(error)).into_response();
+    }
+
+    // 请求体大小上限：API Key 的设置优先于全局配置
+    let limit = state
+        .api_keys
+        .max_body_bytes(&authed.key_id)
+        .unwrap_or_else(|| {
+            state
+                .kiro_provider
+                .as_ref()
+                .map_or(super::router::MAX_BODY_SIZE, |p| {
+                    p.token_manager().config().max_body_bytes
+                })
+        });
+    request = match limit_body(request, limit).await {
+        Ok(request) => request,
+        Err(response) => return response,
+    };
+
+    if let Err(exceeded) = state.api_keys.check_quota(&authed.key_id) {
+        return quota_exceeded_response(exceeded);
+    }
+
+    if let Err(limited) = state.api_keys.check_rate_limit(&authed) {
+        return rate_limited_response(limited);
+    }
+
+    if let Some(fields) = AccessFields::current() {
+        fields.set_key_id(&authed.key_id);
+    }
+
+    // API Key 限制了凭据地域时，本请求只使用这些地域的凭据
+    let regions = state.api_keys.allowed_regions(&authed.key_id);
     request
         .extensions_mut()
         .insert::<AuthenticatedApiKey>(authed);
-    next.run(request).await
+    region_pin::scope(regions, next.run(request)).await
+}
+
+/// 读取请求体并限制大小，超出时返回 413 `invalid_request_error`
+async fn limit_body(request: Request<Body>, limit: u64) -> Result<Request<Body>, Response> {
+    let too_large = || {
+        let error = ErrorResponse::new(
+            "invalid_request_error",
+            format!("Request body exceeds the maximum size of {} bytes", limit),
+        );
+        (StatusCode::PAYLOAD_TOO_LARGE, Json(error)).into_response()
+    };
+    let declared = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared.is_some_and(|len| len > limit) {
+        return Err(too_large());
+    }
+
+    let (parts, body) = request.into_parts();
+    match axum::body::to_bytes(body, usize::try_from(limit).unwrap_or(usize::MAX)).await {
+        Ok(bytes) => Ok(Request::from_parts(parts, Body::from(bytes))),
+        Err(_) => Err(too_large()),
+    }
+}
+
+/// 路径所需的 API Key 范围（嵌套路由内的相对路径，`/models/{model}:{action}` 为 Gemini 接口）
+fn route_scope(path: &str) -> Option<ApiKeyScope> {
+    if path == "/models" {
+        return Some(ApiKeyScope::Models);
+    }
+    if path == "/messages/count_tokens"
+        || path.starts_with("/models/") && path.ends_with(":countTokens")
+    {
+        return Some(ApiKeyScope::CountTokens);
+    }
+    (path.starts_with("/messages")
+        || path.starts_with("/models/")
+        || path == "/chat/completions"
+        || path == "/browser-tokens")
+        .then_some(ApiKeyScope::Messages)
+}
+
+/// 浏览器临时令牌可访问的路径（嵌套路由内的相对路径）
+fn is_browser_token_scope(path: &str) -> bool {
+    matches!(path, "/messages" | "/messages/count_tokens")
+}
+
+/// 记录 /v1 端点族指标
+pub async fn track_v1_metrics(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    track_metrics(state, EndpointFamily::V1, request, next).await
+}
+
+/// 记录 /cc/v1 端点族指标
+pub async fn track_cc_metrics(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    track_metrics(state, EndpointFamily::Cc, request, next).await
+}
+
+/// 按内容过滤规则拒绝或脱敏 JSON 请求体，脱敏时通过 `x-kiro-warnings` 提示
+pub async fn filter_content(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(provider) = &state.kiro_provider else {
+        return next.run(request).await;
+    };
+    let config = provider.token_manager().config();
+    if !state.content_filter.is_active(&config) {
+        return next.run(request).await;
+    }
+
+    let (mut parts, body) = request.into_parts();
+    // 请求体大小已在认证中间件中限制
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            let error =
+                ErrorResponse::new("invalid_request_error", format!("读取请求体失败: {}", e));
+            return (StatusCode::PAYLOAD_TOO_LARGE, Json(error)).into_response();
+        }
+    };
+    let Ok(mut value) = serde_json::from_slice::<serde_json::Value>(&bytes) else {
+        return next
+            .run(Request::from_parts(parts, Body::from(bytes)))
+            .await;
+    };
+
+    match state.content_filter.apply(&config, &mut value) {
+        FilterOutcome::Rejected(rule) => {
+            tracing::warn!("请求命中内容过滤规则 {}，已拒绝", rule);
+            let error = ErrorResponse::new(
+                "invalid_request_error",
+                format!("Request blocked by content filter rule '{}'", rule),
+            );
+            (StatusCode::BAD_REQUEST, Json(error)).into_response()
+        }
+        FilterOutcome::Redacted(0) => {
+            next.run(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        }
+        FilterOutcome::Redacted(count) => {
+            tracing::info!("请求中 {} 处内容被内容过滤规则脱敏", count);
+            parts.headers.remove(header::CONTENT_LENGTH);
+            let body = Body::from(serde_json::to_vec(&value).unwrap_or_default());
+            let mut response = next.run(Request::from_parts(parts, body)).await;
+            let warning = format!("{} value(s) redacted by content filter", count);
+            let warnings = match response
+                .headers()
+                .get("x-kiro-warnings")
+                .and_then(|v| v.to_str().ok())
+            {
+                Some(existing) => format!("{}; {}", existing, warning),
+                None => warning,
+            };
+            if let Ok(value) = HeaderValue::from_str(&warnings) {
+                response.headers_mut().insert("x-kiro-warnings", value);
+            }
+            response
+        }
+    }
+}
+
+/// 响应体发送完毕（或客户端断开导致响应体被丢弃）时记录一次请求
+struct MetricsGuard {
+    metrics: Arc<EndpointMetrics>,
+    family: EndpointFamily,
+    status: u16,
+    started: Instant,
+}
+
+impl Drop for MetricsGuard {
+    fn drop(&mut self) {
+        self.metrics
+            .record(self.family, self.status, self.started.elapsed());
+    }
+}
+
+async fn track_metrics(
+    state: AppState,
+    family: EndpointFamily,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(metrics) = state.endpoint_metrics else {
+        return next.run(request).await;
+    };
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let guard = MetricsGuard {
+        metrics,
+        family,
+        status: response.status().as_u16(),
+        started,
+    };
+
+    // 延迟统计到响应体结束，流式 / 缓冲流请求同样反映真实耗时
+    let (parts, body) = response.into_parts();
+    let body = Body::from_stream(body.into_data_stream().map(move |chunk| {
+        let _ = &guard;
+        chunk
+    }));
+    Response::from_parts(parts, body)
+}
+
+/// 构造 429 限流响应（附带 Retry-After）
+fn rate_limited_response(limited: RateLimited) -> Response {
+    let retry_after = limited.retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    let message = match limited.kind {
+        RateLimitKind::Requests => "API key request rate limit exceeded",
+        RateLimitKind::Tokens => "API key token rate limit exceeded",
+    };
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(ErrorResponse::new("rate_limit_error", message)),
+    )
+        .into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
+
+/// 构造 429 配额超限响应（Retry-After 为距配额重置的秒数）
+pub(crate) fn quota_exceeded_response(exceeded: QuotaExceeded) -> Response {
+    let retry_after = exceeded.retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    let message = match exceeded.period {
+        QuotaPeriod::Daily => format!("API key daily token quota of {} exceeded", exceeded.limit),
+        QuotaPeriod::Monthly => {
+            format!("API key monthly token quota of {} exceeded", exceeded.limit)
+        }
+        QuotaPeriod::MonthlyBudget => format!(
+            "API key monthly budget of ${:.2} exceeded",
+            pricing::to_usd(exceeded.limit)
+        ),
+    };
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(ErrorResponse::new("quota_exceeded", message)),
+    )
+        .into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    response
 }
 
 pub fn cors_layer() -> tower_http::cors::CorsLayer {
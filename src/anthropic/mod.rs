@@ -8,6 +8,7 @@
 //! - `GET /v1/models` - 获取可用模型列表
 //! - `POST /v1/messages` - 创建消息（对话）
 //! - `POST /v1/messages/count_tokens` - 计算 token 数量
+//! - `/v1/messages/batches` - 消息批处理（创建、查询、结果下载、取消、删除）
 //!
 //! ## Claude Code 兼容端点 (/cc/v1)
 //! - `POST /cc/v1/messages` - 创建消息（流式响应会等待 contextUsageEvent 后再发送 message_start，确保 input_tokens 准确）
@@ -22,12 +23,21 @@
 //! axum::serve(listener, app).await?;
 //! ```
 
-mod converter;
-mod handlers;
-mod middleware;
+pub(crate) mod batches;
+pub(crate) mod compaction;
+#[cfg(test)]
+mod conformance;
+pub(crate) mod converter;
+pub(crate) mod handlers;
+pub(crate) mod heartbeat;
+mod idempotency;
+mod message_id;
+pub(crate) mod middleware;
+mod prompt_cache;
+mod response_cache;
 mod router;
-mod stream;
+pub(crate) mod stream;
 pub mod types;
 mod websearch;
 
-pub use router::create_router_with_provider;
+pub use router::{RouterDeps, create_router_with_provider};
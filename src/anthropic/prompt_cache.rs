@@ -0,0 +1,260 @@
+//! Prompt Caching（`cache_control`）用量模拟
+//!
+//! Kiro 上游没有提示词缓存，`cache_control` 标记无法转发。为了让客户端能按 Anthropic 语义判断缓存行为，
+//! 这里按 API Key 记录每个缓存断点之前的前缀（顺序为 tools → system → messages）：
+//! 前缀在 TTL（默认 5 分钟，`ttl: "1h"` 为 1 小时）内再次出现时计为 `cache_read_input_tokens`，
+//! 其余断点前缀计为 `cache_creation_input_tokens`，`input_tokens` 相应扣除这两部分。
+//! 命中会刷新 TTL。状态仅保存在内存中。
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::token;
+
+use super::types::{CacheControl, MessagesRequest};
+
+/// 默认缓存时长
+const DEFAULT_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// `ttl: "1h"` 对应的缓存时长
+const EXTENDED_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// 最多记录的前缀数，超出时先清理过期条目，再淘汰最早过期的条目
+const MAX_ENTRIES: usize = 10_000;
+
+/// 缓存相关的 token 用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheUsage {
+    pub creation_input_tokens: i32,
+    pub read_input_tokens: i32,
+}
+
+impl CacheUsage {
+    /// 写入 usage 对象：`input_tokens` 扣除缓存部分，并补充两个缓存字段
+    ///
+    /// 缓存部分按估算值计算，超过实际输入 tokens 时截断
+    pub fn apply(&self, usage: &mut serde_json::Value) {
+        let input = usage["input_tokens"].as_i64().unwrap_or(0).max(0);
+        let read = (self.read_input_tokens as i64).min(input);
+        let creation = (self.creation_input_tokens as i64).min(input - read);
+        usage["input_tokens"] = json!(input - read - creation);
+        usage["cache_creation_input_tokens"] = json!(creation);
+        usage["cache_read_input_tokens"] = json!(read);
+    }
+}
+
+/// 缓存断点：断点之前前缀的哈希、累计 tokens 与缓存时长
+struct Breakpoint {
+    hash: String,
+    tokens: u64,
+    ttl: Duration,
+}
+
+/// 按 API Key 记录的缓存前缀
+pub struct PromptCache {
+    /// 前缀哈希 -> 过期时间
+    entries: Mutex<HashMap<String, Instant>>,
+}
+
+impl PromptCache {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 计算请求的缓存用量并记录断点前缀，请求没有 `cache_control` 标记时返回 None
+    pub fn lookup(&self, key_id: &str, payload: &MessagesRequest) -> Option<CacheUsage> {
+        self.lookup_at(key_id, payload, Instant::now())
+    }
+
+    fn lookup_at(
+        &self,
+        key_id: &str,
+        payload: &MessagesRequest,
+        now: Instant,
+    ) -> Option<CacheUsage> {
+        let breakpoints = breakpoints(key_id, payload);
+        let last = breakpoints.last()?;
+
+        let mut entries = self.entries.lock();
+        // 取最长的未过期前缀作为缓存读取部分
+        let read = breakpoints
+            .iter()
+            .rev()
+            .find(|b| entries.get(&b.hash).is_some_and(|expires| *expires > now))
+            .map_or(0, |b| b.tokens);
+        let creation = last.tokens.saturating_sub(read);
+
+        if entries.len() + breakpoints.len() > MAX_ENTRIES {
+            entries.retain(|_, expires| *expires > now);
+            while entries.len() + breakpoints.len() > MAX_ENTRIES {
+                let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, expires)| **expires)
+                    .map(|(k, _)| k.clone())
+                else {
+                    break;
+                };
+                entries.remove(&oldest);
+            }
+        }
+        for b in &breakpoints {
+            let expires = now + b.ttl;
+            let entry = entries.entry(b.hash.clone()).or_insert(expires);
+            *entry = (*entry).max(expires);
+        }
+
+        Some(CacheUsage {
+            creation_input_tokens: creation.min(i32::MAX as u64) as i32,
+            read_input_tokens: read.min(i32::MAX as u64) as i32,
+        })
+    }
+}
+
+/// 按 tools → system → messages 的顺序收集缓存断点
+fn breakpoints(key_id: &str, payload: &MessagesRequest) -> Vec<Breakpoint> {
+    let mut hasher = Sha256::new();
+    hasher.update(key_id.as_bytes());
+    hasher.update([0]);
+    hasher.update(payload.model.as_bytes());
+    let mut tokens = 0u64;
+    let mut result = Vec::new();
+
+    let mut push = |hasher: &Sha256, tokens: u64, control: &CacheControl| {
+        result.push(Breakpoint {
+            hash: hex::encode(hasher.clone().finalize()),
+            tokens,
+            ttl: control.ttl(),
+        });
+    };
+
+    for tool in payload.tools.iter().flatten() {
+        let text = serde_json::to_string(&(&tool.name, &tool.description, &tool.input_schema))
+            .unwrap_or_default();
+        hasher.update(text.as_bytes());
+        tokens += token::count_tokens(&text);
+        if let Some(control) = &tool.cache_control {
+            push(&hasher, tokens, control);
+        }
+    }
+
+    for system in payload.system.iter().flatten() {
+        hasher.update(system.text.as_bytes());
+        tokens += token::count_tokens(&system.text);
+        if let Some(control) = &system.cache_control {
+            push(&hasher, tokens, control);
+        }
+    }
+
+    for message in &payload.messages {
+        hasher.update(message.role.as_bytes());
+        let blocks = match &message.content {
+            serde_json::Value::Array(blocks) => blocks.clone(),
+            other => vec![other.clone()],
+        };
+        for block in &blocks {
+            let text = match block.get("text").and_then(|t| t.as_str()) {
+                Some(text) => text.to_string(),
+                None => match block {
+                    serde_json::Value::String(s) => s.clone(),
+                    _ => {
+                        let mut block = block.clone();
+                        if let Some(map) = block.as_object_mut() {
+                            map.remove("cache_control");
+                        }
+                        block.to_string()
+                    }
+                },
+            };
+            hasher.update(text.as_bytes());
+            tokens += token::count_tokens(&text);
+            if let Some(control) = block
+                .get("cache_control")
+                .and_then(|c| serde_json::from_value::<CacheControl>(c.clone()).ok())
+            {
+                push(&hasher, tokens, &control);
+            }
+        }
+    }
+
+    result
+}
+
+impl CacheControl {
+    fn ttl(&self) -> Duration {
+        match self.ttl.as_deref() {
+            Some("1h") => EXTENDED_TTL,
+            _ => DEFAULT_TTL,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(body: serde_json::Value) -> MessagesRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    fn cached_request(question: &str) -> MessagesRequest {
+        request(json!({
+            "model": "claude-sonnet-4-6",
+            "max_tokens": 10,
+            "system": [{
+                "type": "text",
+                "text": "You are a helpful assistant that answers questions about Rust.",
+                "cache_control": {"type": "ephemeral"}
+            }],
+            "messages": [{"role": "user", "content": question}]
+        }))
+    }
+
+    #[test]
+    fn test_lookup_reports_creation_then_read() {
+        let cache = PromptCache::new();
+        let now = Instant::now();
+
+        let plain = request(json!({
+            "model": "claude-sonnet-4-6",
+            "max_tokens": 10,
+            "messages": [{"role": "user", "content": "hi"}]
+        }));
+        assert_eq!(cache.lookup_at("k1", &plain, now), None);
+
+        let first = cache.lookup_at("k1", &cached_request("a"), now).unwrap();
+        assert!(first.creation_input_tokens > 0);
+        assert_eq!(first.read_input_tokens, 0);
+
+        // 相同前缀、不同问题命中缓存
+        let second = cache.lookup_at("k1", &cached_request("b"), now).unwrap();
+        assert_eq!(second.read_input_tokens, first.creation_input_tokens);
+        assert_eq!(second.creation_input_tokens, 0);
+
+        // 其他 API Key 与过期后不命中
+        let other = cache.lookup_at("k2", &cached_request("a"), now).unwrap();
+        assert_eq!(other.read_input_tokens, 0);
+        let expired = cache
+            .lookup_at("k1", &cached_request("a"), now + DEFAULT_TTL * 2)
+            .unwrap();
+        assert_eq!(expired.read_input_tokens, 0);
+    }
+
+    #[test]
+    fn test_apply_caps_cached_tokens_to_input() {
+        let usage = CacheUsage {
+            creation_input_tokens: 30,
+            read_input_tokens: 80,
+        };
+        let mut value = json!({"input_tokens": 100, "output_tokens": 5});
+        usage.apply(&mut value);
+        assert_eq!(value["input_tokens"], 0);
+        assert_eq!(value["cache_read_input_tokens"], 80);
+        assert_eq!(value["cache_creation_input_tokens"], 20);
+    }
+}
@@ -0,0 +1,239 @@
+//! 非流式响应缓存
+//!
+//! 按 (API Key, 规范化请求体) 的 SHA-256 缓存成功的非流式 `/v1/messages` 响应，
+//! 相同请求在 TTL 内直接返回缓存内容（带 `x-kiro-cache: hit` 响应头），不消耗凭据额度。
+//! 规范化时忽略 JSON 字段顺序与 `stream` 字段。配置热更新，关闭后缓存随即清空。
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+
+use crate::model::config::{Config, ResponseCacheConfig};
+
+use super::types::MessagesRequest;
+
+/// 命中缓存时附加的响应头
+const CACHE_HEADER: &str = "x-kiro-cache";
+
+struct CachedResponse {
+    headers: HeaderMap,
+    body: Bytes,
+    stored_at: Instant,
+    /// 写入序号，用于淘汰最早写入的条目
+    seq: u64,
+}
+
+/// 响应缓存
+pub struct ResponseCache {
+    entries: Mutex<HashMap<String, CachedResponse>>,
+    next_seq: AtomicU64,
+}
+
+impl ResponseCache {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            next_seq: AtomicU64::new(0),
+        }
+    }
+
+    /// 当前生效的缓存配置，未启用时返回 None 并清空缓存
+    fn policy(&self, config: &Config) -> Option<ResponseCacheConfig> {
+        match config.response_cache.as_ref().filter(|c| c.enabled) {
+            Some(policy) => Some(policy.clone()),
+            None => {
+                let mut entries = self.entries.lock();
+                if !entries.is_empty() {
+                    entries.clear();
+                }
+                None
+            }
+        }
+    }
+
+    /// 计算缓存键，未启用缓存时返回 None
+    pub fn key(&self, config: &Config, key_id: &str, payload: &MessagesRequest) -> Option<String> {
+        self.policy(config)?;
+        let mut value = serde_json::to_value(payload).ok()?;
+        if let Some(map) = value.as_object_mut() {
+            map.remove("stream");
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(key_id.as_bytes());
+        hasher.update([0]);
+        hasher.update(value.to_string().as_bytes());
+        Some(hex::encode(hasher.finalize()))
+    }
+
+    /// 查找未过期的缓存响应
+    pub fn get(&self, config: &Config, key: &str) -> Option<Response> {
+        let ttl = Duration::from_secs(self.policy(config)?.ttl_secs);
+        let mut entries = self.entries.lock();
+        let cached = entries.get(key)?;
+        if cached.stored_at.elapsed() >= ttl {
+            entries.remove(key);
+            return None;
+        }
+        let mut response = (StatusCode::OK, Body::from(cached.body.clone())).into_response();
+        *response.headers_mut() = cached.headers.clone();
+        response
+            .headers_mut()
+            .insert(CACHE_HEADER, HeaderValue::from_static("hit"));
+        Some(response)
+    }
+
+    /// 缓存成功的响应并原样返回；非 200 响应或超过单条大小上限的响应不缓存
+    pub async fn store(&self, config: &Config, key: String, response: Response) -> Response {
+        let Some(policy) = self.policy(config) else {
+            return response;
+        };
+        if response.status() != StatusCode::OK {
+            return response;
+        }
+        let (parts, body) = response.into_parts();
+        let body = match axum::body::to_bytes(body, usize::MAX).await {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::warn!("读取响应体失败，跳过缓存: {}", e);
+                return (StatusCode::BAD_GATEWAY, "failed to read upstream response")
+                    .into_response();
+            }
+        };
+        if body.len() <= policy.max_entry_bytes {
+            let mut headers = parts.headers.clone();
+            headers.remove(header::CONTENT_LENGTH);
+            headers.remove(header::TRANSFER_ENCODING);
+            self.insert(&policy, key, headers, body.clone(), Instant::now());
+        }
+        Response::from_parts(parts, Body::from(body))
+    }
+
+    fn insert(
+        &self,
+        policy: &ResponseCacheConfig,
+        key: String,
+        headers: HeaderMap,
+        body: Bytes,
+        now: Instant,
+    ) {
+        if policy.max_entries == 0 {
+            return;
+        }
+        let ttl = Duration::from_secs(policy.ttl_secs);
+        let mut entries = self.entries.lock();
+        if entries.len() >= policy.max_entries && !entries.contains_key(&key) {
+            entries.retain(|_, e| now.duration_since(e.stored_at) < ttl);
+            // 仍然已满时淘汰最早写入的条目
+            while entries.len() >= policy.max_entries {
+                let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, e)| e.seq)
+                    .map(|(k, _)| k.clone())
+                else {
+                    break;
+                };
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            key,
+            CachedResponse {
+                headers,
+                body,
+                stored_at: now,
+                seq: self.next_seq.fetch_add(1, Ordering::Relaxed),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_entries: usize) -> Config {
+        let mut config = Config::default();
+        config.response_cache = Some(ResponseCacheConfig {
+            enabled: true,
+            ttl_secs: 60,
+            max_entries,
+            max_entry_bytes: 16,
+        });
+        config
+    }
+
+    fn request(body: &str) -> MessagesRequest {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn test_key_ignores_stream_and_field_order_but_not_api_key() {
+        let cache = ResponseCache::new();
+        let config = config(10);
+        let a = request(
+            r#"{"model":"claude-sonnet-4-6","max_tokens":10,"stream":false,"messages":[{"role":"user","content":"hi"}]}"#,
+        );
+        let b = request(
+            r#"{"messages":[{"role":"user","content":"hi"}],"max_tokens":10,"model":"claude-sonnet-4-6"}"#,
+        );
+        let key = cache.key(&config, "k1", &a).unwrap();
+        assert_eq!(cache.key(&config, "k1", &b), Some(key.clone()));
+        assert_ne!(cache.key(&config, "k2", &a), Some(key));
+        assert_eq!(cache.key(&Config::default(), "k1", &a), None);
+    }
+
+    #[tokio::test]
+    async fn test_store_respects_size_bounds() {
+        let cache = ResponseCache::new();
+        let config = config(2);
+
+        let response = cache
+            .store(&config, "a".to_string(), "small".into_response())
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let hit = cache.get(&config, "a").unwrap();
+        assert_eq!(hit.headers()[CACHE_HEADER], "hit");
+        let body = axum::body::to_bytes(hit.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"small");
+
+        // 超过单条大小上限或非 200 的响应不缓存，但原样返回
+        let response = cache
+            .store(&config, "big".to_string(), "x".repeat(32).into_response())
+            .await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body.len(), 32);
+        assert!(cache.get(&config, "big").is_none());
+        cache
+            .store(
+                &config,
+                "err".to_string(),
+                StatusCode::TOO_MANY_REQUESTS.into_response(),
+            )
+            .await;
+        assert!(cache.get(&config, "err").is_none());
+
+        // 条目数达到上限时淘汰最早的条目
+        cache
+            .store(&config, "b".to_string(), "b".into_response())
+            .await;
+        cache
+            .store(&config, "c".to_string(), "c".into_response())
+            .await;
+        assert!(cache.get(&config, "a").is_none());
+        assert!(cache.get(&config, "c").is_some());
+
+        // 关闭缓存后清空
+        assert!(cache.get(&Config::default(), "c").is_none());
+        assert!(cache.get(&config, "c").is_none());
+    }
+}
@@ -10,23 +10,56 @@ use axum::{
 };
 
 use crate::apikeys::ApiKeyManager;
+use crate::batch::BatchStore;
+use crate::common::jwt::JwtVerifier;
+use crate::content_filter::ContentFilter;
+use crate::endpoint_metrics::EndpointMetrics;
+use crate::gemini::post_gemini;
 use crate::kiro::provider::KiroProvider;
+use crate::openai::post_chat_completions;
+use crate::openapi::get_openapi;
 use crate::request_log::RequestLog;
 
 use super::{
-    handlers::{count_tokens, get_models, post_messages, post_messages_cc},
-    middleware::{AppState, auth_middleware, cors_layer},
+    batches::{
+        cancel_batch, create_batch, delete_batch, get_batch, get_batch_results, list_batches,
+        resume_batches,
+    },
+    handlers::{
+        count_tokens, get_models, post_browser_token, post_messages, post_messages_cc, readyz,
+    },
+    middleware::{
+        AppState, auth_middleware, cors_layer, filter_content, track_cc_metrics, track_v1_metrics,
+    },
 };
 
-const MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
+/// 未配置 Kiro provider 时的请求体大小上限（配置后由 `maxBodyBytes` 决定）
+pub(crate) const MAX_BODY_SIZE: u64 = 50 * 1024 * 1024;
 
-pub fn create_router_with_provider(
-    api_keys: Arc<ApiKeyManager>,
-    kiro_provider: Option<KiroProvider>,
-    profile_arn: Option<String>,
-    request_log: Option<Arc<RequestLog>>,
-) -> Router {
-    let mut state = AppState::new(api_keys);
+/// 业务路由依赖，可选项为 None 时不启用对应功能
+pub struct RouterDeps {
+    pub api_keys: Arc<ApiKeyManager>,
+    pub kiro_provider: Option<Arc<KiroProvider>>,
+    pub profile_arn: Option<String>,
+    pub request_log: Option<Arc<RequestLog>>,
+    pub endpoint_metrics: Option<Arc<EndpointMetrics>>,
+    pub jwt_verifier: Option<Arc<JwtVerifier>>,
+    pub batches: Option<Arc<BatchStore>>,
+    pub content_filter: Arc<ContentFilter>,
+}
+
+pub fn create_router_with_provider(deps: RouterDeps) -> Router {
+    let RouterDeps {
+        api_keys,
+        kiro_provider,
+        profile_arn,
+        request_log,
+        endpoint_metrics,
+        jwt_verifier,
+        batches,
+        content_filter,
+    } = deps;
+    let mut state = AppState::new(api_keys).with_content_filter(content_filter);
     if let Some(provider) = kiro_provider {
         state = state.with_kiro_provider(provider);
     }
@@ -36,11 +69,38 @@ pub fn create_router_with_provider(
     if let Some(log) = request_log {
         state = state.with_request_log(log);
     }
+    if let Some(metrics) = endpoint_metrics {
+        state = state.with_endpoint_metrics(metrics);
+    }
+    if let Some(verifier) = jwt_verifier {
+        state = state.with_jwt_verifier(verifier);
+    }
+    if let Some(batches) = batches {
+        state = state.with_batches(batches);
+    }
+    resume_batches(&state);
 
     let v1_routes = Router::new()
         .route("/models", get(get_models))
         .route("/messages", post(post_messages))
         .route("/messages/count_tokens", post(count_tokens))
+        .route("/messages/batches", post(create_batch).get(list_batches))
+        .route(
+            "/messages/batches/{id}",
+            get(get_batch).delete(delete_batch),
+        )
+        .route("/messages/batches/{id}/results", get(get_batch_results))
+        .route("/messages/batches/{id}/cancel", post(cancel_batch))
+        .route("/chat/completions", post(post_chat_completions))
+        .route("/browser-tokens", post(post_browser_token))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            filter_content,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            track_v1_metrics,
+        ))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
@@ -49,15 +109,38 @@ pub fn create_router_with_provider(
     let cc_v1_routes = Router::new()
         .route("/messages", post(post_messages_cc))
         .route("/messages/count_tokens", post(count_tokens))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            filter_content,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            track_cc_metrics,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ));
+
+    let gemini_routes = Router::new()
+        .route("/models/{model_action}", post(post_gemini))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            filter_content,
+        ))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
         ));
 
     Router::new()
+        .route("/openapi.json", get(get_openapi))
+        .route("/readyz", get(readyz))
         .nest("/v1", v1_routes)
         .nest("/cc/v1", cc_v1_routes)
+        .nest("/v1beta", gemini_routes)
         .layer(cors_layer())
-        .layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
+        // 请求体大小由认证中间件按配置与 API Key 限制
+        .layer(DefaultBodyLimit::disable())
         .with_state(state)
 }
@@ -9,6 +9,8 @@ use uuid::Uuid;
 
 use crate::kiro::model::events::Event;
 
+use super::prompt_cache::CacheUsage;
+
 /// 找到小于等于目标位置的最近有效UTF-8字符边界
 ///
 /// UTF-8字符可能占用1-4个字节，直接按字节位置切片可能会切在多字节字符中间导致panic。
@@ -349,12 +351,29 @@ impl SseStateManager {
     ) -> Vec<SseEvent> {
         let mut events = Vec::new();
 
-        // 如果是 tool_use 块，先关闭之前的文本块
         if block_type == "tool_use" {
             self.has_tool_use = true;
+        }
+
+        // 检查块是否已存在（同一工具调用的后续增量会重复调用）
+        if self
+            .active_blocks
+            .get(&index)
+            .is_some_and(|block| block.started)
+        {
+            tracing::debug!("块 {} 已启动，跳过重复的 content_block_start", index);
+            return events;
+        }
+
+        // 内容块必须依次输出：开始新的 text / tool_use 块前，先关闭仍在输出的 text / tool_use 块
+        // （例如上一个工具调用尚未收到 stop 就开始了下一个工具调用或文本）
+        if block_type == "tool_use" || block_type == "text" {
             for (block_index, block) in self.active_blocks.iter_mut() {
-                if block.block_type == "text" && block.started && !block.stopped {
-                    // 自动发送 content_block_stop 关闭文本块
+                if (block.block_type == "text" || block.block_type == "tool_use")
+                    && block.started
+                    && !block.stopped
+                {
+                    // 自动发送 content_block_stop 关闭该块
                     events.push(SseEvent::new(
                         "content_block_stop",
                         json!({
@@ -367,18 +386,10 @@ impl SseStateManager {
             }
         }
 
-        // 检查块是否已存在
-        if let Some(block) = self.active_blocks.get_mut(&index) {
-            if block.started {
-                tracing::debug!("块 {} 已启动，跳过重复的 content_block_start", index);
-                return events;
-            }
-            block.started = true;
-        } else {
-            let mut block = BlockState::new(block_type);
-            block.started = true;
-            self.active_blocks.insert(index, block);
-        }
+        self.active_blocks
+            .entry(index)
+            .or_insert_with(|| BlockState::new(block_type))
+            .started = true;
 
         events.push(SseEvent::new("content_block_start", data));
         events
@@ -498,8 +509,12 @@ pub struct StreamContext {
     pub input_tokens: i32,
     /// 从 contextUsageEvent 计算的实际输入 tokens
     pub context_input_tokens: Option<i32>,
+    /// 上游 contextUsageEvent 报告的上下文使用百分比
+    pub context_usage_percentage: Option<f64>,
     /// 输出 tokens 累计
     pub output_tokens: i32,
+    /// 输出 token 上限（`modelMaxOutputTokens`），达到后丢弃后续内容
+    output_limit: Option<i32>,
     /// 工具块索引映射 (tool_id -> block_index)
     pub tool_block_indices: HashMap<String, i32>,
     /// thinking 是否启用
@@ -517,6 +532,8 @@ pub struct StreamContext {
     /// 是否需要剥离 thinking 内容开头的换行符
     /// 模型输出 `<thinking>\n` 时，`\n` 可能与标签在同一 chunk 或下一 chunk
     strip_thinking_leading_newline: bool,
+    /// `cache_control` 缓存用量（没有缓存断点时为 0，始终写入 usage）
+    cache_usage: CacheUsage,
 }
 
 impl StreamContext {
@@ -532,7 +549,9 @@ impl StreamContext {
             message_id: format!("msg_{}", Uuid::new_v4().to_string().replace('-', "")),
             input_tokens,
             context_input_tokens: None,
+            context_usage_percentage: None,
             output_tokens: 0,
+            output_limit: None,
             tool_block_indices: HashMap::new(),
             thinking_enabled,
             thinking_buffer: String::new(),
@@ -541,12 +560,37 @@ impl StreamContext {
             thinking_block_index: None,
             text_block_index: None,
             strip_thinking_leading_newline: false,
+            cache_usage: CacheUsage::default(),
         }
     }
 
+    /// 设置输出 token 上限
+    pub fn with_output_limit(mut self, limit: Option<i32>) -> Self {
+        self.output_limit = limit;
+        self
+    }
+
+    /// 设置缓存用量
+    pub fn with_cache_usage(mut self, cache_usage: CacheUsage) -> Self {
+        self.cache_usage = cache_usage;
+        self
+    }
+
+    /// 设置消息 ID（默认随机生成）
+    pub fn with_message_id(mut self, message_id: String) -> Self {
+        self.message_id = message_id;
+        self
+    }
+
+    /// 输出是否已达到上限
+    pub fn output_limit_reached(&self) -> bool {
+        self.output_limit
+            .is_some_and(|limit| self.output_tokens >= limit)
+    }
+
     /// 生成 message_start 事件
     pub fn create_message_start_event(&self) -> serde_json::Value {
-        json!({
+        let mut event = json!({
             "type": "message_start",
             "message": {
                 "id": self.message_id,
@@ -561,7 +605,9 @@ impl StreamContext {
                     "output_tokens": 1
                 }
             }
-        })
+        });
+        self.cache_usage.apply(&mut event["message"]["usage"]);
+        event
     }
 
     /// 生成初始事件序列 (message_start + 文本块 start)
@@ -605,6 +651,13 @@ impl StreamContext {
 
     /// 处理 Kiro 事件并转换为 Anthropic SSE 事件
     pub fn process_kiro_event(&mut self, event: &Event) -> Vec<SseEvent> {
+        // 达到输出上限后丢弃后续内容，但继续处理 contextUsageEvent 以获取准确的输入 tokens
+        if matches!(event, Event::AssistantResponse(_) | Event::ToolUse(_))
+            && self.output_limit_reached()
+        {
+            self.state_manager.set_stop_reason("max_tokens");
+            return Vec::new();
+        }
         match event {
             Event::AssistantResponse(resp) => self.process_assistant_response(&resp.content),
             Event::ToolUse(tool_use) => self.process_tool_use(tool_use),
@@ -615,6 +668,7 @@ impl StreamContext {
                     * (CONTEXT_WINDOW_SIZE as f64)
                     / 100.0) as i32;
                 self.context_input_tokens = Some(actual_input_tokens);
+                self.context_usage_percentage = Some(context_usage.context_usage_percentage);
                 // 上下文使用量达到 100% 时，设置 stop_reason 为 model_context_window_exceeded
                 if context_usage.context_usage_percentage >= 100.0 {
                     self.state_manager
@@ -1091,6 +1145,16 @@ impl StreamContext {
             self.state_manager
                 .generate_final_events(final_input_tokens, self.output_tokens),
         );
+
+        // 上下文使用百分比写入 message_delta 的 usage，客户端可据此提前压缩历史
+        if let Some(percentage) = self.context_usage_percentage {
+            for event in events.iter_mut().filter(|e| e.event == "message_delta") {
+                event.data["usage"]["context_usage_percentage"] = json!(percentage);
+            }
+        }
+        for event in events.iter_mut().filter(|e| e.event == "message_delta") {
+            self.cache_usage.apply(&mut event.data["usage"]);
+        }
         events
     }
 
@@ -1152,6 +1216,24 @@ impl BufferedStreamContext {
         }
     }
 
+    /// 设置输出 token 上限
+    pub fn with_output_limit(mut self, limit: Option<i32>) -> Self {
+        self.inner = self.inner.with_output_limit(limit);
+        self
+    }
+
+    /// 设置缓存用量
+    pub fn with_cache_usage(mut self, cache_usage: CacheUsage) -> Self {
+        self.inner = self.inner.with_cache_usage(cache_usage);
+        self
+    }
+
+    /// 设置消息 ID
+    pub fn with_message_id(mut self, message_id: String) -> Self {
+        self.inner = self.inner.with_message_id(message_id);
+        self
+    }
+
     /// 处理 Kiro 事件并缓冲结果
     ///
     /// 复用 StreamContext 的事件处理逻辑，但把结果缓存而不是立即发送。
@@ -1198,6 +1280,7 @@ impl BufferedStreamContext {
                 if let Some(message) = event.data.get_mut("message") {
                     if let Some(usage) = message.get_mut("usage") {
                         usage["input_tokens"] = serde_json::json!(final_input_tokens);
+                        self.inner.cache_usage.apply(usage);
                     }
                 }
             }
@@ -1354,6 +1437,82 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_partial_tool_use_streams_input_json_delta_incrementally() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        let _ = ctx.generate_initial_events();
+        let tool = |input: &str, stop: bool| crate::kiro::model::events::ToolUseEvent {
+            name: "get_weather".to_string(),
+            tool_use_id: "tool_1".to_string(),
+            input: input.to_string(),
+            stop,
+        };
+
+        // 第一段参数：立即输出 content_block_start 与 input_json_delta，不等待 stop
+        let first = ctx.process_tool_use(&tool("{\"city\": ", false));
+        let start = first
+            .iter()
+            .find(|e| {
+                e.event == "content_block_start" && e.data["content_block"]["type"] == "tool_use"
+            })
+            .expect("tool_use block should start on the first partial event");
+        let index = start.data["index"].clone();
+        assert_eq!(start.data["content_block"]["input"], json!({}));
+        assert!(first.iter().any(|e| {
+            e.data["delta"]["type"] == "input_json_delta"
+                && e.data["delta"]["partial_json"] == "{\"city\": "
+        }));
+        assert!(
+            !first
+                .iter()
+                .any(|e| e.event == "content_block_stop" && e.data["index"] == index)
+        );
+
+        // 后续片段只输出 delta，不重复 content_block_start
+        let second = ctx.process_tool_use(&tool("\"Paris\"", false));
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].data["index"], index);
+        assert_eq!(second[0].data["delta"]["partial_json"], "\"Paris\"");
+
+        let last = ctx.process_tool_use(&tool("}", true));
+        let events: Vec<&str> = last.iter().map(|e| e.event.as_str()).collect();
+        assert_eq!(events, ["content_block_delta", "content_block_stop"]);
+        assert_eq!(last[1].data["index"], index);
+    }
+
+    #[test]
+    fn test_unfinished_tool_use_is_stopped_before_next_block() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        let _ = ctx.generate_initial_events();
+        let tool = |id: &str| crate::kiro::model::events::ToolUseEvent {
+            name: "test_tool".to_string(),
+            tool_use_id: id.to_string(),
+            input: "{".to_string(),
+            stop: false,
+        };
+
+        let first = ctx.process_tool_use(&tool("tool_1"));
+        let first_index = first
+            .iter()
+            .find(|e| e.event == "content_block_start")
+            .map(|e| e.data["index"].clone())
+            .unwrap();
+
+        // 上一个工具调用未收到 stop 就开始下一个：先关闭上一个块，保证块不交错
+        let second = ctx.process_tool_use(&tool("tool_2"));
+        let pos_stop = second
+            .iter()
+            .position(|e| e.event == "content_block_stop" && e.data["index"] == first_index);
+        let pos_start = second.iter().position(|e| e.event == "content_block_start");
+        assert!(pos_stop.is_some() && pos_stop < pos_start);
+
+        // 文本同样先关闭仍在输出的工具块
+        let text = ctx.process_assistant_response("done");
+        assert_eq!(text[0].event, "content_block_stop");
+        assert_eq!(text[1].event, "content_block_start");
+        assert_eq!(text[1].data["content_block"]["type"], "text");
+    }
+
     #[test]
     fn test_tool_use_flushes_pending_thinking_buffer_text_before_tool_block() {
         // thinking 模式下，短文本可能被暂存在 thinking_buffer 以等待 `<thinking>` 的跨 chunk 匹配。
@@ -1874,6 +2033,75 @@ mod tests {
         assert_eq!(text, "world", "text should be 'world', got: {:?}", text);
     }
 
+    #[test]
+    fn test_output_limit_cuts_stream_with_max_tokens() {
+        use crate::kiro::model::events::AssistantResponseEvent;
+
+        let text = |content: &str| {
+            let mut event = AssistantResponseEvent::default();
+            event.content = content.to_string();
+            Event::AssistantResponse(event)
+        };
+        let mut ctx =
+            StreamContext::new_with_thinking("test-model", 1, false).with_output_limit(Some(3));
+        let mut all = ctx.generate_initial_events();
+        all.extend(ctx.process_kiro_event(&text("hello world, this is long")));
+        assert!(ctx.output_limit_reached());
+        all.extend(ctx.process_kiro_event(&text("dropped")));
+        all.extend(ctx.generate_final_events());
+
+        assert_eq!(collect_text_content(&all), "hello world, this is long");
+        let message_delta = all.iter().find(|e| e.event == "message_delta").unwrap();
+        assert_eq!(message_delta.data["delta"]["stop_reason"], "max_tokens");
+    }
+
+    #[test]
+    fn test_context_usage_percentage_in_message_delta() {
+        use crate::kiro::model::events::ContextUsageEvent;
+
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        let _initial_events = ctx.generate_initial_events();
+        ctx.process_kiro_event(&Event::ContextUsage(ContextUsageEvent {
+            context_usage_percentage: 42.5,
+        }));
+        let final_events = ctx.generate_final_events();
+
+        let message_delta = final_events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .expect("should have message_delta event");
+        assert_eq!(
+            message_delta.data["usage"]["context_usage_percentage"],
+            42.5
+        );
+        assert_eq!(message_delta.data["usage"]["input_tokens"], 85000);
+    }
+
+    #[test]
+    fn test_cache_usage_in_message_start_and_delta() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 100, false).with_cache_usage(
+            CacheUsage {
+                creation_input_tokens: 10,
+                read_input_tokens: 60,
+            },
+        );
+        let start = ctx.create_message_start_event();
+        assert_eq!(start["message"]["usage"]["input_tokens"], 30);
+        assert_eq!(start["message"]["usage"]["cache_read_input_tokens"], 60);
+
+        let _initial_events = ctx.generate_initial_events();
+        let final_events = ctx.generate_final_events();
+        let message_delta = final_events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .expect("should have message_delta event");
+        assert_eq!(message_delta.data["usage"]["input_tokens"], 30);
+        assert_eq!(
+            message_delta.data["usage"]["cache_creation_input_tokens"],
+            10
+        );
+    }
+
     #[test]
     fn test_thinking_only_sets_max_tokens_stop_reason() {
         // 整个流只有 thinking 块，没有 text 也没有 tool_use，stop_reason 应为 max_tokens
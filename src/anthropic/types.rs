@@ -2,17 +2,18 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
 
 // === 错误响应 ===
 
 /// API 错误响应
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ErrorResponse {
     pub error: ErrorDetail,
 }
 
 /// 错误详情
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ErrorDetail {
     #[serde(rename = "type")]
     pub error_type: String,
@@ -39,7 +40,7 @@ impl ErrorResponse {
 // === Models 端点类型 ===
 
 /// 模型信息
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct Model {
     pub id: String,
     pub object: String,
@@ -52,7 +53,7 @@ pub struct Model {
 }
 
 /// 模型列表响应
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ModelsResponse {
     pub object: String,
     pub data: Vec<Model>,
@@ -61,10 +62,10 @@ pub struct ModelsResponse {
 // === Messages 端点类型 ===
 
 /// 最大思考预算 tokens
-const MAX_BUDGET_TOKENS: i32 = 24576;
+pub(crate) const MAX_BUDGET_TOKENS: i32 = 24576;
 
 /// Thinking 配置
-#[derive(Debug, Deserialize, Clone, Serialize)]
+#[derive(Debug, Deserialize, Clone, Serialize, ToSchema)]
 pub struct Thinking {
     #[serde(rename = "type")]
     pub thinking_type: String,
@@ -94,7 +95,7 @@ where
 }
 
 /// OutputConfig 配置
-#[derive(Debug, Deserialize, Clone, Serialize)]
+#[derive(Debug, Deserialize, Clone, Serialize, ToSchema)]
 pub struct OutputConfig {
     #[serde(default = "default_effort")]
     pub effort: String,
@@ -105,16 +106,18 @@ fn default_effort() -> String {
 }
 
 /// Claude Code 请求中的 metadata
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
 pub struct Metadata {
     /// 用户 ID，格式如: user_xxx_account__session_0b4445e1-f5be-49e1-87ce-62bbc28ad705
     pub user_id: Option<String>,
 }
 
 /// Messages 请求体
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct MessagesRequest {
     pub model: String,
+    /// 未指定时为 0，转换时按 `defaultMaxTokens` 补全
+    #[serde(default)]
     pub max_tokens: i32,
     pub messages: Vec<Message>,
     #[serde(default)]
@@ -152,6 +155,7 @@ where
         {
             Ok(Some(vec![SystemMessage {
                 text: value.to_string(),
+                cache_control: None,
             }]))
         }
 
@@ -189,7 +193,7 @@ where
 }
 
 /// 消息
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
 pub struct Message {
     pub role: String,
     /// 可以是 string 或 ContentBlock 数组
@@ -197,9 +201,22 @@ pub struct Message {
 }
 
 /// 系统消息
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
 pub struct SystemMessage {
     pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+}
+
+/// Prompt Caching 断点标记（`{"type": "ephemeral", "ttl": "5m" | "1h"}`）
+///
+/// 上游不支持提示词缓存，仅用于计算 usage 中的缓存字段
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct CacheControl {
+    #[serde(rename = "type")]
+    pub cache_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<String>,
 }
 
 /// 工具定义
@@ -207,7 +224,7 @@ pub struct SystemMessage {
 /// 支持两种格式：
 /// 1. 普通工具：{ name, description, input_schema }
 /// 2. WebSearch 工具：{ type: "web_search_20250305", name: "web_search", max_uses: 8 }
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
 pub struct Tool {
     /// 工具类型，如 "web_search_20250305"（可选，仅 WebSearch 工具）
     #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
@@ -224,6 +241,9 @@ pub struct Tool {
     /// 最大使用次数（仅 WebSearch 工具）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_uses: Option<i32>,
+    /// Prompt Caching 断点标记
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
 }
 
 impl Tool {
@@ -258,21 +278,63 @@ pub struct ContentBlock {
     pub is_error: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source: Option<ImageSource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
 }
 
 /// 图片数据源
+///
+/// - `base64`: `media_type` + `data`
+/// - `url`: `url`（仅支持 `data:` URL，远程图片需客户端先下载）
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ImageSource {
     #[serde(rename = "type")]
     pub source_type: String,
-    pub media_type: String,
-    pub data: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+// === 请求级特性开关 ===
+
+/// 请求级特性开关，来自查询参数 `?beta=a,b&debug=c`
+///
+/// - beta: `compress-tools`（工具定义超限时压缩描述）、`dedupe-text`（强制折叠重复文本）
+/// - debug: `dry-run`（仅返回转换结果，不请求上游）、`verbose`（在响应头中返回转换摘要）
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct RequestFlags {
+    #[serde(default)]
+    pub beta: Option<String>,
+    #[serde(default)]
+    pub debug: Option<String>,
+}
+
+impl RequestFlags {
+    fn contains(list: &Option<String>, flag: &str) -> bool {
+        list.as_deref().is_some_and(|v| {
+            v.split(',')
+                .any(|item| item.trim().eq_ignore_ascii_case(flag))
+        })
+    }
+
+    /// 是否启用了指定 beta 特性
+    pub fn has_beta(&self, flag: &str) -> bool {
+        Self::contains(&self.beta, flag)
+    }
+
+    /// 是否启用了指定 debug 特性
+    pub fn has_debug(&self, flag: &str) -> bool {
+        Self::contains(&self.debug, flag)
+    }
 }
 
 // === Count Tokens 端点类型 ===
 
 /// Token 计数请求
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CountTokensRequest {
     pub model: String,
     pub messages: Vec<Message>,
@@ -284,10 +346,41 @@ pub struct CountTokensRequest {
     pub system: Option<Vec<SystemMessage>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<Tool>>,
+    /// 与 messages 请求相同，thinking 配置会注入到上游提示词中并计入 token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<Thinking>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_config: Option<OutputConfig>,
+    /// 转换时被忽略（工具定义照常发送），仅为兼容客户端接收
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<serde_json::Value>,
 }
 
 /// Token 计数响应
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CountTokensResponse {
     pub input_tokens: i32,
 }
+
+// === 浏览器临时令牌 ===
+
+/// 浏览器临时令牌签发请求
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct BrowserTokenRequest {
+    /// 有效期（秒），不超过 `browserTokenMaxTtlSecs`，未指定时取上限
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+    /// 绑定的浏览器来源（如 `https://app.example.com`），设置后仅接受该 Origin 的请求
+    #[serde(default)]
+    pub origin: Option<String>,
+}
+
+/// 浏览器临时令牌签发响应
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BrowserTokenResponse {
+    pub token: String,
+    pub expires_in: u64,
+    pub expires_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+}
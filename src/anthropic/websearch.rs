@@ -211,14 +211,21 @@ pub fn parse_search_results(mcp_response: &McpResponse) -> Option<WebSearchResul
 
 /// 生成 WebSearch SSE 响应流
 pub fn create_websearch_sse_stream(
+    message_id: String,
     model: String,
     query: String,
     tool_use_id: String,
     search_results: Option<WebSearchResults>,
     input_tokens: i32,
 ) -> impl Stream<Item = Result<Bytes, Infallible>> {
-    let events =
-        generate_websearch_events(&model, &query, &tool_use_id, search_results, input_tokens);
+    let events = generate_websearch_events(
+        &message_id,
+        &model,
+        &query,
+        &tool_use_id,
+        search_results,
+        input_tokens,
+    );
 
     stream::iter(
         events
@@ -229,6 +236,7 @@ pub fn create_websearch_sse_stream(
 
 /// 生成 WebSearch SSE 事件序列
 fn generate_websearch_events(
+    message_id: &str,
     model: &str,
     query: &str,
     tool_use_id: &str,
@@ -236,10 +244,6 @@ fn generate_websearch_events(
     input_tokens: i32,
 ) -> Vec<SseEvent> {
     let mut events = Vec::new();
-    let message_id = format!(
-        "msg_{}",
-        Uuid::new_v4().to_string().replace('-', "")[..24].to_string()
-    );
 
     // 1. message_start
     events.push(SseEvent::new(
@@ -324,27 +328,32 @@ fn generate_websearch_events(
     ));
 
     // 5. content_block_start (web_search_tool_result, index 2)
-    // 官方 API 的 web_search_tool_result 没有 tool_use_id 字段
-    let search_content = if let Some(ref results) = search_results {
-        results
-            .results
-            .iter()
-            .map(|r| {
-                let page_age = r.published_date.and_then(|ms| {
-                    chrono::DateTime::from_timestamp_millis(ms)
-                        .map(|dt| dt.format("%B %-d, %Y").to_string())
-                });
-                json!({
-                    "type": "web_search_result",
-                    "title": r.title,
-                    "url": r.url,
-                    "encrypted_content": r.snippet.clone().unwrap_or_default(),
-                    "page_age": page_age
+    // 官方 API 的 web_search_tool_result 没有 tool_use_id 字段；搜索失败时 content 为错误对象
+    let search_content = match search_results {
+        Some(ref results) if results.error.is_none() || !results.results.is_empty() => {
+            let items = results
+                .results
+                .iter()
+                .map(|r| {
+                    let page_age = r.published_date.and_then(|ms| {
+                        chrono::DateTime::from_timestamp_millis(ms)
+                            .map(|dt| dt.format("%B %-d, %Y").to_string())
+                    });
+                    json!({
+                        "type": "web_search_result",
+                        "title": r.title,
+                        "url": r.url,
+                        "encrypted_content": r.snippet.clone().unwrap_or_default(),
+                        "page_age": page_age
+                    })
                 })
-            })
-            .collect::<Vec<_>>()
-    } else {
-        vec![]
+                .collect::<Vec<_>>();
+            json!(items)
+        }
+        _ => json!({
+            "type": "web_search_tool_result_error",
+            "error_code": "unavailable"
+        }),
     };
 
     events.push(SseEvent::new(
@@ -368,51 +377,67 @@ fn generate_websearch_events(
         }),
     ));
 
-    // 7. content_block_start (text, index 3)
-    events.push(SseEvent::new(
-        "content_block_start",
-        json!({
-            "type": "content_block_start",
-            "index": 3,
-            "content_block": {
-                "type": "text",
-                "text": ""
-            }
-        }),
-    ));
+    // 7. 搜索结果摘要（text, index 从 3 开始）：每条结果单独一个文本块，
+    // 先通过 citations_delta 发送引用来源，再分块发送文本
+    let blocks = summary_blocks(query, &search_results);
+    let mut summary_len = 0;
+    for (index, block) in (3..).zip(&blocks) {
+        let mut content_block = json!({"type": "text", "text": ""});
+        if block.citation.is_some() {
+            content_block["citations"] = json!([]);
+        }
+        events.push(SseEvent::new(
+            "content_block_start",
+            json!({
+                "type": "content_block_start",
+                "index": index,
+                "content_block": content_block
+            }),
+        ));
 
-    // 8. content_block_delta (text_delta) - 生成搜索结果摘要
-    let summary = generate_search_summary(query, &search_results);
+        if let Some(ref citation) = block.citation {
+            events.push(SseEvent::new(
+                "content_block_delta",
+                json!({
+                    "type": "content_block_delta",
+                    "index": index,
+                    "delta": {
+                        "type": "citations_delta",
+                        "citation": citation
+                    }
+                }),
+            ));
+        }
+
+        let chunk_size = 100;
+        for chunk in block.text.chars().collect::<Vec<_>>().chunks(chunk_size) {
+            let text: String = chunk.iter().collect();
+            events.push(SseEvent::new(
+                "content_block_delta",
+                json!({
+                    "type": "content_block_delta",
+                    "index": index,
+                    "delta": {
+                        "type": "text_delta",
+                        "text": text
+                    }
+                }),
+            ));
+        }
 
-    // 分块发送文本
-    let chunk_size = 100;
-    for chunk in summary.chars().collect::<Vec<_>>().chunks(chunk_size) {
-        let text: String = chunk.iter().collect();
         events.push(SseEvent::new(
-            "content_block_delta",
+            "content_block_stop",
             json!({
-                "type": "content_block_delta",
-                "index": 3,
-                "delta": {
-                    "type": "text_delta",
-                    "text": text
-                }
+                "type": "content_block_stop",
+                "index": index
             }),
         ));
+        summary_len += block.text.len();
     }
 
-    // 9. content_block_stop (text)
-    events.push(SseEvent::new(
-        "content_block_stop",
-        json!({
-            "type": "content_block_stop",
-            "index": 3
-        }),
-    ));
-
-    // 10. message_delta
+    // 8. message_delta
     // 官方 API 的 message_delta.delta 中没有 stop_sequence 字段
-    let output_tokens = (summary.len() as i32 + 3) / 4; // 简单估算
+    let output_tokens = (summary_len as i32 + 3) / 4; // 简单估算
     events.push(SseEvent::new(
         "message_delta",
         json!({
@@ -421,7 +446,10 @@ fn generate_websearch_events(
                 "stop_reason": "end_turn"
             },
             "usage": {
+                "input_tokens": input_tokens,
                 "output_tokens": output_tokens,
+                "cache_creation_input_tokens": 0,
+                "cache_read_input_tokens": 0,
                 "server_tool_use": {
                     "web_search_requests": 1
                 }
@@ -429,7 +457,7 @@ fn generate_websearch_events(
         }),
     ));
 
-    // 11. message_stop
+    // 9. message_stop
     events.push(SseEvent::new(
         "message_stop",
         json!({
@@ -440,30 +468,69 @@ fn generate_websearch_events(
     events
 }
 
-/// 生成搜索结果摘要
-fn generate_search_summary(query: &str, results: &Option<WebSearchResults>) -> String {
-    let mut summary = format!("Here are the search results for \"{}\":\n\n", query);
+/// 搜索结果摘要中的一个文本块
+struct SummaryBlock {
+    text: String,
+    /// 引用的搜索结果（`web_search_result_location`）
+    citation: Option<serde_json::Value>,
+}
+
+/// 按字符数截断文本（安全处理 UTF-8 多字节字符）
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((idx, _)) => format!("{}...", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+/// 生成引用搜索结果的 citation
+///
+/// `encrypted_index` 在官方 API 中是不透明的加密索引，这里用结果序号与 URL 生成占位值
+fn result_citation(index: usize, result: &WebSearchResult) -> serde_json::Value {
+    let cited_text = result.snippet.as_deref().unwrap_or(&result.title);
+    json!({
+        "type": "web_search_result_location",
+        "url": result.url,
+        "title": result.title,
+        "encrypted_index": hex::encode(format!("{}:{}", index, result.url)),
+        "cited_text": truncate_chars(cited_text, 150)
+    })
+}
+
+/// 将搜索结果摘要拆分为文本块，每条结果一个带引用的文本块
+fn summary_blocks(query: &str, results: &Option<WebSearchResults>) -> Vec<SummaryBlock> {
+    let mut intro = format!("Here are the search results for \"{}\":\n\n", query);
+    let mut blocks = Vec::new();
 
     if let Some(results) = results {
         for (i, result) in results.results.iter().enumerate() {
-            summary.push_str(&format!("{}. **{}**\n", i + 1, result.title));
+            let mut text = format!("{}. **{}**\n", i + 1, result.title);
             if let Some(ref snippet) = result.snippet {
-                // 截断过长的摘要（安全处理 UTF-8 多字节字符）
-                let truncated = match snippet.char_indices().nth(200) {
-                    Some((idx, _)) => format!("{}...", &snippet[..idx]),
-                    None => snippet.clone(),
-                };
-                summary.push_str(&format!("   {}\n", truncated));
+                text.push_str(&format!("   {}\n", truncate_chars(snippet, 200)));
             }
-            summary.push_str(&format!("   Source: {}\n\n", result.url));
+            text.push_str(&format!("   Source: {}\n\n", result.url));
+            blocks.push(SummaryBlock {
+                text,
+                citation: Some(result_citation(i, result)),
+            });
         }
-    } else {
-        summary.push_str("No results found.\n");
+    }
+    if blocks.is_empty() {
+        intro.push_str("No results found.\n");
     }
 
-    summary.push_str("\nPlease note that these are web search results and may not be fully accurate or up-to-date.");
-
-    summary
+    blocks.insert(
+        0,
+        SummaryBlock {
+            text: intro,
+            citation: None,
+        },
+    );
+    blocks.push(SummaryBlock {
+        text: "\nPlease note that these are web search results and may not be fully accurate or up-to-date.".to_string(),
+        citation: None,
+    });
+    blocks
 }
 
 /// 处理 WebSearch 请求
@@ -503,8 +570,15 @@ pub async fn handle_websearch_request(
 
     // 4. 生成 SSE 响应
     let model = payload.model.clone();
-    let stream =
-        create_websearch_sse_stream(model, query, tool_use_id, search_results, input_tokens);
+    let message_id = super::message_id::next(&provider.token_manager().config());
+    let stream = create_websearch_sse_stream(
+        message_id,
+        model,
+        query,
+        tool_use_id,
+        search_results,
+        input_tokens,
+    );
 
     Response::builder()
         .status(StatusCode::OK)
@@ -565,6 +639,7 @@ mod tests {
                 description: String::new(),
                 input_schema: Default::default(),
                 max_uses: Some(8),
+                cache_control: None,
             }]),
             tool_choice: None,
             thinking: None,
@@ -595,6 +670,7 @@ mod tests {
                     description: String::new(),
                     input_schema: Default::default(),
                     max_uses: Some(8),
+                    cache_control: None,
                 },
                 Tool {
                     tool_type: None,
@@ -602,6 +678,7 @@ mod tests {
                     description: "Other tool".to_string(),
                     input_schema: Default::default(),
                     max_uses: None,
+                    cache_control: None,
                 },
             ]),
             tool_choice: None,
@@ -748,10 +825,41 @@ mod tests {
             error: None,
         };
 
-        let summary = generate_search_summary("test", &Some(results));
+        let blocks = summary_blocks("test", &Some(results));
+        let summary: String = blocks.iter().map(|b| b.text.as_str()).collect();
 
         assert!(summary.contains("Test Result"));
         assert!(summary.contains("https://example.com"));
         assert!(summary.contains("This is a test snippet"));
+
+        // 每条结果一个带引用的文本块
+        assert_eq!(blocks.len(), 3);
+        let citation = blocks[1].citation.as_ref().unwrap();
+        assert_eq!(citation["type"], "web_search_result_location");
+        assert_eq!(citation["url"], "https://example.com");
+        assert_eq!(citation["cited_text"], "This is a test snippet");
+        assert!(blocks[0].citation.is_none());
+    }
+
+    #[test]
+    fn test_websearch_message_delta_has_usage() {
+        let events =
+            generate_websearch_events("msg_1", "claude-sonnet-4-6", "q", "srvtoolu_1", None, 42);
+        let delta = events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .expect("should have message_delta event");
+        assert_eq!(delta.data["usage"]["input_tokens"], 42);
+        assert!(delta.data["usage"]["output_tokens"].as_i64().unwrap() > 0);
+
+        // 搜索失败时返回错误对象
+        let result = events
+            .iter()
+            .find(|e| e.data["content_block"]["type"] == "web_search_tool_result")
+            .unwrap();
+        assert_eq!(
+            result.data["content_block"]["content"]["error_code"],
+            "unavailable"
+        );
     }
 }
@@ -0,0 +1,1792 @@
+#[cfg(feature = "postgres")]
+mod postgres;
+mod sqlite;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Datelike, SecondsFormat, TimeZone, Utc};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::common::auth;
+use crate::pricing;
+use crate::webhook::KeyWebhook;
+use sqlite::SqliteStore;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyRecord {
+    pub id: String,
+    pub name: String,
+    pub key: String,
+    pub enabled: bool,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub request_count: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// 每分钟请求数上限（None 表示不限制）
+    #[serde(default)]
+    pub rpm_limit: Option<u64>,
+    /// 每分钟 token 数上限（输入 + 输出，None 表示不限制）
+    #[serde(default)]
+    pub tpm_limit: Option<u64>,
+    /// 每日 token 配额（UTC 自然日，None 表示不限制）
+    #[serde(default)]
+    pub daily_token_limit: Option<u64>,
+    /// 每月 token 配额（UTC 自然月，None 表示不限制）
+    #[serde(default)]
+    pub monthly_token_limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyPublicInfo {
+    pub id: String,
+    pub name: String,
+    pub key: String,
+    pub enabled: bool,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub request_count: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub rpm_limit: Option<u64>,
+    pub tpm_limit: Option<u64>,
+    pub daily_token_limit: Option<u64>,
+    pub monthly_token_limit: Option<u64>,
+    /// 当前自然日已用 token
+    pub daily_tokens_used: u64,
+    /// 当前自然月已用 token
+    pub monthly_tokens_used: u64,
+    /// 每日用量下次重置时间（RFC3339）
+    pub daily_reset_at: Option<String>,
+    /// 每月用量下次重置时间（RFC3339）
+    pub monthly_reset_at: Option<String>,
+    /// 历史压缩阈值（上下文使用率百分比，0 表示不压缩，None 表示沿用全局配置）
+    pub compaction_threshold: Option<f64>,
+    /// 允许使用的凭据 API Region（None 表示不限制）
+    pub allowed_regions: Option<Vec<String>>,
+    /// 允许访问的端点范围（None 表示不限制）
+    pub scopes: Option<Vec<ApiKeyScope>>,
+    /// 请求完成回调地址（None 表示不回调）
+    pub webhook_url: Option<String>,
+    /// 累计费用（美元，按模型单价计算）
+    pub spend_usd: f64,
+    /// 当前自然月费用（美元）
+    pub monthly_spend_usd: f64,
+    /// 月度费用预算（美元，None 表示不限制）
+    pub monthly_budget_usd: Option<f64>,
+    /// 请求体大小上限（字节，None 表示沿用全局配置）
+    pub max_body_bytes: Option<u64>,
+    pub key_preview: String,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyUsageOverview {
+    pub total_keys: usize,
+    pub enabled_keys: usize,
+    pub total_requests: u64,
+    pub total_input_tokens: u64,
+    pub total_output_tokens: u64,
+}
+
+/// 终端用户用量（按请求的 `metadata.user_id` 聚合）
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyUserUsage {
+    pub key_id: String,
+    /// 终端用户 ID（Claude Code 的会话后缀已去除）
+    pub user_id: String,
+    pub request_count: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub last_used_at: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthenticatedApiKey {
+    pub key_id: String,
+    pub rpm_limit: Option<u64>,
+    pub tpm_limit: Option<u64>,
+    /// 允许访问的端点范围（None 表示不限制）
+    pub scopes: Option<Vec<ApiKeyScope>>,
+}
+
+impl AuthenticatedApiKey {
+    /// 是否允许访问指定范围的端点
+    pub fn allows(&self, scope: ApiKeyScope) -> bool {
+        self.scopes.as_ref().is_none_or(|s| s.contains(&scope))
+    }
+}
+
+/// API Key 可访问的端点范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyScope {
+    /// 生成内容：`/messages`、消息批处理、`/chat/completions`、Gemini `generateContent`
+    Messages,
+    /// `/messages/count_tokens` 与 Gemini `countTokens`
+    CountTokens,
+    /// `/models`
+    Models,
+}
+
+impl ApiKeyScope {
+    fn as_str(self) -> &'static str {
+        match self {
+            ApiKeyScope::Messages => "messages",
+            ApiKeyScope::CountTokens => "count_tokens",
+            ApiKeyScope::Models => "models",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "messages" => Some(ApiKeyScope::Messages),
+            "count_tokens" => Some(ApiKeyScope::CountTokens),
+            "models" => Some(ApiKeyScope::Models),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ApiKeyScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 解析数据库中逗号分隔的范围，忽略无法识别的值
+fn split_scopes(raw: &str) -> Vec<ApiKeyScope> {
+    raw.split(',')
+        .filter_map(|s| ApiKeyScope::parse(s.trim()))
+        .collect()
+}
+
+/// 内存中的已启用 Key（认证热路径使用）
+struct EnabledKey {
+    key: String,
+    auth: AuthenticatedApiKey,
+    webhook: Option<Arc<KeyWebhook>>,
+    max_body_bytes: Option<u64>,
+    allowed_regions: Option<Vec<String>>,
+    /// 每日 token、每月 token、月度费用（微美元）上限
+    quota_limits: [Option<i64>; 3],
+}
+
+impl EnabledKey {
+    fn from_row(row: KeyRow) -> Self {
+        Self {
+            auth: AuthenticatedApiKey {
+                key_id: row.id,
+                rpm_limit: row.rpm_limit.map(|v| v.max(0) as u64),
+                tpm_limit: row.tpm_limit.map(|v| v.max(0) as u64),
+                scopes: row.scopes.map(|s| split_scopes(&s)),
+            },
+            key: row.key,
+            webhook: match (row.webhook_url, row.webhook_secret) {
+                (Some(url), Some(secret)) => Some(Arc::new(KeyWebhook { url, secret })),
+                _ => None,
+            },
+            max_body_bytes: row.max_body_bytes.map(|v| v.max(0) as u64),
+            allowed_regions: row.allowed_regions.map(|r| split_regions(&r)),
+            quota_limits: [
+                row.daily_token_limit,
+                row.monthly_token_limit,
+                row.monthly_budget_micros,
+            ],
+        }
+    }
+}
+
+/// 已启用 Key 的内存缓存
+struct KeyCache {
+    keys: Arc<Vec<EnabledKey>>,
+    loaded_at: Instant,
+}
+
+/// 限流拒绝原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitKind {
+    Requests,
+    Tokens,
+}
+
+/// 限流拒绝信息
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimited {
+    pub kind: RateLimitKind,
+    /// 建议客户端等待的时长（用于 Retry-After）
+    pub retry_after: Duration,
+}
+
+/// 浏览器临时令牌前缀
+pub const BROWSER_TOKEN_PREFIX: &str = "kbt_";
+
+/// 浏览器临时令牌（仅保存在内存中，重启后失效）
+struct BrowserToken {
+    key_id: String,
+    /// 绑定的来源（请求的 Origin 必须一致）
+    origin: Option<String>,
+    expires_at: Instant,
+}
+
+/// 配额周期
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaPeriod {
+    Daily,
+    Monthly,
+    /// 月度费用预算（`limit` 为微美元）
+    MonthlyBudget,
+}
+
+/// 配额超限信息
+#[derive(Debug, Clone, Copy)]
+pub struct QuotaExceeded {
+    pub period: QuotaPeriod,
+    pub limit: u64,
+    /// 距配额重置的时长（用于 Retry-After）
+    pub retry_after: Duration,
+}
+
+/// 某个周期内的用量（已过重置时间时视为 0）
+struct PeriodUsage {
+    used: u64,
+    reset_at: Option<String>,
+}
+
+impl PeriodUsage {
+    fn effective(used: i64, reset_at: Option<String>, now: &str) -> Self {
+        match reset_at {
+            Some(reset_at) if reset_at.as_str() > now => Self {
+                used: used.max(0) as u64,
+                reset_at: Some(reset_at),
+            },
+            _ => Self {
+                used: 0,
+                reset_at: None,
+            },
+        }
+    }
+}
+
+/// 令牌桶：容量为每分钟上限，按 limit/60 每秒匀速补充
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: u64, now: Instant) -> Self {
+        Self {
+            capacity: limit as f64,
+            tokens: limit as f64,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, limit: u64, now: Instant) {
+        // 上限被修改时同步容量
+        self.capacity = limit as f64;
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.updated_at = now;
+    }
+
+    /// 补充到 `target` 个令牌所需的时间
+    fn wait_for(&self, target: f64) -> Duration {
+        let rate = self.capacity / 60.0;
+        if rate <= 0.0 {
+            return Duration::from_secs(60);
+        }
+        Duration::from_secs_f64(((target - self.tokens) / rate).max(0.0))
+    }
+}
+
+/// 单个 Key 的限流状态
+#[derive(Debug, Default)]
+struct KeyBuckets {
+    requests: Option<TokenBucket>,
+    tokens: Option<TokenBucket>,
+}
+
+/// API Key 存储模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ApiKeyStoreMode {
+    /// 正常读写 api_keys.db
+    Persistent,
+    /// api_keys.db 不可写：已有数据加载到内存，修改在重启后丢失
+    ReadOnly,
+    /// api_keys.db 不可读：仅有配置中的 apiKey，修改在重启后丢失
+    Memory,
+}
+
+/// API Key 存储状态（用于 /readyz 与 Admin API）
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyStoreStatus {
+    pub mode: ApiKeyStoreMode,
+    /// 降级原因（仅降级时）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ApiKeyStoreStatus {
+    pub fn is_degraded(&self) -> bool {
+        self.error.is_some()
+    }
+}
+
+/// 数据库中的一条 API Key 记录
+#[derive(Debug, Clone)]
+pub(crate) struct KeyRow {
+    pub id: String,
+    pub name: String,
+    pub key: String,
+    pub enabled: bool,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub request_count: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub rpm_limit: Option<i64>,
+    pub tpm_limit: Option<i64>,
+    pub daily_token_limit: Option<i64>,
+    pub monthly_token_limit: Option<i64>,
+    pub daily_tokens_used: i64,
+    pub monthly_tokens_used: i64,
+    pub daily_reset_at: Option<String>,
+    pub monthly_reset_at: Option<String>,
+    pub compaction_threshold: Option<f64>,
+    /// 逗号分隔
+    pub allowed_regions: Option<String>,
+    /// 逗号分隔
+    pub scopes: Option<String>,
+    pub webhook_url: Option<String>,
+    pub webhook_secret: Option<String>,
+    pub spend_micros: i64,
+    pub monthly_spend_micros: i64,
+    pub monthly_budget_micros: Option<i64>,
+    pub max_body_bytes: Option<i64>,
+}
+
+/// 待写入字段的值（NULL 也带类型，以便写入强类型数据库的任意列）
+#[derive(Debug, Clone)]
+pub(crate) enum FieldValue {
+    Bool(bool),
+    Int(Option<i64>),
+    Real(Option<f64>),
+    Text(Option<String>),
+}
+
+impl From<Option<u64>> for FieldValue {
+    fn from(value: Option<u64>) -> Self {
+        FieldValue::Int(value.map(|v| v as i64))
+    }
+}
+
+impl From<Option<String>> for FieldValue {
+    fn from(value: Option<String>) -> Self {
+        FieldValue::Text(value)
+    }
+}
+
+/// 一次请求的用量（时间戳均已格式化）
+pub(crate) struct UsageRecord<'a> {
+    pub key_id: &'a str,
+    /// 终端用户 ID（已去除会话后缀）
+    pub user_id: Option<&'a str>,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cost_micros: i64,
+    /// 最近使用时间（RFC3339）
+    pub used_at: String,
+    /// 配额时间戳格式的当前时间，用于判断周期是否已重置
+    pub period_now: String,
+    pub next_day_start: String,
+    pub next_month_start: String,
+}
+
+/// API Key 存储后端
+///
+/// 只负责读写数据；认证缓存、限流与配额判断在 [`ApiKeyManager`] 中完成，与后端无关。
+/// 写入失败时静默忽略（与请求处理解耦），由各后端自行记录日志。
+pub(crate) trait ApiKeyStore: Send + Sync {
+    /// 全部记录（读取失败时返回错误，调用方不能把失败当作没有记录）
+    fn keys(&self) -> anyhow::Result<Vec<KeyRow>>;
+
+    /// 按 ID 读取记录
+    fn key(&self, id: &str) -> Option<KeyRow>;
+
+    /// 新增已启用的记录，ID 或 Key 已存在时不做任何事，返回是否插入
+    fn insert_key(&self, id: &str, name: &str, key: &str, created_at: &str) -> bool;
+
+    /// 更新字段（列名由调用方保证合法），返回记录是否存在
+    fn update(&self, id: &str, fields: &[(&str, FieldValue)]) -> bool;
+
+    /// 更新最近使用时间（只前进不后退）
+    fn touch(&self, id: &str, last_used_at: &str);
+
+    /// 累计一次请求的用量、费用与周期用量
+    fn record_usage(&self, usage: &UsageRecord<'_>);
+
+    /// 终端用户用量，按 token 总量降序；`key_id` 为 None 时返回全部
+    fn user_usage(&self, key_id: Option<&str>) -> Vec<ApiKeyUserUsage>;
+
+    /// 删除记录及其终端用户用量，返回记录是否存在
+    fn delete_key(&self, id: &str) -> bool;
+
+    /// 是否与其他实例共享（共享时定期刷新认证缓存，以感知其他实例的修改）
+    fn shared(&self) -> bool {
+        false
+    }
+}
+
+/// 共享存储时认证缓存的有效期
+const SHARED_KEY_CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// 按连接串打开外部数据库（目前仅支持 PostgreSQL，需启用 `postgres` 特性）
+fn open_database(database_url: &str) -> anyhow::Result<Box<dyn ApiKeyStore>> {
+    if !database_url.starts_with("postgres://") && !database_url.starts_with("postgresql://") {
+        anyhow::bail!("不支持的数据库连接串，仅支持 postgres://");
+    }
+    #[cfg(feature = "postgres")]
+    {
+        Ok(Box::new(postgres::PgStore::connect(database_url)?))
+    }
+    #[cfg(not(feature = "postgres"))]
+    {
+        anyhow::bail!("当前构建未启用 postgres 特性")
+    }
+}
+
+pub struct ApiKeyManager {
+    store: Box<dyn ApiKeyStore>,
+    buckets: Mutex<HashMap<String, KeyBuckets>>,
+    browser_tokens: Mutex<HashMap<String, BrowserToken>>,
+    store_status: ApiKeyStoreStatus,
+    /// 已启用 Key 的内存缓存，Key 被修改后清空，下次认证时从数据库重建
+    enabled_keys: RwLock<Option<KeyCache>>,
+    /// 认证时记录、尚未写入数据库的最近使用时间（Key ID -> RFC3339）
+    pending_last_used: Mutex<HashMap<String, String>>,
+    /// 配置中的 apiKey（即使对应记录被删除，重启后也会重新写入，自定义 Key 不能与其重复）
+    master_key: String,
+}
+
+impl ApiKeyManager {
+    /// 创建 API Key 管理器，数据库不可用时降级运行（见 [`ApiKeyStoreMode`]）而不是退出
+    pub fn new(initial_key: String, store_path: Option<PathBuf>) -> Self {
+        let (store, store_status) = SqliteStore::open(store_path.as_deref());
+        Self::with_store(initial_key, Box::new(store), store_status)
+    }
+
+    /// 使用外部数据库（`databaseUrl`）创建，多个实例可共享同一份数据；
+    /// 连接失败时与 SQLite 不可读时一样降级为内存模式
+    pub fn connect(initial_key: String, database_url: &str) -> Self {
+        match open_database(database_url) {
+            Ok(store) => {
+                let status = ApiKeyStoreStatus {
+                    mode: ApiKeyStoreMode::Persistent,
+                    error: None,
+                };
+                Self::with_store(initial_key, store, status)
+            }
+            Err(e) => {
+                tracing::error!(
+                    "API Key 数据库连接失败: {:#}；仅使用配置中的 apiKey，所有修改在重启后丢失",
+                    e
+                );
+                let status = ApiKeyStoreStatus {
+                    mode: ApiKeyStoreMode::Memory,
+                    error: Some(format!("{:#}", e)),
+                };
+                Self::with_store(initial_key, Box::new(SqliteStore::memory()), status)
+            }
+        }
+    }
+
+    fn with_store(
+        initial_key: String,
+        store: Box<dyn ApiKeyStore>,
+        store_status: ApiKeyStoreStatus,
+    ) -> Self {
+        let manager = Self {
+            store,
+            buckets: Mutex::new(HashMap::new()),
+            browser_tokens: Mutex::new(HashMap::new()),
+            store_status,
+            enabled_keys: RwLock::new(None),
+            pending_last_used: Mutex::new(HashMap::new()),
+            master_key: initial_key.clone(),
+        };
+
+        // 确保 initial_key 存在（常量时间比较）
+        let keys = match manager.store.keys() {
+            Ok(keys) => keys,
+            Err(e) => {
+                tracing::error!("读取 API Key 失败，跳过配置 apiKey 的检查: {:#}", e);
+                return manager;
+            }
+        };
+        let name = if keys.is_empty() {
+            Some("Default")
+        } else if !initial_key.trim().is_empty()
+            && !keys
+                .iter()
+                .any(|k| auth::constant_time_eq(k.key.as_str(), initial_key.as_str()))
+        {
+            Some("Config API Key")
+        } else {
+            None
+        };
+        if let Some(name) = name {
+            manager.store.insert_key(
+                &Uuid::new_v4().to_string(),
+                name,
+                &initial_key,
+                &Utc::now().to_rfc3339(),
+            );
+        }
+
+        manager
+    }
+
+    /// 存储状态
+    pub fn store_status(&self) -> ApiKeyStoreStatus {
+        self.store_status.clone()
+    }
+
+    pub fn authenticate(&self, incoming: &str) -> Option<AuthenticatedApiKey> {
+        let keys = self.enabled_keys();
+        let entry = keys
+            .iter()
+            .find(|k| auth::constant_time_eq(k.key.as_str(), incoming))?;
+        self.pending_last_used
+            .lock()
+            .insert(entry.auth.key_id.clone(), Utc::now().to_rfc3339());
+        Some(entry.auth.clone())
+    }
+
+    /// 按 ID 获取已启用 Key 的认证信息（浏览器令牌使用）
+    fn authenticate_id(&self, key_id: &str) -> Option<AuthenticatedApiKey> {
+        self.enabled_key(key_id, |k| k.auth.clone())
+    }
+
+    /// 从已启用 Key 缓存中读取指定 Key 的信息，请求路径上不访问数据库
+    fn enabled_key<T>(&self, key_id: &str, f: impl FnOnce(&EnabledKey) -> T) -> Option<T> {
+        self.enabled_keys()
+            .iter()
+            .find(|k| k.auth.key_id == key_id)
+            .map(f)
+    }
+
+    /// 已启用 Key 列表，缓存为空（或共享存储的缓存已过期）时从数据库加载
+    ///
+    /// 加载失败时继续使用已有缓存（下一个有效期后再重试），没有缓存时本次返回空列表且不缓存
+    fn enabled_keys(&self) -> Arc<Vec<EnabledKey>> {
+        let fresh = |cache: &KeyCache| {
+            !self.store.shared() || cache.loaded_at.elapsed() < SHARED_KEY_CACHE_TTL
+        };
+        if let Some(cache) = self.enabled_keys.read().as_ref().filter(|c| fresh(c)) {
+            return cache.keys.clone();
+        }
+        // 持有写锁加载，保证与并发的失效操作有序
+        let mut cache = self.enabled_keys.write();
+        if let Some(cache) = cache.as_ref().filter(|c| fresh(c)) {
+            return cache.keys.clone();
+        }
+        let rows = match self.store.keys() {
+            Ok(rows) => rows,
+            Err(e) => {
+                tracing::warn!("加载 API Key 失败，继续使用已有认证缓存: {:#}", e);
+                return match cache.as_mut() {
+                    Some(stale) => {
+                        stale.loaded_at = Instant::now();
+                        stale.keys.clone()
+                    }
+                    None => Arc::new(Vec::new()),
+                };
+            }
+        };
+        let keys: Arc<Vec<EnabledKey>> = Arc::new(
+            rows.into_iter()
+                .filter(|row| row.enabled)
+                .map(EnabledKey::from_row)
+                .collect(),
+        );
+        *cache = Some(KeyCache {
+            keys: keys.clone(),
+            loaded_at: Instant::now(),
+        });
+        keys
+    }
+
+    /// 清空已启用 Key 缓存（不能在持有数据库锁时调用）
+    pub(crate) fn invalidate_key_cache(&self) {
+        *self.enabled_keys.write() = None;
+    }
+
+    /// 将认证时记录的最近使用时间写入数据库
+    fn flush_last_used(&self) {
+        let pending = std::mem::take(&mut *self.pending_last_used.lock());
+        for (id, last_used_at) in pending {
+            self.store.touch(&id, &last_used_at);
+        }
+    }
+
+    /// 外部身份（JWT）认证：按身份映射到一条 API Key 记录，首次出现时自动创建
+    ///
+    /// 记录的 Key 值随机生成且不对外展示；claims 中带有限流配置时优先于记录上的配置
+    pub fn authenticate_external(
+        &self,
+        subject: &str,
+        rpm_limit: Option<u64>,
+        tpm_limit: Option<u64>,
+    ) -> Option<AuthenticatedApiKey> {
+        let id = format!("jwt:{}", subject);
+        // 已有记录时直接命中认证缓存，只在首次出现（或记录被禁用）时才写数据库
+        let mut authed = match self.authenticate_id(&id) {
+            Some(authed) => authed,
+            None => {
+                let inserted = self.store.insert_key(
+                    &id,
+                    &format!("JWT {}", subject),
+                    &format!("jwt-{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+                    &Utc::now().to_rfc3339(),
+                );
+                if !inserted {
+                    // 记录已存在但已禁用（或写入失败）
+                    return None;
+                }
+                self.invalidate_key_cache();
+                self.authenticate_id(&id)?
+            }
+        };
+        authed.rpm_limit = rpm_limit.or(authed.rpm_limit);
+        authed.tpm_limit = tpm_limit.or(authed.tpm_limit);
+        Some(authed)
+    }
+
+    /// 为指定 Key 签发浏览器临时令牌
+    ///
+    /// 令牌继承该 Key 的限流与配额，Key 被禁用或删除后立即失效
+    pub fn issue_browser_token(
+        &self,
+        key_id: &str,
+        ttl: Duration,
+        origin: Option<String>,
+    ) -> String {
+        let token = format!(
+            "{}{}{}",
+            BROWSER_TOKEN_PREFIX,
+            Uuid::new_v4().simple(),
+            Uuid::new_v4().simple()
+        );
+        let now = Instant::now();
+        let mut tokens = self.browser_tokens.lock();
+        tokens.retain(|_, t| t.expires_at > now);
+        tokens.insert(
+            token.clone(),
+            BrowserToken {
+                key_id: key_id.to_string(),
+                origin,
+                expires_at: now + ttl,
+            },
+        );
+        token
+    }
+
+    /// 校验浏览器临时令牌（有效期与绑定来源）
+    pub fn authenticate_browser_token(
+        &self,
+        token: &str,
+        origin: Option<&str>,
+    ) -> Option<AuthenticatedApiKey> {
+        let key_id = {
+            let mut tokens = self.browser_tokens.lock();
+            let entry = tokens.get(token)?;
+            if entry.expires_at <= Instant::now() {
+                tokens.remove(token);
+                return None;
+            }
+            if entry
+                .origin
+                .as_deref()
+                .is_some_and(|bound| Some(bound) != origin)
+            {
+                return None;
+            }
+            entry.key_id.clone()
+        };
+        self.authenticate_id(&key_id)
+    }
+
+    /// 检查并消耗一次请求配额
+    ///
+    /// RPM 按请求消耗令牌；TPM 在请求完成后由 `record_usage` 扣减，
+    /// 桶内令牌耗尽（≤ 0）时拒绝新请求，直到补充为正数
+    pub fn check_rate_limit(&self, key: &AuthenticatedApiKey) -> Result<(), RateLimited> {
+        if key.rpm_limit.is_none() && key.tpm_limit.is_none() {
+            self.buckets.lock().remove(&key.key_id);
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock();
+        let entry = buckets.entry(key.key_id.clone()).or_default();
+
+        let mut requests = sync_bucket(entry.requests.take(), key.rpm_limit, now);
+        let tokens = sync_bucket(entry.tokens.take(), key.tpm_limit, now);
+
+        let result = if let Some(bucket) = tokens.as_ref().filter(|b| b.tokens <= 0.0) {
+            // 至少补充到 1 个 token 才放行，避免边界抖动
+            Err(RateLimited {
+                kind: RateLimitKind::Tokens,
+                retry_after: bucket.wait_for(1.0),
+            })
+        } else if let Some(bucket) = requests.as_mut() {
+            if bucket.tokens >= 1.0 {
+                bucket.tokens -= 1.0;
+                Ok(())
+            } else {
+                Err(RateLimited {
+                    kind: RateLimitKind::Requests,
+                    retry_after: bucket.wait_for(1.0),
+                })
+            }
+        } else {
+            Ok(())
+        };
+
+        entry.requests = requests;
+        entry.tokens = tokens;
+        result
+    }
+
+    /// 检查每日 / 每月 token 配额与月度费用预算，已用量达到上限时拒绝（硬截断，直到周期重置）
+    ///
+    /// 上限取自已启用 Key 缓存，未设置任何配额的 Key 不访问数据库；
+    /// 已用量随每次请求变化，设置了配额时才从数据库读取
+    pub fn check_quota(&self, key_id: &str) -> Result<(), QuotaExceeded> {
+        let Some([daily, monthly, budget]) = self.enabled_key(key_id, |k| k.quota_limits) else {
+            return Ok(());
+        };
+        if daily.is_none() && monthly.is_none() && budget.is_none() {
+            return Ok(());
+        }
+        let now = Utc::now();
+        let now_str = quota_timestamp(now);
+        let Some(row) = self.store.key(key_id) else {
+            return Ok(());
+        };
+
+        let periods = [
+            (
+                QuotaPeriod::Daily,
+                daily,
+                row.daily_tokens_used,
+                row.daily_reset_at,
+            ),
+            (
+                QuotaPeriod::Monthly,
+                monthly,
+                row.monthly_tokens_used,
+                row.monthly_reset_at.clone(),
+            ),
+            (
+                QuotaPeriod::MonthlyBudget,
+                budget,
+                row.monthly_spend_micros,
+                row.monthly_reset_at,
+            ),
+        ];
+        for (period, limit, used, reset_at) in periods {
+            let Some(limit) = limit.map(|v| v.max(0) as u64) else {
+                continue;
+            };
+            let usage = PeriodUsage::effective(used, reset_at, &now_str);
+            if usage.used >= limit {
+                let retry_after = usage
+                    .reset_at
+                    .and_then(|r| DateTime::parse_from_rfc3339(&r).ok())
+                    .and_then(|r| (r.with_timezone(&Utc) - now).to_std().ok())
+                    .unwrap_or(Duration::from_secs(60));
+                return Err(QuotaExceeded {
+                    period,
+                    limit,
+                    retry_after,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// 记录一次请求的用量与费用，`user_id` 为请求的 `metadata.user_id`
+    pub fn record_usage(
+        &self,
+        key_id: &str,
+        user_id: Option<&str>,
+        model: &str,
+        input_tokens: u64,
+        output_tokens: u64,
+    ) {
+        let cost = pricing::cost_micros(model, input_tokens, output_tokens) as i64;
+        if let Some(bucket) = self
+            .buckets
+            .lock()
+            .get_mut(key_id)
+            .and_then(|b| b.tokens.as_mut())
+        {
+            bucket.tokens -= (input_tokens + output_tokens) as f64;
+        }
+
+        self.pending_last_used.lock().remove(key_id);
+        let now = Utc::now();
+        self.store.record_usage(&UsageRecord {
+            key_id,
+            user_id: user_id.map(end_user_id).filter(|u| !u.is_empty()),
+            input_tokens: input_tokens as i64,
+            output_tokens: output_tokens as i64,
+            cost_micros: cost,
+            used_at: now.to_rfc3339(),
+            period_now: quota_timestamp(now),
+            next_day_start: quota_timestamp(next_day_start(now)),
+            next_month_start: quota_timestamp(next_month_start(now)),
+        });
+    }
+
+    /// 全部记录（Admin 展示用，读取失败时记录日志并返回空列表）
+    fn all_keys(&self) -> Vec<KeyRow> {
+        self.store.keys().unwrap_or_else(|e| {
+            tracing::warn!("读取 API Key 失败: {:#}", e);
+            Vec::new()
+        })
+    }
+
+    pub fn get_name_by_id(&self, key_id: &str) -> Option<String> {
+        self.store.key(key_id).map(|row| row.name)
+    }
+
+    pub fn list(&self) -> Vec<ApiKeyPublicInfo> {
+        self.flush_last_used();
+        let now = quota_timestamp(Utc::now());
+        self.all_keys()
+            .into_iter()
+            .map(|row| {
+                let daily =
+                    PeriodUsage::effective(row.daily_tokens_used, row.daily_reset_at.clone(), &now);
+                let monthly = PeriodUsage::effective(
+                    row.monthly_tokens_used,
+                    row.monthly_reset_at.clone(),
+                    &now,
+                );
+                let monthly_spend =
+                    PeriodUsage::effective(row.monthly_spend_micros, row.monthly_reset_at, &now);
+                ApiKeyPublicInfo {
+                    key_preview: preview_key(&row.key),
+                    id: row.id,
+                    name: row.name,
+                    key: row.key,
+                    enabled: row.enabled,
+                    created_at: row.created_at,
+                    last_used_at: row.last_used_at,
+                    request_count: row.request_count.max(0) as u64,
+                    input_tokens: row.input_tokens.max(0) as u64,
+                    output_tokens: row.output_tokens.max(0) as u64,
+                    rpm_limit: row.rpm_limit.map(|v| v.max(0) as u64),
+                    tpm_limit: row.tpm_limit.map(|v| v.max(0) as u64),
+                    daily_token_limit: row.daily_token_limit.map(|v| v.max(0) as u64),
+                    monthly_token_limit: row.monthly_token_limit.map(|v| v.max(0) as u64),
+                    daily_tokens_used: daily.used,
+                    monthly_tokens_used: monthly.used,
+                    daily_reset_at: daily.reset_at,
+                    monthly_reset_at: monthly.reset_at,
+                    compaction_threshold: row.compaction_threshold,
+                    allowed_regions: row.allowed_regions.map(|r| split_regions(&r)),
+                    scopes: row.scopes.map(|s| split_scopes(&s)),
+                    webhook_url: row.webhook_url,
+                    spend_usd: pricing::to_usd(row.spend_micros.max(0) as u64),
+                    monthly_spend_usd: pricing::to_usd(monthly_spend.used),
+                    monthly_budget_usd: row
+                        .monthly_budget_micros
+                        .map(|v| pricing::to_usd(v.max(0) as u64)),
+                    max_body_bytes: row.max_body_bytes.map(|v| v.max(0) as u64),
+                }
+            })
+            .collect()
+    }
+
+    pub fn overview(&self) -> ApiKeyUsageOverview {
+        let keys = self.all_keys();
+        ApiKeyUsageOverview {
+            total_keys: keys.len(),
+            enabled_keys: keys.iter().filter(|k| k.enabled).count(),
+            total_requests: keys.iter().map(|k| k.request_count.max(0) as u64).sum(),
+            total_input_tokens: keys.iter().map(|k| k.input_tokens.max(0) as u64).sum(),
+            total_output_tokens: keys.iter().map(|k| k.output_tokens.max(0) as u64).sum(),
+        }
+    }
+
+    /// 按终端用户聚合的用量，按 token 总量降序；`key_id` 为 None 时返回全部 API Key
+    pub fn user_usage(&self, key_id: Option<&str>) -> Vec<ApiKeyUserUsage> {
+        self.store.user_usage(key_id)
+    }
+
+    pub fn create_key(&self, name: String) -> ApiKeyRecord {
+        let raw = format!("sk-kiro-rs-{}", Uuid::new_v4().simple());
+        let item = new_record(name, raw);
+        self.insert_record(&item);
+        item
+    }
+
+    /// 使用调用方提供的 Key 创建（从其他网关迁移时沿用原有 Key），
+    /// 校验格式与强度，且不能与配置中的 apiKey 或已有 Key 重复
+    ///
+    /// 重复由数据库的唯一约束判定（插入被忽略），并发导入相同的 Key 时只有一个成功
+    pub fn create_key_with_value(&self, name: String, key: String) -> anyhow::Result<ApiKeyRecord> {
+        validate_custom_key(&key)?;
+        if auth::constant_time_eq(self.master_key.as_str(), key.as_str()) {
+            anyhow::bail!("Key 已存在");
+        }
+        let item = new_record(name, key);
+        if !self.insert_record(&item) {
+            anyhow::bail!("Key 已存在");
+        }
+        Ok(item)
+    }
+
+    /// 写入新记录，返回是否插入（Key 与已有记录重复或写入失败时为 false）
+    fn insert_record(&self, item: &ApiKeyRecord) -> bool {
+        let inserted = self
+            .store
+            .insert_key(&item.id, &item.name, &item.key, &item.created_at);
+        self.invalidate_key_cache();
+        inserted
+    }
+
+    pub fn set_enabled(&self, id: &str, enabled: bool) -> bool {
+        let changed = self
+            .store
+            .update(id, &[("enabled", FieldValue::Bool(enabled))]);
+        self.invalidate_key_cache();
+        changed
+    }
+
+    /// 设置限流上限（None 表示不限制）
+    pub fn set_limits(&self, id: &str, rpm_limit: Option<u64>, tpm_limit: Option<u64>) -> bool {
+        let changed = self.store.update(
+            id,
+            &[
+                ("rpm_limit", rpm_limit.into()),
+                ("tpm_limit", tpm_limit.into()),
+            ],
+        );
+        self.invalidate_key_cache();
+        changed
+    }
+
+    /// 设置每日 / 每月 token 配额（None 表示不限制）
+    pub fn set_quota(
+        &self,
+        id: &str,
+        daily_token_limit: Option<u64>,
+        monthly_token_limit: Option<u64>,
+    ) -> bool {
+        let changed = self.store.update(
+            id,
+            &[
+                ("daily_token_limit", daily_token_limit.into()),
+                ("monthly_token_limit", monthly_token_limit.into()),
+            ],
+        );
+        self.invalidate_key_cache();
+        changed
+    }
+
+    /// 设置月度费用预算（微美元，None 表示不限制）
+    pub fn set_budget(&self, id: &str, monthly_budget_micros: Option<u64>) -> bool {
+        let changed = self.store.update(
+            id,
+            &[("monthly_budget_micros", monthly_budget_micros.into())],
+        );
+        self.invalidate_key_cache();
+        changed
+    }
+
+    /// 设置请求体大小上限（字节，None 表示沿用全局配置）
+    pub fn set_max_body_bytes(&self, id: &str, max_body_bytes: Option<u64>) -> bool {
+        let changed = self
+            .store
+            .update(id, &[("max_body_bytes", max_body_bytes.into())]);
+        self.invalidate_key_cache();
+        changed
+    }
+
+    /// 读取已启用 Key 的请求体大小上限，未设置时返回 None
+    pub fn max_body_bytes(&self, key_id: &str) -> Option<u64> {
+        self.enabled_key(key_id, |k| k.max_body_bytes).flatten()
+    }
+
+    /// 设置历史压缩阈值（None 表示沿用全局配置）
+    pub fn set_compaction_threshold(&self, id: &str, threshold: Option<f64>) -> bool {
+        self.store
+            .update(id, &[("compaction_threshold", FieldValue::Real(threshold))])
+    }
+
+    /// 读取 API Key 的历史压缩阈值，未设置时返回 None
+    pub fn compaction_threshold(&self, key_id: &str) -> Option<f64> {
+        self.store.key(key_id)?.compaction_threshold
+    }
+
+    /// 设置允许使用的凭据 API Region（None 表示不限制）
+    pub fn set_allowed_regions(&self, id: &str, regions: Option<&[String]>) -> bool {
+        let changed = self.store.update(
+            id,
+            &[("allowed_regions", regions.map(|r| r.join(",")).into())],
+        );
+        self.invalidate_key_cache();
+        changed
+    }
+
+    /// 设置允许访问的端点范围（None 表示不限制）
+    pub fn set_scopes(&self, id: &str, scopes: Option<&[ApiKeyScope]>) -> bool {
+        let scopes = scopes.map(|s| s.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(","));
+        let changed = self.store.update(id, &[("scopes", scopes.into())]);
+        self.invalidate_key_cache();
+        changed
+    }
+
+    /// 设置请求完成回调（None 表示不回调）
+    pub fn set_webhook(&self, id: &str, webhook: Option<&KeyWebhook>) -> bool {
+        let changed = self.store.update(
+            id,
+            &[
+                ("webhook_url", webhook.map(|w| w.url.clone()).into()),
+                ("webhook_secret", webhook.map(|w| w.secret.clone()).into()),
+            ],
+        );
+        self.invalidate_key_cache();
+        changed
+    }
+
+    /// 已启用 Key 的请求完成回调
+    pub fn webhook(&self, key_id: &str) -> Option<Arc<KeyWebhook>> {
+        self.enabled_key(key_id, |k| k.webhook.clone()).flatten()
+    }
+
+    /// 读取已启用 Key 允许使用的凭据 API Region，未限制时返回 None
+    pub fn allowed_regions(&self, key_id: &str) -> Option<Vec<String>> {
+        self.enabled_key(key_id, |k| k.allowed_regions.clone())
+            .flatten()
+    }
+
+    /// 清零当前周期的配额用量（下次记录用量时重新开始计算周期）
+    pub fn reset_quota_usage(&self, id: &str) -> bool {
+        self.store.update(
+            id,
+            &[
+                ("daily_tokens_used", FieldValue::Int(Some(0))),
+                ("monthly_tokens_used", FieldValue::Int(Some(0))),
+                ("monthly_spend_micros", FieldValue::Int(Some(0))),
+                ("daily_reset_at", FieldValue::Text(None)),
+                ("monthly_reset_at", FieldValue::Text(None)),
+            ],
+        )
+    }
+
+    pub fn delete_key(&self, id: &str) -> bool {
+        let changed = self.store.delete_key(id);
+        if changed {
+            self.buckets.lock().remove(id);
+            self.pending_last_used.lock().remove(id);
+        }
+        self.invalidate_key_cache();
+        changed
+    }
+}
+
+/// 新建的已启用记录（随机 ID，无用量与限制）
+fn new_record(name: String, key: String) -> ApiKeyRecord {
+    ApiKeyRecord {
+        id: Uuid::new_v4().to_string(),
+        name,
+        key,
+        enabled: true,
+        created_at: Utc::now().to_rfc3339(),
+        last_used_at: None,
+        request_count: 0,
+        input_tokens: 0,
+        output_tokens: 0,
+        rpm_limit: None,
+        tpm_limit: None,
+        daily_token_limit: None,
+        monthly_token_limit: None,
+    }
+}
+
+/// 终端用户 ID：去掉 Claude Code `metadata.user_id` 的 `_session_<uuid>` 后缀，
+/// 使同一用户的多个会话合并统计
+fn end_user_id(user_id: &str) -> &str {
+    match user_id.split_once("_session_") {
+        Some((user, _)) => user.trim_end_matches('_'),
+        None => user_id,
+    }
+}
+
+/// 按当前上限补充令牌桶；上限被移除时丢弃桶
+fn sync_bucket(
+    bucket: Option<TokenBucket>,
+    limit: Option<u64>,
+    now: Instant,
+) -> Option<TokenBucket> {
+    let limit = limit?;
+    let mut bucket = bucket.unwrap_or_else(|| TokenBucket::new(limit, now));
+    bucket.refill(limit, now);
+    Some(bucket)
+}
+
+/// 配额时间戳统一使用秒精度 UTC（`Z` 结尾），保证字符串比较与时间顺序一致
+fn quota_timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// 下一个 UTC 自然日的开始时间
+fn next_day_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    let tomorrow = now.date_naive() + chrono::Days::new(1);
+    Utc.from_utc_datetime(&tomorrow.and_hms_opt(0, 0, 0).unwrap_or_default())
+}
+
+/// 下一个 UTC 自然月的开始时间
+fn next_month_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    let (year, month) = if now.month() == 12 {
+        (now.year() + 1, 1)
+    } else {
+        (now.year(), now.month() + 1)
+    };
+    Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0)
+        .single()
+        .unwrap_or_else(|| next_day_start(now))
+}
+
+/// 自定义 Key 必须使用的前缀
+const CUSTOM_KEY_PREFIX: &str = "sk-";
+/// 自定义 Key 的长度范围
+const CUSTOM_KEY_MIN_LEN: usize = 32;
+const CUSTOM_KEY_MAX_LEN: usize = 256;
+/// 自定义 Key 前缀之后部分的估算熵下限（bit）
+const CUSTOM_KEY_MIN_ENTROPY_BITS: f64 = 100.0;
+
+/// 校验自定义 Key：`sk-` 前缀、可见 ASCII 字符、长度与按字符频率估算的熵
+pub fn validate_custom_key(key: &str) -> anyhow::Result<()> {
+    let Some(body) = key.strip_prefix(CUSTOM_KEY_PREFIX) else {
+        anyhow::bail!("Key 必须以 {} 开头", CUSTOM_KEY_PREFIX);
+    };
+    if !key.bytes().all(|b| b.is_ascii_graphic()) {
+        anyhow::bail!("Key 只能包含可见 ASCII 字符");
+    }
+    if !(CUSTOM_KEY_MIN_LEN..=CUSTOM_KEY_MAX_LEN).contains(&key.len()) {
+        anyhow::bail!(
+            "Key 长度必须为 {}-{} 个字符",
+            CUSTOM_KEY_MIN_LEN,
+            CUSTOM_KEY_MAX_LEN
+        );
+    }
+    let mut counts = HashMap::new();
+    for b in body.bytes() {
+        *counts.entry(b).or_insert(0usize) += 1;
+    }
+    let len = body.len() as f64;
+    let entropy: f64 = counts
+        .values()
+        .map(|&n| {
+            let p = n as f64 / len;
+            -p * p.log2() * len
+        })
+        .sum();
+    if entropy < CUSTOM_KEY_MIN_ENTROPY_BITS {
+        anyhow::bail!("Key 随机性不足，请使用随机生成的 Key");
+    }
+    Ok(())
+}
+
+pub(crate) fn preview_key(raw: &str) -> String {
+    let len = raw.len();
+    if len <= 8 {
+        return "********".to_string();
+    }
+    format!("{}****{}", &raw[..4], &raw[len.saturating_sub(4)..])
+}
+
+/// 解析逗号分隔的地域列表
+fn split_regions(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    fn limited_key(
+        manager: &ApiKeyManager,
+        rpm: Option<u64>,
+        tpm: Option<u64>,
+    ) -> AuthenticatedApiKey {
+        let record = manager.create_key("test".to_string());
+        assert!(manager.set_limits(&record.id, rpm, tpm));
+        manager.authenticate(&record.key).unwrap()
+    }
+
+    #[test]
+    fn test_unusable_store_degrades_instead_of_panicking() {
+        let dir = std::env::temp_dir().join(format!("kiro-apikeys-{}", Uuid::new_v4()));
+        let db_path = dir.join("api_keys.db");
+        let created = {
+            let manager = ApiKeyManager::new("sk-initial".to_string(), Some(db_path.clone()));
+            assert_eq!(manager.store_status().mode, ApiKeyStoreMode::Persistent);
+            manager.create_key("saved".to_string())
+        };
+
+        // 只读加载保留已有的 API Key
+        let conn = sqlite::memory_connection();
+        assert_eq!(sqlite::load_read_only(&db_path, &conn).unwrap(), 2);
+
+        // 路径是目录时无法打开，降级为内存模式，配置中的 apiKey 仍可用
+        let manager = ApiKeyManager::new("sk-initial".to_string(), Some(dir.clone()));
+        let status = manager.store_status();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(status.mode, ApiKeyStoreMode::Memory);
+        assert!(status.is_degraded());
+        assert!(manager.authenticate("sk-initial").is_some());
+        assert!(manager.authenticate(&created.key).is_none());
+    }
+
+    #[test]
+    fn test_key_cache_follows_admin_mutations() {
+        let manager = ApiKeyManager::new("sk-initial".to_string(), None);
+        let record = manager.create_key("cached".to_string());
+        assert!(manager.authenticate(&record.key).is_some());
+        // 认证不写数据库，最近使用时间在列表查询时写入
+        assert!(manager.pending_last_used.lock().contains_key(&record.id));
+        let listed = manager.list();
+        let listed = listed.iter().find(|k| k.id == record.id).unwrap();
+        assert!(listed.last_used_at.is_some());
+
+        assert!(manager.set_limits(&record.id, Some(5), None));
+        assert_eq!(
+            manager.authenticate(&record.key).unwrap().rpm_limit,
+            Some(5)
+        );
+        assert!(manager.set_enabled(&record.id, false));
+        assert!(manager.authenticate(&record.key).is_none());
+        assert!(manager.set_enabled(&record.id, true));
+        assert!(manager.delete_key(&record.id));
+        assert!(manager.authenticate(&record.key).is_none());
+        assert!(manager.authenticate("sk-initial").is_some());
+    }
+
+    /// 模拟共享存储读取失败并统计写入次数（其余操作委托给内存 SQLite）
+    struct FlakyStore {
+        inner: SqliteStore,
+        failing: Arc<AtomicBool>,
+        inserts: Arc<AtomicUsize>,
+    }
+
+    impl FlakyStore {
+        fn manager(failing: Arc<AtomicBool>, inserts: Arc<AtomicUsize>) -> ApiKeyManager {
+            ApiKeyManager::with_store(
+                "sk-initial".to_string(),
+                Box::new(FlakyStore {
+                    inner: SqliteStore::memory(),
+                    failing,
+                    inserts,
+                }),
+                ApiKeyStoreStatus {
+                    mode: ApiKeyStoreMode::Persistent,
+                    error: None,
+                },
+            )
+        }
+    }
+
+    impl ApiKeyStore for FlakyStore {
+        fn keys(&self) -> anyhow::Result<Vec<KeyRow>> {
+            if self.failing.load(Ordering::Relaxed) {
+                anyhow::bail!("connection reset");
+            }
+            self.inner.keys()
+        }
+        fn key(&self, id: &str) -> Option<KeyRow> {
+            if self.failing.load(Ordering::Relaxed) {
+                return None;
+            }
+            self.inner.key(id)
+        }
+        fn insert_key(&self, id: &str, name: &str, key: &str, created_at: &str) -> bool {
+            self.inserts.fetch_add(1, Ordering::Relaxed);
+            self.inner.insert_key(id, name, key, created_at)
+        }
+        fn update(&self, id: &str, fields: &[(&str, FieldValue)]) -> bool {
+            self.inner.update(id, fields)
+        }
+        fn touch(&self, id: &str, last_used_at: &str) {
+            self.inner.touch(id, last_used_at)
+        }
+        fn record_usage(&self, usage: &UsageRecord<'_>) {
+            self.inner.record_usage(usage)
+        }
+        fn user_usage(&self, key_id: Option<&str>) -> Vec<ApiKeyUserUsage> {
+            self.inner.user_usage(key_id)
+        }
+        fn delete_key(&self, id: &str) -> bool {
+            self.inner.delete_key(id)
+        }
+        fn shared(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_failed_reload_keeps_previous_key_cache() {
+        let failing = Arc::new(AtomicBool::new(false));
+        let manager = FlakyStore::manager(failing.clone(), Arc::default());
+        let key = "sk-oneapi-7fQ2xL9pVw4Rk8ZmT3bN6cYhJ1sD5gEa".to_string();
+        assert!(
+            manager
+                .create_key_with_value("import".to_string(), key.clone())
+                .is_ok()
+        );
+        assert!(manager.authenticate("sk-initial").is_some());
+
+        // 缓存过期后数据库读取失败：继续使用已有缓存
+        if let Some(cache) = manager.enabled_keys.write().as_mut() {
+            cache.loaded_at -= SHARED_KEY_CACHE_TTL;
+        }
+        failing.store(true, Ordering::Relaxed);
+        assert!(manager.authenticate("sk-initial").is_some());
+
+        // 读取失败不影响自定义 Key 的重复检查（由唯一约束判定）
+        assert!(
+            manager
+                .create_key_with_value("dup".to_string(), key)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_request_settings_are_read_from_key_cache() {
+        let failing = Arc::new(AtomicBool::new(false));
+        let manager = FlakyStore::manager(failing.clone(), Arc::default());
+        let id = manager.list().remove(0).id;
+        let regions = vec!["us-east-1".to_string()];
+        assert!(manager.set_max_body_bytes(&id, Some(1024)));
+        assert!(manager.set_allowed_regions(&id, Some(&regions)));
+        assert!(manager.authenticate("sk-initial").is_some());
+
+        // 缓存有效期内不再访问数据库
+        failing.store(true, Ordering::Relaxed);
+        assert_eq!(manager.max_body_bytes(&id), Some(1024));
+        assert_eq!(manager.allowed_regions(&id), Some(regions));
+        assert!(manager.check_quota(&id).is_ok());
+    }
+
+    #[test]
+    fn test_custom_key_validation_and_duplicates() {
+        let manager = ApiKeyManager::new("sk-initial".to_string(), None);
+        let key = "sk-oneapi-7fQ2xL9pVw4Rk8ZmT3bN6cYhJ1sD5gEa".to_string();
+        let record = manager
+            .create_key_with_value("migrated".to_string(), key.clone())
+            .unwrap();
+        assert_eq!(manager.authenticate(&key).unwrap().key_id, record.id);
+        assert!(
+            manager
+                .create_key_with_value("dup".to_string(), key)
+                .is_err()
+        );
+
+        // 前缀、长度、空白与熵不满足要求时拒绝
+        for weak in [
+            "pk-7fQ2xL9pVw4Rk8ZmT3bN6cYhJ1sD5gEaUu",
+            "sk-7fQ2xL9pVw4R",
+            "sk-7fQ2xL9pVw4Rk8ZmT 3bN6cYhJ1sD5gEaUu",
+            "sk-aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "sk-abababababababababababababababababababab",
+        ] {
+            assert!(validate_custom_key(weak).is_err(), "{}", weak);
+        }
+        assert!(validate_custom_key(&manager.create_key("gen".to_string()).key).is_ok());
+    }
+
+    #[test]
+    fn test_custom_key_rejects_master_key_and_concurrent_duplicates() {
+        let master = "sk-master-Vq8nR2tLw5Yc7Kp3Xh9Dm4Fz6Bj1Gs0A".to_string();
+        let manager = Arc::new(ApiKeyManager::new(master.clone(), None));
+        // 配置中的 apiKey 记录被删除后仍不能作为自定义 Key 导入（重启后会重新写入）
+        let master_id = manager.list().remove(0).id;
+        assert!(manager.delete_key(&master_id));
+        assert!(
+            manager
+                .create_key_with_value("master".to_string(), master)
+                .is_err()
+        );
+
+        let key = "sk-oneapi-7fQ2xL9pVw4Rk8ZmT3bN6cYhJ1sD5gEa".to_string();
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let (manager, key) = (manager.clone(), key.clone());
+                std::thread::spawn(move || {
+                    manager
+                        .create_key_with_value(format!("import-{}", i), key)
+                        .is_ok()
+                })
+            })
+            .collect();
+        let created = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|ok| *ok)
+            .count();
+        assert_eq!(created, 1);
+        assert_eq!(manager.list().iter().filter(|k| k.key == key).count(), 1);
+    }
+
+    #[test]
+    fn test_rpm_limit_rejects_after_burst() {
+        let manager = ApiKeyManager::new("sk-initial".to_string(), None);
+        let key = limited_key(&manager, Some(2), None);
+        assert_eq!(key.rpm_limit, Some(2));
+
+        assert!(manager.check_rate_limit(&key).is_ok());
+        assert!(manager.check_rate_limit(&key).is_ok());
+        let limited = manager.check_rate_limit(&key).unwrap_err();
+        assert_eq!(limited.kind, RateLimitKind::Requests);
+        assert!(limited.retry_after > Duration::ZERO);
+        assert!(limited.retry_after <= Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_tpm_limit_rejects_after_usage() {
+        let manager = ApiKeyManager::new("sk-initial".to_string(), None);
+        let key = limited_key(&manager, None, Some(1000));
+
+        assert!(manager.check_rate_limit(&key).is_ok());
+        manager.record_usage(&key.key_id, None, "m", 800, 400);
+        let limited = manager.check_rate_limit(&key).unwrap_err();
+        assert_eq!(limited.kind, RateLimitKind::Tokens);
+    }
+
+    #[test]
+    fn test_daily_quota_hard_cutoff_and_reset() {
+        let manager = ApiKeyManager::new("sk-initial".to_string(), None);
+        let key = manager.create_key("quota".to_string());
+        assert!(manager.set_quota(&key.id, Some(1000), None));
+
+        assert!(manager.check_quota(&key.id).is_ok());
+        manager.record_usage(&key.id, None, "m", 600, 500);
+        let exceeded = manager.check_quota(&key.id).unwrap_err();
+        assert_eq!(exceeded.period, QuotaPeriod::Daily);
+        assert_eq!(exceeded.limit, 1000);
+        assert!(exceeded.retry_after <= Duration::from_secs(24 * 3600));
+
+        let info = manager.list().into_iter().find(|k| k.id == key.id).unwrap();
+        assert_eq!(info.daily_tokens_used, 1100);
+        assert_eq!(info.monthly_tokens_used, 1100);
+
+        assert!(manager.reset_quota_usage(&key.id));
+        assert!(manager.check_quota(&key.id).is_ok());
+    }
+
+    #[test]
+    fn test_usage_aggregated_per_end_user() {
+        let manager = ApiKeyManager::new("sk-initial".to_string(), None);
+        let key = manager.create_key("app".to_string());
+        let session = |n: u8| {
+            format!(
+                "user_abc_account__session_{:08}-0000-0000-0000-000000000000",
+                n
+            )
+        };
+        manager.record_usage(&key.id, Some(&session(1)), "m", 100, 10);
+        manager.record_usage(&key.id, Some(&session(2)), "m", 50, 5);
+        manager.record_usage(&key.id, Some("alice"), "m", 10, 1);
+        manager.record_usage(&key.id, None, "m", 1000, 100);
+
+        let users = manager.user_usage(Some(&key.id));
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].user_id, "user_abc_account");
+        assert_eq!(users[0].request_count, 2);
+        assert_eq!(users[0].input_tokens, 150);
+        assert_eq!(users[1].user_id, "alice");
+        assert!(manager.user_usage(Some("missing")).is_empty());
+        assert_eq!(manager.user_usage(None).len(), 2);
+
+        assert!(manager.delete_key(&key.id));
+        assert!(manager.user_usage(None).is_empty());
+    }
+
+    #[test]
+    fn test_quota_usage_rolls_over_after_reset_time() {
+        let manager = ApiKeyManager::new("sk-initial".to_string(), None);
+        let key = manager.create_key("quota".to_string());
+        assert!(manager.set_quota(&key.id, None, Some(100)));
+        manager.record_usage(&key.id, None, "m", 100, 0);
+        assert!(manager.check_quota(&key.id).is_err());
+
+        // 模拟周期已结束
+        assert!(manager.store.update(
+            &key.id,
+            &[(
+                "monthly_reset_at",
+                FieldValue::Text(Some("2000-01-01T00:00:00Z".to_string()))
+            )]
+        ));
+        assert!(manager.check_quota(&key.id).is_ok());
+
+        manager.record_usage(&key.id, None, "m", 30, 0);
+        let info = manager.list().into_iter().find(|k| k.id == key.id).unwrap();
+        assert_eq!(info.monthly_tokens_used, 30);
+        assert!(info.monthly_reset_at.unwrap().as_str() > "2000-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn test_monthly_budget_blocks_until_reset() {
+        // 使用专用模型名，避免与其他测试共享的单价表冲突
+        let mut prices = std::collections::BTreeMap::new();
+        prices.insert(
+            "budget-test-model".to_string(),
+            crate::model::config::ModelPrice {
+                input_per_mtok: 3.0,
+                output_per_mtok: 15.0,
+            },
+        );
+        pricing::apply(&prices);
+
+        let manager = ApiKeyManager::new("sk-initial".to_string(), None);
+        let key = manager.create_key("budget".to_string());
+        assert!(manager.set_budget(&key.id, Some(pricing::from_usd(0.01))));
+        manager.record_usage(&key.id, None, "budget-test-model", 1000, 100);
+        assert!(manager.check_quota(&key.id).is_ok());
+        manager.record_usage(&key.id, None, "budget-test-model", 1000, 400);
+        let exceeded = manager.check_quota(&key.id).unwrap_err();
+        assert_eq!(exceeded.period, QuotaPeriod::MonthlyBudget);
+
+        let info = manager.list().into_iter().find(|k| k.id == key.id).unwrap();
+        assert_eq!(info.spend_usd, 0.0135);
+        assert_eq!(info.monthly_spend_usd, 0.0135);
+        assert_eq!(info.monthly_budget_usd, Some(0.01));
+
+        // 重置周期用量后恢复，累计费用保留
+        assert!(manager.reset_quota_usage(&key.id));
+        assert!(manager.check_quota(&key.id).is_ok());
+        let info = manager.list().into_iter().find(|k| k.id == key.id).unwrap();
+        assert_eq!(info.monthly_spend_usd, 0.0);
+        assert_eq!(info.spend_usd, 0.0135);
+    }
+
+    #[test]
+    fn test_period_boundaries() {
+        let now = Utc.with_ymd_and_hms(2025, 12, 31, 13, 45, 0).unwrap();
+        assert_eq!(quota_timestamp(next_day_start(now)), "2026-01-01T00:00:00Z");
+        assert_eq!(
+            quota_timestamp(next_month_start(now)),
+            "2026-01-01T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn test_browser_token_inherits_key_and_expires() {
+        let manager = ApiKeyManager::new("sk-initial".to_string(), None);
+        let key = limited_key(&manager, Some(5), None);
+
+        let token = manager.issue_browser_token(&key.key_id, Duration::from_secs(60), None);
+        assert!(token.starts_with(BROWSER_TOKEN_PREFIX));
+        let authed = manager.authenticate_browser_token(&token, None).unwrap();
+        assert_eq!(authed.key_id, key.key_id);
+        assert_eq!(authed.rpm_limit, Some(5));
+        assert!(manager.authenticate(&token).is_none());
+
+        let expired = manager.issue_browser_token(&key.key_id, Duration::ZERO, None);
+        assert!(manager.authenticate_browser_token(&expired, None).is_none());
+
+        assert!(manager.set_enabled(&key.key_id, false));
+        assert!(manager.authenticate_browser_token(&token, None).is_none());
+    }
+
+    #[test]
+    fn test_browser_token_bound_to_origin() {
+        let manager = ApiKeyManager::new("sk-initial".to_string(), None);
+        let key = manager.authenticate("sk-initial").unwrap();
+        let token = manager.issue_browser_token(
+            &key.key_id,
+            Duration::from_secs(60),
+            Some("https://app.example.com".to_string()),
+        );
+
+        assert!(
+            manager
+                .authenticate_browser_token(&token, Some("https://app.example.com"))
+                .is_some()
+        );
+        assert!(
+            manager
+                .authenticate_browser_token(&token, Some("https://evil.example.com"))
+                .is_none()
+        );
+        assert!(manager.authenticate_browser_token(&token, None).is_none());
+    }
+
+    #[test]
+    fn test_external_identity_maps_to_key_record() {
+        let manager = ApiKeyManager::new("sk-initial".to_string(), None);
+        let first = manager
+            .authenticate_external("alice", Some(10), None)
+            .unwrap();
+        assert_eq!(first.key_id, "jwt:alice");
+        assert_eq!(first.rpm_limit, Some(10));
+
+        assert!(manager.set_limits(&first.key_id, Some(5), Some(1000)));
+        let second = manager.authenticate_external("alice", None, None).unwrap();
+        assert_eq!(second.rpm_limit, Some(5));
+        assert_eq!(second.tpm_limit, Some(1000));
+        assert_eq!(manager.list().len(), 2);
+
+        assert!(manager.set_enabled(&first.key_id, false));
+        assert!(manager.authenticate_external("alice", None, None).is_none());
+    }
+
+    #[test]
+    fn test_external_identity_writes_only_on_first_sight() {
+        let inserts = Arc::new(AtomicUsize::new(0));
+        let manager = FlakyStore::manager(Arc::default(), inserts.clone());
+        let initial = inserts.load(Ordering::Relaxed);
+        for _ in 0..3 {
+            assert!(manager.authenticate_external("bob", None, None).is_some());
+        }
+        assert_eq!(inserts.load(Ordering::Relaxed), initial + 1);
+    }
+
+    #[test]
+    fn test_unlimited_key_is_never_limited() {
+        let manager = ApiKeyManager::new("sk-initial".to_string(), None);
+        let key = manager.authenticate("sk-initial").unwrap();
+        for _ in 0..100 {
+            assert!(manager.check_rate_limit(&key).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_scopes_restrict_authenticated_key() {
+        let manager = ApiKeyManager::new("sk-initial".to_string(), None);
+        let record = manager.create_key("tooling".to_string());
+        assert!(
+            manager
+                .authenticate(&record.key)
+                .unwrap()
+                .allows(ApiKeyScope::Messages)
+        );
+
+        let scopes = [ApiKeyScope::CountTokens, ApiKeyScope::Models];
+        assert!(manager.set_scopes(&record.id, Some(&scopes)));
+        let authed = manager.authenticate(&record.key).unwrap();
+        assert!(!authed.allows(ApiKeyScope::Messages));
+        assert!(authed.allows(ApiKeyScope::CountTokens));
+        let info = manager
+            .list()
+            .into_iter()
+            .find(|k| k.id == record.id)
+            .unwrap();
+        assert_eq!(info.scopes, Some(scopes.to_vec()));
+
+        assert!(manager.set_scopes(&record.id, None));
+        assert!(
+            manager
+                .authenticate(&record.key)
+                .unwrap()
+                .allows(ApiKeyScope::Messages)
+        );
+        assert!(!manager.set_scopes("missing", None));
+    }
+
+    #[test]
+    fn test_max_body_bytes_round_trip() {
+        let manager = ApiKeyManager::new("initial".to_string(), None);
+        let record = manager.create_key("uploads".to_string());
+        assert_eq!(manager.max_body_bytes(&record.id), None);
+
+        assert!(manager.set_max_body_bytes(&record.id, Some(1024)));
+        assert_eq!(manager.max_body_bytes(&record.id), Some(1024));
+        let info = manager
+            .list()
+            .into_iter()
+            .find(|k| k.id == record.id)
+            .unwrap();
+        assert_eq!(info.max_body_bytes, Some(1024));
+
+        assert!(manager.set_max_body_bytes(&record.id, None));
+        assert_eq!(manager.max_body_bytes(&record.id), None);
+        assert!(!manager.set_max_body_bytes("missing", Some(1)));
+    }
+
+    #[test]
+    fn test_webhook_round_trip() {
+        let manager = ApiKeyManager::new("initial".to_string(), None);
+        let record = manager.create_key("hooks".to_string());
+        assert_eq!(manager.webhook(&record.id), None);
+
+        let webhook = KeyWebhook {
+            url: "https://example.com/hook".to_string(),
+            secret: "whsec_test".to_string(),
+        };
+        assert!(manager.set_webhook(&record.id, Some(&webhook)));
+        assert_eq!(manager.webhook(&record.id).as_deref(), Some(&webhook));
+        let info = manager
+            .list()
+            .into_iter()
+            .find(|k| k.id == record.id)
+            .unwrap();
+        assert_eq!(
+            info.webhook_url.as_deref(),
+            Some("https://example.com/hook")
+        );
+
+        assert!(manager.set_webhook(&record.id, None));
+        assert_eq!(manager.webhook(&record.id), None);
+        assert!(!manager.set_webhook("missing", None));
+    }
+
+    #[test]
+    fn test_allowed_regions_round_trip() {
+        let manager = ApiKeyManager::new("initial".to_string(), None);
+        let record = manager.create_key("eu".to_string());
+        assert_eq!(manager.allowed_regions(&record.id), None);
+
+        let regions = vec!["eu-central-1".to_string(), "eu-west-1".to_string()];
+        assert!(manager.set_allowed_regions(&record.id, Some(&regions)));
+        assert_eq!(manager.allowed_regions(&record.id), Some(regions.clone()));
+        let info = manager
+            .list()
+            .into_iter()
+            .find(|k| k.id == record.id)
+            .unwrap();
+        assert_eq!(info.allowed_regions, Some(regions));
+
+        assert!(manager.set_allowed_regions(&record.id, None));
+        assert_eq!(manager.allowed_regions(&record.id), None);
+        assert!(!manager.set_allowed_regions("missing", None));
+    }
+}
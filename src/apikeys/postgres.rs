@@ -0,0 +1,475 @@
+//! PostgreSQL 存储（`postgres` 特性）
+//!
+//! 配置 `databaseUrl` 后 API Key 与用量数据写入 PostgreSQL，多个副本共享同一份数据。
+//! [`ApiKeyManager`](super::ApiKeyManager) 的接口是同步的，查询在专用的 Tokio 运行时上执行，
+//! 调用方阻塞等待结果（与 SQLite 存储持锁执行查询的行为一致）；在多线程运行时的工作线程上调用时
+//! 通过 `block_in_place` 等待，该线程上的其他任务先移交给其他工作线程，不会被阻塞。
+
+use std::future::Future;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use sqlx::Row;
+use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
+use tokio::runtime::{Runtime, RuntimeFlavor};
+
+use super::{ApiKeyStore, ApiKeyUserUsage, FieldValue, KeyRow, UsageRecord};
+
+/// 连接池大小
+const MAX_CONNECTIONS: u32 = 8;
+
+/// 获取连接的超时时间
+const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 建表时持有的事务级咨询锁，避免多个副本同时启动时并发迁移
+const MIGRATION_LOCK_KEY: i64 = 0x6b69_726f_6b65_7973;
+
+/// 创建表结构并补充旧表缺少的列
+async fn init_schema(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(MIGRATION_LOCK_KEY)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS api_keys (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            key TEXT NOT NULL,
+            enabled BOOLEAN NOT NULL DEFAULT TRUE,
+            created_at TEXT NOT NULL,
+            last_used_at TEXT,
+            request_count BIGINT NOT NULL DEFAULT 0,
+            input_tokens BIGINT NOT NULL DEFAULT 0,
+            output_tokens BIGINT NOT NULL DEFAULT 0
+        )",
+    )
+    .execute(&mut *tx)
+    .await?;
+    for column in [
+        "rpm_limit BIGINT",
+        "tpm_limit BIGINT",
+        "daily_token_limit BIGINT",
+        "monthly_token_limit BIGINT",
+        "daily_tokens_used BIGINT NOT NULL DEFAULT 0",
+        "monthly_tokens_used BIGINT NOT NULL DEFAULT 0",
+        "daily_reset_at TEXT",
+        "monthly_reset_at TEXT",
+        "compaction_threshold DOUBLE PRECISION",
+        "allowed_regions TEXT",
+        "scopes TEXT",
+        "webhook_url TEXT",
+        "webhook_secret TEXT",
+        "spend_micros BIGINT NOT NULL DEFAULT 0",
+        "monthly_spend_micros BIGINT NOT NULL DEFAULT 0",
+        "monthly_budget_micros BIGINT",
+        "max_body_bytes BIGINT",
+    ] {
+        sqlx::query(&format!(
+            "ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS {}",
+            column
+        ))
+        .execute(&mut *tx)
+        .await?;
+    }
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS api_key_users (
+            key_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            request_count BIGINT NOT NULL DEFAULT 0,
+            input_tokens BIGINT NOT NULL DEFAULT 0,
+            output_tokens BIGINT NOT NULL DEFAULT 0,
+            last_used_at TEXT,
+            PRIMARY KEY (key_id, user_id)
+        )",
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    // Key 唯一：自定义 Key 的重复检查依赖该约束。放在迁移事务之外，
+    // 旧数据中已有重复 Key 导致创建失败时只记录日志，不影响启动
+    if let Err(e) =
+        sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS idx_api_keys_key ON api_keys (key)")
+            .execute(pool)
+            .await
+    {
+        tracing::warn!("创建 API Key 唯一索引失败（已有重复的 Key？）: {}", e);
+    }
+    Ok(())
+}
+
+fn key_row(row: &PgRow) -> Result<KeyRow, sqlx::Error> {
+    Ok(KeyRow {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        key: row.try_get("key")?,
+        enabled: row.try_get("enabled")?,
+        created_at: row.try_get("created_at")?,
+        last_used_at: row.try_get("last_used_at")?,
+        request_count: row.try_get("request_count")?,
+        input_tokens: row.try_get("input_tokens")?,
+        output_tokens: row.try_get("output_tokens")?,
+        rpm_limit: row.try_get("rpm_limit")?,
+        tpm_limit: row.try_get("tpm_limit")?,
+        daily_token_limit: row.try_get("daily_token_limit")?,
+        monthly_token_limit: row.try_get("monthly_token_limit")?,
+        daily_tokens_used: row.try_get("daily_tokens_used")?,
+        monthly_tokens_used: row.try_get("monthly_tokens_used")?,
+        daily_reset_at: row.try_get("daily_reset_at")?,
+        monthly_reset_at: row.try_get("monthly_reset_at")?,
+        compaction_threshold: row.try_get("compaction_threshold")?,
+        allowed_regions: row.try_get("allowed_regions")?,
+        scopes: row.try_get("scopes")?,
+        webhook_url: row.try_get("webhook_url")?,
+        webhook_secret: row.try_get("webhook_secret")?,
+        spend_micros: row.try_get("spend_micros")?,
+        monthly_spend_micros: row.try_get("monthly_spend_micros")?,
+        monthly_budget_micros: row.try_get("monthly_budget_micros")?,
+        max_body_bytes: row.try_get("max_body_bytes")?,
+    })
+}
+
+/// 在运行时上执行并同步等待结果（调用方可以在任意线程，包括其他运行时的工作线程）
+fn block_on<F>(runtime: &Runtime, future: F) -> F::Output
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (tx, rx) = mpsc::sync_channel(1);
+    runtime.spawn(async move {
+        let _ = tx.send(future.await);
+    });
+    let wait = || rx.recv().expect("API Key 数据库运行时已停止");
+    // 单线程运行时不支持 block_in_place（仅测试中出现），直接等待
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(wait)
+        }
+        _ => wait(),
+    }
+}
+
+pub(super) struct PgStore {
+    pool: PgPool,
+    /// 执行查询的专用运行时（释放时在后台关闭，见 Drop）
+    runtime: Option<Runtime>,
+}
+
+impl PgStore {
+    /// 连接数据库并建表
+    pub(super) fn connect(database_url: &str) -> anyhow::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("apikeys-postgres")
+            .enable_all()
+            .build()?;
+        let database_url = database_url.to_string();
+        let pool = block_on(&runtime, async move {
+            let pool = PgPoolOptions::new()
+                .max_connections(MAX_CONNECTIONS)
+                .acquire_timeout(ACQUIRE_TIMEOUT)
+                .connect(&database_url)
+                .await?;
+            init_schema(&pool).await?;
+            Ok::<_, sqlx::Error>(pool)
+        })?;
+        tracing::info!("API Key 存储使用 PostgreSQL");
+        Ok(Self {
+            pool,
+            runtime: Some(runtime),
+        })
+    }
+
+    /// 执行一次数据库操作并返回结果
+    fn try_run<T, F>(&self, query: impl FnOnce(PgPool) -> F) -> anyhow::Result<T>
+    where
+        F: Future<Output = Result<T, sqlx::Error>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let runtime = self
+            .runtime
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("API Key 数据库运行时已停止"))?;
+        Ok(block_on(runtime, query(self.pool.clone()))?)
+    }
+
+    /// 执行一次数据库操作，失败时记录日志并返回 None
+    fn run<T, F>(&self, op: &str, query: impl FnOnce(PgPool) -> F) -> Option<T>
+    where
+        F: Future<Output = Result<T, sqlx::Error>> + Send + 'static,
+        T: Send + 'static,
+    {
+        match self.try_run(query) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                tracing::warn!("API Key 数据库操作失败（{}）: {}", op, e);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for PgStore {
+    fn drop(&mut self) {
+        // 可能在异步上下文中释放，不能阻塞等待运行时退出
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl ApiKeyStore for PgStore {
+    fn keys(&self) -> anyhow::Result<Vec<KeyRow>> {
+        self.try_run(|pool| async move {
+            let rows = sqlx::query("SELECT * FROM api_keys ORDER BY created_at")
+                .fetch_all(&pool)
+                .await?;
+            rows.iter().map(key_row).collect()
+        })
+    }
+
+    fn key(&self, id: &str) -> Option<KeyRow> {
+        let id = id.to_string();
+        self.run("读取 API Key", |pool| async move {
+            let row = sqlx::query("SELECT * FROM api_keys WHERE id = $1")
+                .bind(id)
+                .fetch_optional(&pool)
+                .await?;
+            row.as_ref().map(key_row).transpose()
+        })
+        .flatten()
+    }
+
+    fn insert_key(&self, id: &str, name: &str, key: &str, created_at: &str) -> bool {
+        let (id, name, key, created_at) = (
+            id.to_string(),
+            name.to_string(),
+            key.to_string(),
+            created_at.to_string(),
+        );
+        self.run("新增 API Key", |pool| async move {
+            let result = sqlx::query(
+                "INSERT INTO api_keys (id, name, key, enabled, created_at) VALUES ($1, $2, $3, TRUE, $4)
+                ON CONFLICT DO NOTHING",
+            )
+            .bind(id)
+            .bind(name)
+            .bind(key)
+            .bind(created_at)
+            .execute(&pool)
+            .await?;
+            Ok(result.rows_affected() > 0)
+        })
+        .unwrap_or(false)
+    }
+
+    fn update(&self, id: &str, fields: &[(&str, FieldValue)]) -> bool {
+        let assignments: Vec<String> = fields
+            .iter()
+            .enumerate()
+            .map(|(i, (column, _))| format!("{} = ${}", column, i + 1))
+            .collect();
+        let sql = format!(
+            "UPDATE api_keys SET {} WHERE id = ${}",
+            assignments.join(", "),
+            fields.len() + 1
+        );
+        let values: Vec<FieldValue> = fields.iter().map(|(_, v)| v.clone()).collect();
+        let id = id.to_string();
+        self.run("更新 API Key", |pool| async move {
+            let mut query = sqlx::query(&sql);
+            for value in values {
+                query = match value {
+                    FieldValue::Bool(v) => query.bind(v),
+                    FieldValue::Int(v) => query.bind(v),
+                    FieldValue::Real(v) => query.bind(v),
+                    FieldValue::Text(v) => query.bind(v),
+                };
+            }
+            let result = query.bind(id).execute(&pool).await?;
+            Ok(result.rows_affected() > 0)
+        })
+        .unwrap_or(false)
+    }
+
+    fn touch(&self, id: &str, last_used_at: &str) {
+        let (id, last_used_at) = (id.to_string(), last_used_at.to_string());
+        self.run("更新最近使用时间", |pool| async move {
+            sqlx::query(
+                "UPDATE api_keys SET last_used_at = $1 WHERE id = $2 AND (last_used_at IS NULL OR last_used_at < $1)",
+            )
+            .bind(last_used_at)
+            .bind(id)
+            .execute(&pool)
+            .await?;
+            Ok(())
+        });
+    }
+
+    fn record_usage(&self, usage: &UsageRecord<'_>) {
+        let key_id = usage.key_id.to_string();
+        let user_id = usage.user_id.map(str::to_string);
+        let (input_tokens, output_tokens, cost) =
+            (usage.input_tokens, usage.output_tokens, usage.cost_micros);
+        let used_at = usage.used_at.clone();
+        let period_now = usage.period_now.clone();
+        let next_day_start = usage.next_day_start.clone();
+        let next_month_start = usage.next_month_start.clone();
+        self.run("记录用量", |pool| async move {
+            let mut tx = pool.begin().await?;
+            sqlx::query(
+                "UPDATE api_keys SET request_count = request_count + 1, input_tokens = input_tokens + $1, output_tokens = output_tokens + $2, last_used_at = $3, spend_micros = spend_micros + $4 WHERE id = $5",
+            )
+            .bind(input_tokens)
+            .bind(output_tokens)
+            .bind(&used_at)
+            .bind(cost)
+            .bind(&key_id)
+            .execute(&mut *tx)
+            .await?;
+            if let Some(user_id) = user_id {
+                sqlx::query(
+                    "INSERT INTO api_key_users (key_id, user_id, request_count, input_tokens, output_tokens, last_used_at) VALUES ($1, $2, 1, $3, $4, $5)
+                    ON CONFLICT (key_id, user_id) DO UPDATE SET
+                        request_count = api_key_users.request_count + 1,
+                        input_tokens = api_key_users.input_tokens + excluded.input_tokens,
+                        output_tokens = api_key_users.output_tokens + excluded.output_tokens,
+                        last_used_at = excluded.last_used_at",
+                )
+                .bind(&key_id)
+                .bind(user_id)
+                .bind(input_tokens)
+                .bind(output_tokens)
+                .bind(&used_at)
+                .execute(&mut *tx)
+                .await?;
+            }
+            // 周期用量：已过重置时间则从本次用量重新累计（月度费用与月度 token 共用重置时间）
+            sqlx::query(
+                "UPDATE api_keys SET
+                    daily_tokens_used = CASE WHEN daily_reset_at IS NULL OR daily_reset_at <= $1 THEN $2 ELSE daily_tokens_used + $2 END,
+                    daily_reset_at = CASE WHEN daily_reset_at IS NULL OR daily_reset_at <= $1 THEN $3 ELSE daily_reset_at END,
+                    monthly_tokens_used = CASE WHEN monthly_reset_at IS NULL OR monthly_reset_at <= $1 THEN $2 ELSE monthly_tokens_used + $2 END,
+                    monthly_spend_micros = CASE WHEN monthly_reset_at IS NULL OR monthly_reset_at <= $1 THEN $6 ELSE monthly_spend_micros + $6 END,
+                    monthly_reset_at = CASE WHEN monthly_reset_at IS NULL OR monthly_reset_at <= $1 THEN $4 ELSE monthly_reset_at END
+                WHERE id = $5",
+            )
+            .bind(period_now)
+            .bind(input_tokens + output_tokens)
+            .bind(next_day_start)
+            .bind(next_month_start)
+            .bind(&key_id)
+            .bind(cost)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await
+        });
+    }
+
+    fn user_usage(&self, key_id: Option<&str>) -> Vec<ApiKeyUserUsage> {
+        let key_id = key_id.map(str::to_string);
+        self.run("读取终端用户用量", |pool| async move {
+            let rows = sqlx::query(
+                "SELECT key_id, user_id, request_count, input_tokens, output_tokens, last_used_at FROM api_key_users
+                WHERE $1::TEXT IS NULL OR key_id = $1
+                ORDER BY input_tokens + output_tokens DESC",
+            )
+            .bind(key_id)
+            .fetch_all(&pool)
+            .await?;
+            rows.iter()
+                .map(|row| {
+                    Ok(ApiKeyUserUsage {
+                        key_id: row.try_get("key_id")?,
+                        user_id: row.try_get("user_id")?,
+                        request_count: row.try_get::<i64, _>("request_count")?.max(0) as u64,
+                        input_tokens: row.try_get::<i64, _>("input_tokens")?.max(0) as u64,
+                        output_tokens: row.try_get::<i64, _>("output_tokens")?.max(0) as u64,
+                        last_used_at: row.try_get("last_used_at")?,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+    }
+
+    fn delete_key(&self, id: &str) -> bool {
+        let id = id.to_string();
+        self.run("删除 API Key", |pool| async move {
+            let mut tx = pool.begin().await?;
+            let deleted = sqlx::query("DELETE FROM api_keys WHERE id = $1")
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            if deleted > 0 {
+                sqlx::query("DELETE FROM api_key_users WHERE key_id = $1")
+                    .bind(&id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await?;
+            Ok(deleted > 0)
+        })
+        .unwrap_or(false)
+    }
+
+    fn shared(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 需要可用的 PostgreSQL：`KIRO_TEST_DATABASE_URL=postgres://... cargo test --features postgres`，
+    /// 未设置时跳过
+    #[tokio::test]
+    async fn test_postgres_store_round_trip() {
+        let Ok(url) = std::env::var("KIRO_TEST_DATABASE_URL") else {
+            return;
+        };
+        // 在异步上下文中同步调用与释放
+        let manager = super::super::ApiKeyManager::connect("sk-pg-initial".to_string(), &url);
+        assert!(!manager.store_status().is_degraded());
+        let created = manager.create_key("pg-manager".to_string());
+        assert!(manager.authenticate(&created.key).is_some());
+        assert!(manager.delete_key(&created.id));
+        drop(manager);
+
+        let store = PgStore::connect(&url).unwrap();
+        let id = format!("test-{}", uuid::Uuid::new_v4());
+        assert!(store.insert_key(&id, "pg", "sk-pg", "2026-01-01T00:00:00Z"));
+        assert!(!store.insert_key(&id, "pg", "sk-pg", "2026-01-01T00:00:00Z"));
+        assert!(store.update(
+            &id,
+            &[
+                ("rpm_limit", FieldValue::Int(Some(10))),
+                ("compaction_threshold", FieldValue::Real(None)),
+                ("enabled", FieldValue::Bool(false)),
+            ]
+        ));
+        store.record_usage(&UsageRecord {
+            key_id: &id,
+            user_id: Some("alice"),
+            input_tokens: 100,
+            output_tokens: 20,
+            cost_micros: 5,
+            used_at: "2026-01-01T00:00:01Z".to_string(),
+            period_now: "2026-01-01T00:00:01Z".to_string(),
+            next_day_start: "2026-01-02T00:00:00Z".to_string(),
+            next_month_start: "2026-02-01T00:00:00Z".to_string(),
+        });
+
+        let row = store.key(&id).unwrap();
+        assert!(!row.enabled);
+        assert_eq!(row.rpm_limit, Some(10));
+        assert_eq!((row.request_count, row.daily_tokens_used), (1, 120));
+        assert_eq!(row.monthly_spend_micros, 5);
+        assert_eq!(store.user_usage(Some(&id))[0].input_tokens, 100);
+        assert!(store.delete_key(&id));
+        assert!(store.key(&id).is_none());
+        assert!(store.user_usage(Some(&id)).is_empty());
+    }
+}
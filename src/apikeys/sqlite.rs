@@ -0,0 +1,400 @@
+//! SQLite 存储（默认）
+//!
+//! 数据保存在配置目录下的 `api_keys.db`。数据库不可写时降级为只读副本，不可读时降级为空的内存数据库，
+//! 见 [`ApiKeyStoreMode`]。
+
+use std::fs;
+use std::path::Path;
+
+use parking_lot::Mutex;
+use rusqlite::types::{ToSqlOutput, Value};
+use rusqlite::{Connection, DatabaseName, OpenFlags, ToSql, params};
+
+use super::{
+    ApiKeyRecord, ApiKeyStore, ApiKeyStoreMode, ApiKeyStoreStatus, ApiKeyUserUsage, FieldValue,
+    KeyRow, UsageRecord,
+};
+
+/// 创建表结构并补充旧表缺少的列
+fn init_schema(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch("PRAGMA journal_mode=WAL; PRAGMA busy_timeout=5000;")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS api_keys (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                key TEXT NOT NULL,
+                enabled INTEGER NOT NULL DEFAULT 1,
+                created_at TEXT NOT NULL,
+                last_used_at TEXT,
+                request_count INTEGER NOT NULL DEFAULT 0,
+                input_tokens INTEGER NOT NULL DEFAULT 0,
+                output_tokens INTEGER NOT NULL DEFAULT 0
+            )",
+        [],
+    )?;
+
+    // 旧表迁移：补充限流与配额字段（列已存在时忽略错误）
+    for column in [
+        "rpm_limit INTEGER",
+        "tpm_limit INTEGER",
+        "daily_token_limit INTEGER",
+        "monthly_token_limit INTEGER",
+        "daily_tokens_used INTEGER NOT NULL DEFAULT 0",
+        "monthly_tokens_used INTEGER NOT NULL DEFAULT 0",
+        "daily_reset_at TEXT",
+        "monthly_reset_at TEXT",
+        "compaction_threshold REAL",
+        "allowed_regions TEXT",
+        "scopes TEXT",
+        "webhook_url TEXT",
+        "webhook_secret TEXT",
+        "spend_micros INTEGER NOT NULL DEFAULT 0",
+        "monthly_spend_micros INTEGER NOT NULL DEFAULT 0",
+        "monthly_budget_micros INTEGER",
+        "max_body_bytes INTEGER",
+    ] {
+        let _ = conn.execute(&format!("ALTER TABLE api_keys ADD COLUMN {}", column), []);
+    }
+
+    // Key 唯一：自定义 Key 的重复检查依赖该约束（旧数据中已有重复 Key 时无法创建，仅记录日志）
+    if let Err(e) = conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_api_keys_key ON api_keys (key)",
+        [],
+    ) {
+        tracing::warn!("创建 API Key 唯一索引失败（已有重复的 Key？）: {}", e);
+    }
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS api_key_users (
+                key_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                request_count INTEGER NOT NULL DEFAULT 0,
+                input_tokens INTEGER NOT NULL DEFAULT 0,
+                output_tokens INTEGER NOT NULL DEFAULT 0,
+                last_used_at TEXT,
+                PRIMARY KEY (key_id, user_id)
+            )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// 打开可读写的数据库：建表后确认数据库未以只读方式打开，并试探获取写锁
+fn open_writable(path: &Path) -> anyhow::Result<Connection> {
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let conn = Connection::open(path)?;
+    init_schema(&conn)?;
+    if conn.is_readonly(DatabaseName::Main)? {
+        anyhow::bail!("数据库为只读");
+    }
+    conn.execute_batch("BEGIN IMMEDIATE; ROLLBACK;")?;
+    Ok(conn)
+}
+
+/// 以只读方式打开数据库，将已有数据复制到内存数据库，返回复制的 API Key 数
+pub(super) fn load_read_only(path: &Path, conn: &Connection) -> anyhow::Result<usize> {
+    let src = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let keys = copy_table(&src, conn, "api_keys")?;
+    let _ = copy_table(&src, conn, "api_key_users");
+    Ok(keys)
+}
+
+/// 按列名复制表数据，源表缺少的列使用默认值
+fn copy_table(src: &Connection, dst: &Connection, table: &str) -> rusqlite::Result<usize> {
+    let dst_columns: Vec<String> = dst
+        .prepare(&format!("PRAGMA table_info({})", table))?
+        .query_map([], |row| row.get(1))?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut stmt = src.prepare(&format!("SELECT * FROM {}", table))?;
+    let columns: Vec<(usize, String)> = stmt
+        .column_names()
+        .into_iter()
+        .enumerate()
+        .filter(|(_, name)| dst_columns.iter().any(|c| c == name))
+        .map(|(i, name)| (i, name.to_string()))
+        .collect();
+    let insert = format!(
+        "INSERT OR IGNORE INTO {} ({}) VALUES ({})",
+        table,
+        columns
+            .iter()
+            .map(|(_, name)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        vec!["?"; columns.len()].join(", ")
+    );
+    let mut rows = stmt.query([])?;
+    let mut copied = 0;
+    while let Some(row) = rows.next()? {
+        let values = columns
+            .iter()
+            .map(|(i, _)| row.get::<_, Value>(*i))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        copied += dst.execute(&insert, rusqlite::params_from_iter(values))?;
+    }
+    Ok(copied)
+}
+
+pub(super) fn memory_connection() -> Connection {
+    let conn = Connection::open_in_memory().expect("无法创建内存数据库");
+    init_schema(&conn).expect("建表失败");
+    conn
+}
+
+/// 自动迁移旧 JSON 文件（`api_keys.json`）
+fn migrate_json(conn: &Connection, db_path: &Path) {
+    let json_path = db_path.with_extension("json");
+    if !json_path.exists() {
+        return;
+    }
+    let Ok(content) = fs::read_to_string(&json_path) else {
+        return;
+    };
+    let Ok(records) = serde_json::from_str::<Vec<ApiKeyRecord>>(&content) else {
+        return;
+    };
+    for r in &records {
+        let _ = conn.execute(
+            "INSERT OR IGNORE INTO api_keys (id, name, key, enabled, created_at, last_used_at, request_count, input_tokens, output_tokens) VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9)",
+            params![r.id, r.name, r.key, r.enabled as i32, r.created_at, r.last_used_at, r.request_count as i64, r.input_tokens as i64, r.output_tokens as i64],
+        );
+    }
+    let migrated = json_path.with_extension("json.migrated");
+    let _ = fs::rename(&json_path, &migrated);
+    tracing::info!("已从 JSON 迁移 {} 条 API Key 到 SQLite", records.len());
+}
+
+fn key_row(row: &rusqlite::Row) -> rusqlite::Result<KeyRow> {
+    Ok(KeyRow {
+        id: row.get("id")?,
+        name: row.get("name")?,
+        key: row.get("key")?,
+        enabled: row.get::<_, i32>("enabled")? != 0,
+        created_at: row.get("created_at")?,
+        last_used_at: row.get("last_used_at")?,
+        request_count: row.get("request_count")?,
+        input_tokens: row.get("input_tokens")?,
+        output_tokens: row.get("output_tokens")?,
+        rpm_limit: row.get("rpm_limit")?,
+        tpm_limit: row.get("tpm_limit")?,
+        daily_token_limit: row.get("daily_token_limit")?,
+        monthly_token_limit: row.get("monthly_token_limit")?,
+        daily_tokens_used: row.get("daily_tokens_used")?,
+        monthly_tokens_used: row.get("monthly_tokens_used")?,
+        daily_reset_at: row.get("daily_reset_at")?,
+        monthly_reset_at: row.get("monthly_reset_at")?,
+        compaction_threshold: row.get("compaction_threshold")?,
+        allowed_regions: row.get("allowed_regions")?,
+        scopes: row.get("scopes")?,
+        webhook_url: row.get("webhook_url")?,
+        webhook_secret: row.get("webhook_secret")?,
+        spend_micros: row.get("spend_micros")?,
+        monthly_spend_micros: row.get("monthly_spend_micros")?,
+        monthly_budget_micros: row.get("monthly_budget_micros")?,
+        max_body_bytes: row.get("max_body_bytes")?,
+    })
+}
+
+impl ToSql for FieldValue {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match self {
+            FieldValue::Bool(v) => v.to_sql(),
+            FieldValue::Int(v) => v.to_sql(),
+            FieldValue::Real(v) => v.to_sql(),
+            FieldValue::Text(v) => v.to_sql(),
+        }
+    }
+}
+
+pub(super) struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// 打开 API Key 存储：数据库不可写时降级为只读副本，不可读时降级为空的内存数据库
+    pub(super) fn open(store_path: Option<&Path>) -> (Self, ApiKeyStoreStatus) {
+        let persistent = ApiKeyStoreStatus {
+            mode: ApiKeyStoreMode::Persistent,
+            error: None,
+        };
+        let error = match store_path.map(open_writable) {
+            Some(Ok(conn)) => {
+                if let Some(path) = store_path {
+                    migrate_json(&conn, path);
+                }
+                return (Self::with_connection(conn), persistent);
+            }
+            Some(Err(e)) => e,
+            None => return (Self::memory(), persistent),
+        };
+        let path = store_path.unwrap_or(Path::new(""));
+
+        let conn = memory_connection();
+        let mode = match load_read_only(path, &conn) {
+            Ok(keys) => {
+                tracing::error!(
+                    "API Key 数据库不可写（{}）: {:#}；已以只读方式加载 {} 个 API Key 到内存，新增、修改与用量统计在重启后丢失",
+                    path.display(),
+                    error,
+                    keys
+                );
+                ApiKeyStoreMode::ReadOnly
+            }
+            Err(read_error) => {
+                tracing::error!(
+                    "API Key 数据库无法打开（{}）: {:#}（只读: {:#}）；仅使用配置中的 apiKey，所有修改在重启后丢失",
+                    path.display(),
+                    error,
+                    read_error
+                );
+                ApiKeyStoreMode::Memory
+            }
+        };
+        (
+            Self::with_connection(conn),
+            ApiKeyStoreStatus {
+                mode,
+                error: Some(format!("{:#}", error)),
+            },
+        )
+    }
+
+    /// 空的内存数据库
+    pub(super) fn memory() -> Self {
+        Self::with_connection(memory_connection())
+    }
+
+    fn with_connection(conn: Connection) -> Self {
+        Self {
+            conn: Mutex::new(conn),
+        }
+    }
+}
+
+impl ApiKeyStore for SqliteStore {
+    fn keys(&self) -> anyhow::Result<Vec<KeyRow>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare("SELECT * FROM api_keys")?;
+        let rows = stmt
+            .query_map([], key_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(rows)
+    }
+
+    fn key(&self, id: &str) -> Option<KeyRow> {
+        self.conn
+            .lock()
+            .query_row("SELECT * FROM api_keys WHERE id = ?1", params![id], key_row)
+            .ok()
+    }
+
+    fn insert_key(&self, id: &str, name: &str, key: &str, created_at: &str) -> bool {
+        self.conn
+            .lock()
+            .execute(
+                "INSERT OR IGNORE INTO api_keys (id, name, key, enabled, created_at, request_count, input_tokens, output_tokens) VALUES (?1,?2,?3,1,?4,0,0,0)",
+                params![id, name, key, created_at],
+            )
+            .is_ok_and(|n| n > 0)
+    }
+
+    fn update(&self, id: &str, fields: &[(&str, FieldValue)]) -> bool {
+        let assignments: Vec<String> = fields
+            .iter()
+            .enumerate()
+            .map(|(i, (column, _))| format!("{} = ?{}", column, i + 1))
+            .collect();
+        let sql = format!(
+            "UPDATE api_keys SET {} WHERE id = ?{}",
+            assignments.join(", "),
+            fields.len() + 1
+        );
+        let mut values: Vec<&dyn ToSql> = fields.iter().map(|(_, v)| v as &dyn ToSql).collect();
+        values.push(&id);
+        self.conn
+            .lock()
+            .execute(&sql, values.as_slice())
+            .is_ok_and(|n| n > 0)
+    }
+
+    fn touch(&self, id: &str, last_used_at: &str) {
+        let _ = self.conn.lock().execute(
+            "UPDATE api_keys SET last_used_at = ?1 WHERE id = ?2 AND (last_used_at IS NULL OR last_used_at < ?1)",
+            params![last_used_at, id],
+        );
+    }
+
+    fn record_usage(&self, usage: &UsageRecord<'_>) {
+        let conn = self.conn.lock();
+        let _ = conn.execute(
+            "UPDATE api_keys SET request_count = request_count + 1, input_tokens = input_tokens + ?1, output_tokens = output_tokens + ?2, last_used_at = ?3, spend_micros = spend_micros + ?4 WHERE id = ?5",
+            params![usage.input_tokens, usage.output_tokens, usage.used_at, usage.cost_micros, usage.key_id],
+        );
+        if let Some(user_id) = usage.user_id {
+            let _ = conn.execute(
+                "INSERT INTO api_key_users (key_id, user_id, request_count, input_tokens, output_tokens, last_used_at) VALUES (?1, ?2, 1, ?3, ?4, ?5)
+                ON CONFLICT (key_id, user_id) DO UPDATE SET
+                    request_count = request_count + 1,
+                    input_tokens = input_tokens + excluded.input_tokens,
+                    output_tokens = output_tokens + excluded.output_tokens,
+                    last_used_at = excluded.last_used_at",
+                params![usage.key_id, user_id, usage.input_tokens, usage.output_tokens, usage.used_at],
+            );
+        }
+        // 周期用量：已过重置时间则从本次用量重新累计（月度费用与月度 token 共用重置时间）
+        let _ = conn.execute(
+            "UPDATE api_keys SET
+                daily_tokens_used = CASE WHEN daily_reset_at IS NULL OR daily_reset_at <= ?1 THEN ?2 ELSE daily_tokens_used + ?2 END,
+                daily_reset_at = CASE WHEN daily_reset_at IS NULL OR daily_reset_at <= ?1 THEN ?3 ELSE daily_reset_at END,
+                monthly_tokens_used = CASE WHEN monthly_reset_at IS NULL OR monthly_reset_at <= ?1 THEN ?2 ELSE monthly_tokens_used + ?2 END,
+                monthly_spend_micros = CASE WHEN monthly_reset_at IS NULL OR monthly_reset_at <= ?1 THEN ?6 ELSE monthly_spend_micros + ?6 END,
+                monthly_reset_at = CASE WHEN monthly_reset_at IS NULL OR monthly_reset_at <= ?1 THEN ?4 ELSE monthly_reset_at END
+            WHERE id = ?5",
+            params![
+                usage.period_now,
+                usage.input_tokens + usage.output_tokens,
+                usage.next_day_start,
+                usage.next_month_start,
+                usage.key_id,
+                usage.cost_micros
+            ],
+        );
+    }
+
+    fn user_usage(&self, key_id: Option<&str>) -> Vec<ApiKeyUserUsage> {
+        let conn = self.conn.lock();
+        let Ok(mut stmt) = conn.prepare(
+            "SELECT key_id, user_id, request_count, input_tokens, output_tokens, last_used_at FROM api_key_users
+                WHERE ?1 IS NULL OR key_id = ?1
+                ORDER BY input_tokens + output_tokens DESC",
+        ) else {
+            return Vec::new();
+        };
+        stmt.query_map(params![key_id], |row| {
+            Ok(ApiKeyUserUsage {
+                key_id: row.get(0)?,
+                user_id: row.get(1)?,
+                request_count: row.get::<_, i64>(2)? as u64,
+                input_tokens: row.get::<_, i64>(3)? as u64,
+                output_tokens: row.get::<_, i64>(4)? as u64,
+                last_used_at: row.get(5)?,
+            })
+        })
+        .map(|rows| rows.filter_map(|r| r.ok()).collect())
+        .unwrap_or_default()
+    }
+
+    fn delete_key(&self, id: &str) -> bool {
+        let conn = self.conn.lock();
+        let changed = conn
+            .execute("DELETE FROM api_keys WHERE id = ?1", params![id])
+            .unwrap_or(0);
+        if changed > 0 {
+            let _ = conn.execute("DELETE FROM api_key_users WHERE key_id = ?1", params![id]);
+        }
+        changed > 0
+    }
+}
@@ -0,0 +1,496 @@
+//! 凭据定时加密备份
+//!
+//! 按 `backup` 配置定期将凭据文件与 api_keys.db 打包加密写入备份目录，
+//! 文件名带 UTC 时间戳，超出保留份数时删除最旧的备份。
+//!
+//! 文件格式：`MAGIC | salt(16) | nonce(12) | 密文`，密钥由密码经 PBKDF2-HMAC-SHA256 派生，
+//! 使用 AES-256-GCM 加密；明文由若干 `名称长度(u16) | 名称 | 数据长度(u64) | 数据` 条目组成。
+
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, bail};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::kiro::token_manager::MultiTokenManager;
+use crate::lease::{BACKUP_JOB, Coordinator};
+use crate::model::arg::DecryptBackupArgs;
+use crate::model::config::BackupConfig;
+
+/// 备份文件头
+const MAGIC: &[u8] = b"KIROBAK1";
+
+const SALT_LEN: usize = 16;
+
+/// PBKDF2 迭代次数
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+const FILE_PREFIX: &str = "kiro-backup-";
+const FILE_SUFFIX: &str = ".kbak";
+
+/// api_keys.db 在备份中的条目名
+const API_KEYS_ENTRY: &str = "api_keys.db";
+
+/// 调度器检查间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 备份失败后的重试间隔
+const FAILURE_RETRY_SECS: i64 = 600;
+
+/// 备份文件信息
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupFile {
+    pub name: String,
+    pub size: u64,
+    /// 文件修改时间（RFC3339）
+    pub created_at: String,
+}
+
+/// 最近一次备份的结果
+#[derive(Debug, Default)]
+struct BackupState {
+    last_success_at: Option<DateTime<Utc>>,
+    last_error: Option<(DateTime<Utc>, String)>,
+}
+
+/// 备份管理器
+pub struct BackupManager {
+    credentials_path: PathBuf,
+    api_keys_db: Option<PathBuf>,
+    /// 未配置 `backup.dir` 时使用的目录
+    default_dir: PathBuf,
+    state: Mutex<BackupState>,
+    /// 防止定时备份与手动备份同时进行
+    running: Mutex<()>,
+}
+
+impl BackupManager {
+    pub fn new(
+        credentials_path: PathBuf,
+        api_keys_db: Option<PathBuf>,
+        default_dir: PathBuf,
+    ) -> Self {
+        Self {
+            credentials_path,
+            api_keys_db,
+            default_dir,
+            state: Mutex::new(BackupState::default()),
+            running: Mutex::new(()),
+        }
+    }
+
+    /// 备份目录
+    pub fn dir(&self, config: &BackupConfig) -> PathBuf {
+        config
+            .dir
+            .as_ref()
+            .filter(|d| !d.trim().is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| self.default_dir.clone())
+    }
+
+    /// 最近一次成功备份的时间
+    pub fn last_success_at(&self) -> Option<DateTime<Utc>> {
+        self.state.lock().last_success_at
+    }
+
+    /// 最近一次备份失败的时间与原因（之后成功过则为 None）
+    pub fn last_error(&self) -> Option<(DateTime<Utc>, String)> {
+        self.state.lock().last_error.clone()
+    }
+
+    /// 列出备份目录中的备份文件（按时间从新到旧）
+    pub fn list(&self, config: &BackupConfig) -> Vec<BackupFile> {
+        let mut files: Vec<BackupFile> = backup_paths(&self.dir(config))
+            .into_iter()
+            .filter_map(|path| {
+                let meta = fs::metadata(&path).ok()?;
+                let modified: DateTime<Utc> = meta.modified().ok()?.into();
+                Some(BackupFile {
+                    name: path.file_name()?.to_string_lossy().into_owned(),
+                    size: meta.len(),
+                    created_at: modified.to_rfc3339(),
+                })
+            })
+            .collect();
+        files.reverse();
+        files
+    }
+
+    /// 立即执行一次备份并按保留份数轮转
+    pub fn run(&self, config: &BackupConfig) -> anyhow::Result<BackupFile> {
+        let Some(_running) = self.running.try_lock() else {
+            bail!("备份正在进行中");
+        };
+        let result = self.write_backup(config);
+        let mut state = self.state.lock();
+        match &result {
+            Ok(_) => {
+                state.last_success_at = Some(Utc::now());
+                state.last_error = None;
+            }
+            Err(e) => state.last_error = Some((Utc::now(), format!("{:#}", e))),
+        }
+        result
+    }
+
+    fn write_backup(&self, config: &BackupConfig) -> anyhow::Result<BackupFile> {
+        let password = config
+            .password
+            .as_deref()
+            .filter(|p| !p.is_empty())
+            .context("未设置 backup.password，拒绝写入未加密的备份")?;
+        let dir = self.dir(config);
+        fs::create_dir_all(&dir).with_context(|| format!("创建备份目录失败: {}", dir.display()))?;
+
+        let mut entries = Vec::new();
+        let credentials = fs::read(&self.credentials_path)
+            .with_context(|| format!("读取凭据文件失败: {}", self.credentials_path.display()))?;
+        let credentials_name = self
+            .credentials_path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "credentials.json".to_string());
+        entries.push((credentials_name, credentials));
+        if let Some(db) = self.api_keys_db.as_deref().filter(|p| p.exists()) {
+            entries.push((API_KEYS_ENTRY.to_string(), snapshot_sqlite(db, &dir)?));
+        }
+
+        let encrypted = encrypt(password, &pack(&entries))?;
+        let name = format!(
+            "{}{}{}",
+            FILE_PREFIX,
+            Utc::now().format("%Y%m%dT%H%M%S%.3fZ"),
+            FILE_SUFFIX
+        );
+        let path = dir.join(&name);
+        let tmp = dir.join(format!(".{}.tmp", name));
+        fs::write(&tmp, &encrypted).with_context(|| format!("写入备份失败: {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("写入备份失败: {}", path.display()))?;
+
+        rotate(&dir, config.keep);
+        tracing::info!("已写入备份: {}", path.display());
+        Ok(BackupFile {
+            name,
+            size: encrypted.len() as u64,
+            created_at: Utc::now().to_rfc3339(),
+        })
+    }
+
+    /// 是否到了下一次定时备份的时间
+    fn is_due(&self, config: &BackupConfig) -> bool {
+        let now = Utc::now();
+        let state = self.state.lock();
+        if state
+            .last_error
+            .as_ref()
+            .is_some_and(|(at, _)| (now - *at).num_seconds() < FAILURE_RETRY_SECS)
+        {
+            return false;
+        }
+        // 重启后以目录中最新备份的时间为准，避免每次启动都立即备份
+        let last = state.last_success_at.or_else(|| {
+            backup_paths(&self.dir(config))
+                .last()
+                .and_then(|p| fs::metadata(p).ok()?.modified().ok())
+                .map(DateTime::<Utc>::from)
+        });
+        let interval = config.interval_hours.max(1) as i64 * 3600;
+        last.is_none_or(|at| (now - at).num_seconds() >= interval)
+    }
+
+    /// 启动定时备份任务（每次检查时读取最新配置，支持热重载）
+    ///
+    /// 多实例部署时只有持有备份租约的实例执行备份
+    pub fn spawn_scheduler(
+        self: Arc<Self>,
+        token_manager: Arc<MultiTokenManager>,
+        coordinator: Arc<Coordinator>,
+    ) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let config = token_manager.config();
+                let Some(backup) = config.backup.clone().filter(|b| b.enabled) else {
+                    continue;
+                };
+                if !coordinator.try_acquire(BACKUP_JOB) || !self.is_due(&backup) {
+                    continue;
+                }
+                let manager = self.clone();
+                match tokio::task::spawn_blocking(move || manager.run(&backup)).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => tracing::warn!("定时备份失败: {:#}", e),
+                    Err(e) => tracing::warn!("定时备份任务异常: {}", e),
+                }
+            }
+        });
+    }
+}
+
+/// 目录中的备份文件路径（按文件名即时间从旧到新）
+fn backup_paths(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(FILE_PREFIX) && n.ends_with(FILE_SUFFIX))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// 删除超出保留份数的最旧备份
+fn rotate(dir: &Path, keep: usize) {
+    let paths = backup_paths(dir);
+    let excess = paths.len().saturating_sub(keep.max(1));
+    for path in &paths[..excess] {
+        if let Err(e) = fs::remove_file(path) {
+            tracing::warn!("删除旧备份失败: {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// 通过 `VACUUM INTO` 获取数据库的一致性快照（不阻塞写入方）
+fn snapshot_sqlite(db: &Path, tmp_dir: &Path) -> anyhow::Result<Vec<u8>> {
+    let tmp = tmp_dir.join(format!(".snapshot-{}.db", uuid::Uuid::new_v4().simple()));
+    let result = rusqlite::Connection::open(db)
+        .and_then(|conn| conn.execute("VACUUM INTO ?1", [tmp.to_string_lossy()]))
+        .with_context(|| format!("导出数据库快照失败: {}", db.display()))
+        .and_then(|_| fs::read(&tmp).context("读取数据库快照失败"));
+    let _ = fs::remove_file(&tmp);
+    result
+}
+
+fn pack(entries: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (name, data) in entries {
+        out.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&(data.len() as u64).to_be_bytes());
+        out.extend_from_slice(data);
+    }
+    out
+}
+
+fn unpack(mut data: &[u8]) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    fn take<'a>(data: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+        if data.len() < len {
+            bail!("备份内容不完整");
+        }
+        let (head, rest) = data.split_at(len);
+        *data = rest;
+        Ok(head)
+    }
+
+    let mut entries = Vec::new();
+    while !data.is_empty() {
+        let name_len = u16::from_be_bytes(take(&mut data, 2)?.try_into()?) as usize;
+        let name = String::from_utf8(take(&mut data, name_len)?.to_vec())?;
+        let data_len = u64::from_be_bytes(take(&mut data, 8)?.try_into()?) as usize;
+        entries.push((name, take(&mut data, data_len)?.to_vec()));
+    }
+    Ok(entries)
+}
+
+fn derive_key(password: &str, salt: &[u8]) -> anyhow::Result<LessSafeKey> {
+    let mut key = [0u8; 32];
+    let iterations = NonZeroU32::new(PBKDF2_ITERATIONS).expect("迭代次数非零");
+    ring::pbkdf2::derive(
+        ring::pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        password.as_bytes(),
+        &mut key,
+    );
+    let key = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| anyhow::anyhow!("创建密钥失败"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// 加密备份内容
+fn encrypt(password: &str, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|_| rng.fill(&mut nonce))
+        .map_err(|_| anyhow::anyhow!("生成随机数失败"))?;
+
+    let mut sealed = plaintext.to_vec();
+    derive_key(password, &salt)?
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(MAGIC),
+            &mut sealed,
+        )
+        .map_err(|_| anyhow::anyhow!("加密失败"))?;
+
+    let mut out = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + sealed.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+/// 解密备份内容
+fn decrypt(password: &str, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let header = MAGIC.len() + SALT_LEN + NONCE_LEN;
+    if data.len() < header || !data.starts_with(MAGIC) {
+        bail!("不是有效的备份文件");
+    }
+    let salt = &data[MAGIC.len()..MAGIC.len() + SALT_LEN];
+    let nonce: [u8; NONCE_LEN] = data[MAGIC.len() + SALT_LEN..header].try_into()?;
+    let mut sealed = data[header..].to_vec();
+    let plaintext = derive_key(password, salt)?
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(MAGIC),
+            &mut sealed,
+        )
+        .map_err(|_| anyhow::anyhow!("解密失败：密码错误或文件已损坏"))?;
+    Ok(plaintext.to_vec())
+}
+
+/// 解密备份文件并将其中的文件写入输出目录，返回写入的路径
+pub fn restore(
+    file: &Path,
+    password: &str,
+    out_dir: &Path,
+    force: bool,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let data = fs::read(file).with_context(|| format!("读取备份失败: {}", file.display()))?;
+    let entries = unpack(&decrypt(password, &data)?)?;
+    fs::create_dir_all(out_dir)?;
+
+    let mut written = Vec::new();
+    for (name, content) in entries {
+        // 条目名只允许普通文件名，防止写出输出目录
+        let Some(file_name) = Path::new(&name).file_name().filter(|n| *n == name.as_str()) else {
+            bail!("备份中包含非法条目名: {}", name);
+        };
+        let path = out_dir.join(file_name);
+        if path.exists() && !force {
+            bail!("文件已存在（使用 --force 覆盖）: {}", path.display());
+        }
+        fs::write(&path, content).with_context(|| format!("写入失败: {}", path.display()))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// `decrypt-backup` 子命令
+pub fn run_decrypt(args: &DecryptBackupArgs) {
+    match restore(
+        Path::new(&args.file),
+        &args.password,
+        Path::new(&args.out),
+        args.force,
+    ) {
+        Ok(paths) => {
+            for path in paths {
+                println!("{}", path.display());
+            }
+        }
+        Err(e) => {
+            eprintln!("还原备份失败: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kiro-backup-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn config(dir: &Path, keep: usize) -> BackupConfig {
+        BackupConfig {
+            enabled: true,
+            dir: Some(dir.join("backups").to_string_lossy().into_owned()),
+            keep,
+            password: Some("secret".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_backup_round_trip_and_rotation() {
+        let dir = temp_dir();
+        let credentials = dir.join("credentials.json");
+        fs::write(&credentials, r#"[{"refreshToken":"r"}]"#).unwrap();
+        let db = dir.join("api_keys.db");
+        rusqlite::Connection::open(&db)
+            .unwrap()
+            .execute_batch("CREATE TABLE t (v TEXT); INSERT INTO t VALUES ('k');")
+            .unwrap();
+
+        let manager = BackupManager::new(credentials, Some(db), dir.join("unused"));
+        let config = config(&dir, 2);
+        assert!(manager.is_due(&config));
+        for _ in 0..3 {
+            manager.run(&config).unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(!manager.is_due(&config));
+        let files = manager.list(&config);
+        assert_eq!(files.len(), 2);
+
+        let backup = manager.dir(&config).join(&files[0].name);
+        let raw = fs::read(&backup).unwrap();
+        assert!(!raw.windows(5).any(|w| w == b"refre"));
+        assert!(restore(&backup, "wrong", &dir.join("out"), false).is_err());
+
+        let out = dir.join("out");
+        let written = restore(&backup, "secret", &out, false).unwrap();
+        assert_eq!(written.len(), 2);
+        assert_eq!(
+            fs::read_to_string(out.join("credentials.json")).unwrap(),
+            r#"[{"refreshToken":"r"}]"#
+        );
+        let value: String = rusqlite::Connection::open(out.join("api_keys.db"))
+            .unwrap()
+            .query_row("SELECT v FROM t", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(value, "k");
+        assert!(restore(&backup, "secret", &out, false).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_backup_requires_password() {
+        let dir = temp_dir();
+        let credentials = dir.join("credentials.json");
+        fs::write(&credentials, "{}").unwrap();
+        let manager = BackupManager::new(credentials, None, dir.clone());
+        let config = BackupConfig {
+            password: None,
+            ..config(&dir, 1)
+        };
+        assert!(manager.run(&config).is_err());
+        assert!(manager.last_error().is_some());
+        assert!(manager.list(&config).is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
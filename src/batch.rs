@@ -0,0 +1,551 @@
+//! 消息批处理存储
+//!
+//! 保存 `/v1/messages/batches` 提交的批次、各请求参数与结果。
+//! 配置目录可用时持久化到 `batches.db`，重启后未完成的批次会继续处理；否则使用内存数据库。
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use parking_lot::Mutex;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use utoipa::ToSchema;
+
+/// 批次处理期限，超时未处理的请求记为 expired
+const PROCESSING_WINDOW_HOURS: i64 = 24;
+
+/// 批次（含结果）保留天数
+const RETENTION_DAYS: i64 = 29;
+
+/// 批次状态
+pub const STATUS_IN_PROGRESS: &str = "in_progress";
+pub const STATUS_CANCELING: &str = "canceling";
+pub const STATUS_ENDED: &str = "ended";
+
+/// 各状态的请求数
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct RequestCounts {
+    pub processing: u64,
+    pub succeeded: u64,
+    pub errored: u64,
+    pub canceled: u64,
+    pub expired: u64,
+}
+
+/// 批次对象（与 Anthropic `MessageBatch` 一致）
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MessageBatch {
+    pub id: String,
+    /// 固定为 `message_batch`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// `in_progress` / `canceling` / `ended`
+    pub processing_status: String,
+    pub request_counts: RequestCounts,
+    pub ended_at: Option<String>,
+    pub created_at: String,
+    pub expires_at: String,
+    pub archived_at: Option<String>,
+    pub cancel_initiated_at: Option<String>,
+    /// 批次结束后可下载结果的路径
+    pub results_url: Option<String>,
+}
+
+/// 批次中的单个请求
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct BatchRequestItem {
+    /// 调用方自定义 ID，结果中原样返回，批次内唯一
+    pub custom_id: String,
+    /// `POST /v1/messages` 请求体（`stream` 会被忽略）
+    #[schema(value_type = Object)]
+    pub params: serde_json::Value,
+}
+
+/// 待处理的请求
+#[derive(Debug, Clone)]
+pub struct PendingRequest {
+    pub idx: i64,
+    pub params: String,
+}
+
+/// 单个请求的结果行（results JSONL 的一行）
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+    pub custom_id: String,
+    pub result: serde_json::Value,
+}
+
+/// 删除批次的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteOutcome {
+    Deleted,
+    NotFound,
+    /// 批次尚未结束，需先取消
+    InProgress,
+}
+
+fn now_rfc3339() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// 批次存储与并发控制
+pub struct BatchStore {
+    conn: Mutex<Connection>,
+    /// 所有批次共享的上游并发许可
+    permits: Arc<Semaphore>,
+}
+
+impl BatchStore {
+    /// 创建内存存储（重启后丢失）
+    pub fn new(concurrency: usize) -> anyhow::Result<Self> {
+        Self::open(Connection::open_in_memory()?, concurrency)
+    }
+
+    /// 创建 SQLite 持久化存储
+    pub fn with_store(path: &Path, concurrency: usize) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Self::open(Connection::open(path)?, concurrency)
+    }
+
+    fn open(conn: Connection, concurrency: usize) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "PRAGMA journal_mode=WAL; PRAGMA busy_timeout=5000; PRAGMA foreign_keys=ON;
+            CREATE TABLE IF NOT EXISTS batches (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT NOT NULL UNIQUE,
+                api_key_id TEXT NOT NULL,
+                status TEXT NOT NULL,
+                created_ms INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                expires_at TEXT NOT NULL,
+                ended_at TEXT,
+                cancel_initiated_at TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_batches_key ON batches (api_key_id);
+            CREATE TABLE IF NOT EXISTS batch_requests (
+                batch_id TEXT NOT NULL REFERENCES batches (id) ON DELETE CASCADE,
+                idx INTEGER NOT NULL,
+                custom_id TEXT NOT NULL,
+                params TEXT NOT NULL,
+                result_type TEXT,
+                result TEXT,
+                PRIMARY KEY (batch_id, idx)
+            );",
+        )?;
+        let store = Self {
+            conn: Mutex::new(conn),
+            permits: Arc::new(Semaphore::new(concurrency.max(1))),
+        };
+        store.prune();
+        Ok(store)
+    }
+
+    pub fn permits(&self) -> Arc<Semaphore> {
+        self.permits.clone()
+    }
+
+    /// 清理超过保留期的批次
+    fn prune(&self) {
+        let cutoff = (Utc::now() - Duration::days(RETENTION_DAYS)).timestamp_millis();
+        if let Err(e) = self
+            .conn
+            .lock()
+            .execute("DELETE FROM batches WHERE created_ms < ?1", [cutoff])
+        {
+            tracing::warn!("清理过期批次失败: {}", e);
+        }
+    }
+
+    /// 创建批次
+    pub fn create(
+        &self,
+        api_key_id: &str,
+        requests: &[BatchRequestItem],
+    ) -> anyhow::Result<MessageBatch> {
+        let id = format!("msgbatch_{}", uuid::Uuid::new_v4().simple());
+        let now = Utc::now();
+        let created_at = now.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let expires_at = (now + Duration::hours(PROCESSING_WINDOW_HOURS))
+            .to_rfc3339_opts(SecondsFormat::Secs, true);
+
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO batches (id, api_key_id, status, created_ms, created_at, expires_at) VALUES (?1,?2,?3,?4,?5,?6)",
+            params![
+                id,
+                api_key_id,
+                STATUS_IN_PROGRESS,
+                now.timestamp_millis(),
+                created_at,
+                expires_at
+            ],
+        )?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO batch_requests (batch_id, idx, custom_id, params) VALUES (?1,?2,?3,?4)",
+            )?;
+            for (idx, item) in requests.iter().enumerate() {
+                stmt.execute(params![
+                    id,
+                    idx as i64,
+                    item.custom_id,
+                    item.params.to_string()
+                ])?;
+            }
+        }
+        tx.commit()?;
+        drop(conn);
+
+        self.get(api_key_id, &id)?
+            .ok_or_else(|| anyhow::anyhow!("批次写入后未找到: {}", id))
+    }
+
+    fn load(conn: &Connection, id: &str) -> anyhow::Result<Option<MessageBatch>> {
+        let row = conn
+            .query_row(
+                "SELECT status, created_at, expires_at, ended_at, cancel_initiated_at FROM batches WHERE id = ?1",
+                [id],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((status, created_at, expires_at, ended_at, cancel_initiated_at)) = row else {
+            return Ok(None);
+        };
+
+        let mut counts = RequestCounts::default();
+        let mut stmt = conn.prepare(
+            "SELECT result_type, COUNT(*) FROM batch_requests WHERE batch_id = ?1 GROUP BY result_type",
+        )?;
+        let rows = stmt.query_map([id], |row| {
+            Ok((row.get::<_, Option<String>>(0)?, row.get::<_, i64>(1)?))
+        })?;
+        for row in rows {
+            let (result_type, count) = row?;
+            let count = count as u64;
+            match result_type.as_deref() {
+                None => counts.processing += count,
+                Some("succeeded") => counts.succeeded += count,
+                Some("canceled") => counts.canceled += count,
+                Some("expired") => counts.expired += count,
+                Some(_) => counts.errored += count,
+            }
+        }
+
+        let results_url =
+            (status == STATUS_ENDED).then(|| format!("/v1/messages/batches/{}/results", id));
+        Ok(Some(MessageBatch {
+            id: id.to_string(),
+            kind: "message_batch".to_string(),
+            processing_status: status,
+            request_counts: counts,
+            ended_at,
+            created_at,
+            expires_at,
+            archived_at: None,
+            cancel_initiated_at,
+            results_url,
+        }))
+    }
+
+    /// 获取批次（仅限创建者的 API Key）
+    pub fn get(&self, api_key_id: &str, id: &str) -> anyhow::Result<Option<MessageBatch>> {
+        let conn = self.conn.lock();
+        if !Self::owned_by(&conn, api_key_id, id)? {
+            return Ok(None);
+        }
+        Self::load(&conn, id)
+    }
+
+    fn owned_by(conn: &Connection, api_key_id: &str, id: &str) -> anyhow::Result<bool> {
+        Ok(conn
+            .query_row(
+                "SELECT 1 FROM batches WHERE id = ?1 AND api_key_id = ?2",
+                [id, api_key_id],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    /// 按创建时间倒序列出批次，`after_id` 用于翻页，返回 (批次, 是否还有更多)
+    pub fn list(
+        &self,
+        api_key_id: &str,
+        limit: usize,
+        after_id: Option<&str>,
+    ) -> anyhow::Result<(Vec<MessageBatch>, bool)> {
+        let conn = self.conn.lock();
+        let ids: Vec<String> = {
+            let mut stmt = conn.prepare(
+                "SELECT id FROM batches WHERE api_key_id = ?1
+                 AND seq < COALESCE((SELECT seq FROM batches WHERE id = ?2), 9223372036854775807)
+                 ORDER BY seq DESC LIMIT ?3",
+            )?;
+            stmt.query_map(params![api_key_id, after_id, limit as i64 + 1], |row| {
+                row.get(0)
+            })?
+            .collect::<Result<_, _>>()?
+        };
+        let has_more = ids.len() > limit;
+        let mut batches = Vec::with_capacity(limit);
+        for id in ids.iter().take(limit) {
+            if let Some(batch) = Self::load(&conn, id)? {
+                batches.push(batch);
+            }
+        }
+        Ok((batches, has_more))
+    }
+
+    /// 发起取消：未开始的请求会被标记为 canceled，已在处理的请求照常完成
+    pub fn cancel(&self, api_key_id: &str, id: &str) -> anyhow::Result<Option<MessageBatch>> {
+        let conn = self.conn.lock();
+        if !Self::owned_by(&conn, api_key_id, id)? {
+            return Ok(None);
+        }
+        conn.execute(
+            "UPDATE batches SET status = ?1, cancel_initiated_at = ?2 WHERE id = ?3 AND status = ?4",
+            params![STATUS_CANCELING, now_rfc3339(), id, STATUS_IN_PROGRESS],
+        )?;
+        Self::load(&conn, id)
+    }
+
+    /// 删除已结束的批次及其结果
+    pub fn delete(&self, api_key_id: &str, id: &str) -> anyhow::Result<DeleteOutcome> {
+        let conn = self.conn.lock();
+        let status: Option<String> = conn
+            .query_row(
+                "SELECT status FROM batches WHERE id = ?1 AND api_key_id = ?2",
+                [id, api_key_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match status.as_deref() {
+            None => Ok(DeleteOutcome::NotFound),
+            Some(STATUS_ENDED) => {
+                conn.execute("DELETE FROM batches WHERE id = ?1", [id])?;
+                Ok(DeleteOutcome::Deleted)
+            }
+            Some(_) => Ok(DeleteOutcome::InProgress),
+        }
+    }
+
+    /// 按提交顺序返回已产生的结果，批次不存在时返回 None
+    pub fn results(&self, api_key_id: &str, id: &str) -> anyhow::Result<Option<Vec<BatchResult>>> {
+        let conn = self.conn.lock();
+        if !Self::owned_by(&conn, api_key_id, id)? {
+            return Ok(None);
+        }
+        let mut stmt = conn.prepare(
+            "SELECT custom_id, result FROM batch_requests WHERE batch_id = ?1 AND result IS NOT NULL ORDER BY idx",
+        )?;
+        let results = stmt
+            .query_map([id], |row| {
+                let result: String = row.get(1)?;
+                Ok(BatchResult {
+                    custom_id: row.get(0)?,
+                    result: serde_json::from_str(&result).unwrap_or_default(),
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(Some(results))
+    }
+
+    /// 未结束的批次（启动时恢复处理），返回 (批次 ID, API Key ID)
+    pub fn unfinished(&self) -> anyhow::Result<Vec<(String, String)>> {
+        let conn = self.conn.lock();
+        let mut stmt =
+            conn.prepare("SELECT id, api_key_id FROM batches WHERE status != ?1 ORDER BY seq")?;
+        let rows = stmt
+            .query_map([STATUS_ENDED], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        Ok(rows)
+    }
+
+    /// 尚未产生结果的请求
+    pub fn pending(&self, id: &str) -> anyhow::Result<Vec<PendingRequest>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            "SELECT idx, params FROM batch_requests WHERE batch_id = ?1 AND result IS NULL ORDER BY idx",
+        )?;
+        let rows = stmt
+            .query_map([id], |row| {
+                Ok(PendingRequest {
+                    idx: row.get(0)?,
+                    params: row.get(1)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(rows)
+    }
+
+    /// 批次是否已发起取消（已删除的批次也视为取消）
+    pub fn is_canceling(&self, id: &str) -> bool {
+        let status: Option<String> = self
+            .conn
+            .lock()
+            .query_row("SELECT status FROM batches WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .optional()
+            .unwrap_or_default();
+        status.is_none_or(|s| s == STATUS_CANCELING)
+    }
+
+    /// 批次是否已超过处理期限
+    pub fn is_expired(&self, id: &str) -> bool {
+        let expires_at: Option<String> = self
+            .conn
+            .lock()
+            .query_row(
+                "SELECT expires_at FROM batches WHERE id = ?1",
+                [id],
+                |row| row.get(0),
+            )
+            .optional()
+            .unwrap_or_default();
+        expires_at
+            .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+            .is_some_and(|t| t < Utc::now())
+    }
+
+    /// 写入单个请求的结果（`result` 为 Anthropic 批处理结果对象，含 `type` 字段）
+    pub fn set_result(&self, id: &str, idx: i64, result: &serde_json::Value) {
+        let result_type = result["type"].as_str().unwrap_or("errored");
+        if let Err(e) = self.conn.lock().execute(
+            "UPDATE batch_requests SET result_type = ?1, result = ?2 WHERE batch_id = ?3 AND idx = ?4",
+            params![result_type, result.to_string(), id, idx],
+        ) {
+            tracing::warn!("写入批处理结果失败: {}: {}", id, e);
+        }
+    }
+
+    /// 结束批次：剩余未处理的请求按 `remaining_type`（canceled / expired）记录
+    pub fn finish(&self, id: &str, remaining_type: &str) {
+        let result = serde_json::json!({ "type": remaining_type }).to_string();
+        let conn = self.conn.lock();
+        let outcome = conn
+            .execute(
+                "UPDATE batch_requests SET result_type = ?1, result = ?2 WHERE batch_id = ?3 AND result IS NULL",
+                params![remaining_type, result, id],
+            )
+            .and_then(|_| {
+                conn.execute(
+                    "UPDATE batches SET status = ?1, ended_at = ?2 WHERE id = ?3",
+                    params![STATUS_ENDED, now_rfc3339(), id],
+                )
+            });
+        if let Err(e) = outcome {
+            tracing::warn!("结束批次失败: {}: {}", id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn items(n: usize) -> Vec<BatchRequestItem> {
+        (0..n)
+            .map(|i| BatchRequestItem {
+                custom_id: format!("req-{}", i),
+                params: json!({"model": "claude-sonnet-4-6", "max_tokens": 16, "messages": []}),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_batch_lifecycle() {
+        let store = BatchStore::new(2).unwrap();
+        let batch = store.create("default", &items(3)).unwrap();
+        assert!(batch.id.starts_with("msgbatch_"));
+        assert_eq!(batch.processing_status, STATUS_IN_PROGRESS);
+        assert_eq!(batch.request_counts.processing, 3);
+        assert!(batch.results_url.is_none());
+
+        // 其他 Key 不可见
+        assert!(store.get("other", &batch.id).unwrap().is_none());
+        assert_eq!(
+            store.delete("default", &batch.id).unwrap(),
+            DeleteOutcome::InProgress
+        );
+
+        let pending = store.pending(&batch.id).unwrap();
+        store.set_result(
+            &batch.id,
+            pending[0].idx,
+            &json!({"type": "succeeded", "message": {"id": "msg_1"}}),
+        );
+        store.set_result(
+            &batch.id,
+            pending[1].idx,
+            &json!({"type": "errored", "error": {"type": "error"}}),
+        );
+
+        let batch = store.cancel("default", &batch.id).unwrap().unwrap();
+        assert_eq!(batch.processing_status, STATUS_CANCELING);
+        assert!(batch.cancel_initiated_at.is_some());
+        assert!(store.is_canceling(&batch.id));
+
+        store.finish(&batch.id, "canceled");
+        let batch = store.get("default", &batch.id).unwrap().unwrap();
+        assert_eq!(batch.processing_status, STATUS_ENDED);
+        assert_eq!(
+            batch.request_counts,
+            RequestCounts {
+                processing: 0,
+                succeeded: 1,
+                errored: 1,
+                canceled: 1,
+                expired: 0,
+            }
+        );
+        assert!(batch.results_url.is_some());
+
+        let results = store.results("default", &batch.id).unwrap().unwrap();
+        let ids: Vec<_> = results.iter().map(|r| r.custom_id.as_str()).collect();
+        assert_eq!(ids, ["req-0", "req-1", "req-2"]);
+        assert_eq!(results[2].result["type"], "canceled");
+
+        assert!(store.unfinished().unwrap().is_empty());
+        assert_eq!(
+            store.delete("default", &batch.id).unwrap(),
+            DeleteOutcome::Deleted
+        );
+        assert!(store.get("default", &batch.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_list_pagination() {
+        let store = BatchStore::new(1).unwrap();
+        let ids: Vec<String> = (0..3)
+            .map(|_| store.create("default", &items(1)).unwrap().id)
+            .collect();
+        store.create("other", &items(1)).unwrap();
+
+        let (page, has_more) = store.list("default", 2, None).unwrap();
+        assert!(has_more);
+        assert_eq!(page[0].id, ids[2]);
+        assert_eq!(page[1].id, ids[1]);
+
+        let (page, has_more) = store.list("default", 2, Some(&ids[1])).unwrap();
+        assert!(!has_more);
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].id, ids[0]);
+        assert_eq!(store.unfinished().unwrap().len(), 4);
+    }
+}
@@ -0,0 +1,344 @@
+//! 进程内基准测试（`kiro-rs bench`）
+//!
+//! 生成合成的 AWS Event Stream 响应，走完整的
+//! 解码 → `Event` → `StreamContext` → SSE 序列化流程，
+//! 输出吞吐量与内存分配次数，用于度量热路径的性能回归。
+//! `--auth` 改为测量 API Key 认证的单次耗时。
+//! 内存分配统计依赖计数分配器，仅在以 `bench-alloc` 特性构建时安装，生产构建不受影响。
+
+#[cfg(feature = "bench-alloc")]
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::anthropic::stream::{SseEvent, StreamContext};
+use crate::apikeys::ApiKeyManager;
+use crate::kiro::model::events::Event;
+use crate::kiro::parser::crc::crc32;
+use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::model::arg::BenchArgs;
+
+/// 统计内存分配的全局分配器（`bench-alloc` 特性）
+///
+/// 仅在基准测试运行期间计数，平时只多一次原子读
+#[cfg(feature = "bench-alloc")]
+pub struct CountingAllocator;
+
+/// 是否安装了计数分配器（未安装时分配次数始终为 0，不输出）
+const COUNTS_ALLOCATIONS: bool = cfg!(feature = "bench-alloc");
+
+/// 未统计内存分配时的提示
+const ALLOCATIONS_UNAVAILABLE: &str = "内存分配: 未统计（需以 --features bench-alloc 构建）";
+
+static COUNTING: AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(feature = "bench-alloc")]
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.load(Ordering::Relaxed) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        }
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if COUNTING.load(Ordering::Relaxed) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            ALLOCATED_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        }
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+/// 单次流水线处理的统计
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct PassStats {
+    frames: usize,
+    sse_events: usize,
+    sse_bytes: usize,
+}
+
+/// 编码单个事件帧（`:message-type` = event）
+pub(crate) fn encode_frame(event_type: &str, payload: &serde_json::Value) -> Vec<u8> {
+    let mut headers = Vec::new();
+    for (name, value) in [
+        (":message-type", "event"),
+        (":event-type", event_type),
+        (":content-type", "application/json"),
+    ] {
+        headers.push(name.len() as u8);
+        headers.extend_from_slice(name.as_bytes());
+        headers.push(7); // String
+        headers.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        headers.extend_from_slice(value.as_bytes());
+    }
+    let payload = serde_json::to_vec(payload).unwrap_or_default();
+
+    let total_len = 12 + headers.len() + payload.len() + 4;
+    let mut frame = Vec::with_capacity(total_len);
+    frame.extend_from_slice(&(total_len as u32).to_be_bytes());
+    frame.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+    let prelude_crc = crc32(&frame);
+    frame.extend_from_slice(&prelude_crc.to_be_bytes());
+    frame.extend_from_slice(&headers);
+    frame.extend_from_slice(&payload);
+    let message_crc = crc32(&frame);
+    frame.extend_from_slice(&message_crc.to_be_bytes());
+    frame
+}
+
+/// 生成一条合成的上游响应流
+fn synthetic_stream(args: &BenchArgs) -> Vec<u8> {
+    const TEXT: &str = "The quick brown fox jumps over the lazy dog. ";
+
+    let mut stream = Vec::new();
+    let mut push = |event_type: &str, payload: serde_json::Value| {
+        stream.extend(encode_frame(event_type, &payload));
+    };
+
+    if args.thinking {
+        push("assistantResponseEvent", json!({"content": "<thinking>\n"}));
+        for _ in 0..args.events / 4 {
+            push("assistantResponseEvent", json!({"content": TEXT}));
+        }
+        push(
+            "assistantResponseEvent",
+            json!({"content": "</thinking>\n\n"}),
+        );
+    }
+    for _ in 0..args.events {
+        push("assistantResponseEvent", json!({"content": TEXT}));
+    }
+    for i in 0..args.tools {
+        let id = format!("tooluse_bench_{}", i);
+        for part in ["{\"path\": ", "\"src/main.rs\", ", "\"line\": 42}"] {
+            push(
+                "toolUseEvent",
+                json!({"name": "read_file", "toolUseId": id, "input": part}),
+            );
+        }
+        push(
+            "toolUseEvent",
+            json!({"name": "read_file", "toolUseId": id, "input": "", "stop": true}),
+        );
+    }
+    push("meteringEvent", json!({"unit": "credit", "usage": 0.01}));
+    push("contextUsageEvent", json!({"contextUsagePercentage": 12.5}));
+    stream
+}
+
+/// 按 `chunk_size` 分块喂给解码器，跑完整条流水线
+fn run_pass(stream: &[u8], chunk_size: usize, thinking: bool) -> PassStats {
+    let mut stats = PassStats::default();
+    let mut decoder = EventStreamDecoder::new();
+    let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4-6", 1000, thinking);
+
+    let emit = |events: Vec<SseEvent>, stats: &mut PassStats| {
+        for event in events {
+            stats.sse_events += 1;
+            stats.sse_bytes += event.to_sse_string().len();
+        }
+    };
+
+    emit(ctx.generate_initial_events(), &mut stats);
+    for chunk in stream.chunks(chunk_size.max(1)) {
+        if let Err(e) = decoder.feed(chunk) {
+            eprintln!("解码器缓冲区错误: {}", e);
+            break;
+        }
+        for result in decoder.decode_iter() {
+            let Ok(frame) = result else { continue };
+            stats.frames += 1;
+            if let Ok(event) = Event::from_frame(frame) {
+                emit(ctx.process_kiro_event(&event), &mut stats);
+            }
+        }
+    }
+    emit(ctx.generate_final_events(), &mut stats);
+    stats
+}
+
+/// 在内存数据库中创建 `keys` 个 API Key，返回最后创建的 Key（认证时需遍历全部 Key）
+fn auth_fixture(keys: usize) -> (ApiKeyManager, String) {
+    let manager = ApiKeyManager::new("sk-bench-initial".to_string(), None);
+    let mut last = "sk-bench-initial".to_string();
+    for i in 0..keys {
+        last = manager.create_key(format!("bench-{}", i)).key;
+    }
+    (manager, last)
+}
+
+/// 计时 `iterations` 次认证，返回单次耗时与内存分配次数
+fn time_auth(iterations: usize, mut authenticate: impl FnMut() -> bool) -> (Duration, f64) {
+    ALLOCATIONS.store(0, Ordering::Relaxed);
+    COUNTING.store(true, Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..iterations {
+        assert!(authenticate(), "基准测试的 API Key 认证失败");
+    }
+    let elapsed = start.elapsed();
+    COUNTING.store(false, Ordering::Relaxed);
+    (
+        elapsed / iterations as u32,
+        ALLOCATIONS.load(Ordering::Relaxed) as f64 / iterations as f64,
+    )
+}
+
+/// API Key 认证基准：内存缓存命中 vs 每次清空缓存、从 SQLite 重新加载
+fn run_auth(args: &BenchArgs) {
+    let (manager, key) = auth_fixture(args.keys);
+    let iterations = args.iterations.max(1);
+    println!("API Key 数: {}, 认证次数: {}", args.keys + 1, iterations);
+
+    let (cached, cached_allocs) = time_auth(iterations, || manager.authenticate(&key).is_some());
+    let (uncached, uncached_allocs) = time_auth(iterations, || {
+        manager.invalidate_key_cache();
+        manager.authenticate(&key).is_some()
+    });
+    if COUNTS_ALLOCATIONS {
+        println!(
+            "内存缓存: {:.3?}/次, 内存分配 {:.1} 次/次",
+            cached, cached_allocs
+        );
+        println!(
+            "查询 SQLite: {:.3?}/次, 内存分配 {:.1} 次/次",
+            uncached, uncached_allocs
+        );
+    } else {
+        println!("内存缓存: {:.3?}/次", cached);
+        println!("查询 SQLite: {:.3?}/次", uncached);
+        println!("{}", ALLOCATIONS_UNAVAILABLE);
+    }
+    println!(
+        "缓存加速: {:.1}x",
+        uncached.as_secs_f64() / cached.as_secs_f64().max(f64::EPSILON)
+    );
+}
+
+/// 运行基准测试并打印结果
+pub fn run(args: &BenchArgs) {
+    if args.auth {
+        run_auth(args);
+        return;
+    }
+    let stream = synthetic_stream(args);
+    let iterations = args.iterations.max(1);
+
+    println!(
+        "合成响应: {} 字节, 文本事件 {}, 工具调用 {}, thinking {}, 分块 {} 字节",
+        stream.len(),
+        args.events,
+        args.tools,
+        if args.thinking { "开" } else { "关" },
+        args.chunk_size
+    );
+
+    // 预热，避免首轮的惰性初始化干扰结果
+    for _ in 0..iterations.min(10) {
+        run_pass(&stream, args.chunk_size, args.thinking);
+    }
+
+    let mut last = PassStats::default();
+    ALLOCATIONS.store(0, Ordering::Relaxed);
+    ALLOCATED_BYTES.store(0, Ordering::Relaxed);
+    COUNTING.store(true, Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..iterations {
+        last = run_pass(&stream, args.chunk_size, args.thinking);
+    }
+    let elapsed = start.elapsed();
+    COUNTING.store(false, Ordering::Relaxed);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let allocated_bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    let mib = |bytes: f64| bytes / (1024.0 * 1024.0);
+    let n = iterations as f64;
+    println!("迭代次数: {}, 总耗时: {:.3?}", iterations, elapsed);
+    println!(
+        "单次响应: {:.3?}（{} 帧 → {} 个 SSE 事件, {} 字节）",
+        elapsed / iterations as u32,
+        last.frames,
+        last.sse_events,
+        last.sse_bytes
+    );
+    println!(
+        "吞吐量: {:.0} 响应/s, {:.0} 帧/s, 输入 {:.1} MiB/s, 输出 {:.1} MiB/s",
+        n / secs,
+        (last.frames as f64 * n) / secs,
+        mib(stream.len() as f64 * n) / secs,
+        mib(last.sse_bytes as f64 * n) / secs
+    );
+    if COUNTS_ALLOCATIONS {
+        println!(
+            "内存分配: {:.0} 次/响应, {:.1} 次/帧, {:.1} KiB/响应",
+            allocations as f64 / n,
+            allocations as f64 / (last.frames as f64 * n).max(1.0),
+            allocated_bytes as f64 / n / 1024.0
+        );
+    } else {
+        println!("{}", ALLOCATIONS_UNAVAILABLE);
+    }
+    if elapsed < Duration::from_millis(100) {
+        println!("提示: 总耗时过短，结果波动较大，可增大 --iterations");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(thinking: bool) -> BenchArgs {
+        BenchArgs {
+            iterations: 1,
+            events: 8,
+            tools: 2,
+            chunk_size: 7,
+            thinking,
+            auth: false,
+            keys: 0,
+        }
+    }
+
+    #[test]
+    fn test_synthetic_stream_runs_through_pipeline() {
+        let args = args(false);
+        let stream = synthetic_stream(&args);
+        let stats = run_pass(&stream, args.chunk_size, args.thinking);
+
+        // 8 文本 + 2 × (3 段参数 + stop) + metering + contextUsage
+        assert_eq!(stats.frames, 8 + 2 * 4 + 2);
+        // 与整块喂入的结果一致
+        assert_eq!(stats, run_pass(&stream, stream.len(), args.thinking));
+        assert!(stats.sse_events > stats.frames);
+    }
+
+    #[test]
+    fn test_thinking_stream_emits_thinking_block() {
+        let args = args(true);
+        let stream = synthetic_stream(&args);
+        let mut decoder = EventStreamDecoder::new();
+        decoder.feed(&stream).unwrap();
+        let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4-6", 1000, true);
+        let events: Vec<_> = decoder
+            .decode_iter()
+            .filter_map(|r| r.ok())
+            .filter_map(|f| Event::from_frame(f).ok())
+            .flat_map(|e| ctx.process_kiro_event(&e))
+            .collect();
+        assert!(
+            events
+                .iter()
+                .any(|e| e.data["delta"]["type"] == "thinking_delta")
+        );
+    }
+}
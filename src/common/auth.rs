@@ -30,6 +30,27 @@ pub fn extract_api_key(request: &Request<Body>) -> Option<String> {
         .map(|s| s.to_string())
 }
 
+/// 提取 Gemini 客户端使用的 API Key
+///
+/// 支持 `x-goog-api-key` header 与 `?key=` 查询参数，仅用于 API 端点（Admin 不接受）
+pub fn extract_gemini_api_key(request: &Request<Body>) -> Option<String> {
+    if let Some(key) = request
+        .headers()
+        .get("x-goog-api-key")
+        .and_then(|v| v.to_str().ok())
+    {
+        return Some(key.to_string());
+    }
+
+    request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("key="))
+            .filter(|key| !key.is_empty())
+            .map(|key| key.to_string())
+    })
+}
+
 /// 常量时间字符串比较，防止时序攻击
 ///
 /// 无论字符串内容如何，比较所需的时间都是恒定的，
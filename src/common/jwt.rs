@@ -0,0 +1,282 @@
+//! JWT 认证
+//!
+//! 校验 HS256（共享密钥）或 JWKS 公钥签名的 JWT，并从 claims 中提取身份与限流配置。
+
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, decode_header};
+use parking_lot::RwLock;
+
+use crate::model::config::JwtConfig;
+
+/// JWKS 缓存有效期
+const JWKS_CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// 遇到未知 kid 时强制刷新 JWKS 的最小间隔（防止被无效令牌刷爆）
+const JWKS_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// JWKS 请求超时
+const JWKS_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 通过校验的 JWT 身份
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JwtIdentity {
+    pub subject: String,
+    pub rpm_limit: Option<u64>,
+    pub tpm_limit: Option<u64>,
+}
+
+/// JWT 校验错误
+#[derive(Debug)]
+pub enum JwtError {
+    /// 签名、有效期、iss / aud 等校验失败
+    Invalid(jsonwebtoken::errors::Error),
+    /// 签名算法与配置不匹配（HS 算法需要 secret，其他算法需要 jwksUrl）
+    UnsupportedAlgorithm(Algorithm),
+    /// JWKS 中找不到对应的公钥
+    KeyNotFound(Option<String>),
+    /// 获取 JWKS 失败
+    Jwks(String),
+    /// 缺少身份 claim
+    MissingSubject(String),
+}
+
+impl std::fmt::Display for JwtError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JwtError::Invalid(e) => write!(f, "JWT 校验失败: {}", e),
+            JwtError::UnsupportedAlgorithm(alg) => write!(f, "未配置 {:?} 算法所需的密钥", alg),
+            JwtError::KeyNotFound(kid) => write!(f, "JWKS 中找不到公钥: kid={:?}", kid),
+            JwtError::Jwks(e) => write!(f, "获取 JWKS 失败: {}", e),
+            JwtError::MissingSubject(claim) => write!(f, "JWT 缺少身份 claim: {}", claim),
+        }
+    }
+}
+
+impl std::error::Error for JwtError {}
+
+/// 形如 JWT（`eyJ` 开头的三段式）的令牌
+pub fn looks_like_jwt(token: &str) -> bool {
+    token.starts_with("eyJ") && token.matches('.').count() == 2
+}
+
+struct CachedJwks {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+/// JWT 校验器
+pub struct JwtVerifier {
+    config: JwtConfig,
+    client: reqwest::Client,
+    jwks: RwLock<Option<CachedJwks>>,
+}
+
+impl JwtVerifier {
+    pub fn new(config: JwtConfig) -> anyhow::Result<Self> {
+        let has_secret = config.secret.as_deref().is_some_and(|s| !s.is_empty());
+        let has_jwks = config.jwks_url.as_deref().is_some_and(|u| !u.is_empty());
+        if !has_secret && !has_jwks {
+            anyhow::bail!("jwt 配置需要 secret 或 jwksUrl");
+        }
+        let client = reqwest::Client::builder()
+            .timeout(JWKS_FETCH_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            config,
+            client,
+            jwks: RwLock::new(None),
+        })
+    }
+
+    /// 校验令牌并提取身份
+    pub async fn verify(&self, token: &str) -> Result<JwtIdentity, JwtError> {
+        let header = decode_header(token).map_err(JwtError::Invalid)?;
+        let key = match header.alg {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => match &self.config.secret {
+                Some(secret) if !secret.is_empty() => DecodingKey::from_secret(secret.as_bytes()),
+                _ => return Err(JwtError::UnsupportedAlgorithm(header.alg)),
+            },
+            alg => {
+                if self.config.jwks_url.is_none() {
+                    return Err(JwtError::UnsupportedAlgorithm(alg));
+                }
+                self.jwks_key(header.kid.as_deref()).await?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        match &self.config.audience {
+            Some(aud) => validation.set_audience(&[aud]),
+            None => validation.validate_aud = false,
+        }
+        if let Some(iss) = &self.config.issuer {
+            validation.set_issuer(&[iss]);
+        }
+
+        let claims = decode::<serde_json::Value>(token, &key, &validation)
+            .map_err(JwtError::Invalid)?
+            .claims;
+        self.identity(&claims)
+    }
+
+    /// 从 claims 中提取身份与限流配置
+    fn identity(&self, claims: &serde_json::Value) -> Result<JwtIdentity, JwtError> {
+        let subject = match &claims[&self.config.subject_claim] {
+            serde_json::Value::String(s) if !s.is_empty() => s.clone(),
+            serde_json::Value::Number(n) => n.to_string(),
+            _ => {
+                return Err(JwtError::MissingSubject(self.config.subject_claim.clone()));
+            }
+        };
+        Ok(JwtIdentity {
+            subject,
+            rpm_limit: claims[&self.config.rpm_claim].as_u64(),
+            tpm_limit: claims[&self.config.tpm_claim].as_u64(),
+        })
+    }
+
+    /// 按 kid 查找 JWKS 公钥，缓存过期或 kid 未知时重新拉取
+    async fn jwks_key(&self, kid: Option<&str>) -> Result<DecodingKey, JwtError> {
+        let cached = self
+            .jwks
+            .read()
+            .as_ref()
+            .map(|c| (c.keys.clone(), c.fetched_at));
+        let keys = match cached {
+            Some((keys, fetched_at)) if fetched_at.elapsed() < JWKS_CACHE_TTL => {
+                match find_key(&keys, kid) {
+                    Some(key) => return Ok(key),
+                    None if fetched_at.elapsed() < JWKS_MIN_REFRESH_INTERVAL => {
+                        return Err(JwtError::KeyNotFound(kid.map(str::to_string)));
+                    }
+                    None => self.fetch_jwks().await?,
+                }
+            }
+            _ => self.fetch_jwks().await?,
+        };
+        find_key(&keys, kid).ok_or_else(|| JwtError::KeyNotFound(kid.map(str::to_string)))
+    }
+
+    async fn fetch_jwks(&self) -> Result<JwkSet, JwtError> {
+        let url = self.config.jwks_url.as_deref().unwrap_or_default();
+        let keys: JwkSet = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| JwtError::Jwks(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| JwtError::Jwks(e.to_string()))?;
+        tracing::debug!("已刷新 JWKS，共 {} 个公钥", keys.keys.len());
+        *self.jwks.write() = Some(CachedJwks {
+            keys: keys.clone(),
+            fetched_at: Instant::now(),
+        });
+        Ok(keys)
+    }
+}
+
+/// 按 kid 查找公钥；令牌未带 kid 且 JWKS 仅有一个公钥时使用该公钥
+fn find_key(keys: &JwkSet, kid: Option<&str>) -> Option<DecodingKey> {
+    let jwk = match kid {
+        Some(kid) => keys.find(kid)?,
+        None if keys.keys.len() == 1 => &keys.keys[0],
+        None => return None,
+    };
+    DecodingKey::from_jwk(jwk).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use serde_json::json;
+
+    fn config() -> JwtConfig {
+        JwtConfig {
+            secret: Some("test-secret".to_string()),
+            jwks_url: None,
+            issuer: Some("https://idp.example.com".to_string()),
+            audience: None,
+            subject_claim: "sub".to_string(),
+            rpm_claim: "rpm_limit".to_string(),
+            tpm_claim: "tpm_limit".to_string(),
+        }
+    }
+
+    fn sign(claims: serde_json::Value, secret: &str) -> String {
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    fn exp(offset_secs: i64) -> i64 {
+        chrono::Utc::now().timestamp() + offset_secs
+    }
+
+    #[tokio::test]
+    async fn test_hs256_token_maps_claims() {
+        let verifier = JwtVerifier::new(config()).unwrap();
+        let token = sign(
+            json!({"sub": "alice", "iss": "https://idp.example.com", "exp": exp(300), "rpm_limit": 10}),
+            "test-secret",
+        );
+        assert!(looks_like_jwt(&token));
+
+        let identity = verifier.verify(&token).await.unwrap();
+        assert_eq!(identity.subject, "alice");
+        assert_eq!(identity.rpm_limit, Some(10));
+        assert_eq!(identity.tpm_limit, None);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_tokens() {
+        let verifier = JwtVerifier::new(config()).unwrap();
+        let iss = "https://idp.example.com";
+
+        let wrong_secret = sign(json!({"sub": "a", "iss": iss, "exp": exp(300)}), "other");
+        assert!(matches!(
+            verifier.verify(&wrong_secret).await,
+            Err(JwtError::Invalid(_))
+        ));
+
+        let expired = sign(
+            json!({"sub": "a", "iss": iss, "exp": exp(-3600)}),
+            "test-secret",
+        );
+        assert!(matches!(
+            verifier.verify(&expired).await,
+            Err(JwtError::Invalid(_))
+        ));
+
+        let wrong_issuer = sign(
+            json!({"sub": "a", "iss": "https://other.example.com", "exp": exp(300)}),
+            "test-secret",
+        );
+        assert!(matches!(
+            verifier.verify(&wrong_issuer).await,
+            Err(JwtError::Invalid(_))
+        ));
+
+        let no_subject = sign(json!({"iss": iss, "exp": exp(300)}), "test-secret");
+        assert!(matches!(
+            verifier.verify(&no_subject).await,
+            Err(JwtError::MissingSubject(_))
+        ));
+    }
+
+    #[test]
+    fn test_requires_secret_or_jwks() {
+        let mut config = config();
+        config.secret = None;
+        assert!(JwtVerifier::new(config).is_err());
+        assert!(!looks_like_jwt("sk-plain-api-key"));
+    }
+}
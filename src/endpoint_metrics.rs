@@ -0,0 +1,221 @@
+//! 端点族（/v1 与 /cc/v1）独立的健康指标
+//!
+//! /cc/v1 走缓冲流路径，失败模式与 /v1 不同，分开统计便于针对性告警。
+//! 延迟按整个响应体发送完毕（或客户端断开）计算，流式请求同样适用。
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// 滑动窗口时长
+const WINDOW: Duration = Duration::from_secs(300);
+
+/// 每个端点族最多保留的样本数
+const MAX_SAMPLES: usize = 4096;
+
+/// 端点族
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointFamily {
+    /// `/v1/*`
+    V1,
+    /// `/cc/v1/*`（缓冲流）
+    Cc,
+}
+
+struct Sample {
+    at: Instant,
+    latency_ms: u64,
+    status: u16,
+}
+
+/// 单个端点族的计数与最近样本
+#[derive(Default)]
+struct FamilyMetrics {
+    requests: AtomicU64,
+    client_errors: AtomicU64,
+    server_errors: AtomicU64,
+    samples: Mutex<VecDeque<Sample>>,
+}
+
+impl FamilyMetrics {
+    fn record(&self, status: u16, latency: Duration, now: Instant) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        match status {
+            400..=499 => self.client_errors.fetch_add(1, Ordering::Relaxed),
+            500..=599 => self.server_errors.fetch_add(1, Ordering::Relaxed),
+            _ => 0,
+        };
+
+        let mut samples = self.samples.lock();
+        samples.push_back(Sample {
+            at: now,
+            latency_ms: latency.as_millis() as u64,
+            status,
+        });
+        while samples.len() > MAX_SAMPLES {
+            samples.pop_front();
+        }
+    }
+
+    fn snapshot(&self, now: Instant) -> FamilyHealth {
+        let mut samples = self.samples.lock();
+        while samples
+            .front()
+            .is_some_and(|s| now.duration_since(s.at) > WINDOW)
+        {
+            samples.pop_front();
+        }
+
+        let mut latencies: Vec<u64> = samples.iter().map(|s| s.latency_ms).collect();
+        latencies.sort_unstable();
+        let window_errors = samples.iter().filter(|s| s.status >= 500).count() as u64;
+        let window_requests = samples.len() as u64;
+
+        FamilyHealth {
+            total_requests: self.requests.load(Ordering::Relaxed),
+            total_client_errors: self.client_errors.load(Ordering::Relaxed),
+            total_server_errors: self.server_errors.load(Ordering::Relaxed),
+            window: WindowHealth {
+                window_secs: WINDOW.as_secs(),
+                requests: window_requests,
+                server_errors: window_errors,
+                error_rate: if window_requests == 0 {
+                    0.0
+                } else {
+                    window_errors as f64 / window_requests as f64
+                },
+                p50_latency_ms: percentile(&latencies, 50),
+                p95_latency_ms: percentile(&latencies, 95),
+                max_latency_ms: latencies.last().copied().unwrap_or(0),
+            },
+        }
+    }
+}
+
+/// 已排序样本的百分位数（最近秩法）
+fn percentile(sorted: &[u64], p: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (sorted.len() * p).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// 端点族健康快照
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FamilyHealth {
+    /// 启动以来的请求总数
+    pub total_requests: u64,
+    /// 启动以来的 4xx 响应数
+    pub total_client_errors: u64,
+    /// 启动以来的 5xx 响应数（含 529）
+    pub total_server_errors: u64,
+    /// 最近窗口内的统计
+    pub window: WindowHealth,
+}
+
+/// 滑动窗口内的健康指标
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowHealth {
+    pub window_secs: u64,
+    pub requests: u64,
+    pub server_errors: u64,
+    /// 5xx 占比（0-1）
+    pub error_rate: f64,
+    pub p50_latency_ms: u64,
+    pub p95_latency_ms: u64,
+    pub max_latency_ms: u64,
+}
+
+/// 各端点族健康快照
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointHealthSnapshot {
+    pub v1: FamilyHealth,
+    pub cc: FamilyHealth,
+}
+
+/// 端点族指标收集器
+#[derive(Default)]
+pub struct EndpointMetrics {
+    v1: FamilyMetrics,
+    cc: FamilyMetrics,
+}
+
+impl EndpointMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn family(&self, family: EndpointFamily) -> &FamilyMetrics {
+        match family {
+            EndpointFamily::V1 => &self.v1,
+            EndpointFamily::Cc => &self.cc,
+        }
+    }
+
+    /// 记录一次请求的最终状态码与耗时
+    pub fn record(&self, family: EndpointFamily, status: u16, latency: Duration) {
+        self.family(family).record(status, latency, Instant::now());
+    }
+
+    pub fn snapshot(&self) -> EndpointHealthSnapshot {
+        let now = Instant::now();
+        EndpointHealthSnapshot {
+            v1: self.v1.snapshot(now),
+            cc: self.cc.snapshot(now),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_families_are_tracked_independently() {
+        let metrics = EndpointMetrics::new();
+        metrics.record(EndpointFamily::V1, 200, Duration::from_millis(100));
+        metrics.record(EndpointFamily::V1, 200, Duration::from_millis(300));
+        metrics.record(EndpointFamily::Cc, 529, Duration::from_millis(5000));
+        metrics.record(EndpointFamily::Cc, 429, Duration::from_millis(10));
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.v1.total_requests, 2);
+        assert_eq!(snapshot.v1.window.error_rate, 0.0);
+        assert_eq!(snapshot.v1.window.p95_latency_ms, 300);
+
+        assert_eq!(snapshot.cc.total_client_errors, 1);
+        assert_eq!(snapshot.cc.total_server_errors, 1);
+        assert_eq!(snapshot.cc.window.error_rate, 0.5);
+        assert_eq!(snapshot.cc.window.max_latency_ms, 5000);
+    }
+
+    #[test]
+    fn test_window_drops_expired_samples() {
+        let family = FamilyMetrics::default();
+        let start = Instant::now();
+        family.record(500, Duration::from_millis(10), start);
+        family.record(200, Duration::from_millis(20), start + WINDOW);
+
+        let snapshot = family.snapshot(start + WINDOW + Duration::from_secs(1));
+        assert_eq!(snapshot.total_requests, 2);
+        assert_eq!(snapshot.window.requests, 1);
+        assert_eq!(snapshot.window.server_errors, 0);
+        assert_eq!(snapshot.window.p50_latency_ms, 20);
+    }
+
+    #[test]
+    fn test_percentile() {
+        let sorted: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&sorted, 50), 50);
+        assert_eq!(percentile(&sorted, 95), 95);
+        assert_eq!(percentile(&[], 95), 0);
+    }
+}
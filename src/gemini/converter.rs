@@ -0,0 +1,345 @@
+//! Gemini → Anthropic 请求转换
+//!
+//! 将 generateContent 请求转换为 Anthropic `MessagesRequest`，
+//! 之后复用 Anthropic → Kiro 的转换流程。
+
+use std::collections::HashMap;
+
+use serde_json::json;
+
+use crate::anthropic::types::{
+    MAX_BUDGET_TOKENS, Message, MessagesRequest, SystemMessage, Thinking, Tool,
+};
+
+use super::types::{Content, FunctionDeclaration, GenerateContentRequest, ToolConfig};
+
+/// 动态思考预算（thinkingBudget = -1）时使用的预算
+const DYNAMIC_THINKING_BUDGET: i32 = 20000;
+
+/// 请求转换错误
+#[derive(Debug)]
+pub enum ConversionError {
+    /// 不支持的内容角色
+    UnsupportedRole(String),
+    /// 不支持的内联数据类型（仅支持图片）
+    UnsupportedMimeType(String),
+}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::UnsupportedRole(role) => write!(f, "不支持的内容角色: {}", role),
+            ConversionError::UnsupportedMimeType(mime) => {
+                write!(f, "inlineData 仅支持图片，不支持: {}", mime)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// 将 Gemini generateContent 请求转换为 Anthropic Messages 请求
+pub fn to_messages_request(
+    model: &str,
+    req: &GenerateContentRequest,
+    stream: bool,
+) -> Result<MessagesRequest, ConversionError> {
+    let system = req
+        .system_instruction
+        .as_ref()
+        .map(content_text)
+        .filter(|text| !text.is_empty())
+        .map(|text| {
+            vec![SystemMessage {
+                text,
+                cache_control: None,
+            }]
+        });
+
+    let mut call_ids = CallIds::default();
+    let mut messages = Vec::with_capacity(req.contents.len());
+    for content in &req.contents {
+        let role = match content.role.as_deref().unwrap_or("user") {
+            "user" => "user",
+            "model" => "assistant",
+            other => return Err(ConversionError::UnsupportedRole(other.to_string())),
+        };
+        let blocks = convert_parts(content, &mut call_ids)?;
+        if blocks.is_empty() {
+            continue;
+        }
+        messages.push(Message {
+            role: role.to_string(),
+            content: serde_json::Value::Array(blocks),
+        });
+    }
+
+    let generation = req.generation_config.as_ref();
+    let thinking = generation
+        .and_then(|g| g.thinking_config.as_ref())
+        .and_then(|t| t.thinking_budget)
+        .and_then(|budget| match budget {
+            0 => None,
+            b if b < 0 => Some(DYNAMIC_THINKING_BUDGET),
+            b => Some(b.min(MAX_BUDGET_TOKENS)),
+        })
+        .map(|budget_tokens| Thinking {
+            thinking_type: "enabled".to_string(),
+            budget_tokens,
+        });
+
+    let tools: Vec<Tool> = req
+        .tools
+        .iter()
+        .flatten()
+        .flat_map(|t| &t.function_declarations)
+        .map(convert_function_declaration)
+        .collect();
+
+    Ok(MessagesRequest {
+        model: model.to_string(),
+        // 未指定时为 0，由 clamp_max_tokens 按 defaultMaxTokens 补全
+        max_tokens: generation.and_then(|g| g.max_output_tokens).unwrap_or(0),
+        messages,
+        stream,
+        system,
+        tools: (!tools.is_empty()).then_some(tools),
+        tool_choice: req.tool_config.as_ref().and_then(convert_tool_config),
+        thinking,
+        output_config: None,
+        metadata: None,
+    })
+}
+
+/// 拼接 content 中的文本 part
+fn content_text(content: &Content) -> String {
+    content
+        .parts
+        .iter()
+        .filter_map(|p| p.text.as_deref())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// 函数调用 ID 分配
+///
+/// Gemini 的 functionCall / functionResponse 可以不带 id，此时按函数名
+/// 将 functionResponse 与最近一次未配对的同名 functionCall 对应
+#[derive(Default)]
+struct CallIds {
+    next: usize,
+    pending: Vec<(String, String)>,
+}
+
+impl CallIds {
+    fn for_call(&mut self, name: &str, id: Option<&str>) -> String {
+        let id = id.map(str::to_string).unwrap_or_else(|| {
+            self.next += 1;
+            format!("call_{}", self.next)
+        });
+        self.pending.push((name.to_string(), id.clone()));
+        id
+    }
+
+    fn for_response(&mut self, name: &str, id: Option<&str>) -> String {
+        if let Some(id) = id {
+            self.pending.retain(|(_, pending)| pending != id);
+            return id.to_string();
+        }
+        match self.pending.iter().position(|(n, _)| n == name) {
+            Some(index) => self.pending.remove(index).1,
+            None => {
+                self.next += 1;
+                format!("call_{}", self.next)
+            }
+        }
+    }
+}
+
+/// 转换 content 的 parts 为 Anthropic content block
+fn convert_parts(
+    content: &Content,
+    call_ids: &mut CallIds,
+) -> Result<Vec<serde_json::Value>, ConversionError> {
+    let mut blocks = Vec::new();
+    for part in &content.parts {
+        if let Some(text) = &part.text {
+            if !part.thought && !text.is_empty() {
+                blocks.push(json!({"type": "text", "text": text}));
+            }
+        } else if let Some(data) = &part.inline_data {
+            if !data.mime_type.starts_with("image/") {
+                return Err(ConversionError::UnsupportedMimeType(data.mime_type.clone()));
+            }
+            blocks.push(json!({
+                "type": "image",
+                "source": {"type": "base64", "media_type": data.mime_type, "data": data.data}
+            }));
+        } else if let Some(call) = &part.function_call {
+            blocks.push(json!({
+                "type": "tool_use",
+                "id": call_ids.for_call(&call.name, call.id.as_deref()),
+                "name": call.name,
+                "input": call.args.clone().unwrap_or_else(|| json!({}))
+            }));
+        } else if let Some(response) = &part.function_response {
+            blocks.push(json!({
+                "type": "tool_result",
+                "tool_use_id": call_ids.for_response(&response.name, response.id.as_deref()),
+                "content": serde_json::to_string(&response.response).unwrap_or_default()
+            }));
+        }
+    }
+    Ok(blocks)
+}
+
+/// 转换函数声明，schema 中大写的 type（OpenAPI 风格）统一转为小写
+fn convert_function_declaration(decl: &FunctionDeclaration) -> Tool {
+    let input_schema: HashMap<String, serde_json::Value> = decl
+        .parameters_json_schema
+        .clone()
+        .or_else(|| decl.parameters.clone().map(normalize_schema))
+        .and_then(|schema| serde_json::from_value(schema).ok())
+        .unwrap_or_else(|| {
+            HashMap::from([
+                ("type".to_string(), json!("object")),
+                ("properties".to_string(), json!({})),
+            ])
+        });
+    Tool {
+        tool_type: None,
+        name: decl.name.clone(),
+        description: decl.description.clone().unwrap_or_default(),
+        input_schema,
+        max_uses: None,
+        cache_control: None,
+    }
+}
+
+/// 递归将 schema 中的 `type` 值转为小写
+fn normalize_schema(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.into_iter()
+                .map(|(key, v)| match (key.as_str(), v) {
+                    ("type", serde_json::Value::String(t)) => {
+                        (key, serde_json::Value::String(t.to_lowercase()))
+                    }
+                    (_, v) => (key, normalize_schema(v)),
+                })
+                .collect(),
+        ),
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.into_iter().map(normalize_schema).collect())
+        }
+        other => other,
+    }
+}
+
+/// 转换 functionCallingConfig（AUTO 为默认行为，不透传）
+fn convert_tool_config(config: &ToolConfig) -> Option<serde_json::Value> {
+    let calling = config.function_calling_config.as_ref()?;
+    match calling.mode.as_deref()?.to_ascii_uppercase().as_str() {
+        "NONE" => Some(json!({"type": "none"})),
+        "ANY" => match calling.allowed_function_names.as_deref() {
+            Some([name]) => Some(json!({"type": "tool", "name": name})),
+            _ => Some(json!({"type": "any"})),
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: serde_json::Value) -> GenerateContentRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_contents_and_system_instruction() {
+        let req = parse(json!({
+            "systemInstruction": {"parts": [{"text": "You are helpful."}]},
+            "contents": [
+                {"role": "user", "parts": [{"text": "Hi"}]},
+                {"role": "model", "parts": [{"text": "thinking...", "thought": true}, {"text": "Hello"}]},
+                {"parts": [{"text": "Bye"}]}
+            ],
+            "generation_config": {"max_output_tokens": 512, "thinking_config": {"thinking_budget": -1}}
+        }));
+
+        let result = to_messages_request("claude-sonnet-4-6", &req, false).unwrap();
+        assert_eq!(result.system.unwrap()[0].text, "You are helpful.");
+        assert_eq!(result.max_tokens, 512);
+        assert_eq!(
+            result.thinking.unwrap().budget_tokens,
+            DYNAMIC_THINKING_BUDGET
+        );
+        assert_eq!(result.messages.len(), 3);
+        assert_eq!(result.messages[1].role, "assistant");
+        let blocks = result.messages[1].content.as_array().unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0]["text"], "Hello");
+        assert_eq!(result.messages[2].role, "user");
+    }
+
+    #[test]
+    fn test_function_calls_paired_by_name() {
+        let req = parse(json!({
+            "contents": [
+                {"role": "user", "parts": [{"text": "Weather in Paris and Rome?"}]},
+                {"role": "model", "parts": [
+                    {"functionCall": {"name": "get_weather", "args": {"city": "Paris"}}},
+                    {"functionCall": {"name": "get_weather", "args": {"city": "Rome"}}}
+                ]},
+                {"role": "user", "parts": [
+                    {"functionResponse": {"name": "get_weather", "response": {"temp": 20}}},
+                    {"functionResponse": {"name": "get_weather", "response": {"temp": 25}}}
+                ]}
+            ],
+            "tools": [{"functionDeclarations": [{
+                "name": "get_weather",
+                "parameters": {"type": "OBJECT", "properties": {"city": {"type": "STRING"}}}
+            }]}],
+            "toolConfig": {"functionCallingConfig": {"mode": "ANY", "allowedFunctionNames": ["get_weather"]}}
+        }));
+
+        let result = to_messages_request("claude-sonnet-4-6", &req, true).unwrap();
+        let calls = result.messages[1].content.as_array().unwrap();
+        let responses = result.messages[2].content.as_array().unwrap();
+        assert_eq!(calls[0]["id"], responses[0]["tool_use_id"]);
+        assert_eq!(calls[1]["id"], responses[1]["tool_use_id"]);
+        assert_ne!(calls[0]["id"], calls[1]["id"]);
+        assert_eq!(responses[1]["content"], "{\"temp\":25}");
+
+        let tools = result.tools.unwrap();
+        assert_eq!(tools[0].input_schema["type"], "object");
+        assert_eq!(
+            tools[0].input_schema["properties"]["city"]["type"],
+            "string"
+        );
+        assert_eq!(
+            result.tool_choice,
+            Some(json!({"type": "tool", "name": "get_weather"}))
+        );
+    }
+
+    #[test]
+    fn test_inline_data_only_supports_images() {
+        let image = parse(json!({
+            "contents": [{"parts": [{"inlineData": {"mimeType": "image/png", "data": "iVBORw0KGgo="}}]}]
+        }));
+        let result = to_messages_request("claude-sonnet-4-6", &image, false).unwrap();
+        let blocks = result.messages[0].content.as_array().unwrap();
+        assert_eq!(blocks[0]["source"]["media_type"], "image/png");
+
+        let pdf = parse(json!({
+            "contents": [{"parts": [{"inline_data": {"mime_type": "application/pdf", "data": "JVBERi0="}}]}]
+        }));
+        assert!(matches!(
+            to_messages_request("claude-sonnet-4-6", &pdf, false),
+            Err(ConversionError::UnsupportedMimeType(_))
+        ));
+    }
+}
@@ -0,0 +1,512 @@
+//! Gemini API Handler 函数
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::{
+    Json as JsonExtractor,
+    body::Body,
+    extract::{Extension, Path, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream};
+use serde_json::json;
+
+use crate::anthropic::compaction;
+use crate::anthropic::converter::{ConversionOptions, convert_request};
+use crate::anthropic::handlers::{
+    RequestLogCtx, clamp_max_tokens, map_conversion_error, map_provider_error,
+    override_thinking_from_model_name, warnings_header_value, with_context_usage_header,
+    with_diagnostic_headers, with_usage_headers,
+};
+use crate::anthropic::heartbeat::Heartbeat;
+use crate::anthropic::middleware::AppState;
+use crate::anthropic::stream::{SseEvent, StreamContext};
+use crate::anthropic::types::{ErrorResponse, RequestFlags};
+use crate::apikeys::{ApiKeyManager, AuthenticatedApiKey};
+use crate::kiro::model::events::Event;
+use crate::kiro::model::requests::kiro::KiroRequest;
+use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::provider::KiroProvider;
+use crate::token;
+
+use super::converter::to_messages_request;
+use super::stream::{ChunkConverter, aggregate, chunk_to_sse_string};
+use super::types::{GeminiQuery, GenerateContentRequest};
+
+/// SSE 注释形式的保活事件
+const PING_SSE: &str = ": ping\r\n\r\n";
+
+/// JSON 数组流的保活（元素之间的空白不影响解析）
+const PING_JSON: &str = "\n";
+
+/// 流式输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamFormat {
+    /// `alt=sse`：每个分块一条 `data:` 事件
+    Sse,
+    /// 默认：分块组成一个 JSON 数组
+    JsonArray,
+}
+
+/// POST /v1beta/models/{model}:{action}
+///
+/// Gemini 兼容接口，支持 generateContent / streamGenerateContent / countTokens，
+/// 路径中的模型名直接作为 Claude 模型名使用
+#[utoipa::path(
+    post,
+    path = "/v1beta/models/{model_action}",
+    tag = "gemini",
+    params(
+        ("model_action" = String, Path, description = "`{model}:generateContent`、`{model}:streamGenerateContent` 或 `{model}:countTokens`"),
+        GeminiQuery,
+        RequestFlags,
+    ),
+    request_body = GenerateContentRequest,
+    responses(
+        (status = 200, description = "`GenerateContentResponse`（流式时为 SSE 或 JSON 数组）"),
+        (status = 404, description = "不支持的方法", body = ErrorResponse),
+        (status = 400, description = "请求无效", body = ErrorResponse),
+        (status = 401, description = "认证失败", body = ErrorResponse),
+        (status = 429, description = "限流或配额超限", body = ErrorResponse),
+        (status = 529, description = "无可用凭据", body = ErrorResponse),
+    ),
+    security(("api_key" = []), ("bearer" = []), ("goog_api_key" = []))
+)]
+pub async fn post_gemini(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthenticatedApiKey>,
+    Path(model_action): Path<String>,
+    Query(query): Query<GeminiQuery>,
+    Query(flags): Query<RequestFlags>,
+    JsonExtractor(request): JsonExtractor<GenerateContentRequest>,
+) -> Response {
+    let Some((model, action)) = model_action.rsplit_once(':') else {
+        return not_found(&model_action);
+    };
+    let stream = match action {
+        "generateContent" => false,
+        "streamGenerateContent" => true,
+        "countTokens" => return count_tokens(model, &request),
+        _ => return not_found(&model_action),
+    };
+    tracing::info!(
+        model = %model,
+        action = %action,
+        content_count = %request.contents.len(),
+        "Received POST /v1beta/models request"
+    );
+
+    let provider = match &state.kiro_provider {
+        Some(p) => p.clone(),
+        None => {
+            tracing::error!("KiroProvider 未配置");
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ErrorResponse::new(
+                    "service_unavailable",
+                    "Kiro API provider not configured",
+                )),
+            )
+                .into_response();
+        }
+    };
+
+    let mut payload = match to_messages_request(model, &request, stream) {
+        Ok(payload) => payload,
+        Err(e) => {
+            tracing::warn!("Gemini 请求转换失败: {}", e);
+            return invalid_request(e.to_string());
+        }
+    };
+    override_thinking_from_model_name(&mut payload);
+
+    // 上下文使用率超过阈值时压缩最早的对话历史
+    let compaction_warning = compaction::compact_history(
+        &provider,
+        &state.api_keys,
+        &auth.key_id,
+        state.profile_arn.clone(),
+        &mut payload,
+    )
+    .await;
+
+    let conversion_options = ConversionOptions::from_config(&provider.token_manager().config())
+        .with_request_flags(&flags);
+    let conversion_result = match convert_request(&payload, &conversion_options) {
+        Ok(result) => result,
+        Err(e) => return map_conversion_error(e),
+    };
+
+    let kiro_request = KiroRequest {
+        conversation_state: conversion_result.conversation_state,
+        profile_arn: state.profile_arn.clone(),
+    };
+    let request_body = match serde_json::to_string(&kiro_request) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("序列化请求失败: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(
+                    "internal_error",
+                    format!("序列化请求失败: {}", e),
+                )),
+            )
+                .into_response();
+        }
+    };
+
+    let mut warnings = conversion_result.warnings;
+    warnings.extend(compaction_warning);
+    let output_limit = match clamp_max_tokens(&mut payload, &conversion_options, &mut warnings) {
+        Ok(limit) => limit,
+        Err(e) => return map_conversion_error(e),
+    };
+    let warnings_header = warnings_header_value(&warnings);
+    let log_ctx = RequestLogCtx::new(
+        state.request_log.clone(),
+        &state.api_keys,
+        &auth.key_id,
+        &payload,
+        warnings,
+    )
+    .with_kiro_request(&provider.token_manager().config(), &request_body);
+
+    let input_tokens = token::count_all_tokens(
+        payload.model.clone(),
+        payload.system.clone(),
+        payload.messages.clone(),
+        payload.tools.clone(),
+    ) as i32;
+    let thinking_enabled = payload
+        .thinking
+        .as_ref()
+        .map(|t| t.is_enabled())
+        .unwrap_or(false);
+    let ctx = StreamContext::new_with_thinking(&payload.model, input_tokens, thinking_enabled)
+        .with_output_limit(output_limit);
+
+    let response = if stream {
+        let response = match provider.call_api_stream(&request_body).await {
+            Ok(resp) => resp,
+            Err(e) => return map_provider_error(e),
+        };
+        let log_ctx = log_ctx.with_served_by(&provider, &response);
+        let format = if query.alt.as_deref() == Some("sse") {
+            StreamFormat::Sse
+        } else {
+            StreamFormat::JsonArray
+        };
+        let content_type = match format {
+            StreamFormat::Sse => "text/event-stream",
+            StreamFormat::JsonArray => "application/json",
+        };
+        let body = create_chunk_stream(
+            response,
+            ChunkStreamParams {
+                ctx,
+                converter: ChunkConverter::new(&payload.model),
+                format,
+                heartbeat: Heartbeat::new(&provider.token_manager().config()),
+                api_keys: state.api_keys.clone(),
+                key_id: auth.key_id.clone(),
+                log_ctx,
+            },
+        );
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CACHE_CONTROL, "no-cache")
+            .header(header::CONNECTION, "keep-alive")
+            .body(Body::from_stream(body))
+            .unwrap()
+    } else {
+        handle_non_stream_request(
+            provider,
+            state.api_keys.clone(),
+            &auth.key_id,
+            &request_body,
+            &payload.model,
+            ctx,
+            log_ctx,
+        )
+        .await
+    };
+
+    with_diagnostic_headers(response, warnings_header, None)
+}
+
+/// 未知的模型动作
+fn not_found(model_action: &str) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse::new(
+            "not_found_error",
+            format!("不支持的方法: {}", model_action),
+        )),
+    )
+        .into_response()
+}
+
+fn invalid_request(message: String) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse::new("invalid_request_error", message)),
+    )
+        .into_response()
+}
+
+/// `:countTokens`：本地估算输入 token 数
+fn count_tokens(model: &str, request: &GenerateContentRequest) -> Response {
+    let payload = match to_messages_request(model, request, false) {
+        Ok(payload) => payload,
+        Err(e) => return invalid_request(e.to_string()),
+    };
+    let total_tokens = token::count_all_tokens(
+        payload.model,
+        payload.system,
+        payload.messages,
+        payload.tools,
+    );
+    Json(json!({"totalTokens": total_tokens.max(1)})).into_response()
+}
+
+/// 流式转换状态
+struct ChunkStreamState<S> {
+    body_stream: S,
+    ctx: StreamContext,
+    converter: ChunkConverter,
+    format: StreamFormat,
+    decoder: EventStreamDecoder,
+    api_keys: Arc<ApiKeyManager>,
+    key_id: String,
+    log_ctx: RequestLogCtx,
+    /// 是否已输出过分块（JSON 数组格式用于决定分隔符）
+    started: bool,
+    finished: bool,
+}
+
+impl<S> ChunkStreamState<S> {
+    /// 将 Anthropic SSE 事件转换为 Gemini 分块字节
+    fn convert(&mut self, events: Vec<SseEvent>) -> Vec<Bytes> {
+        let chunks: Vec<_> = events
+            .iter()
+            .flat_map(|e| self.converter.convert(e))
+            .collect();
+        chunks
+            .into_iter()
+            .map(|chunk| {
+                let text = match self.format {
+                    StreamFormat::Sse => chunk_to_sse_string(&chunk),
+                    StreamFormat::JsonArray => {
+                        let separator = if self.started { ",\r\n" } else { "[" };
+                        format!(
+                            "{}{}",
+                            separator,
+                            serde_json::to_string(&chunk).unwrap_or_default()
+                        )
+                    }
+                };
+                self.started = true;
+                self.log_ctx.response_events.push(chunk);
+                Bytes::from(text)
+            })
+            .collect()
+    }
+
+    /// 流结束：记录用量并输出剩余分块（JSON 数组格式补齐结尾）
+    fn finish(&mut self, status: &str) -> Vec<Bytes> {
+        self.finished = true;
+        let (input, output) = self.ctx.final_usage();
+        self.api_keys.record_usage(
+            &self.key_id,
+            self.log_ctx.user_id(),
+            self.log_ctx.model(),
+            input.max(0) as u64,
+            output.max(0) as u64,
+        );
+        self.log_ctx
+            .record(input, output, self.ctx.token_source(), status);
+        let final_events = self.ctx.generate_final_events();
+        let mut bytes = self.convert(final_events);
+        if self.format == StreamFormat::JsonArray {
+            bytes.push(Bytes::from(if self.started { "]" } else { "[]" }));
+        }
+        bytes
+    }
+}
+
+/// 流式响应参数
+struct ChunkStreamParams {
+    ctx: StreamContext,
+    converter: ChunkConverter,
+    format: StreamFormat,
+    heartbeat: Heartbeat,
+    api_keys: Arc<ApiKeyManager>,
+    key_id: String,
+    log_ctx: RequestLogCtx,
+}
+
+/// 创建 Gemini 格式的流式响应，空闲时按 heartbeat 配置发送保活
+fn create_chunk_stream(
+    response: reqwest::Response,
+    params: ChunkStreamParams,
+) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    let ChunkStreamParams {
+        mut ctx,
+        converter,
+        format,
+        heartbeat,
+        api_keys,
+        key_id,
+        log_ctx,
+    } = params;
+    let initial_events = ctx.generate_initial_events();
+    let mut state = ChunkStreamState {
+        body_stream: response.bytes_stream(),
+        ctx,
+        converter,
+        format,
+        decoder: EventStreamDecoder::new(),
+        api_keys,
+        key_id,
+        log_ctx,
+        started: false,
+        finished: false,
+    };
+    let initial = state.convert(initial_events);
+
+    let processing_stream = stream::unfold(
+        (state, heartbeat),
+        |(mut state, mut ping_interval)| async move {
+            if state.finished {
+                return None;
+            }
+
+            let bytes = tokio::select! {
+                chunk_result = state.body_stream.next() => match chunk_result {
+                    Some(Ok(chunk)) => {
+                        if let Err(e) = state.decoder.feed(&chunk) {
+                            tracing::warn!("缓冲区溢出: {}", e);
+                        }
+                        let mut events = Vec::new();
+                        for result in state.decoder.decode_iter() {
+                            match result {
+                                Ok(frame) => {
+                                    if let Ok(event) = Event::from_frame(frame) {
+                                        events.extend(state.ctx.process_kiro_event(&event));
+                                    }
+                                }
+                                Err(e) => tracing::warn!("解码事件失败: {}", e),
+                            }
+                        }
+                        if !events.is_empty() {
+                            ping_interval.reset();
+                        }
+                        state.convert(events)
+                    }
+                    Some(Err(e)) => {
+                        tracing::error!("读取响应流失败: {}", e);
+                        state.finish(&format!("error: {}", e))
+                    }
+                    None => state.finish("success"),
+                },
+                _ = ping_interval.tick() => {
+                    tracing::trace!("发送保活");
+                    let ping = match state.format {
+                        StreamFormat::Sse => PING_SSE,
+                        StreamFormat::JsonArray => PING_JSON,
+                    };
+                    vec![Bytes::from(ping)]
+                }
+            };
+
+            let items: Vec<Result<Bytes, Infallible>> = bytes.into_iter().map(Ok).collect();
+            Some((stream::iter(items), (state, ping_interval)))
+        },
+    )
+    .flatten();
+
+    stream::iter(initial.into_iter().map(Ok)).chain(processing_stream)
+}
+
+/// 处理非流式请求：聚合全部分块后返回单个 `GenerateContentResponse`
+async fn handle_non_stream_request(
+    provider: Arc<KiroProvider>,
+    api_keys: Arc<ApiKeyManager>,
+    key_id: &str,
+    request_body: &str,
+    model: &str,
+    mut ctx: StreamContext,
+    log_ctx: RequestLogCtx,
+) -> Response {
+    let response = match provider.call_api(request_body).await {
+        Ok(resp) => resp,
+        Err(e) => return map_provider_error(e),
+    };
+    let log_ctx = log_ctx.with_served_by(&provider, &response);
+
+    let body_bytes = match response.bytes().await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("读取响应体失败: {}", e);
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(ErrorResponse::new(
+                    "api_error",
+                    format!("读取响应失败: {}", e),
+                )),
+            )
+                .into_response();
+        }
+    };
+
+    let mut decoder = EventStreamDecoder::new();
+    if let Err(e) = decoder.feed(&body_bytes) {
+        tracing::warn!("缓冲区溢出: {}", e);
+    }
+
+    let mut converter = ChunkConverter::new(model);
+    let mut chunks = Vec::new();
+    for event in ctx.generate_initial_events() {
+        chunks.extend(converter.convert(&event));
+    }
+    for result in decoder.decode_iter() {
+        match result {
+            Ok(frame) => {
+                if let Ok(event) = Event::from_frame(frame) {
+                    for sse in ctx.process_kiro_event(&event) {
+                        chunks.extend(converter.convert(&sse));
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("解码事件失败: {}", e),
+        }
+    }
+    for event in ctx.generate_final_events() {
+        chunks.extend(converter.convert(&event));
+    }
+
+    let (input, output) = ctx.final_usage();
+    api_keys.record_usage(
+        key_id,
+        log_ctx.user_id(),
+        log_ctx.model(),
+        input.max(0) as u64,
+        output.max(0) as u64,
+    );
+    let body = aggregate(model, &chunks);
+    log_ctx.record_with_body(
+        input,
+        output,
+        ctx.token_source(),
+        "success",
+        serde_json::to_string(&body).unwrap_or_default(),
+    );
+
+    let response = with_usage_headers(Json(body).into_response(), input, output);
+    with_context_usage_header(response, ctx.context_usage_percentage)
+}
@@ -0,0 +1,249 @@
+//! Anthropic SSE 事件 → Gemini generateContent 响应转换
+//!
+//! Kiro 事件先经 `StreamContext` 转换为 Anthropic SSE 事件，
+//! 再由本模块转换为 `GenerateContentResponse` 分块（流式）或单个响应（非流式）。
+
+use std::collections::HashMap;
+
+use serde_json::json;
+
+use crate::anthropic::stream::SseEvent;
+
+/// 将 Anthropic stop_reason 映射为 Gemini finishReason
+pub fn map_finish_reason(stop_reason: &str) -> &'static str {
+    match stop_reason {
+        "max_tokens" | "model_context_window_exceeded" => "MAX_TOKENS",
+        _ => "STOP",
+    }
+}
+
+/// 构造 usageMetadata 对象
+fn usage_json(input: i32, output: i32) -> serde_json::Value {
+    json!({
+        "promptTokenCount": input,
+        "candidatesTokenCount": output,
+        "totalTokenCount": input + output
+    })
+}
+
+/// 流式分块转换器
+///
+/// 工具调用的参数在 content_block_stop 时整体输出（Gemini 的 functionCall 不支持增量参数）
+pub struct ChunkConverter {
+    model: String,
+    /// Anthropic 内容块索引 -> (id, name, 已累积的参数 JSON)
+    tool_calls: HashMap<i64, (String, String, String)>,
+}
+
+impl ChunkConverter {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            tool_calls: HashMap::new(),
+        }
+    }
+
+    fn chunk(&self, parts: Vec<serde_json::Value>) -> serde_json::Value {
+        json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": parts},
+                "index": 0
+            }],
+            "modelVersion": self.model
+        })
+    }
+
+    /// 转换单个 Anthropic SSE 事件，返回零或多个分块
+    pub fn convert(&mut self, event: &SseEvent) -> Vec<serde_json::Value> {
+        let data = &event.data;
+        let index = data["index"].as_i64().unwrap_or_default();
+        match event.event.as_str() {
+            "content_block_start" if data["content_block"]["type"] == "tool_use" => {
+                let block = &data["content_block"];
+                self.tool_calls.insert(
+                    index,
+                    (
+                        block["id"].as_str().unwrap_or_default().to_string(),
+                        block["name"].as_str().unwrap_or_default().to_string(),
+                        String::new(),
+                    ),
+                );
+                Vec::new()
+            }
+            "content_block_delta" => {
+                let delta = &data["delta"];
+                match delta["type"].as_str() {
+                    Some("text_delta") => vec![self.chunk(vec![json!({"text": delta["text"]})])],
+                    Some("thinking_delta") => {
+                        vec![self.chunk(vec![json!({"text": delta["thinking"], "thought": true})])]
+                    }
+                    Some("input_json_delta") => {
+                        if let Some(call) = self.tool_calls.get_mut(&index) {
+                            call.2
+                                .push_str(delta["partial_json"].as_str().unwrap_or(""));
+                        }
+                        Vec::new()
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            "content_block_stop" => {
+                let Some((id, name, arguments)) = self.tool_calls.remove(&index) else {
+                    return Vec::new();
+                };
+                let args: serde_json::Value = if arguments.trim().is_empty() {
+                    json!({})
+                } else {
+                    serde_json::from_str(&arguments).unwrap_or_else(|e| {
+                        tracing::warn!("工具调用参数 JSON 解析失败: {}, id: {}", e, id);
+                        json!({})
+                    })
+                };
+                vec![self.chunk(vec![json!({
+                    "functionCall": {"id": id, "name": name, "args": args}
+                })])]
+            }
+            "message_delta" => {
+                let usage = &data["usage"];
+                let input = usage["input_tokens"].as_i64().unwrap_or_default() as i32;
+                let output = usage["output_tokens"].as_i64().unwrap_or_default() as i32;
+                let stop_reason = data["delta"]["stop_reason"].as_str().unwrap_or("end_turn");
+                let mut chunk = self.chunk(Vec::new());
+                chunk["candidates"][0]["finishReason"] = json!(map_finish_reason(stop_reason));
+                chunk["usageMetadata"] = usage_json(input, output);
+                vec![chunk]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// 非流式响应聚合：合并全部分块为单个 `GenerateContentResponse`
+///
+/// 相邻且 thought 标记相同的文本 part 会被合并
+pub fn aggregate(model: &str, chunks: &[serde_json::Value]) -> serde_json::Value {
+    let mut parts: Vec<serde_json::Value> = Vec::new();
+    let mut finish_reason = None;
+    let mut usage = None;
+
+    for chunk in chunks {
+        let candidate = &chunk["candidates"][0];
+        if let Some(reason) = candidate.get("finishReason") {
+            finish_reason = Some(reason.clone());
+        }
+        if let Some(metadata) = chunk.get("usageMetadata") {
+            usage = Some(metadata.clone());
+        }
+        for part in candidate["content"]["parts"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            if let (Some(text), Some(last)) = (part["text"].as_str(), parts.last_mut())
+                && last["thought"] == part["thought"]
+                && let Some(existing) = last["text"].as_str()
+            {
+                last["text"] = json!(format!("{}{}", existing, text));
+                continue;
+            }
+            parts.push(part.clone());
+        }
+    }
+
+    json!({
+        "candidates": [{
+            "content": {"role": "model", "parts": parts},
+            "finishReason": finish_reason.unwrap_or_else(|| json!("STOP")),
+            "index": 0
+        }],
+        "usageMetadata": usage.unwrap_or_else(|| usage_json(0, 0)),
+        "modelVersion": model
+    })
+}
+
+/// 将分块格式化为 SSE 字符串（`alt=sse`）
+pub fn chunk_to_sse_string(chunk: &serde_json::Value) -> String {
+    format!(
+        "data: {}\r\n\r\n",
+        serde_json::to_string(chunk).unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> Vec<SseEvent> {
+        vec![
+            SseEvent::new("message_start", json!({"type": "message_start"})),
+            SseEvent::new(
+                "content_block_delta",
+                json!({"index": 0, "delta": {"type": "thinking_delta", "thinking": "Hmm"}}),
+            ),
+            SseEvent::new(
+                "content_block_delta",
+                json!({"index": 1, "delta": {"type": "text_delta", "text": "Let me "}}),
+            ),
+            SseEvent::new(
+                "content_block_delta",
+                json!({"index": 1, "delta": {"type": "text_delta", "text": "check."}}),
+            ),
+            SseEvent::new(
+                "content_block_start",
+                json!({"index": 2, "content_block": {"type": "tool_use", "id": "t1", "name": "get_weather", "input": {}}}),
+            ),
+            SseEvent::new(
+                "content_block_delta",
+                json!({"index": 2, "delta": {"type": "input_json_delta", "partial_json": "{\"city\":"}}),
+            ),
+            SseEvent::new(
+                "content_block_delta",
+                json!({"index": 2, "delta": {"type": "input_json_delta", "partial_json": "\"Paris\"}"}}),
+            ),
+            SseEvent::new("content_block_stop", json!({"index": 2})),
+            SseEvent::new(
+                "message_delta",
+                json!({"delta": {"stop_reason": "tool_use"}, "usage": {"input_tokens": 10, "output_tokens": 5}}),
+            ),
+            SseEvent::new("message_stop", json!({"type": "message_stop"})),
+        ]
+    }
+
+    fn convert_all() -> Vec<serde_json::Value> {
+        let mut converter = ChunkConverter::new("claude-sonnet-4-6");
+        events().iter().flat_map(|e| converter.convert(e)).collect()
+    }
+
+    #[test]
+    fn test_chunk_converter() {
+        let chunks = convert_all();
+        assert_eq!(chunks.len(), 5);
+        let parts = |i: usize| chunks[i]["candidates"][0]["content"]["parts"].clone();
+        assert_eq!(parts(0)[0]["thought"], true);
+        assert_eq!(parts(1)[0]["text"], "Let me ");
+        assert_eq!(parts(3)[0]["functionCall"]["args"]["city"], "Paris");
+        assert_eq!(parts(3)[0]["functionCall"]["id"], "t1");
+        assert_eq!(chunks[4]["candidates"][0]["finishReason"], "STOP");
+        assert_eq!(chunks[4]["usageMetadata"]["totalTokenCount"], 15);
+    }
+
+    #[test]
+    fn test_aggregate_merges_text_parts() {
+        let response = aggregate("claude-sonnet-4-6", &convert_all());
+        let parts = response["candidates"][0]["content"]["parts"]
+            .as_array()
+            .unwrap();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0]["text"], "Hmm");
+        assert_eq!(parts[1]["text"], "Let me check.");
+        assert_eq!(parts[2]["functionCall"]["name"], "get_weather");
+        assert_eq!(response["usageMetadata"]["promptTokenCount"], 10);
+    }
+
+    #[test]
+    fn test_map_finish_reason() {
+        assert_eq!(map_finish_reason("end_turn"), "STOP");
+        assert_eq!(map_finish_reason("tool_use"), "STOP");
+        assert_eq!(map_finish_reason("max_tokens"), "MAX_TOKENS");
+    }
+}
@@ -0,0 +1,143 @@
+//! Gemini generateContent API 类型定义
+//!
+//! 字段按 Gemini REST 的 camelCase 命名，同时接受 snake_case 别名（proto JSON 两种写法均合法）。
+
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+
+/// generateContent / streamGenerateContent / countTokens 请求体
+#[derive(Debug, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentRequest {
+    #[serde(default)]
+    pub contents: Vec<Content>,
+    #[serde(alias = "system_instruction")]
+    pub system_instruction: Option<Content>,
+    pub tools: Option<Vec<GeminiTool>>,
+    #[serde(alias = "tool_config")]
+    pub tool_config: Option<ToolConfig>,
+    #[serde(alias = "generation_config")]
+    pub generation_config: Option<GenerationConfig>,
+}
+
+/// 对话内容（role 为 user 或 model）
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+pub struct Content {
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub parts: Vec<Part>,
+}
+
+/// 内容片段，每个 part 只会设置其中一种数据
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Part {
+    pub text: Option<String>,
+    /// 是否为思考内容（回传历史时忽略）
+    #[serde(default)]
+    pub thought: bool,
+    #[serde(alias = "inline_data")]
+    pub inline_data: Option<InlineData>,
+    #[serde(alias = "function_call")]
+    pub function_call: Option<FunctionCall>,
+    #[serde(alias = "function_response")]
+    pub function_response: Option<FunctionResponse>,
+}
+
+/// base64 内联数据
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineData {
+    #[serde(alias = "mime_type")]
+    pub mime_type: String,
+    pub data: String,
+}
+
+/// 模型发起的函数调用
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[schema(as = GeminiFunctionCall)]
+pub struct FunctionCall {
+    #[serde(default)]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub args: Option<serde_json::Value>,
+}
+
+/// 客户端回传的函数执行结果
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct FunctionResponse {
+    #[serde(default)]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub response: serde_json::Value,
+}
+
+/// 工具定义（仅支持 functionDeclarations）
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiTool {
+    #[serde(default, alias = "function_declarations")]
+    pub function_declarations: Vec<FunctionDeclaration>,
+}
+
+/// 函数声明
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionDeclaration {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// OpenAPI 子集 schema（type 可能为大写，如 `OBJECT`）
+    #[serde(default)]
+    pub parameters: Option<serde_json::Value>,
+    /// 标准 JSON Schema，优先于 `parameters`
+    #[serde(default, alias = "parameters_json_schema")]
+    pub parameters_json_schema: Option<serde_json::Value>,
+}
+
+/// 工具调用配置
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolConfig {
+    #[serde(alias = "function_calling_config")]
+    pub function_calling_config: Option<FunctionCallingConfig>,
+}
+
+/// 函数调用模式：AUTO / ANY / NONE
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionCallingConfig {
+    #[serde(default)]
+    pub mode: Option<String>,
+    #[serde(default, alias = "allowed_function_names")]
+    pub allowed_function_names: Option<Vec<String>>,
+}
+
+/// 生成参数（仅使用输出上限与思考配置）
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationConfig {
+    #[serde(alias = "max_output_tokens")]
+    pub max_output_tokens: Option<i32>,
+    #[serde(alias = "thinking_config")]
+    pub thinking_config: Option<ThinkingConfig>,
+}
+
+/// 思考配置
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ThinkingConfig {
+    /// 思考 token 预算（0 表示关闭，-1 表示动态）
+    #[serde(alias = "thinking_budget")]
+    pub thinking_budget: Option<i32>,
+}
+
+/// URL 查询参数
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct GeminiQuery {
+    /// `sse` 时以 SSE 输出流式响应，否则输出 JSON 数组
+    pub alt: Option<String>,
+}
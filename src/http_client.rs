@@ -36,6 +36,28 @@ impl ProxyConfig {
     }
 }
 
+/// HTTP Client 超时配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientTimeouts {
+    /// 建立连接超时（秒），None 表示不单独限制
+    pub connect_secs: Option<u64>,
+    /// 读取超时（秒，两次收到数据之间的最长间隔），None 表示不单独限制
+    pub read_secs: Option<u64>,
+    /// 请求总超时（秒）
+    pub total_secs: u64,
+}
+
+impl ClientTimeouts {
+    /// 只限制总超时
+    pub fn total(secs: u64) -> Self {
+        Self {
+            connect_secs: None,
+            read_secs: None,
+            total_secs: secs,
+        }
+    }
+}
+
 /// 构建 HTTP Client
 ///
 /// # Arguments
@@ -49,7 +71,22 @@ pub fn build_client(
     timeout_secs: u64,
     tls_backend: TlsBackend,
 ) -> anyhow::Result<Client> {
-    let mut builder = Client::builder().timeout(Duration::from_secs(timeout_secs));
+    build_client_with_timeouts(proxy, ClientTimeouts::total(timeout_secs), tls_backend)
+}
+
+/// 构建带连接 / 读取超时的 HTTP Client
+pub fn build_client_with_timeouts(
+    proxy: Option<&ProxyConfig>,
+    timeouts: ClientTimeouts,
+    tls_backend: TlsBackend,
+) -> anyhow::Result<Client> {
+    let mut builder = Client::builder().timeout(Duration::from_secs(timeouts.total_secs));
+    if let Some(secs) = timeouts.connect_secs {
+        builder = builder.connect_timeout(Duration::from_secs(secs));
+    }
+    if let Some(secs) = timeouts.read_secs {
+        builder = builder.read_timeout(Duration::from_secs(secs));
+    }
 
     if tls_backend == TlsBackend::Rustls {
         builder = builder.use_rustls_tls();
@@ -0,0 +1,199 @@
+//! 故障注入
+//!
+//! 按 [`ChaosConfig`] 在 Provider 层模拟上游故障：首个数据块前的随机延迟、
+//! 响应体中途断开、以及不发送请求直接返回的 429。
+//! 注入的 429 与真实上游错误走同一条重试/故障转移路径。
+
+use std::io;
+use std::time::Duration;
+
+use futures::{StreamExt, stream};
+use parking_lot::Mutex;
+
+use crate::model::config::ChaosConfig;
+
+/// 模拟 429 的响应体（与上游错误格式一致）
+const THROTTLE_BODY: &str =
+    r#"{"message":"Too many requests (injected fault)","reason":"INJECTED_FAULT"}"#;
+
+/// 故障注入器
+pub struct FaultInjector {
+    /// (当前种子, 随机数生成器)，配置中的种子变化时重新播种
+    rng: Mutex<(Option<u64>, fastrand::Rng)>,
+}
+
+impl FaultInjector {
+    pub fn new() -> Self {
+        Self {
+            rng: Mutex::new((None, fastrand::Rng::new())),
+        }
+    }
+
+    /// 按概率掷骰，`rate <= 0` 时不消耗随机数
+    fn roll(&self, config: &ChaosConfig, rate: f64) -> bool {
+        if rate <= 0.0 {
+            return false;
+        }
+        let mut guard = self.rng.lock();
+        if guard.0 != config.seed {
+            *guard = (
+                config.seed,
+                config
+                    .seed
+                    .map(fastrand::Rng::with_seed)
+                    .unwrap_or_default(),
+            );
+        }
+        guard.1.f64() < rate
+    }
+
+    fn delay(&self, config: &ChaosConfig) -> Option<Duration> {
+        if !self.roll(config, config.delay_rate) {
+            return None;
+        }
+        let max = config.delay_max_ms.max(config.delay_min_ms);
+        let ms = self.rng.lock().1.u64(config.delay_min_ms..=max);
+        Some(Duration::from_millis(ms))
+    }
+
+    /// 返回模拟的 429 响应（命中时不应再向上游发送请求）
+    pub fn throttle(&self, config: &ChaosConfig) -> Option<reqwest::Response> {
+        if !self.roll(config, config.throttle_rate) {
+            return None;
+        }
+        tracing::warn!("故障注入: 模拟上游 429");
+        let response = http::Response::builder()
+            .status(429)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(reqwest::Body::from(THROTTLE_BODY))
+            .ok()?;
+        Some(reqwest::Response::from(response))
+    }
+
+    /// 包装成功响应：按概率在首个数据块前延迟，或在若干数据块后断开
+    pub fn wrap(&self, config: &ChaosConfig, response: reqwest::Response) -> reqwest::Response {
+        let delay = self.delay(config);
+        let drop_after = self
+            .roll(config, config.drop_rate)
+            .then_some(config.drop_after_chunks);
+        if delay.is_none() && drop_after.is_none() {
+            return response;
+        }
+        tracing::warn!(
+            "故障注入: 延迟 {:?}，{} 个数据块后断开",
+            delay.unwrap_or_default(),
+            drop_after.map_or("不".to_string(), |n| n.to_string())
+        );
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = stream::unfold(
+            (response.bytes_stream(), delay, 0u32, false),
+            move |(mut inner, delay, sent, done)| async move {
+                if done {
+                    return None;
+                }
+                if let Some(delay) = delay {
+                    tokio::time::sleep(delay).await;
+                }
+                if drop_after.is_some_and(|n| sent >= n) {
+                    let error = io::Error::new(
+                        io::ErrorKind::ConnectionReset,
+                        "connection reset (injected fault)",
+                    );
+                    return Some((Err(error), (inner, None, sent, true)));
+                }
+                let item = inner.next().await?.map_err(io::Error::other);
+                Some((item, (inner, None, sent + 1, false)))
+            },
+        );
+
+        let mut builder = http::Response::builder().status(status);
+        if let Some(h) = builder.headers_mut() {
+            *h = headers;
+        }
+        let rebuilt = builder
+            .body(reqwest::Body::wrap_stream(body))
+            .expect("重建响应失败");
+        reqwest::Response::from(rebuilt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunked(chunks: &[&'static str]) -> reqwest::Response {
+        let chunks: Vec<Result<bytes::Bytes, io::Error>> = chunks
+            .iter()
+            .map(|c| Ok(bytes::Bytes::from_static(c.as_bytes())))
+            .collect();
+        let response = http::Response::builder()
+            .status(200)
+            .body(reqwest::Body::wrap_stream(stream::iter(chunks)))
+            .unwrap();
+        reqwest::Response::from(response)
+    }
+
+    #[test]
+    fn test_seeded_rolls_are_reproducible() {
+        let config = ChaosConfig {
+            enabled: true,
+            throttle_rate: 0.5,
+            seed: Some(42),
+            ..Default::default()
+        };
+        let sequence = |injector: &FaultInjector| -> Vec<bool> {
+            (0..32)
+                .map(|_| injector.roll(&config, config.throttle_rate))
+                .collect()
+        };
+        let first = sequence(&FaultInjector::new());
+        assert_eq!(first, sequence(&FaultInjector::new()));
+        assert!(first.contains(&true) && first.contains(&false));
+        assert!(!FaultInjector::new().roll(&config, 0.0));
+    }
+
+    #[tokio::test]
+    async fn test_throttle_returns_upstream_style_429() {
+        let config = ChaosConfig {
+            enabled: true,
+            throttle_rate: 1.0,
+            ..Default::default()
+        };
+        let response = FaultInjector::new().throttle(&config).unwrap();
+        assert_eq!(response.status(), 429);
+        let body = response.text().await.unwrap();
+        assert!(body.contains("INJECTED_FAULT"));
+    }
+
+    #[tokio::test]
+    async fn test_wrap_drops_mid_stream() {
+        let config = ChaosConfig {
+            enabled: true,
+            drop_rate: 1.0,
+            drop_after_chunks: 2,
+            ..Default::default()
+        };
+        let response = FaultInjector::new().wrap(&config, chunked(&["a", "b", "c"]));
+        let mut body = response.bytes_stream();
+        assert_eq!(body.next().await.unwrap().unwrap(), "a");
+        assert_eq!(body.next().await.unwrap().unwrap(), "b");
+        assert!(body.next().await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wrap_delays_first_chunk() {
+        let config = ChaosConfig {
+            enabled: true,
+            delay_rate: 1.0,
+            delay_min_ms: 50,
+            delay_max_ms: 50,
+            ..Default::default()
+        };
+        let start = std::time::Instant::now();
+        let response = FaultInjector::new().wrap(&config, chunked(&["a", "b"]));
+        assert_eq!(response.bytes().await.unwrap(), "ab");
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,114 @@
+//! 凭据级并发限制
+//!
+//! 每个凭据一个信号量，限制同时进行的上游请求数（`credentialMaxConcurrency`），
+//! 避免突发并发全部压在优先级最高的凭据上触发上游限流。
+//! 许可随响应体一起释放，流式响应在传输结束前一直占用。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::StreamExt;
+use parking_lot::Mutex;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// 凭据并发限制器
+pub struct ConcurrencyLimiter {
+    /// 凭据 ID -> (创建时的上限, 信号量)，上限热更新后重建信号量
+    semaphores: Mutex<HashMap<u64, (usize, Arc<Semaphore>)>>,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new() -> Self {
+        Self {
+            semaphores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn semaphore(&self, id: u64, limit: usize) -> Arc<Semaphore> {
+        let mut semaphores = self.semaphores.lock();
+        match semaphores.get(&id) {
+            Some((current, semaphore)) if *current == limit => semaphore.clone(),
+            // 上限变化时旧信号量上的许可随请求结束自然释放
+            _ => {
+                let semaphore = Arc::new(Semaphore::new(limit));
+                semaphores.insert(id, (limit, semaphore.clone()));
+                semaphore
+            }
+        }
+    }
+
+    /// 尝试占用凭据的一个并发名额，已满时返回 None
+    pub fn try_acquire(&self, id: u64, limit: usize) -> Option<OwnedSemaphorePermit> {
+        self.semaphore(id, limit).try_acquire_owned().ok()
+    }
+
+    /// 等待凭据空出并发名额
+    pub async fn acquire(&self, id: u64, limit: usize) -> OwnedSemaphorePermit {
+        self.semaphore(id, limit)
+            .acquire_owned()
+            .await
+            .expect("凭据信号量不会被关闭")
+    }
+
+    /// 当前正在进行的请求数（用于日志）
+    pub fn in_flight(&self, id: u64) -> usize {
+        self.semaphores
+            .lock()
+            .get(&id)
+            .map_or(0, |(limit, s)| limit.saturating_sub(s.available_permits()))
+    }
+}
+
+/// 将许可绑定到响应体，响应体读完或被丢弃时释放
+pub fn hold(permit: OwnedSemaphorePermit, response: reqwest::Response) -> reqwest::Response {
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response.bytes_stream().map(move |item| {
+        let _ = &permit;
+        item
+    });
+
+    let mut builder = http::Response::builder().status(status);
+    if let Some(h) = builder.headers_mut() {
+        *h = headers;
+    }
+    let rebuilt = builder
+        .body(reqwest::Body::wrap_stream(body))
+        .expect("重建响应失败");
+    reqwest::Response::from(rebuilt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_limit_per_credential_and_release_with_body() {
+        let limiter = ConcurrencyLimiter::new();
+        let first = limiter.try_acquire(1, 1).unwrap();
+        assert!(limiter.try_acquire(1, 1).is_none());
+        assert!(limiter.try_acquire(2, 1).is_some());
+        assert_eq!(limiter.in_flight(1), 1);
+
+        let response = reqwest::Response::from(
+            http::Response::builder()
+                .status(200)
+                .body(reqwest::Body::from("ok"))
+                .unwrap(),
+        );
+        let response = hold(first, response);
+        assert!(limiter.try_acquire(1, 1).is_none());
+        assert_eq!(response.text().await.unwrap(), "ok");
+        assert!(limiter.try_acquire(1, 1).is_some());
+    }
+
+    #[test]
+    fn test_limit_change_recreates_semaphore() {
+        let limiter = ConcurrencyLimiter::new();
+        let _held = limiter.try_acquire(1, 1).unwrap();
+        assert!(limiter.try_acquire(1, 1).is_none());
+        let _a = limiter.try_acquire(1, 2).unwrap();
+        let _b = limiter.try_acquire(1, 2).unwrap();
+        assert!(limiter.try_acquire(1, 2).is_none());
+    }
+}
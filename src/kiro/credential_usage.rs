@@ -0,0 +1,159 @@
+//! 凭据级用量统计
+//!
+//! 按凭据累计请求数与输入/输出 tokens，同时按 UTC 小时分桶保留最近 30 天的明细，
+//! 用于查看各 Kiro 账号在不同时间窗口内的实际消耗。随统计数据一起保存到 `kiro_stats.json`。
+//! 配置了模型单价（`pricing`）时同时累计费用。
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// 小时明细保留时长
+pub const RETENTION_HOURS: i64 = 30 * 24;
+
+/// 用量计数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageCounters {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// 按模型单价计算的费用（微美元，1e-6 USD）
+    #[serde(default)]
+    pub cost_micros: u64,
+}
+
+impl UsageCounters {
+    fn add(&mut self, other: &UsageCounters) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cost_micros += other.cost_micros;
+    }
+}
+
+/// 单个凭据的用量
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialUsage {
+    /// 累计用量
+    total: UsageCounters,
+    /// 按小时的用量，键为 UTC 整点的 Unix 时间戳（秒）
+    hourly: BTreeMap<i64, UsageCounters>,
+}
+
+/// 单个小时的用量
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HourlyUsage {
+    /// 整点时间（RFC3339）
+    pub hour: String,
+    #[serde(flatten)]
+    pub usage: UsageCounters,
+}
+
+/// 凭据用量报告
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialUsageReport {
+    pub id: u64,
+    /// 累计用量（自开始统计起）
+    pub total: UsageCounters,
+    pub last_hour: UsageCounters,
+    pub last_24_hours: UsageCounters,
+    pub last_7_days: UsageCounters,
+    pub last_30_days: UsageCounters,
+    /// 最近若干小时的明细（按时间正序，仅包含有用量的小时）
+    pub hourly: Vec<HourlyUsage>,
+}
+
+fn hour_start(time: DateTime<Utc>) -> i64 {
+    time.timestamp() - (time.minute() * 60 + time.second()) as i64
+}
+
+impl CredentialUsage {
+    /// 记录一次请求的用量，并清理超出保留时长的小时明细
+    pub fn record(
+        &mut self,
+        input_tokens: u64,
+        output_tokens: u64,
+        cost_micros: u64,
+        now: DateTime<Utc>,
+    ) {
+        let usage = UsageCounters {
+            requests: 1,
+            input_tokens,
+            output_tokens,
+            cost_micros,
+        };
+        self.total.add(&usage);
+        let hour = hour_start(now);
+        self.hourly.entry(hour).or_default().add(&usage);
+        let cutoff = hour - RETENTION_HOURS * 3600;
+        self.hourly = self.hourly.split_off(&cutoff);
+    }
+
+    /// 最近 `hours` 小时（含当前小时）的用量合计
+    fn window(&self, hours: i64, now: DateTime<Utc>) -> UsageCounters {
+        let since = hour_start(now) - (hours - 1) * 3600;
+        let mut sum = UsageCounters::default();
+        for usage in self.hourly.range(since..).map(|(_, u)| u) {
+            sum.add(usage);
+        }
+        sum
+    }
+
+    /// 生成用量报告，`hours` 为小时明细覆盖的时长（最多 30 天）
+    pub fn report(&self, id: u64, hours: i64, now: DateTime<Utc>) -> CredentialUsageReport {
+        let since = hour_start(now - Duration::hours(hours.clamp(1, RETENTION_HOURS) - 1));
+        let hourly = self
+            .hourly
+            .range(since..)
+            .filter_map(|(hour, usage)| {
+                let time = Utc.timestamp_opt(*hour, 0).single()?;
+                Some(HourlyUsage {
+                    hour: time.to_rfc3339(),
+                    usage: *usage,
+                })
+            })
+            .collect();
+        CredentialUsageReport {
+            id,
+            total: self.total,
+            last_hour: self.window(1, now),
+            last_24_hours: self.window(24, now),
+            last_7_days: self.window(7 * 24, now),
+            last_30_days: self.window(RETENTION_HOURS, now),
+            hourly,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_and_retention() {
+        let now = Utc.with_ymd_and_hms(2026, 3, 10, 12, 30, 0).unwrap();
+        let mut usage = CredentialUsage::default();
+        usage.record(100, 10, 0, now - Duration::days(40));
+        usage.record(50, 5, 500, now - Duration::days(3));
+        usage.record(20, 2, 200, now - Duration::hours(2));
+        usage.record(10, 1, 100, now);
+
+        let report = usage.report(7, 24, now);
+        assert_eq!(report.total.requests, 4);
+        assert_eq!(report.total.input_tokens, 180);
+        assert_eq!(report.last_hour.input_tokens, 10);
+        assert_eq!(report.last_24_hours.input_tokens, 30);
+        assert_eq!(report.last_7_days.output_tokens, 8);
+        assert_eq!(report.last_24_hours.cost_micros, 300);
+        // 超过 30 天的明细已清理，只计入累计用量
+        assert_eq!(report.last_30_days.requests, 3);
+        assert_eq!(report.hourly.len(), 2);
+        assert_eq!(report.hourly[1].hour, "2026-03-10T12:00:00+00:00");
+    }
+}
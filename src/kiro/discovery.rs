@@ -0,0 +1,278 @@
+//! 本机 Kiro 凭据发现
+//!
+//! 扫描 Kiro IDE / AWS SSO 的本地令牌缓存（`~/.aws/sso/cache`、`~/.kiro`），
+//! 找出含 refreshToken 的令牌文件，供管理 API 与 `--import-local-credentials` 导入。
+//! IdC 令牌通过 `clientIdHash` 关联同目录下的客户端注册文件补全 clientId / clientSecret。
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::token_manager::MultiTokenManager;
+
+/// 本地令牌文件（Kiro IDE `kiro-auth-token.json` 及 AWS SSO 缓存格式）
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenFile {
+    refresh_token: Option<String>,
+    access_token: Option<String>,
+    expires_at: Option<String>,
+    auth_method: Option<String>,
+    provider: Option<String>,
+    profile_arn: Option<String>,
+    region: Option<String>,
+    client_id_hash: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// IdC 客户端注册文件
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ClientRegistration {
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// 发现的本地凭据
+#[derive(Debug, Clone)]
+pub struct DiscoveredCredential {
+    /// 令牌文件路径
+    pub source: PathBuf,
+    /// 登录方式（Github / Google / BuilderId 等），文件中未记录时为 None
+    pub provider: Option<String>,
+    pub credentials: KiroCredentials,
+}
+
+/// 默认扫描目录（相对用户主目录）
+fn default_dirs(home: &Path) -> Vec<PathBuf> {
+    vec![
+        home.join(".aws").join("sso").join("cache"),
+        home.join(".kiro"),
+    ]
+}
+
+/// 当前用户主目录
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .filter(|h| !h.is_empty())
+        .map(PathBuf::from)
+}
+
+/// 扫描当前用户主目录下的已知位置
+pub fn discover() -> Vec<DiscoveredCredential> {
+    match home_dir() {
+        Some(home) => discover_in(&default_dirs(&home)),
+        None => {
+            tracing::warn!("无法确定用户主目录，跳过本地凭据扫描");
+            Vec::new()
+        }
+    }
+}
+
+/// 导入扫描到的全部凭据（`--import-local-credentials`），已存在的凭据会被跳过
+pub async fn import_all(token_manager: &MultiTokenManager) {
+    let found = discover();
+    if found.is_empty() {
+        tracing::info!("未在本机发现 Kiro 凭据");
+        return;
+    }
+    for credential in found {
+        let source = credential.source.display().to_string();
+        match token_manager.add_credential(credential.credentials).await {
+            Ok(id) => tracing::info!("已导入本机凭据 #{}: {}", id, source),
+            Err(e) => tracing::warn!("导入本机凭据失败: {}: {}", source, e),
+        }
+    }
+}
+
+/// 扫描指定目录（不递归）中的 JSON 令牌文件，按路径排序
+pub fn discover_in(dirs: &[PathBuf]) -> Vec<DiscoveredCredential> {
+    let mut found = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            if let Some(credential) = parse_token_file(&path) {
+                found.push(credential);
+            }
+        }
+    }
+    found
+}
+
+/// 解析单个令牌文件，不含 refreshToken 的文件（如客户端注册文件）返回 None
+fn parse_token_file(path: &Path) -> Option<DiscoveredCredential> {
+    let content = fs::read_to_string(path).ok()?;
+    let dir = path.parent();
+    parse_token(path.to_path_buf(), &content, |hash| {
+        fs::read_to_string(dir?.join(format!("{}.json", hash))).ok()
+    })
+}
+
+/// 解析令牌内容，`registration` 按 `clientIdHash` 查找客户端注册文件内容
+fn parse_token(
+    source: PathBuf,
+    content: &str,
+    registration: impl Fn(&str) -> Option<String>,
+) -> Option<DiscoveredCredential> {
+    let token: TokenFile = serde_json::from_str(content).ok()?;
+    let refresh_token = token.refresh_token.filter(|t| !t.trim().is_empty())?;
+
+    let (mut client_id, mut client_secret) = (token.client_id, token.client_secret);
+    if client_id.is_none()
+        && let Some(hash) = &token.client_id_hash
+        && let Some(content) = registration(hash)
+        && let Ok(registration) = serde_json::from_str::<ClientRegistration>(&content)
+    {
+        client_id = registration.client_id;
+        client_secret = registration.client_secret;
+    }
+
+    // Kiro IDE 记录为 "social" / "IdC"；带客户端注册信息的 SSO 令牌一律按 IdC 处理
+    let auth_method = match token.auth_method.as_deref() {
+        Some(m) if m.eq_ignore_ascii_case("social") => "social",
+        Some(_) => "idc",
+        None if client_id.is_some() => "idc",
+        None => "social",
+    };
+    if auth_method == "idc" && (client_id.is_none() || client_secret.is_none()) {
+        tracing::debug!("跳过缺少客户端注册信息的 IdC 令牌: {}", source.display());
+        return None;
+    }
+
+    Some(DiscoveredCredential {
+        source,
+        provider: token.provider,
+        credentials: KiroCredentials {
+            access_token: token.access_token,
+            refresh_token: Some(refresh_token),
+            profile_arn: token.profile_arn,
+            expires_at: token.expires_at,
+            auth_method: Some(auth_method.to_string()),
+            client_id,
+            client_secret,
+            region: token.region,
+            ..Default::default()
+        },
+    })
+}
+
+/// 解析上传的令牌文件（文件名, 内容），IdC 客户端注册文件须一并上传
+///
+/// 返回 (解析出的凭据, 未能解析出凭据的文件名)；客户端注册文件不计入未解析列表
+pub fn parse_uploaded(files: &[(String, String)]) -> (Vec<DiscoveredCredential>, Vec<String>) {
+    let file_name = |name: &str| name.rsplit(['/', '\\']).next().unwrap_or(name).to_string();
+    let lookup = |hash: &str| {
+        let target = format!("{}.json", hash);
+        files
+            .iter()
+            .find(|(name, _)| file_name(name) == target)
+            .map(|(_, content)| content.clone())
+    };
+
+    let mut found = Vec::new();
+    let mut skipped = Vec::new();
+    for (name, content) in files {
+        match parse_token(PathBuf::from(name), content, lookup) {
+            Some(credential) => found.push(credential),
+            None if serde_json::from_str::<ClientRegistration>(content)
+                .is_ok_and(|r| r.client_id.is_some()) => {}
+            None => skipped.push(name.clone()),
+        }
+    }
+    (found, skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kiro-discovery-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_discovers_social_and_idc_tokens() {
+        let dir = temp_dir();
+        fs::write(
+            dir.join("kiro-auth-token.json"),
+            r#"{"accessToken":"a","refreshToken":"r-social","expiresAt":"2026-01-01T00:00:00Z","authMethod":"social","provider":"Github","profileArn":"arn:aws:codewhisperer:us-east-1:1:profile/X"}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("sso-token.json"),
+            r#"{"refreshToken":"r-idc","authMethod":"IdC","provider":"BuilderId","region":"us-east-1","clientIdHash":"abc"}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("abc.json"),
+            r#"{"clientId":"cid","clientSecret":"secret","expiresAt":"2026-01-01T00:00:00Z"}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("orphan.json"),
+            r#"{"refreshToken":"r","authMethod":"IdC"}"#,
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "refreshToken").unwrap();
+
+        let found = discover_in(&[dir.clone(), dir.join("missing")]);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(found.len(), 2);
+        let social = &found[0];
+        assert!(social.source.ends_with("kiro-auth-token.json"));
+        assert_eq!(social.provider.as_deref(), Some("Github"));
+        assert_eq!(social.credentials.auth_method.as_deref(), Some("social"));
+        assert!(social.credentials.profile_arn.is_some());
+
+        let idc = &found[1];
+        assert_eq!(idc.credentials.auth_method.as_deref(), Some("idc"));
+        assert_eq!(idc.credentials.refresh_token.as_deref(), Some("r-idc"));
+        assert_eq!(idc.credentials.client_id.as_deref(), Some("cid"));
+        assert_eq!(idc.credentials.client_secret.as_deref(), Some("secret"));
+        assert_eq!(idc.credentials.region.as_deref(), Some("us-east-1"));
+    }
+
+    #[test]
+    fn test_parse_uploaded_resolves_registration_by_file_name() {
+        let files = vec![
+            (
+                "cache/sso-token.json".to_string(),
+                r#"{"refreshToken":"r-idc","authMethod":"IdC","clientIdHash":"abc"}"#.to_string(),
+            ),
+            (
+                "cache/abc.json".to_string(),
+                r#"{"clientId":"cid","clientSecret":"secret"}"#.to_string(),
+            ),
+            (
+                "kiro-auth-token.json".to_string(),
+                r#"{"refreshToken":"r-social","authMethod":"social"}"#.to_string(),
+            ),
+            (
+                "orphan.json".to_string(),
+                r#"{"refreshToken":"r","authMethod":"IdC","clientIdHash":"zzz"}"#.to_string(),
+            ),
+            ("broken.json".to_string(), "not json".to_string()),
+        ];
+
+        let (found, skipped) = parse_uploaded(&files);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].credentials.client_id.as_deref(), Some("cid"));
+        assert_eq!(found[0].source, PathBuf::from("cache/sso-token.json"));
+        assert_eq!(found[1].credentials.auth_method.as_deref(), Some("social"));
+        assert_eq!(skipped, vec!["orphan.json", "broken.json"]);
+    }
+}
@@ -0,0 +1,130 @@
+//! 非流式请求对冲
+//!
+//! 非流式请求超过近期 p95 耗时仍未完成时，在另一个凭据上发起一份相同的请求，
+//! 取先完成的结果。对冲受预算约束：每个请求按 `budgetPercent` 积累额度，
+//! 发起一次对冲消耗 1 份，上游整体变慢时不会把额度消耗翻倍。
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::model::config::HedgingConfig;
+
+/// 参与 p95 统计的最近请求数
+const LATENCY_WINDOW: usize = 200;
+/// 样本不足时不对冲
+const MIN_SAMPLES: usize = 20;
+/// 预算额度上限，避免长时间空闲后集中对冲
+const MAX_BUDGET: f64 = 10.0;
+
+/// 对冲状态：近期耗时与剩余预算
+pub struct Hedger {
+    /// 最近非流式请求的耗时（毫秒）
+    latencies: Mutex<VecDeque<u64>>,
+    budget: Mutex<f64>,
+}
+
+impl Hedger {
+    pub fn new() -> Self {
+        Self {
+            latencies: Mutex::new(VecDeque::with_capacity(LATENCY_WINDOW)),
+            budget: Mutex::new(0.0),
+        }
+    }
+
+    /// 记录一次请求耗时
+    pub fn record(&self, elapsed: Duration) {
+        let mut latencies = self.latencies.lock();
+        if latencies.len() >= LATENCY_WINDOW {
+            latencies.pop_front();
+        }
+        latencies.push_back(elapsed.as_millis() as u64);
+    }
+
+    /// 近期耗时的 p95，样本不足时返回 None
+    fn p95(&self) -> Option<Duration> {
+        let mut sorted: Vec<u64> = self.latencies.lock().iter().copied().collect();
+        if sorted.len() < MIN_SAMPLES {
+            return None;
+        }
+        sorted.sort_unstable();
+        let index = (sorted.len() * 95).div_ceil(100) - 1;
+        Some(Duration::from_millis(sorted[index]))
+    }
+
+    /// 发起对冲前的等待时长（不低于 `minDelayMs`），样本不足时返回 None
+    pub fn delay(&self, config: &HedgingConfig) -> Option<Duration> {
+        self.p95()
+            .map(|p95| p95.max(Duration::from_millis(config.min_delay_ms)))
+    }
+
+    /// 每个请求积累一份预算
+    pub fn deposit(&self, config: &HedgingConfig) {
+        let mut budget = self.budget.lock();
+        *budget = (*budget + config.budget_percent.max(0.0) / 100.0).min(MAX_BUDGET);
+    }
+
+    /// 尝试消耗一次对冲预算
+    pub fn try_spend(&self) -> bool {
+        let mut budget = self.budget.lock();
+        if *budget >= 1.0 {
+            *budget -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(budget_percent: f64) -> HedgingConfig {
+        HedgingConfig {
+            enabled: true,
+            min_delay_ms: 100,
+            budget_percent,
+        }
+    }
+
+    #[test]
+    fn test_delay_uses_p95_with_floor() {
+        let hedger = Hedger::new();
+        for i in 1..=100 {
+            if i == MIN_SAMPLES as u64 {
+                assert!(hedger.delay(&config(10.0)).is_none());
+            }
+            hedger.record(Duration::from_millis(i * 10));
+        }
+        assert_eq!(
+            hedger.delay(&config(10.0)),
+            Some(Duration::from_millis(950))
+        );
+
+        let fast = Hedger::new();
+        for _ in 0..MIN_SAMPLES {
+            fast.record(Duration::from_millis(5));
+        }
+        assert_eq!(fast.delay(&config(10.0)), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn test_budget_limits_hedges() {
+        let hedger = Hedger::new();
+        let config = config(10.0);
+        let mut hedges = 0;
+        for _ in 0..100 {
+            hedger.deposit(&config);
+            if hedger.try_spend() {
+                hedges += 1;
+            }
+        }
+        assert!((9..=10).contains(&hedges));
+
+        let disabled = Hedger::new();
+        disabled.deposit(&self::config(0.0));
+        assert!(!disabled.try_spend());
+    }
+}
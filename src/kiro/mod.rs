@@ -1,7 +1,16 @@
 //! Kiro API 客户端模块
 
+pub mod chaos;
+pub mod concurrency;
+pub mod credential_usage;
+pub mod discovery;
+pub mod hedging;
 pub mod machine_id;
 pub mod model;
 pub mod parser;
 pub mod provider;
+pub mod proxy_health;
+pub mod recording;
+pub mod region_pin;
+pub mod rotation;
 pub mod token_manager;
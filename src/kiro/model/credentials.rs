@@ -6,12 +6,13 @@
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
+use utoipa::ToSchema;
 
 use crate::http_client::ProxyConfig;
-use crate::model::config::Config;
+use crate::model::config::{Config, RequestPolicy};
 
 /// Kiro OAuth 凭证
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct KiroCredentials {
     /// 凭据唯一标识符（自增 ID）
@@ -96,6 +97,19 @@ pub struct KiroCredentials {
     /// 凭据是否被禁用（默认为 false）
     #[serde(default)]
     pub disabled: bool,
+
+    /// 是否属于灰度（canary）凭据池
+    /// 配合 config.json 的 canaryPercentage 使用，按比例分流请求
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub canary: bool,
+
+    /// 凭据级上游请求超时与重试策略（可选）
+    /// 按字段覆盖 config.json 的 requestPolicy，例如为走慢代理的凭据放宽超时
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub request_policy: Option<RequestPolicy>,
 }
 
 /// 判断是否为零（用于跳过序列化）
@@ -216,6 +230,13 @@ impl KiroCredentials {
             .unwrap_or(config.effective_api_region())
     }
 
+    /// 是否配置了凭据自身的代理（`direct` 不算）
+    pub fn has_own_proxy(&self) -> bool {
+        self.proxy_url
+            .as_deref()
+            .is_some_and(|url| !url.eq_ignore_ascii_case(Self::PROXY_DIRECT))
+    }
+
     /// 获取有效的代理配置
     /// 优先级：凭据代理 > 全局代理 > 无代理
     /// 特殊值 "direct" 表示显式不使用代理（即使全局配置了代理）
@@ -339,6 +360,8 @@ mod tests {
             proxy_username: None,
             proxy_password: None,
             disabled: false,
+            canary: false,
+            request_policy: None,
         };
 
         let json = creds.to_pretty_json().unwrap();
@@ -457,6 +480,8 @@ mod tests {
             proxy_username: None,
             proxy_password: None,
             disabled: false,
+            canary: false,
+            request_policy: None,
         };
 
         let json = creds.to_pretty_json().unwrap();
@@ -487,6 +512,8 @@ mod tests {
             proxy_username: None,
             proxy_password: None,
             disabled: false,
+            canary: false,
+            request_policy: None,
         };
 
         let json = creds.to_pretty_json().unwrap();
@@ -599,6 +626,8 @@ mod tests {
             proxy_username: None,
             proxy_password: None,
             disabled: false,
+            canary: false,
+            request_policy: None,
         };
 
         let json = original.to_pretty_json().unwrap();
@@ -19,6 +19,19 @@ pub struct UsageLimitsResponse {
     /// 使用量明细列表
     #[serde(default)]
     pub usage_breakdown_list: Vec<UsageBreakdown>,
+
+    /// 账号信息（请求带 isEmailRequired=true 时返回）
+    #[serde(default)]
+    pub user_info: Option<UserInfo>,
+}
+
+/// 账号信息
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserInfo {
+    /// 账号邮箱
+    #[serde(default)]
+    pub email: Option<String>,
 }
 
 /// 订阅信息
@@ -139,6 +152,14 @@ impl UsageLimitsResponse {
             .and_then(|info| info.subscription_title.as_deref())
     }
 
+    /// 获取账号邮箱
+    pub fn email(&self) -> Option<&str> {
+        self.user_info
+            .as_ref()
+            .and_then(|info| info.email.as_deref())
+            .filter(|email| !email.is_empty())
+    }
+
     /// 获取第一个使用量明细
     fn primary_breakdown(&self) -> Option<&UsageBreakdown> {
         self.usage_breakdown_list.first()
@@ -197,4 +218,14 @@ impl UsageLimitsResponse {
 
         total
     }
+
+    /// 获取使用率（百分比，0-100），无限额时为 0
+    pub fn usage_percentage(&self) -> f64 {
+        let usage_limit = self.usage_limit();
+        if usage_limit > 0.0 {
+            (self.current_usage() / usage_limit * 100.0).min(100.0)
+        } else {
+            0.0
+        }
+    }
 }
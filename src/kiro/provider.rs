@@ -4,20 +4,38 @@
 //! 支持流式和非流式请求
 //! 支持多凭据故障转移和重试
 
+use chrono::Utc;
 use reqwest::Client;
-use reqwest::header::{AUTHORIZATION, CONNECTION, CONTENT_TYPE, HOST, HeaderMap, HeaderValue};
+use reqwest::header::{
+    AUTHORIZATION, CONNECTION, CONTENT_TYPE, HOST, HeaderMap, HeaderValue, RETRY_AFTER,
+};
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use tokio::time::sleep;
 use uuid::Uuid;
 
-use crate::http_client::{ProxyConfig, build_client};
+use crate::http_client::{ClientTimeouts, ProxyConfig, build_client_with_timeouts};
+use crate::kiro::chaos::FaultInjector;
+use crate::kiro::concurrency::{self, ConcurrencyLimiter};
+use crate::kiro::hedging::Hedger;
 use crate::kiro::machine_id;
 use crate::kiro::model::credentials::KiroCredentials;
-use crate::kiro::token_manager::{CallContext, MultiTokenManager};
-use crate::model::config::TlsBackend;
+use crate::kiro::model::events::Event;
+use crate::kiro::model::requests::conversation::{
+    ConversationState, CurrentMessage, UserInputMessage,
+};
+use crate::kiro::model::requests::kiro::KiroRequest;
+use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::recording;
+use crate::kiro::token_manager::{CallContext, ErrorClass, MultiTokenManager};
+use crate::model::config::{
+    BackoffStrategy, FailoverClass, HedgingConfig, ProxyFallbackConfig, ProxyFallbackTarget,
+    RecordingMode, RequestPolicy, TlsBackend,
+};
 use parking_lot::Mutex;
+use tokio::sync::OwnedSemaphorePermit;
 
 /// 每个凭据的最大重试次数
 const MAX_RETRIES_PER_CREDENTIAL: usize = 3;
@@ -25,19 +43,242 @@ const MAX_RETRIES_PER_CREDENTIAL: usize = 3;
 /// 总重试次数硬上限（避免无限重试）
 const MAX_TOTAL_RETRIES: usize = 9;
 
+/// 上游请求默认总超时（秒）
+const REQUEST_TIMEOUT_SECS: u64 = 720;
+
+/// 重试退避默认基准间隔与最大间隔（毫秒）
+const BACKOFF_BASE_MS: u64 = 200;
+const BACKOFF_MAX_MS: u64 = 2_000;
+
+/// 预热请求使用的模型（输出极短，开销最低）
+pub const WARM_UP_MODEL: &str = "claude-haiku-4.5";
+
+/// 上游错误代码：对话历史累积超出模型上下文窗口
+pub const CONTENT_LENGTH_EXCEEDS_THRESHOLD: &str = "CONTENT_LENGTH_EXCEEDS_THRESHOLD";
+
+/// 上游错误代码：限流（部分限流响应为 400 且只在响应体的 `__type` 中出现）
+pub const THROTTLING_EXCEPTION: &str = "ThrottlingException";
+
+/// Provider 层错误
+///
+/// 以 `anyhow::Error` 形式返回，handler 通过 `downcast_ref` 取出后映射为 HTTP 响应
+#[derive(Debug)]
+pub enum ProviderError {
+    /// 上游返回错误状态码
+    Upstream {
+        status: u16,
+        /// 上游错误代码（响应体中的 `reason` 字段）
+        code: Option<String>,
+        /// 上游错误信息（响应体中的 `message` 字段，缺失时为原始响应体）
+        message: String,
+        /// 是否为瞬态错误（408/429/5xx），可重试
+        retryable: bool,
+        /// 上游响应的 Retry-After（秒）
+        retry_after_secs: Option<u64>,
+    },
+    /// 所有凭据均不可用（全部禁用或持续失败）
+    ///
+    /// 由 handler 映射为 529 `overloaded_error`（最后一次失败为上游限流时为 429 `rate_limit_error`），
+    /// 并通过 Retry-After 提示客户端退避
+    Overloaded {
+        message: String,
+        /// 预估的最早恢复等待秒数
+        retry_after_secs: u64,
+        /// 最后一次失败是否为上游限流
+        throttled: bool,
+    },
+}
+
+impl ProviderError {
+    /// 从上游错误响应构造
+    pub fn upstream(status: reqwest::StatusCode, body: &str) -> Self {
+        let value = serde_json::from_str::<serde_json::Value>(body).ok();
+        let field = |name: &str| {
+            value.as_ref().and_then(|v| {
+                v.get(name)
+                    .or_else(|| v.get("error").and_then(|e| e.get(name)))
+                    .and_then(|f| f.as_str())
+                    .map(str::to_string)
+            })
+        };
+        // 部分错误不带 JSON reason，仅在响应体中出现错误代码
+        let code = field("reason").or_else(|| {
+            [CONTENT_LENGTH_EXCEEDS_THRESHOLD, THROTTLING_EXCEPTION]
+                .into_iter()
+                .find(|code| body.contains(code))
+                .map(str::to_string)
+        });
+        Self::Upstream {
+            status: status.as_u16(),
+            code,
+            message: field("message").unwrap_or_else(|| body.to_string()),
+            retryable: matches!(status.as_u16(), 408 | 429) || status.is_server_error(),
+            retry_after_secs: None,
+        }
+    }
+
+    /// 附加上游响应头中的 Retry-After（仅支持秒数形式）
+    pub fn with_retry_after(mut self, headers: &HeaderMap) -> Self {
+        if let Self::Upstream {
+            retry_after_secs, ..
+        } = &mut self
+        {
+            *retry_after_secs = headers
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse().ok());
+        }
+        self
+    }
+
+    /// 是否为上游限流（429 或 ThrottlingException）
+    pub fn is_throttled(&self) -> bool {
+        match self {
+            Self::Upstream { status, code, .. } => {
+                *status == 429 || code.as_deref() == Some(THROTTLING_EXCEPTION)
+            }
+            Self::Overloaded { throttled, .. } => *throttled,
+        }
+    }
+
+    /// 上游错误代码
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            Self::Upstream { code, .. } => code.as_deref(),
+            Self::Overloaded { .. } => None,
+        }
+    }
+
+    /// 是否可重试
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Upstream { retryable, .. } => *retryable,
+            Self::Overloaded { .. } => true,
+        }
+    }
+
+    /// 上下文窗口已满（对话历史累积超出限制）
+    pub fn is_context_window_full(&self) -> bool {
+        self.code() == Some(CONTENT_LENGTH_EXCEEDS_THRESHOLD)
+    }
+
+    /// 单次输入过长（请求体本身超出上游限制）
+    pub fn is_input_too_long(&self) -> bool {
+        matches!(self, Self::Upstream { message, .. } if message.contains("Input is too long"))
+    }
+}
+
+impl std::fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Upstream {
+                status,
+                code: Some(code),
+                message,
+                ..
+            } => write!(f, "{} [{}] {}", status, code, message),
+            Self::Upstream {
+                status, message, ..
+            } => write!(f, "{} {}", status, message),
+            Self::Overloaded { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+/// 成功响应所用的凭据 ID，附加在 `call_api` / `call_api_stream` 返回的响应扩展中
+#[derive(Debug, Clone, Copy)]
+pub struct ServedBy(pub u64);
+
+impl ServedBy {
+    /// 读取响应所用的凭据 ID
+    pub fn of(response: &reqwest::Response) -> Option<u64> {
+        response.extensions().get::<ServedBy>().map(|s| s.0)
+    }
+}
+
+/// 凭据探测请求的结果
+#[derive(Debug)]
+pub struct ProbeOutcome {
+    /// 从发出请求到收到首个数据块的耗时
+    pub first_byte_ms: u64,
+    /// 模型回复的文本
+    pub reply: String,
+}
+
+/// 单个请求的故障转移限制（`requestPolicy` 的 `maxCredentialsPerRequest`、`retryBudgetSecs` 与 `failoverOn`）
+struct FailoverBudget {
+    started: Instant,
+    budget: Option<Duration>,
+    max_credentials: Option<usize>,
+    failover_on: Vec<FailoverClass>,
+    /// 本次请求已使用的凭据
+    tried: Vec<u64>,
+}
+
+impl FailoverBudget {
+    fn new(policy: &RequestPolicy) -> Self {
+        Self {
+            started: Instant::now(),
+            budget: policy
+                .retry_budget_secs
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs),
+            max_credentials: policy.max_credentials_per_request.filter(|&n| n > 0),
+            failover_on: policy
+                .failover_on
+                .clone()
+                .unwrap_or_else(|| FailoverClass::DEFAULT.to_vec()),
+            tried: Vec::new(),
+        }
+    }
+
+    /// 重试耗时预算已用完
+    fn expired(&self) -> bool {
+        self.budget.is_some_and(|b| self.started.elapsed() >= b)
+    }
+
+    /// 记录本次使用的凭据，会超出凭据数上限时返回 false
+    fn admit(&mut self, id: u64) -> bool {
+        if self.tried.contains(&id) {
+            return true;
+        }
+        if self
+            .max_credentials
+            .is_some_and(|max| self.tried.len() >= max)
+        {
+            return false;
+        }
+        self.tried.push(id);
+        true
+    }
+
+    /// 该类错误是否继续重试
+    fn allows(&self, class: FailoverClass) -> bool {
+        self.failover_on.contains(&class)
+    }
+}
+
 /// Kiro API Provider
 ///
 /// 核心组件，负责与 Kiro API 通信
 /// 支持多凭据故障转移和重试机制
 pub struct KiroProvider {
     token_manager: Arc<MultiTokenManager>,
-    /// 全局代理配置（用于凭据无自定义代理时的回退）
-    global_proxy: Option<ProxyConfig>,
-    /// Client 缓存：key = effective proxy config, value = reqwest::Client
-    /// 不同代理配置的凭据使用不同的 Client，共享相同代理的凭据复用 Client
-    client_cache: Mutex<HashMap<Option<ProxyConfig>, Client>>,
+    /// Client 缓存：key = (effective proxy config, 超时配置), value = reqwest::Client
+    /// 不同代理或超时配置的凭据使用不同的 Client，配置相同的凭据复用 Client
+    client_cache: Mutex<HashMap<(Option<ProxyConfig>, ClientTimeouts), Client>>,
     /// TLS 后端配置
     tls_backend: TlsBackend,
+    /// 故障注入（配置 `chaos.enabled` 时生效）
+    chaos: FaultInjector,
+    /// 凭据级并发限制（配置 `credentialMaxConcurrency` 时生效）
+    limiter: ConcurrencyLimiter,
+    /// 非流式请求对冲（配置 `hedging.enabled` 时生效）
+    hedger: Hedger,
+    /// 替换 generateAssistantResponse 地址（仅测试中指向进程内的模拟上游）
+    endpoint_override: Option<String>,
 }
 
 impl KiroProvider {
@@ -48,33 +289,134 @@ impl KiroProvider {
 
     /// 创建带代理配置的 KiroProvider 实例
     pub fn with_proxy(token_manager: Arc<MultiTokenManager>, proxy: Option<ProxyConfig>) -> Self {
-        let tls_backend = token_manager.config().tls_backend;
-        // 预热：构建全局代理对应的 Client
-        let initial_client =
-            build_client(proxy.as_ref(), 720, tls_backend).expect("创建 HTTP 客户端失败");
+        let config = token_manager.config();
+        let tls_backend = config.tls_backend;
+        // 预热：构建全局代理与全局超时配置对应的 Client
+        let timeouts = Self::timeouts(&config.request_policy.clone().unwrap_or_default());
+        let initial_client = build_client_with_timeouts(proxy.as_ref(), timeouts, tls_backend)
+            .expect("创建 HTTP 客户端失败");
         let mut cache = HashMap::new();
-        cache.insert(proxy.clone(), initial_client);
+        cache.insert((proxy, timeouts), initial_client);
 
         Self {
             token_manager,
-            global_proxy: proxy,
             client_cache: Mutex::new(cache),
             tls_backend,
+            chaos: FaultInjector::new(),
+            limiter: ConcurrencyLimiter::new(),
+            hedger: Hedger::new(),
+            endpoint_override: None,
         }
     }
 
-    /// 根据凭据的代理配置获取（或创建并缓存）对应的 reqwest::Client
-    fn client_for(&self, credentials: &KiroCredentials) -> anyhow::Result<Client> {
-        let effective = credentials.effective_proxy(self.global_proxy.as_ref());
+    /// 将请求发往指定地址而不是 Kiro API（用于一致性测试）
+    #[cfg(test)]
+    pub(crate) fn with_endpoint(mut self, url: impl Into<String>) -> Self {
+        self.endpoint_override = Some(url.into());
+        self
+    }
+
+    /// 根据凭据的代理与超时配置获取（或创建并缓存）对应的 reqwest::Client
+    fn client_for(&self, ctx: &CallContext) -> anyhow::Result<Client> {
+        // 全局代理与超时配置从 token_manager 读取，配置热重载后对新请求立即生效
+        let global = self.token_manager.proxy();
+        let effective = match self.proxy_fallback(ctx) {
+            // 凭据代理处于回退期时改走回退目标
+            Some(fallback)
+                if self.token_manager.proxy_health().is_fallen_back(
+                    ctx.id,
+                    &fallback,
+                    Utc::now(),
+                ) =>
+            {
+                match fallback.target {
+                    ProxyFallbackTarget::Global => global,
+                    ProxyFallbackTarget::Direct => None,
+                }
+            }
+            _ => ctx.credentials.effective_proxy(global.as_ref()),
+        };
+        let timeouts = Self::timeouts(&self.policy_for(&ctx.credentials));
+        let key = (effective, timeouts);
         let mut cache = self.client_cache.lock();
-        if let Some(client) = cache.get(&effective) {
+        if let Some(client) = cache.get(&key) {
             return Ok(client.clone());
         }
-        let client = build_client(effective.as_ref(), 720, self.tls_backend)?;
-        cache.insert(effective, client.clone());
+        let client = build_client_with_timeouts(key.0.as_ref(), timeouts, self.tls_backend)?;
+        cache.insert(key, client.clone());
         Ok(client)
     }
 
+    /// 凭据配置了自身代理且启用 `proxyFallback` 时返回回退配置
+    fn proxy_fallback(&self, ctx: &CallContext) -> Option<ProxyFallbackConfig> {
+        if !ctx.credentials.has_own_proxy() {
+            return None;
+        }
+        self.token_manager
+            .config()
+            .proxy_fallback
+            .clone()
+            .filter(|c| c.enabled)
+    }
+
+    /// 记录经凭据代理发送的结果：连接失败计入代理健康状态，收到任何响应即视为代理可用
+    fn record_proxy_result(&self, ctx: &CallContext, result: Result<(), &reqwest::Error>) {
+        let Some(fallback) = self.proxy_fallback(ctx) else {
+            return;
+        };
+        let health = self.token_manager.proxy_health();
+        let now = Utc::now();
+        // 回退期间请求未经过凭据代理
+        if health.is_fallen_back(ctx.id, &fallback, now) {
+            return;
+        }
+        match result {
+            Ok(()) => health.record_success(ctx.id),
+            Err(e) if e.is_connect() => {
+                health.record_failure(ctx.id, &fallback, &e.to_string(), now)
+            }
+            Err(_) => {}
+        }
+    }
+
+    /// 凭据生效的超时与重试策略（凭据级配置按字段覆盖全局配置）
+    fn policy_for(&self, credentials: &KiroCredentials) -> RequestPolicy {
+        let config = self.token_manager.config();
+        credentials
+            .request_policy
+            .clone()
+            .unwrap_or_default()
+            .merged_over(config.request_policy.as_ref())
+    }
+
+    fn timeouts(policy: &RequestPolicy) -> ClientTimeouts {
+        ClientTimeouts {
+            connect_secs: policy.connect_timeout_secs,
+            read_secs: policy.read_timeout_secs,
+            total_secs: policy.timeout_secs.unwrap_or(REQUEST_TIMEOUT_SECS),
+        }
+    }
+
+    /// 单个请求的最大尝试次数
+    ///
+    /// 各凭据的尝试次数（`maxRetriesPerCredential`，凭据级可覆盖）之和，不超过 `maxTotalRetries`
+    fn max_attempts(&self) -> usize {
+        let policy = self
+            .token_manager
+            .config()
+            .request_policy
+            .clone()
+            .unwrap_or_default();
+        let per_credential = policy
+            .max_retries_per_credential
+            .unwrap_or(MAX_RETRIES_PER_CREDENTIAL);
+        let total = policy.max_total_retries.unwrap_or(MAX_TOTAL_RETRIES);
+        self.token_manager
+            .retry_budget(per_credential)
+            .min(total)
+            .max(1)
+    }
+
     /// 获取 token_manager 的引用
     pub fn token_manager(&self) -> &MultiTokenManager {
         &self.token_manager
@@ -106,9 +448,12 @@ impl KiroProvider {
 
     /// 获取凭据级 API 基础 URL
     fn base_url_for(&self, credentials: &KiroCredentials) -> String {
+        if let Some(url) = &self.endpoint_override {
+            return url.clone();
+        }
         format!(
             "https://q.{}.amazonaws.com/generateAssistantResponse",
-            credentials.effective_api_region(self.token_manager.config())
+            credentials.effective_api_region(&self.token_manager.config())
         )
     }
 
@@ -116,7 +461,7 @@ impl KiroProvider {
     fn mcp_url_for(&self, credentials: &KiroCredentials) -> String {
         format!(
             "https://q.{}.amazonaws.com/mcp",
-            credentials.effective_api_region(self.token_manager.config())
+            credentials.effective_api_region(&self.token_manager.config())
         )
     }
 
@@ -124,7 +469,7 @@ impl KiroProvider {
     fn base_domain_for(&self, credentials: &KiroCredentials) -> String {
         format!(
             "q.{}.amazonaws.com",
-            credentials.effective_api_region(self.token_manager.config())
+            credentials.effective_api_region(&self.token_manager.config())
         )
     }
 
@@ -152,7 +497,7 @@ impl KiroProvider {
     fn build_headers(&self, ctx: &CallContext) -> anyhow::Result<HeaderMap> {
         let config = self.token_manager.config();
 
-        let machine_id = machine_id::generate_from_credentials(&ctx.credentials, config)
+        let machine_id = machine_id::generate_from_credentials(&ctx.credentials, &config)
             .ok_or_else(|| anyhow::anyhow!("无法生成 machine_id，请检查凭证配置"))?;
 
         let kiro_version = &config.kiro_version;
@@ -207,7 +552,7 @@ impl KiroProvider {
     fn build_mcp_headers(&self, ctx: &CallContext) -> anyhow::Result<HeaderMap> {
         let config = self.token_manager.config();
 
-        let machine_id = machine_id::generate_from_credentials(&ctx.credentials, config)
+        let machine_id = machine_id::generate_from_credentials(&ctx.credentials, &config)
             .ok_or_else(|| anyhow::anyhow!("无法生成 machine_id，请检查凭证配置"))?;
 
         let kiro_version = &config.kiro_version;
@@ -265,7 +610,104 @@ impl KiroProvider {
     /// # Returns
     /// 返回原始的 HTTP Response，不做解析
     pub async fn call_api(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
-        self.call_api_with_retry(request_body, false).await
+        let config = self.token_manager.config();
+        match config.hedging.as_ref().filter(|h| h.enabled) {
+            Some(hedging) => self.call_api_hedged(request_body, hedging).await,
+            None => {
+                self.call_api_with_retry(request_body, false, &[], None)
+                    .await
+            }
+        }
+    }
+
+    /// 带对冲的非流式请求
+    ///
+    /// 超过近期 p95 耗时仍未完成且预算充足时，在其他凭据上发起相同请求，取先成功的结果；
+    /// 落后的一方随 future 一起被丢弃（连接随之取消）
+    async fn call_api_hedged(
+        &self,
+        request_body: &str,
+        config: &HedgingConfig,
+    ) -> anyhow::Result<reqwest::Response> {
+        self.hedger.deposit(config);
+        let start = Instant::now();
+        let primary_id = AtomicU64::new(0);
+        let primary = self.call_api_buffered(request_body, &[], Some(&primary_id));
+        tokio::pin!(primary);
+
+        let delay = self.hedger.delay(config);
+        let wait = async {
+            match delay {
+                Some(delay) => sleep(delay).await,
+                None => std::future::pending().await,
+            }
+        };
+        let result = tokio::select! {
+            result = &mut primary => Some(result),
+            _ = wait => None,
+        };
+        let result = match result {
+            Some(result) => result,
+            None if self.token_manager.available_count() < 2 || !self.hedger.try_spend() => {
+                primary.await
+            }
+            None => {
+                let avoid = [primary_id.load(Ordering::Relaxed)];
+                tracing::info!(
+                    "非流式请求 {:?} 内未完成，在其他凭据上发起对冲请求（主请求凭据 #{}）",
+                    delay.unwrap_or_default(),
+                    avoid[0]
+                );
+                let hedge = self.call_api_buffered(request_body, &avoid, None);
+                tokio::pin!(hedge);
+                tokio::select! {
+                    result = &mut primary => match result {
+                        Ok(response) => Ok(response),
+                        Err(e) => {
+                            tracing::warn!("主请求失败，等待对冲请求: {}", e);
+                            hedge.await
+                        }
+                    },
+                    result = &mut hedge => match result {
+                        Ok(response) => {
+                            tracing::info!("对冲请求先于主请求完成");
+                            Ok(response)
+                        }
+                        Err(e) => {
+                            tracing::warn!("对冲请求失败，等待主请求: {}", e);
+                            primary.await
+                        }
+                    },
+                }
+            }
+        };
+        if result.is_ok() {
+            self.hedger.record(start.elapsed());
+        }
+        result
+    }
+
+    /// 发送非流式请求并读完响应体，使对冲覆盖完整的生成耗时
+    async fn call_api_buffered(
+        &self,
+        request_body: &str,
+        avoid: &[u64],
+        in_use: Option<&AtomicU64>,
+    ) -> anyhow::Result<reqwest::Response> {
+        let response = self
+            .call_api_with_retry(request_body, false, avoid, in_use)
+            .await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+
+        let mut builder = http::Response::builder().status(status);
+        if let Some(h) = builder.headers_mut() {
+            *h = headers;
+        }
+        Ok(reqwest::Response::from(
+            builder.body(reqwest::Body::from(body))?,
+        ))
     }
 
     /// 发送流式 API 请求
@@ -282,7 +724,75 @@ impl KiroProvider {
     /// # Returns
     /// 返回原始的 HTTP Response，调用方负责处理流式数据
     pub async fn call_api_stream(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
-        self.call_api_with_retry(request_body, true).await
+        self.call_api_with_retry(request_body, true, &[], None)
+            .await
+    }
+
+    /// 用指定凭据发送一次预热请求并读完响应
+    ///
+    /// 不经过凭据选择、不重试，也不计入凭据的成功 / 失败统计；
+    /// 用于凭据加入轮换前建立连接、刷新 Token 并验证端到端可用
+    pub async fn warm_up(&self, id: u64) -> anyhow::Result<()> {
+        self.probe(id, None).await.map(|_| ())
+    }
+
+    /// 用指定凭据发送一条简短提示词并读完响应，返回首字节耗时与模型回复
+    ///
+    /// 与预热相同，不经过凭据选择、不重试，也不计入凭据统计；`model` 为 Kiro 模型 ID，
+    /// 未指定时使用预热模型。上游在事件流中返回错误或异常时视为失败
+    pub async fn probe(&self, id: u64, model: Option<&str>) -> anyhow::Result<ProbeOutcome> {
+        let ctx = self.token_manager.context_for(id).await?;
+        let model = model.unwrap_or(WARM_UP_MODEL);
+        let request = KiroRequest {
+            conversation_state: ConversationState::new(Uuid::new_v4().to_string())
+                .with_agent_task_type("vibe")
+                .with_chat_trigger_type("MANUAL")
+                .with_current_message(CurrentMessage::new(
+                    UserInputMessage::new("Reply with OK.", model).with_origin("AI_EDITOR"),
+                )),
+            profile_arn: ctx.credentials.profile_arn.clone(),
+        };
+        let started = Instant::now();
+        let sent = self
+            .client_for(&ctx)?
+            .post(self.base_url_for(&ctx.credentials))
+            .headers(self.build_headers(&ctx)?)
+            .body(serde_json::to_string(&request)?)
+            .send()
+            .await;
+        self.record_proxy_result(&ctx, sent.as_ref().map(|_| ()));
+        let mut response = sent?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProviderError::upstream(status, &body).into());
+        }
+
+        let mut first_byte = None;
+        let mut decoder = EventStreamDecoder::new();
+        let mut reply = String::new();
+        while let Some(chunk) = response.chunk().await? {
+            first_byte.get_or_insert_with(|| started.elapsed());
+            decoder.feed(&chunk)?;
+            for frame in decoder.decode_iter() {
+                match Event::from_frame(frame?)? {
+                    Event::AssistantResponse(e) => reply.push_str(&e.content),
+                    Event::Error {
+                        error_code,
+                        error_message,
+                    } => anyhow::bail!("上游返回错误 {}: {}", error_code, error_message),
+                    Event::Exception {
+                        exception_type,
+                        message,
+                    } => anyhow::bail!("上游返回异常 {}: {}", exception_type, message),
+                    _ => {}
+                }
+            }
+        }
+        Ok(ProbeOutcome {
+            first_byte_ms: first_byte.unwrap_or_else(|| started.elapsed()).as_millis() as u64,
+            reply,
+        })
     }
 
     /// 发送 MCP API 请求
@@ -300,8 +810,7 @@ impl KiroProvider {
 
     /// 内部方法：带重试逻辑的 MCP API 调用
     async fn call_mcp_with_retry(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
-        let total_credentials = self.token_manager.total_count();
-        let max_retries = (total_credentials * MAX_RETRIES_PER_CREDENTIAL).min(MAX_TOTAL_RETRIES);
+        let max_retries = self.max_attempts();
         let mut last_error: Option<anyhow::Error> = None;
 
         for attempt in 0..max_retries {
@@ -316,6 +825,7 @@ impl KiroProvider {
             };
 
             let url = self.mcp_url_for(&ctx.credentials);
+            let policy = self.policy_for(&ctx.credentials);
             let headers = match self.build_mcp_headers(&ctx) {
                 Ok(h) => h,
                 Err(e) => {
@@ -325,14 +835,15 @@ impl KiroProvider {
             };
 
             // 发送请求
-            let response = match self
-                .client_for(&ctx.credentials)?
+            let sent = self
+                .client_for(&ctx)?
                 .post(&url)
                 .headers(headers)
                 .body(request_body.to_string())
                 .send()
-                .await
-            {
+                .await;
+            self.record_proxy_result(&ctx, sent.as_ref().map(|_| ()));
+            let response = match sent {
                 Ok(resp) => resp,
                 Err(e) => {
                     tracing::warn!(
@@ -341,9 +852,11 @@ impl KiroProvider {
                         max_retries,
                         e
                     );
+                    self.token_manager
+                        .report_error(ctx.id, Self::error_class_for_send(&e));
                     last_error = Some(e.into());
                     if attempt + 1 < max_retries {
-                        sleep(Self::retry_delay(attempt)).await;
+                        sleep(Self::retry_delay(&policy, attempt)).await;
                     }
                     continue;
                 }
@@ -358,58 +871,68 @@ impl KiroProvider {
             }
 
             // 失败响应
+            let headers = response.headers().clone();
             let body = response.text().await.unwrap_or_default();
+            let error = ProviderError::upstream(status, &body).with_retry_after(&headers);
 
             // 402 额度用尽
             if status.as_u16() == 402 && Self::is_monthly_request_limit(&body) {
+                self.token_manager.report_error(ctx.id, ErrorClass::Other);
                 let has_available = self.token_manager.report_quota_exhausted(ctx.id);
                 if !has_available {
-                    anyhow::bail!("MCP 请求失败（所有凭据已用尽）: {} {}", status, body);
+                    return Err(
+                        self.overloaded(format!("MCP 请求失败（所有凭据已用尽）: {}", error))
+                    );
                 }
-                last_error = Some(anyhow::anyhow!("MCP 请求失败: {} {}", status, body));
+                last_error = Some(error.into());
                 continue;
             }
 
             // 400 Bad Request
             if status.as_u16() == 400 {
-                anyhow::bail!("MCP 请求失败: {} {}", status, body);
+                return Err(error.into());
             }
 
             // 401/403 凭据问题
             if matches!(status.as_u16(), 401 | 403) {
+                self.token_manager.report_error(ctx.id, ErrorClass::Auth);
                 let has_available = self.token_manager.report_failure(ctx.id);
                 if !has_available {
-                    anyhow::bail!("MCP 请求失败（所有凭据已用尽）: {} {}", status, body);
+                    return Err(
+                        self.overloaded(format!("MCP 请求失败（所有凭据已用尽）: {}", error))
+                    );
                 }
-                last_error = Some(anyhow::anyhow!("MCP 请求失败: {} {}", status, body));
+                last_error = Some(error.into());
                 continue;
             }
 
             // 瞬态错误
-            if matches!(status.as_u16(), 408 | 429) || status.is_server_error() {
+            if error.is_retryable() {
+                self.token_manager
+                    .report_error(ctx.id, ErrorClass::from_status(status.as_u16()));
                 tracing::warn!(
-                    "MCP 请求失败（上游瞬态错误，尝试 {}/{}）: {} {}",
+                    "MCP 请求失败（上游瞬态错误，尝试 {}/{}）: {}",
                     attempt + 1,
                     max_retries,
-                    status,
-                    body
+                    error
                 );
-                last_error = Some(anyhow::anyhow!("MCP 请求失败: {} {}", status, body));
+                last_error = Some(error.into());
                 if attempt + 1 < max_retries {
-                    sleep(Self::retry_delay(attempt)).await;
+                    sleep(Self::retry_delay(&policy, attempt)).await;
                 }
                 continue;
             }
 
             // 其他 4xx
             if status.is_client_error() {
-                anyhow::bail!("MCP 请求失败: {} {}", status, body);
+                return Err(error.into());
             }
 
             // 兜底
-            last_error = Some(anyhow::anyhow!("MCP 请求失败: {} {}", status, body));
+            self.token_manager.report_error(ctx.id, ErrorClass::Other);
+            last_error = Some(error.into());
             if attempt + 1 < max_retries {
-                sleep(Self::retry_delay(attempt)).await;
+                sleep(Self::retry_delay(&policy, attempt)).await;
             }
         }
 
@@ -421,33 +944,77 @@ impl KiroProvider {
     /// 内部方法：带重试逻辑的 API 调用
     ///
     /// 重试策略：
-    /// - 每个凭据最多重试 MAX_RETRIES_PER_CREDENTIAL 次
-    /// - 总重试次数 = min(凭据数量 × 每凭据重试次数, MAX_TOTAL_RETRIES)
-    /// - 硬上限 9 次，避免无限重试
+    /// - 每个凭据最多重试 `requestPolicy.maxRetriesPerCredential` 次（默认 3，凭据级可覆盖）
+    /// - 总重试次数 = min(各凭据重试次数之和, `requestPolicy.maxTotalRetries`)
+    /// - 默认硬上限 9 次，避免无限重试；重试间隔按 `requestPolicy.backoff` 退避
+    /// - 可按 `maxCredentialsPerRequest` / `retryBudgetSecs` 限制使用的凭据数与总耗时，
+    ///   不在 `failoverOn` 中的错误类别直接返回
+    ///
+    /// `avoid` 中的凭据仅在没有其他可用凭据时使用；`in_use` 记录当前使用的凭据（供对冲请求避开）
     async fn call_api_with_retry(
         &self,
         request_body: &str,
         is_stream: bool,
+        avoid: &[u64],
+        in_use: Option<&AtomicU64>,
     ) -> anyhow::Result<reqwest::Response> {
-        let total_credentials = self.token_manager.total_count();
-        let max_retries = (total_credentials * MAX_RETRIES_PER_CREDENTIAL).min(MAX_TOTAL_RETRIES);
+        let max_retries = self.max_attempts();
         let mut last_error: Option<anyhow::Error> = None;
         let api_type = if is_stream { "流式" } else { "非流式" };
 
         // 尝试从请求体中提取模型信息
         let model = Self::extract_model_from_request(request_body);
 
+        let config = self.token_manager.config();
+        let chaos = config.chaos.as_ref().filter(|c| c.enabled);
+
+        // 回放模式：不调用上游、不占用凭据，直接返回录制的响应
+        let recording_config = config.recording.as_ref();
+        if let Some(replay) = recording_config.filter(|r| r.mode == RecordingMode::Replay) {
+            return recording::replay(replay, request_body);
+        }
+        let record = recording_config.filter(|r| r.mode == RecordingMode::Record);
+
+        // 首字节超时（仅流式请求）及本次请求中需要避开的凭据（已超时、对冲时主请求使用的）
+        let first_byte_timeout = match config.stream_first_byte_timeout_secs {
+            secs if is_stream && secs > 0 => Some(Duration::from_secs(secs)),
+            _ => None,
+        };
+        let mut timed_out_ids: Vec<u64> = avoid.to_vec();
+        let concurrency_limit = config.credential_max_concurrency;
+        let mut budget = FailoverBudget::new(&config.request_policy.clone().unwrap_or_default());
+
         for attempt in 0..max_retries {
+            if attempt > 0 && budget.expired() {
+                tracing::warn!("{} API 请求已用完重试耗时预算，停止重试", api_type);
+                break;
+            }
+
             // 获取调用上下文（绑定 index、credentials、token）
-            let ctx = match self.token_manager.acquire_context(model.as_deref()).await {
+            let acquired = self
+                .acquire_with_permit(model.as_deref(), &timed_out_ids, concurrency_limit)
+                .await;
+            let (ctx, permit) = match acquired {
                 Ok(c) => c,
                 Err(e) => {
                     last_error = Some(e);
                     continue;
                 }
             };
+            if !budget.admit(ctx.id) {
+                tracing::warn!(
+                    "{} API 请求已尝试 {} 个凭据，停止故障转移",
+                    api_type,
+                    budget.tried.len()
+                );
+                break;
+            }
+            if let Some(in_use) = in_use {
+                in_use.store(ctx.id, Ordering::Relaxed);
+            }
 
             let url = self.base_url_for(&ctx.credentials);
+            let policy = self.policy_for(&ctx.credentials);
             let headers = match self.build_headers(&ctx) {
                 Ok(h) => h,
                 Err(e) => {
@@ -456,15 +1023,23 @@ impl KiroProvider {
                 }
             };
 
-            // 发送请求
-            let response = match self
-                .client_for(&ctx.credentials)?
-                .post(&url)
-                .headers(headers)
-                .body(request_body.to_string())
-                .send()
-                .await
-            {
+            // 发送请求（故障注入命中时直接使用模拟的 429 响应）
+            let injected = chaos.and_then(|c| self.chaos.throttle(c));
+            let sent = match injected {
+                Some(resp) => Ok(resp),
+                None => {
+                    let sent = self
+                        .client_for(&ctx)?
+                        .post(&url)
+                        .headers(headers)
+                        .body(request_body.to_string())
+                        .send()
+                        .await;
+                    self.record_proxy_result(&ctx, sent.as_ref().map(|_| ()));
+                    sent
+                }
+            };
+            let response = match sent {
                 Ok(resp) => resp,
                 Err(e) => {
                     tracing::warn!(
@@ -475,9 +1050,14 @@ impl KiroProvider {
                     );
                     // 网络错误通常是上游/链路瞬态问题，不应导致"禁用凭据"或"切换凭据"
                     // （否则一段时间网络抖动会把所有凭据都误禁用，需要重启才能恢复）
+                    self.token_manager
+                        .report_error(ctx.id, Self::error_class_for_send(&e));
+                    if !budget.allows(FailoverClass::Network) {
+                        return Err(e.into());
+                    }
                     last_error = Some(e.into());
                     if attempt + 1 < max_retries {
-                        sleep(Self::retry_delay(attempt)).await;
+                        sleep(Self::retry_delay(&policy, attempt)).await;
                     }
                     continue;
                 }
@@ -487,12 +1067,50 @@ impl KiroProvider {
 
             // 成功响应
             if status.is_success() {
+                let response = match permit {
+                    Some(permit) => concurrency::hold(permit, response),
+                    None => response,
+                };
+                let response = match chaos {
+                    Some(c) => self.chaos.wrap(c, response),
+                    None => response,
+                };
+                let response = match first_byte_timeout {
+                    Some(timeout) => match Self::await_first_chunk(response, timeout).await {
+                        Ok(resp) => resp,
+                        Err(e) => {
+                            tracing::warn!(
+                                "凭据 #{} 流式响应首字节超时，换凭据重试（尝试 {}/{}）: {}",
+                                ctx.id,
+                                attempt + 1,
+                                max_retries,
+                                e
+                            );
+                            self.token_manager.report_error(ctx.id, ErrorClass::Timeout);
+                            if !budget.allows(FailoverClass::Network) {
+                                return Err(e);
+                            }
+                            timed_out_ids.push(ctx.id);
+                            last_error = Some(e);
+                            continue;
+                        }
+                    },
+                    None => response,
+                };
+                let response = match record {
+                    Some(r) => recording::record(r, request_body, response),
+                    None => response,
+                };
                 self.token_manager.report_success(ctx.id);
+                let mut response = response;
+                response.extensions_mut().insert(ServedBy(ctx.id));
                 return Ok(response);
             }
 
             // 失败响应：读取 body 用于日志/错误信息
+            let headers = response.headers().clone();
             let body = response.text().await.unwrap_or_default();
+            let error = ProviderError::upstream(status, &body).with_retry_after(&headers);
 
             // 402 Payment Required 且额度用尽：禁用凭据并故障转移
             if status.as_u16() == 402 && Self::is_monthly_request_limit(&body) {
@@ -504,28 +1122,32 @@ impl KiroProvider {
                     body
                 );
 
+                self.token_manager.report_error(ctx.id, ErrorClass::Other);
                 let has_available = self.token_manager.report_quota_exhausted(ctx.id);
                 if !has_available {
-                    anyhow::bail!(
-                        "{} API 请求失败（所有凭据已用尽）: {} {}",
-                        api_type,
-                        status,
-                        body
-                    );
+                    return Err(self.overloaded(format!(
+                        "{} API 请求失败（所有凭据已用尽）: {}",
+                        api_type, error
+                    )));
+                }
+                if !budget.allows(FailoverClass::Quota) {
+                    return Err(error.into());
                 }
 
-                last_error = Some(anyhow::anyhow!(
-                    "{} API 请求失败: {} {}",
-                    api_type,
-                    status,
-                    body
-                ));
+                last_error = Some(error.into());
                 continue;
             }
 
-            // 400 Bad Request - 请求问题，重试/切换凭据无意义
+            // 400 Bad Request - 请求问题，重试/切换凭据通常无意义（`failoverOn` 含 `client` 时重试）
             if status.as_u16() == 400 {
-                anyhow::bail!("{} API 请求失败: {} {}", api_type, status, body);
+                if !budget.allows(FailoverClass::Client) {
+                    return Err(error.into());
+                }
+                last_error = Some(error.into());
+                if attempt + 1 < max_retries {
+                    sleep(Self::retry_delay(&policy, attempt)).await;
+                }
+                continue;
             }
 
             // 401/403 - 更可能是凭据/权限问题：计入失败并允许故障转移
@@ -538,53 +1160,66 @@ impl KiroProvider {
                     body
                 );
 
+                self.token_manager.report_error(ctx.id, ErrorClass::Auth);
                 let has_available = self.token_manager.report_failure(ctx.id);
                 if !has_available {
-                    anyhow::bail!(
-                        "{} API 请求失败（所有凭据已用尽）: {} {}",
-                        api_type,
-                        status,
-                        body
-                    );
+                    return Err(self.overloaded(format!(
+                        "{} API 请求失败（所有凭据已用尽）: {}",
+                        api_type, error
+                    )));
+                }
+                if !budget.allows(FailoverClass::Auth) {
+                    return Err(error.into());
                 }
 
-                last_error = Some(anyhow::anyhow!(
-                    "{} API 请求失败: {} {}",
-                    api_type,
-                    status,
-                    body
-                ));
+                last_error = Some(error.into());
                 continue;
             }
 
             // 429/408/5xx - 瞬态上游错误：重试但不禁用或切换凭据
             // （避免 429 high traffic / 502 high load 等瞬态错误把所有凭据锁死）
-            if matches!(status.as_u16(), 408 | 429) || status.is_server_error() {
+            if error.is_retryable() {
+                self.token_manager
+                    .report_error(ctx.id, ErrorClass::from_status(status.as_u16()));
+                let class = if error.is_throttled() {
+                    FailoverClass::Throttled
+                } else {
+                    FailoverClass::Server
+                };
+                if !budget.allows(class) {
+                    return Err(error.into());
+                }
                 tracing::warn!(
-                    "API 请求失败（上游瞬态错误，尝试 {}/{}）: {} {}",
+                    "{} API 请求失败（上游瞬态错误，尝试 {}/{}）: {}",
+                    api_type,
                     attempt + 1,
                     max_retries,
-                    status,
-                    body
+                    error
                 );
-                last_error = Some(anyhow::anyhow!(
-                    "{} API 请求失败: {} {}",
-                    api_type,
-                    status,
-                    body
-                ));
+                last_error = Some(error.into());
                 if attempt + 1 < max_retries {
-                    sleep(Self::retry_delay(attempt)).await;
+                    sleep(Self::retry_delay(&policy, attempt)).await;
                 }
                 continue;
             }
 
             // 其他 4xx - 通常为请求/配置问题：直接返回，不计入凭据失败
             if status.is_client_error() {
-                anyhow::bail!("{} API 请求失败: {} {}", api_type, status, body);
+                if !budget.allows(FailoverClass::Client) {
+                    return Err(error.into());
+                }
+                last_error = Some(error.into());
+                if attempt + 1 < max_retries {
+                    sleep(Self::retry_delay(&policy, attempt)).await;
+                }
+                continue;
             }
 
             // 兜底：当作可重试的瞬态错误处理（不切换凭据）
+            self.token_manager.report_error(ctx.id, ErrorClass::Other);
+            if !budget.allows(FailoverClass::Server) {
+                return Err(error.into());
+            }
             tracing::warn!(
                 "API 请求失败（未知错误，尝试 {}/{}）: {} {}",
                 attempt + 1,
@@ -592,33 +1227,125 @@ impl KiroProvider {
                 status,
                 body
             );
-            last_error = Some(anyhow::anyhow!(
-                "{} API 请求失败: {} {}",
-                api_type,
-                status,
-                body
-            ));
+            last_error = Some(error.into());
             if attempt + 1 < max_retries {
-                sleep(Self::retry_delay(attempt)).await;
+                sleep(Self::retry_delay(&policy, attempt)).await;
             }
         }
 
-        // 所有重试都失败
-        Err(last_error.unwrap_or_else(|| {
+        // 所有重试都失败：凭据全部禁用或持续失败，按过载处理（最后一次为上游限流时按限流返回）
+        let last_error = last_error.unwrap_or_else(|| {
             anyhow::anyhow!(
                 "{} API 请求失败：已达到最大重试次数（{}次）",
                 api_type,
                 max_retries
             )
+        });
+        let throttled = last_error
+            .downcast_ref::<ProviderError>()
+            .is_some_and(ProviderError::is_throttled);
+        Err(anyhow::Error::new(ProviderError::Overloaded {
+            message: last_error.to_string(),
+            retry_after_secs: self.token_manager.estimated_recovery_secs(),
+            throttled,
         }))
     }
 
-    fn retry_delay(attempt: usize) -> Duration {
-        // 指数退避 + 少量抖动，避免上游抖动时放大故障
-        const BASE_MS: u64 = 200;
-        const MAX_MS: u64 = 2_000;
-        let exp = BASE_MS.saturating_mul(2u64.saturating_pow(attempt.min(6) as u32));
-        let backoff = exp.min(MAX_MS);
+    /// 获取调用上下文并占用该凭据的并发名额
+    ///
+    /// 凭据并发已满时溢出到下一个可用凭据（不计入重试次数）；
+    /// 所有凭据均已满时在选中的凭据上排队等待。`limit` 为 0 时不限制
+    async fn acquire_with_permit(
+        &self,
+        model: Option<&str>,
+        excluded: &[u64],
+        limit: usize,
+    ) -> anyhow::Result<(CallContext, Option<OwnedSemaphorePermit>)> {
+        let mut skipped = excluded.to_vec();
+        loop {
+            let ctx = self
+                .token_manager
+                .acquire_context_excluding(model, &skipped)
+                .await?;
+            if limit == 0 {
+                return Ok((ctx, None));
+            }
+            if let Some(permit) = self.limiter.try_acquire(ctx.id, limit) {
+                return Ok((ctx, Some(permit)));
+            }
+            if skipped.contains(&ctx.id) {
+                tracing::debug!(
+                    "所有凭据并发均已满，凭据 #{} 排队等待（进行中 {}）",
+                    ctx.id,
+                    self.limiter.in_flight(ctx.id)
+                );
+                let permit = self.limiter.acquire(ctx.id, limit).await;
+                return Ok((ctx, Some(permit)));
+            }
+            tracing::debug!("凭据 #{} 并发已满（{}），溢出到下一个凭据", ctx.id, limit);
+            skipped.push(ctx.id);
+        }
+    }
+
+    /// 等待流式响应的首个数据块
+    ///
+    /// 在 `timeout` 内收到首个数据块时，将其与剩余数据重新拼装为 Response 返回；
+    /// 超时或读取失败时返回错误（此时尚未向客户端发送任何内容，可以安全重试）
+    async fn await_first_chunk(
+        response: reqwest::Response,
+        timeout: Duration,
+    ) -> anyhow::Result<reqwest::Response> {
+        use futures::StreamExt;
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let mut body_stream = response.bytes_stream();
+
+        let first = match tokio::time::timeout(timeout, body_stream.next()).await {
+            Ok(Some(Ok(chunk))) => Some(chunk),
+            Ok(Some(Err(e))) => anyhow::bail!("读取流式响应首个数据块失败: {}", e),
+            Ok(None) => None,
+            Err(_) => anyhow::bail!("首字节超时（{}s 内未收到上游事件）", timeout.as_secs_f64()),
+        };
+
+        let body = futures::stream::iter(first.map(Ok)).chain(body_stream);
+        let mut builder = http::Response::builder().status(status);
+        if let Some(h) = builder.headers_mut() {
+            *h = headers;
+        }
+        let rebuilt = builder.body(reqwest::Body::wrap_stream(body))?;
+        Ok(reqwest::Response::from(rebuilt))
+    }
+
+    /// 包装为过载错误，附带预估的恢复等待时间
+    fn overloaded(&self, message: impl std::fmt::Display) -> anyhow::Error {
+        anyhow::Error::new(ProviderError::Overloaded {
+            message: message.to_string(),
+            retry_after_secs: self.token_manager.estimated_recovery_secs(),
+            throttled: false,
+        })
+    }
+
+    /// 请求发送失败的错误归类
+    fn error_class_for_send(err: &reqwest::Error) -> ErrorClass {
+        if err.is_timeout() {
+            ErrorClass::Timeout
+        } else {
+            ErrorClass::Other
+        }
+    }
+
+    fn retry_delay(policy: &RequestPolicy, attempt: usize) -> Duration {
+        // 默认指数退避 + 少量抖动，避免上游抖动时放大故障
+        let base = policy.backoff_base_ms.unwrap_or(BACKOFF_BASE_MS);
+        let max = policy.backoff_max_ms.unwrap_or(BACKOFF_MAX_MS).max(base);
+        let backoff = match policy.backoff.unwrap_or(BackoffStrategy::Exponential) {
+            BackoffStrategy::None => return Duration::ZERO,
+            BackoffStrategy::Fixed => base,
+            BackoffStrategy::Exponential => base
+                .saturating_mul(2u64.saturating_pow(attempt.min(6) as u32))
+                .min(max),
+        };
         let jitter_max = (backoff / 4).max(1);
         let jitter = fastrand::u64(0..=jitter_max);
         Duration::from_millis(backoff.saturating_add(jitter))
@@ -659,6 +1386,37 @@ mod tests {
         KiroProvider::new(Arc::new(tm))
     }
 
+    fn response_from_stream<S>(body: S) -> reqwest::Response
+    where
+        S: futures::Stream<Item = Result<bytes::Bytes, std::io::Error>> + Send + Sync + 'static,
+    {
+        let response = http::Response::builder()
+            .status(200)
+            .body(reqwest::Body::wrap_stream(body))
+            .unwrap();
+        reqwest::Response::from(response)
+    }
+
+    #[tokio::test]
+    async fn test_await_first_chunk_preserves_body() {
+        let chunks = vec![Ok(bytes::Bytes::from("ab")), Ok(bytes::Bytes::from("cd"))];
+        let response = response_from_stream(futures::stream::iter(chunks));
+
+        let response = KiroProvider::await_first_chunk(response, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.bytes().await.unwrap(), "abcd");
+    }
+
+    #[tokio::test]
+    async fn test_await_first_chunk_times_out() {
+        let response = response_from_stream(futures::stream::pending());
+
+        let result = KiroProvider::await_first_chunk(response, Duration::from_millis(20)).await;
+        assert!(result.unwrap_err().to_string().contains("首字节超时"));
+    }
+
     #[test]
     fn test_base_url() {
         let config = Config::default();
@@ -709,6 +1467,66 @@ mod tests {
         assert_eq!(headers.get(CONNECTION).unwrap(), "close");
     }
 
+    #[test]
+    fn test_provider_error_parses_upstream_body() {
+        let err = ProviderError::upstream(
+            reqwest::StatusCode::BAD_REQUEST,
+            r#"{"message":"Input is too long.","reason":"CONTENT_LENGTH_EXCEEDS_THRESHOLD"}"#,
+        );
+        assert_eq!(err.code(), Some(CONTENT_LENGTH_EXCEEDS_THRESHOLD));
+        assert!(err.is_context_window_full());
+        assert!(err.is_input_too_long());
+        assert!(!err.is_retryable());
+        assert_eq!(
+            err.to_string(),
+            "400 [CONTENT_LENGTH_EXCEEDS_THRESHOLD] Input is too long."
+        );
+
+        let err = ProviderError::upstream(reqwest::StatusCode::BAD_REQUEST, "Input is too long");
+        assert_eq!(err.code(), None);
+        assert!(!err.is_context_window_full());
+        assert!(err.is_input_too_long());
+    }
+
+    #[test]
+    fn test_provider_error_detects_throttling() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("12"));
+        let err = ProviderError::upstream(reqwest::StatusCode::TOO_MANY_REQUESTS, "slow down")
+            .with_retry_after(&headers);
+        assert!(err.is_throttled());
+        assert!(matches!(
+            err,
+            ProviderError::Upstream {
+                retry_after_secs: Some(12),
+                ..
+            }
+        ));
+
+        // 400 + ThrottlingException 同样视为限流
+        let err = ProviderError::upstream(
+            reqwest::StatusCode::BAD_REQUEST,
+            r#"{"__type":"com.amazon.coral.availability#ThrottlingException","message":"Rate exceeded"}"#,
+        );
+        assert!(err.is_throttled());
+        assert_eq!(err.code(), Some(THROTTLING_EXCEPTION));
+
+        let err = ProviderError::upstream(reqwest::StatusCode::SERVICE_UNAVAILABLE, "busy")
+            .with_retry_after(&HeaderMap::new());
+        assert!(!err.is_throttled());
+    }
+
+    #[test]
+    fn test_provider_error_retryable_status() {
+        for (status, retryable) in [(429, true), (408, true), (503, true), (403, false)] {
+            let status = reqwest::StatusCode::from_u16(status).unwrap();
+            assert_eq!(
+                ProviderError::upstream(status, "oops").is_retryable(),
+                retryable
+            );
+        }
+    }
+
     #[test]
     fn test_is_monthly_request_limit_detects_reason() {
         let body = r#"{"message":"You have reached the limit.","reason":"MONTHLY_REQUEST_COUNT"}"#;
@@ -726,4 +1544,83 @@ mod tests {
         let body = r#"{"message":"nope","reason":"DAILY_REQUEST_COUNT"}"#;
         assert!(!KiroProvider::is_monthly_request_limit(body));
     }
+
+    #[test]
+    fn test_request_policy_credential_overrides_config() {
+        let mut config = Config::default();
+        config.request_policy = Some(RequestPolicy {
+            connect_timeout_secs: Some(5),
+            timeout_secs: Some(60),
+            max_retries_per_credential: Some(1),
+            max_total_retries: Some(4),
+            ..Default::default()
+        });
+        let credentials = KiroCredentials {
+            request_policy: Some(RequestPolicy {
+                timeout_secs: Some(300),
+                max_retries_per_credential: Some(6),
+                backoff: Some(BackoffStrategy::None),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let provider = create_test_provider(config, credentials.clone());
+
+        let policy = provider.policy_for(&credentials);
+        assert_eq!(
+            KiroProvider::timeouts(&policy),
+            ClientTimeouts {
+                connect_secs: Some(5),
+                read_secs: None,
+                total_secs: 300,
+            }
+        );
+        assert_eq!(KiroProvider::retry_delay(&policy, 3), Duration::ZERO);
+        // 凭据级尝试次数 6 受全局上限 4 约束
+        assert_eq!(provider.max_attempts(), 4);
+    }
+
+    #[test]
+    fn test_failover_budget_limits() {
+        let defaults = FailoverBudget::new(&RequestPolicy::default());
+        assert!(defaults.allows(FailoverClass::Auth));
+        assert!(!defaults.allows(FailoverClass::Client));
+        assert!(!defaults.expired());
+
+        let mut budget = FailoverBudget::new(&RequestPolicy {
+            max_credentials_per_request: Some(2),
+            retry_budget_secs: Some(30),
+            failover_on: Some(vec![FailoverClass::Network, FailoverClass::Throttled]),
+            ..Default::default()
+        });
+        assert!(budget.admit(1));
+        assert!(budget.admit(2));
+        // 已使用过的凭据可继续重试，第三个凭据超出上限
+        assert!(budget.admit(1));
+        assert!(!budget.admit(3));
+        assert!(!budget.allows(FailoverClass::Auth));
+        assert!(budget.allows(FailoverClass::Throttled));
+
+        budget.started -= Duration::from_secs(31);
+        assert!(budget.expired());
+    }
+
+    #[test]
+    fn test_retry_delay_strategies() {
+        let fixed = RequestPolicy {
+            backoff: Some(BackoffStrategy::Fixed),
+            backoff_base_ms: Some(1000),
+            ..Default::default()
+        };
+        for attempt in 0..4 {
+            let delay = KiroProvider::retry_delay(&fixed, attempt).as_millis();
+            assert!((1000..=1250).contains(&delay));
+        }
+
+        let exponential = RequestPolicy::default();
+        let first = KiroProvider::retry_delay(&exponential, 0).as_millis();
+        let capped = KiroProvider::retry_delay(&exponential, 10).as_millis();
+        assert!((200..=250).contains(&first));
+        assert!((2000..=2500).contains(&capped));
+    }
 }
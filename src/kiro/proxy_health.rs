@@ -0,0 +1,184 @@
+//! 凭据级代理健康检测
+//!
+//! 记录配置了自身代理的凭据经代理发送请求时的连接失败，连续失败达到 `proxyFallback.failureThreshold`
+//! 后在 `recoverySecs` 内改走全局代理或直连；到期后重新尝试凭据代理，再失败一次即继续回退。
+//! 状态仅保存在内存中，在凭据列表中展示。
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::model::config::{ProxyFallbackConfig, ProxyFallbackTarget};
+
+/// 代理健康状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyState {
+    /// 最近一次请求成功（或尚无失败）
+    Healthy,
+    /// 出现连续失败，但未达到回退阈值
+    Degraded,
+    /// 已回退到全局代理或直连
+    Fallback,
+}
+
+/// 代理健康状态快照（用于凭据列表）
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyHealthSnapshot {
+    pub state: ProxyState,
+    /// 连续连接失败次数
+    pub consecutive_failures: u32,
+    /// 回退截止时间（RFC3339，仅回退期间）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_until: Option<String>,
+    /// 最近一次失败原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    consecutive_failures: u32,
+    fallback_until: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+/// 凭据代理健康状态
+pub struct ProxyHealth {
+    entries: Mutex<HashMap<u64, Entry>>,
+}
+
+impl ProxyHealth {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 凭据当前是否应绕过自身代理；回退到期时恢复使用凭据代理（保留一次失败即回退）
+    pub fn is_fallen_back(
+        &self,
+        id: u64,
+        config: &ProxyFallbackConfig,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let mut entries = self.entries.lock();
+        let Some(entry) = entries.get_mut(&id) else {
+            return false;
+        };
+        match entry.fallback_until {
+            Some(until) if now < until => true,
+            Some(_) => {
+                tracing::info!("凭据 #{} 代理回退到期，重新尝试凭据代理", id);
+                entry.fallback_until = None;
+                entry.consecutive_failures = config.failure_threshold.saturating_sub(1);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// 记录一次经凭据代理的连接失败，达到阈值时开始回退
+    pub fn record_failure(
+        &self,
+        id: u64,
+        config: &ProxyFallbackConfig,
+        error: &str,
+        now: DateTime<Utc>,
+    ) {
+        let mut entries = self.entries.lock();
+        let entry = entries.entry(id).or_default();
+        entry.consecutive_failures += 1;
+        entry.last_error = Some(error.to_string());
+        if entry.fallback_until.is_none() && entry.consecutive_failures >= config.failure_threshold
+        {
+            let until = now + Duration::seconds(config.recovery_secs as i64);
+            tracing::warn!(
+                "凭据 #{} 代理连续 {} 次连接失败，{} 秒内改用{}",
+                id,
+                entry.consecutive_failures,
+                config.recovery_secs,
+                match config.target {
+                    ProxyFallbackTarget::Global => "全局代理",
+                    ProxyFallbackTarget::Direct => "直连",
+                }
+            );
+            entry.fallback_until = Some(until);
+        }
+    }
+
+    /// 记录一次经凭据代理的成功请求
+    pub fn record_success(&self, id: u64) {
+        let mut entries = self.entries.lock();
+        if let Some(entry) = entries.get_mut(&id) {
+            entry.consecutive_failures = 0;
+            entry.last_error = None;
+        }
+    }
+
+    /// 清除凭据的健康状态（凭据删除或代理配置变更时）
+    pub fn reset(&self, id: u64) {
+        self.entries.lock().remove(&id);
+    }
+
+    /// 凭据代理健康状态快照
+    pub fn snapshot(&self, id: u64, now: DateTime<Utc>) -> ProxyHealthSnapshot {
+        let entries = self.entries.lock();
+        let entry = entries.get(&id);
+        let fallback_until = entry
+            .and_then(|e| e.fallback_until)
+            .filter(|until| now < *until);
+        let consecutive_failures = entry.map_or(0, |e| e.consecutive_failures);
+        let state = if fallback_until.is_some() {
+            ProxyState::Fallback
+        } else if consecutive_failures > 0 {
+            ProxyState::Degraded
+        } else {
+            ProxyState::Healthy
+        };
+        ProxyHealthSnapshot {
+            state,
+            consecutive_failures,
+            fallback_until: fallback_until.map(|t| t.to_rfc3339()),
+            last_error: entry.and_then(|e| e.last_error.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fallback_after_threshold_and_recovery() {
+        let config = ProxyFallbackConfig {
+            enabled: true,
+            failure_threshold: 2,
+            recovery_secs: 60,
+            target: ProxyFallbackTarget::Direct,
+        };
+        let health = ProxyHealth::new();
+        let now = Utc::now();
+
+        health.record_failure(1, &config, "connect refused", now);
+        assert!(!health.is_fallen_back(1, &config, now));
+        assert_eq!(health.snapshot(1, now).state, ProxyState::Degraded);
+
+        health.record_failure(1, &config, "connect refused", now);
+        assert!(health.is_fallen_back(1, &config, now));
+        assert_eq!(health.snapshot(1, now).state, ProxyState::Fallback);
+
+        // 回退到期后重新尝试凭据代理，再失败一次即继续回退
+        let later = now + Duration::seconds(61);
+        assert!(!health.is_fallen_back(1, &config, later));
+        health.record_failure(1, &config, "connect refused", later);
+        assert!(health.is_fallen_back(1, &config, later));
+
+        health.record_success(2);
+        assert_eq!(health.snapshot(2, now).state, ProxyState::Healthy);
+    }
+}
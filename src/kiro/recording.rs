@@ -0,0 +1,191 @@
+//! 上游响应录制与回放
+//!
+//! 按 [`RecordingConfig`] 在 Provider 层录制或回放 generateAssistantResponse 的原始响应：
+//! `record` 模式在成功响应完整读完后写入 `<dir>/<指纹>.bin`（原始 event-stream 字节）与
+//! `<指纹>.json`（请求体，便于排查）；`replay` 模式不调用上游、不占用凭据，直接以录制的字节作为响应。
+//! 指纹为去掉每次请求随机生成的 `conversationId`、`agentContinuationId` 与凭据相关的 `profileArn`
+//! 后请求体的 SHA-256，相同对话内容的请求对应同一份录制。
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use futures::{StreamExt, stream};
+use sha2::{Digest, Sha256};
+
+use crate::kiro::provider::ProviderError;
+use crate::model::config::RecordingConfig;
+
+/// 计算请求指纹
+pub fn fingerprint(request_body: &str) -> String {
+    let normalized = match serde_json::from_str::<serde_json::Value>(request_body) {
+        Ok(mut value) => {
+            if let Some(obj) = value.as_object_mut() {
+                obj.remove("profileArn");
+            }
+            if let Some(state) = value
+                .get_mut("conversationState")
+                .and_then(|s| s.as_object_mut())
+            {
+                state.remove("conversationId");
+                state.remove("agentContinuationId");
+            }
+            value.to_string()
+        }
+        Err(_) => request_body.to_string(),
+    };
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+/// 录制中的响应
+struct Capture {
+    dir: PathBuf,
+    key: String,
+    request_body: String,
+    bytes: Vec<u8>,
+}
+
+impl Capture {
+    fn save(&self) {
+        let result = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(self.dir.join(format!("{}.bin", self.key)), &self.bytes))
+            .and_then(|_| {
+                fs::write(
+                    self.dir.join(format!("{}.json", self.key)),
+                    &self.request_body,
+                )
+            });
+        match result {
+            Ok(()) => tracing::debug!("已录制上游响应 {}（{} 字节）", self.key, self.bytes.len()),
+            Err(e) => tracing::warn!("写入录制文件失败 {}: {}", self.key, e),
+        }
+    }
+}
+
+/// 包装成功响应：转发数据块的同时收集原始字节，响应完整读完后写入录制目录（中途出错不保存）
+pub fn record(
+    config: &RecordingConfig,
+    request_body: &str,
+    response: reqwest::Response,
+) -> reqwest::Response {
+    let capture = Capture {
+        dir: PathBuf::from(&config.dir),
+        key: fingerprint(request_body),
+        request_body: request_body.to_string(),
+        bytes: Vec::new(),
+    };
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = stream::unfold(
+        (response.bytes_stream(), Some(capture)),
+        |(mut inner, mut capture)| async move {
+            match inner.next().await {
+                Some(Ok(chunk)) => {
+                    if let Some(capture) = capture.as_mut() {
+                        capture.bytes.extend_from_slice(&chunk);
+                    }
+                    Some((Ok(chunk), (inner, capture)))
+                }
+                Some(Err(e)) => Some((Err(io::Error::other(e)), (inner, None))),
+                None => {
+                    if let Some(capture) = capture {
+                        capture.save();
+                    }
+                    None
+                }
+            }
+        },
+    );
+
+    let mut builder = http::Response::builder().status(status);
+    if let Some(h) = builder.headers_mut() {
+        *h = headers;
+    }
+    let rebuilt = builder
+        .body(reqwest::Body::wrap_stream(body))
+        .expect("重建响应失败");
+    reqwest::Response::from(rebuilt)
+}
+
+/// 回放录制的响应，没有对应录制时返回 404 上游错误
+pub fn replay(config: &RecordingConfig, request_body: &str) -> anyhow::Result<reqwest::Response> {
+    let key = fingerprint(request_body);
+    let path = Path::new(&config.dir).join(format!("{}.bin", key));
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("没有可回放的录制 {}: {}", path.display(), e);
+            let body = serde_json::json!({
+                "message": format!("No recording for request {}", key),
+                "reason": "RECORDING_NOT_FOUND",
+            });
+            return Err(
+                ProviderError::upstream(reqwest::StatusCode::NOT_FOUND, &body.to_string()).into(),
+            );
+        }
+    };
+    tracing::debug!("回放上游响应 {}（{} 字节）", key, bytes.len());
+    let response = http::Response::builder()
+        .status(200)
+        .header(
+            http::header::CONTENT_TYPE,
+            "application/vnd.amazon.eventstream",
+        )
+        .body(reqwest::Body::from(bytes))?;
+    Ok(reqwest::Response::from(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::config::RecordingMode;
+
+    fn request(conversation_id: &str, text: &str) -> String {
+        serde_json::json!({
+            "conversationState": {
+                "conversationId": conversation_id,
+                "agentContinuationId": conversation_id,
+                "currentMessage": {"userInputMessage": {"content": text}},
+            },
+            "profileArn": "arn:aws:codewhisperer:us-east-1:123:profile/x",
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        assert_eq!(
+            fingerprint(&request("a", "hello")),
+            fingerprint(&request("b", "hello"))
+        );
+        assert_ne!(
+            fingerprint(&request("a", "hello")),
+            fingerprint(&request("a", "bye"))
+        );
+
+        let dir = std::env::temp_dir().join(format!("kiro-recording-{}", uuid::Uuid::new_v4()));
+        let mut config = RecordingConfig {
+            mode: RecordingMode::Record,
+            dir: dir.to_string_lossy().into_owned(),
+        };
+        let chunks: Vec<Result<bytes::Bytes, io::Error>> = vec![Ok("ab".into()), Ok("cd".into())];
+        let upstream = http::Response::builder()
+            .status(200)
+            .body(reqwest::Body::wrap_stream(stream::iter(chunks)))
+            .unwrap();
+        let recorded = record(&config, &request("a", "hello"), upstream.into());
+        assert_eq!(recorded.bytes().await.unwrap(), "abcd");
+
+        config.mode = RecordingMode::Replay;
+        let replayed = replay(&config, &request("c", "hello")).unwrap();
+        assert_eq!(replayed.bytes().await.unwrap(), "abcd");
+        let missing = replay(&config, &request("c", "bye")).unwrap_err();
+        assert_eq!(
+            missing
+                .downcast_ref::<ProviderError>()
+                .and_then(|e| e.code()),
+            Some("RECORDING_NOT_FOUND")
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
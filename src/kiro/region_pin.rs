@@ -0,0 +1,47 @@
+//! API Key 地域限制
+//!
+//! 设置了 `allowedRegions` 的 API Key 只能使用有效 API Region（`apiRegion`，未设置时为全局配置）
+//! 在列表中的凭据，用于有数据驻留要求的用户。限制随请求所在的任务传递到凭据选择，
+//! 没有符合条件的凭据时请求直接失败，不会回退到其他地域。
+
+use std::future::Future;
+use std::sync::Arc;
+
+tokio::task_local! {
+    static ALLOWED_REGIONS: Arc<Vec<String>>;
+}
+
+/// 在地域限制下执行 `fut`，`regions` 为 None 或空列表时不限制
+pub async fn scope<F: Future>(regions: Option<Vec<String>>, fut: F) -> F::Output {
+    match regions.filter(|r| !r.is_empty()) {
+        Some(regions) => ALLOWED_REGIONS.scope(Arc::new(regions), fut).await,
+        None => fut.await,
+    }
+}
+
+/// 当前请求允许的地域，不限制时返回 None
+pub fn current() -> Option<Arc<Vec<String>>> {
+    ALLOWED_REGIONS.try_with(Arc::clone).ok()
+}
+
+/// 地域是否在允许列表中（不区分大小写）
+pub fn allows(regions: &[String], region: &str) -> bool {
+    regions.iter().any(|r| r.eq_ignore_ascii_case(region))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scope_sets_and_clears_regions() {
+        assert!(current().is_none());
+        let inside = scope(Some(vec!["eu-central-1".to_string()]), async { current() }).await;
+        assert_eq!(inside.as_deref(), Some(&vec!["eu-central-1".to_string()]));
+        assert!(scope(Some(Vec::new()), async { current() }).await.is_none());
+        assert!(current().is_none());
+
+        assert!(allows(&["EU-Central-1".to_string()], "eu-central-1"));
+        assert!(!allows(&["eu-central-1".to_string()], "us-east-1"));
+    }
+}
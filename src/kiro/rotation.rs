@@ -0,0 +1,96 @@
+//! 凭据轮换策略
+//!
+//! 按 `rotation.periodHours` 划分时间段（以 Unix 纪元为起点，即按 UTC 对齐），
+//! 每个时间段只启用 `groups` 中的一组凭据并依次轮换，把订阅用量均摊到整个凭据池；
+//! 不在任何组中的凭据始终可用。当前组没有可用凭据时回退到全部凭据。
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+
+use crate::model::config::RotationConfig;
+
+fn period_secs(policy: &RotationConfig) -> i64 {
+    policy.period_hours.max(1) as i64 * 3600
+}
+
+/// 当前生效的组序号，策略未启用或没有分组时返回 None
+pub fn active_group(policy: &RotationConfig, now: DateTime<Utc>) -> Option<usize> {
+    if !policy.enabled || policy.groups.is_empty() {
+        return None;
+    }
+    let period = now.timestamp().div_euclid(period_secs(policy));
+    Some(period.rem_euclid(policy.groups.len() as i64) as usize)
+}
+
+/// 当前时间段被轮换停用的凭据（同时属于当前组的凭据不停用）
+pub fn inactive_credentials(policy: &RotationConfig, now: DateTime<Utc>) -> HashSet<u64> {
+    let Some(active) = active_group(policy, now) else {
+        return HashSet::new();
+    };
+    let active_ids = &policy.groups[active];
+    policy
+        .groups
+        .iter()
+        .flatten()
+        .copied()
+        .filter(|id| !active_ids.contains(id))
+        .collect()
+}
+
+/// 下一次轮换的时间，策略未生效时返回 None
+pub fn next_rotation(policy: &RotationConfig, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    active_group(policy, now)?;
+    let period = period_secs(policy);
+    DateTime::from_timestamp((now.timestamp().div_euclid(period) + 1) * period, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn policy(period_hours: u64, groups: Vec<Vec<u64>>) -> RotationConfig {
+        RotationConfig {
+            enabled: true,
+            period_hours,
+            groups,
+        }
+    }
+
+    #[test]
+    fn test_daily_rotation_alternates_groups() {
+        let policy = policy(24, vec![vec![1, 2], vec![3, 4]]);
+        let day = Utc.with_ymd_and_hms(2026, 3, 2, 12, 0, 0).unwrap();
+        let next_day = day + chrono::Duration::days(1);
+
+        let today = active_group(&policy, day).unwrap();
+        assert_ne!(active_group(&policy, next_day), Some(today));
+        assert_eq!(
+            active_group(&policy, next_day + chrono::Duration::days(1)),
+            Some(today)
+        );
+
+        let inactive = inactive_credentials(&policy, day);
+        assert_eq!(inactive.len(), 2);
+        assert!(policy.groups[today].iter().all(|id| !inactive.contains(id)));
+        assert_eq!(
+            next_rotation(&policy, day),
+            Some(Utc.with_ymd_and_hms(2026, 3, 3, 0, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_shared_and_disabled_policies() {
+        let now = Utc.with_ymd_and_hms(2026, 3, 2, 0, 0, 0).unwrap();
+        // 同时属于两组的凭据始终可用
+        let shared = policy(1, vec![vec![1, 5], vec![2, 5]]);
+        assert!(!inactive_credentials(&shared, now).contains(&5));
+
+        let mut disabled = policy(24, vec![vec![1], vec![2]]);
+        disabled.enabled = false;
+        assert!(inactive_credentials(&disabled, now).is_empty());
+        assert_eq!(next_rotation(&disabled, now), None);
+        assert!(inactive_credentials(&policy(24, Vec::new()), now).is_empty());
+    }
+}
@@ -4,25 +4,34 @@
 //! 支持单凭据 (TokenManager) 和多凭据 (MultiTokenManager) 管理
 
 use anyhow::bail;
-use chrono::{DateTime, Duration, Utc};
-use parking_lot::Mutex;
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use tokio::sync::Mutex as TokioMutex;
+use utoipa::ToSchema;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering};
 use std::time::{Duration as StdDuration, Instant};
 
 use crate::http_client::{ProxyConfig, build_client};
+use crate::kiro::credential_usage::{CredentialUsage, CredentialUsageReport};
 use crate::kiro::machine_id;
 use crate::kiro::model::credentials::KiroCredentials;
 use crate::kiro::model::token_refresh::{
     IdcRefreshRequest, IdcRefreshResponse, RefreshRequest, RefreshResponse,
 };
 use crate::kiro::model::usage_limits::UsageLimitsResponse;
-use crate::model::config::Config;
+use crate::kiro::proxy_health::{ProxyHealth, ProxyHealthSnapshot};
+use crate::kiro::{region_pin, rotation};
+use crate::lease::{Coordinator, USAGE_MONITOR_JOB};
+use crate::model::config::{
+    CircuitBreakerConfig, Config, ModelPrice, RotationConfig, TokenRefreshConfig,
+};
+use crate::pricing;
 
 /// Token 管理器
 ///
@@ -104,7 +113,7 @@ pub(crate) fn is_token_expiring_soon(credentials: &KiroCredentials) -> bool {
     is_token_expiring_within(credentials, 10).unwrap_or(false)
 }
 
-fn sha256_hex(input: &str) -> String {
+pub(crate) fn sha256_hex(input: &str) -> String {
     let mut hasher = Sha256::new();
     hasher.update(input.as_bytes());
     let result = hasher.finalize();
@@ -312,6 +321,39 @@ async fn refresh_idc_token(
     Ok(new_credentials)
 }
 
+/// 用 getUsageLimits 返回的订阅等级与账号邮箱更新凭据，返回是否有变化
+///
+/// 上游返回的邮箱以账号实际信息为准，覆盖手动填写的邮箱
+fn apply_account_info(
+    id: u64,
+    credentials: &mut KiroCredentials,
+    usage_limits: &UsageLimitsResponse,
+) -> bool {
+    let mut changed = false;
+    if let Some(subscription_title) = usage_limits.subscription_title() {
+        let old_title = credentials.subscription_title.clone();
+        if old_title.as_deref() != Some(subscription_title) {
+            credentials.subscription_title = Some(subscription_title.to_string());
+            tracing::info!(
+                "凭据 #{} 订阅等级已更新: {:?} -> {}",
+                id,
+                old_title,
+                subscription_title
+            );
+            changed = true;
+        }
+    }
+    if let Some(email) = usage_limits.email() {
+        let old_email = credentials.email.clone();
+        if old_email.as_deref() != Some(email) {
+            credentials.email = Some(email.to_string());
+            tracing::info!("凭据 #{} 账号邮箱已更新: {:?} -> {}", id, old_email, email);
+            changed = true;
+        }
+    }
+    changed
+}
+
 /// getUsageLimits API 所需的 x-amz-user-agent header 前缀
 const USAGE_LIMITS_AMZ_USER_AGENT_PREFIX: &str = "aws-sdk-js/1.0.0";
 
@@ -333,7 +375,7 @@ pub(crate) async fn get_usage_limits(
 
     // 构建 URL
     let mut url = format!(
-        "https://{}/getUsageLimits?origin=AI_EDITOR&resourceType=AGENTIC_REQUEST",
+        "https://{}/getUsageLimits?origin=AI_EDITOR&resourceType=AGENTIC_REQUEST&isEmailRequired=true",
         host
     );
 
@@ -404,6 +446,66 @@ struct CredentialEntry {
     success_count: u64,
     /// 最后一次 API 调用时间（RFC3339 格式）
     last_used_at: Option<String>,
+    /// 按错误类别累计的失败次数
+    error_counts: ErrorCounts,
+    /// 请求数与 token 用量
+    usage: CredentialUsage,
+    /// 连续熔断次数（决定下次冷却时长，调用成功后清零）
+    circuit_trips: u32,
+    /// 熔断冷却结束时间（仅启用 circuitBreaker 时设置）
+    cooldown_until: Option<DateTime<Utc>>,
+}
+
+/// API 调用错误类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// 401/403 凭据或权限问题
+    Auth,
+    /// 429 限流
+    Throttle,
+    /// 5xx 上游错误
+    Server,
+    /// 408、网络超时、首字节超时
+    Timeout,
+    /// 其他错误（额度用尽、网络错误等）
+    Other,
+}
+
+impl ErrorClass {
+    /// 根据 HTTP 状态码归类
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            401 | 403 => ErrorClass::Auth,
+            429 => ErrorClass::Throttle,
+            408 => ErrorClass::Timeout,
+            500..=599 => ErrorClass::Server,
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
+/// 按错误类别累计的失败次数（持久化，重启后保留）
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorCounts {
+    pub auth: u64,
+    pub throttle: u64,
+    pub server: u64,
+    pub timeout: u64,
+    pub other: u64,
+}
+
+impl ErrorCounts {
+    fn record(&mut self, class: ErrorClass) {
+        let counter = match class {
+            ErrorClass::Auth => &mut self.auth,
+            ErrorClass::Throttle => &mut self.throttle,
+            ErrorClass::Server => &mut self.server,
+            ErrorClass::Timeout => &mut self.timeout,
+            ErrorClass::Other => &mut self.other,
+        };
+        *counter += 1;
+    }
 }
 
 /// 禁用原因
@@ -415,6 +517,29 @@ enum DisabledReason {
     TooManyFailures,
     /// 额度已用尽（如 MONTHLY_REQUEST_COUNT）
     QuotaExceeded,
+    /// 额度使用率达到 `usageDisableThreshold`，记录下次重置时间（Unix 秒）
+    UsageThreshold { reset_at: Option<i64> },
+}
+
+/// 单个凭据池（stable / canary）的调用计数
+#[derive(Default)]
+struct PoolCounters {
+    /// 路由到该池的请求数
+    requests: AtomicU64,
+    /// API 调用成功次数
+    successes: AtomicU64,
+    /// API 调用失败次数
+    failures: AtomicU64,
+}
+
+impl PoolCounters {
+    fn snapshot(&self) -> PoolStatsSnapshot {
+        PoolStatsSnapshot {
+            requests: self.requests.load(Ordering::Relaxed),
+            successes: self.successes.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+        }
+    }
 }
 
 /// 统计数据持久化条目
@@ -422,6 +547,10 @@ enum DisabledReason {
 struct StatsEntry {
     success_count: u64,
     last_used_at: Option<String>,
+    #[serde(default)]
+    error_counts: ErrorCounts,
+    #[serde(default)]
+    usage: CredentialUsage,
 }
 
 // ============================================================================
@@ -450,6 +579,12 @@ pub struct CredentialEntrySnapshot {
     pub refresh_token_hash: Option<String>,
     /// 用户邮箱（用于前端显示）
     pub email: Option<String>,
+    /// 订阅等级（获取使用额度时自动更新）
+    pub subscription_title: Option<String>,
+    /// 连续熔断次数
+    pub circuit_trips: u32,
+    /// 熔断冷却结束时间（RFC3339）
+    pub cooldown_until: Option<String>,
     /// API 调用成功次数
     pub success_count: u64,
     /// 最后一次 API 调用时间（RFC3339 格式）
@@ -459,6 +594,38 @@ pub struct CredentialEntrySnapshot {
     /// 代理 URL（用于前端展示）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy_url: Option<String>,
+    /// 是否属于 canary 凭据池
+    pub canary: bool,
+    /// 按错误类别累计的失败次数
+    pub error_counts: ErrorCounts,
+    /// 凭据代理健康状态（仅配置了凭据级代理时）
+    pub proxy_health: Option<ProxyHealthSnapshot>,
+}
+
+/// 凭据池调用统计快照
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolStatsSnapshot {
+    /// 路由到该池的请求数
+    pub requests: u64,
+    /// API 调用成功次数
+    pub successes: u64,
+    /// API 调用失败次数
+    pub failures: u64,
+}
+
+/// 灰度路由状态快照
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CanarySnapshot {
+    /// 灰度流量百分比（0-100）
+    pub percentage: u8,
+    /// canary 凭据数量
+    pub canary_credentials: usize,
+    /// 常规凭据池统计
+    pub stable: PoolStatsSnapshot,
+    /// canary 凭据池统计
+    pub canary: PoolStatsSnapshot,
 }
 
 /// 凭据管理器状态快照
@@ -480,8 +647,10 @@ pub struct ManagerSnapshot {
 /// 支持多个凭据的管理，实现固定优先级 + 故障转移策略
 /// 故障统计基于 API 调用结果，而非 Token 刷新结果
 pub struct MultiTokenManager {
-    config: Config,
-    proxy: Option<ProxyConfig>,
+    /// 当前生效的配置（支持热重载，替换时不影响已持有旧配置的请求）
+    config: RwLock<Arc<Config>>,
+    /// 全局代理配置（支持热重载）
+    proxy: RwLock<Option<ProxyConfig>>,
     /// 凭据条目列表
     entries: Mutex<Vec<CredentialEntry>>,
     /// 当前活动凭据 ID
@@ -498,12 +667,48 @@ pub struct MultiTokenManager {
     last_stats_save_at: Mutex<Option<Instant>>,
     /// 统计数据是否有未落盘更新
     stats_dirty: AtomicBool,
+    /// 灰度流量百分比（运行时可修改）
+    canary_percentage: AtomicU8,
+    /// 常规凭据池统计
+    stable_counters: PoolCounters,
+    /// canary 凭据池统计
+    canary_counters: PoolCounters,
+    /// 凭据级代理健康状态
+    proxy_health: ProxyHealth,
 }
 
 /// 每个凭据最大 API 调用失败次数
 const MAX_FAILURES_PER_CREDENTIAL: u32 = 3;
 /// 统计数据持久化防抖间隔
 const STATS_SAVE_DEBOUNCE: StdDuration = StdDuration::from_secs(30);
+/// 瞬态失败（或自动禁用后可自愈）时建议的重试等待秒数
+const TRANSIENT_RETRY_AFTER_SECS: u64 = 5;
+/// 无法预估恢复时间（如全部手动禁用）时建议的重试等待秒数
+const DEFAULT_RETRY_AFTER_SECS: u64 = 60;
+/// 额度巡检任务的唤醒间隔（实际巡检间隔由 `usageCheckIntervalMins` 决定）
+const USAGE_MONITOR_TICK: StdDuration = StdDuration::from_secs(60);
+/// 主动刷新 Token 的检查间隔
+const TOKEN_REFRESH_TICK: StdDuration = StdDuration::from_secs(30);
+/// 主动刷新失败后的重试间隔（秒）
+const TOKEN_REFRESH_RETRY_SECS: i64 = 60;
+
+/// 触发自动禁用（熔断）的连续失败次数
+fn failure_threshold(breaker: Option<&CircuitBreakerConfig>) -> u32 {
+    breaker.map_or(MAX_FAILURES_PER_CREDENTIAL, |b| b.failure_threshold.max(1))
+}
+
+/// 距下个自然月（UTC）开始的秒数，至少为 1
+fn seconds_until_next_month(now: DateTime<Utc>) -> u64 {
+    let (year, month) = if now.month() == 12 {
+        (now.year() + 1, 1)
+    } else {
+        (now.year(), now.month() + 1)
+    };
+    Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0)
+        .single()
+        .map(|next| (next - now).num_seconds().max(1) as u64)
+        .unwrap_or(DEFAULT_RETRY_AFTER_SECS)
+}
 
 /// API 调用上下文
 ///
@@ -573,6 +778,10 @@ impl MultiTokenManager {
                     },
                     success_count: 0,
                     last_used_at: None,
+                    error_counts: ErrorCounts::default(),
+                    usage: CredentialUsage::default(),
+                    circuit_trips: 0,
+                    cooldown_until: None,
                 }
             })
             .collect();
@@ -597,9 +806,10 @@ impl MultiTokenManager {
             .unwrap_or(0);
 
         let load_balancing_mode = config.load_balancing_mode.clone();
+        let canary_percentage = config.canary_percentage.min(100);
         let manager = Self {
-            config,
-            proxy,
+            config: RwLock::new(Arc::new(config)),
+            proxy: RwLock::new(proxy),
             entries: Mutex::new(entries),
             current_id: Mutex::new(initial_id),
             refresh_lock: TokioMutex::new(()),
@@ -608,6 +818,10 @@ impl MultiTokenManager {
             load_balancing_mode: Mutex::new(load_balancing_mode),
             last_stats_save_at: Mutex::new(None),
             stats_dirty: AtomicBool::new(false),
+            canary_percentage: AtomicU8::new(canary_percentage),
+            stable_counters: PoolCounters::default(),
+            canary_counters: PoolCounters::default(),
+            proxy_health: ProxyHealth::new(),
         };
 
         // 如果有新分配的 ID 或新生成的 machineId，立即持久化到配置文件
@@ -625,9 +839,14 @@ impl MultiTokenManager {
         Ok(manager)
     }
 
-    /// 获取配置的引用
-    pub fn config(&self) -> &Config {
-        &self.config
+    /// 获取当前生效的配置
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().clone()
+    }
+
+    /// 获取当前生效的全局代理配置
+    pub fn proxy(&self) -> Option<ProxyConfig> {
+        self.proxy.read().clone()
     }
 
     /// 获取当前活动凭据的克隆
@@ -646,11 +865,66 @@ impl MultiTokenManager {
         self.entries.lock().len()
     }
 
+    /// 所有凭据的尝试次数之和（凭据级 `requestPolicy.maxRetriesPerCredential` 覆盖默认值）
+    pub fn retry_budget(&self, default_per_credential: usize) -> usize {
+        self.entries
+            .lock()
+            .iter()
+            .map(|e| {
+                e.credentials
+                    .request_policy
+                    .as_ref()
+                    .and_then(|p| p.max_retries_per_credential)
+                    .unwrap_or(default_per_credential)
+            })
+            .sum()
+    }
+
     /// 获取可用凭据数量
     pub fn available_count(&self) -> usize {
         self.entries.lock().iter().filter(|e| !e.disabled).count()
     }
 
+    /// 预估最早恢复可用的等待秒数（用于 529 响应的 Retry-After）
+    ///
+    /// - 存在启用中的凭据（仅瞬态失败）或自动禁用的凭据（下次请求时自愈）：短暂等待
+    /// - 熔断冷却中的凭据：等到冷却结束
+    /// - 额度用尽或达到使用率阈值的凭据：等到额度重置（未知时按下个自然月 UTC）
+    /// - 仅剩手动禁用的凭据：无法预估，使用默认值
+    pub fn estimated_recovery_secs(&self) -> u64 {
+        let now = Utc::now();
+        self.entries
+            .lock()
+            .iter()
+            .filter_map(|e| match (e.disabled, e.disabled_reason) {
+                (false, _) => Some(TRANSIENT_RETRY_AFTER_SECS),
+                (true, Some(DisabledReason::TooManyFailures)) => {
+                    Some(e.cooldown_until.map_or(TRANSIENT_RETRY_AFTER_SECS, |at| {
+                        (at - now).num_seconds().max(1) as u64
+                    }))
+                }
+                (true, Some(DisabledReason::QuotaExceeded)) => Some(seconds_until_next_month(now)),
+                (true, Some(DisabledReason::UsageThreshold { reset_at })) => {
+                    Some(reset_at.map_or_else(
+                        || seconds_until_next_month(now),
+                        |at| (at - now.timestamp()).max(1) as u64,
+                    ))
+                }
+                _ => None,
+            })
+            .min()
+            .unwrap_or(DEFAULT_RETRY_AFTER_SECS)
+    }
+
+    /// 当前时间段被轮换策略停用的凭据
+    fn rotation_inactive(&self) -> HashSet<u64> {
+        self.config()
+            .rotation
+            .as_ref()
+            .map(|policy| rotation::inactive_credentials(policy, Utc::now()))
+            .unwrap_or_default()
+    }
+
     /// 根据负载均衡模式选择下一个凭据
     ///
     /// - priority 模式：选择优先级最高（priority 最小）的可用凭据
@@ -658,7 +932,19 @@ impl MultiTokenManager {
     ///
     /// # 参数
     /// - `model`: 可选的模型名称，用于过滤支持该模型的凭据（如 opus 模型需要付费订阅）
-    fn select_next_credential(&self, model: Option<&str>) -> Option<(u64, KiroCredentials)> {
+    /// - `pool`: 可选的凭据池过滤（Some(true) 仅 canary，Some(false) 仅常规，None 不限）
+    /// - `excluded`: 本次请求需要跳过的凭据 ID
+    ///
+    /// 请求带有 API Key 地域限制时只选择有效 API Region 在允许列表中的凭据
+    fn select_next_credential(
+        &self,
+        model: Option<&str>,
+        pool: Option<bool>,
+        excluded: &[u64],
+    ) -> Option<(u64, KiroCredentials)> {
+        let inactive = self.rotation_inactive();
+        let config = self.config();
+        let pinned = region_pin::current();
         let entries = self.entries.lock();
 
         // 检查是否是 opus 模型
@@ -670,14 +956,19 @@ impl MultiTokenManager {
         let available: Vec<_> = entries
             .iter()
             .filter(|e| {
-                if e.disabled {
+                if e.disabled || excluded.contains(&e.id) {
                     return false;
                 }
                 // 如果是 opus 模型，需要检查订阅等级
                 if is_opus && !e.credentials.supports_opus() {
                     return false;
                 }
-                true
+                if let Some(regions) = &pinned
+                    && !region_pin::allows(regions, e.credentials.effective_api_region(&config))
+                {
+                    return false;
+                }
+                pool.is_none_or(|canary| e.credentials.canary == canary)
             })
             .collect();
 
@@ -685,6 +976,18 @@ impl MultiTokenManager {
             return None;
         }
 
+        // 轮换策略停用的凭据仅在当前组没有可用凭据时使用
+        let rotated: Vec<_> = available
+            .iter()
+            .copied()
+            .filter(|e| !inactive.contains(&e.id))
+            .collect();
+        let available = if rotated.is_empty() {
+            available
+        } else {
+            rotated
+        };
+
         let mode = self.load_balancing_mode.lock().clone();
         let mode = mode.as_str();
 
@@ -717,8 +1020,35 @@ impl MultiTokenManager {
     /// # 参数
     /// - `model`: 可选的模型名称，用于过滤支持该模型的凭据（如 opus 模型需要付费订阅）
     pub async fn acquire_context(&self, model: Option<&str>) -> anyhow::Result<CallContext> {
+        self.acquire_context_excluding(model, &[]).await
+    }
+
+    /// 获取 API 调用上下文，跳过 `excluded` 中的凭据
+    ///
+    /// 用于同一请求内的故障转移（如首字节超时后换凭据重试）；
+    /// 排除后没有其他可用凭据时回退到被排除的凭据
+    pub async fn acquire_context_excluding(
+        &self,
+        model: Option<&str>,
+        excluded: &[u64],
+    ) -> anyhow::Result<CallContext> {
+        self.release_cooled_down();
         let total = self.total_count();
         let mut tried_count = 0;
+        let pool = self.pick_pool();
+        let pinned = region_pin::current();
+        // 目标凭据池无可用凭据时回退到全部凭据，排除后无可用凭据时忽略排除
+        let select = |model: Option<&str>| {
+            self.select_next_credential(model, pool, excluded)
+                .or_else(|| pool.and_then(|_| self.select_next_credential(model, None, excluded)))
+                .or_else(|| {
+                    if excluded.is_empty() {
+                        None
+                    } else {
+                        self.select_next_credential(model, None, &[])
+                    }
+                })
+        };
 
         loop {
             if tried_count >= total {
@@ -733,15 +1063,23 @@ impl MultiTokenManager {
                 let is_balanced = self.load_balancing_mode.lock().as_str() == "balanced";
 
                 // balanced 模式：每次请求都轮询选择，不固定 current_id
-                // priority 模式：优先使用 current_id 指向的凭据
-                let current_hit = if is_balanced {
+                // priority 模式：优先使用 current_id 指向的凭据（canary 请求除外）
+                // 带地域限制的请求不使用、也不改变当前凭据
+                let current_hit = if is_balanced || pool == Some(true) || pinned.is_some() {
                     None
                 } else {
+                    let inactive = self.rotation_inactive();
                     let entries = self.entries.lock();
                     let current_id = *self.current_id.lock();
                     entries
                         .iter()
-                        .find(|e| e.id == current_id && !e.disabled)
+                        .find(|e| {
+                            e.id == current_id
+                                && !e.disabled
+                                && !excluded.contains(&e.id)
+                                && !inactive.contains(&e.id)
+                                && (pool.is_none() || !e.credentials.canary)
+                        })
                         .map(|e| (e.id, e.credentials.clone()))
                 };
 
@@ -749,10 +1087,11 @@ impl MultiTokenManager {
                     hit
                 } else {
                     // 当前凭据不可用或 balanced 模式，根据负载均衡策略选择
-                    let mut best = self.select_next_credential(model);
+                    let mut best = select(model);
 
                     // 没有可用凭据：如果是"自动禁用导致全灭"，做一次类似重启的自愈
-                    if best.is_none() {
+                    // （启用熔断时由冷却结束后的探测恢复，不自愈）
+                    if best.is_none() && self.circuit_breaker().is_none() {
                         let mut entries = self.entries.lock();
                         if entries.iter().any(|e| {
                             e.disabled && e.disabled_reason == Some(DisabledReason::TooManyFailures)
@@ -768,16 +1107,22 @@ impl MultiTokenManager {
                                 }
                             }
                             drop(entries);
-                            best = self.select_next_credential(model);
+                            best = select(model);
                         }
                     }
 
                     if let Some((new_id, new_creds)) = best {
-                        // 更新 current_id
-                        let mut current_id = self.current_id.lock();
-                        *current_id = new_id;
+                        // 更新 current_id（canary 请求不影响常规流量的当前凭据；
+                        // 同一请求内的临时排除如超时、并发已满也不改变后续请求的当前凭据）
+                        if pool != Some(true) && excluded.is_empty() && pinned.is_none() {
+                            let mut current_id = self.current_id.lock();
+                            *current_id = new_id;
+                        }
                         (new_id, new_creds)
                     } else {
+                        if let Some(regions) = &pinned {
+                            bail!("没有位于允许地域（{}）的可用凭据", regions.join(", "));
+                        }
                         let entries = self.entries.lock();
                         // 注意：必须在 bail! 之前计算 available_count，
                         // 因为 available_count() 会尝试获取 entries 锁，
@@ -791,6 +1136,9 @@ impl MultiTokenManager {
             // 尝试获取/刷新 Token
             match self.try_ensure_token(id, &credentials).await {
                 Ok(ctx) => {
+                    self.pool_counters(ctx.credentials.canary)
+                        .requests
+                        .fetch_add(1, Ordering::Relaxed);
                     return Ok(ctx);
                 }
                 Err(e) => {
@@ -804,6 +1152,44 @@ impl MultiTokenManager {
         }
     }
 
+    /// 按灰度百分比决定本次请求使用的凭据池
+    ///
+    /// 存在可用 canary 凭据时，命中灰度返回 Some(true)，否则返回 Some(false)（包括灰度为 0%，
+    /// canary 凭据不承接常规流量）；没有可用 canary 凭据，或未命中灰度但常规池为空时返回 None
+    fn pick_pool(&self) -> Option<bool> {
+        let percentage = self.canary_percentage.load(Ordering::Relaxed);
+        let (has_canary, has_stable) = self
+            .entries
+            .lock()
+            .iter()
+            .filter(|e| !e.disabled)
+            .fold((false, false), |(canary, stable), e| {
+                (
+                    canary || e.credentials.canary,
+                    stable || !e.credentials.canary,
+                )
+            });
+        if !has_canary {
+            return None;
+        }
+        if fastrand::u8(0..100) < percentage {
+            Some(true)
+        } else if has_stable {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// 获取凭据池对应的统计计数器
+    fn pool_counters(&self, canary: bool) -> &PoolCounters {
+        if canary {
+            &self.canary_counters
+        } else {
+            &self.stable_counters
+        }
+    }
+
     /// 切换到下一个优先级最高的可用凭据（内部方法）
     fn switch_to_next_by_priority(&self) {
         let entries = self.entries.lock();
@@ -881,9 +1267,9 @@ impl MultiTokenManager {
 
             if is_token_expired(&current_creds) || is_token_expiring_soon(&current_creds) {
                 // 确实需要刷新
-                let effective_proxy = current_creds.effective_proxy(self.proxy.as_ref());
+                let effective_proxy = current_creds.effective_proxy(self.proxy().as_ref());
                 let new_creds =
-                    refresh_token(&current_creds, &self.config, effective_proxy.as_ref()).await?;
+                    refresh_token(&current_creds, &self.config(), effective_proxy.as_ref()).await?;
 
                 if is_token_expired(&new_creds) {
                     anyhow::bail!("刷新后的 Token 仍然无效或已过期");
@@ -1014,6 +1400,8 @@ impl MultiTokenManager {
             if let Some(s) = stats.get(&entry.id.to_string()) {
                 entry.success_count = s.success_count;
                 entry.last_used_at = s.last_used_at.clone();
+                entry.error_counts = s.error_counts;
+                entry.usage = s.usage.clone();
             }
         }
         *self.last_stats_save_at.lock() = Some(Instant::now());
@@ -1038,6 +1426,8 @@ impl MultiTokenManager {
                         StatsEntry {
                             success_count: e.success_count,
                             last_used_at: e.last_used_at.clone(),
+                            error_counts: e.error_counts,
+                            usage: e.usage.clone(),
                         },
                     )
                 })
@@ -1084,7 +1474,11 @@ impl MultiTokenManager {
         {
             let mut entries = self.entries.lock();
             if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+                self.pool_counters(entry.credentials.canary)
+                    .successes
+                    .fetch_add(1, Ordering::Relaxed);
                 entry.failure_count = 0;
+                entry.circuit_trips = 0;
                 entry.success_count += 1;
                 entry.last_used_at = Some(Utc::now().to_rfc3339());
                 tracing::debug!(
@@ -1097,14 +1491,42 @@ impl MultiTokenManager {
         self.save_stats_debounced();
     }
 
+    /// 记录指定凭据的一次错误（按类别累计，不影响连续失败计数）
+    pub fn report_error(&self, id: u64, class: ErrorClass) {
+        {
+            let mut entries = self.entries.lock();
+            if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+                entry.error_counts.record(class);
+            }
+        }
+        self.save_stats_debounced();
+    }
+
+    /// 记录指定凭据完成的一次请求的 token 用量与费用
+    pub fn record_usage(&self, id: u64, model: &str, input_tokens: u64, output_tokens: u64) {
+        let cost = pricing::cost_micros(model, input_tokens, output_tokens);
+        {
+            let mut entries = self.entries.lock();
+            if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+                entry
+                    .usage
+                    .record(input_tokens, output_tokens, cost, Utc::now());
+            }
+        }
+        self.save_stats_debounced();
+    }
+
     /// 报告指定凭据 API 调用失败
     ///
-    /// 增加失败计数，达到阈值时禁用凭据并切换到优先级最高的可用凭据
+    /// 增加失败计数，达到阈值时禁用凭据并切换到优先级最高的可用凭据；
+    /// 启用熔断时按连续熔断次数设置冷却结束时间
     /// 返回是否还有可用凭据可以重试
     ///
     /// # Arguments
     /// * `id` - 凭据 ID（来自 CallContext）
     pub fn report_failure(&self, id: u64) -> bool {
+        let breaker = self.circuit_breaker();
+        let threshold = failure_threshold(breaker.as_ref());
         let result = {
             let mut entries = self.entries.lock();
             let mut current_id = self.current_id.lock();
@@ -1114,6 +1536,9 @@ impl MultiTokenManager {
                 None => return entries.iter().any(|e| !e.disabled),
             };
 
+            self.pool_counters(entry.credentials.canary)
+                .failures
+                .fetch_add(1, Ordering::Relaxed);
             entry.failure_count += 1;
             entry.last_used_at = Some(Utc::now().to_rfc3339());
             let failure_count = entry.failure_count;
@@ -1122,13 +1547,29 @@ impl MultiTokenManager {
                 "凭据 #{} API 调用失败（{}/{}）",
                 id,
                 failure_count,
-                MAX_FAILURES_PER_CREDENTIAL
+                threshold
             );
 
-            if failure_count >= MAX_FAILURES_PER_CREDENTIAL {
+            if failure_count >= threshold {
                 entry.disabled = true;
                 entry.disabled_reason = Some(DisabledReason::TooManyFailures);
-                tracing::error!("凭据 #{} 已连续失败 {} 次，已被禁用", id, failure_count);
+                match &breaker {
+                    Some(breaker) => {
+                        entry.circuit_trips += 1;
+                        let secs = breaker.cooldown_secs(entry.circuit_trips);
+                        entry.cooldown_until = Some(Utc::now() + Duration::seconds(secs as i64));
+                        tracing::error!(
+                            "凭据 #{} 已连续失败 {} 次，熔断 {} 秒（第 {} 次连续熔断）",
+                            id,
+                            failure_count,
+                            secs,
+                            entry.circuit_trips
+                        );
+                    }
+                    None => {
+                        tracing::error!("凭据 #{} 已连续失败 {} 次，已被禁用", id, failure_count)
+                    }
+                }
 
                 // 切换到优先级最高的可用凭据
                 if let Some(next) = entries
@@ -1153,6 +1594,35 @@ impl MultiTokenManager {
         result
     }
 
+    /// 熔断参数（未配置或未启用时返回 None）
+    fn circuit_breaker(&self) -> Option<CircuitBreakerConfig> {
+        self.config().circuit_breaker.clone().filter(|c| c.enabled)
+    }
+
+    /// 冷却结束的熔断凭据重新启用并进入探测：失败计数置为阈值减一，再失败一次即重新熔断
+    fn release_cooled_down(&self) {
+        let Some(breaker) = self.circuit_breaker() else {
+            return;
+        };
+        let now = Utc::now();
+        let mut entries = self.entries.lock();
+        for entry in entries.iter_mut().filter(|e| {
+            e.disabled
+                && e.disabled_reason == Some(DisabledReason::TooManyFailures)
+                && e.cooldown_until.is_some_and(|at| at <= now)
+        }) {
+            entry.disabled = false;
+            entry.disabled_reason = None;
+            entry.cooldown_until = None;
+            entry.failure_count = failure_threshold(Some(&breaker)) - 1;
+            tracing::info!(
+                "凭据 #{} 熔断冷却结束，重新启用并探测（已连续熔断 {} 次）",
+                entry.id,
+                entry.circuit_trips
+            );
+        }
+    }
+
     /// 报告指定凭据额度已用尽
     ///
     /// 用于处理 402 Payment Required 且 reason 为 `MONTHLY_REQUEST_COUNT` 的场景：
@@ -1173,6 +1643,9 @@ impl MultiTokenManager {
                 return entries.iter().any(|e| !e.disabled);
             }
 
+            self.pool_counters(entry.credentials.canary)
+                .failures
+                .fetch_add(1, Ordering::Relaxed);
             entry.disabled = true;
             entry.disabled_reason = Some(DisabledReason::QuotaExceeded);
             entry.last_used_at = Some(Utc::now().to_rfc3339());
@@ -1203,6 +1676,198 @@ impl MultiTokenManager {
         result
     }
 
+    /// 启动额度巡检任务（每次检查时读取最新配置，支持热重载）
+    ///
+    /// 使用率达到 `usageDisableThreshold` 的凭据自动禁用，避免用到 100% 后请求直接报错；
+    /// 被禁用的凭据在额度重置时间之后重新查询，低于阈值即重新启用；
+    /// 多实例部署时只有持有巡检租约的实例执行巡检
+    pub fn spawn_usage_monitor(self: Arc<Self>, coordinator: Arc<Coordinator>) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(USAGE_MONITOR_TICK);
+            let mut last_check: Option<Instant> = None;
+            loop {
+                ticker.tick().await;
+                let config = self.config();
+                if config.usage_disable_threshold <= 0.0
+                    || !coordinator.try_acquire(USAGE_MONITOR_JOB)
+                {
+                    continue;
+                }
+                let interval = StdDuration::from_secs(config.usage_check_interval_mins.max(1) * 60);
+                if last_check.is_some_and(|at| at.elapsed() < interval) {
+                    continue;
+                }
+                last_check = Some(Instant::now());
+                self.check_usage(config.usage_disable_threshold).await;
+            }
+        });
+    }
+
+    /// 启动 Token 主动刷新任务（每次检查时读取最新配置，支持热重载）
+    ///
+    /// 在 `expiresAt` 前 `tokenRefresh.marginSecs` 秒、再随机提前至多 `jitterSecs` 秒刷新，
+    /// 避免空闲后的首个请求同步等待刷新；失败时稍后重试，请求时的按需刷新仍然生效
+    pub fn spawn_token_refresher(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(TOKEN_REFRESH_TICK);
+            // 凭据 ID → (计划所依据的 expiresAt, 计划刷新时间)
+            let mut schedule: HashMap<u64, (String, DateTime<Utc>)> = HashMap::new();
+            loop {
+                ticker.tick().await;
+                let policy = self.config().token_refresh.clone().unwrap_or_default();
+                if !policy.enabled {
+                    schedule.clear();
+                    continue;
+                }
+                for (id, expires_at) in self.due_refreshes(&policy, &mut schedule, Utc::now()) {
+                    if let Err(e) = self.refresh_ahead(id, &expires_at).await {
+                        tracing::warn!("凭据 #{} 主动刷新 Token 失败: {}", id, e);
+                        if let Some((_, due)) = schedule.get_mut(&id) {
+                            *due = Utc::now() + Duration::seconds(TOKEN_REFRESH_RETRY_SECS);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// 按刷新计划选出到期的凭据及其当前 `expiresAt`（跳过禁用和没有过期时间的凭据）
+    fn due_refreshes(
+        &self,
+        policy: &TokenRefreshConfig,
+        schedule: &mut HashMap<u64, (String, DateTime<Utc>)>,
+        now: DateTime<Utc>,
+    ) -> Vec<(u64, String)> {
+        let entries = self.entries.lock();
+        schedule.retain(|id, _| entries.iter().any(|e| e.id == *id));
+        entries
+            .iter()
+            .filter(|e| !e.disabled)
+            .filter_map(|e| {
+                let expires_at = e.credentials.expires_at.as_ref()?;
+                let due = match schedule.get(&e.id) {
+                    Some((planned_for, due)) if planned_for == expires_at => *due,
+                    _ => {
+                        let expires = DateTime::parse_from_rfc3339(expires_at).ok()?;
+                        let ahead = policy.margin_secs + fastrand::u64(0..=policy.jitter_secs);
+                        let due = expires.with_timezone(&Utc) - Duration::seconds(ahead as i64);
+                        schedule.insert(e.id, (expires_at.clone(), due));
+                        due
+                    }
+                };
+                (due <= now).then(|| (e.id, expires_at.clone()))
+            })
+            .collect()
+    }
+
+    /// 主动刷新指定凭据的 Token；获取刷新锁后 `expiresAt` 已变化（其他请求已刷新）时跳过
+    async fn refresh_ahead(&self, id: u64, planned_expires_at: &str) -> anyhow::Result<()> {
+        let _guard = self.refresh_lock.lock().await;
+        let current_creds = {
+            let entries = self.entries.lock();
+            match entries.iter().find(|e| e.id == id) {
+                Some(e) => e.credentials.clone(),
+                None => return Ok(()),
+            }
+        };
+        if current_creds.expires_at.as_deref() != Some(planned_expires_at) {
+            tracing::debug!("凭据 #{} 的 Token 已被其他请求刷新，跳过主动刷新", id);
+            return Ok(());
+        }
+
+        let effective_proxy = current_creds.effective_proxy(self.proxy().as_ref());
+        let new_creds =
+            refresh_token(&current_creds, &self.config(), effective_proxy.as_ref()).await?;
+        {
+            let mut entries = self.entries.lock();
+            if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+                entry.credentials = new_creds.clone();
+            }
+        }
+        if let Err(e) = self.persist_credentials() {
+            tracing::warn!("Token 刷新后持久化失败: {}", e);
+        }
+        tracing::info!(
+            "凭据 #{} 已主动刷新 Token，新的过期时间: {}",
+            id,
+            new_creds.expires_at.as_deref().unwrap_or("-")
+        );
+        Ok(())
+    }
+
+    /// 查询需要巡检的凭据额度并更新禁用状态
+    async fn check_usage(&self, threshold: f64) {
+        let now = Utc::now().timestamp();
+        let ids: Vec<u64> = self
+            .entries
+            .lock()
+            .iter()
+            .filter(|e| match (e.disabled, e.disabled_reason) {
+                (false, _) => true,
+                (true, Some(DisabledReason::UsageThreshold { reset_at })) => {
+                    reset_at.is_none_or(|at| now >= at)
+                }
+                _ => false,
+            })
+            .map(|e| e.id)
+            .collect();
+
+        for id in ids {
+            match self.get_usage_limits_for(id).await {
+                Ok(usage) => {
+                    let reset_at = usage.next_date_reset.map(|at| at as i64);
+                    self.apply_usage_percentage(id, usage.usage_percentage(), reset_at, threshold);
+                }
+                Err(e) => tracing::warn!("凭据 #{} 额度巡检失败: {}", id, e),
+            }
+        }
+    }
+
+    /// 按使用率禁用或重新启用凭据，返回状态是否发生变化
+    ///
+    /// 只处理启用中的凭据和因使用率阈值禁用的凭据，手动禁用等其他状态不受影响
+    fn apply_usage_percentage(
+        &self,
+        id: u64,
+        percentage: f64,
+        reset_at: Option<i64>,
+        threshold: f64,
+    ) -> bool {
+        {
+            let mut entries = self.entries.lock();
+            let Some(entry) = entries.iter_mut().find(|e| e.id == id) else {
+                return false;
+            };
+            let over = percentage >= threshold;
+            match (entry.disabled, entry.disabled_reason) {
+                (false, _) if over => {
+                    entry.disabled = true;
+                    entry.disabled_reason = Some(DisabledReason::UsageThreshold { reset_at });
+                    tracing::warn!(
+                        "凭据 #{} 额度使用率 {:.1}% 已达到阈值 {}%，已自动禁用",
+                        id,
+                        percentage,
+                        threshold
+                    );
+                }
+                (true, Some(DisabledReason::UsageThreshold { .. })) if !over => {
+                    entry.disabled = false;
+                    entry.disabled_reason = None;
+                    entry.failure_count = 0;
+                    tracing::info!("凭据 #{} 额度使用率降至 {:.1}%，已重新启用", id, percentage);
+                }
+                (true, Some(DisabledReason::UsageThreshold { .. })) => {
+                    // 重置时间已过但上游尚未重置，等待新的重置时间
+                    entry.disabled_reason = Some(DisabledReason::UsageThreshold { reset_at });
+                    return false;
+                }
+                _ => return false,
+            }
+        }
+        self.select_highest_priority();
+        true
+    }
+
     /// 切换到优先级最高的可用凭据
     ///
     /// 返回是否成功切换
@@ -1232,10 +1897,10 @@ impl MultiTokenManager {
     /// 获取使用额度信息
     pub async fn get_usage_limits(&self) -> anyhow::Result<UsageLimitsResponse> {
         let ctx = self.acquire_context(None).await?;
-        let effective_proxy = ctx.credentials.effective_proxy(self.proxy.as_ref());
+        let effective_proxy = ctx.credentials.effective_proxy(self.proxy().as_ref());
         get_usage_limits(
             &ctx.credentials,
-            &self.config,
+            &self.config(),
             &ctx.token,
             effective_proxy.as_ref(),
         )
@@ -1246,11 +1911,27 @@ impl MultiTokenManager {
     // Admin API 方法
     // ========================================================================
 
+    /// 凭据用量报告，`hours` 为小时明细覆盖的时长
+    pub fn credential_usage(&self, id: u64, hours: i64) -> anyhow::Result<CredentialUsageReport> {
+        let entries = self.entries.lock();
+        let entry = entries
+            .iter()
+            .find(|e| e.id == id)
+            .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
+        Ok(entry.usage.report(id, hours, Utc::now()))
+    }
+
+    /// 凭据级代理健康状态
+    pub fn proxy_health(&self) -> &ProxyHealth {
+        &self.proxy_health
+    }
+
     /// 获取管理器状态快照（用于 Admin API）
     pub fn snapshot(&self) -> ManagerSnapshot {
         let entries = self.entries.lock();
         let current_id = *self.current_id.lock();
         let available = entries.iter().filter(|e| !e.disabled).count();
+        let now = Utc::now();
 
         ManagerSnapshot {
             entries: entries
@@ -1271,10 +1952,19 @@ impl MultiTokenManager {
                     expires_at: e.credentials.expires_at.clone(),
                     refresh_token_hash: e.credentials.refresh_token.as_deref().map(sha256_hex),
                     email: e.credentials.email.clone(),
+                    subscription_title: e.credentials.subscription_title.clone(),
+                    circuit_trips: e.circuit_trips,
+                    cooldown_until: e.cooldown_until.map(|at| at.to_rfc3339()),
                     success_count: e.success_count,
                     last_used_at: e.last_used_at.clone(),
+                    error_counts: e.error_counts,
                     has_proxy: e.credentials.proxy_url.is_some(),
                     proxy_url: e.credentials.proxy_url.clone(),
+                    canary: e.credentials.canary,
+                    proxy_health: e
+                        .credentials
+                        .has_own_proxy()
+                        .then(|| self.proxy_health.snapshot(e.id, now)),
                 })
                 .collect(),
             current_id,
@@ -1325,9 +2015,11 @@ impl MultiTokenManager {
                 .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
             entry.disabled = disabled;
             if !disabled {
-                // 启用时重置失败计数
+                // 启用时重置失败计数与熔断状态
                 entry.failure_count = 0;
                 entry.disabled_reason = None;
+                entry.circuit_trips = 0;
+                entry.cooldown_until = None;
             } else {
                 entry.disabled_reason = Some(DisabledReason::Manual);
             }
@@ -1368,14 +2060,31 @@ impl MultiTokenManager {
             entry.failure_count = 0;
             entry.disabled = false;
             entry.disabled_reason = None;
+            entry.circuit_trips = 0;
+            entry.cooldown_until = None;
         }
         // 持久化更改
         self.persist_credentials()?;
         Ok(())
     }
 
-    /// 获取指定凭据的使用额度（Admin API）
-    pub async fn get_usage_limits_for(&self, id: u64) -> anyhow::Result<UsageLimitsResponse> {
+    /// 设置凭据是否属于 canary 凭据池（Admin API）
+    pub fn set_canary(&self, id: u64, canary: bool) -> anyhow::Result<()> {
+        {
+            let mut entries = self.entries.lock();
+            let entry = entries
+                .iter_mut()
+                .find(|e| e.id == id)
+                .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
+            entry.credentials.canary = canary;
+        }
+        // 持久化更改
+        self.persist_credentials()?;
+        Ok(())
+    }
+
+    /// 获取指定凭据的有效 Token（过期或即将过期时刷新）
+    async fn token_for(&self, id: u64) -> anyhow::Result<String> {
         let credentials = {
             let entries = self.entries.lock();
             entries
@@ -1400,9 +2109,9 @@ impl MultiTokenManager {
             };
 
             if is_token_expired(&current_creds) || is_token_expiring_soon(&current_creds) {
-                let effective_proxy = current_creds.effective_proxy(self.proxy.as_ref());
+                let effective_proxy = current_creds.effective_proxy(self.proxy().as_ref());
                 let new_creds =
-                    refresh_token(&current_creds, &self.config, effective_proxy.as_ref()).await?;
+                    refresh_token(&current_creds, &self.config(), effective_proxy.as_ref()).await?;
                 {
                     let mut entries = self.entries.lock();
                     if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
@@ -1426,6 +2135,30 @@ impl MultiTokenManager {
                 .access_token
                 .ok_or_else(|| anyhow::anyhow!("凭据无 access_token"))?
         };
+        Ok(token)
+    }
+
+    /// 获取指定凭据的调用上下文（不经过凭据选择，用于预热等定向请求）
+    pub async fn context_for(&self, id: u64) -> anyhow::Result<CallContext> {
+        let token = self.token_for(id).await?;
+        let credentials = {
+            let entries = self.entries.lock();
+            entries
+                .iter()
+                .find(|e| e.id == id)
+                .map(|e| e.credentials.clone())
+                .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?
+        };
+        Ok(CallContext {
+            id,
+            credentials,
+            token,
+        })
+    }
+
+    /// 获取指定凭据的使用额度（Admin API）
+    pub async fn get_usage_limits_for(&self, id: u64) -> anyhow::Result<UsageLimitsResponse> {
+        let token = self.token_for(id).await?;
 
         let credentials = {
             let entries = self.entries.lock();
@@ -1436,38 +2169,25 @@ impl MultiTokenManager {
                 .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?
         };
 
-        let effective_proxy = credentials.effective_proxy(self.proxy.as_ref());
-        let usage_limits =
-            get_usage_limits(&credentials, &self.config, &token, effective_proxy.as_ref()).await?;
-
-        // 更新订阅等级到凭据（仅在发生变化时持久化）
-        if let Some(subscription_title) = usage_limits.subscription_title() {
-            let changed = {
-                let mut entries = self.entries.lock();
-                if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
-                    let old_title = entry.credentials.subscription_title.clone();
-                    if old_title.as_deref() != Some(subscription_title) {
-                        entry.credentials.subscription_title = Some(subscription_title.to_string());
-                        tracing::info!(
-                            "凭据 #{} 订阅等级已更新: {:?} -> {}",
-                            id,
-                            old_title,
-                            subscription_title
-                        );
-                        true
-                    } else {
-                        false
-                    }
-                } else {
-                    false
-                }
-            };
+        let effective_proxy = credentials.effective_proxy(self.proxy().as_ref());
+        let usage_limits = get_usage_limits(
+            &credentials,
+            &self.config(),
+            &token,
+            effective_proxy.as_ref(),
+        )
+        .await?;
 
-            if changed {
-                if let Err(e) = self.persist_credentials() {
-                    tracing::warn!("订阅等级更新后持久化失败（不影响本次请求）: {}", e);
-                }
-            }
+        // 更新订阅等级与账号邮箱到凭据（仅在发生变化时持久化）
+        let changed = {
+            let mut entries = self.entries.lock();
+            entries
+                .iter_mut()
+                .find(|e| e.id == id)
+                .is_some_and(|entry| apply_account_info(id, &mut entry.credentials, &usage_limits))
+        };
+        if changed && let Err(e) = self.persist_credentials() {
+            tracing::warn!("订阅等级更新后持久化失败（不影响本次请求）: {}", e);
         }
 
         Ok(usage_limits)
@@ -1513,9 +2233,9 @@ impl MultiTokenManager {
         }
 
         // 3. 尝试刷新 Token 验证凭据有效性
-        let effective_proxy = new_cred.effective_proxy(self.proxy.as_ref());
+        let effective_proxy = new_cred.effective_proxy(self.proxy().as_ref());
         let mut validated_cred =
-            refresh_token(&new_cred, &self.config, effective_proxy.as_ref()).await?;
+            refresh_token(&new_cred, &self.config(), effective_proxy.as_ref()).await?;
 
         // 4. 分配新 ID
         let new_id = {
@@ -1550,10 +2270,15 @@ impl MultiTokenManager {
                 id: new_id,
                 credentials: validated_cred,
                 failure_count: 0,
-                disabled: false,
-                disabled_reason: None,
+                // 需要预热的凭据先以禁用状态加入，预热成功后再启用
+                disabled: new_cred.disabled,
+                disabled_reason: new_cred.disabled.then_some(DisabledReason::Manual),
                 success_count: 0,
                 last_used_at: None,
+                error_counts: ErrorCounts::default(),
+                usage: CredentialUsage::default(),
+                circuit_trips: 0,
+                cooldown_until: None,
             });
         }
 
@@ -1601,6 +2326,7 @@ impl MultiTokenManager {
 
             // 删除凭据
             entries.retain(|e| e.id != id);
+            self.proxy_health.reset(id);
 
             was_current
         };
@@ -1630,6 +2356,41 @@ impl MultiTokenManager {
         Ok(())
     }
 
+    /// 从配置文件重新加载配置并立即生效（Admin API）
+    ///
+    /// 负载均衡模式、灰度百分比、全局代理及请求处理相关配置对后续请求生效，
+    /// 进行中的请求继续使用旧配置；仅在启动时生效的字段保持不变，返回其中被修改的字段名
+    pub fn reload_config(&self) -> anyhow::Result<Vec<&'static str>> {
+        use anyhow::Context;
+
+        let current = self.config();
+        let config_path = current
+            .config_path()
+            .ok_or_else(|| anyhow::anyhow!("配置文件路径未知，无法重新加载"))?;
+        let mut config = Config::load(config_path)
+            .with_context(|| format!("重新加载配置失败: {}", config_path.display()))?;
+
+        if config.load_balancing_mode != "priority" && config.load_balancing_mode != "balanced" {
+            bail!("无效的负载均衡模式: {}", config.load_balancing_mode);
+        }
+        if config.canary_percentage > 100 {
+            bail!("灰度百分比必须在 0-100 之间: {}", config.canary_percentage);
+        }
+
+        let restart_required = config.retain_startup_fields(&current);
+        *self.load_balancing_mode.lock() = config.load_balancing_mode.clone();
+        self.canary_percentage
+            .store(config.canary_percentage, Ordering::Relaxed);
+        *self.proxy.write() = config.proxy_config();
+        *self.config.write() = Arc::new(config);
+
+        tracing::info!("配置已重新加载: {}", config_path.display());
+        if !restart_required.is_empty() {
+            tracing::warn!("以下配置项需重启后生效: {:?}", restart_required);
+        }
+        Ok(restart_required)
+    }
+
     /// 获取负载均衡模式（Admin API）
     pub fn get_load_balancing_mode(&self) -> String {
         self.load_balancing_mode.lock().clone()
@@ -1638,7 +2399,7 @@ impl MultiTokenManager {
     fn persist_load_balancing_mode(&self, mode: &str) -> anyhow::Result<()> {
         use anyhow::Context;
 
-        let config_path = match self.config.config_path() {
+        let config_path = match self.config().config_path() {
             Some(path) => path.to_path_buf(),
             None => {
                 tracing::warn!("配置文件路径未知，负载均衡模式仅在当前进程生效: {}", mode);
@@ -1678,6 +2439,144 @@ impl MultiTokenManager {
         tracing::info!("负载均衡模式已设置为: {}", mode);
         Ok(())
     }
+
+    /// 获取灰度路由状态及分池统计（Admin API）
+    pub fn canary_snapshot(&self) -> CanarySnapshot {
+        let canary_credentials = self
+            .entries
+            .lock()
+            .iter()
+            .filter(|e| e.credentials.canary)
+            .count();
+        CanarySnapshot {
+            percentage: self.canary_percentage.load(Ordering::Relaxed),
+            canary_credentials,
+            stable: self.stable_counters.snapshot(),
+            canary: self.canary_counters.snapshot(),
+        }
+    }
+
+    /// 设置灰度流量百分比（Admin API）
+    pub fn set_canary_percentage(&self, percentage: u8) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        if percentage > 100 {
+            anyhow::bail!("灰度百分比必须在 0-100 之间: {}", percentage);
+        }
+
+        let previous = self.canary_percentage.swap(percentage, Ordering::Relaxed);
+        if previous == percentage {
+            return Ok(());
+        }
+
+        if let Some(config_path) = self.config().config_path() {
+            let persisted = Config::load(config_path)
+                .and_then(|mut config| {
+                    config.canary_percentage = percentage;
+                    config.save()
+                })
+                .with_context(|| format!("持久化灰度百分比失败: {}", config_path.display()));
+            if let Err(err) = persisted {
+                self.canary_percentage.store(previous, Ordering::Relaxed);
+                return Err(err);
+            }
+        } else {
+            tracing::warn!(
+                "配置文件路径未知，灰度百分比仅在当前进程生效: {}",
+                percentage
+            );
+        }
+
+        tracing::info!("灰度流量百分比已设置为: {}%", percentage);
+        Ok(())
+    }
+
+    /// 修改配置：写回配置文件后立即对当前进程生效（配置文件路径未知时仅在当前进程生效）
+    fn update_config(&self, what: &str, apply: impl Fn(&mut Config)) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let current = self.config();
+        if let Some(config_path) = current.config_path() {
+            Config::load(config_path)
+                .and_then(|mut config| {
+                    apply(&mut config);
+                    config.save()
+                })
+                .with_context(|| format!("持久化{}失败: {}", what, config_path.display()))?;
+        } else {
+            tracing::warn!("配置文件路径未知，{}仅在当前进程生效", what);
+        }
+
+        let mut config = (*current).clone();
+        apply(&mut config);
+        *self.config.write() = Arc::new(config);
+        Ok(())
+    }
+
+    /// 启用或停用定时备份（Admin API）
+    pub fn set_backup_enabled(&self, enabled: bool) -> anyhow::Result<()> {
+        self.update_config("备份开关", |config| {
+            config.backup.get_or_insert_default().enabled = enabled;
+        })?;
+        tracing::info!("定时备份已{}", if enabled { "启用" } else { "停用" });
+        Ok(())
+    }
+
+    /// 设置凭据轮换策略（Admin API）
+    pub fn set_rotation(&self, rotation: RotationConfig) -> anyhow::Result<()> {
+        self.update_config("凭据轮换策略", |config| {
+            config.rotation = Some(rotation.clone());
+        })?;
+        tracing::info!(
+            "凭据轮换策略已{}（{} 组，每 {} 小时轮换）",
+            if rotation.enabled { "启用" } else { "停用" },
+            rotation.groups.len(),
+            rotation.period_hours
+        );
+        Ok(())
+    }
+
+    /// 替换模型别名映射（Admin API）
+    pub fn set_model_mappings(&self, mappings: BTreeMap<String, String>) -> anyhow::Result<()> {
+        let count = mappings.len();
+        self.update_config("模型映射", |config| {
+            config.model_mappings = mappings.clone();
+        })?;
+        tracing::info!("模型映射已更新（{} 条）", count);
+        Ok(())
+    }
+
+    /// 替换模型输出上限设置（Admin API）
+    pub fn set_output_limits(
+        &self,
+        limits: BTreeMap<String, i32>,
+        default_max_tokens: i32,
+        overflow_strategy: String,
+    ) -> anyhow::Result<()> {
+        let count = limits.len();
+        self.update_config("模型输出上限", |config| {
+            config.model_max_output_tokens = limits.clone();
+            config.default_max_tokens = default_max_tokens;
+            config.max_tokens_overflow_strategy = overflow_strategy.clone();
+        })?;
+        tracing::info!(
+            "模型输出上限已更新（{} 条，默认 max_tokens {}，超限策略 {}）",
+            count,
+            default_max_tokens,
+            overflow_strategy
+        );
+        Ok(())
+    }
+
+    /// 替换模型单价表（Admin API）
+    pub fn set_pricing(&self, prices: BTreeMap<String, ModelPrice>) -> anyhow::Result<()> {
+        let count = prices.len();
+        self.update_config("模型单价", |config| {
+            config.pricing = prices.clone();
+        })?;
+        tracing::info!("模型单价已更新（{} 条）", count);
+        Ok(())
+    }
 }
 
 impl Drop for MultiTokenManager {
@@ -1700,6 +2599,28 @@ mod tests {
         assert!(tm.credentials().access_token.is_none());
     }
 
+    #[test]
+    fn test_apply_account_info_from_usage_limits() {
+        let usage: UsageLimitsResponse = serde_json::from_value(serde_json::json!({
+            "subscriptionInfo": {"subscriptionTitle": "KIRO PRO"},
+            "userInfo": {"email": "dev@example.com", "userId": "u-1"}
+        }))
+        .unwrap();
+        let mut credentials = KiroCredentials {
+            email: Some("wrong@example.com".to_string()),
+            ..Default::default()
+        };
+        assert!(apply_account_info(1, &mut credentials, &usage));
+        assert_eq!(credentials.email.as_deref(), Some("dev@example.com"));
+        assert_eq!(credentials.subscription_title.as_deref(), Some("KIRO PRO"));
+        assert!(!apply_account_info(1, &mut credentials, &usage));
+
+        // 上游未返回账号信息时保留原值
+        let usage: UsageLimitsResponse = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert!(!apply_account_info(1, &mut credentials, &usage));
+        assert_eq!(credentials.email.as_deref(), Some("dev@example.com"));
+    }
+
     #[test]
     fn test_is_token_expired_with_expired_token() {
         let mut credentials = KiroCredentials::default();
@@ -1802,6 +2723,271 @@ mod tests {
         assert_eq!(manager.available_count(), 2);
     }
 
+    #[tokio::test]
+    async fn test_acquire_context_excluding_skips_excluded_credential() {
+        let config = Config::default();
+        let cred1 = KiroCredentials {
+            priority: 0,
+            access_token: Some("token1".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let cred2 = KiroCredentials {
+            priority: 1,
+            access_token: Some("token2".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let manager =
+            MultiTokenManager::new(config, vec![cred1, cred2], None, None, false).unwrap();
+
+        let first = manager.acquire_context(None).await.unwrap();
+        let second = manager
+            .acquire_context_excluding(None, &[first.id])
+            .await
+            .unwrap();
+        assert_ne!(first.id, second.id);
+        // 临时排除不改变 priority 模式的当前凭据
+        assert_eq!(manager.acquire_context(None).await.unwrap().id, first.id);
+
+        // 全部排除时回退到被排除的凭据
+        let fallback = manager
+            .acquire_context_excluding(None, &[first.id, second.id])
+            .await
+            .unwrap();
+        assert!(fallback.id == first.id || fallback.id == second.id);
+    }
+
+    #[tokio::test]
+    async fn test_region_pinned_request_only_uses_allowed_regions() {
+        let config = Config::default();
+        let credential = |priority: u32, api_region: Option<&str>| KiroCredentials {
+            priority,
+            api_region: api_region.map(str::to_string),
+            access_token: Some(format!("token{}", priority)),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let manager = MultiTokenManager::new(
+            config,
+            vec![credential(0, None), credential(1, Some("eu-central-1"))],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        let eu = Some(vec!["eu-central-1".to_string()]);
+        let ctx = region_pin::scope(eu, manager.acquire_context(None))
+            .await
+            .unwrap();
+        assert_eq!(ctx.credentials.api_region.as_deref(), Some("eu-central-1"));
+        // 地域限制不影响其他请求的当前凭据
+        let ctx = manager.acquire_context(None).await.unwrap();
+        assert_eq!(ctx.credentials.priority, 0);
+
+        // 没有符合条件的凭据时直接失败，不回退到其他地域
+        let ap = Some(vec!["ap-southeast-1".to_string()]);
+        assert!(
+            region_pin::scope(ap, manager.acquire_context(None))
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_reload_config_applies_runtime_fields() {
+        let dir = std::env::temp_dir().join(format!("kiro-reload-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.json");
+        std::fs::write(&config_path, r#"{"port": 8080, "apiKey": "k1"}"#).unwrap();
+
+        let config = Config::load(&config_path).unwrap();
+        let manager = MultiTokenManager::new(config, vec![], None, None, false).unwrap();
+        let system_version = manager.config().system_version.clone();
+
+        std::fs::write(
+            &config_path,
+            r#"{
+                "port": 9090,
+                "apiKey": "k1",
+                "loadBalancingMode": "balanced",
+                "canaryPercentage": 20,
+                "proxyUrl": "http://127.0.0.1:7890",
+                "nonStreamHeartbeatSecs": 15
+            }"#,
+        )
+        .unwrap();
+        let restart_required = manager.reload_config().unwrap();
+
+        assert_eq!(restart_required, vec!["port"]);
+        assert_eq!(manager.get_load_balancing_mode(), "balanced");
+        assert_eq!(manager.canary_snapshot().percentage, 20);
+        assert_eq!(manager.proxy().unwrap().url, "http://127.0.0.1:7890");
+        let reloaded = manager.config();
+        assert_eq!(reloaded.port, 8080);
+        assert_eq!(reloaded.non_stream_heartbeat_secs, 15);
+        assert_eq!(reloaded.system_version, system_version);
+
+        // 无效配置不生效
+        std::fs::write(&config_path, r#"{"loadBalancingMode": "random"}"#).unwrap();
+        assert!(manager.reload_config().is_err());
+        assert_eq!(manager.get_load_balancing_mode(), "balanced");
+
+        std::fs::write(&config_path, r#"{"toolsOverflowStrategy": "compres"}"#).unwrap();
+        assert!(manager.reload_config().is_err());
+        assert_eq!(manager.config().tools_overflow_strategy, "reject");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_estimated_recovery_secs() {
+        let config = Config::default();
+        let manager = MultiTokenManager::new(
+            config,
+            vec![KiroCredentials::default(), KiroCredentials::default()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        let ids: Vec<u64> = manager.snapshot().entries.iter().map(|e| e.id).collect();
+        assert_eq!(
+            manager.estimated_recovery_secs(),
+            TRANSIENT_RETRY_AFTER_SECS
+        );
+
+        // 全部手动禁用：无法预估
+        manager.set_disabled(ids[0], true).unwrap();
+        manager.set_disabled(ids[1], true).unwrap();
+        assert_eq!(manager.estimated_recovery_secs(), DEFAULT_RETRY_AFTER_SECS);
+
+        // 额度用尽：等到下个月重置
+        manager.set_disabled(ids[1], false).unwrap();
+        manager.report_quota_exhausted(ids[1]);
+        let secs = manager.estimated_recovery_secs();
+        assert!(secs > 0 && secs <= 31 * 24 * 3600);
+    }
+
+    #[test]
+    fn test_usage_threshold_disables_and_reenables() {
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![KiroCredentials::default(), KiroCredentials::default()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        let ids: Vec<u64> = manager.snapshot().entries.iter().map(|e| e.id).collect();
+        let reset_at = Utc::now().timestamp() + 3600;
+
+        assert!(!manager.apply_usage_percentage(ids[0], 50.0, Some(reset_at), 90.0));
+        assert!(manager.apply_usage_percentage(ids[0], 95.0, Some(reset_at), 90.0));
+        assert_eq!(manager.available_count(), 1);
+        assert_eq!(manager.snapshot().current_id, ids[1]);
+        let secs = manager.estimated_recovery_secs();
+        assert_eq!(secs, TRANSIENT_RETRY_AFTER_SECS);
+
+        // 手动禁用的凭据不会被巡检重新启用
+        manager.set_disabled(ids[1], true).unwrap();
+        assert!(!manager.apply_usage_percentage(ids[1], 0.0, None, 90.0));
+        let secs = manager.estimated_recovery_secs();
+        assert!(secs > 3500 && secs <= 3600);
+
+        // 额度重置后低于阈值，重新启用
+        assert!(!manager.apply_usage_percentage(ids[0], 100.0, None, 90.0));
+        assert!(manager.apply_usage_percentage(ids[0], 1.0, None, 90.0));
+        assert_eq!(manager.snapshot().current_id, ids[0]);
+    }
+
+    #[test]
+    fn test_due_refreshes_schedules_ahead_of_expiry() {
+        let now = Utc::now();
+        let expiring = |minutes: i64| KiroCredentials {
+            expires_at: Some((now + Duration::minutes(minutes)).to_rfc3339()),
+            ..Default::default()
+        };
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![
+                expiring(12),
+                expiring(120),
+                expiring(12),
+                KiroCredentials::default(),
+            ],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        let ids: Vec<u64> = manager.snapshot().entries.iter().map(|e| e.id).collect();
+        manager.set_disabled(ids[2], true).unwrap();
+        let policy = TokenRefreshConfig {
+            enabled: true,
+            margin_secs: 900,
+            jitter_secs: 0,
+        };
+
+        // 只有启用中且在提前量内过期的凭据到期
+        let mut schedule = HashMap::new();
+        let due = manager.due_refreshes(&policy, &mut schedule, now);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0, ids[0]);
+        assert_eq!(schedule.len(), 2);
+
+        // expiresAt 未变化时沿用已有计划，随机提前量不会重新抽取
+        schedule.get_mut(&ids[1]).unwrap().1 = now;
+        let due = manager.due_refreshes(&policy, &mut schedule, now);
+        assert_eq!(due.len(), 2);
+
+        // 随机提前量叠加在固定提前量之上
+        let policy = TokenRefreshConfig {
+            jitter_secs: 7200,
+            ..policy
+        };
+        let mut schedule = HashMap::new();
+        manager.due_refreshes(&policy, &mut schedule, now);
+        let (_, due_at) = &schedule[&ids[1]];
+        assert!(*due_at <= now + Duration::minutes(105));
+        assert!(*due_at >= now - Duration::minutes(15));
+    }
+
+    #[test]
+    fn test_seconds_until_next_month() {
+        let now = Utc.with_ymd_and_hms(2025, 12, 31, 23, 59, 0).unwrap();
+        assert_eq!(seconds_until_next_month(now), 60);
+    }
+
+    #[test]
+    fn test_error_class_from_status() {
+        assert_eq!(ErrorClass::from_status(401), ErrorClass::Auth);
+        assert_eq!(ErrorClass::from_status(403), ErrorClass::Auth);
+        assert_eq!(ErrorClass::from_status(429), ErrorClass::Throttle);
+        assert_eq!(ErrorClass::from_status(408), ErrorClass::Timeout);
+        assert_eq!(ErrorClass::from_status(503), ErrorClass::Server);
+        assert_eq!(ErrorClass::from_status(400), ErrorClass::Other);
+    }
+
+    #[test]
+    fn test_report_error_accumulates_by_class() {
+        let config = Config::default();
+        let manager =
+            MultiTokenManager::new(config, vec![KiroCredentials::default()], None, None, false)
+                .unwrap();
+        let id = manager.snapshot().entries[0].id;
+
+        manager.report_error(id, ErrorClass::Throttle);
+        manager.report_error(id, ErrorClass::Throttle);
+        manager.report_error(id, ErrorClass::Server);
+
+        let counts = manager.snapshot().entries[0].error_counts;
+        assert_eq!(counts.throttle, 2);
+        assert_eq!(counts.server, 1);
+        assert_eq!(counts.auth, 0);
+    }
+
     #[test]
     fn test_multi_token_manager_empty_credentials() {
         let config = Config::default();
@@ -1857,6 +3043,62 @@ mod tests {
         assert_eq!(manager.available_count(), 0);
     }
 
+    #[test]
+    fn test_circuit_breaker_backs_off_and_probes() {
+        let mut config = Config::default();
+        config.circuit_breaker = Some(CircuitBreakerConfig {
+            enabled: true,
+            failure_threshold: 2,
+            base_cooldown_secs: 30,
+            max_cooldown_secs: 100,
+        });
+        let manager = MultiTokenManager::new(
+            config,
+            vec![KiroCredentials::default(), KiroCredentials::default()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        let expire_cooldown = || {
+            let mut entries = manager.entries.lock();
+            let entry = entries.iter_mut().find(|e| e.id == 1).unwrap();
+            entry.cooldown_until = Some(Utc::now() - Duration::seconds(1));
+        };
+
+        manager.report_failure(1);
+        assert!(manager.report_failure(1));
+        let snapshot = manager.snapshot();
+        assert!(snapshot.entries[0].disabled);
+        assert_eq!(snapshot.entries[0].circuit_trips, 1);
+
+        // 冷却未结束时不放行
+        manager.release_cooled_down();
+        assert_eq!(manager.available_count(), 1);
+
+        // 冷却结束后探测，探测失败一次即再次熔断，冷却时长翻倍
+        expire_cooldown();
+        manager.release_cooled_down();
+        assert_eq!(manager.available_count(), 2);
+        manager.report_failure(1);
+        let entry = manager.snapshot().entries.remove(0);
+        assert!(entry.disabled);
+        assert_eq!(entry.circuit_trips, 2);
+        let until = DateTime::parse_from_rfc3339(entry.cooldown_until.as_deref().unwrap()).unwrap();
+        let remaining = (until.with_timezone(&Utc) - Utc::now()).num_seconds();
+        assert!((55..=60).contains(&remaining));
+
+        // 探测成功后清零熔断次数
+        expire_cooldown();
+        manager.release_cooled_down();
+        manager.report_success(1);
+        assert_eq!(manager.snapshot().entries[0].circuit_trips, 0);
+
+        let breaker = manager.circuit_breaker().unwrap();
+        assert_eq!(breaker.cooldown_secs(3), 100);
+        assert_eq!(breaker.cooldown_secs(64), 100);
+    }
+
     #[test]
     fn test_multi_token_manager_report_success() {
         let config = Config::default();
@@ -1953,6 +3195,134 @@ mod tests {
         assert_eq!(manager.available_count(), 2);
     }
 
+    #[tokio::test]
+    async fn test_canary_routing_splits_traffic_by_pool() {
+        let config = Config::default();
+        let stable = KiroCredentials {
+            access_token: Some("stable".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let canary = KiroCredentials {
+            access_token: Some("canary".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            priority: 1,
+            canary: true,
+            ..Default::default()
+        };
+
+        let manager =
+            MultiTokenManager::new(config, vec![stable, canary], None, None, false).unwrap();
+        manager.set_canary_percentage(100).unwrap();
+
+        // 100% 灰度：全部路由到 canary 池，且不影响常规流量的当前凭据
+        let ctx = manager.acquire_context(None).await.unwrap();
+        assert_eq!(ctx.token, "canary");
+        assert_eq!(manager.snapshot().current_id, 1);
+        manager.report_success(ctx.id);
+
+        // 关闭灰度：回到常规优先级选择
+        manager.set_canary_percentage(0).unwrap();
+        let ctx = manager.acquire_context(None).await.unwrap();
+        assert_eq!(ctx.token, "stable");
+        manager.report_failure(ctx.id);
+
+        let snapshot = manager.canary_snapshot();
+        assert_eq!(snapshot.canary_credentials, 1);
+        assert_eq!(snapshot.canary.requests, 1);
+        assert_eq!(snapshot.canary.successes, 1);
+        assert_eq!(snapshot.stable.requests, 1);
+        assert_eq!(snapshot.stable.failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_canary_routing_falls_back_when_pool_empty() {
+        let config = Config::default();
+        let cred = KiroCredentials {
+            access_token: Some("stable".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+
+        let manager = MultiTokenManager::new(config, vec![cred], None, None, false).unwrap();
+        manager.set_canary_percentage(100).unwrap();
+
+        // 没有 canary 凭据时不限制凭据池
+        let ctx = manager.acquire_context(None).await.unwrap();
+        assert_eq!(ctx.token, "stable");
+        assert!(manager.set_canary_percentage(101).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_canary_routing_zero_percent_excludes_canary_pool() {
+        let credential = |token: &str, priority: u32, canary: bool| KiroCredentials {
+            access_token: Some(token.to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            priority,
+            canary,
+            ..Default::default()
+        };
+        // canary 凭据优先级更高，但灰度为 0% 时不应承接常规流量
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![credential("canary", 0, true), credential("stable", 1, false)],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        manager.set_load_balancing_mode("balanced".to_string()).unwrap();
+        assert_eq!(manager.canary_snapshot().percentage, 0);
+
+        let mut stable_id = 0;
+        for _ in 0..5 {
+            let ctx = manager.acquire_context(None).await.unwrap();
+            assert_eq!(ctx.token, "stable");
+            stable_id = ctx.id;
+            manager.report_success(ctx.id);
+        }
+        assert_eq!(manager.canary_snapshot().canary.requests, 0);
+
+        // 常规池为空时回退到 canary 凭据
+        manager.set_disabled(stable_id, true).unwrap();
+        let ctx = manager.acquire_context(None).await.unwrap();
+        assert_eq!(ctx.token, "canary");
+    }
+
+    #[tokio::test]
+    async fn test_rotation_prefers_active_group_and_falls_back() {
+        let credential = |token: &str, priority: u32| KiroCredentials {
+            access_token: Some(token.to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            priority,
+            ..Default::default()
+        };
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![credential("one", 0), credential("two", 1)],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+        // 周期足够长，当前始终处于第 0 组
+        manager
+            .set_rotation(RotationConfig {
+                enabled: true,
+                period_hours: 1_000_000,
+                groups: vec![vec![2], vec![1]],
+            })
+            .unwrap();
+
+        let ctx = manager.acquire_context(None).await.unwrap();
+        assert_eq!(ctx.token, "two");
+
+        // 当前组没有可用凭据时回退到其他组
+        manager.set_disabled(2, true).unwrap();
+        let ctx = manager.acquire_context(None).await.unwrap();
+        assert_eq!(ctx.token, "one");
+    }
+
     #[test]
     fn test_multi_token_manager_report_quota_exhausted() {
         let config = Config::default();
@@ -50,6 +50,74 @@ struct WebAuthSession {
     client_secret: String,
     region: String,
     credential_id: Option<u64>,
+    /// 两次向上游轮询之间的最小间隔（秒），收到 slow_down 时增加
+    poll_interval_secs: i64,
+    last_poll_at: Option<chrono::DateTime<Utc>>,
+    poll_count: u32,
+}
+
+/// 设备授权默认轮询间隔（秒，RFC 8628）
+const DEFAULT_POLL_INTERVAL_SECS: i64 = 5;
+
+/// 收到 slow_down 时增加的轮询间隔（秒，RFC 8628）
+const SLOW_DOWN_INCREMENT_SECS: i64 = 5;
+
+/// 单个会话最多向上游轮询的次数
+const MAX_POLLS_PER_SESSION: u32 = 120;
+
+/// 状态查询是否需要向上游轮询
+#[derive(Debug, PartialEq, Eq)]
+enum PollGate {
+    /// 向上游轮询（已记录本次轮询）
+    Poll,
+    /// 距上次轮询不足最小间隔，直接返回当前状态
+    Wait,
+    /// 轮询次数已达上限
+    Exhausted,
+}
+
+impl WebAuthSession {
+    fn new(
+        start: &StartDeviceAuthResponse,
+        auth_method: String,
+        register: RegisterClientResponse,
+        region: String,
+    ) -> Self {
+        Self {
+            state_id: Uuid::new_v4().to_string(),
+            device_code: start.device_code.clone(),
+            user_code: start.user_code.clone(),
+            verification_uri_complete: start.verification_uri_complete.clone(),
+            expires_in: start.expires_in.max(60),
+            started_at: Utc::now(),
+            status: SessionStatus::Pending,
+            error: None,
+            auth_method,
+            client_id: register.client_id,
+            client_secret: register.client_secret,
+            region,
+            credential_id: None,
+            poll_interval_secs: start.interval.unwrap_or(DEFAULT_POLL_INTERVAL_SECS).max(1),
+            last_poll_at: None,
+            poll_count: 0,
+        }
+    }
+
+    /// 判断本次状态查询是否向上游轮询；需要轮询时先记录，避免并发查询重复轮询
+    fn claim_poll(&mut self, now: chrono::DateTime<Utc>) -> PollGate {
+        if self.poll_count >= MAX_POLLS_PER_SESSION {
+            return PollGate::Exhausted;
+        }
+        if self
+            .last_poll_at
+            .is_some_and(|last| (now - last).num_seconds() < self.poll_interval_secs)
+        {
+            return PollGate::Wait;
+        }
+        self.last_poll_at = Some(now);
+        self.poll_count += 1;
+        PollGate::Poll
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -96,6 +164,8 @@ struct StartDeviceAuthResponse {
     user_code: String,
     verification_uri_complete: String,
     expires_in: i64,
+    #[serde(default)]
+    interval: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -179,24 +249,11 @@ async fn start_auth(
         Err(e) => return error_html(StatusCode::BAD_GATEWAY, &e),
     };
 
-    let state_id = Uuid::new_v4().to_string();
-    let session = WebAuthSession {
-        state_id: state_id.clone(),
-        device_code: start.device_code,
-        user_code: start.user_code,
-        verification_uri_complete: start.verification_uri_complete.clone(),
-        expires_in: start.expires_in.max(60),
-        started_at: Utc::now(),
-        status: SessionStatus::Pending,
-        error: None,
-        auth_method,
-        client_id: register.client_id,
-        client_secret: register.client_secret,
-        region,
-        credential_id: None,
-    };
-
-    state.sessions.lock().insert(state_id, session.clone());
+    let session = WebAuthSession::new(&start, auth_method, register, region);
+    state
+        .sessions
+        .lock()
+        .insert(session.state_id.clone(), session.clone());
 
     Html(render_start_html(&session)).into_response()
 }
@@ -270,23 +327,8 @@ async fn start_auth_json(
         }
     };
 
-    let state_id = Uuid::new_v4().to_string();
-    let session = WebAuthSession {
-        state_id: state_id.clone(),
-        device_code: start.device_code,
-        user_code: start.user_code.clone(),
-        verification_uri_complete: start.verification_uri_complete.clone(),
-        expires_in: start.expires_in.max(60),
-        started_at: Utc::now(),
-        status: SessionStatus::Pending,
-        error: None,
-        auth_method,
-        client_id: register.client_id,
-        client_secret: register.client_secret,
-        region,
-        credential_id: None,
-    };
-
+    let session = WebAuthSession::new(&start, auth_method, register, region);
+    let state_id = session.state_id.clone();
     state.sessions.lock().insert(state_id.clone(), session);
 
     Json(json!({
@@ -302,10 +344,17 @@ async fn check_status(
     State(state): State<KiroOAuthWebState>,
     Query(query): Query<StatusQuery>,
 ) -> impl IntoResponse {
-    let current = {
-        let sessions = state.sessions.lock();
-        match sessions.get(&query.state) {
-            Some(s) => s.clone(),
+    let (current, gate) = {
+        let mut sessions = state.sessions.lock();
+        match sessions.get_mut(&query.state) {
+            Some(s) => {
+                let gate = if matches!(s.status, SessionStatus::Pending) {
+                    s.claim_poll(Utc::now())
+                } else {
+                    PollGate::Wait
+                };
+                (s.clone(), gate)
+            }
             None => {
                 return (
                     StatusCode::NOT_FOUND,
@@ -322,7 +371,20 @@ async fn check_status(
         if elapsed >= next.expires_in {
             next.status = SessionStatus::Failed;
             next.error = Some("authentication timed out".to_string());
-        } else {
+        } else if gate == PollGate::Exhausted {
+            tracing::warn!(
+                "OAuth 会话 {} 轮询次数已达上限（{}），终止授权",
+                next.state_id,
+                MAX_POLLS_PER_SESSION
+            );
+            next.status = SessionStatus::Failed;
+            next.error = Some("too many status polls".to_string());
+        } else if gate == PollGate::Poll {
+            tracing::debug!(
+                "OAuth 会话 {} 第 {} 次轮询设备授权结果",
+                next.state_id,
+                next.poll_count
+            );
             let client = match build_http_client(&state.config) {
                 Ok(c) => c,
                 Err(e) => {
@@ -347,7 +409,14 @@ async fn check_status(
             .await
             {
                 PollResult::Pending => {}
-                PollResult::SlowDown => {}
+                PollResult::SlowDown => {
+                    next.poll_interval_secs += SLOW_DOWN_INCREMENT_SECS;
+                    tracing::info!(
+                        "OAuth 会话 {} 收到 slow_down，轮询间隔调整为 {}s",
+                        next.state_id,
+                        next.poll_interval_secs
+                    );
+                }
                 PollResult::Token(token) => {
                     let req = AddCredentialRequest {
                         refresh_token: token.refresh_token,
@@ -363,6 +432,8 @@ async fn check_status(
                         proxy_url: None,
                         proxy_username: None,
                         proxy_password: None,
+                        request_policy: None,
+                        warm_up: None,
                     };
 
                     match state.admin.service.add_credential(req).await {
@@ -393,7 +464,8 @@ async fn check_status(
     match next.status {
         SessionStatus::Pending => Json(json!({
             "status":"pending",
-            "remaining_seconds": remaining
+            "remaining_seconds": remaining,
+            "interval_seconds": next.poll_interval_secs
         }))
         .into_response(),
         SessionStatus::Success => Json(json!({
@@ -462,6 +534,8 @@ async fn import_token(
         proxy_url: None,
         proxy_username: None,
         proxy_password: None,
+        request_policy: None,
+        warm_up: None,
     };
 
     match state.admin.service.add_credential(req).await {
@@ -622,7 +696,7 @@ fn render_start_html(session: &WebAuthSession) -> String {
         return;
       }}
       timerBox.innerText = "剩余时间：" + data.remaining_seconds + "s";
-      setTimeout(poll, 3000);
+      setTimeout(poll, (data.interval_seconds || 5) * 1000);
     }}
     setTimeout(() => document.querySelector(".btn").click(), 300);
     poll();
@@ -925,3 +999,55 @@ const SELECT_HTML: &str = r##"<!doctype html>
   </script>
 </body>
 </html>"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> WebAuthSession {
+        let start = StartDeviceAuthResponse {
+            device_code: "device".to_string(),
+            user_code: "ABCD".to_string(),
+            verification_uri_complete: "https://example.com".to_string(),
+            expires_in: 600,
+            interval: None,
+        };
+        let register = RegisterClientResponse {
+            client_id: "cid".to_string(),
+            client_secret: "secret".to_string(),
+        };
+        WebAuthSession::new(&start, "idc".to_string(), register, "us-east-1".to_string())
+    }
+
+    #[test]
+    fn test_claim_poll_enforces_interval_and_cap() {
+        let mut session = session();
+        let now = Utc::now();
+        assert_eq!(session.claim_poll(now), PollGate::Poll);
+        assert_eq!(
+            session.claim_poll(now + chrono::Duration::seconds(2)),
+            PollGate::Wait
+        );
+        assert_eq!(
+            session.claim_poll(now + chrono::Duration::seconds(5)),
+            PollGate::Poll
+        );
+
+        // slow_down 后按新的间隔等待
+        session.poll_interval_secs += SLOW_DOWN_INCREMENT_SECS;
+        assert_eq!(
+            session.claim_poll(now + chrono::Duration::seconds(12)),
+            PollGate::Wait
+        );
+        assert_eq!(
+            session.claim_poll(now + chrono::Duration::seconds(15)),
+            PollGate::Poll
+        );
+
+        session.poll_count = MAX_POLLS_PER_SESSION;
+        assert_eq!(
+            session.claim_poll(now + chrono::Duration::hours(1)),
+            PollGate::Exhausted
+        );
+    }
+}
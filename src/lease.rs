@@ -0,0 +1,166 @@
+//! 多实例协调
+//!
+//! 多个副本共享同一个租约数据库时，后台任务（定时备份、额度巡检）每次运行前先获取对应的租约，
+//! 只有租约持有者执行任务并在每次检查时续约；持有者宕机后租约过期，由其他实例接管。
+//! 未启用 `coordination` 时所有任务都在本实例运行。
+//!
+//! 租约数据库为 SQLite 文件，放在各实例共享的卷上即可（使用默认的回滚日志模式，
+//! 不开启 WAL，以兼容网络文件系统）。
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use chrono::Utc;
+use parking_lot::Mutex;
+use rusqlite::{Connection, params};
+
+use crate::model::config::CoordinationConfig;
+
+/// 定时备份任务的租约名
+pub const BACKUP_JOB: &str = "backup";
+
+/// 额度巡检任务的租约名
+pub const USAGE_MONITOR_JOB: &str = "usage_monitor";
+
+/// 租约数据库加锁等待时间（毫秒）
+const BUSY_TIMEOUT_MS: u64 = 2000;
+
+struct LeaseStore {
+    conn: Mutex<Connection>,
+    ttl_secs: i64,
+    holder: String,
+    /// 当前持有的租约，仅用于记录角色变化日志
+    held: Mutex<HashSet<String>>,
+}
+
+/// 后台任务协调器
+pub struct Coordinator {
+    store: Option<LeaseStore>,
+}
+
+impl Coordinator {
+    /// 单实例模式：所有任务都在本实例运行
+    pub fn standalone() -> Self {
+        Self { store: None }
+    }
+
+    /// 按配置打开租约数据库，未启用时返回单实例模式
+    pub fn open(
+        config: Option<&CoordinationConfig>,
+        data_dir: Option<&Path>,
+    ) -> anyhow::Result<Self> {
+        let Some(config) = config.filter(|c| c.enabled) else {
+            return Ok(Self::standalone());
+        };
+        let path = match &config.lease_db {
+            Some(path) => path.into(),
+            None => data_dir.unwrap_or(Path::new(".")).join("leases.db"),
+        };
+        let holder = config.instance_id.clone().unwrap_or_else(|| {
+            let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "kiro-rs".to_string());
+            format!(
+                "{}-{}",
+                host,
+                &uuid::Uuid::new_v4().simple().to_string()[..8]
+            )
+        });
+        tracing::info!(
+            "已启用多实例协调: 实例 {}，租约数据库 {}",
+            holder,
+            path.display()
+        );
+        Self::with_connection(Connection::open(path)?, config.lease_ttl_secs, holder)
+    }
+
+    fn with_connection(conn: Connection, ttl_secs: u64, holder: String) -> anyhow::Result<Self> {
+        conn.busy_timeout(std::time::Duration::from_millis(BUSY_TIMEOUT_MS))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS leases (
+                name TEXT PRIMARY KEY,
+                holder TEXT NOT NULL,
+                expires_at INTEGER NOT NULL
+            );",
+        )?;
+        Ok(Self {
+            store: Some(LeaseStore {
+                conn: Mutex::new(conn),
+                ttl_secs: ttl_secs.max(1) as i64,
+                holder,
+                held: Mutex::new(HashSet::new()),
+            }),
+        })
+    }
+
+    /// 获取或续约任务租约，返回本实例是否应执行该任务
+    ///
+    /// 租约数据库不可用时返回 false（宁可暂停任务，也不让所有实例同时执行）
+    pub fn try_acquire(&self, job: &str) -> bool {
+        let Some(store) = &self.store else {
+            return true;
+        };
+        let acquired = match store.acquire_at(job, Utc::now().timestamp()) {
+            Ok(acquired) => acquired,
+            Err(e) => {
+                tracing::warn!("获取租约 {} 失败: {}", job, e);
+                false
+            }
+        };
+        let mut held = store.held.lock();
+        if acquired && held.insert(job.to_string()) {
+            tracing::info!("本实例（{}）开始执行后台任务 {}", store.holder, job);
+        } else if !acquired && held.remove(job) {
+            tracing::info!("后台任务 {} 已由其他实例接管", job);
+        }
+        acquired
+    }
+}
+
+impl LeaseStore {
+    /// 租约空闲、已过期或已由本实例持有时获取（续约）
+    fn acquire_at(&self, job: &str, now: i64) -> rusqlite::Result<bool> {
+        let changed = self.conn.lock().execute(
+            "INSERT INTO leases (name, holder, expires_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(name) DO UPDATE SET holder = excluded.holder, expires_at = excluded.expires_at
+             WHERE leases.holder = excluded.holder OR leases.expires_at <= ?4",
+            params![job, self.holder, now + self.ttl_secs, now],
+        )?;
+        Ok(changed > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lease_is_exclusive_until_expired() {
+        let path = std::env::temp_dir().join(format!("kiro-leases-{}.db", uuid::Uuid::new_v4()));
+        let open = |holder: &str| {
+            Coordinator::with_connection(Connection::open(&path).unwrap(), 60, holder.to_string())
+                .unwrap()
+                .store
+                .unwrap()
+        };
+        let (a, b) = (open("a"), open("b"));
+
+        assert!(a.acquire_at(BACKUP_JOB, 1000).unwrap());
+        assert!(!b.acquire_at(BACKUP_JOB, 1010).unwrap());
+        // 其他任务的租约互不影响
+        assert!(b.acquire_at(USAGE_MONITOR_JOB, 1010).unwrap());
+        // 持有者续约后过期时间顺延
+        assert!(a.acquire_at(BACKUP_JOB, 1050).unwrap());
+        assert!(!b.acquire_at(BACKUP_JOB, 1100).unwrap());
+        // 持有者停止续约，过期后由其他实例接管
+        assert!(b.acquire_at(BACKUP_JOB, 1110).unwrap());
+        assert!(!a.acquire_at(BACKUP_JOB, 1120).unwrap());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_standalone_always_runs() {
+        let coordinator = Coordinator::open(None, None).unwrap();
+        assert!(coordinator.try_acquire(BACKUP_JOB));
+        assert!(coordinator.try_acquire(USAGE_MONITOR_JOB));
+    }
+}
@@ -0,0 +1,266 @@
+//! 合成负载生成器（`kiro-rs loadtest`）
+//!
+//! 以固定速率向本地或远程实例发送 `/v1/messages` 请求，
+//! 统计状态码分布与延迟分位数，用于上线前的浸泡测试（观察服务端内存是否稳定）。
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use parking_lot::Mutex;
+use serde_json::json;
+use tokio::sync::Semaphore;
+
+use crate::model::arg::{LoadtestArgs, PayloadShape};
+
+/// 进度输出间隔
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 单个请求的结果
+struct Sample {
+    /// HTTP 状态码，请求发送失败时为 None
+    status: Option<u16>,
+    /// 完整耗时（流式请求为读完整个响应体）
+    latency: Duration,
+    /// 首字节耗时
+    ttfb: Duration,
+}
+
+/// 累计统计
+#[derive(Default)]
+struct Stats {
+    samples: Vec<Sample>,
+    /// 因并发上限而跳过的请求数
+    dropped: u64,
+}
+
+/// 按负载形状构造请求体
+fn build_payload(shape: PayloadShape, model: &str, stream: bool, seq: u64) -> serde_json::Value {
+    let mut body = json!({
+        "model": model,
+        "max_tokens": 256,
+        "stream": stream,
+        "messages": [{"role": "user", "content": format!("[loadtest #{}] Reply with one short sentence.", seq)}]
+    });
+    match shape {
+        PayloadShape::Short => {}
+        PayloadShape::Long => {
+            let filler = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(400);
+            body["system"] = json!(format!("Background document:\n{}", filler));
+            body["messages"] = json!([
+                {"role": "user", "content": "Summarize the background document."},
+                {"role": "assistant", "content": "It is placeholder text."},
+                {"role": "user", "content": format!("[loadtest #{}] Now reply with one short sentence.", seq)}
+            ]);
+        }
+        PayloadShape::Tools => {
+            body["tools"] = json!([{
+                "name": "get_weather",
+                "description": "Get the current weather for a city",
+                "input_schema": {
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "required": ["city"]
+                }
+            }]);
+            body["messages"] = json!([{
+                "role": "user",
+                "content": format!("[loadtest #{}] What's the weather in Paris?", seq)
+            }]);
+        }
+        PayloadShape::Thinking => {
+            body["max_tokens"] = json!(2048);
+            body["thinking"] = json!({"type": "enabled", "budget_tokens": 1024});
+        }
+    }
+    body
+}
+
+/// 延迟分位数（`samples` 需已排序）
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((sorted.len() as f64) * p).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// 发送单个请求并读完响应体
+async fn send_one(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: &str,
+    body: &serde_json::Value,
+) -> Sample {
+    let start = Instant::now();
+    let response = match client
+        .post(url)
+        .header("x-api-key", api_key)
+        .json(body)
+        .send()
+        .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            tracing::debug!("请求发送失败: {}", e);
+            let elapsed = start.elapsed();
+            return Sample {
+                status: None,
+                latency: elapsed,
+                ttfb: elapsed,
+            };
+        }
+    };
+    let status = response.status().as_u16();
+    let mut ttfb = None;
+    let mut body_stream = response.bytes_stream();
+    while let Some(chunk) = body_stream.next().await {
+        ttfb.get_or_insert_with(|| start.elapsed());
+        if chunk.is_err() {
+            break;
+        }
+    }
+    let latency = start.elapsed();
+    Sample {
+        status: Some(status),
+        latency,
+        ttfb: ttfb.unwrap_or(latency),
+    }
+}
+
+/// 打印统计摘要
+fn report(label: &str, stats: &Stats, elapsed: Duration) {
+    let mut by_status: BTreeMap<String, usize> = BTreeMap::new();
+    for sample in &stats.samples {
+        let key = sample
+            .status
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "网络错误".to_string());
+        *by_status.entry(key).or_default() += 1;
+    }
+    let mut latencies: Vec<Duration> = stats.samples.iter().map(|s| s.latency).collect();
+    let mut ttfbs: Vec<Duration> = stats.samples.iter().map(|s| s.ttfb).collect();
+    latencies.sort();
+    ttfbs.sort();
+
+    let status_summary = by_status
+        .iter()
+        .map(|(status, count)| format!("{}={}", status, count))
+        .collect::<Vec<_>>()
+        .join(" ");
+    println!(
+        "[{}] {:.0}s 完成 {} 个请求（{:.1} req/s），跳过 {}，状态 {}",
+        label,
+        elapsed.as_secs_f64(),
+        stats.samples.len(),
+        stats.samples.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        stats.dropped,
+        if status_summary.is_empty() {
+            "-".to_string()
+        } else {
+            status_summary
+        }
+    );
+    println!(
+        "[{}] 延迟 p50={:.0?} p90={:.0?} p99={:.0?} max={:.0?}，首字节 p50={:.0?} p99={:.0?}",
+        label,
+        percentile(&latencies, 0.50),
+        percentile(&latencies, 0.90),
+        percentile(&latencies, 0.99),
+        latencies.last().copied().unwrap_or_default(),
+        percentile(&ttfbs, 0.50),
+        percentile(&ttfbs, 0.99),
+    );
+}
+
+/// 运行负载测试
+pub async fn run(args: &LoadtestArgs) {
+    let url = format!("{}/v1/messages", args.url.trim_end_matches('/'));
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(args.timeout_secs))
+        .build()
+        .unwrap_or_else(|e| {
+            eprintln!("创建 HTTP 客户端失败: {}", e);
+            std::process::exit(1);
+        });
+    let rps = args.rps.max(0.01);
+    let duration = Duration::from_secs(args.duration);
+
+    println!(
+        "目标 {}，速率 {} req/s，持续 {}s，负载形状 {:?}，{}，并发上限 {}",
+        url,
+        rps,
+        args.duration,
+        args.shape,
+        if args.stream { "流式" } else { "非流式" },
+        args.concurrency
+    );
+
+    let stats = Arc::new(Mutex::new(Stats::default()));
+    let permits = Arc::new(Semaphore::new(args.concurrency.max(1)));
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / rps));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Burst);
+
+    let start = Instant::now();
+    let mut last_report = start;
+    let mut seq = 0u64;
+    while start.elapsed() < duration {
+        ticker.tick().await;
+        seq += 1;
+
+        let Ok(permit) = permits.clone().try_acquire_owned() else {
+            stats.lock().dropped += 1;
+            continue;
+        };
+        let body = build_payload(args.shape, &args.model, args.stream, seq);
+        let client = client.clone();
+        let url = url.clone();
+        let api_key = args.api_key.clone();
+        let task_stats = stats.clone();
+        tokio::spawn(async move {
+            let sample = send_one(&client, &url, &api_key, &body).await;
+            task_stats.lock().samples.push(sample);
+            drop(permit);
+        });
+
+        if last_report.elapsed() >= REPORT_INTERVAL {
+            last_report = Instant::now();
+            report("进行中", &stats.lock(), start.elapsed());
+        }
+    }
+
+    // 等待在途请求完成
+    let _ = permits.acquire_many(args.concurrency.max(1) as u32).await;
+    report("完成", &stats.lock(), start.elapsed());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anthropic::types::MessagesRequest;
+
+    #[test]
+    fn test_payload_shapes_are_valid_requests() {
+        for shape in [
+            PayloadShape::Short,
+            PayloadShape::Long,
+            PayloadShape::Tools,
+            PayloadShape::Thinking,
+        ] {
+            let body = build_payload(shape, "claude-sonnet-4-6", true, 7);
+            let req: MessagesRequest = serde_json::from_value(body).unwrap();
+            assert!(req.stream);
+            assert_eq!(req.messages.last().unwrap().role, "user");
+        }
+    }
+
+    #[test]
+    fn test_percentile() {
+        let sorted: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&sorted, 0.50), Duration::from_millis(50));
+        assert_eq!(percentile(&sorted, 0.99), Duration::from_millis(99));
+        assert_eq!(percentile(&sorted, 1.0), Duration::from_millis(100));
+        assert_eq!(percentile(&[], 0.5), Duration::ZERO);
+    }
+}
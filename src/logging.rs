@@ -0,0 +1,196 @@
+//! 日志初始化与运行时过滤规则调整
+//!
+//! 运行时调整可指定时长，到期后自动恢复为调整前的规则（期间再次调整会取消上一次的恢复计划）。
+
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use tracing_subscriber::{EnvFilter, Registry, prelude::*, reload};
+
+/// 日志过滤规则的重载句柄
+static FILTER_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// 临时调整的恢复计划
+static REVERT: Mutex<Option<Revert>> = Mutex::new(None);
+
+/// 每次调整递增，用于让过期的恢复任务失效
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// 临时调整到期后恢复的规则
+#[derive(Debug, Clone)]
+pub struct Revert {
+    generation: u64,
+    pub at: DateTime<Utc>,
+    pub filter: String,
+}
+
+/// 默认日志级别
+const DEFAULT_LEVEL: &str = "info";
+
+/// 本程序日志的 target 前缀
+const CRATE_TARGET: &str = "kiro_rs";
+
+/// 本程序的顶层模块，过滤规则中可省略 `kiro_rs::` 前缀（如 `kiro::provider=debug`）
+const CRATE_MODULES: &[&str] = &[
+    "access_log",
+    "admin",
+    "admin_ui",
+    "anthropic",
+    "apikeys",
+    "backup",
+    "batch",
+    "common",
+    "content_filter",
+    "endpoint_metrics",
+    "gemini",
+    "http_client",
+    "kiro",
+    "kiro_oauth_web",
+    "lease",
+    "logging",
+    "model",
+    "openai",
+    "request_log",
+    "token",
+    "upstream",
+];
+
+/// 为省略了 crate 前缀的本程序模块补全 target
+fn expand_directives(directives: &str) -> String {
+    directives
+        .split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(|directive| {
+            let target_end = directive.find(['[', '=']).unwrap_or(directive.len());
+            let root = directive[..target_end]
+                .split("::")
+                .next()
+                .unwrap_or_default();
+            if CRATE_MODULES.contains(&root) {
+                format!("{}::{}", CRATE_TARGET, directive)
+            } else {
+                directive.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// 初始化日志：优先使用 RUST_LOG，其次为配置中的 logLevel，默认 info
+pub fn init(log_level: Option<&str>) {
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(log_level.unwrap_or(DEFAULT_LEVEL)))
+        .unwrap_or_else(|_| EnvFilter::new(DEFAULT_LEVEL));
+    let (filter, handle) = reload::Layer::new(filter);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+    let _ = FILTER_HANDLE.set(handle);
+}
+
+fn parse_filter(directives: &str) -> anyhow::Result<EnvFilter> {
+    EnvFilter::try_new(expand_directives(directives))
+        .map_err(|e| anyhow::anyhow!("无效的日志过滤规则 {}: {}", directives, e))
+}
+
+fn reload_filter(filter: EnvFilter) -> anyhow::Result<()> {
+    let handle = FILTER_HANDLE
+        .get()
+        .ok_or_else(|| anyhow::anyhow!("日志系统未初始化"))?;
+    handle.reload(filter)?;
+    Ok(())
+}
+
+/// 运行时替换日志过滤规则（EnvFilter 语法），并取消尚未执行的恢复计划
+pub fn set_filter(directives: &str) -> anyhow::Result<()> {
+    reload_filter(parse_filter(directives)?)?;
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    *REVERT.lock() = None;
+    Ok(())
+}
+
+/// 临时替换日志过滤规则，`duration` 后恢复为调整前的规则
+///
+/// 连续临时调整时恢复为第一次调整前的规则
+pub fn set_filter_for(directives: &str, duration: Duration) -> anyhow::Result<Revert> {
+    let filter = parse_filter(directives)?;
+    let previous = match REVERT.lock().as_ref() {
+        Some(revert) => Some(revert.filter.clone()),
+        None => current_filter(),
+    }
+    .ok_or_else(|| anyhow::anyhow!("日志系统未初始化"))?;
+    reload_filter(filter)?;
+
+    let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    let revert = Revert {
+        generation,
+        at: Utc::now() + chrono::Duration::from_std(duration)?,
+        filter: previous,
+    };
+    *REVERT.lock() = Some(revert.clone());
+
+    tokio::spawn(async move {
+        tokio::time::sleep(duration).await;
+        let revert = {
+            let mut pending = REVERT.lock();
+            if pending.as_ref().map(|r| r.generation) != Some(generation) {
+                return;
+            }
+            pending.take()
+        };
+        if let Some(revert) = revert {
+            match parse_filter(&revert.filter).and_then(reload_filter) {
+                Ok(()) => tracing::info!("临时日志过滤规则已到期，恢复为: {}", revert.filter),
+                Err(e) => tracing::warn!("恢复日志过滤规则失败: {}", e),
+            }
+        }
+    });
+    Ok(revert)
+}
+
+/// 尚未执行的恢复计划
+pub fn pending_revert() -> Option<Revert> {
+    REVERT.lock().clone()
+}
+
+/// 当前生效的日志过滤规则
+pub fn current_filter() -> Option<String> {
+    FILTER_HANDLE.get()?.with_current(|f| f.to_string()).ok()
+}
+
+/// 配置热重载后应用 logLevel（设置了 RUST_LOG 时以环境变量为准，不做调整）
+pub fn apply_config_level(log_level: Option<&str>) -> anyhow::Result<()> {
+    if std::env::var_os(EnvFilter::DEFAULT_ENV).is_some() {
+        return Ok(());
+    }
+    set_filter(log_level.unwrap_or(DEFAULT_LEVEL))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_crate_module_directives() {
+        assert_eq!(
+            expand_directives("info, kiro::provider=debug,hyper=warn,anthropic[request]=trace"),
+            "info,kiro_rs::kiro::provider=debug,hyper=warn,kiro_rs::anthropic[request]=trace"
+        );
+        assert_eq!(
+            expand_directives("kiro_rs::kiro=debug"),
+            "kiro_rs::kiro=debug"
+        );
+        assert!(EnvFilter::try_new(expand_directives("kiro::provider=debug")).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_temporary_filter_is_not_scheduled() {
+        assert!(set_filter_for("kiro=notalevel", Duration::from_secs(60)).is_err());
+        assert!(pending_revert().is_none());
+    }
+}
@@ -1,14 +1,30 @@
+mod access_log;
 mod admin;
 mod admin_ui;
 mod anthropic;
 mod apikeys;
+mod backup;
+mod batch;
+mod bench;
 mod common;
+mod content_filter;
+mod endpoint_metrics;
+mod gemini;
 mod http_client;
 mod kiro;
 mod kiro_oauth_web;
+mod lease;
+mod loadtest;
+mod logging;
+mod migrate;
 mod model;
+mod openai;
+mod openapi;
+mod pricing;
 pub mod request_log;
 pub mod token;
+mod upstream;
+mod webhook;
 
 use std::path::Path;
 use std::sync::Arc;
@@ -17,24 +33,48 @@ use clap::Parser;
 use kiro::model::credentials::{CredentialsConfig, KiroCredentials};
 use kiro::provider::KiroProvider;
 use kiro::token_manager::MultiTokenManager;
-use model::arg::Args;
-use model::config::Config;
+use model::arg::{Args, Command};
+use model::config::{AdminRole, AdminUserConfig, Config};
+
+// 计数分配器只用于 `bench` 子命令统计内存分配，默认构建不安装
+#[cfg(feature = "bench-alloc")]
+#[global_allocator]
+static ALLOCATOR: bench::CountingAllocator = bench::CountingAllocator;
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
 
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .init();
+    match &args.command {
+        Some(Command::Bench(bench_args)) => {
+            bench::run(bench_args);
+            return;
+        }
+        Some(Command::Loadtest(loadtest_args)) => {
+            loadtest::run(loadtest_args).await;
+            return;
+        }
+        Some(Command::DecryptBackup(decrypt_args)) => {
+            backup::run_decrypt(decrypt_args);
+            return;
+        }
+        Some(Command::Migrate(migrate_args)) => {
+            let credentials_path = args
+                .credentials
+                .as_deref()
+                .unwrap_or(KiroCredentials::default_credentials_path());
+            migrate::run(credentials_path, migrate_args);
+            return;
+        }
+        None => {}
+    }
 
     let config_path = args
         .config
         .unwrap_or_else(|| Config::default_config_path().to_string());
-    let config = Config::load(&config_path).unwrap_or_else(|e| {
+    let config = Config::load(&config_path);
+    logging::init(config.as_ref().ok().and_then(|c| c.log_level.as_deref()));
+    let config = config.unwrap_or_else(|e| {
         tracing::error!("加载配置失败: {}", e);
         std::process::exit(1);
     });
@@ -58,25 +98,70 @@ async fn main() {
         std::process::exit(1);
     });
 
-    let api_key_store = Path::new(&config_path)
-        .parent()
+    let data_dir = Path::new(&config_path).parent().map(Path::to_path_buf);
+    // 使用外部数据库时不备份 api_keys.db
+    let api_key_store = data_dir
+        .as_ref()
+        .filter(|_| config.database_url.is_none())
         .map(|p| p.join("api_keys.db"));
-    let api_keys = Arc::new(apikeys::ApiKeyManager::new(api_key.clone(), api_key_store));
-    let request_log = Arc::new(request_log::RequestLog::new());
-
-    let proxy_config = config.proxy_url.as_ref().map(|url| {
-        let mut proxy = http_client::ProxyConfig::new(url);
-        if let (Some(username), Some(password)) = (&config.proxy_username, &config.proxy_password) {
-            proxy = proxy.with_auth(username, password);
+    let api_keys = Arc::new(match config.database_url.as_deref() {
+        Some(database_url) => apikeys::ApiKeyManager::connect(api_key.clone(), database_url),
+        None => apikeys::ApiKeyManager::new(api_key.clone(), api_key_store.clone()),
+    });
+    let request_log = match data_dir.as_ref().filter(|_| config.request_log_persist) {
+        Some(dir) => {
+            let retention = request_log::RetentionPolicy {
+                days: config.request_log_retention_days,
+                max_entries: config.request_log_max_entries,
+            };
+            request_log::RequestLog::with_store(&dir.join("request_logs.db"), retention)
+                .unwrap_or_else(|e| {
+                    tracing::error!("打开请求日志数据库失败，回退到内存日志: {}", e);
+                    request_log::RequestLog::new()
+                })
         }
-        proxy
+        None => request_log::RequestLog::new(),
+    };
+    let request_log = match data_dir
+        .as_ref()
+        .filter(|_| config.request_log_blob_threshold_bytes > 0)
+    {
+        Some(dir) => request_log.with_blobs(
+            dir.join("request_log_blobs"),
+            config.request_log_blob_threshold_bytes,
+        ),
+        None => request_log,
+    };
+    let content_filter = Arc::new(content_filter::ContentFilter::new());
+    let request_log = Arc::new(request_log.with_content_filter(content_filter.clone()));
+    let batches = match data_dir.as_ref() {
+        Some(dir) => {
+            batch::BatchStore::with_store(&dir.join("batches.db"), config.batch_concurrency)
+                .or_else(|e| {
+                    tracing::error!("打开批处理数据库失败，回退到内存存储: {}", e);
+                    batch::BatchStore::new(config.batch_concurrency)
+                })
+        }
+        None => batch::BatchStore::new(config.batch_concurrency),
+    };
+    let batches = batches.map(Arc::new).unwrap_or_else(|e| {
+        tracing::error!("创建批处理存储失败: {}", e);
+        std::process::exit(1);
     });
+    let endpoint_metrics = Arc::new(endpoint_metrics::EndpointMetrics::new());
+    webhook::init(
+        config.webhook.clone().unwrap_or_default(),
+        config.tls_backend,
+    );
+    pricing::apply(&config.pricing);
+
+    let proxy_config = config.proxy_config();
 
     let token_manager = MultiTokenManager::new(
         config.clone(),
         credentials_list,
         proxy_config.clone(),
-        Some(credentials_path.into()),
+        Some(credentials_path.clone().into()),
         is_multiple_format,
     )
     .unwrap_or_else(|e| {
@@ -84,7 +169,32 @@ async fn main() {
         std::process::exit(1);
     });
     let token_manager = Arc::new(token_manager);
-    let kiro_provider = KiroProvider::with_proxy(token_manager.clone(), proxy_config.clone());
+    if args.import_local_credentials {
+        kiro::discovery::import_all(&token_manager).await;
+    }
+    let backups = Arc::new(backup::BackupManager::new(
+        credentials_path.into(),
+        api_key_store,
+        data_dir
+            .as_deref()
+            .unwrap_or(Path::new("."))
+            .join("backups"),
+    ));
+    let coordinator = lease::Coordinator::open(config.coordination.as_ref(), data_dir.as_deref())
+        .unwrap_or_else(|e| {
+            tracing::error!("打开租约数据库失败: {}", e);
+            std::process::exit(1);
+        });
+    let coordinator = Arc::new(coordinator);
+    backups
+        .clone()
+        .spawn_scheduler(token_manager.clone(), coordinator.clone());
+    token_manager.clone().spawn_usage_monitor(coordinator);
+    token_manager.clone().spawn_token_refresher();
+    let kiro_provider = Arc::new(KiroProvider::with_proxy(
+        token_manager.clone(),
+        proxy_config.clone(),
+    ));
 
     token::init_config(token::CountTokensConfig {
         api_url: config.count_tokens_api_url.clone(),
@@ -94,12 +204,29 @@ async fn main() {
         tls_backend: config.tls_backend,
     });
 
-    let anthropic_app = anthropic::create_router_with_provider(
-        api_keys.clone(),
-        Some(kiro_provider),
-        first_credentials.profile_arn.clone(),
-        Some(request_log.clone()),
-    );
+    let jwt_verifier = config.jwt.clone().map(|jwt_config| {
+        let verifier = common::jwt::JwtVerifier::new(jwt_config).unwrap_or_else(|e| {
+            tracing::error!("JWT 认证配置无效: {}", e);
+            std::process::exit(1);
+        });
+        tracing::info!("已启用 JWT 认证");
+        Arc::new(verifier)
+    });
+
+    if config.chaos.as_ref().is_some_and(|c| c.enabled) {
+        tracing::warn!("已启用故障注入（chaos），请勿在生产环境使用");
+    }
+
+    let anthropic_app = anthropic::create_router_with_provider(anthropic::RouterDeps {
+        api_keys: api_keys.clone(),
+        kiro_provider: Some(kiro_provider.clone()),
+        profile_arn: first_credentials.profile_arn.clone(),
+        request_log: Some(request_log.clone()),
+        endpoint_metrics: Some(endpoint_metrics.clone()),
+        jwt_verifier,
+        batches: Some(batches),
+        content_filter,
+    });
 
     let admin_enabled = config
         .admin_api_key
@@ -110,21 +237,33 @@ async fn main() {
             .admin_password
             .as_ref()
             .map(|p| !p.trim().is_empty())
-            .unwrap_or(false);
+            .unwrap_or(false)
+        || !config.admin_users.is_empty();
 
     let app = if admin_enabled {
-        let admin_service = admin::AdminService::new(token_manager.clone(), api_keys.clone(), Some(request_log.clone()));
+        let admin_service = admin::AdminService::new(token_manager.clone(), api_keys.clone(), Some(request_log.clone()))
+            .with_endpoint_metrics(endpoint_metrics)
+            .with_backups(backups)
+            .with_provider(kiro_provider);
 
-        let admin_username = config
-            .admin_username
-            .clone()
-            .unwrap_or_else(|| "admin".to_string());
-        let admin_password = config
-            .admin_password
-            .clone()
-            .unwrap_or_else(|| "admin".to_string());
+        // adminUsername / adminPassword 对应的账号为 operator；只配置了 adminUsers 时不再启用默认账号
+        let mut admin_users = Vec::new();
+        if config.admin_password.is_some() || config.admin_users.is_empty() {
+            admin_users.push(AdminUserConfig {
+                username: config
+                    .admin_username
+                    .clone()
+                    .unwrap_or_else(|| "admin".to_string()),
+                password: config
+                    .admin_password
+                    .clone()
+                    .unwrap_or_else(|| "admin".to_string()),
+                role: AdminRole::Operator,
+            });
+        }
+        admin_users.extend(config.admin_users.iter().cloned());
 
-        let admin_state = admin::AdminState::new(admin_username, admin_password, admin_service);
+        let admin_state = admin::AdminState::new(admin_users, admin_service);
         let admin_app = admin::create_admin_router(admin_state.clone());
         let admin_ui_app = admin_ui::create_admin_ui_router();
         let oauth_web_app =
@@ -139,9 +278,31 @@ async fn main() {
         anthropic_app
     };
 
+    let access_log = match config.access_log_path.as_deref() {
+        Some(path) => access_log::AccessLog::open(
+            path,
+            config.access_log_format,
+            config.access_log_rotation.as_ref(),
+        )
+        .unwrap_or_else(|e| {
+            tracing::error!("打开访问日志失败: {}: {}", path, e);
+            std::process::exit(1);
+        }),
+        None => access_log::AccessLog::disabled(),
+    };
+    let app = app.layer(axum::middleware::from_fn_with_state(
+        Arc::new(access_log),
+        access_log::request_id_middleware,
+    ));
+
     let addr = format!("{}:{}", config.host, config.port);
     tracing::info!("启动服务: {}", addr);
 
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
@@ -0,0 +1,155 @@
+//! `migrate` 子命令：将旧版凭据文件升级为当前格式
+//!
+//! 单对象格式转换为数组格式，为缺少 ID 的凭据分配 ID，`authMethod` 的 `builder-id` / `iam`
+//! 改为 `idc`，凭据级兼容字段 `region` 改为 `authRegion`。修改前将原文件复制为
+//! `<文件名>.bak-<时间戳>`，并逐条打印变更；`--dry-run` 只打印不写入。
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::kiro::model::credentials::{CredentialsConfig, KiroCredentials};
+use crate::model::arg::MigrateArgs;
+
+/// 单个文件的迁移结果
+struct Migration {
+    content: String,
+    changes: Vec<String>,
+}
+
+/// 迁移凭据文件内容
+fn migrate_credentials(content: &str) -> anyhow::Result<Migration> {
+    let config: CredentialsConfig = serde_json::from_str(content)?;
+    let mut changes = Vec::new();
+    let mut creds = match config {
+        CredentialsConfig::Single(cred) => {
+            changes.push("单凭据对象转换为数组格式".to_string());
+            vec![cred]
+        }
+        CredentialsConfig::Multiple(creds) => creds,
+    };
+
+    let mut next_id = creds.iter().filter_map(|c| c.id).max().unwrap_or(0) + 1;
+    for (index, cred) in creds.iter_mut().enumerate() {
+        let label = match cred.id {
+            Some(id) => format!("凭据 #{}", id),
+            None => {
+                let id = next_id;
+                next_id += 1;
+                cred.id = Some(id);
+                changes.push(format!("第 {} 个凭据: 分配 id {}", index + 1, id));
+                format!("凭据 #{}", id)
+            }
+        };
+        if let Some(method) = cred.auth_method.clone() {
+            cred.canonicalize_auth_method();
+            if cred.auth_method.as_deref() != Some(method.as_str()) {
+                changes.push(format!("{}: authMethod \"{}\" 改为 \"idc\"", label, method));
+            }
+        }
+        migrate_region(cred, &label, &mut changes);
+    }
+
+    Ok(Migration {
+        content: serde_json::to_string_pretty(&creds)?,
+        changes,
+    })
+}
+
+/// 凭据级 `region` 仅用于 Token 刷新，与 `authRegion` 含义相同
+fn migrate_region(cred: &mut KiroCredentials, label: &str, changes: &mut Vec<String>) {
+    let Some(region) = cred.region.take() else {
+        return;
+    };
+    match &cred.auth_region {
+        None => {
+            changes.push(format!("{}: region \"{}\" 改为 authRegion", label, region));
+            cred.auth_region = Some(region);
+        }
+        Some(auth_region) => changes.push(format!(
+            "{}: 删除被 authRegion \"{}\" 覆盖的 region \"{}\"",
+            label, auth_region, region
+        )),
+    }
+}
+
+/// 备份原文件并写入迁移后的内容，返回备份路径
+fn write_with_backup(path: &Path, content: &str) -> anyhow::Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let backup = path.with_file_name(format!(
+        "{}.bak-{}",
+        file_name,
+        chrono::Local::now().format("%Y%m%d%H%M%S")
+    ));
+    fs::copy(path, &backup).with_context(|| format!("备份 {} 失败", path.display()))?;
+    fs::write(path, content).with_context(|| format!("写入 {} 失败", path.display()))?;
+    Ok(backup)
+}
+
+/// 迁移凭据文件并打印变更
+fn migrate_file(path: &Path, dry_run: bool) -> anyhow::Result<()> {
+    if !path.exists() {
+        println!("{}: 文件不存在，跳过", path.display());
+        return Ok(());
+    }
+    let content = fs::read_to_string(path)?;
+    if content.trim().is_empty() {
+        println!("{}: 文件为空，跳过", path.display());
+        return Ok(());
+    }
+    let migration =
+        migrate_credentials(&content).with_context(|| format!("解析 {} 失败", path.display()))?;
+    if migration.changes.is_empty() {
+        println!("{}: 已是当前格式，无需修改", path.display());
+        return Ok(());
+    }
+
+    println!("{}:", path.display());
+    for change in &migration.changes {
+        println!("  - {}", change);
+    }
+    if dry_run {
+        println!("  （--dry-run，未写入）");
+    } else {
+        let backup = write_with_backup(path, &migration.content)?;
+        println!("  已备份原文件到 {}", backup.display());
+    }
+    Ok(())
+}
+
+/// `migrate` 子命令
+pub fn run(credentials_path: &str, args: &MigrateArgs) {
+    if let Err(e) = migrate_file(Path::new(credentials_path), args.dry_run) {
+        eprintln!("迁移失败: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_legacy_credentials() {
+        let legacy = r#"{"refreshToken": "rt", "authMethod": "builder-id", "region": "us-east-2"}"#;
+        let migration = migrate_credentials(legacy).unwrap();
+        assert_eq!(migration.changes.len(), 4);
+        let creds: Vec<KiroCredentials> = serde_json::from_str(&migration.content).unwrap();
+        assert_eq!(creds[0].id, Some(1));
+        assert_eq!(creds[0].auth_method.as_deref(), Some("idc"));
+        assert_eq!(creds[0].auth_region.as_deref(), Some("us-east-2"));
+        assert_eq!(creds[0].region, None);
+
+        // 再次迁移无变更
+        assert!(
+            migrate_credentials(&migration.content)
+                .unwrap()
+                .changes
+                .is_empty()
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
 /// Anthropic <-> Kiro API 客户端
 #[derive(Parser, Debug)]
@@ -11,4 +11,136 @@ pub struct Args {
     /// 凭证文件路径
     #[arg(long)]
     pub credentials: Option<String>,
+
+    /// 启动时扫描本机 Kiro IDE / AWS SSO 令牌缓存（~/.aws/sso/cache、~/.kiro）并导入其中的凭据
+    #[arg(long)]
+    pub import_local_credentials: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// 子命令（不指定时启动服务）
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 进程内基准测试：用合成事件流驱动 解码 → 转换 → SSE 流水线
+    Bench(BenchArgs),
+    /// 合成负载测试：以固定速率向运行中的实例发送 /v1/messages 请求
+    Loadtest(LoadtestArgs),
+    /// 解密备份文件并还原其中的凭据文件与 api_keys.db
+    DecryptBackup(DecryptBackupArgs),
+    /// 将旧版单凭据格式的凭据文件升级为当前的多凭据格式（修改前自动备份）
+    Migrate(MigrateArgs),
+}
+
+/// `bench` 子命令参数
+#[derive(clap::Args, Debug, Clone)]
+pub struct BenchArgs {
+    /// 迭代次数（每次处理一条完整的合成响应）
+    #[arg(long, default_value_t = 200)]
+    pub iterations: usize,
+
+    /// 每条响应的文本事件数
+    #[arg(long, default_value_t = 500)]
+    pub events: usize,
+
+    /// 每条响应的工具调用数
+    #[arg(long, default_value_t = 2)]
+    pub tools: usize,
+
+    /// 每次喂给解码器的字节数（模拟网络分块）
+    #[arg(long, default_value_t = 4096)]
+    pub chunk_size: usize,
+
+    /// 在响应开头加入 thinking 块
+    #[arg(long)]
+    pub thinking: bool,
+
+    /// 改为测量 API Key 认证的单次耗时（内存缓存 vs 每次查询 SQLite），迭代次数为认证次数
+    #[arg(long)]
+    pub auth: bool,
+
+    /// `--auth` 模式下创建的 API Key 数量
+    #[arg(long, default_value_t = 50)]
+    pub keys: usize,
+}
+
+/// `loadtest` 子命令参数
+#[derive(clap::Args, Debug, Clone)]
+pub struct LoadtestArgs {
+    /// 目标实例地址
+    #[arg(long, default_value = "http://127.0.0.1:8990")]
+    pub url: String,
+
+    /// 目标实例的 API Key
+    #[arg(long)]
+    pub api_key: String,
+
+    /// 每秒请求数（可为小数）
+    #[arg(long, default_value_t = 5.0)]
+    pub rps: f64,
+
+    /// 持续时间（秒）
+    #[arg(long, default_value_t = 60)]
+    pub duration: u64,
+
+    /// 请求负载形状
+    #[arg(long, value_enum, default_value_t = PayloadShape::Short)]
+    pub shape: PayloadShape,
+
+    /// 发送流式请求
+    #[arg(long)]
+    pub stream: bool,
+
+    /// 请求的模型
+    #[arg(long, default_value = "claude-sonnet-4-6")]
+    pub model: String,
+
+    /// 最大在途请求数，超出时跳过本次发送
+    #[arg(long, default_value_t = 64)]
+    pub concurrency: usize,
+
+    /// 单个请求超时（秒）
+    #[arg(long, default_value_t = 300)]
+    pub timeout_secs: u64,
+}
+
+/// 负载测试的请求形状
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadShape {
+    /// 单轮短对话
+    Short,
+    /// 长 system prompt + 多轮历史
+    Long,
+    /// 携带工具定义
+    Tools,
+    /// 开启 thinking
+    Thinking,
+}
+
+/// `decrypt-backup` 子命令参数
+#[derive(clap::Args, Debug, Clone)]
+pub struct DecryptBackupArgs {
+    /// 备份文件路径
+    pub file: String,
+
+    /// 备份密码（配置中的 backup.password）
+    #[arg(long)]
+    pub password: String,
+
+    /// 输出目录
+    #[arg(long, default_value = ".")]
+    pub out: String,
+
+    /// 覆盖输出目录中已存在的文件
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// `migrate` 子命令参数（凭据文件路径使用全局的 `--credentials`）
+#[derive(clap::Args, Debug, Clone)]
+pub struct MigrateArgs {
+    /// 只打印将要进行的修改，不写入文件
+    #[arg(long)]
+    pub dry_run: bool,
 }
@@ -1,8 +1,11 @@
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::http_client::ProxyConfig;
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 pub enum TlsBackend {
@@ -92,15 +95,1104 @@ pub struct Config {
     #[serde(default)]
     pub admin_password: Option<String>,
 
+    /// 额外的 Admin 账号（可指定只读角色），adminUsername / adminPassword 对应的账号始终为 operator，修改后需重启
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub admin_users: Vec<AdminUserConfig>,
+
+    /// Admin 登录会话：最长有效期与闲置超时，支持热重载（对已登录的会话同样生效）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_session: Option<AdminSessionConfig>,
+
     /// 璐熻浇鍧囪　妯″紡锛?priority" 鎴?"balanced"锛?
     #[serde(default = "default_load_balancing_mode")]
     pub load_balancing_mode: String,
 
+    /// 灰度流量百分比（0-100），按比例将请求路由到 canary 凭据池，默认 0（关闭）
+    #[serde(default)]
+    pub canary_percentage: u8,
+
+    /// 凭据轮换策略：按周期轮流启用不同的凭据组，分散订阅用量，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<RotationConfig>,
+
+    /// 工具定义序列化后的最大字节数（可选，未配置时不限制）
+    #[serde(default)]
+    pub max_tools_bytes: Option<usize>,
+
+    /// 工具定义超限时的处理策略："reject"（拒绝并列出最大的工具）或 "compress"（压缩描述）
+    #[serde(default = "default_tools_overflow_strategy")]
+    pub tools_overflow_strategy: String,
+
+    /// 是否折叠历史中重复出现的 system / 文本内容（默认开启）
+    #[serde(default = "default_true")]
+    pub dedupe_repeated_text: bool,
+
+    /// 非流式请求心跳间隔（秒），超过该时长仍未完成时改为 chunked 响应并定期写入空白字符，默认 0（关闭）
+    #[serde(default)]
+    pub non_stream_heartbeat_secs: u64,
+
+    /// 请求体大小上限（字节），API Key 可单独覆盖，默认 50 MB，支持热重载
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: u64,
+
+    /// 流式请求首字节超时（秒），超时未收到上游首个事件时换凭据重试，默认 0（关闭）
+    #[serde(default)]
+    pub stream_first_byte_timeout_secs: u64,
+
+    /// 单个凭据同时进行的最大上游请求数（0 表示不限制），已满时溢出到下一个凭据，支持热重载
+    #[serde(default)]
+    pub credential_max_concurrency: usize,
+
+    /// 上游请求超时与重试策略（凭据可用同名字段按项覆盖），支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_policy: Option<RequestPolicy>,
+
+    /// 凭据添加 / 启用时先发送一次预热请求，成功后才加入轮换，支持热重载
+    #[serde(default)]
+    pub credential_warm_up: bool,
+
+    /// 额度使用率（百分比）达到该值的凭据自动禁用，额度重置后重新启用，默认 0（关闭），支持热重载
+    #[serde(default)]
+    pub usage_disable_threshold: f64,
+
+    /// 额度巡检间隔（分钟），默认 30
+    #[serde(default = "default_usage_check_interval_mins")]
+    pub usage_check_interval_mins: u64,
+
+    /// 在 Token 过期前主动刷新，未设置时按默认参数启用，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_refresh: Option<TokenRefreshConfig>,
+
+    /// 是否将请求日志持久化到 SQLite（与 api_keys.db 同目录的 request_logs.db），默认关闭
+    #[serde(default)]
+    pub request_log_persist: bool,
+
+    /// 请求日志是否同时记录转换后发往上游的 Kiro 请求体（用于排查转换问题，日志体积会明显增大），支持热重载
+    #[serde(default)]
+    pub request_log_kiro_request: bool,
+
+    /// 请求日志中超过该字节数的请求/响应体外置为磁盘上的内容寻址文件（`request_log_blobs` 目录），日志只保留引用，默认 0（关闭）
+    #[serde(default)]
+    pub request_log_blob_threshold_bytes: usize,
+
+    /// 持久化日志保留天数（0 表示不按时间清理），默认 7
+    #[serde(default = "default_request_log_retention_days")]
+    pub request_log_retention_days: u32,
+
+    /// 持久化日志最多保留条数（0 表示不限制），默认 100000
+    #[serde(default = "default_request_log_max_entries")]
+    pub request_log_max_entries: usize,
+
+    /// 日志级别过滤（EnvFilter 语法，如 "info" 或 "info,kiro_rs::kiro=debug"），
+    /// 未设置 RUST_LOG 时生效，支持热重载
+    #[serde(default)]
+    pub log_level: Option<String>,
+
+    /// 浏览器临时令牌的最长有效期（秒），未设置时禁用 `POST /v1/browser-tokens`
+    #[serde(default)]
+    pub browser_token_max_ttl_secs: Option<u64>,
+
+    /// API Key 存储使用的外部数据库连接串（`postgres://...`，需启用 `postgres` 特性），
+    /// 多个副本可共享同一份 Key 与用量数据；未设置时使用配置目录下的 `api_keys.db`，修改后需重启
+    #[serde(default)]
+    pub database_url: Option<String>,
+
+    /// 访问日志路径（每个请求一行，`-` 表示标准输出），未设置时不输出，修改后需重启
+    #[serde(default)]
+    pub access_log_path: Option<String>,
+
+    /// 访问日志格式：`json`（默认）或 `combined`（Apache combined 格式 + 扩展字段），修改后需重启
+    #[serde(default)]
+    pub access_log_format: AccessLogFormat,
+
+    /// 访问日志文件按大小轮转，未设置时不轮转，修改后需重启
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_log_rotation: Option<AccessLogRotationConfig>,
+
+    /// 消息批处理（`/v1/messages/batches`）同时向上游发送的最大请求数，默认 4，修改后需重启
+    #[serde(default = "default_batch_concurrency")]
+    pub batch_concurrency: usize,
+
+    /// 模型别名映射：客户端模型名（不区分大小写，可用 `*` 通配）→ Kiro 模型 ID，优先于内置映射规则，支持热重载
+    #[serde(default)]
+    pub model_mappings: BTreeMap<String, String>,
+
+    /// 无法识别的模型名回退到的 Kiro 模型 ID，未设置时返回 400，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_model: Option<String>,
+
+    /// 模型单价：客户端模型名（不区分大小写，`*` 为默认单价）→ 每百万 token 的美元价格，
+    /// 用于按 API Key / 凭据累计费用与月度预算，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub pricing: BTreeMap<String, ModelPrice>,
+
+    /// 各模型的最大输出 token：Kiro 模型 ID（映射后）→ 上限。请求的 max_tokens 会被收紧到该值，
+    /// 超出部分的输出被截断（stop_reason 为 max_tokens），`/v1/models` 同步返回该值，支持热重载
+    #[serde(default)]
+    pub model_max_output_tokens: BTreeMap<String, i32>,
+
+    /// 请求未指定 max_tokens 时使用的值（不超过模型输出上限），默认 32000，支持热重载
+    #[serde(default = "default_max_tokens")]
+    pub default_max_tokens: i32,
+
+    /// max_tokens 超过模型输出上限时的处理策略："clamp"（收紧到上限）或 "reject"（返回 400），支持热重载
+    #[serde(default = "default_max_tokens_overflow_strategy")]
+    pub max_tokens_overflow_strategy: String,
+
+    /// JWT 认证（可选），与静态 API Key 并存，修改后需重启
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwt: Option<JwtConfig>,
+
+    /// 故障注入（仅用于预发环境演练故障转移与重试），未设置或未启用时不生效，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chaos: Option<ChaosConfig>,
+
+    /// 上游响应录制与回放（仅用于测试与调试解析问题），未设置时正常调用上游，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recording: Option<RecordingConfig>,
+
+    /// 非流式请求对冲，未设置或未启用时不对冲，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hedging: Option<HedgingConfig>,
+
+    /// 凭据级代理健康检测：代理连续连接失败时临时改用全局代理或直连，未设置或未启用时不回退，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_fallback: Option<ProxyFallbackConfig>,
+
+    /// 凭据熔断：连续失败后按指数退避冷却，冷却结束后自动放行探测请求，未设置或未启用时沿用禁用 + 全部禁用时自愈，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+
+    /// 流式响应保活（间隔、ping 事件或 SSE 注释行、关闭），未设置时每 25 秒发送 `event: ping`，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heartbeat: Option<HeartbeatConfig>,
+
+    /// 长对话自动压缩：上下文使用率超过阈值时把最早的若干轮总结为摘要，未设置或未启用时不压缩，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compaction: Option<CompactionConfig>,
+
+    /// 额外的 Anthropic 兼容上游：按模型 / API Key 直接路由，或在 Kiro 凭据全部不可用时兜底，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub upstreams: Vec<UpstreamConfig>,
+
+    /// 内容过滤：拒绝或脱敏请求中命中规则的密钥 / PII，并从请求日志中擦除，未设置或未启用时不过滤，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_filter: Option<ContentFilterConfig>,
+
+    /// 非流式响应缓存：相同请求在 TTL 内直接返回缓存的响应，未设置或未启用时不缓存，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_cache: Option<ResponseCacheConfig>,
+
+    /// 非流式请求的 `Idempotency-Key` 去重，未设置时按默认参数启用，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idempotency: Option<IdempotencyConfig>,
+
+    /// 响应消息 ID 格式（前缀与可复现的种子），未设置时为 `msg_` + 随机 ID，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<MessageIdConfig>,
+
+    /// 定时加密备份凭据文件与 api_keys.db，未设置或未启用时不自动备份，支持热重载
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<BackupConfig>,
+
+    /// 多实例协调：共享存储上的租约，保证定时备份、额度巡检等后台任务同一时间只在一个实例上运行，修改后需重启
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coordination: Option<CoordinationConfig>,
+
+    /// API Key 请求完成回调的攒批与重试参数（回调地址按 API Key 设置），修改后需重启
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<WebhookConfig>,
+
     /// 閰嶇疆鏂囦欢璺緞锛堣繍琛屾椂鍏冩暟鎹紝涓嶅啓鍏?JSON锛?
     #[serde(skip)]
     config_path: Option<PathBuf>,
 }
 
+/// JWT 认证配置
+///
+/// `secret`（HS256）与 `jwksUrl`（RS256 / ES256 等）二选一；
+/// 通过校验的令牌按身份 claim 映射到一条 API Key 记录，用量、配额与禁用状态均复用该记录
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct JwtConfig {
+    /// HS256 共享密钥
+    #[serde(default)]
+    pub secret: Option<String>,
+
+    /// JWKS 地址
+    #[serde(default)]
+    pub jwks_url: Option<String>,
+
+    /// 要求的 iss（可选）
+    #[serde(default)]
+    pub issuer: Option<String>,
+
+    /// 要求的 aud（可选）
+    #[serde(default)]
+    pub audience: Option<String>,
+
+    /// 作为身份标识的 claim，默认 "sub"
+    #[serde(default = "default_jwt_subject_claim")]
+    pub subject_claim: String,
+
+    /// 读取每分钟请求数上限的 claim，默认 "rpm_limit"
+    #[serde(default = "default_jwt_rpm_claim")]
+    pub rpm_claim: String,
+
+    /// 读取每分钟 token 数上限的 claim，默认 "tpm_limit"
+    #[serde(default = "default_jwt_tpm_claim")]
+    pub tpm_claim: String,
+}
+
+/// 故障注入配置
+///
+/// 在 Provider 层按概率注入上游延迟、流中断与 429，概率取值 0-1；
+/// 设置 `seed` 后注入序列可复现
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChaosConfig {
+    /// 总开关
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 响应首个数据块前注入延迟的概率
+    #[serde(default)]
+    pub delay_rate: f64,
+
+    /// 注入延迟的范围（毫秒）
+    #[serde(default)]
+    pub delay_min_ms: u64,
+    #[serde(default)]
+    pub delay_max_ms: u64,
+
+    /// 不发送请求、直接返回模拟 429 的概率
+    #[serde(default)]
+    pub throttle_rate: f64,
+
+    /// 响应体传输中途断开的概率
+    #[serde(default)]
+    pub drop_rate: f64,
+
+    /// 断开前已传输的数据块数
+    #[serde(default)]
+    pub drop_after_chunks: u32,
+
+    /// 随机种子
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+/// 上游响应录制与回放配置
+///
+/// `record` 模式把每个成功响应的原始 event-stream 字节写入 `dir`；`replay` 模式不调用上游、
+/// 不占用凭据，按请求指纹从 `dir` 读取录制的响应
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingConfig {
+    pub mode: RecordingMode,
+
+    /// 录制文件目录
+    pub dir: String,
+}
+
+/// 录制 / 回放模式
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingMode {
+    /// 正常调用上游并录制响应
+    Record,
+    /// 回放录制的响应
+    Replay,
+}
+
+/// 非流式请求对冲配置
+///
+/// 请求耗时超过近期 p95（不低于 `min_delay_ms`）时在另一个凭据上发起相同请求，
+/// 取先完成的结果；对冲次数不超过请求数的 `budget_percent`%
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HedgingConfig {
+    /// 总开关
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 发起对冲前的最短等待（毫秒）
+    #[serde(default = "default_hedging_min_delay_ms")]
+    pub min_delay_ms: u64,
+
+    /// 对冲预算（占非流式请求数的百分比）
+    #[serde(default = "default_hedging_budget_percent")]
+    pub budget_percent: f64,
+}
+
+fn default_hedging_min_delay_ms() -> u64 {
+    1000
+}
+
+fn default_hedging_budget_percent() -> f64 {
+    10.0
+}
+
+/// 访问日志格式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// 每行一个 JSON 对象
+    #[default]
+    Json,
+    /// Apache combined 格式，末尾追加 `key=value` 扩展字段
+    Combined,
+}
+
+/// 访问日志轮转配置
+///
+/// 当前文件超过 `max_size_mb` 时依次重命名为 `<path>.1`、`<path>.2` …，最多保留 `max_files` 个历史文件
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessLogRotationConfig {
+    /// 单个文件的大小上限（MB）
+    #[serde(default = "default_access_log_max_size_mb")]
+    pub max_size_mb: u64,
+
+    /// 保留的历史文件数（0 表示轮转时直接丢弃旧内容）
+    #[serde(default = "default_access_log_max_files")]
+    pub max_files: usize,
+}
+
+fn default_access_log_max_size_mb() -> u64 {
+    100
+}
+
+fn default_access_log_max_files() -> usize {
+    5
+}
+
+/// 代理回退目标
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyFallbackTarget {
+    /// 全局代理（未配置全局代理时直连）
+    #[default]
+    Global,
+    /// 直连
+    Direct,
+}
+
+/// 凭据级代理回退配置
+///
+/// 凭据自身代理连续 `failure_threshold` 次连接失败后，在 `recovery_secs` 内改走 `target`；
+/// 到期后重新尝试凭据代理，再失败一次即继续回退
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyFallbackConfig {
+    /// 总开关
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 触发回退的连续连接失败次数
+    #[serde(default = "default_proxy_failure_threshold")]
+    pub failure_threshold: u32,
+
+    /// 回退持续时间（秒）
+    #[serde(default = "default_proxy_recovery_secs")]
+    pub recovery_secs: u64,
+
+    /// 回退目标
+    #[serde(default)]
+    pub target: ProxyFallbackTarget,
+}
+
+fn default_proxy_failure_threshold() -> u32 {
+    3
+}
+
+fn default_proxy_recovery_secs() -> u64 {
+    300
+}
+
+/// 模型单价（美元 / 百万 token）
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPrice {
+    /// 输入 token 单价
+    pub input_per_mtok: f64,
+    /// 输出 token 单价
+    pub output_per_mtok: f64,
+}
+
+/// 凭据熔断配置
+///
+/// 凭据连续 `failure_threshold` 次 API 调用失败后熔断，冷却 `base_cooldown_secs`，
+/// 之后每次连续熔断冷却时长翻倍，最长 `max_cooldown_secs`。冷却结束后凭据重新参与选择（探测），
+/// 探测失败一次即再次熔断，成功则清零熔断次数
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitBreakerConfig {
+    /// 总开关
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 触发熔断的连续失败次数
+    #[serde(default = "default_circuit_failure_threshold")]
+    pub failure_threshold: u32,
+
+    /// 首次熔断的冷却时长（秒）
+    #[serde(default = "default_circuit_base_cooldown_secs")]
+    pub base_cooldown_secs: u64,
+
+    /// 冷却时长上限（秒）
+    #[serde(default = "default_circuit_max_cooldown_secs")]
+    pub max_cooldown_secs: u64,
+}
+
+fn default_circuit_failure_threshold() -> u32 {
+    3
+}
+
+fn default_circuit_base_cooldown_secs() -> u64 {
+    30
+}
+
+fn default_circuit_max_cooldown_secs() -> u64 {
+    1800
+}
+
+impl CircuitBreakerConfig {
+    /// 第 `trips` 次连续熔断的冷却时长（秒）
+    pub fn cooldown_secs(&self, trips: u32) -> u64 {
+        let factor = 1u64 << trips.saturating_sub(1).min(32);
+        self.base_cooldown_secs
+            .saturating_mul(factor)
+            .min(self.max_cooldown_secs.max(self.base_cooldown_secs))
+    }
+}
+
+/// 流式响应保活配置
+///
+/// 上游空闲超过 `interval_secs` 时向客户端写入保活数据，有内容下发时重新计时
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct HeartbeatConfig {
+    /// 总开关，关闭后不发送任何保活数据
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 保活间隔（秒），0 等同于关闭
+    #[serde(default = "default_heartbeat_interval_secs")]
+    pub interval_secs: u64,
+
+    /// Anthropic 端点的保活形式（OpenAI / Gemini 端点始终使用注释行）
+    #[serde(default)]
+    pub style: HeartbeatStyle,
+}
+
+fn default_heartbeat_interval_secs() -> u64 {
+    25
+}
+
+/// 保活形式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HeartbeatStyle {
+    /// Anthropic 风格的 `event: ping` 事件
+    #[default]
+    Event,
+    /// SSE 注释行 `: keep-alive`，兼容不认识 ping 事件的严格客户端
+    Comment,
+}
+
+/// Anthropic 兼容上游配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct UpstreamConfig {
+    /// 名称（用于日志与 token 来源标记）
+    pub name: String,
+
+    /// 基础地址，请求发往 `{baseUrl}/v1/messages`
+    #[serde(default = "default_upstream_base_url")]
+    pub base_url: String,
+
+    /// 以 `x-api-key` 发送的 API Key
+    pub api_key: String,
+
+    /// 直接路由到该上游的模型（包含匹配，不区分大小写）
+    #[serde(default)]
+    pub models: Vec<String>,
+
+    /// 直接路由到该上游的 API Key ID
+    #[serde(default)]
+    pub api_key_ids: Vec<String>,
+
+    /// Kiro 凭据全部不可用（529）时是否作为兜底
+    #[serde(default)]
+    pub fallback: bool,
+}
+
+fn default_upstream_base_url() -> String {
+    "https://api.anthropic.com".to_string()
+}
+
+/// Admin 账号角色
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminRole {
+    /// 只读：只能查看，不能修改配置、凭据或导出凭据
+    #[default]
+    Viewer,
+    /// 完全权限
+    Operator,
+}
+
+/// Admin 账号
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminUserConfig {
+    pub username: String,
+    pub password: String,
+    /// 未指定时为 viewer
+    #[serde(default)]
+    pub role: AdminRole,
+}
+
+/// Admin 会话配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminSessionConfig {
+    /// 会话自登录起的最长有效期（小时）
+    #[serde(default = "default_admin_session_ttl_hours")]
+    pub ttl_hours: u64,
+
+    /// 闲置超时（分钟），超过该时长没有请求时会话失效，0 表示不限制
+    #[serde(default)]
+    pub idle_timeout_minutes: u64,
+}
+
+fn default_admin_session_ttl_hours() -> u64 {
+    24
+}
+
+impl Default for AdminSessionConfig {
+    fn default() -> Self {
+        Self {
+            ttl_hours: default_admin_session_ttl_hours(),
+            idle_timeout_minutes: 0,
+        }
+    }
+}
+
+/// 凭据轮换策略
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RotationConfig {
+    /// 总开关
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 每组凭据连续生效的时长（小时），按 UTC 对齐
+    #[serde(default = "default_rotation_period_hours")]
+    pub period_hours: u64,
+
+    /// 依次轮换的凭据组（凭据 ID），不在任何组中的凭据始终可用
+    #[serde(default)]
+    pub groups: Vec<Vec<u64>>,
+}
+
+fn default_rotation_period_hours() -> u64 {
+    24
+}
+
+/// 主动刷新 Token 配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenRefreshConfig {
+    /// 总开关，关闭后只在请求时刷新即将过期的 Token
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 在过期前多久刷新（秒）
+    #[serde(default = "default_token_refresh_margin_secs")]
+    pub margin_secs: u64,
+
+    /// 随机提前的最大秒数，避免多个凭据同时刷新
+    #[serde(default = "default_token_refresh_jitter_secs")]
+    pub jitter_secs: u64,
+}
+
+impl Default for TokenRefreshConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            margin_secs: default_token_refresh_margin_secs(),
+            jitter_secs: default_token_refresh_jitter_secs(),
+        }
+    }
+}
+
+fn default_token_refresh_margin_secs() -> u64 {
+    900
+}
+
+fn default_token_refresh_jitter_secs() -> u64 {
+    300
+}
+
+/// `Idempotency-Key` 去重配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct IdempotencyConfig {
+    /// 总开关
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// 响应保留时长（秒），超过后相同的 Idempotency-Key 会重新请求上游
+    #[serde(default = "default_idempotency_ttl_secs")]
+    pub ttl_secs: u64,
+
+    /// 最多保留的响应数，超出时淘汰最早写入的条目
+    #[serde(default = "default_idempotency_max_entries")]
+    pub max_entries: usize,
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_secs: default_idempotency_ttl_secs(),
+            max_entries: default_idempotency_max_entries(),
+        }
+    }
+}
+
+fn default_idempotency_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_idempotency_max_entries() -> usize {
+    1000
+}
+
+/// 非流式响应缓存配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseCacheConfig {
+    /// 总开关
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 缓存有效期（秒）
+    #[serde(default = "default_response_cache_ttl_secs")]
+    pub ttl_secs: u64,
+
+    /// 最多缓存的响应数，超出时淘汰最早写入的条目
+    #[serde(default = "default_response_cache_max_entries")]
+    pub max_entries: usize,
+
+    /// 单条响应体大小上限（字节），超过时不缓存
+    #[serde(default = "default_response_cache_max_entry_bytes")]
+    pub max_entry_bytes: usize,
+}
+
+fn default_response_cache_ttl_secs() -> u64 {
+    300
+}
+
+fn default_response_cache_max_entries() -> usize {
+    1000
+}
+
+fn default_response_cache_max_entry_bytes() -> usize {
+    1024 * 1024
+}
+
+/// 响应消息 ID 配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageIdConfig {
+    /// ID 前缀
+    #[serde(default = "default_message_id_prefix")]
+    pub prefix: String,
+
+    /// 随机种子，设置后按序号生成可复现的 ID（用于测试）
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+fn default_message_id_prefix() -> String {
+    "msg_".to_string()
+}
+
+/// 内容过滤配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentFilterConfig {
+    /// 总开关
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 自定义规则，按顺序匹配
+    #[serde(default)]
+    pub rules: Vec<ContentFilterRule>,
+
+    /// 启用内置的常见密钥规则（AWS / Anthropic / OpenAI / GitHub Key、私钥），命中时脱敏
+    #[serde(default)]
+    pub builtin_secrets: bool,
+
+    /// 出现即拒绝请求的词（字面量，不区分大小写）
+    #[serde(default)]
+    pub deny_list: Vec<String>,
+
+    /// 写入请求日志前是否用同一组规则擦除请求 / 响应体
+    #[serde(default = "default_true")]
+    pub scrub_logs: bool,
+}
+
+/// 内容过滤规则
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentFilterRule {
+    /// 规则名称（出现在拒绝响应与日志中）
+    pub name: String,
+
+    /// 正则表达式
+    pub pattern: String,
+
+    /// 命中后的处理方式
+    #[serde(default)]
+    pub action: ContentFilterAction,
+
+    /// 脱敏时的替换文本
+    #[serde(default = "default_redaction")]
+    pub replacement: String,
+}
+
+/// 内容过滤规则命中后的处理方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentFilterAction {
+    /// 替换命中的文本后继续转发
+    #[default]
+    Redact,
+    /// 拒绝整个请求
+    Reject,
+}
+
+fn default_redaction() -> String {
+    "[REDACTED]".to_string()
+}
+
+/// 对话历史压缩配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompactionConfig {
+    /// 总开关
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 触发压缩的估算上下文使用率（百分比），可被 API Key 的设置覆盖
+    #[serde(default = "default_compaction_threshold_percent")]
+    pub threshold_percent: f64,
+
+    /// 生成摘要使用的模型
+    #[serde(default = "default_compaction_model")]
+    pub model: String,
+
+    /// 保留原样不压缩的最近消息数
+    #[serde(default = "default_compaction_keep_recent_messages")]
+    pub keep_recent_messages: usize,
+}
+
+fn default_compaction_threshold_percent() -> f64 {
+    80.0
+}
+
+fn default_compaction_model() -> String {
+    "claude-haiku-4.5".to_string()
+}
+
+fn default_compaction_keep_recent_messages() -> usize {
+    6
+}
+
+/// 定时备份配置
+///
+/// 备份文件以 `password` 派生的密钥做 AES-256-GCM 加密，
+/// 可用 `kiro-rs decrypt-backup` 解密还原
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupConfig {
+    /// 是否启用定时备份（未启用时仍可通过 Admin API 手动备份）
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 备份目录，未设置时为配置文件所在目录下的 `backups`
+    #[serde(default)]
+    pub dir: Option<String>,
+
+    /// 备份间隔（小时），默认 24
+    #[serde(default = "default_backup_interval_hours")]
+    pub interval_hours: u64,
+
+    /// 保留的备份份数（超出时删除最旧的），默认 7
+    #[serde(default = "default_backup_keep")]
+    pub keep: usize,
+
+    /// 加密密码
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: None,
+            interval_hours: default_backup_interval_hours(),
+            keep: default_backup_keep(),
+            password: None,
+        }
+    }
+}
+
+/// API Key 请求完成回调配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookConfig {
+    /// 每次发送的最大事件数，默认 1（每个请求发送一次）
+    #[serde(default = "default_webhook_batch_size")]
+    pub batch_size: usize,
+
+    /// 未攒满一批时最长等待时间（毫秒），默认 1000
+    #[serde(default = "default_webhook_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+
+    /// 单次发送超时（秒），默认 10
+    #[serde(default = "default_webhook_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// 发送失败后的重试次数，默认 3
+    #[serde(default = "default_webhook_max_retries")]
+    pub max_retries: u32,
+}
+
+fn default_webhook_batch_size() -> usize {
+    1
+}
+
+fn default_webhook_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_webhook_timeout_secs() -> u64 {
+    10
+}
+
+fn default_webhook_max_retries() -> u32 {
+    3
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: default_webhook_batch_size(),
+            flush_interval_ms: default_webhook_flush_interval_ms(),
+            timeout_secs: default_webhook_timeout_secs(),
+            max_retries: default_webhook_max_retries(),
+        }
+    }
+}
+
+/// 重试退避策略
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackoffStrategy {
+    /// 指数退避（基准间隔逐次翻倍，不超过最大间隔）
+    Exponential,
+    /// 固定间隔
+    Fixed,
+    /// 立即重试
+    None,
+}
+
+/// 上游请求失败的类别，用于 [`RequestPolicy::failover_on`]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FailoverClass {
+    /// 请求发送失败、流式首字节超时
+    Network,
+    /// 429 等上游限流
+    Throttled,
+    /// 408 与 5xx
+    Server,
+    /// 401 / 403
+    Auth,
+    /// 402 额度用尽
+    Quota,
+    /// 400 等其他 4xx，通常为请求本身的问题
+    Client,
+}
+
+impl FailoverClass {
+    /// 未配置 `failoverOn` 时重试的错误类别
+    pub const DEFAULT: [FailoverClass; 5] = [
+        Self::Network,
+        Self::Throttled,
+        Self::Server,
+        Self::Auth,
+        Self::Quota,
+    ];
+}
+
+/// 上游请求超时与重试策略
+///
+/// config.json 中为全局默认值，凭据中的 `requestPolicy` 按字段覆盖；都未设置的字段使用内置默认值
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestPolicy {
+    /// 建立连接的超时（秒），默认不单独限制
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_secs: Option<u64>,
+
+    /// 读取超时（秒，两次收到数据之间的最长间隔），默认不单独限制
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_timeout_secs: Option<u64>,
+
+    /// 单次请求总超时（秒，流式请求包含读完整个响应），默认 720
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+
+    /// 每个凭据的最大尝试次数，默认 3
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_retries_per_credential: Option<usize>,
+
+    /// 单个请求的总尝试次数上限，默认 9（仅在 config.json 中生效）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_total_retries: Option<usize>,
+
+    /// 单个请求最多尝试的不同凭据数，默认不限制（仅在 config.json 中生效）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_credentials_per_request: Option<usize>,
+
+    /// 单个请求重试与故障转移的总耗时预算（秒），超出后不再重试，默认不限制（仅在 config.json 中生效）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_budget_secs: Option<u64>,
+
+    /// 触发重试或换凭据的错误类别，其他错误直接返回，默认除 `client` 外的全部类别（仅在 config.json 中生效）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failover_on: Option<Vec<FailoverClass>>,
+
+    /// 退避策略，默认 exponential
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff: Option<BackoffStrategy>,
+
+    /// 退避基准间隔（毫秒），默认 200
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff_base_ms: Option<u64>,
+
+    /// 退避最大间隔（毫秒），默认 2000
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff_max_ms: Option<u64>,
+}
+
+impl RequestPolicy {
+    /// 以 `self` 中已设置的字段覆盖 `fallback`
+    pub fn merged_over(&self, fallback: Option<&RequestPolicy>) -> RequestPolicy {
+        let fallback = fallback.cloned().unwrap_or_default();
+        RequestPolicy {
+            connect_timeout_secs: self.connect_timeout_secs.or(fallback.connect_timeout_secs),
+            read_timeout_secs: self.read_timeout_secs.or(fallback.read_timeout_secs),
+            timeout_secs: self.timeout_secs.or(fallback.timeout_secs),
+            max_retries_per_credential: self
+                .max_retries_per_credential
+                .or(fallback.max_retries_per_credential),
+            max_total_retries: self.max_total_retries.or(fallback.max_total_retries),
+            max_credentials_per_request: self
+                .max_credentials_per_request
+                .or(fallback.max_credentials_per_request),
+            retry_budget_secs: self.retry_budget_secs.or(fallback.retry_budget_secs),
+            failover_on: self.failover_on.clone().or(fallback.failover_on),
+            backoff: self.backoff.or(fallback.backoff),
+            backoff_base_ms: self.backoff_base_ms.or(fallback.backoff_base_ms),
+            backoff_max_ms: self.backoff_max_ms.or(fallback.backoff_max_ms),
+        }
+    }
+}
+
+/// 多实例协调配置
+///
+/// 多个副本共享同一个租约数据库（如挂载在共享卷上的 SQLite 文件），
+/// 后台任务每次运行前先获取对应租约，持有者定期续约，宕机后租约过期由其他实例接管
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CoordinationConfig {
+    /// 是否启用
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// 租约数据库路径，未设置时为配置文件所在目录下的 `leases.db`
+    #[serde(default)]
+    pub lease_db: Option<String>,
+
+    /// 租约有效期（秒），默认 180，应大于后台任务的检查间隔（60 秒）
+    #[serde(default = "default_lease_ttl_secs")]
+    pub lease_ttl_secs: u64,
+
+    /// 实例标识，未设置时使用 `HOSTNAME` 环境变量加随机后缀
+    #[serde(default)]
+    pub instance_id: Option<String>,
+}
+
+fn default_lease_ttl_secs() -> u64 {
+    180
+}
+
+fn default_usage_check_interval_mins() -> u64 {
+    30
+}
+
+fn default_backup_interval_hours() -> u64 {
+    24
+}
+
+fn default_backup_keep() -> usize {
+    7
+}
+
+fn default_jwt_subject_claim() -> String {
+    "sub".to_string()
+}
+
+fn default_jwt_rpm_claim() -> String {
+    "rpm_limit".to_string()
+}
+
+fn default_jwt_tpm_claim() -> String {
+    "tpm_limit".to_string()
+}
+
 fn default_host() -> String {
     "127.0.0.1".to_string()
 }
@@ -138,6 +1230,38 @@ fn default_load_balancing_mode() -> String {
     "priority".to_string()
 }
 
+fn default_true() -> bool {
+    true
+}
+
+fn default_request_log_retention_days() -> u32 {
+    7
+}
+
+fn default_request_log_max_entries() -> usize {
+    100_000
+}
+
+fn default_batch_concurrency() -> usize {
+    4
+}
+
+fn default_tools_overflow_strategy() -> String {
+    "reject".to_string()
+}
+
+fn default_max_tokens() -> i32 {
+    32000
+}
+
+fn default_max_tokens_overflow_strategy() -> String {
+    "clamp".to_string()
+}
+
+fn default_max_body_bytes() -> u64 {
+    50 * 1024 * 1024
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -161,7 +1285,57 @@ impl Default for Config {
             admin_api_key: None,
             admin_username: None,
             admin_password: None,
+            admin_users: Vec::new(),
+            admin_session: None,
             load_balancing_mode: default_load_balancing_mode(),
+            canary_percentage: 0,
+            rotation: None,
+            max_tools_bytes: None,
+            tools_overflow_strategy: default_tools_overflow_strategy(),
+            dedupe_repeated_text: true,
+            non_stream_heartbeat_secs: 0,
+            max_body_bytes: default_max_body_bytes(),
+            stream_first_byte_timeout_secs: 0,
+            credential_max_concurrency: 0,
+            request_policy: None,
+            credential_warm_up: false,
+            usage_disable_threshold: 0.0,
+            usage_check_interval_mins: default_usage_check_interval_mins(),
+            token_refresh: None,
+            request_log_persist: false,
+            request_log_kiro_request: false,
+            request_log_blob_threshold_bytes: 0,
+            request_log_retention_days: default_request_log_retention_days(),
+            request_log_max_entries: default_request_log_max_entries(),
+            log_level: None,
+            browser_token_max_ttl_secs: None,
+            database_url: None,
+            access_log_path: None,
+            access_log_format: AccessLogFormat::default(),
+            access_log_rotation: None,
+            batch_concurrency: default_batch_concurrency(),
+            model_mappings: BTreeMap::new(),
+            fallback_model: None,
+            pricing: BTreeMap::new(),
+            model_max_output_tokens: BTreeMap::new(),
+            default_max_tokens: default_max_tokens(),
+            max_tokens_overflow_strategy: default_max_tokens_overflow_strategy(),
+            jwt: None,
+            chaos: None,
+            recording: None,
+            hedging: None,
+            proxy_fallback: None,
+            circuit_breaker: None,
+            heartbeat: None,
+            compaction: None,
+            upstreams: Vec::new(),
+            content_filter: None,
+            response_cache: None,
+            idempotency: None,
+            message_id: None,
+            backup: None,
+            coordination: None,
+            webhook: None,
             config_path: None,
         }
     }
@@ -197,6 +1371,10 @@ impl Config {
 
         let content = fs::read_to_string(path)?;
         let mut config: Config = serde_json::from_str(&content)?;
+        let strategy = &config.tools_overflow_strategy;
+        if !strategy.eq_ignore_ascii_case("reject") && !strategy.eq_ignore_ascii_case("compress") {
+            anyhow::bail!("无效的工具超限策略: {}", strategy);
+        }
         config.config_path = Some(path.to_path_buf());
         Ok(config)
     }
@@ -205,6 +1383,60 @@ impl Config {
     pub fn config_path(&self) -> Option<&Path> {
         self.config_path.as_deref()
     }
+    /// 根据 proxy_url / proxy_username / proxy_password 构建全局代理配置
+    pub fn proxy_config(&self) -> Option<ProxyConfig> {
+        self.proxy_url.as_ref().map(|url| {
+            let mut proxy = ProxyConfig::new(url);
+            if let (Some(username), Some(password)) = (&self.proxy_username, &self.proxy_password) {
+                proxy = proxy.with_auth(username, password);
+            }
+            proxy
+        })
+    }
+
+    /// 热重载时保留只在启动时生效的字段，返回配置文件中已修改、需重启才能生效的字段名
+    ///
+    /// system_version 未配置时为随机值，同样沿用当前值以免请求指纹漂移
+    pub fn retain_startup_fields(&mut self, current: &Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        macro_rules! retain {
+            ($($field:ident => $name:literal),* $(,)?) => {
+                $(
+                    if self.$field != current.$field {
+                        changed.push($name);
+                        self.$field = current.$field.clone();
+                    }
+                )*
+            };
+        }
+        retain!(
+            host => "host",
+            port => "port",
+            api_key => "apiKey",
+            admin_api_key => "adminApiKey",
+            admin_username => "adminUsername",
+            admin_password => "adminPassword",
+            admin_users => "adminUsers",
+            tls_backend => "tlsBackend",
+            count_tokens_api_url => "countTokensApiUrl",
+            count_tokens_api_key => "countTokensApiKey",
+            count_tokens_auth_type => "countTokensAuthType",
+            request_log_persist => "requestLogPersist",
+            request_log_retention_days => "requestLogRetentionDays",
+            request_log_max_entries => "requestLogMaxEntries",
+            request_log_blob_threshold_bytes => "requestLogBlobThresholdBytes",
+            database_url => "databaseUrl",
+            access_log_path => "accessLogPath",
+            access_log_format => "accessLogFormat",
+            access_log_rotation => "accessLogRotation",
+            batch_concurrency => "batchConcurrency",
+            jwt => "jwt",
+            webhook => "webhook",
+            coordination => "coordination",
+        );
+        self.system_version = current.system_version.clone();
+        changed
+    }
 
     /// 灏嗗綋鍓嶉厤缃啓鍥炲師濮嬮厤缃枃浠?
     pub fn save(&self) -> anyhow::Result<()> {
@@ -0,0 +1,349 @@
+//! OpenAI → Anthropic 请求转换
+//!
+//! 将 Chat Completions 请求转换为 Anthropic `MessagesRequest`，
+//! 之后复用 Anthropic → Kiro 的转换流程。
+
+use std::collections::HashMap;
+
+use serde_json::json;
+
+use crate::anthropic::types::{Message, MessagesRequest, Metadata, SystemMessage, Tool};
+
+use super::types::{ChatCompletionRequest, ChatMessage, ChatTool};
+
+/// 请求转换错误
+#[derive(Debug)]
+pub enum ConversionError {
+    /// 不支持的消息角色
+    UnsupportedRole(String),
+    /// 图片只支持 data URL（base64）形式
+    UnsupportedImageUrl,
+}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversionError::UnsupportedRole(role) => write!(f, "不支持的消息角色: {}", role),
+            ConversionError::UnsupportedImageUrl => {
+                write!(f, "图片仅支持 data URL（data:<media_type>;base64,...）")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// 将 OpenAI Chat Completions 请求转换为 Anthropic Messages 请求
+pub fn to_messages_request(
+    req: &ChatCompletionRequest,
+) -> Result<MessagesRequest, ConversionError> {
+    let mut system = Vec::new();
+    let mut messages: Vec<Message> = Vec::new();
+
+    for msg in &req.messages {
+        match msg.role.as_str() {
+            "system" | "developer" => {
+                let text = content_to_text(msg.content.as_ref());
+                if !text.is_empty() {
+                    system.push(SystemMessage {
+                        text,
+                        cache_control: None,
+                    });
+                }
+            }
+            "user" => messages.push(Message {
+                role: "user".to_string(),
+                content: convert_user_content(msg.content.as_ref())?,
+            }),
+            "assistant" => messages.push(Message {
+                role: "assistant".to_string(),
+                content: convert_assistant_content(msg),
+            }),
+            "tool" => push_tool_result(&mut messages, msg),
+            other => return Err(ConversionError::UnsupportedRole(other.to_string())),
+        }
+    }
+
+    Ok(MessagesRequest {
+        model: req.model.clone(),
+        // 未指定时为 0，由 clamp_max_tokens 按 defaultMaxTokens 补全
+        max_tokens: req.max_completion_tokens.or(req.max_tokens).unwrap_or(0),
+        messages,
+        stream: req.stream,
+        system: (!system.is_empty()).then_some(system),
+        tools: req.tools.as_ref().map(|tools| convert_tools(tools)),
+        tool_choice: req.tool_choice.as_ref().and_then(convert_tool_choice),
+        thinking: None,
+        output_config: None,
+        metadata: req.user.clone().map(|user_id| Metadata {
+            user_id: Some(user_id),
+        }),
+    })
+}
+
+/// 提取 content 中的纯文本（string 或 text part 数组）
+fn content_to_text(content: Option<&serde_json::Value>) -> String {
+    match content {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// 转换 user 消息内容（text / image_url part → Anthropic content block）
+fn convert_user_content(
+    content: Option<&serde_json::Value>,
+) -> Result<serde_json::Value, ConversionError> {
+    let Some(serde_json::Value::Array(parts)) = content else {
+        return Ok(json!(content_to_text(content)));
+    };
+
+    let mut blocks = Vec::new();
+    for part in parts {
+        match part.get("type").and_then(|t| t.as_str()) {
+            Some("text") => {
+                let text = part.get("text").and_then(|t| t.as_str()).unwrap_or("");
+                blocks.push(json!({"type": "text", "text": text}));
+            }
+            Some("image_url") => {
+                let url = part
+                    .get("image_url")
+                    .and_then(|i| i.get("url"))
+                    .and_then(|u| u.as_str())
+                    .unwrap_or("");
+                let (media_type, data) =
+                    parse_data_url(url).ok_or(ConversionError::UnsupportedImageUrl)?;
+                blocks.push(json!({
+                    "type": "image",
+                    "source": {"type": "base64", "media_type": media_type, "data": data}
+                }));
+            }
+            _ => {}
+        }
+    }
+    Ok(serde_json::Value::Array(blocks))
+}
+
+/// 解析 `data:<media_type>;base64,<data>` 形式的图片 URL
+fn parse_data_url(url: &str) -> Option<(&str, &str)> {
+    let rest = url.strip_prefix("data:")?;
+    let (meta, data) = rest.split_once(',')?;
+    let media_type = meta.strip_suffix(";base64")?;
+    Some((media_type, data))
+}
+
+/// 转换 assistant 消息内容（文本 + tool_calls → tool_use block）
+fn convert_assistant_content(msg: &ChatMessage) -> serde_json::Value {
+    let text = content_to_text(msg.content.as_ref());
+    let Some(tool_calls) = msg.tool_calls.as_ref().filter(|c| !c.is_empty()) else {
+        return json!(text);
+    };
+
+    let mut blocks = Vec::new();
+    if !text.is_empty() {
+        blocks.push(json!({"type": "text", "text": text}));
+    }
+    for call in tool_calls {
+        let input: serde_json::Value = if call.function.arguments.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str(&call.function.arguments).unwrap_or_else(|e| {
+                tracing::warn!("tool_calls 参数 JSON 解析失败: {}, id: {}", e, call.id);
+                json!({})
+            })
+        };
+        blocks.push(json!({
+            "type": "tool_use",
+            "id": call.id,
+            "name": call.function.name,
+            "input": input
+        }));
+    }
+    serde_json::Value::Array(blocks)
+}
+
+/// 将 tool 消息转换为 tool_result，连续的 tool 消息合并到同一条 user 消息
+fn push_tool_result(messages: &mut Vec<Message>, msg: &ChatMessage) {
+    let block = json!({
+        "type": "tool_result",
+        "tool_use_id": msg.tool_call_id.clone().unwrap_or_default(),
+        "content": content_to_text(msg.content.as_ref())
+    });
+
+    if let Some(last) = messages.last_mut()
+        && last.role == "user"
+        && let Some(blocks) = last.content.as_array_mut()
+        && blocks
+            .iter()
+            .all(|b| b.get("type").and_then(|t| t.as_str()) == Some("tool_result"))
+    {
+        blocks.push(block);
+        return;
+    }
+
+    messages.push(Message {
+        role: "user".to_string(),
+        content: json!([block]),
+    });
+}
+
+/// 转换 function 工具定义
+fn convert_tools(tools: &[ChatTool]) -> Vec<Tool> {
+    tools
+        .iter()
+        .filter(|t| t.tool_type.is_empty() || t.tool_type == "function")
+        .map(|t| {
+            let input_schema: HashMap<String, serde_json::Value> = t
+                .function
+                .parameters
+                .as_ref()
+                .and_then(|p| serde_json::from_value(p.clone()).ok())
+                .unwrap_or_else(|| {
+                    HashMap::from([
+                        ("type".to_string(), json!("object")),
+                        ("properties".to_string(), json!({})),
+                    ])
+                });
+            Tool {
+                tool_type: None,
+                name: t.function.name.clone(),
+                description: t.function.description.clone().unwrap_or_default(),
+                input_schema,
+                max_uses: None,
+                cache_control: None,
+            }
+        })
+        .collect()
+}
+
+/// 转换 tool_choice（"auto" 为默认行为，不透传）
+fn convert_tool_choice(choice: &serde_json::Value) -> Option<serde_json::Value> {
+    match choice {
+        serde_json::Value::String(s) => match s.as_str() {
+            "none" => Some(json!({"type": "none"})),
+            "required" => Some(json!({"type": "any"})),
+            _ => None,
+        },
+        serde_json::Value::Object(_) => choice
+            .get("function")
+            .and_then(|f| f.get("name"))
+            .map(|name| json!({"type": "tool", "name": name})),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: serde_json::Value) -> ChatCompletionRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_system_and_text_messages() {
+        let req = parse(json!({
+            "model": "claude-sonnet-4-6",
+            "max_tokens": 1024,
+            "messages": [
+                {"role": "system", "content": "You are helpful."},
+                {"role": "developer", "content": [{"type": "text", "text": "Be brief."}]},
+                {"role": "user", "content": "Hi"}
+            ]
+        }));
+
+        let result = to_messages_request(&req).unwrap();
+        assert_eq!(result.max_tokens, 1024);
+        let system = result.system.unwrap();
+        assert_eq!(system.len(), 2);
+        assert_eq!(system[1].text, "Be brief.");
+        assert_eq!(result.messages.len(), 1);
+        assert_eq!(result.messages[0].content, json!("Hi"));
+    }
+
+    #[test]
+    fn test_tool_calls_and_tool_results() {
+        let req = parse(json!({
+            "model": "claude-sonnet-4-6",
+            "messages": [
+                {"role": "user", "content": "Weather?"},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function",
+                     "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}},
+                    {"id": "call_2", "type": "function",
+                     "function": {"name": "get_time", "arguments": ""}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "Sunny"},
+                {"role": "tool", "tool_call_id": "call_2", "content": "12:00"}
+            ],
+            "tools": [{"type": "function", "function": {
+                "name": "get_weather",
+                "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+            }}],
+            "tool_choice": "auto"
+        }));
+
+        let result = to_messages_request(&req).unwrap();
+        assert_eq!(result.max_tokens, 0);
+        assert!(result.tool_choice.is_none());
+        assert_eq!(result.messages.len(), 3);
+
+        let assistant = result.messages[1].content.as_array().unwrap();
+        assert_eq!(assistant.len(), 2);
+        assert_eq!(assistant[0]["type"], "tool_use");
+        assert_eq!(assistant[0]["input"]["city"], "Paris");
+        assert_eq!(assistant[1]["input"], json!({}));
+
+        let results = result.messages[2].content.as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1]["tool_use_id"], "call_2");
+
+        let tools = result.tools.unwrap();
+        assert_eq!(tools[0].name, "get_weather");
+        assert!(tools[0].input_schema.contains_key("properties"));
+    }
+
+    #[test]
+    fn test_image_data_url() {
+        let req = parse(json!({
+            "model": "claude-sonnet-4-6",
+            "messages": [{"role": "user", "content": [
+                {"type": "text", "text": "What is this?"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}}
+            ]}]
+        }));
+
+        let result = to_messages_request(&req).unwrap();
+        let blocks = result.messages[0].content.as_array().unwrap();
+        assert_eq!(blocks[1]["source"]["media_type"], "image/png");
+        assert_eq!(blocks[1]["source"]["data"], "iVBORw0KGgo=");
+
+        let remote = parse(json!({
+            "model": "claude-sonnet-4-6",
+            "messages": [{"role": "user", "content": [
+                {"type": "image_url", "image_url": {"url": "https://example.com/a.png"}}
+            ]}]
+        }));
+        assert!(matches!(
+            to_messages_request(&remote),
+            Err(ConversionError::UnsupportedImageUrl)
+        ));
+    }
+
+    #[test]
+    fn test_tool_choice_mapping() {
+        assert_eq!(
+            convert_tool_choice(&json!("required")),
+            Some(json!({"type": "any"}))
+        );
+        assert_eq!(
+            convert_tool_choice(&json!({"type": "function", "function": {"name": "f"}})),
+            Some(json!({"type": "tool", "name": "f"}))
+        );
+        assert_eq!(convert_tool_choice(&json!("auto")), None);
+    }
+}
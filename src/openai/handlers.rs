@@ -0,0 +1,398 @@
+//! OpenAI API Handler 函数
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::{
+    Json as JsonExtractor,
+    body::Body,
+    extract::{Extension, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream};
+
+use crate::anthropic::compaction;
+use crate::anthropic::converter::{ConversionOptions, convert_request};
+use crate::anthropic::handlers::{
+    RequestLogCtx, clamp_max_tokens, map_conversion_error, map_provider_error,
+    override_thinking_from_model_name, warnings_header_value, with_context_usage_header,
+    with_diagnostic_headers, with_usage_headers,
+};
+use crate::anthropic::heartbeat::Heartbeat;
+use crate::anthropic::middleware::AppState;
+use crate::anthropic::stream::StreamContext;
+use crate::anthropic::types::{ErrorResponse, RequestFlags};
+use crate::apikeys::{ApiKeyManager, AuthenticatedApiKey};
+use crate::kiro::model::events::Event;
+use crate::kiro::model::requests::kiro::KiroRequest;
+use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::provider::KiroProvider;
+use crate::token;
+
+use super::converter::to_messages_request;
+use super::stream::{ChunkConverter, CompletionAggregator, DONE_SSE, chunk_to_sse_string};
+use super::types::ChatCompletionRequest;
+
+/// SSE 注释形式的保活事件（OpenAI 客户端会忽略注释行）
+const PING_SSE: &str = ": ping\n\n";
+
+/// POST /v1/chat/completions
+///
+/// OpenAI 兼容的对话接口，复用 Anthropic → Kiro 转换流程
+#[utoipa::path(
+    post,
+    path = "/v1/chat/completions",
+    tag = "openai",
+    params(RequestFlags),
+    request_body = ChatCompletionRequest,
+    responses(
+        (status = 200, description = "`chat.completion`（`stream: true` 时为 `chat.completion.chunk` SSE）"),
+        (status = 400, description = "请求无效", body = ErrorResponse),
+        (status = 401, description = "认证失败", body = ErrorResponse),
+        (status = 429, description = "限流或配额超限", body = ErrorResponse),
+        (status = 529, description = "无可用凭据", body = ErrorResponse),
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+pub async fn post_chat_completions(
+    State(state): State<AppState>,
+    Extension(auth): Extension<AuthenticatedApiKey>,
+    Query(flags): Query<RequestFlags>,
+    JsonExtractor(request): JsonExtractor<ChatCompletionRequest>,
+) -> Response {
+    tracing::info!(
+        model = %request.model,
+        stream = %request.stream,
+        message_count = %request.messages.len(),
+        "Received POST /v1/chat/completions request"
+    );
+    let provider = match &state.kiro_provider {
+        Some(p) => p.clone(),
+        None => {
+            tracing::error!("KiroProvider 未配置");
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ErrorResponse::new(
+                    "service_unavailable",
+                    "Kiro API provider not configured",
+                )),
+            )
+                .into_response();
+        }
+    };
+
+    let mut payload = match to_messages_request(&request) {
+        Ok(payload) => payload,
+        Err(e) => {
+            tracing::warn!("OpenAI 请求转换失败: {}", e);
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new("invalid_request_error", e.to_string())),
+            )
+                .into_response();
+        }
+    };
+    override_thinking_from_model_name(&mut payload);
+
+    // 上下文使用率超过阈值时压缩最早的对话历史
+    let compaction_warning = compaction::compact_history(
+        &provider,
+        &state.api_keys,
+        &auth.key_id,
+        state.profile_arn.clone(),
+        &mut payload,
+    )
+    .await;
+
+    let conversion_options = ConversionOptions::from_config(&provider.token_manager().config())
+        .with_request_flags(&flags);
+    let conversion_result = match convert_request(&payload, &conversion_options) {
+        Ok(result) => result,
+        Err(e) => return map_conversion_error(e),
+    };
+
+    let kiro_request = KiroRequest {
+        conversation_state: conversion_result.conversation_state,
+        profile_arn: state.profile_arn.clone(),
+    };
+    let request_body = match serde_json::to_string(&kiro_request) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("序列化请求失败: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(
+                    "internal_error",
+                    format!("序列化请求失败: {}", e),
+                )),
+            )
+                .into_response();
+        }
+    };
+
+    let mut warnings = conversion_result.warnings;
+    warnings.extend(compaction_warning);
+    let output_limit = match clamp_max_tokens(&mut payload, &conversion_options, &mut warnings) {
+        Ok(limit) => limit,
+        Err(e) => return map_conversion_error(e),
+    };
+    let warnings_header = warnings_header_value(&warnings);
+    let log_ctx = RequestLogCtx::new(
+        state.request_log.clone(),
+        &state.api_keys,
+        &auth.key_id,
+        &payload,
+        warnings,
+    )
+    .with_kiro_request(&provider.token_manager().config(), &request_body);
+
+    let input_tokens = token::count_all_tokens(
+        payload.model.clone(),
+        payload.system.clone(),
+        payload.messages.clone(),
+        payload.tools.clone(),
+    ) as i32;
+    let thinking_enabled = payload
+        .thinking
+        .as_ref()
+        .map(|t| t.is_enabled())
+        .unwrap_or(false);
+    let ctx = StreamContext::new_with_thinking(&payload.model, input_tokens, thinking_enabled)
+        .with_output_limit(output_limit);
+
+    let response = if request.stream {
+        let response = match provider.call_api_stream(&request_body).await {
+            Ok(resp) => resp,
+            Err(e) => return map_provider_error(e),
+        };
+        let log_ctx = log_ctx.with_served_by(&provider, &response);
+        let converter = ChunkConverter::new(&payload.model, request.include_usage());
+        let stream = create_chunk_stream(
+            response,
+            ctx,
+            converter,
+            Heartbeat::new(&provider.token_manager().config()),
+            state.api_keys.clone(),
+            auth.key_id.clone(),
+            log_ctx,
+        );
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .header(header::CONNECTION, "keep-alive")
+            .body(Body::from_stream(stream))
+            .unwrap()
+    } else {
+        handle_non_stream_request(
+            provider,
+            state.api_keys.clone(),
+            &auth.key_id,
+            &request_body,
+            &payload.model,
+            ctx,
+            log_ctx,
+        )
+        .await
+    };
+
+    with_diagnostic_headers(response, warnings_header, None)
+}
+
+/// 流式转换状态
+struct ChunkStreamState<S> {
+    body_stream: S,
+    ctx: StreamContext,
+    converter: ChunkConverter,
+    decoder: EventStreamDecoder,
+    api_keys: Arc<ApiKeyManager>,
+    key_id: String,
+    log_ctx: RequestLogCtx,
+    finished: bool,
+}
+
+impl<S> ChunkStreamState<S> {
+    /// 将 Anthropic SSE 事件转换为 OpenAI chunk 字节
+    fn convert(&mut self, events: Vec<crate::anthropic::stream::SseEvent>) -> Vec<Bytes> {
+        events
+            .iter()
+            .flat_map(|e| self.converter.convert(e))
+            .map(|chunk| {
+                let sse = chunk_to_sse_string(&chunk);
+                self.log_ctx.response_events.push(chunk);
+                Bytes::from(sse)
+            })
+            .collect()
+    }
+
+    /// 流结束：记录用量并输出剩余 chunk 与 `[DONE]`
+    fn finish(&mut self, status: &str) -> Vec<Bytes> {
+        self.finished = true;
+        let (input, output) = self.ctx.final_usage();
+        self.api_keys.record_usage(
+            &self.key_id,
+            self.log_ctx.user_id(),
+            self.log_ctx.model(),
+            input.max(0) as u64,
+            output.max(0) as u64,
+        );
+        self.log_ctx
+            .record(input, output, self.ctx.token_source(), status);
+        let final_events = self.ctx.generate_final_events();
+        let mut bytes = self.convert(final_events);
+        bytes.push(Bytes::from(DONE_SSE));
+        bytes
+    }
+}
+
+/// 创建 OpenAI 格式的 SSE 流，空闲时按 heartbeat 配置发送注释保活
+fn create_chunk_stream(
+    response: reqwest::Response,
+    mut ctx: StreamContext,
+    converter: ChunkConverter,
+    heartbeat: Heartbeat,
+    api_keys: Arc<ApiKeyManager>,
+    key_id: String,
+    log_ctx: RequestLogCtx,
+) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    let initial_events = ctx.generate_initial_events();
+    let mut state = ChunkStreamState {
+        body_stream: response.bytes_stream(),
+        ctx,
+        converter,
+        decoder: EventStreamDecoder::new(),
+        api_keys,
+        key_id,
+        log_ctx,
+        finished: false,
+    };
+    let initial = state.convert(initial_events);
+
+    let processing_stream = stream::unfold(
+        (state, heartbeat),
+        |(mut state, mut ping_interval)| async move {
+            if state.finished {
+                return None;
+            }
+
+            let bytes = tokio::select! {
+                chunk_result = state.body_stream.next() => match chunk_result {
+                    Some(Ok(chunk)) => {
+                        if let Err(e) = state.decoder.feed(&chunk) {
+                            tracing::warn!("缓冲区溢出: {}", e);
+                        }
+                        let mut events = Vec::new();
+                        for result in state.decoder.decode_iter() {
+                            match result {
+                                Ok(frame) => {
+                                    if let Ok(event) = Event::from_frame(frame) {
+                                        events.extend(state.ctx.process_kiro_event(&event));
+                                    }
+                                }
+                                Err(e) => tracing::warn!("解码事件失败: {}", e),
+                            }
+                        }
+                        if !events.is_empty() {
+                            ping_interval.reset();
+                        }
+                        state.convert(events)
+                    }
+                    Some(Err(e)) => {
+                        tracing::error!("读取响应流失败: {}", e);
+                        state.finish(&format!("error: {}", e))
+                    }
+                    None => state.finish("success"),
+                },
+                _ = ping_interval.tick() => {
+                    tracing::trace!("发送 ping 保活注释");
+                    vec![Bytes::from(PING_SSE)]
+                }
+            };
+
+            let items: Vec<Result<Bytes, Infallible>> = bytes.into_iter().map(Ok).collect();
+            Some((stream::iter(items), (state, ping_interval)))
+        },
+    )
+    .flatten();
+
+    stream::iter(initial.into_iter().map(Ok)).chain(processing_stream)
+}
+
+/// 处理非流式请求：聚合全部事件后返回 `chat.completion`
+async fn handle_non_stream_request(
+    provider: Arc<KiroProvider>,
+    api_keys: Arc<ApiKeyManager>,
+    key_id: &str,
+    request_body: &str,
+    model: &str,
+    mut ctx: StreamContext,
+    log_ctx: RequestLogCtx,
+) -> Response {
+    let response = match provider.call_api(request_body).await {
+        Ok(resp) => resp,
+        Err(e) => return map_provider_error(e),
+    };
+    let log_ctx = log_ctx.with_served_by(&provider, &response);
+
+    let body_bytes = match response.bytes().await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("读取响应体失败: {}", e);
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(ErrorResponse::new(
+                    "api_error",
+                    format!("读取响应失败: {}", e),
+                )),
+            )
+                .into_response();
+        }
+    };
+
+    let mut decoder = EventStreamDecoder::new();
+    if let Err(e) = decoder.feed(&body_bytes) {
+        tracing::warn!("缓冲区溢出: {}", e);
+    }
+
+    let mut aggregator = CompletionAggregator::default();
+    for event in ctx.generate_initial_events() {
+        aggregator.push(&event);
+    }
+    for result in decoder.decode_iter() {
+        match result {
+            Ok(frame) => {
+                if let Ok(event) = Event::from_frame(frame) {
+                    for sse in ctx.process_kiro_event(&event) {
+                        aggregator.push(&sse);
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("解码事件失败: {}", e),
+        }
+    }
+    for event in ctx.generate_final_events() {
+        aggregator.push(&event);
+    }
+
+    let (input, output) = ctx.final_usage();
+    api_keys.record_usage(
+        key_id,
+        log_ctx.user_id(),
+        log_ctx.model(),
+        input.max(0) as u64,
+        output.max(0) as u64,
+    );
+    let body = aggregator.into_response(model, input, output);
+    log_ctx.record_with_body(
+        input,
+        output,
+        ctx.token_source(),
+        "success",
+        serde_json::to_string(&body).unwrap_or_default(),
+    );
+
+    let response = with_usage_headers(Json(body).into_response(), input, output);
+    with_context_usage_header(response, ctx.context_usage_percentage)
+}
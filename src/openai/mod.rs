@@ -0,0 +1,11 @@
+//! OpenAI API 兼容服务模块
+//!
+//! 提供 `POST /v1/chat/completions`（流式与非流式），
+//! 请求转换为 Anthropic 格式后复用 Kiro 转换与调用流程，响应以 OpenAI 格式返回。
+
+mod converter;
+pub(crate) mod handlers;
+mod stream;
+pub mod types;
+
+pub use handlers::post_chat_completions;
@@ -0,0 +1,339 @@
+//! Anthropic SSE 事件 → OpenAI Chat Completions 响应转换
+//!
+//! Kiro 事件先经 `StreamContext` 转换为 Anthropic SSE 事件，
+//! 再由本模块转换为 `chat.completion.chunk`（流式）或 `chat.completion`（非流式）。
+
+use std::collections::HashMap;
+
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::anthropic::stream::SseEvent;
+
+/// 流式结束标记
+pub const DONE_SSE: &str = "data: [DONE]\n\n";
+
+/// 将 Anthropic stop_reason 映射为 OpenAI finish_reason
+pub fn map_finish_reason(stop_reason: &str) -> &'static str {
+    match stop_reason {
+        "tool_use" => "tool_calls",
+        "max_tokens" | "model_context_window_exceeded" => "length",
+        _ => "stop",
+    }
+}
+
+/// 生成 chat completion ID
+fn completion_id() -> String {
+    format!("chatcmpl-{}", Uuid::new_v4().simple())
+}
+
+/// 构造 usage 对象
+fn usage_json(input: i32, output: i32) -> serde_json::Value {
+    json!({
+        "prompt_tokens": input,
+        "completion_tokens": output,
+        "total_tokens": input + output
+    })
+}
+
+/// 流式 chunk 转换器
+pub struct ChunkConverter {
+    id: String,
+    model: String,
+    created: i64,
+    include_usage: bool,
+    /// Anthropic 内容块索引 -> OpenAI tool_calls 索引
+    tool_indices: HashMap<i64, usize>,
+    usage: Option<(i32, i32)>,
+}
+
+impl ChunkConverter {
+    pub fn new(model: impl Into<String>, include_usage: bool) -> Self {
+        Self {
+            id: completion_id(),
+            model: model.into(),
+            created: chrono::Utc::now().timestamp(),
+            include_usage,
+            tool_indices: HashMap::new(),
+            usage: None,
+        }
+    }
+
+    fn chunk(&self, delta: serde_json::Value, finish_reason: Option<&str>) -> serde_json::Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{
+                "index": 0,
+                "delta": delta,
+                "finish_reason": finish_reason
+            }]
+        })
+    }
+
+    /// 转换单个 Anthropic SSE 事件，返回零或多个 chunk
+    pub fn convert(&mut self, event: &SseEvent) -> Vec<serde_json::Value> {
+        let data = &event.data;
+        match event.event.as_str() {
+            "message_start" => vec![self.chunk(json!({"role": "assistant", "content": ""}), None)],
+            "content_block_start" => {
+                let block = &data["content_block"];
+                if block["type"] != "tool_use" {
+                    return Vec::new();
+                }
+                let index = self.tool_indices.len();
+                self.tool_indices
+                    .insert(data["index"].as_i64().unwrap_or_default(), index);
+                vec![self.chunk(
+                    json!({"tool_calls": [{
+                        "index": index,
+                        "id": block["id"],
+                        "type": "function",
+                        "function": {"name": block["name"], "arguments": ""}
+                    }]}),
+                    None,
+                )]
+            }
+            "content_block_delta" => {
+                let delta = &data["delta"];
+                match delta["type"].as_str() {
+                    Some("text_delta") => {
+                        vec![self.chunk(json!({"content": delta["text"]}), None)]
+                    }
+                    Some("thinking_delta") => {
+                        vec![self.chunk(json!({"reasoning_content": delta["thinking"]}), None)]
+                    }
+                    Some("input_json_delta") => {
+                        let block_index = data["index"].as_i64().unwrap_or_default();
+                        let Some(&index) = self.tool_indices.get(&block_index) else {
+                            return Vec::new();
+                        };
+                        vec![self.chunk(
+                            json!({"tool_calls": [{
+                                "index": index,
+                                "function": {"arguments": delta["partial_json"]}
+                            }]}),
+                            None,
+                        )]
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            "message_delta" => {
+                let usage = &data["usage"];
+                self.usage = Some((
+                    usage["input_tokens"].as_i64().unwrap_or_default() as i32,
+                    usage["output_tokens"].as_i64().unwrap_or_default() as i32,
+                ));
+                let stop_reason = data["delta"]["stop_reason"].as_str().unwrap_or("end_turn");
+                vec![self.chunk(json!({}), Some(map_finish_reason(stop_reason)))]
+            }
+            "message_stop" if self.include_usage => {
+                let (input, output) = self.usage.unwrap_or_default();
+                vec![json!({
+                    "id": self.id,
+                    "object": "chat.completion.chunk",
+                    "created": self.created,
+                    "model": self.model,
+                    "choices": [],
+                    "usage": usage_json(input, output)
+                })]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// 将 chunk 格式化为 SSE 字符串
+pub fn chunk_to_sse_string(chunk: &serde_json::Value) -> String {
+    format!(
+        "data: {}\n\n",
+        serde_json::to_string(chunk).unwrap_or_default()
+    )
+}
+
+/// 非流式响应聚合器：收集全部 Anthropic SSE 事件后生成 `chat.completion`
+#[derive(Default)]
+pub struct CompletionAggregator {
+    content: String,
+    reasoning: String,
+    /// (id, name, arguments)
+    tool_calls: Vec<(String, String, String)>,
+    tool_indices: HashMap<i64, usize>,
+    stop_reason: Option<String>,
+}
+
+impl CompletionAggregator {
+    pub fn push(&mut self, event: &SseEvent) {
+        let data = &event.data;
+        match event.event.as_str() {
+            "content_block_start" if data["content_block"]["type"] == "tool_use" => {
+                let block = &data["content_block"];
+                self.tool_indices.insert(
+                    data["index"].as_i64().unwrap_or_default(),
+                    self.tool_calls.len(),
+                );
+                self.tool_calls.push((
+                    block["id"].as_str().unwrap_or_default().to_string(),
+                    block["name"].as_str().unwrap_or_default().to_string(),
+                    String::new(),
+                ));
+            }
+            "content_block_delta" => {
+                let delta = &data["delta"];
+                match delta["type"].as_str() {
+                    Some("text_delta") => {
+                        self.content.push_str(delta["text"].as_str().unwrap_or(""))
+                    }
+                    Some("thinking_delta") => self
+                        .reasoning
+                        .push_str(delta["thinking"].as_str().unwrap_or("")),
+                    Some("input_json_delta") => {
+                        let block_index = data["index"].as_i64().unwrap_or_default();
+                        if let Some(&index) = self.tool_indices.get(&block_index) {
+                            self.tool_calls[index]
+                                .2
+                                .push_str(delta["partial_json"].as_str().unwrap_or(""));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            "message_delta" => {
+                self.stop_reason = data["delta"]["stop_reason"].as_str().map(String::from);
+            }
+            _ => {}
+        }
+    }
+
+    /// 生成 `chat.completion` 响应体
+    pub fn into_response(self, model: &str, input: i32, output: i32) -> serde_json::Value {
+        let mut message = json!({
+            "role": "assistant",
+            "content": (!self.content.is_empty()).then_some(self.content),
+        });
+        if !self.reasoning.is_empty() {
+            message["reasoning_content"] = json!(self.reasoning);
+        }
+        if !self.tool_calls.is_empty() {
+            message["tool_calls"] = self
+                .tool_calls
+                .into_iter()
+                .map(|(id, name, arguments)| {
+                    json!({
+                        "id": id,
+                        "type": "function",
+                        "function": {
+                            "name": name,
+                            "arguments": if arguments.is_empty() { "{}".to_string() } else { arguments }
+                        }
+                    })
+                })
+                .collect();
+        }
+
+        json!({
+            "id": completion_id(),
+            "object": "chat.completion",
+            "created": chrono::Utc::now().timestamp(),
+            "model": model,
+            "choices": [{
+                "index": 0,
+                "message": message,
+                "finish_reason": map_finish_reason(self.stop_reason.as_deref().unwrap_or("end_turn"))
+            }],
+            "usage": usage_json(input, output)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool_events() -> Vec<SseEvent> {
+        vec![
+            SseEvent::new("message_start", json!({"type": "message_start"})),
+            SseEvent::new(
+                "content_block_delta",
+                json!({"index": 0, "delta": {"type": "text_delta", "text": "Let me check."}}),
+            ),
+            SseEvent::new(
+                "content_block_start",
+                json!({"index": 1, "content_block": {"type": "tool_use", "id": "t1", "name": "get_weather", "input": {}}}),
+            ),
+            SseEvent::new(
+                "content_block_delta",
+                json!({"index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"city\":"}}),
+            ),
+            SseEvent::new(
+                "content_block_delta",
+                json!({"index": 1, "delta": {"type": "input_json_delta", "partial_json": "\"Paris\"}"}}),
+            ),
+            SseEvent::new(
+                "message_delta",
+                json!({"delta": {"stop_reason": "tool_use"}, "usage": {"input_tokens": 10, "output_tokens": 5}}),
+            ),
+            SseEvent::new("message_stop", json!({"type": "message_stop"})),
+        ]
+    }
+
+    #[test]
+    fn test_chunk_converter_tool_calls() {
+        let mut converter = ChunkConverter::new("claude-sonnet-4-6", true);
+        let chunks: Vec<_> = tool_events()
+            .iter()
+            .flat_map(|e| converter.convert(e))
+            .collect();
+
+        assert_eq!(chunks.len(), 7);
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], "Let me check.");
+        let start = &chunks[2]["choices"][0]["delta"]["tool_calls"][0];
+        assert_eq!(start["index"], 0);
+        assert_eq!(start["function"]["name"], "get_weather");
+        assert_eq!(
+            chunks[4]["choices"][0]["delta"]["tool_calls"][0]["function"]["arguments"],
+            "\"Paris\"}"
+        );
+        assert_eq!(chunks[5]["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(chunks[6]["usage"]["total_tokens"], 15);
+    }
+
+    #[test]
+    fn test_chunk_converter_without_usage() {
+        let mut converter = ChunkConverter::new("claude-sonnet-4-6", false);
+        let chunks: Vec<_> = tool_events()
+            .iter()
+            .flat_map(|e| converter.convert(e))
+            .collect();
+        assert_eq!(chunks.len(), 6);
+    }
+
+    #[test]
+    fn test_completion_aggregator() {
+        let mut aggregator = CompletionAggregator::default();
+        for event in tool_events() {
+            aggregator.push(&event);
+        }
+        let response = aggregator.into_response("claude-sonnet-4-6", 10, 5);
+
+        let choice = &response["choices"][0];
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        assert_eq!(choice["message"]["content"], "Let me check.");
+        assert_eq!(
+            choice["message"]["tool_calls"][0]["function"]["arguments"],
+            "{\"city\":\"Paris\"}"
+        );
+        assert_eq!(response["usage"]["prompt_tokens"], 10);
+    }
+
+    #[test]
+    fn test_map_finish_reason() {
+        assert_eq!(map_finish_reason("end_turn"), "stop");
+        assert_eq!(map_finish_reason("max_tokens"), "length");
+        assert_eq!(map_finish_reason("tool_use"), "tool_calls");
+    }
+}
@@ -0,0 +1,84 @@
+//! OpenAI Chat Completions API 类型定义
+
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+/// Chat Completions 请求体
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ChatCompletionRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub stream: bool,
+    pub max_tokens: Option<i32>,
+    /// 新版客户端使用的输出上限字段，优先于 `max_tokens`
+    pub max_completion_tokens: Option<i32>,
+    pub tools: Option<Vec<ChatTool>>,
+    pub tool_choice: Option<serde_json::Value>,
+    pub stream_options: Option<StreamOptions>,
+    /// 终端用户标识，映射到 Anthropic `metadata.user_id`
+    pub user: Option<String>,
+}
+
+impl ChatCompletionRequest {
+    /// 流式响应是否需要在末尾附带 usage chunk
+    pub fn include_usage(&self) -> bool {
+        self.stream_options
+            .as_ref()
+            .is_some_and(|o| o.include_usage)
+    }
+}
+
+/// 流式选项
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct StreamOptions {
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+/// 对话消息
+///
+/// role 取值：system / developer / user / assistant / tool
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ChatMessage {
+    pub role: String,
+    /// 可以是 string、content part 数组或 null（assistant 仅含 tool_calls 时）
+    #[serde(default)]
+    pub content: Option<serde_json::Value>,
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// role 为 tool 时对应的工具调用 ID
+    pub tool_call_id: Option<String>,
+}
+
+/// assistant 消息中的工具调用
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ToolCall {
+    pub id: String,
+    pub function: FunctionCall,
+}
+
+/// 工具调用的函数名与参数（参数为 JSON 字符串）
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct FunctionCall {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: String,
+}
+
+/// 工具定义
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ChatTool {
+    #[serde(rename = "type", default)]
+    pub tool_type: String,
+    pub function: FunctionDefinition,
+}
+
+/// 函数定义
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct FunctionDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub parameters: Option<serde_json::Value>,
+}
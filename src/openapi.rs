@@ -0,0 +1,191 @@
+//! OpenAPI 文档
+//!
+//! 由 utoipa 根据处理函数上的 `#[utoipa::path]` 注解与请求/响应类型生成 OpenAPI 3.1 文档，
+//! 通过 `GET /openapi.json` 提供（无需认证）。
+
+use axum::Json;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::admin::handlers as admin;
+use crate::anthropic::batches;
+use crate::anthropic::handlers as anthropic;
+use crate::gemini::handlers as gemini;
+use crate::openai::handlers as openai;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "kiro-rs", description = "Anthropic / OpenAI / Gemini 兼容 API 与管理 API"),
+    paths(
+        anthropic::get_models,
+        anthropic::post_messages,
+        anthropic::count_tokens,
+        batches::create_batch,
+        batches::list_batches,
+        batches::get_batch,
+        batches::get_batch_results,
+        batches::cancel_batch,
+        batches::delete_batch,
+        anthropic::post_browser_token,
+        anthropic::post_messages_cc,
+        openai::post_chat_completions,
+        gemini::post_gemini,
+        admin::login,
+        admin::list_sessions,
+        admin::revoke_session,
+        admin::get_all_credentials,
+        admin::add_credential,
+        admin::export_credentials,
+        admin::discover_local_credentials,
+        admin::import_local_credentials,
+        admin::upload_credentials,
+        admin::delete_credential,
+        admin::export_credential,
+        admin::set_credential_disabled,
+        admin::set_credential_priority,
+        admin::reset_failure_count,
+        admin::set_credential_canary,
+        admin::get_credential_balance,
+        admin::get_credential_usage,
+        admin::test_credential,
+        admin::get_total_balance,
+        admin::get_load_balancing_mode,
+        admin::set_load_balancing_mode,
+        admin::get_canary,
+        admin::set_canary_percentage,
+        admin::reload_config,
+        admin::set_log_level,
+        admin::get_logging,
+        admin::set_logging,
+        admin::get_model_mappings,
+        admin::set_model_mappings,
+        admin::get_output_limits,
+        admin::set_output_limits,
+        admin::get_pricing,
+        admin::set_pricing,
+        admin::get_rotation,
+        admin::set_rotation,
+        admin::get_backups,
+        admin::run_backup,
+        admin::set_backup_enabled,
+        admin::list_api_keys,
+        admin::create_api_key,
+        admin::import_api_keys,
+        admin::delete_api_key,
+        admin::set_api_key_disabled,
+        admin::set_api_key_limits,
+        admin::set_api_key_quota,
+        admin::reset_api_key_quota,
+        admin::set_api_key_budget,
+        admin::set_api_key_body_limit,
+        admin::set_api_key_compaction,
+        admin::set_api_key_regions,
+        admin::set_api_key_scopes,
+        admin::set_api_key_webhook,
+        admin::get_api_stats,
+        admin::get_endpoint_health,
+        admin::get_user_usage,
+        admin::get_request_logs,
+        admin::clear_request_logs,
+        admin::get_request_log,
+        admin::stream_request_logs,
+        admin::export_request_logs,
+        admin::get_log_enabled,
+        admin::set_log_enabled,
+    ),
+    modifiers(&SecurityAddon, &ClaudeCodeCountTokens),
+    tags(
+        (name = "anthropic", description = "Anthropic Messages API 兼容端点"),
+        (name = "claude-code", description = "Claude Code 兼容端点（/cc/v1）"),
+        (name = "openai", description = "OpenAI Chat Completions 兼容端点"),
+        (name = "gemini", description = "Gemini generateContent 兼容端点"),
+        (name = "admin", description = "管理 API"),
+    )
+)]
+pub struct ApiDoc;
+
+/// 注册认证方式
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("x-api-key"))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "goog_api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("x-goog-api-key"))),
+        );
+        components.add_security_scheme(
+            "admin_session",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("POST /api/admin/auth/login 返回的会话令牌"))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// `count_tokens` 同时挂载在 /v1 与 /cc/v1 下，复制一份文档到 /cc/v1
+struct ClaudeCodeCountTokens;
+
+impl Modify for ClaudeCodeCountTokens {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let paths = &mut openapi.paths.paths;
+        let Some(mut item) = paths.get("/v1/messages/count_tokens").cloned() else {
+            return;
+        };
+        if let Some(operation) = item.post.as_mut() {
+            operation.tags = Some(vec!["claude-code".to_string()]);
+            operation.operation_id = Some("count_tokens_cc".to_string());
+        }
+        paths.insert("/cc/v1/messages/count_tokens".to_string(), item);
+    }
+}
+
+/// GET /openapi.json
+pub async fn get_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_covers_public_routes() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        assert!(spec["openapi"].as_str().unwrap().starts_with("3.1"));
+
+        let paths = &spec["paths"];
+        for path in [
+            "/v1/messages",
+            "/v1/chat/completions",
+            "/v1/messages/batches/{id}/results",
+            "/cc/v1/messages",
+            "/cc/v1/messages/count_tokens",
+            "/v1beta/models/{model_action}",
+            "/api/admin/credentials",
+            "/api/admin/apikeys/{id}/limits",
+        ] {
+            assert!(paths.get(path).is_some(), "缺少路径: {}", path);
+        }
+        assert_eq!(
+            paths["/cc/v1/messages/count_tokens"]["post"]["tags"][0],
+            "claude-code"
+        );
+
+        let schemas = &spec["components"]["schemas"];
+        assert!(schemas.get("MessagesRequest").is_some());
+        assert!(schemas.get("GeminiFunctionCall").is_some());
+        assert!(spec["components"]["securitySchemes"]["admin_session"].is_object());
+    }
+}
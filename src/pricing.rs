@@ -0,0 +1,101 @@
+//! 按模型单价计算请求费用
+//!
+//! 单价来自配置 `pricing`（客户端模型名 → 每百万输入 / 输出 token 的美元价格，`*` 为未列出模型的默认单价），
+//! 在启动、热重载配置与 `PUT /api/admin/config/pricing` 时更新。费用以微美元（1e-6 USD）整数累计，避免浮点误差。
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use crate::model::config::ModelPrice;
+
+/// 未列出模型使用的单价键
+pub const DEFAULT_MODEL: &str = "*";
+
+/// 每美元的微美元数
+const MICROS_PER_USD: f64 = 1_000_000.0;
+
+static PRICES: RwLock<Option<Arc<PriceTable>>> = RwLock::new(None);
+
+/// 单价表（模型名已转为小写）
+struct PriceTable(BTreeMap<String, ModelPrice>);
+
+impl PriceTable {
+    fn new(prices: &BTreeMap<String, ModelPrice>) -> Self {
+        Self(
+            prices
+                .iter()
+                .map(|(model, price)| (model.to_lowercase(), price.clone()))
+                .collect(),
+        )
+    }
+
+    fn cost_micros(&self, model: &str, input_tokens: u64, output_tokens: u64) -> u64 {
+        let Some(price) = self
+            .0
+            .get(&model.to_lowercase())
+            .or_else(|| self.0.get(DEFAULT_MODEL))
+        else {
+            return 0;
+        };
+        // 每百万 token 的美元价格恰好等于每 token 的微美元价格
+        let micros = input_tokens as f64 * price.input_per_mtok
+            + output_tokens as f64 * price.output_per_mtok;
+        micros.round().max(0.0) as u64
+    }
+}
+
+/// 应用配置中的单价表
+pub fn apply(prices: &BTreeMap<String, ModelPrice>) {
+    *PRICES.write() = Some(Arc::new(PriceTable::new(prices)));
+}
+
+/// 按当前单价表计算一次请求的费用（微美元），模型未定价时为 0
+pub fn cost_micros(model: &str, input_tokens: u64, output_tokens: u64) -> u64 {
+    PRICES.read().as_ref().map_or(0, |table| {
+        table.cost_micros(model, input_tokens, output_tokens)
+    })
+}
+
+/// 微美元转换为美元
+pub fn to_usd(micros: u64) -> f64 {
+    micros as f64 / MICROS_PER_USD
+}
+
+/// 美元转换为微美元
+pub fn from_usd(usd: f64) -> u64 {
+    (usd.max(0.0) * MICROS_PER_USD).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_uses_model_then_default_price() {
+        let mut prices = BTreeMap::new();
+        prices.insert(
+            "Claude-Sonnet-4-6".to_string(),
+            ModelPrice {
+                input_per_mtok: 3.0,
+                output_per_mtok: 15.0,
+            },
+        );
+        let table = PriceTable::new(&prices);
+        assert_eq!(table.cost_micros("claude-sonnet-4-6", 1000, 100), 4500);
+        assert_eq!(table.cost_micros("claude-opus-4-6", 1000, 100), 0);
+        assert_eq!(to_usd(4500), 0.0045);
+        assert_eq!(from_usd(0.0045), 4500);
+
+        prices.insert(
+            DEFAULT_MODEL.to_string(),
+            ModelPrice {
+                input_per_mtok: 1.0,
+                output_per_mtok: 5.0,
+            },
+        );
+        let table = PriceTable::new(&prices);
+        assert_eq!(table.cost_micros("claude-opus-4-6", 1000, 100), 1500);
+    }
+}
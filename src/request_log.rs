@@ -1,12 +1,47 @@
-use std::collections::VecDeque;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
 
+use chrono::{DateTime, Utc};
 use parking_lot::Mutex;
-use serde::Serialize;
+use rusqlite::{Connection, OptionalExtension, Row, params, params_from_iter, types::Value};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::content_filter::ContentFilter;
+use crate::kiro::token_manager::sha256_hex;
 
 const MAX_LOG_ENTRIES: usize = 200;
 
-#[derive(Debug, Clone, Serialize)]
+/// 实时日志推送的缓冲条数，订阅方落后超过该条数时丢弃最旧的日志
+const LIVE_BUFFER: usize = 256;
+
+/// 持久化存储每写入多少条执行一次保留策略清理
+const PRUNE_INTERVAL: u64 = 100;
+
+/// 单页最大条数
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// 外置内容在日志中的引用前缀，后接内容的 SHA-256
+const BLOB_REF_PREFIX: &str = "blob:sha256:";
+
+/// 未被引用的外置内容至少保留的时长，避免删除刚写入、日志尚未落库的内容
+const BLOB_GRACE: Duration = Duration::from_secs(60);
+
+/// 导出时每批读取的条数
+const EXPORT_BATCH: usize = 500;
+
+/// 持久化写入队列容量，队列满时丢弃新日志
+const WRITER_QUEUE: usize = 1024;
+
+/// 查询单条日志的列
+const SELECT_COLUMNS: &str = "id, timestamp, model, stream, message_count, input_tokens, output_tokens, token_source, duration_ms, status, api_key_id, request_body, response_body, warnings, kiro_request_body, user_id";
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct RequestLogEntry {
     pub id: String,
@@ -20,13 +55,671 @@ pub struct RequestLogEntry {
     pub duration_ms: u64,
     pub status: String,
     pub api_key_id: String,
+    /// 请求 `metadata.user_id`（经同一 API Key 接入的终端用户）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
     pub request_body: String,
+    /// 转换后发往上游的 Kiro 请求体（配置 `requestLogKiroRequest` 开启时记录）
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub kiro_request_body: String,
     pub response_body: String,
+    /// 转换过程中丢弃或改写内容的提示
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// 日志查询条件
+///
+/// - `since_id`: 只返回该条目之后的日志（用于增量轮询）
+/// - `api_key_id` / `user_id`: 精确匹配（`user_id` 为请求的 `metadata.user_id`）
+/// - `model`: 模型名包含匹配；`status`: 前缀匹配（如 `error`）
+/// - `from` / `to`: RFC3339 时间范围
+/// - `page` 从 1 开始，第 1 页为最新的日志，页内按时间正序
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+pub struct LogQuery {
+    pub since_id: Option<String>,
+    pub api_key_id: Option<String>,
+    pub user_id: Option<String>,
+    pub model: Option<String>,
+    pub status: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub page: Option<usize>,
+    pub page_size: Option<usize>,
+}
+
+impl LogQuery {
+    fn page(&self) -> usize {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    fn page_size(&self) -> usize {
+        self.page_size
+            .unwrap_or(MAX_LOG_ENTRIES)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    fn time_bound(value: &Option<String>) -> Option<i64> {
+        value
+            .as_deref()
+            .and_then(|v| DateTime::parse_from_rfc3339(v).ok())
+            .map(|t| t.timestamp_millis())
+    }
+
+    pub(crate) fn matches(&self, entry: &RequestLogEntry) -> bool {
+        if self
+            .api_key_id
+            .as_ref()
+            .is_some_and(|k| &entry.api_key_id != k)
+        {
+            return false;
+        }
+        if self
+            .user_id
+            .as_ref()
+            .is_some_and(|u| entry.user_id.as_ref() != Some(u))
+        {
+            return false;
+        }
+        if self
+            .model
+            .as_ref()
+            .is_some_and(|m| !entry.model.contains(m.as_str()))
+        {
+            return false;
+        }
+        if self
+            .status
+            .as_ref()
+            .is_some_and(|s| !entry.status.starts_with(s.as_str()))
+        {
+            return false;
+        }
+        let ts = timestamp_millis(&entry.timestamp);
+        if Self::time_bound(&self.from).is_some_and(|from| ts < from) {
+            return false;
+        }
+        if Self::time_bound(&self.to).is_some_and(|to| ts > to) {
+            return false;
+        }
+        true
+    }
+}
+
+/// 日志导出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Jsonl,
+    Csv,
+}
+
+/// 日志导出条件
+///
+/// - `format`: `jsonl`（默认）或 `csv`
+/// - `from` / `to`: RFC3339 时间范围；`api_key_id`: 精确匹配
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+pub struct ExportQuery {
+    pub format: Option<ExportFormat>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub api_key_id: Option<String>,
+}
+
+impl ExportQuery {
+    pub fn format(&self) -> ExportFormat {
+        self.format.unwrap_or_default()
+    }
+
+    fn log_query(&self) -> LogQuery {
+        LogQuery {
+            api_key_id: self.api_key_id.clone(),
+            from: self.from.clone(),
+            to: self.to.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// 导出的日志字段（不含请求/响应体）
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportRecord<'a> {
+    id: &'a str,
+    timestamp: &'a str,
+    api_key_id: &'a str,
+    user_id: Option<&'a str>,
+    model: &'a str,
+    stream: bool,
+    message_count: usize,
+    input_tokens: i32,
+    output_tokens: i32,
+    token_source: &'a str,
+    duration_ms: u64,
+    status: &'a str,
+}
+
+/// CSV 表头，与 [`ExportRecord`] 字段顺序一致
+const CSV_HEADER: &str = "id,timestamp,apiKeyId,userId,model,stream,messageCount,inputTokens,outputTokens,tokenSource,durationMs,status\n";
+
+impl ExportFormat {
+    /// 文件开头（CSV 表头）
+    pub fn header(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "",
+            ExportFormat::Csv => CSV_HEADER,
+        }
+    }
+
+    /// 单条日志对应的一行（含换行）
+    pub fn line(&self, entry: &RequestLogEntry) -> String {
+        let record = ExportRecord {
+            id: &entry.id,
+            timestamp: &entry.timestamp,
+            api_key_id: &entry.api_key_id,
+            user_id: entry.user_id.as_deref(),
+            model: &entry.model,
+            stream: entry.stream,
+            message_count: entry.message_count,
+            input_tokens: entry.input_tokens,
+            output_tokens: entry.output_tokens,
+            token_source: &entry.token_source,
+            duration_ms: entry.duration_ms,
+            status: &entry.status,
+        };
+        match self {
+            ExportFormat::Jsonl => {
+                format!("{}\n", serde_json::to_string(&record).unwrap_or_default())
+            }
+            ExportFormat::Csv => {
+                let fields = [
+                    csv_field(record.id),
+                    csv_field(record.timestamp),
+                    csv_field(record.api_key_id),
+                    csv_field(record.user_id.unwrap_or_default()),
+                    csv_field(record.model),
+                    record.stream.to_string(),
+                    record.message_count.to_string(),
+                    record.input_tokens.to_string(),
+                    record.output_tokens.to_string(),
+                    csv_field(record.token_source),
+                    record.duration_ms.to_string(),
+                    csv_field(record.status),
+                ];
+                format!("{}\n", fields.join(","))
+            }
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "application/x-ndjson",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+/// 按 RFC 4180 转义 CSV 字段
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// 日志分页结果
+#[derive(Debug, Clone)]
+pub struct LogPage {
+    pub entries: Vec<RequestLogEntry>,
+    pub total: usize,
+    pub page: usize,
+    pub page_size: usize,
+}
+
+/// 持久化日志保留策略（0 表示不限制）
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    pub days: u32,
+    pub max_entries: usize,
+}
+
+fn timestamp_millis(timestamp: &str) -> i64 {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| t.timestamp_millis())
+        .unwrap_or_default()
+}
+
+fn entry_from_row(row: &Row<'_>) -> rusqlite::Result<RequestLogEntry> {
+    let warnings: String = row.get(13)?;
+    Ok(RequestLogEntry {
+        id: row.get(0)?,
+        timestamp: row.get(1)?,
+        model: row.get(2)?,
+        stream: row.get::<_, i32>(3)? != 0,
+        message_count: row.get::<_, i64>(4)? as usize,
+        input_tokens: row.get(5)?,
+        output_tokens: row.get(6)?,
+        token_source: row.get(7)?,
+        duration_ms: row.get::<_, i64>(8)? as u64,
+        status: row.get(9)?,
+        api_key_id: row.get(10)?,
+        user_id: row.get(15)?,
+        request_body: row.get(11)?,
+        kiro_request_body: row.get(14)?,
+        response_body: row.get(12)?,
+        warnings: serde_json::from_str(&warnings).unwrap_or_default(),
+    })
+}
+
+impl RequestLogEntry {
+    fn bodies_mut(&mut self) -> [&mut String; 3] {
+        [
+            &mut self.request_body,
+            &mut self.kiro_request_body,
+            &mut self.response_body,
+        ]
+    }
+
+    /// 条目引用的外置内容哈希
+    fn blob_refs(&self) -> impl Iterator<Item = &str> {
+        [
+            &self.request_body,
+            &self.kiro_request_body,
+            &self.response_body,
+        ]
+        .into_iter()
+        .filter_map(|body| body.strip_prefix(BLOB_REF_PREFIX))
+    }
+}
+
+/// 大请求/响应体的内容寻址存储
+///
+/// 超过阈值的字段以 SHA-256 为文件名写入目录，日志中只保留 `blob:sha256:<hash>` 引用，
+/// 相同内容只存一份；查询单条日志时再还原
+struct BlobStore {
+    dir: PathBuf,
+    threshold: usize,
+}
+
+impl BlobStore {
+    fn path(&self, hash: &str) -> Option<PathBuf> {
+        let valid = hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit());
+        valid.then(|| self.dir.join(hash))
+    }
+
+    /// 将超过阈值的字段写入磁盘并替换为引用，写入失败时保留原文
+    fn offload(&self, entry: &mut RequestLogEntry) {
+        for body in entry.bodies_mut() {
+            if body.len() <= self.threshold || body.starts_with(BLOB_REF_PREFIX) {
+                continue;
+            }
+            let hash = sha256_hex(body);
+            let path = self.dir.join(&hash);
+            let result = if path.exists() {
+                // 已存在相同内容：刷新修改时间，避免被当作过期内容清理
+                fs::File::options()
+                    .append(true)
+                    .open(&path)
+                    .and_then(|f| f.set_modified(SystemTime::now()))
+            } else {
+                let tmp = self.dir.join(format!("{}.tmp", hash));
+                fs::write(&tmp, body.as_bytes()).and_then(|_| fs::rename(&tmp, &path))
+            };
+            match result {
+                Ok(()) => *body = format!("{}{}", BLOB_REF_PREFIX, hash),
+                Err(e) => tracing::warn!("写入日志外置内容失败: {}", e),
+            }
+        }
+    }
+
+    /// 将引用还原为原文，内容缺失时保留引用
+    fn resolve(&self, entry: &mut RequestLogEntry) {
+        for body in entry.bodies_mut() {
+            let Some(path) = body
+                .strip_prefix(BLOB_REF_PREFIX)
+                .and_then(|h| self.path(h))
+            else {
+                continue;
+            };
+            match fs::read_to_string(&path) {
+                Ok(content) => *body = content,
+                Err(e) => tracing::warn!("读取日志外置内容失败: {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    /// 删除未被引用且超过保留时长的内容
+    fn sweep(&self, referenced: &HashSet<String>) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let now = SystemTime::now();
+        for path in entries.filter_map(|e| e.ok().map(|e| e.path())) {
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            let hash = name.strip_suffix(".tmp").unwrap_or(name);
+            if referenced.contains(hash) {
+                continue;
+            }
+            let expired = fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .is_ok_and(|t| now.duration_since(t).unwrap_or_default() >= BLOB_GRACE);
+            if expired {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+}
+
+/// SQLite 日志存储
+struct LogStore {
+    conn: Mutex<Connection>,
+    retention: RetentionPolicy,
+    inserted: AtomicU64,
+}
+
+impl LogStore {
+    fn open(conn: Connection, retention: RetentionPolicy) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "PRAGMA journal_mode=WAL; PRAGMA busy_timeout=5000;
+            CREATE TABLE IF NOT EXISTS request_logs (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT NOT NULL UNIQUE,
+                ts_ms INTEGER NOT NULL,
+                timestamp TEXT NOT NULL,
+                model TEXT NOT NULL,
+                stream INTEGER NOT NULL,
+                message_count INTEGER NOT NULL,
+                input_tokens INTEGER NOT NULL,
+                output_tokens INTEGER NOT NULL,
+                token_source TEXT NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                status TEXT NOT NULL,
+                api_key_id TEXT NOT NULL,
+                request_body TEXT NOT NULL,
+                response_body TEXT NOT NULL,
+                warnings TEXT NOT NULL DEFAULT '[]',
+                kiro_request_body TEXT NOT NULL DEFAULT '',
+                user_id TEXT
+            );
+            CREATE INDEX IF NOT EXISTS idx_request_logs_ts ON request_logs (ts_ms);
+            CREATE INDEX IF NOT EXISTS idx_request_logs_key ON request_logs (api_key_id);",
+        )?;
+        // 旧表迁移：补充 Kiro 请求体与终端用户字段（列已存在时忽略错误）
+        for column in ["kiro_request_body TEXT NOT NULL DEFAULT ''", "user_id TEXT"] {
+            let _ = conn.execute(
+                &format!("ALTER TABLE request_logs ADD COLUMN {}", column),
+                [],
+            );
+        }
+        let store = Self {
+            conn: Mutex::new(conn),
+            retention,
+            inserted: AtomicU64::new(0),
+        };
+        store.prune();
+        Ok(store)
+    }
+
+    /// 写入日志，返回本次是否执行了保留策略清理
+    fn insert(&self, entry: &RequestLogEntry) -> bool {
+        let result = self.conn.lock().execute(
+            "INSERT OR IGNORE INTO request_logs (id, ts_ms, timestamp, model, stream, message_count, input_tokens, output_tokens, token_source, duration_ms, status, api_key_id, request_body, response_body, warnings, kiro_request_body, user_id) VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9,?10,?11,?12,?13,?14,?15,?16,?17)",
+            params![
+                entry.id,
+                timestamp_millis(&entry.timestamp),
+                entry.timestamp,
+                entry.model,
+                entry.stream as i32,
+                entry.message_count as i64,
+                entry.input_tokens,
+                entry.output_tokens,
+                entry.token_source,
+                entry.duration_ms as i64,
+                entry.status,
+                entry.api_key_id,
+                entry.request_body,
+                entry.response_body,
+                serde_json::to_string(&entry.warnings).unwrap_or_else(|_| "[]".to_string()),
+                entry.kiro_request_body,
+                entry.user_id,
+            ],
+        );
+        if let Err(e) = result {
+            tracing::warn!("写入请求日志失败: {}", e);
+            return false;
+        }
+        if self.inserted.fetch_add(1, Ordering::Relaxed) % PRUNE_INTERVAL == PRUNE_INTERVAL - 1 {
+            self.prune();
+            return true;
+        }
+        false
+    }
+
+    /// 按保留策略清理过期/超量日志
+    fn prune(&self) {
+        let conn = self.conn.lock();
+        if self.retention.days > 0 {
+            let cutoff = Utc::now().timestamp_millis() - self.retention.days as i64 * 86_400_000;
+            if let Err(e) = conn.execute("DELETE FROM request_logs WHERE ts_ms < ?1", [cutoff]) {
+                tracing::warn!("清理过期请求日志失败: {}", e);
+            }
+        }
+        if self.retention.max_entries > 0
+            && let Err(e) = conn.execute(
+                "DELETE FROM request_logs WHERE seq <= (SELECT seq FROM request_logs ORDER BY seq DESC LIMIT 1 OFFSET ?1)",
+                [self.retention.max_entries as i64],
+            )
+        {
+            tracing::warn!("清理超量请求日志失败: {}", e);
+        }
+    }
+
+    /// 删除全部日志
+    fn clear(&self) -> anyhow::Result<()> {
+        self.conn.lock().execute("DELETE FROM request_logs", [])?;
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> anyhow::Result<Option<RequestLogEntry>> {
+        let conn = self.conn.lock();
+        let entry = conn
+            .query_row(
+                &format!("SELECT {} FROM request_logs WHERE id = ?1", SELECT_COLUMNS),
+                [id],
+                entry_from_row,
+            )
+            .optional()?;
+        Ok(entry)
+    }
+
+    /// 所有日志引用的外置内容哈希
+    fn blob_refs(&self) -> anyhow::Result<HashSet<String>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            "SELECT request_body, kiro_request_body, response_body FROM request_logs WHERE instr(request_body, ?1) = 1 OR instr(kiro_request_body, ?1) = 1 OR instr(response_body, ?1) = 1",
+        )?;
+        let mut refs = HashSet::new();
+        let mut rows = stmt.query([BLOB_REF_PREFIX])?;
+        while let Some(row) = rows.next()? {
+            for i in 0..3 {
+                let body: String = row.get(i)?;
+                if let Some(hash) = body.strip_prefix(BLOB_REF_PREFIX) {
+                    refs.insert(hash.to_string());
+                }
+            }
+        }
+        Ok(refs)
+    }
+
+    /// 查询条件对应的 SQL 条件与参数
+    fn filter(query: &LogQuery) -> (Vec<&'static str>, Vec<Value>) {
+        let mut clauses: Vec<&str> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+        if let Some(id) = &query.since_id {
+            clauses.push("seq > COALESCE((SELECT seq FROM request_logs WHERE id = ?), 0)");
+            values.push(Value::Text(id.clone()));
+        }
+        if let Some(key) = &query.api_key_id {
+            clauses.push("api_key_id = ?");
+            values.push(Value::Text(key.clone()));
+        }
+        if let Some(user) = &query.user_id {
+            clauses.push("user_id = ?");
+            values.push(Value::Text(user.clone()));
+        }
+        if let Some(model) = &query.model {
+            clauses.push("instr(model, ?) > 0");
+            values.push(Value::Text(model.clone()));
+        }
+        if let Some(status) = &query.status {
+            clauses.push("instr(status, ?) = 1");
+            values.push(Value::Text(status.clone()));
+        }
+        if let Some(from) = LogQuery::time_bound(&query.from) {
+            clauses.push("ts_ms >= ?");
+            values.push(Value::Integer(from));
+        }
+        if let Some(to) = LogQuery::time_bound(&query.to) {
+            clauses.push("ts_ms <= ?");
+            values.push(Value::Integer(to));
+        }
+        (clauses, values)
+    }
+
+    fn query(&self, query: &LogQuery) -> anyhow::Result<LogPage> {
+        let (clauses, values) = Self::filter(query);
+        let where_sql = if clauses.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", clauses.join(" AND "))
+        };
+
+        let conn = self.conn.lock();
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM request_logs {}", where_sql),
+            params_from_iter(values.iter()),
+            |row| row.get(0),
+        )?;
+
+        let (page, page_size) = (query.page(), query.page_size());
+        let mut page_values = values;
+        page_values.push(Value::Integer(page_size as i64));
+        page_values.push(Value::Integer(((page - 1) * page_size) as i64));
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM request_logs {} ORDER BY seq DESC LIMIT ? OFFSET ?",
+            SELECT_COLUMNS, where_sql
+        ))?;
+        let mut entries: Vec<RequestLogEntry> = stmt
+            .query_map(params_from_iter(page_values.iter()), entry_from_row)?
+            .filter_map(|r| r.ok())
+            .collect();
+        entries.reverse();
+
+        Ok(LogPage {
+            entries,
+            total: total as usize,
+            page,
+            page_size,
+        })
+    }
+
+    /// 按写入顺序读取 `after_seq` 之后的一批日志，返回条目与最后一条的序号
+    fn export_batch(
+        &self,
+        query: &LogQuery,
+        after_seq: i64,
+    ) -> anyhow::Result<(Vec<RequestLogEntry>, Option<i64>)> {
+        let (mut clauses, mut values) = Self::filter(query);
+        clauses.push("seq > ?");
+        values.push(Value::Integer(after_seq));
+        values.push(Value::Integer(EXPORT_BATCH as i64));
+
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, seq FROM request_logs WHERE {} ORDER BY seq ASC LIMIT ?",
+            SELECT_COLUMNS,
+            clauses.join(" AND ")
+        ))?;
+        let mut last_seq = None;
+        let entries = stmt
+            .query_map(params_from_iter(values.iter()), |row| {
+                Ok((entry_from_row(row)?, row.get::<_, i64>(16)?))
+            })?
+            .filter_map(|r| r.ok())
+            .map(|(entry, seq)| {
+                last_seq = Some(seq);
+                entry
+            })
+            .collect();
+        Ok((entries, last_seq))
+    }
+}
+
+/// 后台写入线程的消息
+enum WriterMessage {
+    Entry(Box<RequestLogEntry>),
+    /// 之前的日志全部写入后回复
+    Flush(std::sync::mpsc::SyncSender<()>),
+}
+
+/// 持久化日志的后台写入线程：外置大字段、推送实时日志、写入 SQLite 并按保留策略清理，
+/// 不占用请求处理路径
+fn run_writer(
+    rx: std::sync::mpsc::Receiver<WriterMessage>,
+    store: Arc<LogStore>,
+    blobs: Option<Arc<BlobStore>>,
+    live: broadcast::Sender<RequestLogEntry>,
+) {
+    for message in rx {
+        let mut entry = match message {
+            WriterMessage::Entry(entry) => *entry,
+            WriterMessage::Flush(done) => {
+                let _ = done.send(());
+                continue;
+            }
+        };
+        if let Some(blobs) = &blobs {
+            blobs.offload(&mut entry);
+        }
+        if live.receiver_count() > 0 {
+            let _ = live.send(entry.clone());
+        }
+        if store.insert(&entry)
+            && let Some(blobs) = &blobs
+        {
+            match store.blob_refs() {
+                Ok(refs) => blobs.sweep(&refs),
+                Err(e) => tracing::warn!("查询日志外置内容引用失败: {}", e),
+            }
+        }
+    }
 }
 
 pub struct RequestLog {
     entries: Mutex<VecDeque<RequestLogEntry>>,
     enabled: AtomicBool,
+    /// 可选的 SQLite 持久化存储（启用后查询走存储，写入由后台线程完成）
+    store: Option<Arc<LogStore>>,
+    /// 持久化存储的后台写入队列（首次写入时启动写入线程）
+    writer: OnceLock<std::sync::mpsc::SyncSender<WriterMessage>>,
+    /// 实时日志推送（`GET /api/admin/logs/stream`）
+    live: broadcast::Sender<RequestLogEntry>,
+    /// 可选的大字段外置存储
+    blobs: Option<Arc<BlobStore>>,
+    /// 写入前擦除请求 / 响应体中的敏感内容
+    content_filter: Option<Arc<ContentFilter>>,
 }
 
 impl RequestLog {
@@ -34,46 +727,496 @@ impl RequestLog {
         Self {
             entries: Mutex::new(VecDeque::with_capacity(MAX_LOG_ENTRIES)),
             enabled: AtomicBool::new(false),
+            store: None,
+            writer: OnceLock::new(),
+            live: broadcast::channel(LIVE_BUFFER).0,
+            blobs: None,
+            content_filter: None,
+        }
+    }
+
+    /// 创建带 SQLite 持久化存储的请求日志
+    ///
+    /// 开启持久化即表示需要记录日志，日志开关默认开启（重启后无需通过 Admin API 重新开启）
+    pub fn with_store(path: &Path, retention: RetentionPolicy) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Self::with_connection(Connection::open(path)?, retention)
+    }
+
+    fn with_connection(conn: Connection, retention: RetentionPolicy) -> anyhow::Result<Self> {
+        Ok(Self {
+            store: Some(Arc::new(LogStore::open(conn, retention)?)),
+            enabled: AtomicBool::new(true),
+            ..Self::new()
+        })
+    }
+
+    /// 超过 `threshold` 字节的请求/响应体外置到 `dir`（内容寻址），日志中只保留引用
+    ///
+    /// 目录创建失败时不启用外置存储
+    pub fn with_blobs(mut self, dir: PathBuf, threshold: usize) -> Self {
+        match fs::create_dir_all(&dir) {
+            Ok(()) => self.blobs = Some(Arc::new(BlobStore { dir, threshold })),
+            Err(e) => tracing::error!("创建请求日志外置内容目录失败，不外置大字段: {}", e),
         }
+        self
+    }
+
+    /// 写入前按内容过滤规则擦除请求 / 响应体
+    pub fn with_content_filter(mut self, filter: Arc<ContentFilter>) -> Self {
+        self.content_filter = Some(filter);
+        self
     }
 
     pub fn is_enabled(&self) -> bool {
         self.enabled.load(Ordering::Relaxed)
     }
 
+    /// 设置日志开关，关闭时只停止记录，已记录的日志保留（清空见 [`RequestLog::clear`]）
     pub fn set_enabled(&self, enabled: bool) {
         self.enabled.store(enabled, Ordering::Relaxed);
-        if !enabled {
-            self.entries.lock().clear();
+    }
+
+    /// 持久化存储的写入队列，首次调用时启动后台写入线程
+    fn writer(&self, store: &Arc<LogStore>) -> &std::sync::mpsc::SyncSender<WriterMessage> {
+        self.writer.get_or_init(|| {
+            let (tx, rx) = std::sync::mpsc::sync_channel(WRITER_QUEUE);
+            let (store, blobs, live) = (store.clone(), self.blobs.clone(), self.live.clone());
+            let spawned = std::thread::Builder::new()
+                .name("request-log-writer".to_string())
+                .spawn(move || run_writer(rx, store, blobs, live));
+            if let Err(e) = spawned {
+                tracing::error!("启动请求日志写入线程失败，持久化日志不再写入: {}", e);
+            }
+            tx
+        })
+    }
+
+    /// 等待已提交的日志全部写入持久化存储（查询前调用，保证能查到刚写入的日志）
+    ///
+    /// 会阻塞当前线程，异步上下文中需放到 `spawn_blocking` 中调用
+    fn flush(&self) {
+        let Some(writer) = self.writer.get() else {
+            return;
+        };
+        let (tx, rx) = std::sync::mpsc::sync_channel(1);
+        if writer.send(WriterMessage::Flush(tx)).is_ok() {
+            let _ = rx.recv();
         }
     }
 
-    pub fn push(&self, entry: RequestLogEntry) {
+    pub fn push(&self, mut entry: RequestLogEntry) {
         if !self.is_enabled() {
             return;
         }
-        let mut entries = self.entries.lock();
-        if entries.len() >= MAX_LOG_ENTRIES {
-            entries.pop_front();
+        if let Some(filter) = &self.content_filter {
+            filter.scrub_entry(&mut entry);
+        }
+        if let Some(store) = &self.store {
+            let sent = self
+                .writer(store)
+                .try_send(WriterMessage::Entry(Box::new(entry)));
+            if let Err(std::sync::mpsc::TrySendError::Full(_)) = sent {
+                tracing::warn!("请求日志写入队列已满，丢弃日志");
+            }
+            return;
         }
+        if let Some(blobs) = &self.blobs {
+            blobs.offload(&mut entry);
+        }
+        if self.live.receiver_count() > 0 {
+            let _ = self.live.send(entry.clone());
+        }
+        let mut entries = self.entries.lock();
+        let evicted = if entries.len() >= MAX_LOG_ENTRIES {
+            entries.pop_front()
+        } else {
+            None
+        };
         entries.push_back(entry);
+        // 淘汰的条目引用了外置内容时清理不再被引用的内容
+        if let Some(blobs) = &self.blobs
+            && evicted.is_some_and(|e| e.blob_refs().next().is_some())
+        {
+            let refs = entries
+                .iter()
+                .flat_map(|e| e.blob_refs())
+                .map(str::to_string)
+                .collect();
+            blobs.sweep(&refs);
+        }
+    }
+
+    /// 按 ID 获取单条日志，外置的请求/响应体会被还原
+    pub fn get(&self, id: &str) -> Option<RequestLogEntry> {
+        self.flush();
+        let mut entry = match &self.store {
+            Some(store) => store.get(id).unwrap_or_else(|e| {
+                tracing::warn!("查询请求日志失败: {}", e);
+                None
+            }),
+            None => self.entries.lock().iter().find(|e| e.id == id).cloned(),
+        }?;
+        if let Some(blobs) = &self.blobs {
+            blobs.resolve(&mut entry);
+        }
+        Some(entry)
     }
 
+    /// 订阅新写入的日志
+    pub fn subscribe(&self) -> broadcast::Receiver<RequestLogEntry> {
+        self.live.subscribe()
+    }
+
+    /// 清空日志：内存日志、持久化存储中的全部日志，以及不再被引用的外置内容
     pub fn clear(&self) {
         self.entries.lock().clear();
+        if let Some(store) = &self.store {
+            self.flush();
+            if let Err(e) = store.clear() {
+                tracing::warn!("清空请求日志失败: {}", e);
+                return;
+            }
+        }
+        if let Some(blobs) = &self.blobs {
+            blobs.sweep(&HashSet::new());
+        }
     }
 
-    pub fn entries_since(&self, since_id: Option<&str>) -> Vec<RequestLogEntry> {
-        let entries = self.entries.lock();
-        match since_id {
-            Some(id) => {
-                if let Some(pos) = entries.iter().position(|e| e.id == id) {
-                    entries.iter().skip(pos + 1).cloned().collect()
-                } else {
-                    entries.iter().cloned().collect()
+    /// 按时间正序分批导出日志，`cursor` 为上一批返回的游标（首批传 0），没有后续批次时游标为 None
+    ///
+    /// 持久化存储按批读取，避免一次加载全部日志；内存日志一次返回全部匹配条目
+    pub fn export_batch(
+        &self,
+        query: &ExportQuery,
+        cursor: i64,
+    ) -> (Vec<RequestLogEntry>, Option<i64>) {
+        let query = query.log_query();
+        if let Some(store) = &self.store {
+            self.flush();
+            return match store.export_batch(&query, cursor) {
+                Ok((entries, last_seq)) => {
+                    let next = last_seq.filter(|_| entries.len() == EXPORT_BATCH);
+                    (entries, next)
+                }
+                Err(e) => {
+                    tracing::warn!("导出请求日志失败: {}", e);
+                    (vec![], None)
                 }
+            };
+        }
+        let entries = self
+            .entries
+            .lock()
+            .iter()
+            .filter(|e| query.matches(e))
+            .cloned()
+            .collect();
+        (entries, None)
+    }
+
+    /// 按条件分页查询日志
+    pub fn query(&self, query: &LogQuery) -> LogPage {
+        if let Some(store) = &self.store {
+            self.flush();
+            match store.query(query) {
+                Ok(page) => return page,
+                Err(e) => tracing::warn!("查询请求日志失败: {}", e),
             }
-            None => entries.iter().cloned().collect(),
         }
+
+        let entries = self.entries.lock();
+        let start = query
+            .since_id
+            .as_ref()
+            .and_then(|id| entries.iter().position(|e| &e.id == id))
+            .map(|pos| pos + 1)
+            .unwrap_or(0);
+        let matched: Vec<&RequestLogEntry> = entries
+            .iter()
+            .skip(start)
+            .filter(|e| query.matches(e))
+            .collect();
+
+        let (page, page_size) = (query.page(), query.page_size());
+        let end = matched.len().saturating_sub((page - 1) * page_size);
+        let begin = end.saturating_sub(page_size);
+        LogPage {
+            entries: matched[begin..end].iter().map(|e| (*e).clone()).collect(),
+            total: matched.len(),
+            page,
+            page_size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, timestamp: &str, model: &str, status: &str) -> RequestLogEntry {
+        RequestLogEntry {
+            id: id.to_string(),
+            timestamp: timestamp.to_string(),
+            model: model.to_string(),
+            stream: false,
+            message_count: 1,
+            input_tokens: 10,
+            output_tokens: 5,
+            token_source: "local(estimate)".to_string(),
+            duration_ms: 100,
+            status: status.to_string(),
+            api_key_id: if id == "c" { "other" } else { "default" }.to_string(),
+            user_id: (id == "b").then(|| "user-1".to_string()),
+            request_body: String::new(),
+            kiro_request_body: format!("{{\"conversationId\":\"{}\"}}", id),
+            response_body: String::new(),
+            warnings: vec!["w".to_string()],
+        }
+    }
+
+    fn fill(log: &RequestLog) {
+        log.set_enabled(true);
+        log.push(entry(
+            "a",
+            "2026-01-01T00:00:00+00:00",
+            "claude-sonnet-4-6",
+            "success",
+        ));
+        log.push(entry(
+            "b",
+            "2026-01-02T00:00:00+00:00",
+            "claude-opus-4-6",
+            "error: boom",
+        ));
+        log.push(entry(
+            "c",
+            "2026-01-03T00:00:00+00:00",
+            "claude-sonnet-4-6",
+            "success",
+        ));
+    }
+
+    fn ids(page: &LogPage) -> Vec<&str> {
+        page.entries.iter().map(|e| e.id.as_str()).collect()
+    }
+
+    fn check_queries(log: &RequestLog) {
+        let all = log.query(&LogQuery::default());
+        assert_eq!(ids(&all), ["a", "b", "c"]);
+        assert_eq!(all.total, 3);
+
+        let since = log.query(&LogQuery {
+            since_id: Some("a".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(ids(&since), ["b", "c"]);
+
+        let filtered = log.query(&LogQuery {
+            model: Some("sonnet".to_string()),
+            api_key_id: Some("default".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(ids(&filtered), ["a"]);
+
+        let errors = log.query(&LogQuery {
+            status: Some("error".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(ids(&errors), ["b"]);
+
+        let by_user = log.query(&LogQuery {
+            user_id: Some("user-1".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(ids(&by_user), ["b"]);
+        assert_eq!(by_user.entries[0].user_id.as_deref(), Some("user-1"));
+
+        let ranged = log.query(&LogQuery {
+            from: Some("2026-01-02T00:00:00Z".to_string()),
+            to: Some("2026-01-02T12:00:00Z".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(ids(&ranged), ["b"]);
+
+        let page2 = log.query(&LogQuery {
+            page: Some(2),
+            page_size: Some(2),
+            ..Default::default()
+        });
+        assert_eq!(ids(&page2), ["a"]);
+        assert_eq!(page2.total, 3);
+    }
+
+    #[test]
+    fn test_memory_log_query() {
+        let log = RequestLog::new();
+        fill(&log);
+        check_queries(&log);
+    }
+
+    #[test]
+    fn test_sqlite_log_query() {
+        let retention = RetentionPolicy {
+            days: 0,
+            max_entries: 0,
+        };
+        let log =
+            RequestLog::with_connection(Connection::open_in_memory().unwrap(), retention).unwrap();
+        fill(&log);
+        check_queries(&log);
+        let first = &log.query(&LogQuery::default()).entries[0];
+        assert_eq!(first.warnings, ["w"]);
+        assert_eq!(
+            first.kiro_request_body,
+            format!(r#"{{"conversationId":"{}"}}"#, first.id)
+        );
+    }
+
+    #[test]
+    fn test_export_formats() {
+        let log = RequestLog::with_connection(
+            Connection::open_in_memory().unwrap(),
+            RetentionPolicy {
+                days: 0,
+                max_entries: 0,
+            },
+        )
+        .unwrap();
+        fill(&log);
+        let query = ExportQuery {
+            from: Some("2026-01-02T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        let (entries, next) = log.export_batch(&query, 0);
+        assert_eq!(
+            entries.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(),
+            ["b", "c"]
+        );
+        assert_eq!(next, None);
+
+        let line = ExportFormat::Jsonl.line(&entries[0]);
+        let value: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+        assert_eq!(value["inputTokens"], 10);
+        assert_eq!(value["durationMs"], 100);
+        assert_eq!(value["userId"], "user-1");
+        assert!(value.get("requestBody").is_none());
+
+        let mut csv = ExportFormat::Csv.header().to_string();
+        entries
+            .iter()
+            .for_each(|e| csv.push_str(&ExportFormat::Csv.line(e)));
+        assert_eq!(
+            csv.lines().nth(1).unwrap(),
+            "b,2026-01-02T00:00:00+00:00,default,user-1,claude-opus-4-6,false,1,10,5,local(estimate),100,error: boom"
+        );
+        assert_eq!(csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+
+    #[test]
+    fn test_sqlite_retention_max_entries() {
+        let retention = RetentionPolicy {
+            days: 0,
+            max_entries: 2,
+        };
+        let log =
+            RequestLog::with_connection(Connection::open_in_memory().unwrap(), retention).unwrap();
+        fill(&log);
+        log.flush();
+        log.store.as_ref().unwrap().prune();
+        assert_eq!(ids(&log.query(&LogQuery::default())), ["b", "c"]);
+    }
+
+    #[test]
+    fn test_persistent_log_survives_disable_until_cleared() {
+        let retention = RetentionPolicy {
+            days: 0,
+            max_entries: 0,
+        };
+        let log =
+            RequestLog::with_connection(Connection::open_in_memory().unwrap(), retention).unwrap();
+        assert!(log.is_enabled());
+        log.push(entry("a", "2026-01-01T00:00:00+00:00", "m", "success"));
+        assert_eq!(log.query(&LogQuery::default()).total, 1);
+
+        // 关闭日志只停止记录，已持久化的日志保留
+        log.set_enabled(false);
+        log.push(entry("b", "2026-01-01T00:00:01+00:00", "m", "success"));
+        assert_eq!(log.query(&LogQuery::default()).total, 1);
+        assert!(log.get("a").is_some());
+
+        log.clear();
+        assert_eq!(log.query(&LogQuery::default()).total, 0);
+        assert!(log.get("a").is_none());
+    }
+
+    #[test]
+    fn test_subscribe_receives_new_entries() {
+        let log = RequestLog::new();
+        let mut rx = log.subscribe();
+        log.push(entry("a", "2026-01-01T00:00:00+00:00", "m", "success"));
+        assert!(rx.try_recv().is_err());
+
+        log.set_enabled(true);
+        log.push(entry("b", "2026-01-01T00:00:00+00:00", "m", "success"));
+        assert_eq!(rx.try_recv().unwrap().id, "b");
+    }
+
+    #[test]
+    fn test_large_bodies_offloaded_to_blobs() {
+        let dir = std::env::temp_dir().join(format!("kiro-log-blobs-{}", uuid::Uuid::new_v4()));
+        let retention = RetentionPolicy {
+            days: 0,
+            max_entries: 0,
+        };
+        let log = RequestLog::with_connection(Connection::open_in_memory().unwrap(), retention)
+            .unwrap()
+            .with_blobs(dir.clone(), 16);
+        log.set_enabled(true);
+        let body = "x".repeat(64);
+        for id in ["a", "b"] {
+            let mut e = entry(id, "2026-01-01T00:00:00+00:00", "m", "success");
+            e.request_body = body.clone();
+            e.response_body = "short".to_string();
+            log.push(e);
+        }
+        log.flush();
+
+        // 相同内容只存一份，列表中只保留引用
+        let files = fs::read_dir(&dir).unwrap().count();
+        let page = log.query(&LogQuery::default());
+        assert!(page.entries[0].request_body.starts_with(BLOB_REF_PREFIX));
+        assert_eq!(page.entries[0].response_body, "short");
+
+        let full = log.get("a").unwrap();
+        assert_eq!(full.request_body, body);
+        assert!(log.get("missing").is_none());
+
+        // 未被引用且超过保留时长的内容会被清理
+        let blobs = log.blobs.as_ref().unwrap();
+        let stale = SystemTime::now() - BLOB_GRACE;
+        for path in fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|e| e.ok().map(|e| e.path()))
+        {
+            fs::File::options()
+                .append(true)
+                .open(path)
+                .unwrap()
+                .set_modified(stale)
+                .unwrap();
+        }
+        blobs.sweep(&log.store.as_ref().unwrap().blob_refs().unwrap());
+        let remaining = fs::read_dir(&dir).unwrap().count();
+        blobs.sweep(&HashSet::new());
+        let swept = fs::read_dir(&dir).unwrap().count();
+        let _ = fs::remove_dir_all(&dir);
+
+        // 每条日志的 Kiro 请求体也超过阈值（内容不同）
+        assert_eq!(files, 3);
+        assert_eq!(remaining, 3);
+        assert_eq!(swept, 0);
     }
 }
@@ -3,9 +3,10 @@
 //! 提供文本 token 数量计算功能。
 //!
 //! # 计算规则
-//! - 非西文字符：每个计 4.5 个字符单位
-//! - 西文字符：每个计 1 个字符单位
-//! - 4 个字符单位 = 1 token（四舍五入）
+//! - 启用 `tokenizer` 特性时使用内嵌的 BPE 分词器（cl100k_base）
+//! - 否则按字符数估算：非西文字符每个计 4.5 个字符单位，西文字符每个计 1 个，
+//!   4 个字符单位 = 1 token（四舍五入）
+//! - 配置了外部 count_tokens API 时优先使用 API 结果
 
 use crate::anthropic::types::{
     CountTokensRequest, CountTokensResponse, Message, SystemMessage, Tool,
@@ -71,12 +72,29 @@ fn is_non_western_char(c: char) -> bool {
 
 /// 计算文本的 token 数量
 ///
+/// 启用 `tokenizer` 特性时使用内嵌的 BPE 分词器，否则按字符数估算
+pub fn count_tokens(text: &str) -> u64 {
+    #[cfg(feature = "tokenizer")]
+    {
+        tiktoken_rs::cl100k_base_singleton()
+            .encode_ordinary(text)
+            .len() as u64
+    }
+    #[cfg(not(feature = "tokenizer"))]
+    {
+        estimate_tokens(text)
+    }
+}
+
+/// 按字符数估算文本的 token 数量
+///
 /// # 计算规则
 /// - 非西文字符：每个计 4.5 个字符单位
 /// - 西文字符：每个计 1 个字符单位
 /// - 4 个字符单位 = 1 token（四舍五入）
 /// ```
-pub fn count_tokens(text: &str) -> u64 {
+#[cfg_attr(feature = "tokenizer", allow(dead_code))]
+fn estimate_tokens(text: &str) -> u64 {
     // println!("text: {}", text);
 
     let char_units: f64 = text
@@ -154,6 +172,9 @@ async fn call_remote_count_tokens(
         messages: messages.clone(),
         system: system.clone(),
         tools: tools.clone(),
+        thinking: None,
+        output_config: None,
+        tool_choice: None,
     };
 
     // 构建请求
@@ -243,3 +264,21 @@ pub(crate) fn estimate_output_tokens(content: &[serde_json::Value]) -> i32 {
 
     total.max(1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "tokenizer")]
+    #[test]
+    fn test_count_tokens_uses_bpe() {
+        assert_eq!(count_tokens("hello world"), 2);
+        assert_eq!(count_tokens(""), 0);
+    }
+
+    #[test]
+    fn test_estimate_tokens_weights_non_western_chars() {
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("你好"), 3);
+    }
+}
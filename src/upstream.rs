@@ -0,0 +1,404 @@
+//! 额外上游后端
+//!
+//! Kiro 之外的 Anthropic 兼容后端（官方 API Key、其他区域部署的反代等）实现 [`Upstream`]，
+//! 由 `upstreams` 配置注册：可按 API Key 或模型直接路由，也可在 Kiro 凭据全部不可用（529）时兜底。
+//! 这些后端直接收发 Anthropic 格式，响应原样转发，只从中提取 usage 用于计量；
+//! Kiro 仍走内置的请求转换与事件流解析路径。
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    http::{StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use futures::StreamExt;
+use futures::future::BoxFuture;
+use futures::stream;
+use parking_lot::Mutex;
+use reqwest::Client;
+use serde_json::Value;
+
+use crate::anthropic::handlers::RequestLogCtx;
+use crate::anthropic::types::{ErrorResponse, MessagesRequest};
+use crate::apikeys::ApiKeyManager;
+use crate::http_client::{ProxyConfig, build_client};
+use crate::model::config::{Config, UpstreamConfig};
+
+/// Anthropic Messages API 版本
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// 上游后端
+pub trait Upstream: Send + Sync {
+    /// 名称（用于日志）
+    fn name(&self) -> &str;
+
+    /// 发送 Anthropic Messages 请求体，返回 Anthropic 格式的原始响应
+    fn send<'a>(&'a self, body: &'a Value) -> BoxFuture<'a, anyhow::Result<reqwest::Response>>;
+}
+
+/// Anthropic 官方 API（或任意兼容 `/v1/messages` 的服务）
+pub struct AnthropicUpstream {
+    name: String,
+    endpoint: String,
+    api_key: String,
+    client: Client,
+}
+
+impl AnthropicUpstream {
+    pub fn new(config: &UpstreamConfig, client: Client) -> Self {
+        Self {
+            name: config.name.clone(),
+            endpoint: format!("{}/v1/messages", config.base_url.trim_end_matches('/')),
+            api_key: config.api_key.clone(),
+            client,
+        }
+    }
+}
+
+impl Upstream for AnthropicUpstream {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn send<'a>(&'a self, body: &'a Value) -> BoxFuture<'a, anyhow::Result<reqwest::Response>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(&self.endpoint)
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", ANTHROPIC_VERSION)
+                .json(body)
+                .send()
+                .await?;
+            Ok(response)
+        })
+    }
+}
+
+/// (构建时的上游配置, 构建时的代理, 上游实例)
+type UpstreamCache = (
+    Vec<UpstreamConfig>,
+    Option<ProxyConfig>,
+    Vec<Arc<dyn Upstream>>,
+);
+
+/// 已注册的上游
+pub struct UpstreamRegistry {
+    /// 配置热更新后重建
+    cache: Mutex<UpstreamCache>,
+}
+
+impl UpstreamRegistry {
+    pub fn new() -> Self {
+        Self {
+            cache: Mutex::new((Vec::new(), None, Vec::new())),
+        }
+    }
+
+    /// 与 `config.upstreams` 一一对应的上游实例
+    fn upstreams(&self, config: &Config) -> Vec<Arc<dyn Upstream>> {
+        let proxy = config.proxy_config();
+        let mut cache = self.cache.lock();
+        if cache.0 != config.upstreams || cache.1 != proxy || cache.2.is_empty() {
+            let upstreams = match build_client(proxy.as_ref(), 720, config.tls_backend) {
+                Ok(client) => config
+                    .upstreams
+                    .iter()
+                    .map(|u| {
+                        Arc::new(AnthropicUpstream::new(u, client.clone())) as Arc<dyn Upstream>
+                    })
+                    .collect(),
+                Err(e) => {
+                    tracing::warn!("创建上游 HTTP 客户端失败: {}", e);
+                    Vec::new()
+                }
+            };
+            *cache = (config.upstreams.clone(), proxy, upstreams);
+        }
+        cache.2.clone()
+    }
+
+    /// 按 API Key 或模型直接路由的上游（API Key 优先）
+    pub fn route(&self, config: &Config, model: &str, key_id: &str) -> Option<Arc<dyn Upstream>> {
+        let index = select_route(&config.upstreams, model, key_id)?;
+        self.upstreams(config).get(index).cloned()
+    }
+
+    /// Kiro 凭据全部不可用时的兜底上游
+    pub fn fallback(&self, config: &Config) -> Option<Arc<dyn Upstream>> {
+        let index = config.upstreams.iter().position(|u| u.fallback)?;
+        self.upstreams(config).get(index).cloned()
+    }
+}
+
+fn select_route(upstreams: &[UpstreamConfig], model: &str, key_id: &str) -> Option<usize> {
+    let model = model.to_lowercase();
+    upstreams
+        .iter()
+        .position(|u| u.api_key_ids.iter().any(|k| k == key_id))
+        .or_else(|| {
+            upstreams.iter().position(|u| {
+                u.models
+                    .iter()
+                    .any(|m| !m.is_empty() && model.contains(&m.to_lowercase()))
+            })
+        })
+}
+
+/// 待用的兜底转发（请求体与日志上下文需在请求被 Kiro 路径消耗前保存）
+pub(crate) struct Fallback {
+    upstream: Arc<dyn Upstream>,
+    body: Value,
+    log_ctx: RequestLogCtx,
+}
+
+impl Fallback {
+    /// 配置了兜底上游时保存请求
+    pub(crate) fn prepare(
+        registry: &UpstreamRegistry,
+        config: &Config,
+        payload: &MessagesRequest,
+        log_ctx: &RequestLogCtx,
+    ) -> Option<Self> {
+        let upstream = registry.fallback(config)?;
+        Some(Self {
+            upstream,
+            body: serde_json::to_value(payload).ok()?,
+            log_ctx: log_ctx.clone(),
+        })
+    }
+
+    /// Kiro 返回 529（凭据全部不可用）或 429（上游限流）时改由兜底上游处理，否则原样返回
+    pub(crate) async fn on_overload(
+        fallback: Option<Self>,
+        response: Response,
+        api_keys: Arc<ApiKeyManager>,
+        key_id: String,
+    ) -> Response {
+        match fallback {
+            Some(fallback) if matches!(response.status().as_u16(), 429 | 529) => {
+                tracing::warn!(
+                    "Kiro 凭据均不可用或被限流，转发到兜底上游 {}",
+                    fallback.upstream.name()
+                );
+                forward(
+                    fallback.upstream,
+                    &fallback.body,
+                    api_keys,
+                    key_id,
+                    fallback.log_ctx,
+                )
+                .await
+            }
+            _ => response,
+        }
+    }
+}
+
+/// 转发请求到上游并计量用量，响应原样返回
+pub(crate) async fn forward(
+    upstream: Arc<dyn Upstream>,
+    body: &Value,
+    api_keys: Arc<ApiKeyManager>,
+    key_id: String,
+    log_ctx: RequestLogCtx,
+) -> Response {
+    let token_source = format!("upstream({})", upstream.name());
+    tracing::info!("请求转发到上游 {}", upstream.name());
+
+    let response = match upstream.send(body).await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::error!("上游 {} 调用失败: {}", upstream.name(), e);
+            log_ctx.record_with_body(0, 0, &token_source, &format!("error: {}", e), String::new());
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(ErrorResponse::new(
+                    "api_error",
+                    format!("上游 API 调用失败: {}", e),
+                )),
+            )
+                .into_response();
+        }
+    };
+
+    let status = response.status();
+    let mut builder = Response::builder().status(status);
+    if let Some(content_type) = response.headers().get(header::CONTENT_TYPE) {
+        builder = builder.header(header::CONTENT_TYPE, content_type.clone());
+    }
+
+    let stream = body["stream"].as_bool().unwrap_or(false);
+    if !stream || !status.is_success() {
+        let bytes = response.bytes().await.unwrap_or_default();
+        let (input, output, label) = if status.is_success() {
+            let (input, output) = serde_json::from_slice::<Value>(&bytes)
+                .map(|v| usage_tokens(&v["usage"]))
+                .unwrap_or_default();
+            api_keys.record_usage(
+                &key_id,
+                log_ctx.user_id(),
+                log_ctx.model(),
+                input as u64,
+                output as u64,
+            );
+            (input, output, "success".to_string())
+        } else {
+            (0, 0, format!("error: upstream {}", status))
+        };
+        log_ctx.record_with_body(
+            input,
+            output,
+            &token_source,
+            &label,
+            String::from_utf8_lossy(&bytes).into_owned(),
+        );
+        return builder.body(Body::from(bytes)).unwrap();
+    }
+
+    // 流式响应边转发边累计 usage，结束时计量
+    let finish = (api_keys, key_id, log_ctx, token_source);
+    let body_stream = stream::unfold(
+        (response.bytes_stream(), SseUsage::default(), Some(finish)),
+        |(mut inner, mut usage, mut finish)| async move {
+            let item = match inner.next().await {
+                Some(Ok(chunk)) => {
+                    usage.feed(&chunk);
+                    return Some((Ok(chunk), (inner, usage, finish)));
+                }
+                Some(Err(e)) => Some(e),
+                None => None,
+            };
+            let (api_keys, key_id, log_ctx, token_source) = finish.take()?;
+            let status = match &item {
+                Some(e) => format!("error: {}", e),
+                None => "success".to_string(),
+            };
+            api_keys.record_usage(
+                &key_id,
+                log_ctx.user_id(),
+                log_ctx.model(),
+                usage.input_tokens as u64,
+                usage.output_tokens as u64,
+            );
+            log_ctx.record_with_body(
+                usage.input_tokens,
+                usage.output_tokens,
+                &token_source,
+                &status,
+                String::new(),
+            );
+            item.map(|e| (Err(e), (inner, usage, None)))
+        },
+    );
+    builder.body(Body::from_stream(body_stream)).unwrap()
+}
+
+/// Anthropic usage 中的 (输入, 输出) token，输入包含缓存读写部分
+fn usage_tokens(usage: &Value) -> (i32, i32) {
+    let field = |name: &str| usage[name].as_i64().unwrap_or(0).max(0) as i32;
+    (
+        field("input_tokens")
+            + field("cache_creation_input_tokens")
+            + field("cache_read_input_tokens"),
+        field("output_tokens"),
+    )
+}
+
+/// 从 Anthropic SSE 响应中累计 usage
+#[derive(Debug, Default)]
+struct SseUsage {
+    /// 未读完的行
+    buffer: Vec<u8>,
+    input_tokens: i32,
+    output_tokens: i32,
+}
+
+impl SseUsage {
+    fn feed(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let Ok(event) = serde_json::from_str::<Value>(data.trim()) else {
+                continue;
+            };
+            match event["type"].as_str() {
+                Some("message_start") => {
+                    (self.input_tokens, self.output_tokens) =
+                        usage_tokens(&event["message"]["usage"]);
+                }
+                Some("message_delta") => {
+                    let (input, output) = usage_tokens(&event["usage"]);
+                    if input > 0 {
+                        self.input_tokens = input;
+                    }
+                    self.output_tokens = output;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upstream(name: &str, models: &[&str], api_key_ids: &[&str]) -> UpstreamConfig {
+        UpstreamConfig {
+            name: name.to_string(),
+            base_url: "https://api.anthropic.com".to_string(),
+            api_key: "sk-ant".to_string(),
+            models: models.iter().map(|m| m.to_string()).collect(),
+            api_key_ids: api_key_ids.iter().map(|k| k.to_string()).collect(),
+            fallback: false,
+        }
+    }
+
+    #[test]
+    fn test_route_prefers_api_key_over_model() {
+        let upstreams = vec![
+            upstream("opus", &["Opus"], &[]),
+            upstream("team", &[], &["key-1"]),
+        ];
+        assert_eq!(
+            select_route(&upstreams, "claude-opus-4-6", "key-2"),
+            Some(0)
+        );
+        assert_eq!(
+            select_route(&upstreams, "claude-opus-4-6", "key-1"),
+            Some(1)
+        );
+        assert_eq!(select_route(&upstreams, "claude-sonnet-4-6", "key-2"), None);
+    }
+
+    #[test]
+    fn test_fallback_registry_follows_config() {
+        let registry = UpstreamRegistry::new();
+        let mut config = Config::default();
+        assert!(registry.fallback(&config).is_none());
+
+        config.upstreams = vec![upstream("direct", &[], &[]), {
+            let mut paid = upstream("paid", &[], &[]);
+            paid.fallback = true;
+            paid
+        }];
+        assert_eq!(registry.fallback(&config).unwrap().name(), "paid");
+        assert!(registry.route(&config, "claude-sonnet-4-6", "k").is_none());
+    }
+
+    #[test]
+    fn test_sse_usage_across_chunks() {
+        let mut usage = SseUsage::default();
+        usage.feed(b"event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":10,\"cache_read_input_tokens\":90,\"output_tokens\":1}}}\n\n");
+        usage.feed(b"event: message_delta\ndata: {\"type\":\"message_de");
+        assert_eq!((usage.input_tokens, usage.output_tokens), (100, 1));
+        usage.feed(b"lta\",\"usage\":{\"output_tokens\":42}}\n\n");
+        assert_eq!((usage.input_tokens, usage.output_tokens), (100, 42));
+    }
+}
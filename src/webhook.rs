@@ -0,0 +1,289 @@
+//! API Key 级请求完成回调（webhook）
+//!
+//! 为 API Key 设置回调地址后，每个请求完成时（与请求日志的记录时机相同）将用量与状态以
+//! 签名的 JSON POST 推送到该地址，请求体为 `{"events": [...]}`。事件按 API Key 与地址攒批：达到
+//! `webhook.batchSize` 条或最早一条等待超过 `flushIntervalMs` 时发送，失败按指数退避重试
+//! `maxRetries` 次后丢弃。签名头 `X-Kiro-Signature: t=<Unix 秒>,v1=<hex>`，
+//! 其中 hex 为以回调密钥对 `<t>.<请求体>` 计算的 HMAC-SHA256。
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use ring::hmac;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::http_client::build_client;
+use crate::model::config::{TlsBackend, WebhookConfig};
+
+/// 签名头
+pub const SIGNATURE_HEADER: &str = "x-kiro-signature";
+
+/// 待发送事件队列容量，队列满时丢弃新事件
+const QUEUE_CAPACITY: usize = 10_000;
+
+static QUEUE: OnceLock<mpsc::Sender<(Arc<KeyWebhook>, WebhookEvent)>> = OnceLock::new();
+
+/// API Key 的回调地址与签名密钥
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyWebhook {
+    pub url: String,
+    pub secret: String,
+}
+
+/// 请求完成事件
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookEvent {
+    pub id: String,
+    /// 请求完成时间（RFC3339）
+    pub timestamp: String,
+    pub api_key_id: String,
+    pub model: String,
+    pub stream: bool,
+    /// `success` 或 `error`
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub input_tokens: i32,
+    pub output_tokens: i32,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+}
+
+/// 拆分请求日志的状态（`success` 或 `error: <原因>`）
+pub fn split_status(status: &str) -> (&'static str, Option<String>) {
+    if status == "success" {
+        ("success", None)
+    } else {
+        let error = status.strip_prefix("error: ").unwrap_or(status);
+        ("error", Some(error.to_string()))
+    }
+}
+
+fn hmac_hex(secret: &str, message: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hex::encode(hmac::sign(&key, message).as_ref())
+}
+
+/// 计算签名头的值
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut message = format!("{}.", timestamp).into_bytes();
+    message.extend_from_slice(body);
+    format!("t={},v1={}", timestamp, hmac_hex(secret, &message))
+}
+
+/// 启动后台发送任务（未调用时 [`enqueue`] 不做任何事）
+pub fn init(config: WebhookConfig, tls_backend: TlsBackend) {
+    let client = match build_client(None, config.timeout_secs, tls_backend) {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("创建 webhook HTTP Client 失败，回调已禁用: {}", e);
+            return;
+        }
+    };
+    let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+    if QUEUE.set(tx).is_ok() {
+        tokio::spawn(run(rx, config, client));
+    }
+}
+
+/// 提交一条事件
+pub fn enqueue(webhook: &Arc<KeyWebhook>, event: WebhookEvent) {
+    let Some(queue) = QUEUE.get() else {
+        return;
+    };
+    if queue.try_send((webhook.clone(), event)).is_err() {
+        tracing::warn!("webhook 队列已满，丢弃事件: {}", webhook.url);
+    }
+}
+
+/// 批次键：API Key ID 与回调地址（不同 Key 共用同一地址时也各自成批、各自签名）
+type BatchKey = (String, String);
+
+/// 同一 API Key、同一回调地址待发送的事件
+struct Batch {
+    webhook: Arc<KeyWebhook>,
+    events: Vec<WebhookEvent>,
+    since: Instant,
+}
+
+async fn run(
+    mut rx: mpsc::Receiver<(Arc<KeyWebhook>, WebhookEvent)>,
+    config: WebhookConfig,
+    client: reqwest::Client,
+) {
+    let interval = Duration::from_millis(config.flush_interval_ms);
+    let batch_size = config.batch_size.max(1);
+    let mut pending: HashMap<BatchKey, Batch> = HashMap::new();
+
+    loop {
+        let deadline = pending
+            .values()
+            .map(|b| b.since + interval)
+            .min()
+            .unwrap_or_else(|| Instant::now() + Duration::from_secs(3600));
+        tokio::select! {
+            received = rx.recv() => {
+                let Some((webhook, event)) = received else {
+                    break;
+                };
+                if let Some(batch) = push_event(&mut pending, webhook, event, batch_size) {
+                    tokio::spawn(deliver(client.clone(), batch, config.max_retries));
+                }
+            }
+            _ = tokio::time::sleep_until(deadline) => {
+                let now = Instant::now();
+                let due: Vec<BatchKey> = pending
+                    .iter()
+                    .filter(|(_, b)| b.since + interval <= now)
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                for key in due {
+                    if let Some(batch) = pending.remove(&key) {
+                        tokio::spawn(deliver(client.clone(), batch, config.max_retries));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 将事件加入所属批次，批次达到 `batch_size` 条时取出返回
+fn push_event(
+    pending: &mut HashMap<BatchKey, Batch>,
+    webhook: Arc<KeyWebhook>,
+    event: WebhookEvent,
+    batch_size: usize,
+) -> Option<Batch> {
+    let key = (event.api_key_id.clone(), webhook.url.clone());
+    let batch = pending.entry(key.clone()).or_insert_with(|| Batch {
+        webhook: webhook.clone(),
+        events: Vec::new(),
+        since: Instant::now(),
+    });
+    // 同一 Key 重置密钥后使用新密钥签名
+    batch.webhook = webhook;
+    batch.events.push(event);
+    if batch.events.len() >= batch_size {
+        pending.remove(&key)
+    } else {
+        None
+    }
+}
+
+/// 发送一批事件，失败时按 1s、2s、4s … 退避重试
+async fn deliver(client: reqwest::Client, batch: Batch, max_retries: u32) {
+    let body = match serde_json::to_vec(&serde_json::json!({ "events": batch.events })) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::warn!("序列化 webhook 事件失败: {}", e);
+            return;
+        }
+    };
+    for attempt in 0..=max_retries {
+        let signature = sign(&batch.webhook.secret, chrono::Utc::now().timestamp(), &body);
+        let result = client
+            .post(&batch.webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .body(body.clone())
+            .send()
+            .await;
+        let error = match result {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => format!("HTTP {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+        if attempt == max_retries {
+            tracing::warn!(
+                "webhook 发送失败，丢弃 {} 条事件: {}: {}",
+                batch.events.len(),
+                batch.webhook.url,
+                error
+            );
+            return;
+        }
+        tracing::debug!(
+            "webhook 发送失败，稍后重试: {}: {}",
+            batch.webhook.url,
+            error
+        );
+        tokio::time::sleep(Duration::from_secs(1 << attempt.min(6))).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_and_status() {
+        // RFC 4231 测试用例 2
+        assert_eq!(
+            hmac_hex("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        let signature = sign("whsec_test", 1700000000, br#"{"events":[]}"#);
+        assert_eq!(
+            signature,
+            format!(
+                "t=1700000000,v1={}",
+                hmac_hex("whsec_test", br#"1700000000.{"events":[]}"#)
+            )
+        );
+
+        assert_eq!(split_status("success"), ("success", None));
+        assert_eq!(
+            split_status("error: upstream timeout"),
+            ("error", Some("upstream timeout".to_string()))
+        );
+    }
+
+    fn event(api_key_id: &str) -> WebhookEvent {
+        WebhookEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: "2026-01-01T00:00:00Z".to_string(),
+            api_key_id: api_key_id.to_string(),
+            model: "claude-sonnet-4".to_string(),
+            stream: false,
+            status: "success",
+            error: None,
+            input_tokens: 1,
+            output_tokens: 1,
+            duration_ms: 1,
+            user_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batches_are_per_key_when_url_is_shared() {
+        let url = "https://hooks.example.com/kiro".to_string();
+        let a = Arc::new(KeyWebhook {
+            url: url.clone(),
+            secret: "whsec_a".to_string(),
+        });
+        let b = Arc::new(KeyWebhook {
+            url: url.clone(),
+            secret: "whsec_b".to_string(),
+        });
+        let mut pending = HashMap::new();
+
+        assert!(push_event(&mut pending, a.clone(), event("key-a"), 2).is_none());
+        assert!(push_event(&mut pending, b.clone(), event("key-b"), 2).is_none());
+        assert_eq!(pending.len(), 2);
+
+        let batch = push_event(&mut pending, a, event("key-a"), 2).unwrap();
+        assert_eq!(batch.webhook.secret, "whsec_a");
+        assert_eq!(batch.events.len(), 2);
+        assert!(batch.events.iter().all(|e| e.api_key_id == "key-a"));
+
+        let remaining = pending.remove(&("key-b".to_string(), url)).unwrap();
+        assert_eq!(remaining.webhook.secret, "whsec_b");
+        assert_eq!(remaining.events.len(), 1);
+        assert!(pending.is_empty());
+    }
+}